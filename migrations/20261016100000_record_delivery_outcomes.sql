@@ -0,0 +1,8 @@
+ALTER TABLE issue_delivery_failures ADD COLUMN error TEXT NULL;
+
+CREATE TABLE issue_deliveries (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    subscriber_email TEXT NOT NULL,
+    delivered_at timestamptz NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, subscriber_email)
+);
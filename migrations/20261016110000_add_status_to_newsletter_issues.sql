@@ -0,0 +1,2 @@
+-- One of 'delivering', 'paused' or 'cancelled'
+ALTER TABLE newsletter_issues ADD COLUMN status TEXT NOT NULL DEFAULT 'delivering';
@@ -0,0 +1,2 @@
+-- Drafts are stored as issues with status 'draft' and are only given a publication date once published
+ALTER TABLE newsletter_issues ALTER COLUMN published_at DROP NOT NULL;
@@ -0,0 +1,8 @@
+-- Tags group subscribers into segments that an issue can be sent to
+CREATE TABLE subscriber_tags (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    tag TEXT NOT NULL,
+    PRIMARY KEY (subscriber_id, tag)
+);
+-- The tag an issue is sent to; NULL means every confirmed subscriber
+ALTER TABLE newsletter_issues ADD COLUMN segment TEXT NULL;
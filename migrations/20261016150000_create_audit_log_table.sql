@@ -0,0 +1,9 @@
+-- Append-only record of sensitive operations. `user_id` is NULL when a subscriber acted on their own data.
+CREATE TABLE audit_log (
+    audit_log_id uuid NOT NULL,
+    PRIMARY KEY (audit_log_id),
+    user_id uuid NULL REFERENCES users (user_id),
+    action TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
@@ -0,0 +1,8 @@
+-- Confirmation links stop working once their token expires. Existing tokens get a fresh week.
+BEGIN;
+    ALTER TABLE subscription_tokens ADD COLUMN expires_at timestamptz NULL;
+    UPDATE subscription_tokens
+        SET expires_at = now() + interval '7 days';
+    ALTER TABLE subscription_tokens
+        ALTER COLUMN expires_at SET NOT NULL;
+COMMIT;
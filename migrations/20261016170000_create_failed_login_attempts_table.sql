@@ -0,0 +1,8 @@
+-- Failed logins, used to lock a client out of an account after repeated failures
+CREATE TABLE failed_login_attempts(
+    username TEXT NOT NULL,
+    ip_address TEXT NOT NULL,
+    attempted_at timestamptz NOT NULL
+);
+CREATE INDEX failed_login_attempts_username_ip_address_idx
+    ON failed_login_attempts (username, ip_address, attempted_at);
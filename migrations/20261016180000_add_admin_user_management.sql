@@ -0,0 +1,11 @@
+-- Admins invite each other: invited users have an email address but no password until they
+-- accept their invitation, and can later be deactivated rather than deleted.
+ALTER TABLE users ADD COLUMN email TEXT NULL;
+ALTER TABLE users ADD COLUMN is_active BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE users ALTER COLUMN password_hash DROP NOT NULL;
+CREATE TABLE user_invitations(
+    invitation_token TEXT NOT NULL,
+    PRIMARY KEY (invitation_token),
+    user_id uuid NOT NULL REFERENCES users (user_id),
+    expires_at timestamptz NOT NULL
+);
@@ -0,0 +1,9 @@
+-- Optional TOTP second factor. `totp_last_used_step` prevents a code from being used twice.
+ALTER TABLE users ADD COLUMN totp_secret TEXT NULL;
+ALTER TABLE users ADD COLUMN totp_last_used_step BIGINT NULL;
+CREATE TABLE recovery_codes(
+    user_id uuid NOT NULL REFERENCES users (user_id),
+    code_hash TEXT NOT NULL,
+    PRIMARY KEY (user_id, code_hash),
+    used_at timestamptz NULL
+);
@@ -0,0 +1,22 @@
+ALTER TABLE newsletter_issues ADD COLUMN click_tracking BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- Identifies a delivery in the tracked links of its email, so that clicks can be attributed to it
+ALTER TABLE issue_delivery_queue ADD COLUMN delivery_id uuid NOT NULL DEFAULT gen_random_uuid();
+ALTER TABLE issue_deliveries ADD COLUMN delivery_id uuid NULL UNIQUE;
+
+-- The links of issues with click tracking, numbered in the order they appear in the HTML content
+CREATE TABLE issue_links (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    link_id INT NOT NULL,
+    url TEXT NOT NULL,
+    PRIMARY KEY(newsletter_issue_id, link_id)
+);
+
+CREATE TABLE link_clicks (
+    newsletter_issue_id uuid NOT NULL,
+    link_id INT NOT NULL,
+    delivery_id uuid NOT NULL REFERENCES issue_deliveries (delivery_id),
+    clicked_at timestamptz NOT NULL,
+    FOREIGN KEY (newsletter_issue_id, link_id) REFERENCES issue_links (newsletter_issue_id, link_id)
+);
+CREATE INDEX link_clicks_newsletter_issue_id_idx ON link_clicks (newsletter_issue_id);
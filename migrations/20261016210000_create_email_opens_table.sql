@@ -0,0 +1,7 @@
+-- Loads of the tracking pixel embedded in issues published with tracking
+CREATE TABLE email_opens (
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    delivery_id uuid NOT NULL REFERENCES issue_deliveries (delivery_id),
+    opened_at timestamptz NOT NULL
+);
+CREATE INDEX email_opens_newsletter_issue_id_idx ON email_opens (newsletter_issue_id);
@@ -0,0 +1,3 @@
+-- Bumped on every password change; sessions remember the version they were opened with and are
+-- terminated once it is outdated.
+ALTER TABLE users ADD COLUMN password_version INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,12 @@
+-- "Remember me" tokens. Only a hash of their secret is stored, and the secret is replaced every
+-- time the token is used.
+CREATE TABLE persistent_logins(
+    persistent_login_id uuid NOT NULL,
+    PRIMARY KEY (persistent_login_id),
+    user_id uuid NOT NULL REFERENCES users (user_id),
+    token_hash TEXT NOT NULL,
+    -- the password version of the user when the token was issued
+    password_version INTEGER NOT NULL,
+    expires_at timestamptz NOT NULL
+);
+CREATE INDEX persistent_logins_user_id_idx ON persistent_logins (user_id);
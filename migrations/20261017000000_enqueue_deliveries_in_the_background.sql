@@ -0,0 +1,4 @@
+-- Published issues are 'enqueuing' while the delivery worker queues a task for each recipient,
+-- in batches ordered by subscriber id, and 'delivering' once every recipient has one. The
+-- cursor is the id of the last subscriber queued so far.
+ALTER TABLE newsletter_issues ADD COLUMN enqueue_cursor uuid NULL;
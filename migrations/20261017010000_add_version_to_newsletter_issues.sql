@@ -0,0 +1,3 @@
+-- Bumped on every edit of a draft; editors send the version they started from, so that an edit
+-- made in the meantime is not silently overwritten.
+ALTER TABLE newsletter_issues ADD COLUMN version INTEGER NOT NULL DEFAULT 0;
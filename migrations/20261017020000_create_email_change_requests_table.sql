@@ -0,0 +1,9 @@
+-- Address changes requested by subscribers; the new address only replaces the old one once
+-- the link sent to it has been followed.
+CREATE TABLE email_change_requests (
+    change_token TEXT NOT NULL,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    new_email TEXT NOT NULL,
+    expires_at timestamptz NOT NULL,
+    PRIMARY KEY (change_token)
+);
@@ -0,0 +1,37 @@
+-- A deployment can run several newsletters, i.e. several lists. Subscriptions, their tokens,
+-- issues and delivery tasks all belong to one of them; existing rows go to the newsletter created
+-- here, which stays the default one as the first created.
+BEGIN;
+    CREATE TABLE newsletters (
+        newsletter_id uuid NOT NULL,
+        slug TEXT NOT NULL UNIQUE,
+        name TEXT NOT NULL,
+        created_at timestamptz NOT NULL DEFAULT now(),
+        PRIMARY KEY (newsletter_id)
+    );
+    INSERT INTO newsletters (newsletter_id, slug, name)
+    VALUES (gen_random_uuid(), 'newsletter', 'Newsletter');
+
+    ALTER TABLE subscriptions
+        ADD COLUMN newsletter_id uuid NULL REFERENCES newsletters (newsletter_id);
+    UPDATE subscriptions SET newsletter_id = (SELECT newsletter_id FROM newsletters);
+    ALTER TABLE subscriptions ALTER COLUMN newsletter_id SET NOT NULL;
+    -- the same address can subscribe to several newsletters
+    ALTER TABLE subscriptions DROP CONSTRAINT subscriptions_email_key;
+    ALTER TABLE subscriptions ADD UNIQUE (newsletter_id, email);
+
+    ALTER TABLE subscription_tokens
+        ADD COLUMN newsletter_id uuid NULL REFERENCES newsletters (newsletter_id);
+    UPDATE subscription_tokens SET newsletter_id = (SELECT newsletter_id FROM newsletters);
+    ALTER TABLE subscription_tokens ALTER COLUMN newsletter_id SET NOT NULL;
+
+    ALTER TABLE newsletter_issues
+        ADD COLUMN newsletter_id uuid NULL REFERENCES newsletters (newsletter_id);
+    UPDATE newsletter_issues SET newsletter_id = (SELECT newsletter_id FROM newsletters);
+    ALTER TABLE newsletter_issues ALTER COLUMN newsletter_id SET NOT NULL;
+
+    ALTER TABLE issue_delivery_queue
+        ADD COLUMN newsletter_id uuid NULL REFERENCES newsletters (newsletter_id);
+    UPDATE issue_delivery_queue SET newsletter_id = (SELECT newsletter_id FROM newsletters);
+    ALTER TABLE issue_delivery_queue ALTER COLUMN newsletter_id SET NOT NULL;
+COMMIT;
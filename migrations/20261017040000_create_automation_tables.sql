@@ -0,0 +1,31 @@
+-- Automation sequences, e.g. a welcome series, are emails that every subscriber of a newsletter
+-- receives once they confirm, each a number of days after their confirmation. A sequence has at
+-- most one step per day offset, and its steps are sent in the order of their offsets.
+CREATE TABLE automation_sequences (
+    sequence_id uuid NOT NULL,
+    newsletter_id uuid NOT NULL REFERENCES newsletters (newsletter_id),
+    name TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (sequence_id)
+);
+
+CREATE TABLE automation_steps (
+    sequence_id uuid NOT NULL REFERENCES automation_sequences (sequence_id),
+    day_offset INT NOT NULL CHECK (day_offset >= 0),
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    PRIMARY KEY (sequence_id, day_offset)
+);
+
+-- One row per subscriber going through a sequence, due when its next step is. The step is the
+-- first one with an offset of at least `next_day_offset`; the row is deleted after the last one.
+CREATE TABLE automation_schedule (
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    sequence_id uuid NOT NULL REFERENCES automation_sequences (sequence_id),
+    enrolled_at timestamptz NOT NULL,
+    next_day_offset INT NOT NULL DEFAULT 0,
+    execute_after timestamptz NOT NULL,
+    n_retries SMALLINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (subscriber_id, sequence_id)
+);
@@ -0,0 +1,3 @@
+-- When the subscriber unsubscribed, to chart unsubscribes over time. Unknown for the subscribers
+-- who unsubscribed before the column existed, and cleared when a subscriber signs up again.
+ALTER TABLE subscriptions ADD COLUMN unsubscribed_at timestamptz NULL;
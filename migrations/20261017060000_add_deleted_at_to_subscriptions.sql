@@ -0,0 +1,5 @@
+-- Subscribers deleted from the admin pages are hidden rather than removed, so that they can be
+-- restored until a background job purges them for good
+ALTER TABLE subscriptions ADD COLUMN deleted_at timestamptz NULL;
+CREATE INDEX subscriptions_deleted_at_idx ON subscriptions (deleted_at)
+    WHERE deleted_at IS NOT NULL;
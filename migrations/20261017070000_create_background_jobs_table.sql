@@ -0,0 +1,17 @@
+-- Background work that does not have a queue of its own, polled by the same worker loop as the
+-- deliveries. Recurring jobs are rescheduled after each run and exist at most once per type.
+CREATE TABLE background_jobs (
+    job_id uuid PRIMARY KEY,
+    job_type TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    -- NULL for jobs that run once
+    repeat_every_seconds BIGINT NULL CHECK (repeat_every_seconds > 0),
+    n_retries SMALLINT NOT NULL DEFAULT 0,
+    execute_after timestamptz NOT NULL DEFAULT now(),
+    created_at timestamptz NOT NULL DEFAULT now(),
+    -- set when a job that runs once has used up its retries; it is kept for inspection
+    failed_at timestamptz NULL,
+    last_error TEXT NULL
+);
+CREATE UNIQUE INDEX background_jobs_recurring_idx ON background_jobs (job_type)
+    WHERE repeat_every_seconds IS NOT NULL;
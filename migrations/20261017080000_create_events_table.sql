@@ -0,0 +1,12 @@
+-- A feed of the changes to subscribers and issues, for clients polling the API. The ids are the
+-- cursors of the feed, so they must only ever grow.
+CREATE TABLE events (
+    event_id BIGSERIAL PRIMARY KEY,
+    event_type TEXT NOT NULL,
+    -- the subscriber or issue the event is about
+    subject_id uuid NOT NULL,
+    data JSONB NOT NULL,
+    occurred_at timestamptz NOT NULL DEFAULT now()
+);
+-- erasing a subscriber erases the events about them
+CREATE INDEX events_subject_id_idx ON events (subject_id);
@@ -0,0 +1,23 @@
+-- Every change of subscriptions.status, appended in the same transaction as the change, so that
+-- the status of a subscriber at any point in time can be reconstructed. Rows are never updated or
+-- deleted, not even when the subscriber is erased: they hold no personal data.
+CREATE TABLE subscription_events (
+    subscription_event_id BIGSERIAL PRIMARY KEY,
+    subscriber_id uuid NOT NULL,
+    -- NULL when the subscriber was created
+    from_status TEXT NULL,
+    to_status TEXT NOT NULL,
+    occurred_at timestamptz NOT NULL DEFAULT now()
+);
+CREATE INDEX subscription_events_subscriber_id_idx
+    ON subscription_events (subscriber_id, subscription_event_id);
+
+-- the existing subscribers get the history we can tell from their row: their signup, then the
+-- change to their current status, the steps in between being unknown
+INSERT INTO subscription_events (subscriber_id, from_status, to_status, occurred_at)
+SELECT id, NULL, 'pending_confirmation', subscribed_at
+FROM subscriptions;
+INSERT INTO subscription_events (subscriber_id, from_status, to_status, occurred_at)
+SELECT id, 'pending_confirmation', status, COALESCE(unsubscribed_at, subscribed_at)
+FROM subscriptions
+WHERE status <> 'pending_confirmation';
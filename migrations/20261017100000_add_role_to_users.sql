@@ -0,0 +1,3 @@
+-- Every user is an administrator for now: the role is carried along with the authenticated user
+-- so that handlers can tell users apart once other roles exist.
+ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'admin';
@@ -0,0 +1,8 @@
+-- One row per background worker process, updated on every iteration of its loop, so that a stuck
+-- worker can be told apart from one with nothing to do
+CREATE TABLE worker_heartbeats (
+    worker_id uuid PRIMARY KEY,
+    hostname TEXT NOT NULL,
+    started_at timestamptz NOT NULL,
+    last_beat_at timestamptz NOT NULL
+);
@@ -0,0 +1,5 @@
+-- The id the email provider gave each delivery, which its webhooks refer to, and when the
+-- provider reported the email as accepted by the recipient's mail server
+ALTER TABLE issue_deliveries
+    ADD COLUMN provider_message_id TEXT NULL UNIQUE,
+    ADD COLUMN confirmed_at timestamptz NULL;
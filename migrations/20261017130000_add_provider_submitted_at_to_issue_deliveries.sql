@@ -0,0 +1,2 @@
+-- When the email provider says it accepted the message, for support lookups against its logs
+ALTER TABLE issue_deliveries ADD COLUMN provider_submitted_at timestamptz NULL;
@@ -0,0 +1,2 @@
+-- Issues that are sent even while the send window is closed
+ALTER TABLE newsletter_issues ADD COLUMN ignores_send_window BOOLEAN NOT NULL DEFAULT false;
@@ -0,0 +1,4 @@
+-- The IANA time zone of a subscriber, e.g. 'Europe/Paris', for the issues sent at a local time
+ALTER TABLE subscriptions ADD COLUMN time_zone TEXT NULL;
+-- Issues sent at this time of day in the time zone of each subscriber, rather than right away
+ALTER TABLE newsletter_issues ADD COLUMN local_send_time TIME NULL;
@@ -0,0 +1,10 @@
+-- Images uploaded by the admins, to be linked from the content of issues
+CREATE TABLE media(
+    media_id uuid PRIMARY KEY,
+    file_name TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    url TEXT NOT NULL,
+    uploaded_by uuid REFERENCES users (user_id) ON DELETE SET NULL,
+    uploaded_at timestamptz NOT NULL
+);
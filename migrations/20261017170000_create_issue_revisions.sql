@@ -0,0 +1,14 @@
+-- The earlier content of drafts, kept whenever a save replaces it so that it can be restored
+CREATE TABLE issue_revisions(
+    revision_id uuid PRIMARY KEY,
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    -- The version of the draft that had this content
+    version INTEGER NOT NULL,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    replaced_at timestamptz NOT NULL,
+    replaced_by uuid REFERENCES users (user_id) ON DELETE SET NULL
+);
+CREATE INDEX issue_revisions_newsletter_issue_id_idx
+    ON issue_revisions (newsletter_issue_id, version);
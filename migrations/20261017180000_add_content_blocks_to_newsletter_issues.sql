@@ -0,0 +1,4 @@
+-- The document of the rich text editor a draft is written with, stored as is so that the editor
+-- can load it again. The text and HTML content remain what is sent to subscribers; saves made
+-- outside of the editor clear the document, which no longer matches them.
+ALTER TABLE newsletter_issues ADD COLUMN content_blocks JSONB NULL;
@@ -0,0 +1,13 @@
+-- Layouts the content of issues is put into when they are published, so that the header, footer
+-- and styles of the emails do not have to be pasted into every issue
+CREATE TABLE email_templates(
+    email_template_id uuid PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    html_layout TEXT NOT NULL,
+    text_layout TEXT NOT NULL,
+    created_at timestamptz NOT NULL,
+    updated_at timestamptz NOT NULL
+);
+-- The template an issue is published with, picked while it is a draft
+ALTER TABLE newsletter_issues
+    ADD COLUMN email_template_id uuid NULL REFERENCES email_templates (email_template_id);
@@ -0,0 +1,15 @@
+-- Proof of the opt-in of subscribers: one row every time they sign up, with where and under which
+-- wording they did. The rows hold personal data, so they are deleted when the subscriber is erased.
+CREATE TABLE subscription_consents(
+    consent_id uuid PRIMARY KEY,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+    consented_at timestamptz NOT NULL,
+    ip_address TEXT NULL,
+    -- the version of the consent text shown by the form, e.g. one per country
+    consent_text_version TEXT NULL,
+    -- ISO 3166-1 alpha-2 code of the country whose consent rules the form applies
+    country TEXT NULL,
+    source_url TEXT NULL
+);
+CREATE INDEX subscription_consents_subscriber_id_idx
+    ON subscription_consents (subscriber_id, consented_at);
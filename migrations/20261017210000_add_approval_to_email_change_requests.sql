@@ -0,0 +1,8 @@
+-- Address changes are first approved from the current address: the unsubscribe token that starts
+-- them is in every issue, so a forwarded issue must not be enough to move a subscription. Pending
+-- requests were never approved this way, so they are dropped; subscribers can ask again.
+DELETE FROM email_change_requests;
+ALTER TABLE email_change_requests ADD COLUMN approval_token TEXT NOT NULL;
+ALTER TABLE email_change_requests ADD COLUMN approved_at timestamptz;
+CREATE UNIQUE INDEX email_change_requests_approval_token_idx
+    ON email_change_requests (approval_token);
@@ -0,0 +1,7 @@
+-- A single row recording what the purges of expired idempotency keys did, so that the metrics of
+-- every process report it, whichever worker ran the purge
+CREATE TABLE idempotency_cleanup_stats (
+    id BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (id),
+    rows_purged_total BIGINT NOT NULL,
+    last_run_at timestamptz NOT NULL
+);
@@ -0,0 +1,84 @@
+use anyhow::Context;
+use chrono::NaiveDate;
+use sqlx::PgPool;
+
+/// The length of the periods a growth series is broken into
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    #[default]
+    Day,
+    /// Weeks start on Monday
+    Week,
+    Month,
+}
+
+impl Granularity {
+    /// The field name `date_trunc` expects
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Granularity::Day => "day",
+            Granularity::Week => "week",
+            Granularity::Month => "month",
+        }
+    }
+}
+
+/// The signups and unsubscribes of one period
+#[derive(Debug, serde::Serialize)]
+pub struct GrowthPoint {
+    /// The first day of the period, in UTC
+    pub period_start: NaiveDate,
+    pub signups: i64,
+    pub unsubscribes: i64,
+}
+
+impl GrowthPoint {
+    pub fn net(&self) -> i64 {
+        self.signups - self.unsubscribes
+    }
+}
+
+/// Counts the signups and unsubscribes of each of the last `n_periods` periods, the oldest first
+/// and the current one last, including the periods in which nothing happened
+#[tracing::instrument(name = "Get subscriber growth", skip(pool))]
+pub async fn get_subscriber_growth(
+    pool: &PgPool,
+    granularity: Granularity,
+    n_periods: i32,
+) -> Result<Vec<GrowthPoint>, anyhow::Error> {
+    let points = sqlx::query_as!(
+        GrowthPoint,
+        r#"
+        WITH periods AS (
+            SELECT generate_series(
+                date_trunc($1, now() AT TIME ZONE 'UTC') - ($2 - 1) * ('1 ' || $1)::interval,
+                date_trunc($1, now() AT TIME ZONE 'UTC'),
+                ('1 ' || $1)::interval
+            ) AS start
+        )
+        SELECT
+            periods.start::date AS "period_start!",
+            (
+                SELECT COUNT(*) FROM subscriptions
+                WHERE
+                    date_trunc($1, subscribed_at AT TIME ZONE 'UTC') = periods.start AND
+                    deleted_at IS NULL
+            ) AS "signups!",
+            (
+                SELECT COUNT(*) FROM subscriptions
+                WHERE
+                    date_trunc($1, unsubscribed_at AT TIME ZONE 'UTC') = periods.start AND
+                    deleted_at IS NULL
+            ) AS "unsubscribes!"
+        FROM periods
+        ORDER BY periods.start
+        "#,
+        granularity.as_str(),
+        n_periods
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve the subscriber growth.")?;
+    Ok(points)
+}
@@ -0,0 +1,169 @@
+mod growth;
+mod overview;
+pub use growth::*;
+pub use overview::*;
+
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::click_tracking::{get_click_stats, LinkClicks};
+use crate::delivery_progress::get_delivery_progress;
+
+/// The delivery and engagement figures of a newsletter issue
+#[derive(Debug, serde::Serialize)]
+pub struct IssueAnalytics {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub status: String,
+    pub recipients: i64,
+    pub delivered: i64,
+    /// Deliveries the email provider refused, after all retries
+    pub failed: i64,
+    pub pending: i64,
+    /// Opens and clicks, only collected for issues published with tracking
+    pub engagement: Option<Engagement>,
+}
+
+/// How recipients interacted with a tracked issue
+#[derive(Debug, serde::Serialize)]
+pub struct Engagement {
+    pub opens: i64,
+    /// Recipients who opened the issue at least once
+    pub unique_opens: i64,
+    /// Recipients who followed at least one link
+    pub unique_clicks: i64,
+    pub links: Vec<LinkClicks>,
+}
+
+impl IssueAnalytics {
+    /// The share of processed deliveries that failed, as a percentage
+    pub fn failure_rate(&self) -> Option<f64> {
+        percentage(self.failed, self.delivered + self.failed)
+    }
+
+    /// The share of delivered emails that were opened, as a percentage
+    pub fn open_rate(&self) -> Option<f64> {
+        let engagement = self.engagement.as_ref()?;
+        percentage(engagement.unique_opens, self.delivered)
+    }
+
+    /// The share of delivered emails in which a link was followed, as a percentage
+    pub fn click_through_rate(&self) -> Option<f64> {
+        let engagement = self.engagement.as_ref()?;
+        percentage(engagement.unique_clicks, self.delivered)
+    }
+}
+
+/// Returns `None` rather than dividing by zero
+fn percentage(count: i64, total: i64) -> Option<f64> {
+    (total > 0).then(|| count as f64 * 100.0 / total as f64)
+}
+
+/// Gathers the analytics of an issue; returns `None` if the issue does not exist
+#[tracing::instrument(name = "Get issue analytics", skip(pool))]
+pub async fn get_issue_analytics(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<IssueAnalytics>, anyhow::Error> {
+    let Some(progress) = get_delivery_progress(pool, issue_id).await? else {
+        return Ok(None);
+    };
+    let engagement = match get_click_stats(pool, issue_id).await? {
+        Some(clicks) => {
+            let opens = get_open_counts(pool, issue_id).await?;
+            Some(Engagement {
+                opens: opens.opens,
+                unique_opens: opens.unique_opens,
+                unique_clicks: clicks.clickers,
+                links: clicks.links,
+            })
+        }
+        None => None,
+    };
+    Ok(Some(IssueAnalytics {
+        newsletter_issue_id: issue_id,
+        recipients: progress.total(),
+        title: progress.title,
+        status: progress.status,
+        delivered: progress.delivered,
+        failed: progress.failed,
+        pending: progress.pending,
+        engagement,
+    }))
+}
+
+struct OpenCounts {
+    opens: i64,
+    unique_opens: i64,
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_open_counts(pool: &PgPool, issue_id: Uuid) -> Result<OpenCounts, anyhow::Error> {
+    let counts = sqlx::query_as!(
+        OpenCounts,
+        r#"
+        SELECT
+            COUNT(*) AS "opens!",
+            COUNT(DISTINCT delivery_id) AS "unique_opens!"
+        FROM email_opens
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to perform a query to retrieve the opens of the issue.")?;
+    Ok(counts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Engagement, IssueAnalytics};
+    use claims::{assert_none, assert_some_eq};
+    use uuid::Uuid;
+
+    fn analytics(delivered: i64, failed: i64, engagement: Option<Engagement>) -> IssueAnalytics {
+        IssueAnalytics {
+            newsletter_issue_id: Uuid::nil(),
+            title: "Title".into(),
+            status: "delivering".into(),
+            recipients: delivered + failed,
+            delivered,
+            failed,
+            pending: 0,
+            engagement,
+        }
+    }
+
+    fn engagement(unique_opens: i64, unique_clicks: i64) -> Option<Engagement> {
+        Some(Engagement {
+            opens: unique_opens * 2,
+            unique_opens,
+            unique_clicks,
+            links: vec![],
+        })
+    }
+
+    #[test]
+    fn rates_are_relative_to_delivered_emails() {
+        let analytics = analytics(8, 2, engagement(4, 2));
+        assert_some_eq!(analytics.failure_rate(), 20.0);
+        assert_some_eq!(analytics.open_rate(), 50.0);
+        assert_some_eq!(analytics.click_through_rate(), 25.0);
+    }
+
+    #[test]
+    fn engagement_rates_are_unknown_without_tracking() {
+        let analytics = analytics(8, 2, None);
+        assert_none!(analytics.open_rate());
+        assert_none!(analytics.click_through_rate());
+    }
+
+    #[test]
+    fn rates_are_unknown_before_the_first_delivery() {
+        let analytics = analytics(0, 0, engagement(0, 0));
+        assert_none!(analytics.failure_rate());
+        assert_none!(analytics.open_rate());
+    }
+}
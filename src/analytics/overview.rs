@@ -0,0 +1,153 @@
+use anyhow::Context;
+use chrono::NaiveDate;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::delivery_progress::{get_delivery_progress, DeliveryProgress};
+
+/// How many days of signups the overview covers, today included
+pub const RECENT_SIGNUP_DAYS: i32 = 14;
+
+/// The figures summarizing the state of the newsletter on the admin dashboard
+#[derive(Debug)]
+pub struct Overview {
+    pub subscribers: SubscriberCounts,
+    /// One entry per day, the oldest first, including days without signups
+    pub recent_signups: Vec<DailySignups>,
+    /// The most recently published issue, if any has been
+    pub last_issue: Option<LastIssue>,
+    pub queue_depth: QueueDepth,
+}
+
+#[derive(Debug)]
+pub struct SubscriberCounts {
+    pub total: i64,
+    pub confirmed: i64,
+    pub pending: i64,
+    pub unsubscribed: i64,
+}
+
+#[derive(Debug)]
+pub struct DailySignups {
+    /// The day, in UTC
+    pub day: NaiveDate,
+    pub signups: i64,
+}
+
+#[derive(Debug)]
+pub struct LastIssue {
+    pub newsletter_issue_id: Uuid,
+    pub progress: DeliveryProgress,
+}
+
+/// The work waiting for the delivery worker
+#[derive(Debug)]
+pub struct QueueDepth {
+    /// Emails of published issues not sent yet
+    pub issue_deliveries: i64,
+    /// Emails of automation sequences due now
+    pub automation_steps: i64,
+}
+
+/// Gathers the figures of the admin dashboard
+#[tracing::instrument(name = "Get overview statistics", skip(pool))]
+pub async fn get_overview(pool: &PgPool) -> Result<Overview, anyhow::Error> {
+    let last_issue = match get_last_issue_id(pool).await? {
+        Some(issue_id) => get_delivery_progress(pool, issue_id)
+            .await?
+            .map(|progress| LastIssue {
+                newsletter_issue_id: issue_id,
+                progress,
+            }),
+        None => None,
+    };
+    Ok(Overview {
+        subscribers: get_subscriber_counts(pool).await?,
+        recent_signups: get_recent_signups(pool).await?,
+        last_issue,
+        queue_depth: get_queue_depth(pool).await?,
+    })
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_subscriber_counts(pool: &PgPool) -> Result<SubscriberCounts, anyhow::Error> {
+    let counts = sqlx::query_as!(
+        SubscriberCounts,
+        r#"
+        SELECT
+            COUNT(*) AS "total!",
+            COUNT(*) FILTER (WHERE status = 'confirmed') AS "confirmed!",
+            COUNT(*) FILTER (WHERE status = 'pending_confirmation') AS "pending!",
+            COUNT(*) FILTER (WHERE status = 'unsubscribed') AS "unsubscribed!"
+        FROM subscriptions
+        WHERE deleted_at IS NULL
+        "#
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to perform a query to count the subscribers.")?;
+    Ok(counts)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_recent_signups(pool: &PgPool) -> Result<Vec<DailySignups>, anyhow::Error> {
+    let signups = sqlx::query_as!(
+        DailySignups,
+        r#"
+        SELECT
+            days.day AS "day!",
+            COUNT(subscriptions.id) AS "signups!"
+        FROM (
+            SELECT (now() AT TIME ZONE 'UTC')::date - offsets.n AS day
+            FROM generate_series(0, $1 - 1) AS offsets(n)
+        ) days
+        LEFT JOIN subscriptions
+            ON
+                (subscriptions.subscribed_at AT TIME ZONE 'UTC')::date = days.day AND
+                subscriptions.deleted_at IS NULL
+        GROUP BY days.day
+        ORDER BY days.day
+        "#,
+        RECENT_SIGNUP_DAYS
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to count the recent signups.")?;
+    Ok(signups)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_last_issue_id(pool: &PgPool) -> Result<Option<Uuid>, anyhow::Error> {
+    let issue_id = sqlx::query_scalar!(
+        r#"
+        SELECT newsletter_issue_id
+        FROM newsletter_issues
+        WHERE status <> 'draft'
+        ORDER BY published_at DESC
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve the last published issue.")?;
+    Ok(issue_id)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_queue_depth(pool: &PgPool) -> Result<QueueDepth, anyhow::Error> {
+    let depth = sqlx::query_as!(
+        QueueDepth,
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM issue_delivery_queue) AS "issue_deliveries!",
+            (
+                SELECT COUNT(*) FROM automation_schedule
+                WHERE execute_after <= now()
+            ) AS "automation_steps!"
+        "#
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to perform a query to measure the delivery queue.")?;
+    Ok(depth)
+}
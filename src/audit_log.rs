@@ -0,0 +1,26 @@
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+/// Appends an entry to the audit log. It should be called in the same transaction as the
+/// operation it records, so that one is never committed without the other.
+#[tracing::instrument(name = "Record audit log entry", skip(transaction))]
+pub async fn record_audit_entry(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Option<Uuid>,
+    action: &str,
+    subject: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_log (audit_log_id, user_id, action, subject, created_at)
+        VALUES ($1, $2, $3, $4, now())
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        action,
+        subject
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
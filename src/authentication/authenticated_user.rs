@@ -0,0 +1,103 @@
+use actix_web::dev::Payload;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest};
+use anyhow::Context;
+use futures_util::future::LocalBoxFuture;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::UserId;
+use crate::routing_helpers::e500;
+
+/// What a user is allowed to do, as stored in `users.role`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserRole {
+    Admin,
+}
+
+impl UserRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserRole::Admin => "admin",
+        }
+    }
+}
+
+impl TryFrom<String> for UserRole {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        match value.as_str() {
+            "admin" => Ok(Self::Admin),
+            other => Err(format!("{} is not a valid user role", other)),
+        }
+    }
+}
+
+/// The user a request was authenticated as by `reject_anonymous_users` or
+/// `reject_unauthenticated_api_clients`. The user is loaded the first time a handler or extractor
+/// asks for it, then kept in the extensions of the request for the next ones.
+#[derive(Debug, Clone)]
+pub struct AuthenticatedUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub role: UserRole,
+}
+
+impl FromRequest for AuthenticatedUser {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move {
+            if let Some(user) = req.extensions().get::<AuthenticatedUser>() {
+                return Ok(user.clone());
+            }
+            let user_id = req
+                .extensions()
+                .get::<UserId>()
+                .copied()
+                .ok_or_else(|| e500("The route is not behind an authentication middleware"))?;
+            let pool = req
+                .app_data::<web::Data<PgPool>>()
+                .ok_or_else(|| e500("The connection pool is not registered"))?;
+            let user = get_authenticated_user(pool, *user_id).await.map_err(e500)?;
+            req.extensions_mut().insert(user.clone());
+            Ok(user)
+        })
+    }
+}
+
+#[tracing::instrument(name = "Get authenticated user", skip(pool))]
+async fn get_authenticated_user(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<AuthenticatedUser, anyhow::Error> {
+    let row = sqlx::query!(
+        "SELECT username, role FROM users WHERE user_id = $1",
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to perform a query to retrieve the authenticated user.")?;
+    Ok(AuthenticatedUser {
+        user_id,
+        username: row.username,
+        role: UserRole::try_from(row.role).map_err(anyhow::Error::msg)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UserRole;
+    use claims::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn roles_round_trip_through_their_database_representation() {
+        assert_ok_eq!(
+            UserRole::try_from(UserRole::Admin.as_str().to_string()),
+            UserRole::Admin
+        );
+        assert_err!(UserRole::try_from("superuser".to_string()));
+    }
+}
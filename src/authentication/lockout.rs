@@ -0,0 +1,175 @@
+use anyhow::Context;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+
+/// How many failed logins a client can make for an account before being locked out of it
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct LoginLockoutPolicy {
+    pub max_failures: i64,
+    /// Failures older than this are forgotten
+    pub window_seconds: i64,
+    /// How long a client stays locked out after its last failure
+    pub lockout_seconds: i64,
+}
+
+impl LoginLockoutPolicy {
+    /// Returns how long the client stays locked out, given its failures within the window
+    fn remaining_lockout(
+        &self,
+        recent_failures: i64,
+        last_failure: Option<DateTime<Utc>>,
+        now: DateTime<Utc>,
+    ) -> Option<std::time::Duration> {
+        if recent_failures < self.max_failures {
+            return None;
+        }
+        let locked_until = last_failure? + Duration::seconds(self.lockout_seconds);
+        (locked_until - now).to_std().ok().filter(|d| !d.is_zero())
+    }
+}
+
+/// Returns how long `ip_address` stays locked out of the account of `username`, if it is
+#[tracing::instrument(name = "Check login lockout", skip(pool, policy))]
+pub async fn login_lockout(
+    pool: &PgPool,
+    policy: &LoginLockoutPolicy,
+    username: &str,
+    ip_address: &str,
+) -> Result<Option<std::time::Duration>, anyhow::Error> {
+    let now = Utc::now();
+    let failures = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!", MAX(attempted_at) AS last_failure
+        FROM failed_login_attempts
+        WHERE username = $1 AND ip_address = $2 AND attempted_at > $3
+        "#,
+        username,
+        ip_address,
+        now - Duration::seconds(policy.window_seconds)
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count the recent failed logins")?;
+    Ok(policy.remaining_lockout(failures.count, failures.last_failure, now))
+}
+
+#[tracing::instrument(name = "Record failed login", skip(pool, policy))]
+pub async fn record_failed_login(
+    pool: &PgPool,
+    policy: &LoginLockoutPolicy,
+    username: &str,
+    ip_address: &str,
+) -> Result<(), anyhow::Error> {
+    let now = Utc::now();
+    let mut transaction = pool.begin().await?;
+    // failures that can no longer cause a lockout are dropped as new ones come in
+    sqlx::query!(
+        r#"
+        DELETE FROM failed_login_attempts
+        WHERE username = $1 AND attempted_at < $2
+        "#,
+        username,
+        now - Duration::seconds(policy.window_seconds.max(policy.lockout_seconds))
+    )
+    .execute(&mut transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO failed_login_attempts (username, ip_address, attempted_at)
+        VALUES ($1, $2, $3)
+        "#,
+        username,
+        ip_address,
+        now
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Forgets the failed logins of a client once it successfully logged in
+#[tracing::instrument(name = "Clear failed logins", skip(pool))]
+pub async fn clear_failed_logins(
+    pool: &PgPool,
+    username: &str,
+    ip_address: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM failed_login_attempts
+        WHERE username = $1 AND ip_address = $2
+        "#,
+        username,
+        ip_address
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Formats a cooldown for humans, rounded up to the minute
+pub fn format_cooldown(cooldown: std::time::Duration) -> String {
+    let minutes = (cooldown.as_secs() + 59) / 60;
+    if minutes <= 1 {
+        "1 minute".into()
+    } else {
+        format!("{} minutes", minutes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_cooldown, LoginLockoutPolicy};
+    use chrono::{Duration, Utc};
+    use claims::{assert_none, assert_some_eq};
+
+    const POLICY: LoginLockoutPolicy = LoginLockoutPolicy {
+        max_failures: 3,
+        window_seconds: 600,
+        lockout_seconds: 900,
+    };
+
+    #[test]
+    fn clients_under_the_failure_limit_are_not_locked_out() {
+        let now = Utc::now();
+        assert_none!(POLICY.remaining_lockout(2, Some(now), now));
+    }
+
+    #[test]
+    fn the_lockout_lasts_from_the_last_failure() {
+        let now = Utc::now();
+        let last_failure = now - Duration::seconds(300);
+        assert_some_eq!(
+            POLICY.remaining_lockout(3, Some(last_failure), now),
+            std::time::Duration::from_secs(600)
+        );
+    }
+
+    #[test]
+    fn the_lockout_expires() {
+        let now = Utc::now();
+        let last_failure = now - Duration::seconds(900);
+        assert_none!(POLICY.remaining_lockout(5, Some(last_failure), now));
+    }
+
+    #[test]
+    fn cooldowns_are_rounded_up_to_the_minute() {
+        assert_eq!(
+            format_cooldown(std::time::Duration::from_secs(5)),
+            "1 minute"
+        );
+        assert_eq!(
+            format_cooldown(std::time::Duration::from_secs(60)),
+            "1 minute"
+        );
+        assert_eq!(
+            format_cooldown(std::time::Duration::from_secs(61)),
+            "2 minutes"
+        );
+        assert_eq!(
+            format_cooldown(std::time::Duration::from_secs(900)),
+            "15 minutes"
+        );
+    }
+}
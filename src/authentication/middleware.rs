@@ -1,10 +1,24 @@
+use crate::authentication::{
+    format_cooldown, get_totp_secret, login_lockout, record_failed_login,
+    remember_me_removal_cookie, use_persistent_login, validate_credentials, AuthError, Credentials,
+    LoginLockoutPolicy, PasswordHashingPolicy, REMEMBER_ME_COOKIE,
+};
+use crate::configuration::SessionSettings;
+use crate::reverse_proxy::ClientInfo;
+use crate::routes::{login_page, ApiError};
 use crate::routing_helpers::{e500, see_other};
 use crate::session_state::TypedSession;
 use actix_web::body::MessageBody;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::error::InternalError;
-use actix_web::{FromRequest, HttpMessage};
+use actix_web::http::header::{HeaderMap, AUTHORIZATION};
+use actix_web::http::Method;
+use actix_web::{web, FromRequest, HttpMessage};
 use actix_web_lab::middleware::Next;
+use anyhow::Context;
+use base64::Engine;
+use secrecy::Secret;
+use sqlx::PgPool;
 use std::fmt::Formatter;
 use std::ops::Deref;
 use uuid::Uuid;
@@ -18,17 +32,215 @@ pub async fn reject_anonymous_users(
         TypedSession::from_request(http_request, payload).await
     }?;
 
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .cloned()
+        .ok_or_else(|| e500("The connection pool is not registered"))?;
+    let session_settings = req
+        .app_data::<web::Data<SessionSettings>>()
+        .cloned()
+        .ok_or_else(|| e500("The session settings are not registered"))?;
+    let session_user_id = session.get_user_id().map_err(e500)?;
+    if let Some(user_id) = session_user_id {
+        // expired sessions, and those of deactivated users or opened before the last password
+        // change, are terminated on their next request
+        if is_valid_session(&pool, &session, &session_settings, user_id)
+            .await
+            .map_err(e500)?
+        {
             req.extensions_mut().insert(UserId(user_id));
-            next.call(req).await
+            return next.call(req).await;
+        }
+    }
+
+    // users who ticked "Remember me" are logged back in without a session
+    let remember_me_token = req.cookie(REMEMBER_ME_COOKIE);
+    let persistent_login = match &remember_me_token {
+        Some(token) => {
+            use_persistent_login(&pool, token.value(), session_settings.remember_me_ttl())
+                .await
+                .map_err(e500)?
+        }
+        None => None,
+    };
+    if let Some(login) = persistent_login {
+        session.renew();
+        session
+            .insert_user_id(login.user_id, login.password_version)
+            .map_err(e500)?;
+        req.extensions_mut().insert(UserId(login.user_id));
+        let mut response = next.call(req).await?;
+        response
+            .response_mut()
+            .add_cookie(&login.cookie)
+            .map_err(e500)?;
+        return Ok(response);
+    }
+
+    // users are sent back to the page they asked for once logged in; forms posted without a
+    // session are not submitted again
+    let next = (req.method() == Method::GET)
+        .then(|| req.uri().path_and_query())
+        .flatten()
+        .map(|path_and_query| path_and_query.as_str());
+    let mut response = see_other(&login_page(next));
+    if remember_me_token.is_some() {
+        response
+            .add_cookie(&remember_me_removal_cookie())
+            .map_err(e500)?;
+    }
+    let e = if session_user_id.is_some() {
+        session.log_out();
+        anyhow::anyhow!("The session is no longer valid")
+    } else {
+        anyhow::anyhow!("The user has not logged in")
+    };
+    Err(InternalError::from_response(e, response).into())
+}
+
+/// Authenticates clients of the admin endpoints of the JSON API, either through the session of a
+/// logged-in user or with HTTP Basic credentials. Failures get the API error envelope rather than
+/// a redirect to the login page.
+pub async fn reject_unauthenticated_api_clients(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .cloned()
+        .ok_or_else(|| e500("The connection pool is not registered"))?;
+    let session_settings = req
+        .app_data::<web::Data<SessionSettings>>()
+        .cloned()
+        .ok_or_else(|| e500("The session settings are not registered"))?;
+
+    let user_id = match session.get_user_id().map_err(e500)? {
+        Some(user_id) => {
+            if !is_valid_session(&pool, &session, &session_settings, user_id)
+                .await
+                .map_err(e500)?
+            {
+                return Err(
+                    ApiError::Unauthorized("The session is no longer valid.".into()).into(),
+                );
+            }
+            user_id
         }
         None => {
-            let response = see_other("/login");
-            let e = anyhow::anyhow!("The user has not logged in");
-            Err(InternalError::from_response(e, response).into())
+            let user_id = authenticate_basic_credentials(&req, &pool).await?;
+            if !is_active_user(&pool, user_id).await.map_err(e500)? {
+                return Err(ApiError::Unauthorized("The user has been deactivated.".into()).into());
+            }
+            user_id
         }
+    };
+    req.extensions_mut().insert(UserId(user_id));
+    next.call(req).await
+}
+
+/// Validates the HTTP Basic credentials of the request, subject to the same lockout as the login
+/// form. Users with two-factor authentication must use a session: a password alone is not enough.
+async fn authenticate_basic_credentials(
+    req: &ServiceRequest,
+    pool: &PgPool,
+) -> Result<Uuid, ApiError> {
+    let credentials =
+        basic_credentials(req.headers()).map_err(|e| ApiError::Unauthorized(format!("{}.", e)))?;
+    let lockout_policy = req
+        .app_data::<web::Data<LoginLockoutPolicy>>()
+        .context("The login lockout policy is not registered")?;
+    let ip_address = ClientInfo::of(req.request())
+        .map(|client| client.ip.to_string())
+        .unwrap_or_else(|| "unknown".into());
+    let username = credentials.username.clone();
+
+    if let Some(cooldown) = login_lockout(pool, lockout_policy, &username, &ip_address).await? {
+        return Err(ApiError::TooManyRequests(format!(
+            "Too many failed login attempts. Try again in {}.",
+            format_cooldown(cooldown)
+        )));
     }
+    let hashing_policy = req
+        .app_data::<web::Data<PasswordHashingPolicy>>()
+        .context("The password hashing policy is not registered")?;
+    let user_id = match validate_credentials(credentials, hashing_policy, pool).await {
+        Ok(user_id) => user_id,
+        Err(AuthError::InvalidCredentials(_)) => {
+            record_failed_login(pool, lockout_policy, &username, &ip_address).await?;
+            return Err(ApiError::Unauthorized("Invalid credentials.".into()));
+        }
+        Err(AuthError::UnexpectedError(e)) => return Err(ApiError::UnexpectedError(e)),
+    };
+    if get_totp_secret(pool, user_id).await?.is_some() {
+        return Err(ApiError::Unauthorized(
+            "This user has two-factor authentication enabled: log in to get a session instead."
+                .into(),
+        ));
+    }
+    Ok(user_id)
+}
+
+/// Extracts the credentials of an `Authorization: Basic <base64(username:password)>` header
+pub fn basic_credentials(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+    let header_value = headers
+        .get(AUTHORIZATION)
+        .context("The 'Authorization' header is missing")?
+        .to_str()
+        .context("The 'Authorization' header is not a valid UTF8 string")?;
+    let base64_encoded = header_value
+        .strip_prefix("Basic ")
+        .context("The authorization scheme is not 'Basic'")?;
+    let decoded_bytes = base64::engine::general_purpose::STANDARD
+        .decode(base64_encoded)
+        .context("Failed to base64-decode the 'Basic' credentials")?;
+    let decoded = String::from_utf8(decoded_bytes)
+        .context("The decoded credentials are not a valid UTF8 string")?;
+    let (username, password) = decoded
+        .split_once(':')
+        .context("The 'Basic' credentials must be formatted as 'username:password'")?;
+    Ok(Credentials {
+        username: username.to_owned(),
+        password: Secret::new(password.to_owned()),
+    })
+}
+
+#[tracing::instrument(name = "Check that the user is active", skip(pool))]
+async fn is_active_user(pool: &PgPool, user_id: Uuid) -> Result<bool, sqlx::Error> {
+    let user = sqlx::query!("SELECT is_active FROM users WHERE user_id = $1", user_id)
+        .fetch_optional(pool)
+        .await?;
+    Ok(user.map_or(false, |user| user.is_active))
+}
+
+/// A session stays valid until its TTL, while its user is active and has not changed their
+/// password since. Idle sessions are dropped by the session store.
+#[tracing::instrument(
+    name = "Check that the session is valid",
+    skip(pool, session, session_settings)
+)]
+async fn is_valid_session(
+    pool: &PgPool,
+    session: &TypedSession,
+    session_settings: &SessionSettings,
+    user_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    if session.is_expired(session_settings.ttl())? {
+        return Ok(false);
+    }
+    let password_version = session.get_password_version()?;
+    let user = sqlx::query!(
+        "SELECT is_active, password_version FROM users WHERE user_id = $1",
+        user_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(user.map_or(false, |user| {
+        user.is_active && user.password_version == password_version
+    }))
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -47,3 +259,31 @@ impl Deref for UserId {
         &self.0
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::basic_credentials;
+    use actix_web::http::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+    use secrecy::ExposeSecret;
+
+    fn headers(authorization: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(authorization).unwrap());
+        headers
+    }
+
+    #[test]
+    fn basic_credentials_are_decoded() {
+        // base64 of "ursula:le:guin": passwords may contain colons
+        let credentials = basic_credentials(&headers("Basic dXJzdWxhOmxlOmd1aW4=")).unwrap();
+        assert_eq!(credentials.username, "ursula");
+        assert_eq!(credentials.password.expose_secret(), "le:guin");
+    }
+
+    #[test]
+    fn other_authorization_schemes_are_rejected() {
+        assert!(basic_credentials(&headers("Bearer dXJzdWxhOmxlOmd1aW4=")).is_err());
+        assert!(basic_credentials(&headers("Basic not base64!")).is_err());
+        assert!(basic_credentials(&HeaderMap::new()).is_err());
+    }
+}
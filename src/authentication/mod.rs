@@ -1,4 +1,26 @@
+mod authenticated_user;
+mod lockout;
 mod middleware;
 mod password;
-pub use middleware::{reject_anonymous_users, UserId};
-pub use password::{change_password, validate_credentials, AuthError, Credentials};
+mod persistent_login;
+mod totp;
+mod two_factor;
+pub use authenticated_user::{AuthenticatedUser, UserRole};
+pub use lockout::{
+    clear_failed_logins, format_cooldown, login_lockout, record_failed_login, LoginLockoutPolicy,
+};
+pub use middleware::{
+    basic_credentials, reject_anonymous_users, reject_unauthenticated_api_clients, UserId,
+};
+pub use password::{
+    change_password, create_user, get_password_version, validate_credentials,
+    validate_new_password, AuthError, Credentials, PasswordHashingPolicy,
+};
+pub use persistent_login::{
+    issue_persistent_login, remember_me_removal_cookie, revoke_persistent_login,
+    use_persistent_login, PersistentLogin, REMEMBER_ME_COOKIE,
+};
+pub use totp::{constant_time_eq, TotpSecret};
+pub use two_factor::{
+    disable_two_factor, enable_two_factor, get_totp_secret, verify_second_factor,
+};
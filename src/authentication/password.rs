@@ -3,6 +3,7 @@ use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
 use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
+use validator::HasLen;
 
 use crate::async_helpers;
 use crate::async_helpers::spawn_blocking_with_tracing;
@@ -20,18 +21,74 @@ pub struct Credentials {
     pub password: Secret<String>,
 }
 
-/// Validates user credentials and returns user's ID
-#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+/// The cost of the Argon2id hashes of new passwords, and the pepper mixed into them. Stored
+/// hashes made with other parameters, or without the pepper, are upgraded when their user logs in.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct PasswordHashingPolicy {
+    /// Memory used to compute a hash, in KiB
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    /// A secret kept out of the database, so that a leaked `users` table cannot be cracked alone.
+    /// Losing it locks every user out.
+    #[serde(default)]
+    pub pepper: Option<Secret<String>>,
+}
+
+impl PasswordHashingPolicy {
+    fn params(&self) -> Result<Params, anyhow::Error> {
+        Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+            .map_err(|e| anyhow::anyhow!("Invalid password hashing parameters: {}", e))
+    }
+
+    fn hasher<'a>(&self, pepper: Option<&'a Secret<String>>) -> Result<Argon2<'a>, anyhow::Error> {
+        let params = self.params()?;
+        match pepper {
+            Some(pepper) => Argon2::new_with_secret(
+                pepper.expose_secret().as_bytes(),
+                Algorithm::Argon2id,
+                Version::V0x13,
+                params,
+            )
+            .map_err(|e| anyhow::anyhow!("Invalid password pepper: {}", e)),
+            None => Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params)),
+        }
+    }
+
+    /// A hash no password matches, which costs as much to check as the hash of a user
+    fn dummy_hash(&self) -> Secret<String> {
+        Secret::new(format!(
+            "$argon2id$v=19$m={},t={},p={}$gZiV/M1gPc22ElAH/Jh1Hw$CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno",
+            self.memory_kib, self.iterations, self.parallelism
+        ))
+    }
+
+    /// Whether a hash verified with or without the pepper was made with another policy
+    fn is_outdated(&self, hash: &PasswordHash, peppered: bool) -> bool {
+        let Ok(params) = Params::try_from(hash) else {
+            return true;
+        };
+        hash.algorithm != Algorithm::Argon2id.ident()
+            || hash.version != Some(Version::V0x13.into())
+            || params.m_cost() != self.memory_kib
+            || params.t_cost() != self.iterations
+            || params.p_cost() != self.parallelism
+            || peppered != self.pepper.is_some()
+    }
+}
+
+/// Validates user credentials and returns user's ID. The stored hash is replaced on the way if it
+/// was made with an outdated policy.
+#[tracing::instrument(name = "Validate credentials", skip(credentials, policy, pool))]
 pub async fn validate_credentials(
     credentials: Credentials,
+    policy: &PasswordHashingPolicy,
     pool: &PgPool,
 ) -> Result<uuid::Uuid, AuthError> {
     // setting default credentials so that we have a password to check; this eliminates a possible timing attack
     // that we would be vulnerable to if we exited early upon finding an invalid username
     let mut user_id = None;
-    let mut expected_password_hash = Secret::new(
-        "$argon2id$v=19$m=15000,t=2,p=1$gZiV/M1gPc22ElAH/Jh1Hw$CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno".to_string()
-    );
+    let mut expected_password_hash = policy.dummy_hash();
     if let Some((stored_user_id, stored_password_hash)) =
         get_stored_credentials(&credentials.username, pool).await?
     {
@@ -42,40 +99,106 @@ pub async fn validate_credentials(
     // `verify_password` can take 5-10 ms to complete; in order to avoid blocking the async scheduler,
     // we're moving the work to a blocking thread. Remember the rule of thumb: async functions should
     // never go too long without reaching an await.
-    async_helpers::spawn_blocking_with_tracing(move || {
-        verify_password_hash(expected_password_hash, credentials.password)
+    let verification_policy = policy.clone();
+    let verified_hash = expected_password_hash.clone();
+    let password = credentials.password.clone();
+    let is_outdated = async_helpers::spawn_blocking_with_tracing(move || {
+        verify_password_hash(&verification_policy, verified_hash, password)
     })
     .await
     .context("Failed to spawn blocking task.")??;
 
     // if user_id is still None at this point, then we never found a valid user from `get_stored_credentials`
-    user_id
+    let user_id = user_id
         .ok_or_else(|| anyhow::anyhow!("Unknown username"))
-        .map_err(AuthError::InvalidCredentials)
+        .map_err(AuthError::InvalidCredentials)?;
+    if is_outdated {
+        // the user is logged in either way: the hash is upgraded on their next login otherwise
+        if let Err(e) = rehash_password(
+            user_id,
+            expected_password_hash,
+            credentials.password,
+            policy,
+            pool,
+        )
+        .await
+        {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to upgrade an outdated password hash.",
+            );
+        }
+    }
+    Ok(user_id)
 }
 
+/// Checks a password against a hash, returning whether the hash should be upgraded. Hashes made
+/// before the pepper was configured are checked without it.
 #[tracing::instrument(
     name = "Verify password hash",
-    skip(expected_password_hash, password_candidate)
+    skip(policy, expected_password_hash, password_candidate)
 )]
 fn verify_password_hash(
+    policy: &PasswordHashingPolicy,
     expected_password_hash: Secret<String>,
     password_candidate: Secret<String>,
-) -> Result<(), AuthError> {
+) -> Result<bool, AuthError> {
     // PasswordHash implements UHC string format, which encodes the hashing algorithm, the algo version,
     // the algo load parameters, the hash, and the salt; this makes it easy to refactor our code to update
     // any of these values in the future. We can migrate old user hashes because the old configuration is
     // encoded in the hash itself.
     let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
         .context("Failed to parse hash in PHC string format.")?;
+    let password = password_candidate.expose_secret().as_bytes();
 
-    Argon2::default()
-        .verify_password(
-            password_candidate.expose_secret().as_bytes(),
-            &expected_password_hash,
-        )
+    // the parameters of the hash itself are used to verify it, only the pepper comes from the policy
+    if let Some(pepper) = &policy.pepper {
+        if policy
+            .hasher(Some(pepper))?
+            .verify_password(password, &expected_password_hash)
+            .is_ok()
+        {
+            return Ok(policy.is_outdated(&expected_password_hash, true));
+        }
+    }
+    policy
+        .hasher(None)?
+        .verify_password(password, &expected_password_hash)
         .context("Invalid password")
-        .map_err(AuthError::InvalidCredentials)
+        .map_err(AuthError::InvalidCredentials)?;
+    Ok(policy.is_outdated(&expected_password_hash, false))
+}
+
+/// Replaces the hash of a password with one following the current policy. Unlike a password change,
+/// this keeps the sessions of the user open. Nothing happens if the password was changed meanwhile.
+#[tracing::instrument(name = "Rehash password", skip_all)]
+async fn rehash_password(
+    user_id: uuid::Uuid,
+    outdated_hash: Secret<String>,
+    password: Secret<String>,
+    policy: &PasswordHashingPolicy,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let policy = policy.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(&policy, password))
+            .await?
+            .context("Failed to hash password")?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $1
+        WHERE user_id = $2 AND password_hash = $3
+        "#,
+        password_hash.expose_secret(),
+        user_id,
+        outdated_hash.expose_secret(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the upgraded password hash.")?;
+    Ok(())
 }
 
 /// Gets stored user credentials based on a username. Returns a tuple of user id and the user's
@@ -89,51 +212,124 @@ async fn get_stored_credentials(
         r#"
         SELECT user_id, password_hash
         FROM users
-        WHERE username = $1
+        WHERE username = $1 AND is_active
         "#,
         username,
     )
     .fetch_optional(pool)
     .await
     .context("Failed to perform a query to retrieve stored credentials")?
-    .map(|row| (row.user_id, Secret::new(row.password_hash)));
+    // invited users cannot log in until they have set their password
+    .and_then(|row| Some((row.user_id, Secret::new(row.password_hash?))));
     Ok(row)
 }
 
-/// Changes the password for the given user_id
-#[tracing::instrument(name = "Change password", skip(password, pool))]
+/// Changes the password for the given user_id, returning its new password version: sessions
+/// opened with an older version are no longer valid
+#[tracing::instrument(name = "Change password", skip(password, policy, pool))]
 pub async fn change_password(
     user_id: uuid::Uuid,
     password: Secret<String>,
+    policy: &PasswordHashingPolicy,
     pool: &PgPool,
-) -> Result<(), anyhow::Error> {
-    let password_hash = spawn_blocking_with_tracing(move || compute_password_hash(password))
-        .await?
-        .context("Failed to hash password")?;
-    sqlx::query!(
+) -> Result<i32, anyhow::Error> {
+    let policy = policy.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(&policy, password))
+            .await?
+            .context("Failed to hash password")?;
+    let user = sqlx::query!(
         r#"
         UPDATE users
-        SET password_hash = $1
+        SET password_hash = $1, password_version = password_version + 1
         WHERE user_id = $2
+        RETURNING password_version
         "#,
         password_hash.expose_secret(),
         user_id,
     )
-    .execute(pool)
+    .fetch_one(pool)
     .await
     .context("Failed to change user's password in the database.")?;
+    Ok(user.password_version)
+}
+
+/// The version of the user's current password, recorded in their session when they log in
+#[tracing::instrument(name = "Get password version", skip(pool))]
+pub async fn get_password_version(
+    pool: &PgPool,
+    user_id: uuid::Uuid,
+) -> Result<i32, anyhow::Error> {
+    let user = sqlx::query!(
+        "SELECT password_version FROM users WHERE user_id = $1",
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to perform a query to retrieve the password version.")?;
+    Ok(user.password_version)
+}
+
+/// Creates an active user who can log in straight away; returns `None` if the username is
+/// already taken
+#[tracing::instrument(name = "Create user", skip(password, policy, pool))]
+pub async fn create_user(
+    username: &str,
+    password: Secret<String>,
+    policy: &PasswordHashingPolicy,
+    pool: &PgPool,
+) -> Result<Option<uuid::Uuid>, anyhow::Error> {
+    let policy = policy.clone();
+    let password_hash =
+        spawn_blocking_with_tracing(move || compute_password_hash(&policy, password))
+            .await?
+            .context("Failed to hash password")?;
+    let user_id = uuid::Uuid::new_v4();
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, password_hash)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (username) DO NOTHING
+        "#,
+        user_id,
+        username,
+        password_hash.expose_secret(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to insert a new user in the database.")?
+    .rows_affected();
+    Ok((n_inserted > 0).then_some(user_id))
+}
+
+/// Checks that a new password is acceptable, returning the reason why it is not otherwise.
+/// `password_check` is the password typed a second time by the user.
+pub fn validate_new_password(
+    password: &Secret<String>,
+    password_check: &Secret<String>,
+) -> Result<(), &'static str> {
+    let password = password.expose_secret();
+    if password != password_check.expose_secret() {
+        return Err("You entered two different new passwords - the field values must match.");
+    }
+    if password.length() <= 12 {
+        return Err("Password must be at least 12 characters.");
+    }
+    if password.length() > 128 {
+        return Err("Password must be no more than 128 characters.");
+    }
     Ok(())
 }
 
 /// Computers the hash of a supplied password
-fn compute_password_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+fn compute_password_hash(
+    policy: &PasswordHashingPolicy,
+    password: Secret<String>,
+) -> Result<Secret<String>, anyhow::Error> {
     let salt = SaltString::generate(&mut rand::thread_rng());
-    let password_hash = Argon2::new(
-        Algorithm::Argon2id,
-        Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
-    )
-    .hash_password(password.expose_secret().as_bytes(), &salt)?
-    .to_string();
+    let password_hash = policy
+        .hasher(policy.pepper.as_ref())?
+        .hash_password(password.expose_secret().as_bytes(), &salt)?
+        .to_string();
     Ok(Secret::new(password_hash))
 }
@@ -0,0 +1,219 @@
+use actix_web::cookie::{time, Cookie, SameSite};
+use anyhow::Context;
+use chrono::Utc;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// The cookie holding the persistent login token of users who ticked "Remember me"
+pub const REMEMBER_ME_COOKIE: &str = "remember_me";
+
+/// A user logged back in from their persistent login token
+pub struct PersistentLogin {
+    pub user_id: Uuid,
+    pub password_version: i32,
+    /// Holds the rotated token, to send back to the browser
+    pub cookie: Cookie<'static>,
+}
+
+/// Creates a persistent login token for a user who just logged in, valid for `ttl`
+#[tracing::instrument(name = "Issue a persistent login", skip(pool))]
+pub async fn issue_persistent_login(
+    pool: &PgPool,
+    user_id: Uuid,
+    password_version: i32,
+    ttl: Duration,
+) -> Result<Cookie<'static>, anyhow::Error> {
+    let persistent_login_id = Uuid::new_v4();
+    let secret = generate_secret();
+    sqlx::query!(
+        r#"
+        INSERT INTO persistent_logins
+            (persistent_login_id, user_id, token_hash, password_version, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        persistent_login_id,
+        user_id,
+        hash_secret(&secret),
+        password_version,
+        Utc::now() + chrono::Duration::from_std(ttl)?
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store a persistent login.")?;
+    Ok(remember_me_cookie(persistent_login_id, &secret, ttl))
+}
+
+/// Logs a user back in from their persistent login token, which is then rotated and valid for
+/// another `ttl`. Returns `None` if the token is unknown or expired, if the user has been
+/// deactivated or has changed their password since.
+///
+/// A token whose secret has already been replaced is either a stale copy held by a thief or the
+/// original of a token a thief already used: every persistent login of the user is revoked.
+#[tracing::instrument(name = "Use a persistent login", skip(pool, token))]
+pub async fn use_persistent_login(
+    pool: &PgPool,
+    token: &str,
+    ttl: Duration,
+) -> Result<Option<PersistentLogin>, anyhow::Error> {
+    let Some((persistent_login_id, secret)) = parse_token(token) else {
+        return Ok(None);
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let login = sqlx::query!(
+        r#"
+        SELECT
+            persistent_logins.user_id,
+            persistent_logins.token_hash,
+            persistent_logins.password_version AS token_password_version,
+            users.password_version,
+            users.is_active
+        FROM persistent_logins
+        JOIN users ON users.user_id = persistent_logins.user_id
+        WHERE persistent_login_id = $1 AND expires_at > now()
+        FOR UPDATE OF persistent_logins
+        "#,
+        persistent_login_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to perform a query to retrieve a persistent login.")?;
+    let Some(login) = login else {
+        return Ok(None);
+    };
+
+    if login.token_hash != hash_secret(secret) {
+        sqlx::query!(
+            "DELETE FROM persistent_logins WHERE user_id = $1",
+            login.user_id
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to revoke the persistent logins of the user.")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to revoke persistent logins.")?;
+        tracing::warn!(user_id = %login.user_id, "A replaced persistent login token was reused.");
+        return Ok(None);
+    }
+    if !login.is_active || login.token_password_version != login.password_version {
+        sqlx::query!(
+            "DELETE FROM persistent_logins WHERE persistent_login_id = $1",
+            persistent_login_id
+        )
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete an outdated persistent login.")?;
+        transaction
+            .commit()
+            .await
+            .context("Failed to commit SQL transaction to delete a persistent login.")?;
+        return Ok(None);
+    }
+
+    let secret = generate_secret();
+    sqlx::query!(
+        r#"
+        UPDATE persistent_logins
+        SET token_hash = $1, expires_at = $2
+        WHERE persistent_login_id = $3
+        "#,
+        hash_secret(&secret),
+        Utc::now() + chrono::Duration::from_std(ttl)?,
+        persistent_login_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to rotate a persistent login token.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to rotate a persistent login token.")?;
+    Ok(Some(PersistentLogin {
+        user_id: login.user_id,
+        password_version: login.password_version,
+        cookie: remember_me_cookie(persistent_login_id, &secret, ttl),
+    }))
+}
+
+/// Deletes the persistent login of a token, when its user logs out
+#[tracing::instrument(name = "Revoke a persistent login", skip(pool, token))]
+pub async fn revoke_persistent_login(pool: &PgPool, token: &str) -> Result<(), anyhow::Error> {
+    if let Some((persistent_login_id, _)) = parse_token(token) {
+        sqlx::query!(
+            "DELETE FROM persistent_logins WHERE persistent_login_id = $1",
+            persistent_login_id
+        )
+        .execute(pool)
+        .await
+        .context("Failed to delete a persistent login.")?;
+    }
+    Ok(())
+}
+
+/// Tells the browser to forget its persistent login token
+pub fn remember_me_removal_cookie() -> Cookie<'static> {
+    let mut cookie = Cookie::build(REMEMBER_ME_COOKIE, "").path("/").finish();
+    cookie.make_removal();
+    cookie
+}
+
+fn remember_me_cookie(persistent_login_id: Uuid, secret: &str, ttl: Duration) -> Cookie<'static> {
+    Cookie::build(
+        REMEMBER_ME_COOKIE,
+        format!("{}:{}", persistent_login_id, secret),
+    )
+    .path("/")
+    .max_age(time::Duration::seconds(ttl.as_secs() as i64))
+    .secure(true)
+    .http_only(true)
+    .same_site(SameSite::Lax)
+    .finish()
+}
+
+/// Splits a `{persistent_login_id}:{secret}` token
+fn parse_token(token: &str) -> Option<(Uuid, &str)> {
+    let (persistent_login_id, secret) = token.split_once(':')?;
+    Some((Uuid::parse_str(persistent_login_id).ok()?, secret))
+}
+
+fn generate_secret() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+/// Secrets are random enough for a fast hash
+fn hash_secret(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_token, remember_me_cookie};
+    use claims::{assert_none, assert_some_eq};
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    #[test]
+    fn tokens_round_trip_through_the_cookie() {
+        let persistent_login_id = Uuid::new_v4();
+        let cookie = remember_me_cookie(persistent_login_id, "s3cret", Duration::from_secs(60));
+        assert_some_eq!(parse_token(cookie.value()), (persistent_login_id, "s3cret"));
+    }
+
+    #[test]
+    fn malformed_tokens_are_ignored() {
+        assert_none!(parse_token("s3cret"));
+        assert_none!(parse_token("not-a-uuid:s3cret"));
+    }
+}
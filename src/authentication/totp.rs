@@ -0,0 +1,186 @@
+//! Time-based one-time passwords (RFC 6238), as generated by authenticator apps.
+
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use secrecy::{ExposeSecret, Secret};
+use sha1::Sha1;
+
+/// Codes change every 30 seconds
+const TIME_STEP_SECONDS: u64 = 30;
+const DIGITS: u32 = 6;
+/// Codes of the previous and next time steps are also accepted, to make up for clock drift and
+/// for the time it takes to type them
+const ALLOWED_DRIFT_STEPS: u64 = 1;
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// A TOTP secret, in the base32 encoding shown to users
+pub struct TotpSecret(Secret<String>);
+
+impl TotpSecret {
+    /// Generates a random 160-bit secret, the size recommended for HMAC-SHA1
+    pub fn generate() -> Self {
+        let mut bytes = [0; 20];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        Self(Secret::new(base32_encode(&bytes)))
+    }
+
+    /// Wraps a secret previously returned by `expose_base32`
+    pub fn from_base32(secret: String) -> Self {
+        Self(Secret::new(secret))
+    }
+
+    pub fn expose_base32(&self) -> &str {
+        self.0.expose_secret()
+    }
+
+    /// The URI authenticator apps import, usually through a QR code
+    pub fn provisioning_uri(&self, issuer: &str, account: &str) -> String {
+        let label = urlencoding_component(&format!("{}:{}", issuer, account));
+        format!(
+            "otpauth://totp/{}?secret={}&issuer={}",
+            label,
+            self.expose_base32(),
+            urlencoding_component(issuer)
+        )
+    }
+
+    /// The code an authenticator app shows at `unix_time`
+    pub fn code_at(&self, unix_time: u64) -> String {
+        let key = base32_decode(self.expose_base32()).expect("The TOTP secret is not base32");
+        hotp(&key, unix_time / TIME_STEP_SECONDS)
+    }
+
+    /// Returns the time step `code` was generated for, if it is valid at `unix_time`.
+    /// Callers should refuse codes of a time step that has already been used, to prevent replays.
+    pub fn verify(&self, code: &str, unix_time: u64) -> Option<u64> {
+        let code = code.trim();
+        if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let key = base32_decode(self.expose_base32())?;
+        let current_step = unix_time / TIME_STEP_SECONDS;
+        (current_step.saturating_sub(ALLOWED_DRIFT_STEPS)..=current_step + ALLOWED_DRIFT_STEPS)
+            .find(|&step| constant_time_eq(hotp(&key, step).as_bytes(), code.as_bytes()))
+    }
+}
+
+/// HMAC-based one-time password (RFC 4226) for `counter`
+fn hotp(key: &[u8], counter: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        binary % 10_u32.pow(DIGITS),
+        width = DIGITS as usize
+    )
+}
+
+/// Compares secrets without leaking, through timing, how much of them matched
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Base32 (RFC 4648) without padding, as used by authenticator apps
+fn base32_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = buffer << 8 | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[(buffer >> bits) as usize & 31] as char);
+        }
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[(buffer << (5 - bits)) as usize & 31] as char);
+    }
+    encoded
+}
+
+fn base32_decode(encoded: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for c in encoded.trim_end_matches('=').bytes() {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|&a| a == c.to_ascii_uppercase())?;
+        buffer = buffer << 5 | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Some(bytes)
+}
+
+/// Percent-encodes everything but unreserved characters
+fn urlencoding_component(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base32_decode, base32_encode, hotp, TotpSecret};
+    use claims::{assert_none, assert_some_eq};
+
+    /// The SHA-1 key of the test vectors of RFC 6238
+    const RFC_KEY: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn codes_match_the_rfc_test_vectors() {
+        for (unix_time, code) in [
+            (59, "287082"),
+            (1111111109, "081804"),
+            (1111111111, "050471"),
+            (1234567890, "005924"),
+            (2000000000, "279037"),
+        ] {
+            assert_eq!(hotp(RFC_KEY, unix_time / 30), code);
+        }
+    }
+
+    #[test]
+    fn base32_matches_the_rfc_test_vectors() {
+        assert_eq!(base32_encode(b"foobar"), "MZXW6YTBOI");
+        assert_some_eq!(base32_decode("MZXW6YTBOI======"), b"foobar".to_vec());
+        assert_none!(base32_decode("not base32!"));
+    }
+
+    #[test]
+    fn codes_of_adjacent_time_steps_are_accepted() {
+        let secret = TotpSecret::from_base32(base32_encode(RFC_KEY));
+        assert_some_eq!(secret.verify("081804", 1111111109), 37037036);
+        assert_some_eq!(secret.verify("081804", 1111111109 + 30), 37037036);
+        assert_none!(secret.verify("081804", 1111111109 + 90));
+        assert_none!(secret.verify("000000", 1111111109));
+        assert_none!(secret.verify("81804", 1111111109));
+    }
+
+    #[test]
+    fn provisioning_uris_are_percent_encoded() {
+        let secret = TotpSecret::from_base32("MZXW6YTBOI".into());
+        assert_eq!(
+            secret.provisioning_uri("Newsletter", "ursula le guin"),
+            "otpauth://totp/Newsletter%3Aursula%20le%20guin?secret=MZXW6YTBOI&issuer=Newsletter"
+        );
+    }
+}
@@ -0,0 +1,217 @@
+use anyhow::Context;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::audit_log::record_audit_entry;
+use crate::authentication::totp::TotpSecret;
+
+/// How many recovery codes are issued when two-factor authentication is enabled
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Returns the TOTP secret of the user, if they enabled two-factor authentication
+#[tracing::instrument(name = "Get TOTP secret", skip(pool))]
+pub async fn get_totp_secret(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Option<TotpSecret>, anyhow::Error> {
+    let user = sqlx::query!("SELECT totp_secret FROM users WHERE user_id = $1", user_id)
+        .fetch_one(pool)
+        .await
+        .context("Failed to retrieve the TOTP secret of the user.")?;
+    Ok(user.totp_secret.map(TotpSecret::from_base32))
+}
+
+/// Enables two-factor authentication with a secret the user proved they set up, by entering the
+/// code of `verified_step`. Returns the recovery codes, which cannot be retrieved later.
+#[tracing::instrument(name = "Enable two-factor authentication", skip(pool, secret))]
+pub async fn enable_two_factor(
+    pool: &PgPool,
+    user_id: Uuid,
+    secret: &TotpSecret,
+    verified_step: u64,
+) -> Result<Vec<String>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = $2, totp_last_used_step = $3
+        WHERE user_id = $1
+        "#,
+        user_id,
+        secret.expose_base32(),
+        verified_step as i64
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to store the TOTP secret.")?;
+    let recovery_codes = replace_recovery_codes(&mut transaction, user_id).await?;
+    record_audit_entry(
+        &mut transaction,
+        Some(user_id),
+        "enable_two_factor",
+        &user_id.to_string(),
+    )
+    .await
+    .context("Failed to record the change in the audit log.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to enable two-factor authentication.")?;
+    Ok(recovery_codes)
+}
+
+#[tracing::instrument(name = "Disable two-factor authentication", skip(pool))]
+pub async fn disable_two_factor(pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = NULL, totp_last_used_step = NULL
+        WHERE user_id = $1
+        "#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to remove the TOTP secret.")?;
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1", user_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the recovery codes.")?;
+    record_audit_entry(
+        &mut transaction,
+        Some(user_id),
+        "disable_two_factor",
+        &user_id.to_string(),
+    )
+    .await
+    .context("Failed to record the change in the audit log.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to disable two-factor authentication.")?;
+    Ok(())
+}
+
+/// Checks a code from the authenticator app, or one of the recovery codes of the user. Either
+/// can only be used once.
+#[tracing::instrument(name = "Verify second factor", skip(pool, secret, code))]
+pub async fn verify_second_factor(
+    pool: &PgPool,
+    user_id: Uuid,
+    secret: &TotpSecret,
+    code: &str,
+    unix_time: u64,
+) -> Result<bool, anyhow::Error> {
+    if let Some(step) = secret.verify(code, unix_time) {
+        let n_updated = sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_last_used_step = $2
+            WHERE
+                user_id = $1 AND
+                (totp_last_used_step IS NULL OR totp_last_used_step < $2)
+            "#,
+            user_id,
+            step as i64
+        )
+        .execute(pool)
+        .await
+        .context("Failed to record the use of a TOTP code.")?
+        .rows_affected();
+        return Ok(n_updated == 1);
+    }
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE recovery_codes
+        SET used_at = now()
+        WHERE user_id = $1 AND code_hash = $2 AND used_at IS NULL
+        "#,
+        user_id,
+        hash_recovery_code(code)
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record the use of a recovery code.")?
+    .rows_affected();
+    Ok(n_updated == 1)
+}
+
+async fn replace_recovery_codes(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+) -> Result<Vec<String>, anyhow::Error> {
+    sqlx::query!("DELETE FROM recovery_codes WHERE user_id = $1", user_id)
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to delete the previous recovery codes.")?;
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect();
+    let hashes: Vec<String> = recovery_codes
+        .iter()
+        .map(|code| hash_recovery_code(code))
+        .collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO recovery_codes (user_id, code_hash)
+        SELECT $1, code_hash FROM UNNEST($2::text[]) AS code_hash
+        "#,
+        user_id,
+        &hashes
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store the recovery codes.")?;
+    Ok(recovery_codes)
+}
+
+/// Generates a code like `x4k2p-9qz7m`
+fn generate_recovery_code() -> String {
+    let mut rng = thread_rng();
+    let characters: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(|c| char::from(c).to_ascii_lowercase())
+        .take(10)
+        .collect();
+    format!("{}-{}", &characters[..5], &characters[5..])
+}
+
+/// Recovery codes are random enough for a fast hash; they are normalized first, so that they can
+/// be typed without the dash or in uppercase
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect();
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_recovery_code, hash_recovery_code};
+
+    #[test]
+    fn recovery_codes_are_two_groups_of_five_characters() {
+        let code = generate_recovery_code();
+        assert_eq!(code.len(), 11);
+        assert_eq!(code.chars().nth(5), Some('-'));
+    }
+
+    #[test]
+    fn recovery_codes_are_normalized_before_hashing() {
+        assert_eq!(
+            hash_recovery_code("x4k2p-9qz7m"),
+            hash_recovery_code(" X4K2P9QZ7M ")
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use tracing::field::display;
+use tracing::Span;
+use uuid::Uuid;
+
+use crate::configuration::EmailFooterSettings;
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClient, EmailOptions};
+use crate::jobs::{handle_send_outcome, ExecutionOutcome, RetryPolicy, SendOutcome};
+use crate::newsletter_content::{
+    append_footer_html, append_footer_text, personalize_html, personalize_text, Personalization,
+};
+use crate::newsletters::list_id;
+use crate::rate_limiter::TokenBucket;
+use crate::routes::mark_subscriber_as_unsubscribed;
+use crate::subscription_tokens::SubscriptionTokens;
+use crate::url_builder::UrlBuilder;
+
+type PostgresTransaction = Transaction<'static, Postgres>;
+
+/// Sends the next step of a subscriber going through an automation sequence, if one is due.
+/// Sent and abandoned steps move the subscriber on to the following one, which is scheduled the
+/// next time their row comes up.
+#[tracing::instrument(
+skip_all,
+fields(
+    subscriber_id=tracing::field::Empty,
+    sequence_id=tracing::field::Empty
+),
+err
+)]
+pub async fn try_execute_automation_step(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    retry_policy: &RetryPolicy,
+    urls: &UrlBuilder,
+    subscription_tokens: &SubscriptionTokens,
+    email_footer: Option<&EmailFooterSettings>,
+    rate_limiter: Option<&mut TokenBucket>,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let Some((mut transaction, task)) = dequeue_automation_task(pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current()
+        .record("subscriber_id", &display(task.subscriber_id))
+        .record("sequence_id", &display(task.sequence_id));
+    let Some(step) = get_next_step(&mut transaction, &task).await? else {
+        // every step has been sent
+        delete_automation_task(transaction, &task).await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    };
+    let due_at = task.enrolled_at + chrono::Duration::days(step.day_offset.into());
+    if due_at > Utc::now() {
+        schedule_step(transaction, &task, step.day_offset, due_at).await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+    let Some(recipient) = get_recipient(&mut transaction, task.subscriber_id).await? else {
+        tracing::info!("Dropping a subscriber who is no longer confirmed from a sequence.");
+        delete_automation_task(transaction, &task).await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    };
+    let subscriber_email = match SubscriberEmail::parse(recipient.email.clone()) {
+        Ok(subscriber_email) => subscriber_email,
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Dropping a subscriber from a sequence. Their stored contact details are invalid.",
+            );
+            delete_automation_task(transaction, &task).await?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+    let unsubscribe_url =
+        urls.unsubscribe_url(&subscription_tokens.unsubscribe_token(task.subscriber_id));
+    let personalization = Personalization {
+        name: &recipient.name,
+        email: &recipient.email,
+        unsubscribe_url: &unsubscribe_url,
+    };
+    let options = EmailOptions {
+        headers: vec![(
+            "List-Id".to_owned(),
+            list_id(
+                &step.newsletter_name,
+                &step.newsletter_slug,
+                urls.base_url(),
+            ),
+        )],
+        ..Default::default()
+    };
+    let (html_content, text_content) = match email_footer {
+        Some(footer) => (
+            append_footer_html(&step.html_content, footer),
+            append_footer_text(&step.text_content, footer),
+        ),
+        None => (step.html_content, step.text_content),
+    };
+    let next_day_offset = step.day_offset + 1;
+    if let Some(rate_limiter) = rate_limiter {
+        rate_limiter.acquire().await;
+    }
+    let outcome = email_client
+        .send_email_with(
+            email_client.sender_of(&step.newsletter_slug),
+            &subscriber_email,
+            &step.title,
+            &personalize_html(&html_content, &personalization),
+            &personalize_text(&text_content, &personalization),
+            &options,
+        )
+        .await;
+    match handle_send_outcome(outcome, task.n_retries, retry_policy, "an automation step") {
+        SendOutcome::Sent(_) => {
+            schedule_step(transaction, &task, next_day_offset, Utc::now()).await?
+        }
+        SendOutcome::Retry { n_retries, backoff } => {
+            let execute_after = Utc::now() + chrono::Duration::from_std(backoff)?;
+            retry_step(transaction, &task, n_retries, execute_after).await?;
+        }
+        // the subscriber leaves the sequence along with the newsletter
+        SendOutcome::Rejected { .. } => {
+            delete_automation_task(transaction, &task).await?;
+            mark_subscriber_as_unsubscribed(task.subscriber_id, pool).await?;
+        }
+        // the step is skipped, the rest of the sequence still goes out
+        SendOutcome::GiveUp { .. } => {
+            schedule_step(transaction, &task, next_day_offset, Utc::now()).await?;
+        }
+    }
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+struct AutomationTask {
+    subscriber_id: Uuid,
+    sequence_id: Uuid,
+    enrolled_at: DateTime<Utc>,
+    next_day_offset: i32,
+    n_retries: i16,
+}
+
+/// Dequeues a subscriber whose next step is due
+#[tracing::instrument(skip_all)]
+async fn dequeue_automation_task(
+    pool: &PgPool,
+) -> Result<Option<(PostgresTransaction, AutomationTask)>, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let task = sqlx::query_as!(
+        AutomationTask,
+        r#"
+        SELECT subscriber_id, sequence_id, enrolled_at, next_day_offset, n_retries
+        FROM automation_schedule
+        WHERE execute_after <= now()
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    Ok(task.map(|task| (transaction, task)))
+}
+
+struct NextStep {
+    day_offset: i32,
+    title: String,
+    text_content: String,
+    html_content: String,
+    /// Slug of the newsletter of the sequence, which picks the sender identity
+    newsletter_slug: String,
+    newsletter_name: String,
+}
+
+/// The first step of the sequence that has not been sent to the subscriber yet, if any
+#[tracing::instrument(skip_all)]
+async fn get_next_step(
+    transaction: &mut PostgresTransaction,
+    task: &AutomationTask,
+) -> Result<Option<NextStep>, anyhow::Error> {
+    let step = sqlx::query_as!(
+        NextStep,
+        r#"
+        SELECT
+            day_offset,
+            title,
+            text_content,
+            html_content,
+            slug AS newsletter_slug,
+            newsletters.name AS newsletter_name
+        FROM automation_steps
+        JOIN automation_sequences USING (sequence_id)
+        JOIN newsletters USING (newsletter_id)
+        WHERE
+            sequence_id = $1 AND
+            day_offset >= $2
+        ORDER BY day_offset
+        LIMIT 1
+        "#,
+        task.sequence_id,
+        task.next_day_offset
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(step)
+}
+
+/// The details needed to personalize a step for the subscriber
+struct Recipient {
+    email: String,
+    name: String,
+}
+
+/// Looks up the subscriber; returns `None` if they are no longer confirmed or were deleted
+#[tracing::instrument(skip_all)]
+async fn get_recipient(
+    transaction: &mut PostgresTransaction,
+    subscriber_id: Uuid,
+) -> Result<Option<Recipient>, anyhow::Error> {
+    let recipient = sqlx::query_as!(
+        Recipient,
+        r#"
+        SELECT email, name
+        FROM subscriptions
+        WHERE
+            id = $1 AND
+            status = 'confirmed' AND
+            deleted_at IS NULL
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(recipient)
+}
+
+/// Moves the subscriber on to the first step with an offset of at least `next_day_offset`,
+/// looked up once `execute_after` has passed
+#[tracing::instrument(skip_all)]
+async fn schedule_step(
+    mut transaction: PostgresTransaction,
+    task: &AutomationTask,
+    next_day_offset: i32,
+    execute_after: DateTime<Utc>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE automation_schedule
+        SET
+            next_day_offset = $3,
+            execute_after = $4,
+            n_retries = 0
+        WHERE
+            subscriber_id = $1 AND
+            sequence_id = $2
+        "#,
+        task.subscriber_id,
+        task.sequence_id,
+        next_day_offset,
+        execute_after
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Reschedules a step that failed to send
+#[tracing::instrument(skip_all)]
+async fn retry_step(
+    mut transaction: PostgresTransaction,
+    task: &AutomationTask,
+    n_retries: i16,
+    execute_after: DateTime<Utc>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE automation_schedule
+        SET
+            n_retries = $3,
+            execute_after = $4
+        WHERE
+            subscriber_id = $1 AND
+            sequence_id = $2
+        "#,
+        task.subscriber_id,
+        task.sequence_id,
+        n_retries,
+        execute_after
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_automation_task(
+    mut transaction: PostgresTransaction,
+    task: &AutomationTask,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM automation_schedule
+        WHERE
+            subscriber_id = $1 AND
+            sequence_id = $2
+        "#,
+        task.subscriber_id,
+        task.sequence_id
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
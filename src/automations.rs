@@ -0,0 +1,114 @@
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// A sequence of emails, e.g. a welcome series, that the subscribers of a newsletter receive
+/// once they confirm
+pub struct AutomationSequence {
+    pub sequence_id: Uuid,
+    pub name: String,
+    pub newsletter_name: String,
+    pub n_steps: i64,
+    /// Subscribers who have not received every step yet
+    pub n_enrolled: i64,
+}
+
+/// One of the emails of a sequence, sent `day_offset` days after the subscriber confirmed
+pub struct AutomationStep {
+    pub day_offset: i32,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+}
+
+/// Every automation sequence, the oldest first
+#[tracing::instrument(name = "Get automation sequences", skip_all)]
+pub async fn get_sequences(pool: &PgPool) -> Result<Vec<AutomationSequence>, sqlx::Error> {
+    sqlx::query_as!(
+        AutomationSequence,
+        r#"
+        SELECT
+            sequence_id,
+            automation_sequences.name,
+            newsletters.name AS newsletter_name,
+            (SELECT COUNT(*) FROM automation_steps s WHERE s.sequence_id = automation_sequences.sequence_id)
+                AS "n_steps!",
+            (SELECT COUNT(*) FROM automation_schedule s WHERE s.sequence_id = automation_sequences.sequence_id)
+                AS "n_enrolled!"
+        FROM automation_sequences
+        JOIN newsletters USING (newsletter_id)
+        ORDER BY automation_sequences.created_at
+        "#
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Finds the sequence with the given id
+#[tracing::instrument(name = "Find an automation sequence", skip(pool))]
+pub async fn find_sequence(
+    pool: &PgPool,
+    sequence_id: Uuid,
+) -> Result<Option<AutomationSequence>, sqlx::Error> {
+    sqlx::query_as!(
+        AutomationSequence,
+        r#"
+        SELECT
+            sequence_id,
+            automation_sequences.name,
+            newsletters.name AS newsletter_name,
+            (SELECT COUNT(*) FROM automation_steps s WHERE s.sequence_id = $1) AS "n_steps!",
+            (SELECT COUNT(*) FROM automation_schedule s WHERE s.sequence_id = $1) AS "n_enrolled!"
+        FROM automation_sequences
+        JOIN newsletters USING (newsletter_id)
+        WHERE sequence_id = $1
+        "#,
+        sequence_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// The steps of a sequence, in the order they are sent
+#[tracing::instrument(name = "Get the steps of an automation sequence", skip(pool))]
+pub async fn get_steps(
+    pool: &PgPool,
+    sequence_id: Uuid,
+) -> Result<Vec<AutomationStep>, sqlx::Error> {
+    sqlx::query_as!(
+        AutomationStep,
+        r#"
+        SELECT day_offset, title, text_content, html_content
+        FROM automation_steps
+        WHERE sequence_id = $1
+        ORDER BY day_offset
+        "#,
+        sequence_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Schedules the sequences of the newsletter of a subscriber who just confirmed. The rows are due
+/// right away: the delivery worker pushes them back to their first step if it is not due yet.
+#[tracing::instrument(
+    name = "Enroll a subscriber in automation sequences",
+    skip(transaction)
+)]
+pub async fn enroll_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO automation_schedule (subscriber_id, sequence_id, enrolled_at, execute_after)
+        SELECT $1, sequence_id, now(), now()
+        FROM automation_sequences
+        WHERE newsletter_id = (SELECT newsletter_id FROM subscriptions WHERE id = $1)
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
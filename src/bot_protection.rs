@@ -0,0 +1,140 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use std::time::Duration;
+
+/// Detects automated submissions of the public subscribe form.
+///
+/// The form contains a honeypot field, hidden from humans, and a signed token recording when it
+/// was rendered: bots tend to fill in every field and to submit the form straight away, or to
+/// replay a token they harvested once.
+pub struct BotProtection {
+    min_fill_time: Duration,
+    max_form_age: Duration,
+    hmac_secret: Secret<String>,
+}
+
+impl BotProtection {
+    /// A `min_fill_time` of zero disables the time trap, which allows API clients and forms
+    /// hosted elsewhere, which have no form token. The tokens that are sent are still checked, so
+    /// that a harvested one stops working after `max_form_age`.
+    pub fn new(
+        min_fill_time: Duration,
+        max_form_age: Duration,
+        hmac_secret: Secret<String>,
+    ) -> Self {
+        Self {
+            min_fill_time,
+            max_form_age,
+            hmac_secret,
+        }
+    }
+
+    /// Issues the token embedded in the form, recording that it was rendered at `now`
+    pub fn form_token(&self, now: DateTime<Utc>) -> String {
+        let timestamp = now.timestamp();
+        format!("{}-{}", timestamp, hex::encode(self.sign(timestamp)))
+    }
+
+    /// Returns `true` if the submission looks like it was made by a bot
+    pub fn is_bot(&self, honeypot: &str, form_token: &str, now: DateTime<Utc>) -> bool {
+        if !honeypot.is_empty() {
+            return true;
+        }
+        if self.min_fill_time.is_zero() && form_token.is_empty() {
+            return false;
+        }
+        match self.rendered_at(form_token) {
+            Some(rendered_at) => {
+                let fill_time = (now.timestamp() - rendered_at).max(0) as u64;
+                fill_time < self.min_fill_time.as_secs() || fill_time > self.max_form_age.as_secs()
+            }
+            None => true,
+        }
+    }
+
+    /// Extracts the render timestamp of a token, if its signature is valid
+    fn rendered_at(&self, form_token: &str) -> Option<i64> {
+        let (timestamp, signature) = form_token.split_once('-')?;
+        let timestamp: i64 = timestamp.parse().ok()?;
+        let signature = hex::decode(signature).ok()?;
+        self.mac(timestamp).verify_slice(&signature).ok()?;
+        Some(timestamp)
+    }
+
+    fn sign(&self, timestamp: i64) -> Vec<u8> {
+        self.mac(timestamp).finalize().into_bytes().to_vec()
+    }
+
+    fn mac(&self, timestamp: i64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.hmac_secret.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size");
+        mac.update(b"subscribe-form:");
+        mac.update(timestamp.to_string().as_bytes());
+        mac
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BotProtection;
+    use chrono::{Duration as ChronoDuration, Utc};
+    use secrecy::Secret;
+    use std::time::Duration;
+
+    fn protection(min_fill_seconds: u64) -> BotProtection {
+        BotProtection::new(
+            Duration::from_secs(min_fill_seconds),
+            Duration::from_secs(60 * 60),
+            Secret::new("secret".to_string()),
+        )
+    }
+
+    #[test]
+    fn a_filled_honeypot_is_a_bot() {
+        assert!(protection(0).is_bot("http://spam.example.com", "", Utc::now()));
+    }
+
+    #[test]
+    fn the_time_trap_can_be_disabled() {
+        assert!(!protection(0).is_bot("", "", Utc::now()));
+    }
+
+    #[test]
+    fn forms_submitted_too_quickly_are_bots() {
+        let protection = protection(3);
+        let rendered_at = Utc::now();
+        let token = protection.form_token(rendered_at);
+        assert!(protection.is_bot("", &token, rendered_at + ChronoDuration::seconds(1)));
+        assert!(!protection.is_bot("", &token, rendered_at + ChronoDuration::seconds(5)));
+    }
+
+    #[test]
+    fn forms_rendered_too_long_ago_are_bots() {
+        let protection = protection(3);
+        let rendered_at = Utc::now();
+        let token = protection.form_token(rendered_at);
+        assert!(!protection.is_bot("", &token, rendered_at + ChronoDuration::minutes(59)));
+        assert!(protection.is_bot("", &token, rendered_at + ChronoDuration::minutes(61)));
+    }
+
+    #[test]
+    fn the_age_of_forms_is_checked_without_the_time_trap() {
+        let protection = protection(0);
+        let rendered_at = Utc::now();
+        let token = protection.form_token(rendered_at);
+        assert!(!protection.is_bot("", &token, rendered_at));
+        assert!(protection.is_bot("", &token, rendered_at + ChronoDuration::minutes(61)));
+        assert!(protection.is_bot("", "123-deadbeef", rendered_at));
+    }
+
+    #[test]
+    fn missing_or_forged_tokens_are_bots() {
+        let protection = protection(3);
+        let now = Utc::now();
+        let forged = format!("{}-deadbeef", (now - ChronoDuration::hours(1)).timestamp());
+        assert!(protection.is_bot("", "", now));
+        assert!(protection.is_bot("", &forged, now));
+    }
+}
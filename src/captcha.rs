@@ -0,0 +1,204 @@
+use reqwest::{Client, Url};
+use secrecy::{ExposeSecret, Secret};
+
+/// The CAPTCHA service protecting the subscribe form
+#[derive(serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptchaProvider {
+    HCaptcha,
+    Turnstile,
+}
+
+impl CaptchaProvider {
+    fn default_verify_url(&self) -> &'static str {
+        match self {
+            CaptchaProvider::HCaptcha => "https://api.hcaptcha.com/siteverify",
+            CaptchaProvider::Turnstile => {
+                "https://challenges.cloudflare.com/turnstile/v0/siteverify"
+            }
+        }
+    }
+
+    fn script_url(&self) -> &'static str {
+        match self {
+            CaptchaProvider::HCaptcha => "https://js.hcaptcha.com/1/api.js",
+            CaptchaProvider::Turnstile => "https://challenges.cloudflare.com/turnstile/v0/api.js",
+        }
+    }
+
+    fn widget_class(&self) -> &'static str {
+        match self {
+            CaptchaProvider::HCaptcha => "h-captcha",
+            CaptchaProvider::Turnstile => "cf-turnstile",
+        }
+    }
+}
+
+/// Checks CAPTCHA responses against the provider's siteverify API
+pub struct CaptchaClient {
+    provider: CaptchaProvider,
+    http_client: Client,
+    verify_url: Url,
+    site_key: String,
+    secret_key: Secret<String>,
+}
+
+impl CaptchaClient {
+    /// `verify_url` overrides the provider's siteverify endpoint
+    pub fn new(
+        provider: CaptchaProvider,
+        verify_url: Option<String>,
+        site_key: String,
+        secret_key: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        let verify_url = verify_url
+            .as_deref()
+            .unwrap_or(provider.default_verify_url());
+        let verify_url = Url::parse(verify_url).expect("Failed to parse the captcha verify_url");
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            provider,
+            http_client,
+            verify_url,
+            site_key,
+            secret_key,
+        }
+    }
+
+    /// Returns whether the provider accepts `response`, the token submitted with the form
+    pub async fn verify(&self, response: &str) -> Result<bool, reqwest::Error> {
+        if response.is_empty() {
+            return Ok(false);
+        }
+        let request_body = SiteVerifyRequest {
+            secret: self.secret_key.expose_secret(),
+            response,
+        };
+        let verification: SiteVerifyResponse = self
+            .http_client
+            .post(self.verify_url.clone())
+            .form(&request_body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if !verification.success {
+            tracing::info!(
+                error_codes = ?verification.error_codes,
+                "The captcha provider rejected a response."
+            );
+        }
+        Ok(verification.success)
+    }
+
+    /// The script loading the provider's widget into the subscribe form
+    pub fn script_url(&self) -> &'static str {
+        self.provider.script_url()
+    }
+
+    /// The class of the element the provider's script turns into the widget
+    pub fn widget_class(&self) -> &'static str {
+        self.provider.widget_class()
+    }
+
+    pub fn site_key(&self) -> &str {
+        &self.site_key
+    }
+}
+
+#[derive(serde::Serialize)]
+struct SiteVerifyRequest<'a> {
+    secret: &'a str,
+    response: &'a str,
+}
+
+#[derive(serde::Deserialize)]
+struct SiteVerifyResponse {
+    success: bool,
+    #[serde(default, rename = "error-codes")]
+    error_codes: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok_eq};
+    use secrecy::Secret;
+    use wiremock::matchers::{any, body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    use super::{CaptchaClient, CaptchaProvider};
+
+    fn captcha_client(base_url: String) -> CaptchaClient {
+        CaptchaClient::new(
+            CaptchaProvider::Turnstile,
+            Some(format!("{}/siteverify", base_url)),
+            "site-key".into(),
+            Secret::new("secret-key".into()),
+            std::time::Duration::from_millis(200),
+        )
+    }
+
+    #[tokio::test]
+    async fn verify_sends_the_secret_and_the_response_to_siteverify() {
+        let mock_server = MockServer::start().await;
+        let client = captcha_client(mock_server.uri());
+
+        Mock::given(path("/siteverify"))
+            .and(method("POST"))
+            .and(body_string_contains("secret=secret-key"))
+            .and(body_string_contains("response=a-token"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": true
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        assert_ok_eq!(client.verify("a-token").await, true);
+    }
+
+    #[tokio::test]
+    async fn verify_rejects_responses_the_provider_rejects() {
+        let mock_server = MockServer::start().await;
+        let client = captcha_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "success": false,
+                "error-codes": ["invalid-input-response"]
+            })))
+            .mount(&mock_server)
+            .await;
+
+        assert_ok_eq!(client.verify("a-token").await, false);
+    }
+
+    #[tokio::test]
+    async fn verify_rejects_empty_responses_without_calling_the_provider() {
+        let mock_server = MockServer::start().await;
+        let client = captcha_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        assert_ok_eq!(client.verify("").await, false);
+    }
+
+    #[tokio::test]
+    async fn verify_fails_if_the_provider_returns_500() {
+        let mock_server = MockServer::start().await;
+        let client = captcha_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .mount(&mock_server)
+            .await;
+
+        assert_err!(client.verify("a-token").await);
+    }
+}
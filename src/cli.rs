@@ -0,0 +1,164 @@
+use anyhow::Context;
+use secrecy::Secret;
+use sqlx::PgPool;
+
+use crate::authentication::{create_user, validate_new_password, PasswordHashingPolicy};
+use crate::configuration::Settings;
+use crate::domain::SubscriberEmail;
+use crate::run_mode::RunMode;
+use crate::startup::{get_connection_pool, Application};
+
+/// Serves the newsletter, or runs one of the operator commands
+#[derive(Debug, clap::Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Which parts of the application to run
+    #[arg(long, value_enum, default_value_t = RunMode::All)]
+    pub mode: RunMode,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Operator tasks, to bootstrap and troubleshoot a deployment
+#[derive(Debug, PartialEq, Eq, clap::Subcommand)]
+pub enum Command {
+    /// Creates an admin user; the password is read from the standard input
+    CreateAdmin { username: String },
+    /// Applies the pending database migrations
+    Migrate,
+    /// Sends an email to the given address through the configured email provider
+    SendTestEmail { recipient: String },
+    /// Checks the configuration and that the database and Redis can be reached
+    ConfigCheck,
+}
+
+impl Command {
+    pub async fn run(self, configuration: Settings) -> Result<(), anyhow::Error> {
+        match self {
+            Command::CreateAdmin { username } => {
+                let pool = get_connection_pool(&configuration.database);
+                create_admin(
+                    &pool,
+                    &configuration.password_hashing,
+                    &username,
+                    read_password()?,
+                )
+                .await
+            }
+            Command::Migrate => {
+                let pool = get_connection_pool(&configuration.database);
+                sqlx::migrate!("./migrations")
+                    .run(&pool)
+                    .await
+                    .context("Failed to migrate the database")?;
+                println!("The database is up to date.");
+                Ok(())
+            }
+            Command::SendTestEmail { recipient } => send_test_email(configuration, recipient).await,
+            Command::ConfigCheck => config_check(configuration).await,
+        }
+    }
+}
+
+/// Reads a password from the first line of the standard input, so it stays out of the shell
+/// history
+fn read_password() -> Result<Secret<String>, anyhow::Error> {
+    eprintln!("Password:");
+    let mut password = String::new();
+    std::io::stdin()
+        .read_line(&mut password)
+        .context("Failed to read the password from the standard input")?;
+    Ok(Secret::new(
+        password.trim_end_matches(['\r', '\n']).to_string(),
+    ))
+}
+
+async fn create_admin(
+    pool: &PgPool,
+    hashing_policy: &PasswordHashingPolicy,
+    username: &str,
+    password: Secret<String>,
+) -> Result<(), anyhow::Error> {
+    validate_new_password(&password, &password).map_err(anyhow::Error::msg)?;
+    match create_user(username, password, hashing_policy, pool).await? {
+        Some(user_id) => {
+            println!("Created the admin user {} ({}).", username, user_id);
+            Ok(())
+        }
+        None => anyhow::bail!("The username {} is already taken.", username),
+    }
+}
+
+async fn send_test_email(configuration: Settings, recipient: String) -> Result<(), anyhow::Error> {
+    let recipient = SubscriberEmail::parse(recipient).map_err(anyhow::Error::msg)?;
+    configuration
+        .email_client
+        .client()
+        .send_email(
+            &recipient,
+            "Test email",
+            "<p>This is a test email from your newsletter.</p>",
+            "This is a test email from your newsletter.",
+        )
+        .await
+        .context("Failed to send the test email")?;
+    println!("A test email has been sent to {}.", recipient.as_ref());
+    Ok(())
+}
+
+/// Reports every problem found rather than stopping at the first one
+async fn config_check(configuration: Settings) -> Result<(), anyhow::Error> {
+    let report = Application::check_configuration(&configuration).await;
+    print!("{}", report);
+    if !report.is_valid() {
+        anyhow::bail!(
+            "{} of {} checks failed.",
+            report.n_failed(),
+            report.checks.len()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cli, Command};
+    use crate::run_mode::RunMode;
+    use claims::{assert_err, assert_ok};
+    use clap::Parser;
+
+    #[test]
+    fn the_application_is_served_by_default() {
+        let cli = assert_ok!(Cli::try_parse_from(["email-newsletter"]));
+        assert_eq!(cli.mode, RunMode::All);
+        assert_eq!(cli.command, None);
+    }
+
+    #[test]
+    fn the_mode_can_be_chosen() {
+        let cli = assert_ok!(Cli::try_parse_from([
+            "email-newsletter",
+            "--mode",
+            "worker"
+        ]));
+        assert_eq!(cli.mode, RunMode::Worker);
+        assert_err!(Cli::try_parse_from(["email-newsletter", "--mode", "cron"]));
+    }
+
+    #[test]
+    fn subcommands_are_parsed() {
+        let cli = assert_ok!(Cli::try_parse_from([
+            "email-newsletter",
+            "create-admin",
+            "ursula"
+        ]));
+        assert_eq!(
+            cli.command,
+            Some(Command::CreateAdmin {
+                username: "ursula".into()
+            })
+        );
+        assert_err!(Cli::try_parse_from(["email-newsletter", "create-admin"]));
+        assert_err!(Cli::try_parse_from(["email-newsletter", "drop-database"]));
+    }
+}
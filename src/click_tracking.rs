@@ -0,0 +1,111 @@
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// How often a tracked link of an issue was followed
+#[derive(Debug, serde::Serialize)]
+pub struct LinkClicks {
+    pub url: String,
+    pub clicks: i64,
+    /// Clicks counted once per recipient
+    pub unique_clicks: i64,
+}
+
+/// The clicks on the tracked links of an issue
+#[derive(Debug)]
+pub struct ClickStats {
+    pub delivered: i64,
+    /// Recipients who followed at least one link
+    pub clickers: i64,
+    pub links: Vec<LinkClicks>,
+}
+
+impl ClickStats {
+    /// The share of recipients who followed at least one link, as a percentage.
+    /// Returns `None` until the issue has been delivered to somebody.
+    pub fn click_through_rate(&self) -> Option<f64> {
+        (self.delivered > 0).then(|| self.clickers as f64 * 100.0 / self.delivered as f64)
+    }
+}
+
+/// Fetches the click statistics of an issue; returns `None` if the issue does not exist or was
+/// published without click tracking
+#[tracing::instrument(name = "Get click statistics", skip(pool))]
+pub async fn get_click_stats(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<ClickStats>, anyhow::Error> {
+    let issue = sqlx::query!(
+        r#"
+        SELECT
+            click_tracking,
+            (
+                SELECT COUNT(*) FROM issue_deliveries
+                WHERE newsletter_issue_id = $1
+            ) AS "delivered!",
+            (
+                SELECT COUNT(DISTINCT delivery_id) FROM link_clicks
+                WHERE newsletter_issue_id = $1
+            ) AS "clickers!"
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve the click counts of the issue.")?;
+    let Some(issue) = issue.filter(|issue| issue.click_tracking) else {
+        return Ok(None);
+    };
+    let links = sqlx::query_as!(
+        LinkClicks,
+        r#"
+        SELECT
+            l.url,
+            COUNT(c.delivery_id) AS "clicks!",
+            COUNT(DISTINCT c.delivery_id) AS "unique_clicks!"
+        FROM issue_links l
+        LEFT JOIN link_clicks c ON
+            c.newsletter_issue_id = l.newsletter_issue_id AND
+            c.link_id = l.link_id
+        WHERE l.newsletter_issue_id = $1
+        GROUP BY l.link_id, l.url
+        ORDER BY l.link_id
+        "#,
+        issue_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve the clicks per link.")?;
+    Ok(Some(ClickStats {
+        delivered: issue.delivered,
+        clickers: issue.clickers,
+        links,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ClickStats;
+    use claims::{assert_none, assert_some_eq};
+
+    fn stats(delivered: i64, clickers: i64) -> ClickStats {
+        ClickStats {
+            delivered,
+            clickers,
+            links: vec![],
+        }
+    }
+
+    #[test]
+    fn click_through_rate_is_the_share_of_recipients_who_clicked() {
+        assert_some_eq!(stats(8, 2).click_through_rate(), 25.0);
+        assert_some_eq!(stats(8, 0).click_through_rate(), 0.0);
+    }
+
+    #[test]
+    fn click_through_rate_is_unknown_before_the_first_delivery() {
+        assert_none!(stats(0, 0).click_through_rate());
+    }
+}
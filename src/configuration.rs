@@ -1,7 +1,28 @@
-use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::authentication::{LoginLockoutPolicy, PasswordHashingPolicy};
+use crate::bot_protection::BotProtection;
+use crate::captcha::{CaptchaClient, CaptchaProvider};
+use actix_cors::Cors;
+use actix_web::http::header::HeaderName;
+use actix_web::http::Method;
+use std::collections::HashMap;
+
+use anyhow::Context;
+
+use crate::domain::{EmailAddressMode, NewsletterSlug, SubscriberEmail, SubscriberNamePolicy};
+use crate::email_client::{
+    CircuitBreakerPolicy, EmailClient, SendRetryPolicy, SenderIdentity, SesCredentials,
+};
+use crate::email_validation::EmailValidator;
+use crate::jobs::RetryPolicy;
+use crate::rate_limiter::Rate;
+use crate::rate_limiting::{RateLimit, RateLimitStore, RateLimiter};
+use crate::reverse_proxy::TrustedProxies;
+use crate::send_window::SendWindow;
+use crate::templates::Branding;
 use secrecy::{ExposeSecret, Secret};
-use serde_aux::field_attributes::deserialize_number_from_string;
+use serde_aux::field_attributes::{
+    deserialize_number_from_string, deserialize_option_number_from_string,
+};
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use sqlx::ConnectOptions;
 
@@ -10,35 +31,631 @@ pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
     pub email_client: EmailClientSettings,
+    /// Receives the delivery and open events of Postmark; disabled if not set
+    pub postmark_webhook: Option<PostmarkWebhookSettings>,
+    pub issue_delivery: IssueDeliverySettings,
+    pub delivery_alerts: DeliveryAlertSettings,
+    /// The UTM parameters added to the links of issues, unless the issue sets its own; links are
+    /// left alone if not set
+    pub utm: Option<UtmSettings>,
+    /// Appended to every issue and automation email as it is sent; emails go out as written if
+    /// not set
+    pub email_footer: Option<EmailFooterSettings>,
+    #[serde(default)]
+    pub html_sanitization: HtmlSanitizationSettings,
+    pub media: MediaSettings,
+    pub confirmation_email: ConfirmationEmailSettings,
+    pub confirmation_page: ConfirmationPageSettings,
+    pub cors: CorsSettings,
+    pub branding: Branding,
+    pub bot_protection: BotProtectionSettings,
+    /// CAPTCHA verification on the subscribe form; disabled if not set
+    pub captcha: Option<CaptchaSettings>,
+    pub email_validation: EmailValidationSettings,
+    #[serde(default)]
+    pub subscriber_names: SubscriberNamePolicy,
+    pub rate_limiting: RateLimitSettings,
+    pub login_lockout: LoginLockoutPolicy,
+    pub password_hashing: PasswordHashingPolicy,
+    pub session: SessionSettings,
+    pub idempotency: IdempotencySettings,
+    pub deleted_subscribers: DeletedSubscriberSettings,
+    pub payload_limits: PayloadLimitSettings,
+    pub telemetry: TelemetrySettings,
+    pub http_caching: HttpCachingSettings,
     pub redis_uri: Secret<String>,
 }
 
+/// How long the responses saved for idempotency keys are kept
+#[derive(serde::Deserialize, Clone)]
+pub struct IdempotencySettings {
+    /// Retries with the same key after this long are processed again
+    pub retention_seconds: u64,
+    pub cleanup_interval_seconds: u64,
+}
+
+impl IdempotencySettings {
+    pub fn retention(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.retention_seconds)
+    }
+
+    pub fn cleanup_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.cleanup_interval_seconds)
+    }
+}
+
+/// The largest request bodies accepted, so that a huge paste cannot exhaust the server
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct PayloadLimitSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub json_bytes: usize,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub form_bytes: usize,
+    /// The HTML content of an issue, checked on its own to tell editors what is too large
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub newsletter_html_bytes: usize,
+}
+
+impl PayloadLimitSettings {
+    /// Rejects the HTML content of an issue, a draft or an automation step over the limit
+    pub fn check_newsletter_html(&self, html_content: &str) -> Result<(), String> {
+        if html_content.len() > self.newsletter_html_bytes {
+            return Err(format!(
+                "The HTML content must not be larger than {} KiB.",
+                self.newsletter_html_bytes / 1024
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// What the HTML content of issues may contain once stored, so that an editor cannot plant
+/// scripts in the public archive
+#[derive(serde::Deserialize, Clone, Debug, Default)]
+pub struct HtmlSanitizationSettings {
+    /// Tags kept in the content; the other tags are removed but their text is kept. A default
+    /// list of formatting, link, image and table tags if empty. `script` is always removed with
+    /// its content, and so are `<style>` blocks unless `style` is listed. `style` attributes are
+    /// kept, since the rules of `<style>` blocks are inlined into them.
+    #[serde(default, deserialize_with = "deserialize_list_from_string")]
+    pub allowed_tags: Vec<String>,
+}
+
+/// Where the images uploaded for the content of issues are kept
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct MediaSettings {
+    #[serde(default)]
+    pub storage: MediaStorageKind,
+    /// With the `disk` storage, where the images are written; they are served under `/media`
+    pub directory: String,
+    pub s3: Option<S3Settings>,
+    /// Larger uploads are rejected with a 413
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_file_bytes: usize,
+}
+
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaStorageKind {
+    #[default]
+    Disk,
+    S3,
+}
+
+/// An S3-compatible bucket; only required when `storage` is `s3`
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct S3Settings {
+    /// E.g. `https://s3.eu-west-1.amazonaws.com`; objects are addressed as `{endpoint}/{bucket}/{key}`
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: Secret<String>,
+    /// Where the objects are publicly served from, e.g. a CDN; `{endpoint}/{bucket}` if not set
+    pub public_base_url: Option<String>,
+}
+
+/// What the logs may contain
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct TelemetrySettings {
+    /// Hash subscriber emails and names in spans and events; only turn off to debug locally
+    pub redact_pii: bool,
+    /// Also write the logs to rolling files, for deployments without a log shipper
+    pub log_file: Option<LogFileSettings>,
+}
+
+/// Where and how much of the logs are kept on disk
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct LogFileSettings {
+    pub directory: String,
+    /// Files are named `{prefix}.{date}.{index}.log`
+    pub file_name_prefix: String,
+    /// A new file is started every day, or once the current one reaches this size
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_file_bytes: u64,
+    /// The oldest files are deleted beyond this count
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_files: usize,
+}
+
+/// How long browsers and proxies may keep the public pages and static assets
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct HttpCachingSettings {
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub static_max_age_seconds: u64,
+    /// The archive changes whenever an issue is published, so it should be kept short
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub pages_max_age_seconds: u64,
+}
+
+/// How long deleted subscribers can be restored before they are purged
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct DeletedSubscriberSettings {
+    pub retention_days: u32,
+    pub purge_interval_seconds: u64,
+}
+
+impl DeletedSubscriberSettings {
+    pub fn retention(&self) -> chrono::Duration {
+        chrono::Duration::days(self.retention_days.into())
+    }
+
+    pub fn purge_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.purge_interval_seconds)
+    }
+}
+
+/// Lifetime of the sessions of logged-in users
+#[derive(serde::Deserialize, Clone)]
+pub struct SessionSettings {
+    /// Sessions end this long after logging in, however active they are
+    pub ttl_seconds: u64,
+    /// Sessions end after this long without a request
+    pub idle_timeout_seconds: u64,
+    /// Users who ticked "Remember me" are logged back in for this long after their last visit
+    pub remember_me_ttl_seconds: u64,
+}
+
+impl SessionSettings {
+    pub fn ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.ttl_seconds)
+    }
+
+    pub fn idle_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.idle_timeout_seconds)
+    }
+
+    pub fn remember_me_ttl(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.remember_me_ttl_seconds)
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct IssueDeliverySettings {
+    pub max_retries: i16,
+    pub backoff_base_seconds: u64,
+    pub backoff_max_seconds: u64,
+    /// `/health/worker` reports the worker as stuck once its heartbeat is older than this
+    pub heartbeat_stale_after_seconds: u64,
+    /// Issues wait outside of these hours, unless they are set to ignore them
+    pub send_window: Option<SendWindow>,
+    /// A worker running on its own serves its `/metrics` on this port, if set; when it runs
+    /// alongside the API, its counters are part of the API's `/metrics`
+    pub metrics_port: Option<u16>,
+}
+
+impl IssueDeliverySettings {
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            max_retries: self.max_retries,
+            base_delay: std::time::Duration::from_secs(self.backoff_base_seconds),
+            max_delay: std::time::Duration::from_secs(self.backoff_max_seconds),
+        }
+    }
+}
+
+/// Thresholds past which deliveries are considered backed up, checked every
+/// `check_interval_seconds` by a background job
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct DeliveryAlertSettings {
+    pub check_interval_seconds: u64,
+    pub max_queue_depth: i64,
+    /// How long the delivery due the longest may wait
+    pub max_oldest_task_age_seconds: i64,
+    /// Also emailed the alerts, e.g. the address of an administrator; logs only if not set
+    pub alert_email: Option<SubscriberEmail>,
+}
+
+impl DeliveryAlertSettings {
+    pub fn check_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.check_interval_seconds)
+    }
+}
+
+/// The email sent to new subscribers; templates are paths relative to the working directory
+#[derive(serde::Deserialize, Clone)]
+pub struct ConfirmationEmailSettings {
+    pub subject: String,
+    pub html_template: String,
+    pub text_template: String,
+    /// A file sent along with every confirmation email, e.g. a PDF guide; none if not set
+    pub attachment: Option<AttachmentSettings>,
+}
+
+/// The copy of the page subscribers land on after following their confirmation link
+#[derive(serde::Deserialize, Clone)]
+pub struct ConfirmationPageSettings {
+    pub heading: String,
+    pub message: String,
+    pub link_text: String,
+    /// Where the link of the page leads; the home page of the application if not set
+    pub site_url: Option<String>,
+}
+
+/// Lets pages on other origins, e.g. the main website of the newsletter, call the JSON API from
+/// the browser
+#[derive(serde::Deserialize, Clone)]
+pub struct CorsSettings {
+    /// Origins such as `https://example.com`; cross-origin calls are not allowed if empty
+    #[serde(deserialize_with = "deserialize_list_from_string")]
+    pub allowed_origins: Vec<String>,
+    #[serde(deserialize_with = "deserialize_list_from_string")]
+    pub allowed_methods: Vec<String>,
+    #[serde(deserialize_with = "deserialize_list_from_string")]
+    pub allowed_headers: Vec<String>,
+    /// How long browsers may cache the response to a preflight request
+    pub max_age_seconds: Option<usize>,
+}
+
+impl CorsSettings {
+    /// Checks the settings up front: the middleware would only fail once the server started
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        for origin in &self.allowed_origins {
+            let url = reqwest::Url::parse(origin)
+                .with_context(|| format!("{} is not a valid CORS origin", origin))?;
+            // browsers send the bare origin, which is compared as a string
+            if url.origin().ascii_serialization() != *origin {
+                anyhow::bail!(
+                    "{} is not a valid CORS origin: expected {}",
+                    origin,
+                    url.origin().ascii_serialization()
+                );
+            }
+        }
+        for method in &self.allowed_methods {
+            Method::from_bytes(method.as_bytes())
+                .with_context(|| format!("{} is not a valid HTTP method", method))?;
+        }
+        for header in &self.allowed_headers {
+            HeaderName::from_bytes(header.as_bytes())
+                .with_context(|| format!("{} is not a valid HTTP header name", header))?;
+        }
+        Ok(())
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.allowed_origins.is_empty()
+    }
+
+    /// The middleware answering preflight requests and adding the CORS headers to the responses.
+    /// Requests from other origins go through without the headers, so that browsers block them
+    /// while same-origin and non-browser clients keep working.
+    pub fn cors(&self) -> Cors {
+        let cors = self
+            .allowed_origins
+            .iter()
+            .fold(Cors::default(), |cors, origin| cors.allowed_origin(origin));
+        cors.allowed_methods(self.allowed_methods.iter().map(String::as_str))
+            .allowed_headers(self.allowed_headers.iter().map(String::as_str))
+            .max_age(self.max_age_seconds)
+            .block_on_origin_mismatch(false)
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct AttachmentSettings {
+    pub path: String,
+    /// The name subscribers see; the name of the file at `path` if not set
+    pub filename: Option<String>,
+    pub content_type: String,
+}
+
+/// Checks that the addresses of new subscribers can receive emails
+#[derive(serde::Deserialize, Clone)]
+pub struct EmailValidationSettings {
+    /// Whether internationalized addresses are accepted
+    #[serde(default)]
+    pub address_mode: EmailAddressMode,
+    /// Whether the email provider supports SMTPUTF8, which UTF-8 local parts need in lenient mode
+    #[serde(default)]
+    pub smtputf8: bool,
+    /// Rejects the addresses at domains without a mail server, which takes a DNS lookup
+    #[serde(default)]
+    pub check_mx: bool,
+    /// Domains of disposable email providers, whose addresses are rejected
+    #[serde(default, deserialize_with = "deserialize_list_from_string")]
+    pub blocked_domains: Vec<String>,
+    /// A file listing more blocked domains, one per line; lines starting with `#` are ignored
+    pub blocked_domains_path: Option<String>,
+}
+
+impl EmailValidationSettings {
+    pub fn validator(self) -> Result<EmailValidator, anyhow::Error> {
+        let mut blocked_domains = self.blocked_domains;
+        if let Some(path) = &self.blocked_domains_path {
+            let list = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read the blocked domains at {}", path))?;
+            blocked_domains.extend(
+                list.lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(str::to_owned),
+            );
+        }
+        let resolver = if self.check_mx {
+            Some(EmailValidator::system_resolver().context("Failed to set up the DNS resolver")?)
+        } else {
+            None
+        };
+        Ok(EmailValidator::new(
+            self.address_mode,
+            self.smtputf8,
+            blocked_domains,
+            resolver,
+        ))
+    }
+}
+
+/// Checks applied to the public subscribe form to drop bot submissions
+#[derive(serde::Deserialize, Clone)]
+pub struct BotProtectionSettings {
+    /// Submissions made sooner than this after the form was rendered are dropped; 0 disables it,
+    /// which is the default since only the built-in form carries a form token
+    pub min_fill_time_seconds: u64,
+    /// Submissions of forms rendered longer ago than this are dropped, so that a harvested
+    /// token cannot be replayed forever
+    pub max_form_age_seconds: u64,
+}
+
+impl BotProtectionSettings {
+    pub fn bot_protection(&self, hmac_secret: Secret<String>) -> BotProtection {
+        BotProtection::new(
+            std::time::Duration::from_secs(self.min_fill_time_seconds),
+            std::time::Duration::from_secs(self.max_form_age_seconds),
+            hmac_secret,
+        )
+    }
+}
+
+#[derive(serde::Deserialize, Clone)]
+pub struct CaptchaSettings {
+    pub provider: CaptchaProvider,
+    pub site_key: String,
+    pub secret_key: Secret<String>,
+    /// Overrides the provider's siteverify endpoint
+    pub verify_url: Option<String>,
+    pub timeout_milliseconds: u64,
+}
+
+impl CaptchaSettings {
+    pub fn client(self) -> CaptchaClient {
+        CaptchaClient::new(
+            self.provider,
+            self.verify_url,
+            self.site_key,
+            self.secret_key,
+            std::time::Duration::from_millis(self.timeout_milliseconds),
+        )
+    }
+}
+
+/// Per-IP request limits on the subscribe and login forms
+#[derive(serde::Deserialize, Clone)]
+pub struct RateLimitSettings {
+    #[serde(default)]
+    pub store: RateLimitStoreKind,
+    pub subscriptions: RateLimit,
+    pub login: RateLimit,
+}
+
+/// Where the request counters of the rate limiter are kept
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitStoreKind {
+    /// Counters are local to each instance of the application
+    #[default]
+    Memory,
+    /// Counters are shared through the Redis instance at `redis_uri`
+    Redis,
+}
+
+impl RateLimitSettings {
+    pub async fn limiter(self, redis_uri: &Secret<String>) -> Result<RateLimiter, anyhow::Error> {
+        let store = match self.store {
+            RateLimitStoreKind::Memory => RateLimitStore::in_memory(),
+            RateLimitStoreKind::Redis => RateLimitStore::redis(redis_uri.expose_secret()).await?,
+        };
+        Ok(RateLimiter::new(store, self.subscriptions, self.login))
+    }
+}
+
 #[derive(serde::Deserialize, Clone)]
 pub struct EmailClientSettings {
     pub base_url: String,
-    pub sender_email: String,
+    pub sender_email: SubscriberEmail,
+    /// Display name of the default sender
+    pub sender_name: Option<String>,
+    pub reply_to: Option<SubscriberEmail>,
+    /// Postmark message stream of the emails; the server's default stream if not set
+    pub message_stream: Option<String>,
     pub authorization_token: Secret<String>,
     pub timeout_milliseconds: u64,
+    /// Maximum number of emails per second the delivery worker sends; unlimited if not set
+    pub max_send_rate: Option<Rate>,
+    #[serde(default)]
+    pub provider: EmailProvider,
+    pub ses: Option<SesSettings>,
+    /// Logs the emails instead of handing them to the provider, e.g. on staging
+    #[serde(default)]
+    pub sandbox: bool,
+    /// In sandbox mode, the file the emails are appended to; they are only logged if not set
+    pub sandbox_log_path: Option<String>,
+    /// Newsletters sending from another identity than the default one, by slug
+    #[serde(default)]
+    pub newsletter_senders: HashMap<NewsletterSlug, SenderSettings>,
+    /// Retries of the requests to the provider that fail for a reason that may go away; a single
+    /// attempt if not set
+    pub retry: Option<EmailRetrySettings>,
+    /// Stops calling the provider for a while once it keeps failing; never if not set
+    pub circuit_breaker: Option<CircuitBreakerSettings>,
 }
 
-impl EmailClientSettings {
-    pub fn sender(&self) -> Result<SubscriberEmail, String> {
-        SubscriberEmail::parse(self.sender_email.clone())
+/// Retries of a send, with a random delay up to an exponential backoff
+#[derive(serde::Deserialize, Clone)]
+pub struct EmailRetrySettings {
+    /// Attempts in total, the first one included
+    pub max_attempts: u32,
+    pub base_delay_milliseconds: u64,
+    pub max_delay_milliseconds: u64,
+}
+
+impl From<EmailRetrySettings> for SendRetryPolicy {
+    fn from(settings: EmailRetrySettings) -> Self {
+        SendRetryPolicy {
+            max_attempts: settings.max_attempts,
+            base_delay: std::time::Duration::from_millis(settings.base_delay_milliseconds),
+            max_delay: std::time::Duration::from_millis(settings.max_delay_milliseconds),
+        }
+    }
+}
+
+/// The provider is considered down after `failure_threshold` failures in a row, and sends fail
+/// straight away for the next `open_seconds`
+#[derive(serde::Deserialize, Clone)]
+pub struct CircuitBreakerSettings {
+    pub failure_threshold: u32,
+    pub open_seconds: u64,
+}
+
+impl From<CircuitBreakerSettings> for CircuitBreakerPolicy {
+    fn from(settings: CircuitBreakerSettings) -> Self {
+        CircuitBreakerPolicy {
+            failure_threshold: settings.failure_threshold,
+            open_for: std::time::Duration::from_secs(settings.open_seconds),
+        }
+    }
+}
+
+/// The default UTM parameters of the links of issues
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct UtmSettings {
+    pub source: String,
+    pub medium: String,
+    /// The same campaign for every issue; each issue is its own campaign, named after its title,
+    /// if not set
+    pub campaign: Option<String>,
+}
+
+/// The footer the law requires in commercial emails, such as the postal address of the sender
+/// under CAN-SPAM. Both texts may use the placeholders filled in for each subscriber, e.g.
+/// `{{ unsubscribe_url }}`.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct EmailFooterSettings {
+    /// Kept line by line
+    pub postal_address: String,
+    /// Shown above the address, e.g. why the subscriber gets the email
+    pub legal_text: Option<String>,
+}
+
+/// The HTTP Basic credentials Postmark calls the webhook with, as set in its URL, e.g.
+/// `https://postmark:<password>@example.com/webhooks/postmark`
+#[derive(serde::Deserialize, Clone)]
+pub struct PostmarkWebhookSettings {
+    pub username: String,
+    pub password: Secret<String>,
+}
+
+/// The sender identity of a newsletter
+#[derive(serde::Deserialize, Clone)]
+pub struct SenderSettings {
+    pub sender_email: SubscriberEmail,
+    pub sender_name: Option<String>,
+    pub reply_to: Option<SubscriberEmail>,
+    pub message_stream: Option<String>,
+}
+
+impl From<SenderSettings> for SenderIdentity {
+    fn from(settings: SenderSettings) -> Self {
+        Self {
+            email: settings.sender_email,
+            name: settings.sender_name,
+            reply_to: settings.reply_to,
+            message_stream: settings.message_stream,
+        }
     }
+}
 
+/// The email delivery API used by `EmailClient`
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailProvider {
+    #[default]
+    Postmark,
+    Ses,
+}
+
+/// Amazon SES specific settings; only required when `provider` is `ses`
+#[derive(serde::Deserialize, Clone)]
+pub struct SesSettings {
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: Secret<String>,
+}
+
+impl EmailClientSettings {
     pub fn timeout(&self) -> std::time::Duration {
         std::time::Duration::from_millis(self.timeout_milliseconds)
     }
 
     pub fn client(self) -> EmailClient {
-        let sender_email = self.sender().expect("Invalid sender email address.");
         let timeout = self.timeout();
-        EmailClient::new(
-            self.base_url,
-            sender_email,
-            self.authorization_token,
-            timeout,
-        )
+        let sender = SenderIdentity {
+            email: self.sender_email,
+            name: self.sender_name,
+            reply_to: self.reply_to,
+            message_stream: self.message_stream,
+        };
+        let newsletter_senders = self
+            .newsletter_senders
+            .into_iter()
+            .map(|(slug, settings)| (slug.as_ref().to_owned(), settings.into()))
+            .collect();
+        let client = match self.provider {
+            _ if self.sandbox => EmailClient::sandbox(
+                self.base_url,
+                sender,
+                self.sandbox_log_path.map(Into::into),
+                timeout,
+            ),
+            EmailProvider::Postmark => {
+                EmailClient::new(self.base_url, sender, self.authorization_token, timeout)
+            }
+            EmailProvider::Ses => {
+                let ses = self
+                    .ses
+                    .expect("Missing `email_client.ses` settings for the SES provider.");
+                let credentials = SesCredentials {
+                    region: ses.region,
+                    access_key_id: ses.access_key_id,
+                    secret_access_key: ses.secret_access_key,
+                };
+                EmailClient::ses(self.base_url, sender, credentials, timeout)
+            }
+        };
+        let retry_policy = self.retry.map_or_else(SendRetryPolicy::none, Into::into);
+        client
+            .with_newsletter_senders(newsletter_senders)
+            .with_resilience(retry_policy, self.circuit_breaker.map(Into::into))
     }
 }
 
@@ -49,6 +666,24 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    /// Serves HTTPS directly, for deployments without a TLS-terminating reverse proxy
+    pub tls: Option<TlsSettings>,
+    /// Directory of the files served under `/static`, e.g. the stylesheet and the logo
+    pub static_dir: String,
+    /// Reverse proxies whose `Forwarded`/`X-Forwarded-*` headers tell the address and scheme of
+    /// clients; the headers are ignored if not set
+    #[serde(default)]
+    pub trusted_proxies: TrustedProxies,
+}
+
+/// PEM files of the server certificate (with its chain) and its private key
+#[derive(serde::Deserialize, Clone)]
+pub struct TlsSettings {
+    pub certificate_path: String,
+    pub private_key_path: String,
+    /// Port of a plain HTTP listener redirecting every request to `base_url`; none if not set
+    #[serde(default, deserialize_with = "deserialize_option_number_from_string")]
+    pub http_redirect_port: Option<u16>,
 }
 
 #[derive(serde::Deserialize, Clone)]
@@ -60,15 +695,41 @@ pub struct DatabaseSettings {
     pub host: String,
     pub database_name: String,
     pub require_ssl: bool,
+    /// Size limits of the connection pool of each process
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub max_connections: u32,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub min_connections: u32,
+    /// How long a request waits for a connection before failing
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub acquire_timeout_seconds: u64,
+    /// Connections above `min_connections` are closed after this long without being used
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub idle_timeout_seconds: u64,
+    /// Queries running longer than this are cancelled by Postgres
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub statement_timeout_milliseconds: u64,
 }
 
 impl DatabaseSettings {
     pub fn with_db(&self) -> PgConnectOptions {
-        let mut options = self.without_db().database(&self.database_name);
+        let statement_timeout = format!("{}ms", self.statement_timeout_milliseconds);
+        let mut options = self
+            .without_db()
+            .database(&self.database_name)
+            .options([("statement_timeout", statement_timeout.as_str())]);
         options.log_statements(tracing_log::log::LevelFilter::Trace);
         options
     }
 
+    pub fn acquire_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.acquire_timeout_seconds)
+    }
+
+    pub fn idle_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.idle_timeout_seconds)
+    }
+
     pub fn without_db(&self) -> PgConnectOptions {
         let ssl_mode = if self.require_ssl {
             PgSslMode::Require
@@ -113,6 +774,37 @@ impl TryFrom<String> for Environment {
     }
 }
 
+/// Accepts a list, or a string of comma-separated items so that lists can be set from an
+/// environment variable
+fn deserialize_list_from_string<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum ListOrString {
+        List(Vec<String>),
+        String(String),
+    }
+
+    let list = match <ListOrString as serde::Deserialize>::deserialize(deserializer)? {
+        ListOrString::List(list) => list,
+        ListOrString::String(items) => items
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(str::to_owned)
+            .collect(),
+    };
+    Ok(list)
+}
+
+/// Reads `base.yaml`, then the file of the environment, then the environment variables.
+///
+/// Every setting can be overridden by a variable named after its path, prefixed with `APP_` and
+/// with its sections separated by `__`: `APP_DATABASE__PORT=5433` sets `database.port`. Lists
+/// take comma-separated items, e.g. `APP_CORS__ALLOWED_ORIGINS=https://a.com,https://b.com`.
+/// Secrets can also be read from a file, see `load_secret_files`.
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
     let base_path = std::env::current_dir().expect("Failed to determine the current directory");
     let configuration_directory = base_path.join("configuration");
@@ -123,11 +815,21 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
         .try_into()
         .expect("Failed to parse APP_ENVIRONMENT");
 
-    let environment_filename = format!("{}.yaml", environment.as_str());
+    build_configuration(&configuration_directory, environment, environment_source())
+}
 
-    let env_source = config::Environment::with_prefix("APP")
+fn environment_source() -> config::Environment {
+    config::Environment::with_prefix("APP")
         .prefix_separator("_")
-        .separator("__");
+        .separator("__")
+}
+
+fn build_configuration(
+    configuration_directory: &std::path::Path,
+    environment: Environment,
+    environment_source: config::Environment,
+) -> Result<Settings, config::ConfigError> {
+    let environment_filename = format!("{}.yaml", environment.as_str());
 
     let settings = config::Config::builder()
         .add_source(config::File::from(
@@ -136,8 +838,192 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
         .add_source(config::File::from(
             configuration_directory.join(environment_filename),
         ))
-        .add_source(env_source)
+        .add_source(environment_source)
         .build()?;
 
-    settings.try_deserialize()
+    load_secret_files(settings)?.try_deserialize()
+}
+
+/// The settings that can be read from a file, as mounted by Docker and Kubernetes secrets
+const SECRET_KEYS: [&str; 7] = [
+    "database.password",
+    "application.hmac_secret",
+    "password_hashing.pepper",
+    "email_client.authorization_token",
+    "email_client.ses.secret_access_key",
+    "captcha.secret_key",
+    "redis_uri",
+];
+
+/// Replaces each secret by the content of the file at `<key>_file`, when set, e.g. through
+/// `APP_DATABASE__PASSWORD_FILE=/run/secrets/database_password`. The file takes precedence over
+/// the value of the secret itself, which the configuration files usually set for local use.
+fn load_secret_files(settings: config::Config) -> Result<config::Config, config::ConfigError> {
+    let mut secrets = Vec::new();
+    for key in SECRET_KEYS {
+        let path = match settings.get_string(&format!("{}_file", key)) {
+            Ok(path) => path,
+            Err(config::ConfigError::NotFound(_)) => continue,
+            Err(e) => return Err(e),
+        };
+        let secret = std::fs::read_to_string(&path).map_err(|e| {
+            config::ConfigError::Message(format!("Failed to read `{}` from {}: {}", key, path, e))
+        })?;
+        // editors and `echo` end files with a newline, which is never part of the secret
+        secrets.push((key, secret.trim_end_matches(['\r', '\n']).to_string()));
+    }
+    if secrets.is_empty() {
+        return Ok(settings);
+    }
+    let mut builder = config::Config::builder().add_source(settings);
+    for (key, secret) in secrets {
+        builder = builder.set_override(key, secret)?;
+    }
+    builder.build()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_configuration, environment_source, Environment, Settings};
+    use claims::assert_ok;
+    use secrecy::ExposeSecret;
+    use std::collections::HashMap;
+
+    fn configuration_with(variables: &[(&str, &str)]) -> Settings {
+        let variables: HashMap<String, String> = variables
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        assert_ok!(build_configuration(
+            std::path::Path::new("configuration"),
+            Environment::Local,
+            environment_source().source(Some(variables)),
+        ))
+    }
+
+    #[test]
+    fn nested_settings_are_overridden_by_environment_variables() {
+        let configuration = configuration_with(&[
+            ("APP_DATABASE__PORT", "6543"),
+            ("APP_APPLICATION__HMAC_SECRET", "from-the-environment"),
+            ("APP_EMAIL_CLIENT__SANDBOX", "true"),
+            ("APP_REDIS_URI", "redis://cache:6379"),
+        ]);
+        assert_eq!(configuration.database.port, 6543);
+        assert_eq!(
+            configuration.application.hmac_secret.expose_secret(),
+            "from-the-environment"
+        );
+        assert!(configuration.email_client.sandbox);
+        assert_eq!(
+            configuration.redis_uri.expose_secret(),
+            "redis://cache:6379"
+        );
+    }
+
+    #[test]
+    fn the_production_configuration_accepts_subscriptions_without_a_form_token() {
+        // production reads its secrets from the environment
+        let variables = HashMap::from([
+            (
+                "APP_APPLICATION__BASE_URL".to_string(),
+                "https://newsletter.example.com".to_string(),
+            ),
+            (
+                "APP_APPLICATION__HMAC_SECRET".to_string(),
+                "from-the-environment".to_string(),
+            ),
+            (
+                "APP_EMAIL_CLIENT__AUTHORIZATION_TOKEN".to_string(),
+                "from-the-environment".to_string(),
+            ),
+        ]);
+        let configuration = assert_ok!(build_configuration(
+            std::path::Path::new("configuration"),
+            Environment::Production,
+            environment_source().source(Some(variables)),
+        ));
+        let bot_protection = configuration
+            .bot_protection
+            .bot_protection(configuration.application.hmac_secret);
+        assert!(!bot_protection.is_bot("", "", chrono::Utc::now()));
+    }
+
+    #[test]
+    fn lists_are_overridden_by_comma_separated_environment_variables() {
+        let configuration = configuration_with(&[
+            (
+                "APP_CORS__ALLOWED_ORIGINS",
+                "https://example.com, https://www.example.com",
+            ),
+            ("APP_EMAIL_VALIDATION__BLOCKED_DOMAINS", "mailinator.com"),
+        ]);
+        assert_eq!(
+            configuration.cors.allowed_origins,
+            ["https://example.com", "https://www.example.com"]
+        );
+        assert_eq!(
+            configuration.email_validation.blocked_domains,
+            ["mailinator.com"]
+        );
+    }
+
+    #[test]
+    fn secrets_are_read_from_the_files_they_point_to() {
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        std::fs::create_dir_all(&directory).unwrap();
+        let password_file = directory.join("database_password");
+        std::fs::write(&password_file, "from-a-file\n").unwrap();
+
+        let configuration = configuration_with(&[(
+            "APP_DATABASE__PASSWORD_FILE",
+            password_file.to_str().unwrap(),
+        )]);
+
+        assert_eq!(
+            configuration.database.password.expose_secret(),
+            "from-a-file"
+        );
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn a_missing_secret_file_is_an_error() {
+        let outcome = build_configuration(
+            std::path::Path::new("configuration"),
+            Environment::Local,
+            environment_source().source(Some(HashMap::from([(
+                "APP_APPLICATION__HMAC_SECRET_FILE".to_string(),
+                "/run/secrets/missing".to_string(),
+            )]))),
+        );
+        let error = outcome
+            .err()
+            .expect("A missing secret file was accepted")
+            .to_string();
+        assert!(error.contains("application.hmac_secret"));
+    }
+
+    #[test]
+    fn a_send_rate_that_is_not_positive_is_an_error() {
+        let outcome = build_configuration(
+            std::path::Path::new("configuration"),
+            Environment::Local,
+            environment_source().source(Some(HashMap::from([(
+                "APP_EMAIL_CLIENT__MAX_SEND_RATE".to_string(),
+                "0".to_string(),
+            )]))),
+        );
+        let error = outcome
+            .err()
+            .expect("A send rate of 0 was accepted")
+            .to_string();
+        assert!(error.contains("not a valid rate"), "{}", error);
+    }
+
+    #[test]
+    fn lists_in_the_configuration_files_are_still_accepted() {
+        let configuration = configuration_with(&[]);
+        assert!(!configuration.cors.allowed_methods.is_empty());
+    }
 }
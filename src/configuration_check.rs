@@ -0,0 +1,205 @@
+use std::fmt::{Display, Formatter};
+
+use anyhow::Context;
+use secrecy::{ExposeSecret, Secret};
+
+use crate::configuration::{EmailProvider, MediaStorageKind, Settings};
+use crate::startup::get_connection_pool;
+
+/// One line of the report of `Application::check_configuration`
+pub struct ConfigurationCheck {
+    pub name: &'static str,
+    pub outcome: Result<(), anyhow::Error>,
+}
+
+impl ConfigurationCheck {
+    pub fn new(name: &'static str, outcome: Result<(), anyhow::Error>) -> Self {
+        Self { name, outcome }
+    }
+}
+
+/// The outcome of every check, so that all the problems can be fixed in one go
+pub struct ConfigurationReport {
+    pub checks: Vec<ConfigurationCheck>,
+}
+
+impl ConfigurationReport {
+    pub fn n_failed(&self) -> usize {
+        self.checks
+            .iter()
+            .filter(|check| check.outcome.is_err())
+            .count()
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.n_failed() == 0
+    }
+}
+
+impl Display for ConfigurationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for check in &self.checks {
+            match &check.outcome {
+                Ok(()) => writeln!(f, "{}: ok", check.name)?,
+                Err(e) => writeln!(f, "{}: {:#}", check.name, e)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Secrets that are set, but to an empty value, e.g. by an environment variable left blank
+pub fn check_secrets(configuration: &Settings) -> Result<(), anyhow::Error> {
+    let mut secrets: Vec<(&str, &Secret<String>)> = vec![
+        ("database.password", &configuration.database.password),
+        (
+            "application.hmac_secret",
+            &configuration.application.hmac_secret,
+        ),
+        ("redis_uri", &configuration.redis_uri),
+    ];
+    let email_client = &configuration.email_client;
+    if !email_client.sandbox {
+        match (email_client.provider, &email_client.ses) {
+            (EmailProvider::Postmark, _) => secrets.push((
+                "email_client.authorization_token",
+                &email_client.authorization_token,
+            )),
+            (EmailProvider::Ses, Some(ses)) => {
+                secrets.push(("email_client.ses.secret_access_key", &ses.secret_access_key))
+            }
+            (EmailProvider::Ses, None) => {}
+        }
+    }
+    if let Some(captcha) = &configuration.captcha {
+        secrets.push(("captcha.secret_key", &captcha.secret_key));
+    }
+    if let Some(pepper) = &configuration.password_hashing.pepper {
+        secrets.push(("password_hashing.pepper", pepper));
+    }
+    if let (MediaStorageKind::S3, Some(s3)) = (configuration.media.storage, &configuration.media.s3)
+    {
+        secrets.push(("media.s3.secret_access_key", &s3.secret_access_key));
+    }
+    let missing: Vec<&str> = secrets
+        .into_iter()
+        .filter(|(_, secret)| secret.expose_secret().trim().is_empty())
+        .map(|(name, _)| name)
+        .collect();
+    if !missing.is_empty() {
+        anyhow::bail!("Missing secrets: {}.", missing.join(", "));
+    }
+    Ok(())
+}
+
+/// The URLs the application calls or links to, which are only used once a request comes in
+pub fn check_urls(configuration: &Settings) -> Result<(), anyhow::Error> {
+    let mut urls = vec![
+        ("application.base_url", &configuration.application.base_url),
+        (
+            "email_client.base_url",
+            &configuration.email_client.base_url,
+        ),
+        ("redis_uri", configuration.redis_uri.expose_secret()),
+    ];
+    if let Some(verify_url) = configuration
+        .captcha
+        .as_ref()
+        .and_then(|captcha| captcha.verify_url.as_ref())
+    {
+        urls.push(("captcha.verify_url", verify_url));
+    }
+    if let (MediaStorageKind::S3, Some(s3)) = (configuration.media.storage, &configuration.media.s3)
+    {
+        urls.push(("media.s3.endpoint", &s3.endpoint));
+        if let Some(public_base_url) = &s3.public_base_url {
+            urls.push(("media.s3.public_base_url", public_base_url));
+        }
+    }
+    let invalid: Vec<String> = urls
+        .into_iter()
+        .filter_map(|(name, url)| {
+            reqwest::Url::parse(url)
+                .err()
+                .map(|e| format!("{} ({})", name, e))
+        })
+        .collect();
+    if !invalid.is_empty() {
+        anyhow::bail!("Invalid URLs: {}.", invalid.join(", "));
+    }
+    Ok(())
+}
+
+/// The sender addresses are already validated when the configuration is loaded
+pub fn check_email_provider(configuration: &Settings) -> Result<(), anyhow::Error> {
+    let settings = &configuration.email_client;
+    if !settings.sandbox && settings.provider == EmailProvider::Ses && settings.ses.is_none() {
+        anyhow::bail!("Missing `email_client.ses` settings for the SES provider.");
+    }
+    Ok(())
+}
+
+pub fn check_media_storage(configuration: &Settings) -> Result<(), anyhow::Error> {
+    let settings = &configuration.media;
+    if settings.storage == MediaStorageKind::S3 && settings.s3.is_none() {
+        anyhow::bail!("Missing `media.s3` settings for the S3 storage.");
+    }
+    Ok(())
+}
+
+pub async fn check_database(configuration: &Settings) -> Result<(), anyhow::Error> {
+    let pool = get_connection_pool(&configuration.database);
+    sqlx::query("SELECT 1")
+        .execute(&pool)
+        .await
+        .context("Failed to connect to the database")?;
+    Ok(())
+}
+
+pub async fn check_redis(configuration: &Settings) -> Result<(), anyhow::Error> {
+    let client = redis::Client::open(configuration.redis_uri.expose_secret().as_str())?;
+    let mut connection = client
+        .get_async_connection()
+        .await
+        .context("Failed to connect to Redis")?;
+    redis::cmd("PING")
+        .query_async::<_, String>(&mut connection)
+        .await
+        .context("Failed to ping Redis")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_secrets, check_urls};
+    use crate::configuration::get_configuration;
+    use claims::{assert_err, assert_ok};
+    use secrecy::Secret;
+
+    #[test]
+    fn the_local_configuration_is_valid() {
+        let configuration = get_configuration().unwrap();
+        assert_ok!(check_secrets(&configuration));
+        assert_ok!(check_urls(&configuration));
+    }
+
+    #[test]
+    fn every_empty_secret_is_reported() {
+        let mut configuration = get_configuration().unwrap();
+        configuration.application.hmac_secret = Secret::new("".into());
+        configuration.database.password = Secret::new(" ".into());
+        let error = assert_err!(check_secrets(&configuration)).to_string();
+        assert!(error.contains("application.hmac_secret"));
+        assert!(error.contains("database.password"));
+    }
+
+    #[test]
+    fn every_unparsable_url_is_reported() {
+        let mut configuration = get_configuration().unwrap();
+        configuration.application.base_url = "newsletter.example.com".into();
+        configuration.email_client.base_url = "http://".into();
+        let error = assert_err!(check_urls(&configuration)).to_string();
+        assert!(error.contains("application.base_url"));
+        assert!(error.contains("email_client.base_url"));
+    }
+}
@@ -0,0 +1,247 @@
+use kuchiki::traits::{NodeIterator, TendrilSink};
+use kuchiki::{NodeRef, Selectors, Specificity};
+
+/// A rule of a `<style>` block that can be copied to the `style` attribute of the elements it
+/// matches
+struct Rule {
+    selectors: Selectors,
+    declarations: Vec<Declaration>,
+}
+
+struct Declaration {
+    property: String,
+    value: String,
+    important: bool,
+}
+
+/// Copies the rules of the `<style>` blocks of the HTML content of an issue to the `style`
+/// attribute of the elements they match, since many email clients drop `<style>` blocks. Rules
+/// that cannot be inlined, e.g. `@media` queries or `:hover` rules, stay in a `<style>` block.
+/// Content without `<style>` block is returned as is.
+pub fn inline_css(html_content: &str) -> String {
+    let lowercase = html_content.to_ascii_lowercase();
+    if !lowercase.contains("<style") {
+        return html_content.to_owned();
+    }
+    let document = kuchiki::parse_html().one(html_content);
+    let style_blocks: Vec<NodeRef> = document
+        .descendants()
+        .elements()
+        .filter(|element| &*element.name.local == "style")
+        .map(|element| element.as_node().clone())
+        .collect();
+    let mut rules = Vec::new();
+    let mut kept = String::new();
+    for style_block in style_blocks {
+        parse_stylesheet(&style_block.text_contents(), &mut rules, &mut kept);
+        style_block.detach();
+    }
+    for element in document.descendants().elements() {
+        let mut matched: Vec<(Specificity, usize, &Declaration)> = Vec::new();
+        for (order, rule) in rules.iter().enumerate() {
+            let Some(specificity) = rule
+                .selectors
+                .0
+                .iter()
+                .filter(|selector| selector.matches(&element))
+                .map(|selector| selector.specificity())
+                .max()
+            else {
+                continue;
+            };
+            matched.extend(
+                rule.declarations
+                    .iter()
+                    .map(|declaration| (specificity, order, declaration)),
+            );
+        }
+        if matched.is_empty() {
+            continue;
+        }
+        // the cascade: important rules beat the `style` attribute, which beats the other rules
+        matched.sort_by_key(|(specificity, order, declaration)| {
+            (declaration.important, *specificity, *order)
+        });
+        let mut attributes = element.attributes.borrow_mut();
+        let inline_declarations = parse_declarations(attributes.get("style").unwrap_or_default());
+        let mut style: Vec<(String, String)> = Vec::new();
+        let normal = matched
+            .iter()
+            .filter(|(_, _, declaration)| !declaration.important);
+        let important = matched
+            .iter()
+            .filter(|(_, _, declaration)| declaration.important);
+        for declaration in normal
+            .map(|(_, _, declaration)| *declaration)
+            .chain(inline_declarations.iter())
+            .chain(important.map(|(_, _, declaration)| *declaration))
+        {
+            style.retain(|(property, _)| *property != declaration.property);
+            style.push((declaration.property.clone(), declaration.value.clone()));
+        }
+        let style = style
+            .iter()
+            .map(|(property, value)| format!("{}: {}", property, value))
+            .collect::<Vec<_>>()
+            .join("; ");
+        attributes.insert("style", style);
+    }
+    let style_block = (!kept.is_empty())
+        .then(|| {
+            find_element(
+                &kuchiki::parse_html().one(format!("<style>{}</style>", kept)),
+                "style",
+            )
+        })
+        .flatten();
+    if lowercase.contains("<html") || lowercase.contains("<body") {
+        if let (Some(style_block), Some(head)) = (style_block, find_element(&document, "head")) {
+            head.append(style_block);
+        }
+        return document.to_string();
+    }
+    // a fragment is parsed into a whole document, of which only the body is returned
+    let mut inlined = style_block
+        .map(|style_block| style_block.to_string())
+        .unwrap_or_default();
+    if let Some(body) = find_element(&document, "body") {
+        for child in body.children() {
+            inlined.push_str(&child.to_string());
+        }
+    }
+    inlined
+}
+
+fn find_element(node: &NodeRef, name: &str) -> Option<NodeRef> {
+    node.descendants()
+        .elements()
+        .find(|element| &*element.name.local == name)
+        .map(|element| element.as_node().clone())
+}
+
+/// Adds the rules that can be inlined to `rules`, and appends the others to `kept`
+fn parse_stylesheet(css: &str, rules: &mut Vec<Rule>, kept: &mut String) {
+    let css = strip_comments(css);
+    let mut rest = css.as_str();
+    while let Some(open) = rest.find('{') {
+        let prelude = rest[..open].trim();
+        // at-rules may nest blocks, e.g. the rules of a `@media` query
+        let mut depth = 0;
+        let mut close = None;
+        for (offset, c) in rest[open..].char_indices() {
+            match c {
+                '{' => depth += 1,
+                '}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        close = Some(open + offset);
+                        break;
+                    }
+                }
+                _ => {}
+            }
+        }
+        let Some(close) = close else {
+            break;
+        };
+        let block = &rest[open + 1..close];
+        let selectors = (!prelude.starts_with('@') && !prelude.contains(':'))
+            .then(|| Selectors::compile(prelude).ok())
+            .flatten();
+        match selectors {
+            Some(selectors) => rules.push(Rule {
+                selectors,
+                declarations: parse_declarations(block),
+            }),
+            None => kept.push_str(&format!("{} {{{}}}\n", prelude, block)),
+        }
+        rest = &rest[close + 1..];
+    }
+}
+
+fn parse_declarations(block: &str) -> Vec<Declaration> {
+    block
+        .split(';')
+        .filter_map(|declaration| declaration.split_once(':'))
+        .filter_map(|(property, value)| {
+            let property = property.trim().to_ascii_lowercase();
+            let value = value.trim();
+            let (value, important) = match value.to_ascii_lowercase().rfind("!important") {
+                Some(position) => (value[..position].trim_end(), true),
+                None => (value, false),
+            };
+            (!property.is_empty() && !value.is_empty()).then(|| Declaration {
+                property,
+                value: value.to_owned(),
+                important,
+            })
+        })
+        .collect()
+}
+
+fn strip_comments(css: &str) -> String {
+    let mut stripped = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("/*") {
+        stripped.push_str(&rest[..start]);
+        rest = match rest[start + 2..].find("*/") {
+            Some(end) => &rest[start + 2 + end + 2..],
+            None => "",
+        };
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::inline_css;
+
+    #[test]
+    fn content_without_style_blocks_is_left_alone() {
+        let html = "<p>Hello &amp; welcome, {{ name }}</p>";
+        assert_eq!(inline_css(html), html);
+    }
+
+    #[test]
+    fn rules_are_copied_to_the_elements_they_match() {
+        let html = "<style>p { color: red; } .lead { font-size: 20px }</style>\
+            <p class=\"lead\">Hello</p><p>World</p>";
+        assert_eq!(
+            inline_css(html),
+            "<p class=\"lead\" style=\"color: red; font-size: 20px\">Hello</p>\
+            <p style=\"color: red\">World</p>"
+        );
+    }
+
+    #[test]
+    fn the_most_specific_rule_and_the_style_attribute_win() {
+        let html = "<style>#intro { color: blue } p { color: red; margin: 0 !important }</style>\
+            <p id=\"intro\" style=\"margin: 4px; font-weight: bold\">Hello</p>";
+        assert_eq!(
+            inline_css(html),
+            "<p id=\"intro\" style=\"color: blue; font-weight: bold; margin: 0\">Hello</p>"
+        );
+    }
+
+    #[test]
+    fn rules_that_cannot_be_inlined_are_kept() {
+        let html = "<style>a:hover { color: red } @media (max-width: 600px) { p { margin: 0 } }\
+            </style><p>Hello</p>";
+        assert_eq!(
+            inline_css(html),
+            "<style>a:hover { color: red }\n@media (max-width: 600px) { p { margin: 0 } }\n</style>\
+            <p>Hello</p>"
+        );
+    }
+
+    #[test]
+    fn whole_documents_are_returned_whole() {
+        let html =
+            "<html><head><style>p { color: red }</style></head><body><p>Hi</p></body></html>";
+        assert_eq!(
+            inline_css(html),
+            "<html><head></head><body><p style=\"color: red\">Hi</p></body></html>"
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::configuration::DeliveryAlertSettings;
+use crate::email_client::EmailClient;
+use crate::send_window::SendWindow;
+
+/// How many deliveries of the issues being delivered are waiting, and for how long
+#[derive(Debug)]
+pub struct DeliveryBacklog {
+    pub queue_depth: i64,
+    /// When the delivery that has been due the longest became due; retries waiting for their
+    /// backoff are not late yet
+    pub oldest_due_at: Option<DateTime<Utc>>,
+}
+
+impl DeliveryBacklog {
+    /// How long the delivery that has been due the longest has been waiting
+    pub fn oldest_task_age_seconds(&self, now: DateTime<Utc>) -> i64 {
+        self.oldest_due_at.map_or(0, |oldest_due_at| {
+            (now - oldest_due_at).num_seconds().max(0)
+        })
+    }
+
+    /// Why deliveries are considered backed up under `settings`, if they are
+    pub fn problems(&self, settings: &DeliveryAlertSettings, now: DateTime<Utc>) -> Vec<String> {
+        let mut problems = Vec::new();
+        if self.queue_depth > settings.max_queue_depth {
+            problems.push(format!(
+                "{} deliveries are queued, over the threshold of {}.",
+                self.queue_depth, settings.max_queue_depth
+            ));
+        }
+        let age = self.oldest_task_age_seconds(now);
+        if age > settings.max_oldest_task_age_seconds {
+            problems.push(format!(
+                "The oldest due delivery has been waiting for {}s, over the threshold of {}s.",
+                age, settings.max_oldest_task_age_seconds
+            ));
+        }
+        problems
+    }
+
+    /// Writes the depth of the queue and the age of its oldest task in the Prometheus text format
+    pub fn write_prometheus(&self, body: &mut String, now: DateTime<Utc>) {
+        writeln!(
+            body,
+            "# HELP issue_delivery_queue_depth Deliveries queued for the issues being delivered.\n\
+            # TYPE issue_delivery_queue_depth gauge\n\
+            issue_delivery_queue_depth {}\n\
+            # HELP issue_delivery_queue_oldest_task_age_seconds How long the delivery due the longest has been waiting.\n\
+            # TYPE issue_delivery_queue_oldest_task_age_seconds gauge\n\
+            issue_delivery_queue_oldest_task_age_seconds {}",
+            self.queue_depth,
+            self.oldest_task_age_seconds(now)
+        )
+        .unwrap();
+    }
+}
+
+/// Deliveries of paused or cancelled issues are left out, since they are not expected to move
+#[tracing::instrument(name = "Get delivery backlog", skip(pool))]
+pub async fn get_delivery_backlog(pool: &PgPool) -> Result<DeliveryBacklog, anyhow::Error> {
+    let backlog = sqlx::query_as!(
+        DeliveryBacklog,
+        r#"
+        SELECT
+            COUNT(*) AS "queue_depth!",
+            MIN(execute_after) FILTER (WHERE execute_after <= now()) AS oldest_due_at
+        FROM issue_delivery_queue
+        WHERE newsletter_issue_id IN (
+            SELECT newsletter_issue_id
+            FROM newsletter_issues
+            WHERE status = 'delivering'
+        )
+        "#
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to perform a query to retrieve the delivery backlog.")?;
+    Ok(backlog)
+}
+
+/// Warns when deliveries are backing up and, if `alert_email` is set, emails it the problems.
+/// The alert is repeated on every check for as long as the backlog lasts. Deliveries waiting for
+/// the send window to open are not late.
+#[tracing::instrument(name = "Check delivery backlog", skip_all)]
+pub async fn check_delivery_backlog(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    settings: &DeliveryAlertSettings,
+    send_window: Option<&SendWindow>,
+) -> Result<(), anyhow::Error> {
+    let mut backlog = get_delivery_backlog(pool).await?;
+    let now = Utc::now();
+    if let Some(window) = send_window {
+        if !window.is_open(now) {
+            return Ok(());
+        }
+        // deliveries that became due overnight only started waiting when the window opened
+        let last_opening = window.last_opening(now);
+        backlog.oldest_due_at = backlog
+            .oldest_due_at
+            .map(|oldest_due_at| oldest_due_at.max(last_opening));
+    }
+    let problems = backlog.problems(settings, now);
+    if problems.is_empty() {
+        return Ok(());
+    }
+    tracing::warn!(
+        queue_depth = backlog.queue_depth,
+        oldest_task_age_seconds = backlog.oldest_task_age_seconds(now),
+        "Deliveries are backing up: {}",
+        problems.join(" ")
+    );
+    if let Some(alert_email) = &settings.alert_email {
+        let text_body = format!(
+            "Newsletter deliveries are backing up:\n\n{}\n",
+            problems.join("\n")
+        );
+        let html_body = format!(
+            "<p>Newsletter deliveries are backing up:</p><ul>{}</ul>",
+            problems
+                .iter()
+                .map(|problem| format!("<li>{}</li>", problem))
+                .collect::<String>()
+        );
+        email_client
+            .send_email(
+                alert_email,
+                "Newsletter deliveries are backing up",
+                &html_body,
+                &text_body,
+            )
+            .await
+            .context("Failed to send the delivery backlog alert.")?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeliveryBacklog;
+    use crate::configuration::DeliveryAlertSettings;
+    use chrono::{Duration, Utc};
+
+    fn settings() -> DeliveryAlertSettings {
+        DeliveryAlertSettings {
+            check_interval_seconds: 300,
+            max_queue_depth: 100,
+            max_oldest_task_age_seconds: 600,
+            alert_email: None,
+        }
+    }
+
+    #[test]
+    fn a_short_queue_of_recent_tasks_is_fine() {
+        let now = Utc::now();
+        let backlog = DeliveryBacklog {
+            queue_depth: 100,
+            oldest_due_at: Some(now - Duration::seconds(600)),
+        };
+        assert!(backlog.problems(&settings(), now).is_empty());
+    }
+
+    #[test]
+    fn each_threshold_crossed_is_a_problem() {
+        let now = Utc::now();
+        let backlog = DeliveryBacklog {
+            queue_depth: 101,
+            oldest_due_at: Some(now - Duration::seconds(601)),
+        };
+        assert_eq!(backlog.problems(&settings(), now).len(), 2);
+    }
+
+    #[test]
+    fn an_empty_queue_has_no_age() {
+        let backlog = DeliveryBacklog {
+            queue_depth: 0,
+            oldest_due_at: None,
+        };
+        assert_eq!(backlog.oldest_task_age_seconds(Utc::now()), 0);
+    }
+}
@@ -0,0 +1,160 @@
+use chrono::NaiveTime;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// Number of recipients queued in each batch: every batch runs in its own short transaction, so
+/// that queueing a large list never holds locks for long
+pub const ENQUEUE_BATCH_SIZE: i64 = 1000;
+
+pub enum EnqueueOutcome {
+    BatchEnqueued,
+    NothingToEnqueue,
+}
+
+/// An issue whose recipients are being queued
+struct EnqueuingIssue {
+    newsletter_issue_id: Uuid,
+    newsletter_id: Uuid,
+    segment: Option<String>,
+    enqueue_cursor: Option<Uuid>,
+    /// When set, recipients get the issue at this time of day in their own time zone
+    local_send_time: Option<NaiveTime>,
+}
+
+/// Queues a delivery task for the next batch of confirmed subscribers in the segment of an issue
+/// that is being enqueued. Once a batch comes up short, every recipient has a task and the issue
+/// moves on to `delivering`.
+#[tracing::instrument(skip(pool), fields(newsletter_issue_id=tracing::field::Empty))]
+pub async fn try_enqueue_batch(
+    pool: &PgPool,
+    batch_size: i64,
+) -> Result<EnqueueOutcome, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let Some(issue) = lock_enqueuing_issue(&mut transaction).await? else {
+        return Ok(EnqueueOutcome::NothingToEnqueue);
+    };
+    tracing::Span::current().record(
+        "newsletter_issue_id",
+        &tracing::field::display(issue.newsletter_issue_id),
+    );
+    let recipients = get_recipient_batch(&mut transaction, &issue, batch_size).await?;
+    let emails: Vec<String> = recipients.iter().map(|r| r.email.clone()).collect();
+    // subscribers who did not tell their time zone get issues sent at a local time in UTC
+    let time_zones: Vec<String> = recipients
+        .iter()
+        .map(|r| r.time_zone.clone().unwrap_or_else(|| "UTC".into()))
+        .collect();
+    // at the next occurrence of the local send time of the issue in each time zone, if it has one
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email,
+            newsletter_id,
+            execute_after
+        )
+        SELECT
+            $1,
+            email,
+            $3,
+            CASE
+                WHEN $5::TIME IS NULL THEN now()
+                WHEN (local_today + $5::TIME) AT TIME ZONE time_zone >= now()
+                    THEN (local_today + $5::TIME) AT TIME ZONE time_zone
+                ELSE (local_today + 1 + $5::TIME) AT TIME ZONE time_zone
+            END
+        FROM (
+            SELECT email, time_zone, (now() AT TIME ZONE time_zone)::date AS local_today
+            FROM UNNEST($2::TEXT[], $4::TEXT[]) AS recipients(email, time_zone)
+        ) AS recipients
+        ON CONFLICT DO NOTHING
+        "#,
+        issue.newsletter_issue_id,
+        &emails,
+        issue.newsletter_id,
+        &time_zones,
+        issue.local_send_time
+    )
+    .execute(&mut transaction)
+    .await?;
+    let enqueue_cursor = recipients.last().map(|r| r.id).or(issue.enqueue_cursor);
+    let status = if (recipients.len() as i64) < batch_size {
+        "delivering"
+    } else {
+        "enqueuing"
+    };
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = $2, enqueue_cursor = $3
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue.newsletter_issue_id,
+        status,
+        enqueue_cursor
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(EnqueueOutcome::BatchEnqueued)
+}
+
+/// Locks the issue that has been waiting for its recipients the longest; issues locked by another
+/// worker are skipped
+async fn lock_enqueuing_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<Option<EnqueuingIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        EnqueuingIssue,
+        r#"
+        SELECT newsletter_issue_id, newsletter_id, segment, enqueue_cursor, local_send_time
+        FROM newsletter_issues
+        WHERE status = 'enqueuing'
+        ORDER BY published_at
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(transaction)
+    .await
+}
+
+struct Recipient {
+    id: Uuid,
+    email: String,
+    time_zone: Option<String>,
+}
+
+/// The confirmed subscribers of the newsletter in the segment of the issue that come after its
+/// cursor
+async fn get_recipient_batch(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue: &EnqueuingIssue,
+    batch_size: i64,
+) -> Result<Vec<Recipient>, sqlx::Error> {
+    sqlx::query_as!(
+        Recipient,
+        r#"
+        SELECT id, email, time_zone
+        FROM subscriptions
+        WHERE
+            status = 'confirmed' AND
+            deleted_at IS NULL AND
+            newsletter_id = $4 AND
+            ($1::uuid IS NULL OR id > $1) AND
+            (
+                $2::TEXT IS NULL OR
+                id IN (SELECT subscriber_id FROM subscriber_tags WHERE tag = $2)
+            )
+        ORDER BY id
+        LIMIT $3
+        "#,
+        issue.enqueue_cursor,
+        issue.segment,
+        batch_size,
+        issue.newsletter_id
+    )
+    .fetch_all(transaction)
+    .await
+}
@@ -0,0 +1,132 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// A snapshot of how far the delivery of a newsletter issue has progressed
+#[derive(Debug)]
+pub struct DeliveryProgress {
+    pub title: String,
+    /// One of `enqueuing`, `delivering`, `paused` or `cancelled`
+    pub status: String,
+    pub delivered: i64,
+    /// Deliveries the provider reported as accepted by the recipient's mail server
+    pub confirmed: i64,
+    pub failed: i64,
+    pub pending: i64,
+    /// When the first email for this issue went out, if any has
+    pub started_at: Option<DateTime<Utc>>,
+    /// Whether the issue is sent even while the send window is closed
+    pub ignores_send_window: bool,
+}
+
+impl DeliveryProgress {
+    pub fn total(&self) -> i64 {
+        self.delivered + self.failed + self.pending
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.pending == 0
+    }
+
+    /// Estimates the time left by extrapolating the throughput observed so far.
+    /// Returns `None` if nothing has been sent yet, since there is nothing to extrapolate from.
+    pub fn eta(&self, now: DateTime<Utc>) -> Option<Duration> {
+        let started_at = self.started_at?;
+        let processed = self.delivered + self.failed;
+        if processed == 0 {
+            return None;
+        }
+        let elapsed = (now - started_at).to_std().ok()?;
+        let seconds_per_task = elapsed.as_secs_f64() / processed as f64;
+        Some(Duration::from_secs_f64(
+            seconds_per_task * self.pending as f64,
+        ))
+    }
+}
+
+/// Fetches the delivery progress of a newsletter issue; returns `None` if the issue does not exist
+#[tracing::instrument(name = "Get delivery progress", skip(pool))]
+pub async fn get_delivery_progress(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<DeliveryProgress>, anyhow::Error> {
+    let progress = sqlx::query_as!(
+        DeliveryProgress,
+        r#"
+        SELECT
+            title,
+            status,
+            (
+                SELECT COUNT(*) FROM issue_deliveries
+                WHERE newsletter_issue_id = $1
+            ) AS "delivered!",
+            (
+                SELECT COUNT(*) FROM issue_deliveries
+                WHERE newsletter_issue_id = $1 AND confirmed_at IS NOT NULL
+            ) AS "confirmed!",
+            (
+                SELECT COUNT(*) FROM issue_delivery_failures
+                WHERE newsletter_issue_id = $1
+            ) AS "failed!",
+            (
+                SELECT COUNT(*) FROM issue_delivery_queue
+                WHERE newsletter_issue_id = $1
+            ) AS "pending!",
+            (
+                SELECT MIN(delivered_at) FROM issue_deliveries
+                WHERE newsletter_issue_id = $1
+            ) AS started_at,
+            ignores_send_window
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve delivery progress.")?;
+    Ok(progress)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeliveryProgress;
+    use chrono::{Duration, Utc};
+
+    fn progress(delivered: i64, failed: i64, pending: i64) -> DeliveryProgress {
+        DeliveryProgress {
+            title: "Title".into(),
+            status: "delivering".into(),
+            delivered,
+            confirmed: 0,
+            failed,
+            pending,
+            started_at: None,
+            ignores_send_window: false,
+        }
+    }
+
+    #[test]
+    fn total_counts_every_task() {
+        assert_eq!(progress(3, 1, 6).total(), 10);
+    }
+
+    #[test]
+    fn eta_is_unknown_before_the_first_delivery() {
+        assert_eq!(progress(0, 0, 10).eta(Utc::now()), None);
+    }
+
+    #[test]
+    fn eta_extrapolates_the_observed_throughput() {
+        let now = Utc::now();
+        let progress = DeliveryProgress {
+            started_at: Some(now - Duration::seconds(60)),
+            ..progress(90, 10, 200)
+        };
+        // 100 tasks per minute, 200 left
+        let eta = progress.eta(now).unwrap();
+        assert_eq!(eta.as_secs(), 120);
+    }
+}
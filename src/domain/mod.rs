@@ -1,7 +1,13 @@
 mod new_subscriber;
+mod newsletter_slug;
 mod subscriber_email;
 mod subscriber_name;
+mod subscriber_tag;
+mod subscription_status;
 
 pub use new_subscriber::NewSubscriber;
-pub use subscriber_email::SubscriberEmail;
-pub use subscriber_name::SubscriberName;
+pub use newsletter_slug::NewsletterSlug;
+pub use subscriber_email::{EmailAddressMode, SubscriberEmail};
+pub use subscriber_name::{SubscriberName, SubscriberNamePolicy};
+pub use subscriber_tag::SubscriberTag;
+pub use subscription_status::SubscriptionStatus;
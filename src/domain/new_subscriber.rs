@@ -1,17 +1,21 @@
-use crate::domain::{SubscriberEmail, SubscriberName};
+use crate::domain::{SubscriberEmail, SubscriberName, SubscriberNamePolicy, SubscriberTag};
 use crate::routes::SubscriptionFormData;
 
 pub struct NewSubscriber {
     pub email: SubscriberEmail,
     pub name: SubscriberName,
+    pub tags: Vec<SubscriberTag>,
 }
 
-impl TryFrom<SubscriptionFormData> for NewSubscriber {
-    type Error = String;
-
-    fn try_from(form: SubscriptionFormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(form.name)?;
+impl NewSubscriber {
+    /// Validates the subscribe form, checking the name against the configured policy
+    pub fn parse(
+        form: SubscriptionFormData,
+        name_policy: &SubscriberNamePolicy,
+    ) -> Result<Self, String> {
+        let name = SubscriberName::parse_with(form.name, name_policy)?;
         let email = SubscriberEmail::parse(form.email)?;
-        Ok(NewSubscriber { name, email })
+        let tags = SubscriberTag::parse_list(&form.tags)?;
+        Ok(NewSubscriber { name, email, tags })
     }
 }
@@ -0,0 +1,65 @@
+/// The short name identifying a newsletter in forms, URLs and API requests
+#[derive(Debug, Clone, PartialEq, Eq, Hash, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct NewsletterSlug(String);
+
+impl NewsletterSlug {
+    /// Slugs are made of lowercase ASCII letters, digits and dashes, so that they can be used
+    /// as-is in URLs
+    pub fn parse(s: String) -> Result<NewsletterSlug, String> {
+        let is_empty = s.is_empty();
+        let is_too_long = s.len() > 64;
+        let contains_forbidden_characters = s
+            .chars()
+            .any(|c| !(c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-'));
+
+        if is_empty || is_too_long || contains_forbidden_characters {
+            Err(format!("{} is not a valid newsletter slug", s))
+        } else {
+            Ok(Self(s))
+        }
+    }
+}
+
+impl TryFrom<String> for NewsletterSlug {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::parse(s)
+    }
+}
+
+impl AsRef<str> for NewsletterSlug {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::NewsletterSlug;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn lowercase_letters_digits_and_dashes_are_accepted() {
+        assert_ok!(NewsletterSlug::parse("rust-weekly-2".to_string()));
+    }
+
+    #[test]
+    fn empty_slugs_are_rejected() {
+        assert_err!(NewsletterSlug::parse("".to_string()));
+    }
+
+    #[test]
+    fn a_slug_longer_than_64_characters_is_rejected() {
+        assert_ok!(NewsletterSlug::parse("a".repeat(64)));
+        assert_err!(NewsletterSlug::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn slugs_containing_invalid_characters_are_rejected() {
+        for slug in &["Rust", "a b", "a_b", "a/b", "é"] {
+            assert_err!(NewsletterSlug::parse(slug.to_string()));
+        }
+    }
+}
@@ -1,38 +1,111 @@
 use validator::validate_email;
 
-#[derive(Debug)]
-pub struct SubscriberEmail(String);
+/// Which internationalized addresses are accepted from new subscribers
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailAddressMode {
+    /// ASCII addresses only
+    #[default]
+    Strict,
+    /// Internationalized domains too, and UTF-8 local parts if the email provider supports
+    /// SMTPUTF8
+    Lenient,
+}
+
+/// Also used for the addresses in the configuration, which are validated when it is loaded.
+/// Internationalized domains are stored in their punycode form, e.g. `ursula@xn--mnchen-3ya.de`
+/// for `ursula@münchen.de`, so that they can be delivered by any email provider.
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct SubscriberEmail {
+    address: String,
+    /// Whether the domain was written with non-ASCII characters
+    idn_domain: bool,
+}
 
 impl std::fmt::Display for SubscriberEmail {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // Just forward to Display impl of wrapped String
-        self.0.fmt(f)
+        self.address.fmt(f)
     }
 }
 
 impl SubscriberEmail {
+    /// Accepts internationalized addresses; whether new subscribers may use them is up to
+    /// `EmailAddressMode`
     pub fn parse(s: String) -> Result<Self, String> {
-        if validate_email(&s) {
-            Ok(Self(s))
+        let invalid = || format!("{} is not a valid subscriber email", s);
+        let (local_part, domain) = s.rsplit_once('@').ok_or_else(invalid)?;
+        let idn_domain = !domain.is_ascii();
+        let domain = if idn_domain {
+            idna::domain_to_ascii_strict(domain).map_err(|_| invalid())?
         } else {
-            Err(format!("{} is not a valid subscriber email", s))
+            domain.to_owned()
+        };
+        // RFC 6531 allows any non-ASCII character wherever ASCII letters are, so they are stood in
+        // for by one when checking the rest of the syntax
+        if local_part
+            .chars()
+            .any(|c| !c.is_ascii() && (c.is_whitespace() || c.is_control()))
+        {
+            return Err(invalid());
+        }
+        let ascii_local_part: String = local_part
+            .chars()
+            .map(|c| if c.is_ascii() { c } else { 'a' })
+            .collect();
+        if !validate_email(format!("{}@{}", ascii_local_part, domain)) {
+            return Err(invalid());
+        }
+        Ok(Self {
+            address: format!("{}@{}", local_part, domain),
+            idn_domain,
+        })
+    }
+
+    /// Whether the local part, before the `@`, has non-ASCII characters, which only email
+    /// providers supporting SMTPUTF8 can deliver to
+    pub fn has_utf8_local_part(&self) -> bool {
+        !self.address.is_ascii()
+    }
+
+    /// Whether the address is accepted from new subscribers in the given mode
+    pub fn is_allowed_in(&self, mode: EmailAddressMode, smtputf8: bool) -> bool {
+        match mode {
+            EmailAddressMode::Strict => !self.idn_domain && !self.has_utf8_local_part(),
+            EmailAddressMode::Lenient => smtputf8 || !self.has_utf8_local_part(),
         }
     }
 }
 
+impl TryFrom<String> for SubscriberEmail {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::parse(s)
+    }
+}
+
 impl AsRef<str> for SubscriberEmail {
     fn as_ref(&self) -> &str {
-        &self.0
+        &self.address
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::SubscriberEmail;
-    use claims::assert_err;
-    use fake::faker::internet::en::SafeEmail;
+    use super::{EmailAddressMode, SubscriberEmail};
+    use claims::{assert_err, assert_ok};
+    use fake::faker::internet::en::{FreeEmailProvider, SafeEmail, Username};
     use fake::Fake;
-    use quickcheck::Gen;
+    use quickcheck::{Arbitrary, Gen};
+
+    const UNICODE_LABELS: [&str; 6] = ["münchen", "bücher", "δοκιμή", "пример", "例え", "café"];
+    const UNICODE_LOCAL_PARTS: [&str; 5] = ["ursula", "josé", "θσερ", "用户", "ünïcödé"];
+
+    fn pick<'a, G: Gen>(g: &mut G, choices: &[&'a str]) -> &'a str {
+        choices[usize::arbitrary(g) % choices.len()]
+    }
 
     #[derive(Clone, Debug)]
     struct ValidEmailFixture(pub String);
@@ -44,11 +117,103 @@ mod tests {
         }
     }
 
+    /// An ASCII local part at a domain with at least one non-ASCII label
+    #[derive(Clone, Debug)]
+    struct IdnEmailFixture(pub String);
+
+    impl quickcheck::Arbitrary for IdnEmailFixture {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            let username: String = Username().fake_with_rng(g);
+            let label = pick(g, &UNICODE_LABELS);
+            let tld = pick(g, &["com", "de", "рф"]);
+            Self(format!("{}@{}.{}", username, label, tld))
+        }
+    }
+
+    /// A local part with non-ASCII characters at an ASCII domain
+    #[derive(Clone, Debug)]
+    struct Utf8LocalPartFixture(pub String);
+
+    impl quickcheck::Arbitrary for Utf8LocalPartFixture {
+        fn arbitrary<G: Gen>(g: &mut G) -> Self {
+            let local_part = pick(g, &UNICODE_LOCAL_PARTS[1..]);
+            let suffix = u16::arbitrary(g);
+            let domain: String = FreeEmailProvider().fake_with_rng(g);
+            Self(format!("{}{}@{}", local_part, suffix, domain))
+        }
+    }
+
     #[quickcheck_macros::quickcheck]
     fn valid_emails_are_parsed_successfully(valid_email: ValidEmailFixture) -> bool {
         SubscriberEmail::parse(valid_email.0).is_ok()
     }
 
+    #[quickcheck_macros::quickcheck]
+    fn ascii_emails_are_kept_as_written_and_allowed_in_every_mode(
+        valid_email: ValidEmailFixture,
+    ) -> bool {
+        let email = SubscriberEmail::parse(valid_email.0.clone()).unwrap();
+        email.as_ref() == valid_email.0
+            && email.is_allowed_in(EmailAddressMode::Strict, false)
+            && email.is_allowed_in(EmailAddressMode::Lenient, false)
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn idn_domains_are_converted_to_punycode(idn_email: IdnEmailFixture) -> bool {
+        let email = SubscriberEmail::parse(idn_email.0).unwrap();
+        let (_, domain) = email.as_ref().rsplit_once('@').unwrap();
+        domain.is_ascii() && domain.split('.').any(|label| label.starts_with("xn--"))
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn idn_domains_are_only_allowed_in_lenient_mode(idn_email: IdnEmailFixture) -> bool {
+        let email = SubscriberEmail::parse(idn_email.0).unwrap();
+        !email.is_allowed_in(EmailAddressMode::Strict, true)
+            && email.is_allowed_in(EmailAddressMode::Lenient, false)
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn parsing_a_parsed_email_again_leaves_it_unchanged(idn_email: IdnEmailFixture) -> bool {
+        let email = SubscriberEmail::parse(idn_email.0).unwrap();
+        let reparsed = SubscriberEmail::parse(email.as_ref().to_owned()).unwrap();
+        reparsed.as_ref() == email.as_ref()
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn utf8_local_parts_require_lenient_mode_and_smtputf8(email: Utf8LocalPartFixture) -> bool {
+        let email = SubscriberEmail::parse(email.0).unwrap();
+        email.has_utf8_local_part()
+            && !email.is_allowed_in(EmailAddressMode::Strict, true)
+            && !email.is_allowed_in(EmailAddressMode::Lenient, false)
+            && email.is_allowed_in(EmailAddressMode::Lenient, true)
+    }
+
+    #[quickcheck_macros::quickcheck]
+    fn parsed_emails_always_have_an_ascii_domain(s: String) -> bool {
+        match SubscriberEmail::parse(s) {
+            Ok(email) => email.as_ref().rsplit_once('@').unwrap().1.is_ascii(),
+            Err(_) => true,
+        }
+    }
+
+    #[test]
+    fn idn_domains_are_stored_in_their_punycode_form() {
+        let email = assert_ok!(SubscriberEmail::parse("ursula@münchen.de".into()));
+        assert_eq!(email.as_ref(), "ursula@xn--mnchen-3ya.de");
+    }
+
+    #[test]
+    fn non_ascii_whitespace_in_the_local_part_is_invalid() {
+        assert_err!(SubscriberEmail::parse("ursula\u{a0}le@domain.com".into()));
+        assert_err!(SubscriberEmail::parse("ursula\u{2028}@domain.com".into()));
+    }
+
+    #[test]
+    fn invalid_idn_domains_are_rejected() {
+        assert_err!(SubscriberEmail::parse("ursula@münchen..de".into()));
+        assert_err!(SubscriberEmail::parse("ursula@mün_chen.de".into()));
+    }
+
     #[test]
     fn empty_string_is_invalid() {
         let email = "".to_string();
@@ -66,4 +231,11 @@ mod tests {
         let email = "@domain.com".to_string();
         assert_err!(SubscriberEmail::parse(email));
     }
+
+    #[test]
+    fn invalid_emails_fail_deserialization() {
+        assert_err!(serde_json::from_str::<SubscriberEmail>(r#""domain.com""#));
+        let email: SubscriberEmail = serde_json::from_str(r#""ursula@domain.com""#).unwrap();
+        assert_eq!(email.as_ref(), "ursula@domain.com");
+    }
 }
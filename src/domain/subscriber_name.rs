@@ -1,21 +1,61 @@
+use unicode_normalization::UnicodeNormalization;
 use unicode_segmentation::UnicodeSegmentation;
 
+/// The rules the names of new subscribers must follow
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SubscriberNamePolicy {
+    /// Longest name accepted, in user-perceived characters
+    pub max_graphemes: usize,
+    /// Names containing any of these characters are rejected
+    pub forbidden_characters: String,
+    /// Drops control characters, e.g. line breaks, instead of storing them
+    pub strip_control_characters: bool,
+    /// Drops the whitespace around the name
+    pub trim: bool,
+}
+
+impl Default for SubscriberNamePolicy {
+    fn default() -> Self {
+        Self {
+            max_graphemes: 256,
+            forbidden_characters: r#"/()"<>\{}"#.into(),
+            strip_control_characters: false,
+            trim: false,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct SubscriberName(String);
 
 impl SubscriberName {
-    /// Returns an Ok Result of `SubscriberName if the input satisfies validation constraints.
+    /// Returns an Ok Result of `SubscriberName if the input satisfies the default policy.
     pub fn parse(s: String) -> Result<SubscriberName, String> {
-        let is_empty_or_whitespace = s.trim().is_empty();
-        let is_too_long = s.graphemes(true).count() > 256;
+        Self::parse_with(s, &SubscriberNamePolicy::default())
+    }
 
-        let forbidden_characters = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
-        let contains_forbidden_characters = s.chars().any(|c| forbidden_characters.contains(&c));
+    /// Normalizes the name to its composed form (NFC), so that the same name is always stored the
+    /// same way whatever the keyboard it was typed on, then checks it against the policy
+    pub fn parse_with(s: String, policy: &SubscriberNamePolicy) -> Result<SubscriberName, String> {
+        let mut name: String = s.nfc().collect();
+        if policy.strip_control_characters {
+            name.retain(|c| !c.is_control());
+        }
+        if policy.trim {
+            name = name.trim().to_owned();
+        }
+
+        let is_empty_or_whitespace = name.trim().is_empty();
+        let is_too_long = name.graphemes(true).count() > policy.max_graphemes;
+        let contains_forbidden_characters = name
+            .chars()
+            .any(|c| policy.forbidden_characters.contains(c));
 
         if is_empty_or_whitespace || is_too_long || contains_forbidden_characters {
             Err(format!("{} is not a valid subscriber name", s))
         } else {
-            Ok(Self(s))
+            Ok(Self(name))
         }
     }
 }
@@ -28,7 +68,7 @@ impl AsRef<str> for crate::domain::SubscriberName {
 
 #[cfg(test)]
 mod tests {
-    use crate::domain::SubscriberName;
+    use crate::domain::{SubscriberName, SubscriberNamePolicy};
     use claims::{assert_err, assert_ok};
 
     #[test]
@@ -63,6 +103,51 @@ mod tests {
         }
     }
 
+    #[test]
+    fn names_are_stored_in_their_composed_form() {
+        // "é" written as an "e" followed by a combining acute accent
+        let name = assert_ok!(SubscriberName::parse("Rene\u{301}e".to_string()));
+        assert_eq!(name.as_ref(), "Ren\u{e9}e");
+    }
+
+    #[test]
+    fn the_policy_sets_the_length_limit_and_the_forbidden_characters() {
+        let policy = SubscriberNamePolicy {
+            max_graphemes: 3,
+            forbidden_characters: "@".into(),
+            ..Default::default()
+        };
+        assert_ok!(SubscriberName::parse_with("ёёё".to_string(), &policy));
+        assert_err!(SubscriberName::parse_with("ёёёё".to_string(), &policy));
+        assert_err!(SubscriberName::parse_with("a@b".to_string(), &policy));
+        assert_ok!(SubscriberName::parse_with("(a)".to_string(), &policy));
+    }
+
+    #[test]
+    fn control_characters_are_stripped_and_names_trimmed_if_the_policy_says_so() {
+        let name = "  Ursula\n Le Guin\u{7}  ".to_string();
+        assert_ok!(SubscriberName::parse(name.clone()));
+        let policy = SubscriberNamePolicy {
+            strip_control_characters: true,
+            trim: true,
+            ..Default::default()
+        };
+        let parsed = assert_ok!(SubscriberName::parse_with(name, &policy));
+        assert_eq!(parsed.as_ref(), "Ursula Le Guin");
+    }
+
+    #[test]
+    fn names_made_of_control_characters_only_are_rejected_once_stripped() {
+        let policy = SubscriberNamePolicy {
+            strip_control_characters: true,
+            ..Default::default()
+        };
+        assert_err!(SubscriberName::parse_with(
+            "\u{0}\u{1b}".to_string(),
+            &policy
+        ));
+    }
+
     #[test]
     fn valid_name_is_parsed_successfully() {
         let name = "Foo Bar".to_string();
@@ -0,0 +1,94 @@
+/// A label attached to subscribers; an issue can target the subscribers sharing a tag
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    /// Tags are case-insensitive, so they are trimmed and lowercased before being validated
+    pub fn parse(s: String) -> Result<SubscriberTag, String> {
+        let tag = s.trim().to_lowercase();
+        let is_empty = tag.is_empty();
+        let is_too_long = tag.chars().count() > 64;
+        let contains_forbidden_characters = tag
+            .chars()
+            .any(|c| !(c.is_alphanumeric() || c == '-' || c == '_'));
+
+        if is_empty || is_too_long || contains_forbidden_characters {
+            Err(format!("{} is not a valid subscriber tag", s))
+        } else {
+            Ok(Self(tag))
+        }
+    }
+
+    /// Parses an optional tag: an empty or blank string means that there is no tag
+    pub fn parse_optional(s: String) -> Result<Option<SubscriberTag>, String> {
+        if s.trim().is_empty() {
+            Ok(None)
+        } else {
+            Self::parse(s).map(Some)
+        }
+    }
+
+    /// Parses a comma-separated list of tags, ignoring empty entries
+    pub fn parse_list(s: &str) -> Result<Vec<SubscriberTag>, String> {
+        s.split(',')
+            .filter(|tag| !tag.trim().is_empty())
+            .map(|tag| Self::parse(tag.to_string()))
+            .collect()
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberTag;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn tags_are_normalized() {
+        let tag = assert_ok!(SubscriberTag::parse(" Rust-Weekly ".to_string()));
+        assert_eq!(tag.as_ref(), "rust-weekly");
+    }
+
+    #[test]
+    fn empty_tags_are_rejected() {
+        assert_err!(SubscriberTag::parse(" ".to_string()));
+    }
+
+    #[test]
+    fn a_tag_longer_than_64_characters_is_rejected() {
+        assert_ok!(SubscriberTag::parse("a".repeat(64)));
+        assert_err!(SubscriberTag::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn tags_containing_invalid_characters_are_rejected() {
+        for tag in &["a b", "a,b", "<b>", "a/b"] {
+            assert_err!(SubscriberTag::parse(tag.to_string()));
+        }
+    }
+
+    #[test]
+    fn a_blank_optional_tag_is_none() {
+        assert_eq!(
+            assert_ok!(SubscriberTag::parse_optional(" ".to_string())),
+            None
+        );
+    }
+
+    #[test]
+    fn a_list_of_tags_ignores_empty_entries() {
+        let tags = assert_ok!(SubscriberTag::parse_list("rust, ,Go,"));
+        let tags: Vec<&str> = tags.iter().map(AsRef::as_ref).collect();
+        assert_eq!(tags, vec!["rust", "go"]);
+    }
+
+    #[test]
+    fn a_list_with_an_invalid_tag_is_rejected() {
+        assert_err!(SubscriberTag::parse_list("rust,a b"));
+    }
+}
@@ -0,0 +1,1543 @@
+mod resilience;
+mod ses;
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::Ordering;
+use std::time::Duration;
+
+use anyhow::Context;
+use base64::Engine;
+use reqwest::header::RETRY_AFTER;
+use reqwest::{Client, StatusCode, Url};
+use secrecy::{ExposeSecret, Secret};
+
+use crate::async_helpers::spawn_blocking_with_tracing;
+use crate::domain::SubscriberEmail;
+use crate::error_handling::error_chain_fmt;
+use resilience::{CircuitBreaker, ResilienceMetrics};
+pub use resilience::{CircuitBreakerPolicy, SendRetryPolicy};
+pub use ses::SesCredentials;
+pub(crate) use ses::{hmac_sha256, signing_key};
+
+pub struct EmailClient {
+    sender: SenderIdentity,
+    /// Senders of the newsletters that don't use the default one, by slug
+    newsletter_senders: HashMap<String, SenderIdentity>,
+    http_client: Client,
+    base_url: Url,
+    backend: EmailBackend,
+    retry_policy: SendRetryPolicy,
+    circuit_breaker: CircuitBreaker,
+    resilience_metrics: ResilienceMetrics,
+}
+
+/// Who the emails appear to come from
+#[derive(Debug, Clone)]
+pub struct SenderIdentity {
+    pub email: SubscriberEmail,
+    /// Shown next to the address by email clients
+    pub name: Option<String>,
+    /// Where replies go, if not to the sender address
+    pub reply_to: Option<SubscriberEmail>,
+    /// The Postmark message stream the emails go through; its default stream if not set.
+    /// SES has no equivalent, so it is ignored there.
+    pub message_stream: Option<String>,
+}
+
+impl SenderIdentity {
+    /// The value of the `From` header, e.g. `"Weekly Digest" <digest@example.com>`
+    fn display_address(&self) -> String {
+        match &self.name {
+            // quotes would end the display name early, so they are dropped
+            Some(name) => format!("\"{}\" <{}>", name.replace('"', ""), self.email.as_ref()),
+            None => self.email.as_ref().to_owned(),
+        }
+    }
+}
+
+impl From<SubscriberEmail> for SenderIdentity {
+    fn from(email: SubscriberEmail) -> Self {
+        Self {
+            email,
+            name: None,
+            reply_to: None,
+            message_stream: None,
+        }
+    }
+}
+
+/// The email delivery API sitting behind an `EmailClient`
+enum EmailBackend {
+    Postmark {
+        authorization_token: Secret<String>,
+    },
+    Ses(SesCredentials),
+    /// Nothing leaves the server: emails are logged, and appended to the file if there is one
+    Sandbox {
+        log_path: Option<PathBuf>,
+    },
+}
+
+#[derive(thiserror::Error)]
+pub enum SendEmailError {
+    /// The provider will never deliver to the recipient: their address is malformed, or it
+    /// stopped delivering to it after bounces or complaints
+    #[error("The email provider rejected the recipient with a {status}: {message}")]
+    Permanent {
+        status: u16,
+        /// The Postmark error code, if the provider sent one
+        error_code: Option<i64>,
+        message: String,
+    },
+    /// The provider refuses the requests of the account, e.g. for an invalid token, a suspended
+    /// account or an unconfirmed sender. Sending may work once the account is fixed, so this is
+    /// retried later and counts against the circuit breaker, but never against the recipient.
+    #[error("The email provider refused the request with a {status}: {message}")]
+    Refused {
+        status: u16,
+        /// The Postmark error code, if the provider sent one
+        error_code: Option<i64>,
+        message: String,
+    },
+    /// Sending again later may work: timeouts, connection failures and server errors
+    #[error("Failed to reach the email provider")]
+    Transient(#[from] reqwest::Error),
+    /// The provider, or a proxy in front of it, answered with a status it never sends for an
+    /// email, e.g. a redirect. Handled like a transient failure: it says nothing of the recipient.
+    #[error("The email provider answered with an unexpected {status}")]
+    UnexpectedResponse { status: u16 },
+    /// The provider asks to slow down, for `retry_after` if it said how long
+    #[error("The email provider is rate limiting the requests")]
+    RateLimited { retry_after: Option<Duration> },
+    #[error("Failed to record the email in the sandbox log")]
+    Sandbox(#[source] anyhow::Error),
+    #[error("The email provider is considered down; not calling it for another {retry_after:?}")]
+    CircuitOpen { retry_after: Duration },
+}
+
+/// What the provider said about an email it accepted
+#[derive(Debug, Default)]
+pub struct SentEmail {
+    /// The id the provider gave the message, which its webhooks refer to; none in sandbox mode
+    pub message_id: Option<String>,
+    /// When the provider accepted the message, as it reports it; SES does not
+    pub submitted_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Postmark refuses to send to addresses that hard bounced, complained or unsubscribed
+const POSTMARK_INACTIVE_RECIPIENT: i64 = 406;
+/// Postmark rejects invalid requests with this code, e.g. when the `To` address is malformed
+const POSTMARK_INVALID_REQUEST: i64 = 300;
+
+/// Whether Postmark blames the recipient for an error, rather than the request or the account
+fn is_recipient_error(error_code: Option<i64>, message: &str) -> bool {
+    match error_code {
+        Some(POSTMARK_INACTIVE_RECIPIENT) => true,
+        Some(POSTMARK_INVALID_REQUEST) => message.contains("'To'"),
+        _ => false,
+    }
+}
+
+impl SendEmailError {
+    /// Turns an unsuccessful response of the provider into an error telling whether to retry
+    async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        if status == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse().ok())
+                .map(Duration::from_secs);
+            return SendEmailError::RateLimited { retry_after };
+        }
+        if !status.is_client_error() || status == StatusCode::REQUEST_TIMEOUT {
+            return match response.error_for_status() {
+                Err(e) => SendEmailError::Transient(e),
+                Ok(response) => SendEmailError::UnexpectedResponse {
+                    status: response.status().as_u16(),
+                },
+            };
+        }
+        // Postmark answers with `{"ErrorCode": 406, "Message": "..."}`, SES with `{"message": "..."}`
+        let body: serde_json::Value = response.json().await.unwrap_or_default();
+        let status = status.as_u16();
+        let error_code = body["ErrorCode"].as_i64();
+        let message = body["Message"]
+            .as_str()
+            .or_else(|| body["message"].as_str())
+            .unwrap_or_default()
+            .to_owned();
+        if is_recipient_error(error_code, &message) {
+            SendEmailError::Permanent {
+                status,
+                error_code,
+                message,
+            }
+        } else {
+            SendEmailError::Refused {
+                status,
+                error_code,
+                message,
+            }
+        }
+    }
+}
+
+impl Debug for SendEmailError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl EmailClient {
+    /// Builds a client delivering emails through Postmark
+    pub fn new(
+        base_url: String,
+        sender: SenderIdentity,
+        authorization_token: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        Self::with_backend(
+            base_url,
+            sender,
+            EmailBackend::Postmark {
+                authorization_token,
+            },
+            timeout,
+        )
+    }
+
+    /// Builds a client delivering emails through Amazon SES
+    pub fn ses(
+        base_url: String,
+        sender: SenderIdentity,
+        credentials: SesCredentials,
+        timeout: std::time::Duration,
+    ) -> Self {
+        Self::with_backend(base_url, sender, EmailBackend::Ses(credentials), timeout)
+    }
+
+    /// Builds a client that never delivers anything, for staging environments. Emails are logged
+    /// and, if `log_path` is set, recorded in that file as JSON lines shaped like Postmark requests.
+    pub fn sandbox(
+        base_url: String,
+        sender: SenderIdentity,
+        log_path: Option<PathBuf>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        Self::with_backend(
+            base_url,
+            sender,
+            EmailBackend::Sandbox { log_path },
+            timeout,
+        )
+    }
+
+    fn with_backend(
+        base_url: String,
+        sender: SenderIdentity,
+        backend: EmailBackend,
+        timeout: std::time::Duration,
+    ) -> Self {
+        // more type-driven development: take a string, parse as a Url. Now we know, from this point forward,
+        // that base_url is valid.
+        let base_url = Url::parse(&base_url).expect("Failed to parse base_url");
+
+        // building new http_client with a timeout; could also use per-request timeouts
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+
+        Self {
+            http_client,
+            base_url,
+            sender,
+            newsletter_senders: HashMap::new(),
+            backend,
+            retry_policy: SendRetryPolicy::none(),
+            circuit_breaker: CircuitBreaker::default(),
+            resilience_metrics: ResilienceMetrics::default(),
+        }
+    }
+
+    /// Retries the requests that failed for a reason that may go away and, with a circuit breaker
+    /// policy, stops calling the provider for a while once it keeps failing
+    pub fn with_resilience(
+        mut self,
+        retry_policy: SendRetryPolicy,
+        circuit_breaker: Option<CircuitBreakerPolicy>,
+    ) -> Self {
+        self.retry_policy = retry_policy;
+        self.circuit_breaker = CircuitBreaker::new(circuit_breaker);
+        self
+    }
+
+    /// Writes the counters of the retries and of the circuit breaker in the Prometheus text format
+    pub fn write_prometheus(&self, body: &mut String) {
+        self.resilience_metrics
+            .write_prometheus(body, self.circuit_breaker.is_open());
+    }
+
+    /// Sets the senders of the newsletters that don't send from the default identity, by slug
+    pub fn with_newsletter_senders(mut self, senders: HashMap<String, SenderIdentity>) -> Self {
+        self.newsletter_senders = senders;
+        self
+    }
+
+    /// The identity the emails of a newsletter are sent from
+    pub fn sender_of(&self, newsletter_slug: &str) -> &SenderIdentity {
+        self.newsletter_senders
+            .get(newsletter_slug)
+            .unwrap_or(&self.sender)
+    }
+
+    /// Sends an email from the default sender identity
+    pub async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), SendEmailError> {
+        self.send_email_from(&self.sender, recipient, subject, html_content, text_content)
+            .await
+    }
+
+    pub async fn send_email_from(
+        &self,
+        sender: &SenderIdentity,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), SendEmailError> {
+        self.send_email_with(
+            sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            &EmailOptions::default(),
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Sends an email with copies, extra headers or a reply address of its own
+    pub async fn send_email_with(
+        &self,
+        sender: &SenderIdentity,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        options: &EmailOptions,
+    ) -> Result<SentEmail, SendEmailError> {
+        let email = Email {
+            sender,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            options,
+        };
+        let mut attempt = 1;
+        loop {
+            if let Err(retry_after) = self.circuit_breaker.check() {
+                self.resilience_metrics
+                    .rejections
+                    .fetch_add(1, Ordering::Relaxed);
+                return Err(SendEmailError::CircuitOpen { retry_after });
+            }
+            let outcome = self.send_once(&email).await;
+            let retry_in = match &outcome {
+                Ok(_) => {
+                    self.circuit_breaker.record_success();
+                    None
+                }
+                Err(
+                    e @ (SendEmailError::Transient(_)
+                    | SendEmailError::UnexpectedResponse { .. }
+                    | SendEmailError::Refused { .. }),
+                ) => {
+                    if self.circuit_breaker.record_failure() {
+                        self.resilience_metrics
+                            .trips
+                            .fetch_add(1, Ordering::Relaxed);
+                        tracing::warn!(
+                            error.cause_chain = ?e,
+                            "The email provider keeps failing; no longer calling it for a while."
+                        );
+                        None
+                    } else if let SendEmailError::Refused { .. } = e {
+                        // the account will not be fixed within the delay of a retry
+                        None
+                    } else {
+                        Some(self.retry_policy.delay(attempt))
+                    }
+                }
+                // the provider is up, and only asks to slow down: waiting longer than a retry
+                // would is left to the caller
+                Err(SendEmailError::RateLimited { retry_after }) => {
+                    self.circuit_breaker.record_success();
+                    Some(retry_after.unwrap_or_else(|| self.retry_policy.delay(attempt)))
+                        .filter(|delay| *delay <= self.retry_policy.max_delay)
+                }
+                // the provider answered: rejected emails say nothing about its health
+                Err(_) => {
+                    self.circuit_breaker.record_success();
+                    None
+                }
+            };
+            match retry_in {
+                Some(delay) if attempt < self.retry_policy.max_attempts => {
+                    tracing::info!(
+                        error.cause_chain = ?outcome.as_ref().err(),
+                        attempt,
+                        "Failed to call the email provider. Retrying in {:?}.",
+                        delay
+                    );
+                    self.resilience_metrics
+                        .retries
+                        .fetch_add(1, Ordering::Relaxed);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                _ => return outcome,
+            }
+        }
+    }
+
+    async fn send_once(&self, email: &Email<'_>) -> Result<SentEmail, SendEmailError> {
+        match &self.backend {
+            EmailBackend::Postmark {
+                authorization_token,
+            } => self.send_postmark_email(authorization_token, email).await,
+            EmailBackend::Ses(credentials) => self.send_ses_email(credentials, email).await,
+            EmailBackend::Sandbox { log_path } => record_sandbox_email(log_path.as_ref(), email)
+                .await
+                .map(|()| SentEmail::default()),
+        }
+    }
+
+    async fn send_postmark_email(
+        &self,
+        authorization_token: &Secret<String>,
+        email: &Email<'_>,
+    ) -> Result<SentEmail, SendEmailError> {
+        let url = self
+            .base_url
+            .join("/email")
+            .expect("Failed to join /email with base url");
+
+        let request_body = email.postmark_request();
+
+        let response = self
+            .http_client
+            .post(url) // doesn't actually send request; that's what `send` method is for
+            .header(
+                "X-Postmark-Server-Token",
+                authorization_token.expose_secret(),
+            )
+            .json(&request_body) // also sets appropriate content-type headers
+            .send()
+            .await?;
+        /* Note that `send` only returns an error if sending the request failed, if a redirect loop
+        was detected, or the redirect limit was exhausted. It does not return errors based on status codes,
+        so we need to check the status ourselves. */
+        if !response.status().is_success() {
+            return Err(SendEmailError::from_response(response).await);
+        }
+        // the email went out even if the body cannot be read, only its id is missing
+        let body: serde_json::Value = response.json().await.unwrap_or_default();
+        Ok(SentEmail {
+            message_id: body["MessageID"].as_str().map(ToOwned::to_owned),
+            submitted_at: body["SubmittedAt"]
+                .as_str()
+                .and_then(|submitted_at| chrono::DateTime::parse_from_rfc3339(submitted_at).ok())
+                .map(|submitted_at| submitted_at.with_timezone(&chrono::Utc)),
+        })
+    }
+
+    async fn send_ses_email(
+        &self,
+        credentials: &SesCredentials,
+        email: &Email<'_>,
+    ) -> Result<SentEmail, SendEmailError> {
+        let url = self
+            .base_url
+            .join(ses::SEND_EMAIL_PATH)
+            .expect("Failed to join the SES path with base url");
+
+        let from = email.sender.display_address();
+        let mut request_body = ses::SendEmailRequest::new(
+            &from,
+            email.recipient.as_ref(),
+            email.subject,
+            email.html_content,
+            email.text_content,
+        );
+        request_body.reply_to_addresses = email.reply_to().into_iter().collect();
+        request_body.destination.cc_addresses =
+            email.options.cc.iter().map(AsRef::as_ref).collect();
+        request_body.destination.bcc_addresses =
+            email.options.bcc.iter().map(AsRef::as_ref).collect();
+        request_body.content.simple.headers = email.headers();
+        request_body.content.simple.attachments = email
+            .options
+            .attachments
+            .iter()
+            .map(|attachment| ses::Attachment {
+                file_name: &attachment.filename,
+                raw_content: attachment.base64_content(),
+                content_type: &attachment.content_type,
+            })
+            .collect();
+        // the signature covers the exact payload bytes, so we serialize the body ourselves
+        // instead of relying on reqwest's `json` method
+        let payload = serde_json::to_vec(&request_body).expect("Failed to serialize SES request");
+        let signed = ses::sign_request(credentials, &url, &payload, chrono::Utc::now());
+
+        let response = self
+            .http_client
+            .post(url)
+            .header("Content-Type", "application/json")
+            .header("X-Amz-Date", signed.amz_date)
+            .header("Authorization", signed.authorization)
+            .body(payload)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(SendEmailError::from_response(response).await);
+        }
+        let body: serde_json::Value = response.json().await.unwrap_or_default();
+        Ok(SentEmail {
+            message_id: body["MessageId"].as_str().map(ToOwned::to_owned),
+            ..Default::default()
+        })
+    }
+}
+
+#[tracing::instrument(name = "Record a sandboxed email", skip_all)]
+async fn record_sandbox_email(
+    log_path: Option<&PathBuf>,
+    email: &Email<'_>,
+) -> Result<(), SendEmailError> {
+    tracing::info!(
+        from = %email.sender.display_address(),
+        to = %email.recipient.as_ref(),
+        subject = %email.subject,
+        "Sandbox mode: the email was not sent.",
+    );
+    let Some(log_path) = log_path.cloned() else {
+        return Ok(());
+    };
+    let mut record = email.postmark_request();
+    // the log is for reading what would have been sent, not for storing every attachment again
+    if let Some(attachments) = record["Attachments"].as_array_mut() {
+        for attachment in attachments {
+            attachment["Content"] = serde_json::Value::Null;
+        }
+    }
+    record["SentAt"] = chrono::Utc::now().to_rfc3339().into();
+    let mut line = record.to_string();
+    line.push('\n');
+    spawn_blocking_with_tracing(move || {
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)
+            .and_then(|mut file| file.write_all(line.as_bytes()))
+            .with_context(|| format!("Failed to append to {}", log_path.display()))
+    })
+    .await
+    .context("Failed to spawn a blocking task")
+    .and_then(|outcome| outcome)
+    .map_err(SendEmailError::Sandbox)
+}
+
+/// What callers can set on an email besides its sender, recipient and content
+#[derive(Debug, Clone, Default)]
+pub struct EmailOptions {
+    /// Where replies go; takes precedence over the reply address of the sender identity
+    pub reply_to: Option<SubscriberEmail>,
+    pub cc: Vec<SubscriberEmail>,
+    pub bcc: Vec<SubscriberEmail>,
+    /// Additional headers, e.g. `List-Id`, as name and value pairs
+    pub headers: Vec<(String, String)>,
+    pub attachments: Vec<Attachment>,
+}
+
+/// Largest file that can be attached to an email. Postmark rejects messages above 10 MB, and
+/// base64 makes attachments a third bigger on the wire.
+pub const MAX_ATTACHMENT_SIZE: usize = 7 * 1024 * 1024;
+
+/// A file sent along with an email, e.g. a PDF for new subscribers
+#[derive(Clone)]
+pub struct Attachment {
+    filename: String,
+    content_type: String,
+    content: Vec<u8>,
+}
+
+impl Attachment {
+    /// Fails if the content is larger than `MAX_ATTACHMENT_SIZE`
+    pub fn new(filename: String, content_type: String, content: Vec<u8>) -> Result<Self, String> {
+        if content.len() > MAX_ATTACHMENT_SIZE {
+            return Err(format!(
+                "{} is too large to be attached to an email: {} bytes, at most {} are allowed.",
+                filename,
+                content.len(),
+                MAX_ATTACHMENT_SIZE
+            ));
+        }
+        Ok(Self {
+            filename,
+            content_type,
+            content,
+        })
+    }
+
+    fn base64_content(&self) -> String {
+        base64::engine::general_purpose::STANDARD.encode(&self.content)
+    }
+}
+
+impl std::fmt::Debug for Attachment {
+    // the content could be megabytes of binary data
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Attachment")
+            .field("filename", &self.filename)
+            .field("content_type", &self.content_type)
+            .field("size", &self.content.len())
+            .finish()
+    }
+}
+
+/// An email on its way to one of the backends
+struct Email<'a> {
+    sender: &'a SenderIdentity,
+    recipient: &'a SubscriberEmail,
+    subject: &'a str,
+    html_content: &'a str,
+    text_content: &'a str,
+    options: &'a EmailOptions,
+}
+
+impl<'a> Email<'a> {
+    fn reply_to(&self) -> Option<&'a str> {
+        self.options
+            .reply_to
+            .as_ref()
+            .or(self.sender.reply_to.as_ref())
+            .map(AsRef::as_ref)
+    }
+
+    /// The body of the Postmark API request sending the email
+    fn postmark_request(&self) -> serde_json::Value {
+        let from = self.sender.display_address();
+        // Postmark takes the copies as comma separated lists
+        let cc = self
+            .options
+            .cc
+            .iter()
+            .map(AsRef::as_ref)
+            .collect::<Vec<_>>()
+            .join(",");
+        let bcc = self
+            .options
+            .bcc
+            .iter()
+            .map(AsRef::as_ref)
+            .collect::<Vec<_>>()
+            .join(",");
+        let request_body = SendEmailRequest {
+            from: &from,
+            to: self.recipient.as_ref(),
+            subject: self.subject,
+            html_body: self.html_content,
+            text_body: self.text_content,
+            reply_to: self.reply_to(),
+            cc: (!cc.is_empty()).then_some(&cc),
+            bcc: (!bcc.is_empty()).then_some(&bcc),
+            headers: self.headers(),
+            attachments: self
+                .options
+                .attachments
+                .iter()
+                .map(|attachment| PostmarkAttachment {
+                    name: &attachment.filename,
+                    content: attachment.base64_content(),
+                    content_type: &attachment.content_type,
+                })
+                .collect(),
+            message_stream: self.sender.message_stream.as_deref(),
+        };
+        serde_json::to_value(request_body).expect("Failed to serialize the Postmark request")
+    }
+
+    fn headers(&self) -> Vec<Header<'a>> {
+        self.options
+            .headers
+            .iter()
+            .map(|(name, value)| Header { name, value })
+            .collect()
+    }
+}
+
+/// A custom header, in the shape both Postmark and SES expect
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Header<'a> {
+    name: &'a str,
+    value: &'a str,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cc: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bcc: Option<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<Header<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<PostmarkAttachment<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message_stream: Option<&'a str>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkAttachment<'a> {
+    name: &'a str,
+    /// Base64 encoded
+    content: String,
+    content_type: &'a str,
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use secrecy::Secret;
+    use wiremock::matchers::{any, body_partial_json, header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{
+        Attachment, CircuitBreakerPolicy, EmailClient, EmailOptions, SendEmailError,
+        SendRetryPolicy, SenderIdentity, SesCredentials, MAX_ATTACHMENT_SIZE,
+    };
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            // Try to parse the body as JSON
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                // check that the body contains mandatory fields
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    struct SesSendEmailBodyMatcher;
+
+    impl wiremock::Match for SesSendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                // check that the body follows the SES v2 `SendEmail` shape
+                body.get("FromEmailAddress").is_some()
+                    && body["Destination"]["ToAddresses"].is_array()
+                    && body["Content"]["Simple"]["Subject"]["Data"].is_string()
+                    && body["Content"]["Simple"]["Body"]["Html"]["Data"].is_string()
+                    && body["Content"]["Simple"]["Body"]["Text"]["Data"].is_string()
+            } else {
+                false
+            }
+        }
+    }
+
+    /// Generates a new email client for tests, using a random sender email and authorization token.
+    fn email_client(base_url: String) -> EmailClient {
+        EmailClient::new(
+            base_url,
+            email().into(),
+            Secret::new(Faker.fake()),
+            std::time::Duration::from_millis(100),
+        )
+    }
+
+    /// Generates a new SES email client for tests, using a random sender email and credentials.
+    fn ses_email_client(base_url: String) -> EmailClient {
+        EmailClient::ses(
+            base_url,
+            email().into(),
+            SesCredentials {
+                region: "us-east-1".into(),
+                access_key_id: Faker.fake(),
+                secret_access_key: Secret::new(Faker.fake()),
+            },
+            std::time::Duration::from_millis(100),
+        )
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake()).unwrap()
+    }
+
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        // Arrange
+        let mock_server = MockServer::start().await; // spins up a server on random available port
+        let email_client = email_client(mock_server.uri());
+
+        // by default, MockServer returns 404 to all requests; we mount a mock to change this behavior
+        Mock::given(header_exists("X-Postmark-Server-Token")) // match requests with that header
+            .and(header("Content-Type", "application/json")) // and with this header, etc...
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200)) // response with 200, no body
+            .expect(1) // expect 1 incoming request; panics if not met by time mock server goes out of scope
+            .mount(&mock_server) // mount the mock to the server
+            .await;
+
+        let subscriber_email = email();
+        let subject = subject();
+        let content = content();
+
+        // Act
+        let _ = email_client
+            .send_email(&subscriber_email, &subject, &content, &content)
+            .await;
+
+        // Assert handled by Mock...expect(1)
+    }
+
+    #[tokio::test]
+    async fn newsletters_with_their_own_sender_are_sent_from_it() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let sender = SenderIdentity {
+            email: SubscriberEmail::parse("digest@example.com".into()).unwrap(),
+            name: Some("The \"Weekly\" Digest".into()),
+            reply_to: Some(SubscriberEmail::parse("editor@example.com".into()).unwrap()),
+            message_stream: Some("broadcast".into()),
+        };
+        let email_client = email_client(mock_server.uri())
+            .with_newsletter_senders([("digest".to_owned(), sender)].into());
+
+        Mock::given(path("/email"))
+            .and(body_partial_json(serde_json::json!({
+                "From": "\"The Weekly Digest\" <digest@example.com>",
+                "ReplyTo": "editor@example.com",
+                "MessageStream": "broadcast",
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email_from(
+                email_client.sender_of("digest"),
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn other_newsletters_are_sent_from_the_default_sender() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        email_client
+            .send_email_from(
+                email_client.sender_of("digest"),
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+            )
+            .await
+            .unwrap();
+
+        // Assert
+        let requests = mock_server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(body["From"], email_client.sender.email.as_ref());
+        assert!(body.get("ReplyTo").is_none());
+        assert!(body.get("MessageStream").is_none());
+    }
+
+    fn options() -> EmailOptions {
+        EmailOptions {
+            reply_to: Some(SubscriberEmail::parse("editor@example.com".into()).unwrap()),
+            cc: vec![
+                SubscriberEmail::parse("first@example.com".into()).unwrap(),
+                SubscriberEmail::parse("second@example.com".into()).unwrap(),
+            ],
+            bcc: vec![SubscriberEmail::parse("archive@example.com".into()).unwrap()],
+            headers: vec![("List-Id".into(), "Digest <digest.example.com>".into())],
+            attachments: vec![Attachment::new(
+                "guide.pdf".into(),
+                "application/pdf".into(),
+                b"%PDF".to_vec(),
+            )
+            .unwrap()],
+        }
+    }
+
+    #[test]
+    fn attachments_are_size_limited() {
+        let attach =
+            |size| Attachment::new("a.pdf".into(), "application/pdf".into(), vec![0; size]);
+        assert_ok!(attach(MAX_ATTACHMENT_SIZE));
+        assert_err!(attach(MAX_ATTACHMENT_SIZE + 1));
+    }
+
+    #[tokio::test]
+    async fn send_email_with_options_adds_them_to_the_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(path("/email"))
+            .and(body_partial_json(serde_json::json!({
+                "ReplyTo": "editor@example.com",
+                "Cc": "first@example.com,second@example.com",
+                "Bcc": "archive@example.com",
+                "Headers": [{ "Name": "List-Id", "Value": "Digest <digest.example.com>" }],
+                "Attachments": [{
+                    "Name": "guide.pdf",
+                    "Content": "JVBERg==",
+                    "ContentType": "application/pdf",
+                }],
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email_with(
+                &email_client.sender,
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                &options(),
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn sandboxed_emails_are_recorded_instead_of_sent() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let log_path = std::env::temp_dir().join(format!("{}.jsonl", uuid::Uuid::new_v4()));
+        let email_client = EmailClient::sandbox(
+            mock_server.uri(),
+            email().into(),
+            Some(log_path.clone()),
+            std::time::Duration::from_millis(100),
+        );
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+        let recipient = email();
+
+        // Act
+        for _ in 0..2 {
+            email_client
+                .send_email_with(
+                    &email_client.sender,
+                    &recipient,
+                    "Sandboxed",
+                    &content(),
+                    &content(),
+                    &options(),
+                )
+                .await
+                .unwrap();
+        }
+
+        // Assert
+        let log = std::fs::read_to_string(&log_path).unwrap();
+        std::fs::remove_file(&log_path).unwrap();
+        let records: Vec<serde_json::Value> = log
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0]["To"], recipient.as_ref());
+        assert_eq!(records[0]["Subject"], "Sandboxed");
+        assert_eq!(records[0]["Attachments"][0]["Name"], "guide.pdf");
+        assert!(records[0]["Attachments"][0]["Content"].is_null());
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_server_returns_200() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let subscriber_email = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+        let subject = subject();
+        let content = content();
+
+        // matching any request here, as this test is about the behavior of our EmailClient, given a 200 response
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let result = email_client
+            .send_email(&subscriber_email, &subject, &content, &content)
+            .await;
+
+        // assert
+        assert_ok!(result);
+    }
+
+    #[tokio::test]
+    async fn postmark_sends_report_the_id_and_submission_time_of_the_message() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "To": "receiver@example.com",
+                "SubmittedAt": "2026-10-16T12:01:05.1794748-05:00",
+                "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+                "ErrorCode": 0,
+                "Message": "OK"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let sent = email_client
+            .send_email_with(
+                email_client.sender_of("any"),
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                &EmailOptions::default(),
+            )
+            .await
+            .unwrap();
+
+        // assert
+        assert_eq!(
+            sent.message_id.as_deref(),
+            Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+        );
+        assert_eq!(
+            sent.submitted_at.unwrap().to_rfc3339(),
+            "2026-10-16T17:01:05.179474800+00:00"
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_server_returns_500() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let subscriber_email = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+        let subject = subject();
+        let content = content();
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let result = email_client
+            .send_email(&subscriber_email, &subject, &content, &content)
+            .await;
+
+        // assert
+        assert_err!(result);
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_server_takes_too_long() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let subscriber_email = SubscriberEmail::parse(SafeEmail().fake()).unwrap();
+        let subject = subject();
+        let content = content();
+
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(11));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let result = email_client
+            .send_email(&subscriber_email, &subject, &content, &content)
+            .await;
+
+        // assert
+        assert_err!(result);
+    }
+
+    #[tokio::test]
+    async fn inactive_recipients_are_rejected_permanently() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "ErrorCode": 406,
+                "Message": "You tried to send to recipient(s) that have been marked as inactive."
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let result = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // assert
+        let error = result.unwrap_err();
+        assert!(matches!(
+            error,
+            SendEmailError::Permanent {
+                status: 422,
+                error_code: Some(406),
+                ..
+            }
+        ));
+    }
+
+    #[tokio::test]
+    async fn other_rejections_are_not_blamed_on_the_recipient() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(401).set_body_json(serde_json::json!({
+                "ErrorCode": 10,
+                "Message": "No Account or Server API tokens were supplied in the HTTP headers."
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let result = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // assert
+        assert!(matches!(
+            result,
+            Err(SendEmailError::Refused {
+                status: 401,
+                error_code: Some(10),
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn invalid_senders_are_not_blamed_on_the_recipient() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "ErrorCode": 400,
+                "Message": "The 'From' address you supplied is not a Sender Signature on your account."
+            })))
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let result = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // assert
+        assert!(matches!(result, Err(SendEmailError::Refused { .. })));
+    }
+
+    #[tokio::test]
+    async fn malformed_recipients_are_rejected_permanently() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "ErrorCode": 300,
+                "Message": "Invalid 'To' address: 'ursula'."
+            })))
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let result = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // assert
+        assert!(matches!(
+            result,
+            Err(SendEmailError::Permanent {
+                error_code: Some(300),
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn unexpected_statuses_are_not_blamed_on_the_recipient() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(302))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let result = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // assert
+        assert!(matches!(
+            result,
+            Err(SendEmailError::UnexpectedResponse { status: 302 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn rate_limits_tell_how_long_to_wait() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "7"))
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let result = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // assert
+        assert!(matches!(
+            result,
+            Err(SendEmailError::RateLimited {
+                retry_after: Some(retry_after)
+            }) if retry_after == std::time::Duration::from_secs(7)
+        ));
+    }
+
+    fn retry_policy(max_attempts: u32) -> SendRetryPolicy {
+        SendRetryPolicy {
+            max_attempts,
+            base_delay: std::time::Duration::from_millis(1),
+            max_delay: std::time::Duration::from_millis(5),
+        }
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_retried() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri()).with_resilience(retry_policy(3), None);
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(503))
+            .up_to_n_times(2)
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let result = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // assert
+        assert_ok!(result);
+        let mut body = String::new();
+        email_client.write_prometheus(&mut body);
+        assert!(body.contains("email_client_retries_total 2\n"));
+    }
+
+    #[tokio::test]
+    async fn rejected_emails_are_not_retried() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri()).with_resilience(retry_policy(3), None);
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let result = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // assert
+        assert_err!(result);
+    }
+
+    #[tokio::test]
+    async fn the_provider_is_not_called_once_the_circuit_breaker_tripped() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri()).with_resilience(
+            retry_policy(5),
+            Some(CircuitBreakerPolicy {
+                failure_threshold: 2,
+                open_for: std::time::Duration::from_secs(60),
+            }),
+        );
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let first = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        let second = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // assert
+        assert!(matches!(first, Err(SendEmailError::Transient(_))));
+        assert!(matches!(second, Err(SendEmailError::CircuitOpen { .. })));
+        let mut body = String::new();
+        email_client.write_prometheus(&mut body);
+        assert!(body.contains("email_client_circuit_breaker_trips_total 1\n"));
+        assert!(body.contains("email_client_circuit_breaker_open 1"));
+    }
+
+    #[tokio::test]
+    async fn refused_accounts_trip_the_circuit_breaker_without_retries() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri()).with_resilience(
+            retry_policy(5),
+            Some(CircuitBreakerPolicy {
+                failure_threshold: 2,
+                open_for: std::time::Duration::from_secs(60),
+            }),
+        );
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "ErrorCode": 412,
+                "Message": "Your account is pending approval."
+            })))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let first = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        let second = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        let third = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // assert
+        assert!(matches!(first, Err(SendEmailError::Refused { .. })));
+        assert!(matches!(second, Err(SendEmailError::Refused { .. })));
+        assert!(matches!(third, Err(SendEmailError::CircuitOpen { .. })));
+    }
+
+    #[tokio::test]
+    async fn ses_send_email_sends_the_expected_request() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = ses_email_client(mock_server.uri());
+
+        Mock::given(header_exists("Authorization"))
+            .and(header_exists("X-Amz-Date"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/v2/email/outbound-emails"))
+            .and(method("POST"))
+            .and(SesSendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // assert handled by Mock...expect(1)
+    }
+
+    #[tokio::test]
+    async fn ses_send_email_with_options_adds_them_to_the_request() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = ses_email_client(mock_server.uri());
+
+        Mock::given(path("/v2/email/outbound-emails"))
+            .and(body_partial_json(serde_json::json!({
+                "ReplyToAddresses": ["editor@example.com"],
+                "Destination": {
+                    "CcAddresses": ["first@example.com", "second@example.com"],
+                    "BccAddresses": ["archive@example.com"],
+                },
+                "Content": { "Simple": {
+                    "Headers": [{ "Name": "List-Id", "Value": "Digest <digest.example.com>" }],
+                    "Attachments": [{
+                        "FileName": "guide.pdf",
+                        "RawContent": "JVBERg==",
+                        "ContentType": "application/pdf",
+                    }],
+                } },
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let outcome = email_client
+            .send_email_with(
+                &email_client.sender,
+                &email(),
+                &subject(),
+                &content(),
+                &content(),
+                &options(),
+            )
+            .await;
+
+        // assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn ses_send_email_succeeds_if_server_returns_200() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = ses_email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let result = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // assert
+        assert_ok!(result);
+    }
+
+    #[tokio::test]
+    async fn ses_send_email_fails_if_server_returns_500() {
+        // arrange
+        let mock_server = MockServer::start().await;
+        let email_client = ses_email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // act
+        let result = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        // assert
+        assert_err!(result);
+    }
+}
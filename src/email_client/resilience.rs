@@ -0,0 +1,197 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+/// How a failed request to the provider is retried before the send is reported as failed
+#[derive(Clone, Debug)]
+pub struct SendRetryPolicy {
+    /// Attempts in total, the first one included
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl SendRetryPolicy {
+    /// A single attempt
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        }
+    }
+
+    /// Full jitter: a random delay up to the exponential backoff of the attempt, so that the
+    /// clients that failed together do not retry together
+    pub(super) fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self
+            .base_delay
+            .checked_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay));
+        backoff.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+}
+
+/// When the circuit breaker trips
+#[derive(Clone, Debug)]
+pub struct CircuitBreakerPolicy {
+    /// Transient failures in a row after which the provider is considered down
+    pub failure_threshold: u32,
+    /// How long sends fail straight away once the breaker tripped
+    pub open_for: Duration,
+}
+
+/// Stops calling a provider that keeps failing, so that callers fail fast instead of waiting for
+/// timeouts. Once `open_for` has passed, requests go through again: the first success closes
+/// the breaker, the first failure trips it again.
+#[derive(Debug, Default)]
+pub(super) struct CircuitBreaker {
+    policy: Option<CircuitBreakerPolicy>,
+    state: Mutex<BreakerState>,
+}
+
+#[derive(Debug, Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub(super) fn new(policy: Option<CircuitBreakerPolicy>) -> Self {
+        Self {
+            policy,
+            state: Mutex::default(),
+        }
+    }
+
+    /// `Err` with the time left if the breaker is open
+    pub(super) fn check(&self) -> Result<(), Duration> {
+        let state = self.lock();
+        match state.open_until {
+            Some(open_until) => match open_until.checked_duration_since(Instant::now()) {
+                Some(retry_after) if !retry_after.is_zero() => Err(retry_after),
+                _ => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+
+    pub(super) fn is_open(&self) -> bool {
+        self.check().is_err()
+    }
+
+    pub(super) fn record_success(&self) {
+        let mut state = self.lock();
+        state.consecutive_failures = 0;
+        state.open_until = None;
+    }
+
+    /// Returns whether the failure tripped the breaker
+    pub(super) fn record_failure(&self) -> bool {
+        let Some(policy) = &self.policy else {
+            return false;
+        };
+        let mut state = self.lock();
+        state.consecutive_failures += 1;
+        if state.consecutive_failures < policy.failure_threshold.max(1) {
+            return false;
+        }
+        state.open_until = Some(Instant::now() + policy.open_for);
+        true
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BreakerState> {
+        self.state
+            .lock()
+            .expect("The circuit breaker lock is poisoned")
+    }
+}
+
+/// Counters of the retry layer and of the circuit breaker of a client
+#[derive(Debug, Default)]
+pub(super) struct ResilienceMetrics {
+    pub(super) retries: AtomicU64,
+    pub(super) trips: AtomicU64,
+    /// Sends failed without calling the provider, because the breaker was open
+    pub(super) rejections: AtomicU64,
+}
+
+impl ResilienceMetrics {
+    pub(super) fn write_prometheus(&self, body: &mut String, circuit_open: bool) {
+        writeln!(
+            body,
+            "# HELP email_client_retries_total Requests to the email provider retried after a failure.\n\
+            # TYPE email_client_retries_total counter\n\
+            email_client_retries_total {}\n\
+            # HELP email_client_circuit_breaker_trips_total Times the email provider was considered down.\n\
+            # TYPE email_client_circuit_breaker_trips_total counter\n\
+            email_client_circuit_breaker_trips_total {}\n\
+            # HELP email_client_circuit_breaker_rejections_total Emails failed straight away while the circuit breaker was open.\n\
+            # TYPE email_client_circuit_breaker_rejections_total counter\n\
+            email_client_circuit_breaker_rejections_total {}\n\
+            # HELP email_client_circuit_breaker_open Whether emails currently fail without calling the provider.\n\
+            # TYPE email_client_circuit_breaker_open gauge\n\
+            email_client_circuit_breaker_open {}",
+            self.retries.load(Ordering::Relaxed),
+            self.trips.load(Ordering::Relaxed),
+            self.rejections.load(Ordering::Relaxed),
+            u8::from(circuit_open)
+        )
+        .unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CircuitBreaker, CircuitBreakerPolicy, SendRetryPolicy};
+    use std::time::Duration;
+
+    #[test]
+    fn retry_delays_are_capped() {
+        let policy = SendRetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(500),
+        };
+        for attempt in 1..10 {
+            assert!(policy.delay(attempt) <= Duration::from_millis(500));
+        }
+        assert!(policy.delay(1) <= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn the_breaker_trips_after_the_threshold_and_closes_on_success() {
+        let breaker = CircuitBreaker::new(Some(CircuitBreakerPolicy {
+            failure_threshold: 2,
+            open_for: Duration::from_secs(60),
+        }));
+        assert!(!breaker.record_failure());
+        assert!(breaker.check().is_ok());
+        assert!(breaker.record_failure());
+        assert!(breaker.is_open());
+        breaker.record_success();
+        assert!(!breaker.is_open());
+    }
+
+    #[test]
+    fn requests_go_through_again_once_the_breaker_was_open_long_enough() {
+        let breaker = CircuitBreaker::new(Some(CircuitBreakerPolicy {
+            failure_threshold: 1,
+            open_for: Duration::ZERO,
+        }));
+        assert!(breaker.record_failure());
+        assert!(breaker.check().is_ok());
+    }
+
+    #[test]
+    fn without_a_policy_the_breaker_never_trips() {
+        let breaker = CircuitBreaker::new(None);
+        for _ in 0..100 {
+            assert!(!breaker.record_failure());
+        }
+        assert!(!breaker.is_open());
+    }
+}
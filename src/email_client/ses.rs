@@ -0,0 +1,268 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+
+use super::Header;
+
+/// The service name SES expects in the SigV4 credential scope
+const SERVICE: &str = "ses";
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+/// Path of the SES v2 `SendEmail` action
+pub const SEND_EMAIL_PATH: &str = "/v2/email/outbound-emails";
+
+/// Credentials and region used to sign requests to the SES API
+#[derive(Clone)]
+pub struct SesCredentials {
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: Secret<String>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct SendEmailRequest<'a> {
+    pub from_email_address: &'a str,
+    pub destination: Destination<'a>,
+    pub content: Content<'a>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub reply_to_addresses: Vec<&'a str>,
+}
+
+impl<'a> SendEmailRequest<'a> {
+    pub fn new(
+        from: &'a str,
+        to: &'a str,
+        subject: &'a str,
+        html_body: &'a str,
+        text_body: &'a str,
+    ) -> Self {
+        Self {
+            from_email_address: from,
+            destination: Destination {
+                to_addresses: vec![to],
+                cc_addresses: Vec::new(),
+                bcc_addresses: Vec::new(),
+            },
+            content: Content {
+                simple: Message {
+                    subject: Data::new(subject),
+                    body: Body {
+                        html: Data::new(html_body),
+                        text: Data::new(text_body),
+                    },
+                    headers: Vec::new(),
+                    attachments: Vec::new(),
+                },
+            },
+            reply_to_addresses: Vec::new(),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Destination<'a> {
+    pub to_addresses: Vec<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub cc_addresses: Vec<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub bcc_addresses: Vec<&'a str>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Content<'a> {
+    pub simple: Message<'a>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Message<'a> {
+    pub subject: Data<'a>,
+    pub body: Body<'a>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<Header<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment<'a>>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Attachment<'a> {
+    pub file_name: &'a str,
+    /// Base64 encoded
+    pub raw_content: String,
+    pub content_type: &'a str,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Body<'a> {
+    pub html: Data<'a>,
+    pub text: Data<'a>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct Data<'a> {
+    pub data: &'a str,
+    pub charset: &'a str,
+}
+
+impl<'a> Data<'a> {
+    fn new(data: &'a str) -> Self {
+        Self {
+            data,
+            charset: "UTF-8",
+        }
+    }
+}
+
+/// The headers that must be attached to a request for SES to accept its SigV4 signature
+pub struct SignedHeaders {
+    pub amz_date: String,
+    pub authorization: String,
+}
+
+/// Signs a JSON POST request to `url` following AWS Signature Version 4.
+/// See https://docs.aws.amazon.com/general/latest/gr/sigv4_signing.html for the details.
+pub fn sign_request(
+    credentials: &SesCredentials,
+    url: &Url,
+    payload: &[u8],
+    now: DateTime<Utc>,
+) -> SignedHeaders {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date_stamp = now.format("%Y%m%d").to_string();
+
+    // the host header is set by reqwest, but we still need to sign the exact value it will send
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let signed_headers = "content-type;host;x-amz-date";
+    let canonical_request = format!(
+        "POST\n{}\n{}\ncontent-type:application/json\nhost:{}\nx-amz-date:{}\n\n{}\n{}",
+        url.path(),
+        url.query().unwrap_or_default(),
+        host,
+        amz_date,
+        signed_headers,
+        hex::encode(Sha256::digest(payload)),
+    );
+
+    let credential_scope = format!(
+        "{}/{}/{}/aws4_request",
+        date_stamp, credentials.region, SERVICE
+    );
+    let string_to_sign = format!(
+        "{}\n{}\n{}\n{}",
+        ALGORITHM,
+        amz_date,
+        credential_scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes())),
+    );
+
+    let signing_key = signing_key(
+        &credentials.secret_access_key,
+        &date_stamp,
+        &credentials.region,
+        SERVICE,
+    );
+    let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+
+    let authorization = format!(
+        "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+        ALGORITHM, credentials.access_key_id, credential_scope, signed_headers, signature
+    );
+    SignedHeaders {
+        amz_date,
+        authorization,
+    }
+}
+
+/// Derives the SigV4 signing key, which is scoped to a single day, region and service
+pub(crate) fn signing_key(
+    secret_access_key: &Secret<String>,
+    date_stamp: &str,
+    region: &str,
+    service: &str,
+) -> Vec<u8> {
+    let key = format!("AWS4{}", secret_access_key.expose_secret());
+    let k_date = hmac_sha256(key.as_bytes(), date_stamp.as_bytes());
+    let k_region = hmac_sha256(&k_date, region.as_bytes());
+    let k_service = hmac_sha256(&k_region, service.as_bytes());
+    hmac_sha256(&k_service, b"aws4_request")
+}
+
+pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    // HMAC accepts keys of any length, so this cannot fail
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use reqwest::Url;
+    use secrecy::Secret;
+
+    use super::{sign_request, signing_key, SesCredentials};
+
+    fn credentials() -> SesCredentials {
+        SesCredentials {
+            region: "us-east-1".into(),
+            access_key_id: "AKIDEXAMPLE".into(),
+            secret_access_key: Secret::new("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".into()),
+        }
+    }
+
+    #[test]
+    fn signing_key_matches_aws_reference_value() {
+        // reference values taken from the AWS SigV4 documentation
+        let key = signing_key(
+            &Secret::new("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".into()),
+            "20120215",
+            "us-east-1",
+            "iam",
+        );
+        assert_eq!(
+            hex::encode(key),
+            "f4780e2d9f65fa895f9c67b32ce1baf0b0d8a43505a000a1a9e090d414db404d"
+        );
+    }
+
+    #[test]
+    fn authorization_header_is_scoped_to_date_region_and_service() {
+        let url =
+            Url::parse("https://email.us-east-1.amazonaws.com/v2/email/outbound-emails").unwrap();
+        let now = chrono::Utc
+            .with_ymd_and_hms(2023, 5, 20, 12, 30, 0)
+            .unwrap();
+
+        let headers = sign_request(&credentials(), &url, b"{}", now);
+
+        assert_eq!(headers.amz_date, "20230520T123000Z");
+        assert!(headers.authorization.starts_with(
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20230520/us-east-1/ses/aws4_request, \
+            SignedHeaders=content-type;host;x-amz-date, Signature="
+        ));
+    }
+
+    #[test]
+    fn signature_depends_on_the_payload() {
+        let url =
+            Url::parse("https://email.us-east-1.amazonaws.com/v2/email/outbound-emails").unwrap();
+        let now = chrono::Utc
+            .with_ymd_and_hms(2023, 5, 20, 12, 30, 0)
+            .unwrap();
+
+        let first = sign_request(&credentials(), &url, b"first", now);
+        let second = sign_request(&credentials(), &url, b"second", now);
+
+        assert_ne!(first.authorization, second.authorization);
+    }
+}
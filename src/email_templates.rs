@@ -0,0 +1,184 @@
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::newsletter_content::expand_placeholders;
+use crate::routing_helpers::html_escape;
+use crate::templates::SelectOption;
+
+/// Replaced with the content of the issue in the layouts of a template
+pub const CONTENT_PLACEHOLDER: &str = "{{ content }}";
+
+/// A reusable layout, e.g. a header, a footer and styles, that the content of issues is put into
+/// when they are published. Besides `{{ content }}`, layouts may use `{{ title }}` and the
+/// placeholders filled in for each subscriber, such as `{{ unsubscribe_url }}`.
+pub struct EmailTemplate {
+    pub email_template_id: Uuid,
+    pub name: String,
+    pub html_layout: String,
+    pub text_layout: String,
+}
+
+impl EmailTemplate {
+    /// The plain text and HTML content of an issue put into the layouts of the template
+    pub fn apply(&self, title: &str, text_content: &str, html_content: &str) -> (String, String) {
+        let text_content =
+            expand_placeholders(&self.text_layout, |placeholder| match placeholder {
+                "content" => Some(text_content.to_owned()),
+                "title" => Some(title.to_owned()),
+                _ => None,
+            });
+        let html_content =
+            expand_placeholders(&self.html_layout, |placeholder| match placeholder {
+                "content" => Some(html_content.to_owned()),
+                "title" => Some(html_escape(title)),
+                _ => None,
+            });
+        (text_content, html_content)
+    }
+}
+
+/// Puts the content of an issue into the layouts of the template picked for it, if any
+pub fn apply_email_template(
+    template: Option<&EmailTemplate>,
+    title: &str,
+    text_content: String,
+    html_content: String,
+) -> (String, String) {
+    match template {
+        Some(template) => template.apply(title, &text_content, &html_content),
+        None => (text_content, html_content),
+    }
+}
+
+/// Layouts must say where the content of issues goes
+pub fn validate_layout(layout: &str, name: &str) -> Result<(), String> {
+    let has_content_placeholder = layout
+        .match_indices("{{")
+        .filter_map(|(start, _)| {
+            let end = layout[start..].find("}}")?;
+            Some(layout[start + 2..start + end].trim())
+        })
+        .any(|placeholder| placeholder == "content");
+    if !has_content_placeholder {
+        return Err(format!(
+            "The {} layout must contain {} where the content of issues goes.",
+            name, CONTENT_PLACEHOLDER
+        ));
+    }
+    Ok(())
+}
+
+/// Parses the id of the template picked by a select field, whose empty option means none
+pub fn parse_email_template_id(value: &str) -> Result<Option<Uuid>, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Ok(None);
+    }
+    value
+        .parse()
+        .map(Some)
+        .map_err(|_| format!("{} is not the id of an email template.", value))
+}
+
+/// Looks up the template picked by the select field of a form. The inner error tells the admin
+/// why the pick cannot be used.
+pub async fn pick_email_template(
+    pool: &PgPool,
+    value: &str,
+) -> Result<Result<Option<EmailTemplate>, String>, sqlx::Error> {
+    let email_template_id = match parse_email_template_id(value) {
+        Ok(Some(email_template_id)) => email_template_id,
+        Ok(None) => return Ok(Ok(None)),
+        Err(e) => return Ok(Err(e)),
+    };
+    let template = find_email_template(pool, email_template_id).await?;
+    Ok(template
+        .map(Some)
+        .ok_or_else(|| "The email template no longer exists.".to_owned()))
+}
+
+#[tracing::instrument(name = "Find an email template", skip(executor))]
+pub async fn find_email_template(
+    executor: impl PgExecutor<'_>,
+    email_template_id: Uuid,
+) -> Result<Option<EmailTemplate>, sqlx::Error> {
+    sqlx::query_as!(
+        EmailTemplate,
+        r#"
+        SELECT email_template_id, name, html_layout, text_layout
+        FROM email_templates
+        WHERE email_template_id = $1
+        "#,
+        email_template_id
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Every email template, by name
+#[tracing::instrument(name = "Get email templates", skip_all)]
+pub async fn get_email_templates(pool: &PgPool) -> Result<Vec<EmailTemplate>, sqlx::Error> {
+    sqlx::query_as!(
+        EmailTemplate,
+        "SELECT email_template_id, name, html_layout, text_layout FROM email_templates ORDER BY name"
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// The options of a select field picking an email template, starting with an empty one for
+/// issues sent as written
+pub fn email_template_options(
+    templates: &[EmailTemplate],
+    selected: Option<Uuid>,
+) -> Vec<SelectOption> {
+    let none = SelectOption {
+        value: String::new(),
+        label: "None: send the content as written".into(),
+        selected: false,
+    };
+    std::iter::once(none)
+        .chain(templates.iter().map(|template| SelectOption {
+            value: template.email_template_id.to_string(),
+            label: template.name.clone(),
+            selected: selected == Some(template.email_template_id),
+        }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_layout, EmailTemplate};
+    use claims::{assert_err, assert_ok};
+    use uuid::Uuid;
+
+    fn template() -> EmailTemplate {
+        EmailTemplate {
+            email_template_id: Uuid::new_v4(),
+            name: "Branded".into(),
+            html_layout:
+                "<h1>{{ title }}</h1>{{ content }}<a href=\"{{ unsubscribe_url }}\">Bye</a>".into(),
+            text_layout: "{{ title }}\n\n{{content}}\n\nUnsubscribe: {{ unsubscribe_url }}".into(),
+        }
+    }
+
+    #[test]
+    fn the_content_is_put_into_the_layouts() {
+        let (text, html) = template().apply("Issue #1 & more", "Hello {{ name }}", "<p>Hello</p>");
+        assert_eq!(
+            text,
+            "Issue #1 & more\n\nHello {{ name }}\n\nUnsubscribe: {{ unsubscribe_url }}"
+        );
+        assert_eq!(
+            html,
+            "<h1>Issue #1 &amp; more</h1><p>Hello</p><a href=\"{{ unsubscribe_url }}\">Bye</a>"
+        );
+    }
+
+    #[test]
+    fn layouts_must_have_a_content_placeholder() {
+        assert_ok!(validate_layout("<div>{{content}}</div>", "HTML"));
+        assert_err!(validate_layout("<div>{{ title }}</div>", "HTML"));
+        assert_err!(validate_layout("", "plain text"));
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::HashSet;
+
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+use trust_dns_resolver::TokioAsyncResolver;
+
+use crate::domain::{EmailAddressMode, SubscriberEmail};
+
+/// Catches the addresses of new subscribers that can never receive our emails, before a
+/// confirmation email is wasted on them: internationalized addresses the email provider cannot
+/// deliver to, those of disposable email providers, and, if enabled, those whose domain has no
+/// mail server.
+pub struct EmailValidator {
+    address_mode: EmailAddressMode,
+    /// Whether the email provider can deliver to UTF-8 local parts
+    smtputf8: bool,
+    /// Lowercase domains; their subdomains are blocked as well
+    blocked_domains: HashSet<String>,
+    /// Looks up the mail servers of the domains; `None` if MX validation is disabled
+    resolver: Option<TokioAsyncResolver>,
+}
+
+impl EmailValidator {
+    pub fn new(
+        address_mode: EmailAddressMode,
+        smtputf8: bool,
+        blocked_domains: impl IntoIterator<Item = String>,
+        resolver: Option<TokioAsyncResolver>,
+    ) -> Self {
+        Self {
+            address_mode,
+            smtputf8,
+            blocked_domains: blocked_domains
+                .into_iter()
+                .map(|domain| domain.trim().trim_matches('.').to_lowercase())
+                .collect(),
+            resolver,
+        }
+    }
+
+    /// A resolver using the DNS configuration of the host
+    pub fn system_resolver() -> Result<TokioAsyncResolver, ResolveError> {
+        TokioAsyncResolver::tokio_from_system_conf()
+    }
+
+    /// Returns an explanation if the address cannot receive emails
+    pub async fn check(&self, email: &SubscriberEmail) -> Result<(), String> {
+        if !email.is_allowed_in(self.address_mode, self.smtputf8) {
+            return Err(format!(
+                "{} is not supported: please use an address made of ASCII characters only.",
+                email.as_ref()
+            ));
+        }
+        let domain = domain_of(email);
+        if self.is_blocked(&domain) {
+            return Err(format!(
+                "Addresses at {} are not accepted: please use a permanent email address.",
+                domain
+            ));
+        }
+        if let Some(resolver) = &self.resolver {
+            if !accepts_mail(resolver, &domain).await {
+                return Err(format!("{} cannot receive emails.", email.as_ref()));
+            }
+        }
+        Ok(())
+    }
+
+    fn is_blocked(&self, domain: &str) -> bool {
+        // foo.mailinator.com is as disposable as mailinator.com
+        let mut suffix = domain;
+        loop {
+            if self.blocked_domains.contains(suffix) {
+                return true;
+            }
+            match suffix.split_once('.') {
+                Some((_, parent)) => suffix = parent,
+                None => return false,
+            }
+        }
+    }
+}
+
+fn domain_of(email: &SubscriberEmail) -> String {
+    let address = email.as_ref();
+    let domain = address
+        .rsplit_once('@')
+        .map_or(address, |(_, domain)| domain);
+    domain.to_lowercase()
+}
+
+/// Whether the domain has a mail server, as defined by RFC 5321: its MX records or, if it has
+/// none, its own address. A "null MX" (RFC 7505) explicitly refuses mail. Lookups that fail for
+/// another reason than the records not existing, e.g. timeouts, let the address through.
+#[tracing::instrument(name = "Look up the mail servers of a domain", skip(resolver))]
+async fn accepts_mail(resolver: &TokioAsyncResolver, domain: &str) -> bool {
+    // the trailing dot keeps the search domains of the host out of the lookup
+    let fqdn = format!("{}.", domain);
+    match resolver.mx_lookup(fqdn.as_str()).await {
+        Ok(mx) => !mx.iter().all(|record| record.exchange().is_root()),
+        Err(e) if is_missing(&e) => match resolver.lookup_ip(fqdn.as_str()).await {
+            Ok(_) => true,
+            Err(e) if is_missing(&e) => false,
+            Err(e) => lookup_failed(e),
+        },
+        Err(e) => lookup_failed(e),
+    }
+}
+
+fn is_missing(error: &ResolveError) -> bool {
+    matches!(error.kind(), ResolveErrorKind::NoRecordsFound { .. })
+}
+
+fn lookup_failed(error: ResolveError) -> bool {
+    tracing::warn!(
+        error.cause_chain = ?error,
+        error.message = %error,
+        "Failed to look up the mail servers of a domain. Accepting the address."
+    );
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::EmailValidator;
+    use crate::domain::{EmailAddressMode, SubscriberEmail};
+
+    fn validator() -> EmailValidator {
+        EmailValidator::new(
+            EmailAddressMode::Strict,
+            false,
+            ["Mailinator.com".to_owned(), " yopmail.com. ".to_owned()],
+            None,
+        )
+    }
+
+    fn email(address: &str) -> SubscriberEmail {
+        SubscriberEmail::parse(address.to_owned()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn addresses_at_blocked_domains_are_rejected() {
+        let validator = validator();
+        assert_err!(validator.check(&email("ursula@mailinator.com")).await);
+        assert_err!(validator.check(&email("ursula@YOPMAIL.com")).await);
+    }
+
+    #[tokio::test]
+    async fn subdomains_of_blocked_domains_are_rejected() {
+        assert_err!(validator().check(&email("ursula@eu.mailinator.com")).await);
+    }
+
+    #[tokio::test]
+    async fn internationalized_addresses_are_rejected_in_strict_mode() {
+        let validator = validator();
+        assert_err!(validator.check(&email("ursula@münchen.de")).await);
+        assert_err!(validator.check(&email("josé@gmail.com")).await);
+    }
+
+    #[tokio::test]
+    async fn lenient_mode_only_accepts_utf8_local_parts_with_smtputf8() {
+        let validator = EmailValidator::new(EmailAddressMode::Lenient, false, [], None);
+        assert_ok!(validator.check(&email("ursula@münchen.de")).await);
+        assert_err!(validator.check(&email("josé@gmail.com")).await);
+
+        let validator = EmailValidator::new(EmailAddressMode::Lenient, true, [], None);
+        assert_ok!(validator.check(&email("josé@gmail.com")).await);
+    }
+
+    #[tokio::test]
+    async fn other_domains_are_accepted() {
+        let validator = validator();
+        assert_ok!(validator.check(&email("ursula@gmail.com")).await);
+        assert_ok!(validator.check(&email("ursula@notmailinator.com")).await);
+    }
+}
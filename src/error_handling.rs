@@ -1,3 +1,10 @@
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{Accept, ContentType, Header};
+use actix_web::{HttpMessage, HttpResponse, ResponseError};
+use actix_web_lab::middleware::Next;
+use tracing_actix_web::RequestId;
+
 /// Iterates over a chain of errors via the `source` method and prints the error with its cause
 pub fn error_chain_fmt(
     error: &impl std::error::Error,
@@ -11,3 +18,74 @@ pub fn error_chain_fmt(
     }
     Ok(())
 }
+
+/// The message of unexpected errors, whose cause is only logged
+pub const UNEXPECTED_ERROR_MESSAGE: &str = "An unexpected error occurred.";
+
+/// What went wrong, attached to error responses for clients that ask for JSON
+struct ClientError {
+    message: String,
+}
+
+/// Errors returned to both browsers and API clients. Browsers get `browser_response`, while
+/// clients sending `Accept: application/json` get `{"error": ..., "request_id": ...}` instead,
+/// as rewritten by `negotiate_error_responses`.
+pub trait NegotiatedError: ResponseError {
+    /// What went wrong, in words fit for clients: the cause of unexpected errors is only logged
+    fn client_message(&self) -> String;
+
+    /// The response for browsers, a plain text explanation unless overridden
+    fn browser_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status_code())
+            .content_type(ContentType::plaintext())
+            .body(self.client_message())
+    }
+
+    /// Marks a response for browsers as the error response of `self`, so that it can be
+    /// negotiated; `ResponseError::error_response` should return
+    /// `self.negotiated(self.browser_response())`
+    fn negotiated(&self, mut browser_response: HttpResponse) -> HttpResponse {
+        browser_response.extensions_mut().insert(ClientError {
+            message: self.client_message(),
+        });
+        browser_response
+    }
+}
+
+/// Whether the client prefers JSON over anything else, e.g. HTML pages
+fn prefers_json(req: &ServiceRequest) -> bool {
+    Accept::parse(req)
+        .ok()
+        .and_then(|accept| accept.ranked().into_iter().next())
+        .map_or(false, |mime| mime.essence_str() == "application/json")
+}
+
+/// Replaces the error responses of `NegotiatedError`s with JSON bodies for the clients asking for
+/// them, with the id of the request to match the logs. It must be wrapped by `TracingLogger`,
+/// which assigns the id.
+pub async fn negotiate_error_responses(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let prefers_json = prefers_json(&req);
+    let request_id = req.extensions().get::<RequestId>().copied();
+    let response = next.call(req).await?.map_into_boxed_body();
+    if !prefers_json {
+        return Ok(response);
+    }
+    let Some(message) = response
+        .response()
+        .extensions()
+        .get::<ClientError>()
+        .map(|error| error.message.clone())
+    else {
+        return Ok(response);
+    };
+    let status = response.status();
+    Ok(
+        response.into_response(HttpResponse::build(status).json(serde_json::json!({
+            "error": message,
+            "request_id": request_id.map(|id| id.to_string()),
+        }))),
+    )
+}
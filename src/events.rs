@@ -0,0 +1,170 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+/// The key of the advisory lock taken by transactions recording events
+const EVENTS_LOCK_KEY: i64 = 0x6576_656e_7473;
+
+/// The changes clients can follow through the event feed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventType {
+    /// A new subscriber, or a former one, signed up and was sent a confirmation email
+    SubscriberSignedUp,
+    SubscriberConfirmed,
+    SubscriberUnsubscribed,
+    /// Deleted from the admin panel; the subscriber can still be restored
+    SubscriberDeleted,
+    SubscriberRestored,
+    /// The subscriber and the earlier events about them are gone for good
+    SubscriberErased,
+    IssuePublished,
+}
+
+impl EventType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventType::SubscriberSignedUp => "subscriber.signed_up",
+            EventType::SubscriberConfirmed => "subscriber.confirmed",
+            EventType::SubscriberUnsubscribed => "subscriber.unsubscribed",
+            EventType::SubscriberDeleted => "subscriber.deleted",
+            EventType::SubscriberRestored => "subscriber.restored",
+            EventType::SubscriberErased => "subscriber.erased",
+            EventType::IssuePublished => "issue.published",
+        }
+    }
+}
+
+/// An entry of the event feed
+#[derive(Debug, serde::Serialize)]
+pub struct Event {
+    /// The cursor to poll from to get the events after this one
+    pub id: i64,
+    #[serde(rename = "type")]
+    pub event_type: String,
+    pub occurred_at: DateTime<Utc>,
+    /// The subscriber or issue as it was right after the change
+    pub data: serde_json::Value,
+}
+
+/// Makes the transaction the only one recording events until it ends. Ids are taken from a
+/// sequence, so without it a transaction could commit an event after a later one has been
+/// polled, and clients would skip it.
+async fn lock_events(transaction: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query!("SELECT pg_advisory_xact_lock($1)", EVENTS_LOCK_KEY)
+        .execute(transaction)
+        .await?;
+    Ok(())
+}
+
+/// Records a change to a subscriber in the event feed, with a copy of the subscriber. Like audit
+/// log entries, it should be called in the same transaction as the change, as late as possible
+/// since no other transaction can record events until it ends.
+#[tracing::instrument(name = "Record subscriber event", skip(transaction))]
+pub async fn record_subscriber_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    event_type: EventType,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    lock_events(transaction).await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO events (event_type, subject_id, data)
+        SELECT $1, id, jsonb_build_object(
+            'subscriber_id', id,
+            'email', email,
+            'name', name,
+            'status', status,
+            'newsletter_id', newsletter_id
+        )
+        FROM subscriptions
+        WHERE id = $2
+        "#,
+        event_type.as_str(),
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Records the erasure of a subscriber in the event feed, removing the earlier events about them:
+/// they hold the personal data that was erased
+#[tracing::instrument(name = "Record subscriber erasure", skip(transaction))]
+pub async fn record_subscriber_erasure(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    lock_events(transaction).await?;
+    sqlx::query!("DELETE FROM events WHERE subject_id = $1", subscriber_id)
+        .execute(&mut *transaction)
+        .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO events (event_type, subject_id, data)
+        VALUES ($1, $2, jsonb_build_object('subscriber_id', $2::uuid))
+        "#,
+        EventType::SubscriberErased.as_str(),
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Records a change to a newsletter issue in the event feed, with a summary of the issue
+#[tracing::instrument(name = "Record issue event", skip(transaction))]
+pub async fn record_issue_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    event_type: EventType,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    lock_events(transaction).await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO events (event_type, subject_id, data)
+        SELECT $1, newsletter_issue_id, jsonb_build_object(
+            'newsletter_issue_id', newsletter_issue_id,
+            'title', title,
+            'newsletter_id', newsletter_id,
+            'published_at', published_at
+        )
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $2
+        "#,
+        event_type.as_str(),
+        newsletter_issue_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Returns up to `limit` events recorded after the one with id `since`, the oldest first, and
+/// whether more are waiting
+#[tracing::instrument(name = "Get events", skip(pool))]
+pub async fn get_events(
+    pool: &PgPool,
+    since: i64,
+    limit: i64,
+) -> Result<(Vec<Event>, bool), anyhow::Error> {
+    let mut events = sqlx::query_as!(
+        Event,
+        r#"
+        SELECT event_id AS id, event_type, occurred_at, data
+        FROM events
+        WHERE event_id > $1
+        ORDER BY event_id
+        LIMIT $2
+        "#,
+        since,
+        limit + 1
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve events.")?;
+    // we fetch one extra row to know whether more events are waiting
+    let has_more = events.len() as i64 > limit;
+    events.truncate(limit as usize);
+    Ok((events, has_more))
+}
@@ -0,0 +1,113 @@
+use actix_web::body::{self, BoxBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{
+    CacheControl, CacheDirective, ETag, EntityTag, Header, IfNoneMatch, TryIntoHeaderValue,
+    CACHE_CONTROL,
+};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{web, HttpResponse};
+use actix_web_lab::middleware::Next;
+use sha2::{Digest, Sha256};
+
+use crate::configuration::HttpCachingSettings;
+
+/// Lets browsers and proxies keep static assets for `static_max_age_seconds`. The files
+/// service already sends an `ETag` and answers conditional requests.
+pub async fn cache_static_assets(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let settings = req.app_data::<web::Data<HttpCachingSettings>>().cloned();
+    let mut response = next.call(req).await?;
+    if let Some(settings) = settings {
+        if response.status() == StatusCode::OK || response.status() == StatusCode::NOT_MODIFIED {
+            response.headers_mut().insert(
+                CACHE_CONTROL,
+                public_cache_control(settings.static_max_age_seconds)
+                    .try_into_value()
+                    .expect("Cache-Control directives are valid header values"),
+            );
+        }
+    }
+    Ok(response)
+}
+
+/// Tags the successful responses of public pages with a hash of their body, answering with a
+/// `304 Not Modified` when the client already holds that version, and lets them be cached for
+/// `pages_max_age_seconds`
+pub async fn cache_public_pages(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let settings = req.app_data::<web::Data<HttpCachingSettings>>().cloned();
+    let if_none_match = IfNoneMatch::parse(req.request()).ok();
+    let is_get = req.method() == Method::GET || req.method() == Method::HEAD;
+    let response = next.call(req).await?.map_into_boxed_body();
+    let Some(settings) = settings else {
+        return Ok(response);
+    };
+    if !is_get || response.status() != StatusCode::OK {
+        return Ok(response);
+    }
+
+    let (request, response) = response.into_parts();
+    let (mut response, body) = response.into_parts();
+    let body = body::to_bytes(body).await?;
+    let etag = body_etag(&body);
+    let cache_control = public_cache_control(settings.pages_max_age_seconds);
+    let not_modified = match if_none_match {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        None => false,
+    };
+    if not_modified {
+        let response = HttpResponse::NotModified()
+            .insert_header(ETag(etag))
+            .insert_header(cache_control)
+            .finish();
+        return Ok(ServiceResponse::new(request, response));
+    }
+    let headers = response.headers_mut();
+    headers.insert(
+        ETag::name(),
+        ETag(etag)
+            .try_into_value()
+            .expect("Entity tags are valid header values"),
+    );
+    headers.insert(
+        CACHE_CONTROL,
+        cache_control
+            .try_into_value()
+            .expect("Cache-Control directives are valid header values"),
+    );
+    Ok(ServiceResponse::new(
+        request,
+        response.set_body(BoxBody::new(body)),
+    ))
+}
+
+fn public_cache_control(max_age_seconds: u64) -> CacheControl {
+    CacheControl(vec![
+        CacheDirective::Public,
+        CacheDirective::MaxAge(max_age_seconds as u32),
+    ])
+}
+
+/// A weak tag, since the compression middleware may encode the body it is computed from
+fn body_etag(body: &[u8]) -> EntityTag {
+    let digest = Sha256::digest(body);
+    EntityTag::new_weak(hex::encode(&digest[..16]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::body_etag;
+
+    #[test]
+    fn identical_bodies_get_the_same_tag() {
+        let tag = body_etag(b"<p>Hello</p>");
+        assert!(tag.weak);
+        assert!(tag.weak_eq(&body_etag(b"<p>Hello</p>")));
+        assert!(!tag.weak_eq(&body_etag(b"<p>Hello!</p>")));
+    }
+}
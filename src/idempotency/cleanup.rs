@@ -0,0 +1,95 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Deletes the saved responses older than `retention`, returning how many were purged. The run
+/// is added to the stats reported by the metrics endpoint.
+#[tracing::instrument(
+    name = "Purge expired idempotency keys",
+    skip(pool),
+    fields(rows_purged = tracing::field::Empty)
+)]
+pub async fn purge_expired_idempotency_keys(
+    pool: &PgPool,
+    retention: Duration,
+) -> Result<u64, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let rows_purged = sqlx::query!(
+        "DELETE FROM idempotency WHERE created_at < now() - $1::float8 * interval '1 second'",
+        retention.as_secs_f64()
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete expired idempotency keys.")?
+    .rows_affected();
+    sqlx::query!(
+        r#"
+        INSERT INTO idempotency_cleanup_stats (rows_purged_total, last_run_at)
+        VALUES ($1, now())
+        ON CONFLICT (id) DO UPDATE SET
+            rows_purged_total = idempotency_cleanup_stats.rows_purged_total + EXCLUDED.rows_purged_total,
+            last_run_at = EXCLUDED.last_run_at
+        "#,
+        i64::try_from(rows_purged)?
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to record the purge of idempotency keys.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the purge of idempotency keys.")?;
+    tracing::Span::current().record("rows_purged", rows_purged);
+    Ok(rows_purged)
+}
+
+/// What the purges of expired idempotency keys did so far, across every worker
+#[derive(Debug, Default)]
+pub struct IdempotencyCleanupStats {
+    pub rows_purged_total: i64,
+    /// Not set until a purge ran
+    pub last_run_at: Option<DateTime<Utc>>,
+}
+
+impl IdempotencyCleanupStats {
+    /// Writes the rows purged and the time of the last purge in the Prometheus text format; the
+    /// time is 0 until a purge ran
+    pub fn write_prometheus(&self, body: &mut String) {
+        writeln!(
+            body,
+            "# HELP idempotency_keys_purged_total Saved responses of idempotency keys purged once expired.\n\
+            # TYPE idempotency_keys_purged_total counter\n\
+            idempotency_keys_purged_total {}\n\
+            # HELP idempotency_cleanup_last_run_timestamp_seconds When expired idempotency keys were last purged.\n\
+            # TYPE idempotency_cleanup_last_run_timestamp_seconds gauge\n\
+            idempotency_cleanup_last_run_timestamp_seconds {}",
+            self.rows_purged_total,
+            self.last_run_at.map_or(0, |last_run_at| last_run_at.timestamp())
+        )
+        .unwrap();
+    }
+}
+
+#[tracing::instrument(name = "Get idempotency cleanup stats", skip(pool))]
+pub async fn get_idempotency_cleanup_stats(
+    pool: &PgPool,
+) -> Result<IdempotencyCleanupStats, anyhow::Error> {
+    let stats =
+        sqlx::query!("SELECT rows_purged_total, last_run_at FROM idempotency_cleanup_stats")
+            .fetch_optional(pool)
+            .await
+            .context("Failed to perform a query to retrieve the idempotency cleanup stats.")?;
+    Ok(
+        stats.map_or_else(IdempotencyCleanupStats::default, |stats| {
+            IdempotencyCleanupStats {
+                rows_purged_total: stats.rows_purged_total,
+                last_run_at: Some(stats.last_run_at),
+            }
+        }),
+    )
+}
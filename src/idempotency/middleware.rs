@@ -0,0 +1,138 @@
+use std::cell::RefCell;
+use std::future::{ready, Ready};
+use std::ops::{Deref, DerefMut};
+use std::rc::Rc;
+
+use actix_web::body::{BoxBody, MessageBody};
+use actix_web::dev::{Payload, ServiceRequest, ServiceResponse};
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest};
+use actix_web_lab::middleware::Next;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+
+use crate::authentication::UserId;
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::routes::ApiError;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+/// Where the middleware keeps the transaction holding the key while the handler runs
+#[derive(Clone)]
+struct TransactionSlot(Rc<RefCell<Option<Transaction<'static, Postgres>>>>);
+
+/// The transaction holding the idempotency key of the request. Handlers making their changes in
+/// it have them committed along with the saved response, or rolled back with the key if the
+/// request fails, so that a retry cannot apply them twice.
+///
+/// Handlers must not commit it: it goes back to the middleware when the extractor is dropped.
+/// Extracting it fails with a 400 if the request has no `Idempotency-Key` header.
+pub struct IdempotentTransaction {
+    slot: TransactionSlot,
+    transaction: Option<Transaction<'static, Postgres>>,
+}
+
+impl Deref for IdempotentTransaction {
+    type Target = Transaction<'static, Postgres>;
+
+    fn deref(&self) -> &Self::Target {
+        self.transaction
+            .as_ref()
+            .expect("The transaction is only taken back on drop")
+    }
+}
+
+impl DerefMut for IdempotentTransaction {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.transaction
+            .as_mut()
+            .expect("The transaction is only taken back on drop")
+    }
+}
+
+impl Drop for IdempotentTransaction {
+    fn drop(&mut self) {
+        *self.slot.0.borrow_mut() = self.transaction.take();
+    }
+}
+
+impl FromRequest for IdempotentTransaction {
+    type Error = ApiError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let slot = req.extensions().get::<TransactionSlot>().cloned();
+        let transaction = slot.and_then(|slot| {
+            let transaction = slot.0.borrow_mut().take()?;
+            Some(Self {
+                slot,
+                transaction: Some(transaction),
+            })
+        });
+        ready(
+            transaction.ok_or_else(|| {
+                ApiError::BadRequest("The Idempotency-Key header is missing.".into())
+            }),
+        )
+    }
+}
+
+/// Lets clients safely retry unsafe requests (POST, DELETE, ...) by sending an `Idempotency-Key`
+/// header: once a request has succeeded, later requests of the same user with the same key get
+/// its saved response rather than being processed again. Failed requests are not saved, so they
+/// can be retried with the same key.
+///
+/// Keys are scoped to the user set by the authentication middleware, which must wrap this one;
+/// anonymous requests, safe methods and requests without the header are passed through. Handlers
+/// extracting an [`IdempotentTransaction`] make their changes in the transaction holding the key.
+pub async fn replay_idempotent_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let user_id = req.extensions().get::<UserId>().copied();
+    let idempotency_key = req.headers().get(IDEMPOTENCY_KEY_HEADER).cloned();
+    let (Some(user_id), Some(idempotency_key)) = (user_id, idempotency_key) else {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    };
+    if req.method().is_safe() {
+        return Ok(next.call(req).await?.map_into_boxed_body());
+    }
+    let idempotency_key: IdempotencyKey = idempotency_key
+        .to_str()
+        .map_err(|_| ApiError::BadRequest("The Idempotency-Key header is not valid.".into()))?
+        .to_owned()
+        .try_into()
+        .map_err(|e: anyhow::Error| ApiError::BadRequest(format!("{}.", e)))?;
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .cloned()
+        .context("The connection pool is not registered")
+        .map_err(ApiError::UnexpectedError)?;
+
+    // concurrent requests with the same key wait here until the first one is done
+    let transaction = match try_processing(&pool, &idempotency_key, *user_id)
+        .await
+        .map_err(ApiError::UnexpectedError)?
+    {
+        NextAction::StartProcessing(transaction) => transaction,
+        NextAction::ReturnSavedResponse(response) => return Ok(req.into_response(response)),
+    };
+    let slot = TransactionSlot(Rc::new(RefCell::new(Some(transaction))));
+    req.extensions_mut().insert(slot.clone());
+    let response = next.call(req).await?.map_into_boxed_body();
+    let transaction = slot
+        .0
+        .borrow_mut()
+        .take()
+        .context("The idempotency transaction was not handed back")
+        .map_err(ApiError::UnexpectedError)?;
+    let status = response.status();
+    if !status.is_success() && !status.is_redirection() {
+        // dropping the transaction frees the key, and rolls back what the handler did in it
+        return Ok(response);
+    }
+    let (request, response) = response.into_parts();
+    let response = save_response(transaction, &idempotency_key, *user_id, response)
+        .await
+        .map_err(ApiError::UnexpectedError)?;
+    Ok(ServiceResponse::new(request, response))
+}
@@ -1,4 +1,10 @@
+mod cleanup;
 mod key;
+mod middleware;
 mod persistence;
+pub use cleanup::{
+    get_idempotency_cleanup_stats, purge_expired_idempotency_keys, IdempotencyCleanupStats,
+};
 pub use key::IdempotencyKey;
+pub use middleware::{replay_idempotent_requests, IdempotentTransaction, IDEMPOTENCY_KEY_HEADER};
 pub use persistence::*;
@@ -1,55 +1,132 @@
-use crate::configuration::Settings;
+use crate::configuration::EmailFooterSettings;
 use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
-use crate::startup::get_connection_pool;
+use crate::email_client::{EmailClient, EmailOptions, SentEmail};
+use crate::jobs::{handle_send_outcome, ExecutionOutcome, RetryPolicy, SendOutcome};
+use crate::newsletter_content::{
+    append_footer_html, append_footer_text, inject_open_tracking_pixel, personalize_html,
+    personalize_text, track_link_clicks, Personalization,
+};
+use crate::newsletters::list_id;
+use crate::rate_limiter::TokenBucket;
+use crate::routes::mark_subscriber_as_unsubscribed;
+use crate::send_window::SendWindow;
+use crate::subscription_tokens::SubscriptionTokens;
+use crate::telemetry::pii_email;
+use crate::url_builder::UrlBuilder;
+use chrono::Utc;
 use sqlx::{PgPool, Postgres, Transaction};
 use std::time::Duration;
 use tracing::field::display;
 use tracing::Span;
 use uuid::Uuid;
 
-pub enum ExecutionOutcome {
-    TaskCompleted,
-    EmptyQueue,
-}
-
 #[tracing::instrument(
 skip_all,
 fields(
     newsletter_issue_id=tracing::field::Empty,
-    subscriber_email=tracing::field::Empty
+    subscriber_email=tracing::field::Empty,
+    provider_message_id=tracing::field::Empty
 ),
 err
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    retry_policy: &RetryPolicy,
+    send_window: Option<&SendWindow>,
+    urls: &UrlBuilder,
+    subscription_tokens: &SubscriptionTokens,
+    email_footer: Option<&EmailFooterSettings>,
+    rate_limiter: Option<&mut TokenBucket>,
 ) -> Result<ExecutionOutcome, anyhow::Error> {
-    let task = dequeue_task(pool).await?;
+    let window_open = send_window.map_or(true, |window| window.is_open(Utc::now()));
+    let task = dequeue_task(pool, window_open).await?;
     if task.is_none() {
         return Ok(ExecutionOutcome::EmptyQueue);
     }
-    let (transaction, issue_id, email) = task.unwrap();
+    let (transaction, task) = task.unwrap();
+    let DeliveryTask {
+        newsletter_issue_id: issue_id,
+        subscriber_email: email,
+        n_retries,
+        delivery_id,
+    } = task;
     Span::current()
         .record("newsletter_issue_id", &display(issue_id))
-        .record("subscriber_email", &display(&email));
+        .record("subscriber_email", &display(pii_email(&email)));
     match SubscriberEmail::parse(email.clone()) {
-        Ok(email) => {
+        Ok(subscriber_email) => {
+            let Some(recipient) = get_recipient(pool, issue_id, &email).await? else {
+                tracing::info!("Skipping a subscriber who is no longer confirmed.");
+                delete_task(transaction, issue_id, &email).await?;
+                return Ok(ExecutionOutcome::TaskCompleted);
+            };
             let issue = get_issue(pool, issue_id).await?;
-            if let Err(e) = email_client
-                .send_email(
-                    &email,
+            // the footer is added as the issue is sent, so that published issues get changes to it
+            let (html_content, text_content) = match email_footer {
+                Some(footer) => (
+                    append_footer_html(&issue.html_content, footer),
+                    append_footer_text(&issue.text_content, footer),
+                ),
+                None => (issue.html_content, issue.text_content),
+            };
+            // links are rewritten before personalization, which leaves the unsubscribe link alone
+            let html_content = if issue.click_tracking {
+                let html_content = track_link_clicks(&html_content, urls, delivery_id);
+                inject_open_tracking_pixel(&html_content, &urls.open_tracking_url(delivery_id))
+            } else {
+                html_content
+            };
+            let unsubscribe_url = urls
+                .unsubscribe_url(&subscription_tokens.unsubscribe_token(recipient.subscriber_id));
+            let personalization = Personalization {
+                name: &recipient.name,
+                email: &email,
+                unsubscribe_url: &unsubscribe_url,
+            };
+            let options = EmailOptions {
+                headers: vec![(
+                    "List-Id".to_owned(),
+                    list_id(
+                        &issue.newsletter_name,
+                        &issue.newsletter_slug,
+                        urls.base_url(),
+                    ),
+                )],
+                ..Default::default()
+            };
+            // only tasks that send an email take a token
+            if let Some(rate_limiter) = rate_limiter {
+                rate_limiter.acquire().await;
+            }
+            let outcome = email_client
+                .send_email_with(
+                    email_client.sender_of(&issue.newsletter_slug),
+                    &subscriber_email,
                     &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
+                    &personalize_html(&html_content, &personalization),
+                    &personalize_text(&text_content, &personalization),
+                    &options,
                 )
-                .await
-            {
-                tracing::error!(
-                    error.cause_chain = ?e,
-                    error.message = %e,
-                    "Failed to deliver issue to a confirmed subscribers. Skipping.",
-                );
+                .await;
+            match handle_send_outcome(outcome, n_retries, retry_policy, "an issue") {
+                SendOutcome::Sent(sent) => {
+                    if let Some(message_id) = &sent.message_id {
+                        Span::current().record("provider_message_id", &display(message_id));
+                    }
+                    record_delivery(transaction, issue_id, &email, delivery_id, &sent).await?
+                }
+                SendOutcome::Retry { n_retries, backoff } => {
+                    retry_task(transaction, issue_id, &email, n_retries, backoff).await?;
+                }
+                SendOutcome::Rejected { n_retries, error } => {
+                    park_task(transaction, issue_id, &email, n_retries, &error).await?;
+                    mark_subscriber_as_unsubscribed(recipient.subscriber_id, pool).await?;
+                }
+                SendOutcome::GiveUp { n_retries, error } => {
+                    park_task(transaction, issue_id, &email, n_retries, &error).await?;
+                }
             }
         }
         Err(e) => {
@@ -58,39 +135,146 @@ pub async fn try_execute_task(
                 error.message = %e,
                 "Skipping a confirmed subscriber. Their stored contact details are invalid.",
             );
+            park_task(transaction, issue_id, &email, n_retries, &e).await?;
         }
     }
-    delete_task(transaction, issue_id, &email).await?;
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
 type PostgresTransaction = Transaction<'static, Postgres>;
 
+struct DeliveryTask {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    n_retries: i16,
+    delivery_id: Uuid,
+}
+
+/// Dequeues a task that is due for execution, skipping tasks scheduled for a later retry,
+/// tasks belonging to paused or cancelled issues and, while the send window is closed, tasks of
+/// issues that do not ignore it
 #[tracing::instrument(skip_all)]
 async fn dequeue_task(
     pool: &PgPool,
-) -> Result<Option<(PostgresTransaction, Uuid, String)>, anyhow::Error> {
+    window_open: bool,
+) -> Result<Option<(PostgresTransaction, DeliveryTask)>, anyhow::Error> {
     let mut transaction = pool.begin().await?;
-    let record = sqlx::query!(
+    let task = sqlx::query_as!(
+        DeliveryTask,
         r#"
-        SELECT newsletter_issue_id, subscriber_email
+        SELECT newsletter_issue_id, subscriber_email, n_retries, delivery_id
         FROM issue_delivery_queue
+        WHERE
+            execute_after <= now() AND
+            newsletter_issue_id IN (
+                SELECT newsletter_issue_id
+                FROM newsletter_issues
+                WHERE status = 'delivering' AND ($1 OR ignores_send_window)
+            )
         FOR UPDATE
         SKIP LOCKED
         LIMIT 1
-        "#
+        "#,
+        window_open
     )
     .fetch_optional(&mut transaction)
     .await?;
-    if let Some(record) = record {
-        Ok(Some((
-            transaction,
-            record.newsletter_issue_id,
-            record.subscriber_email,
-        )))
-    } else {
-        Ok(None)
-    }
+    Ok(task.map(|task| (transaction, task)))
+}
+
+/// Reschedules a failed task so that it is retried after the given backoff
+#[tracing::instrument(skip_all)]
+async fn retry_task(
+    mut transaction: PostgresTransaction,
+    issue_id: Uuid,
+    email: &str,
+    n_retries: i16,
+    backoff: Duration,
+) -> Result<(), anyhow::Error> {
+    let execute_after = Utc::now() + chrono::Duration::from_std(backoff)?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_retries = $3,
+            execute_after = $4
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        issue_id,
+        email,
+        n_retries,
+        execute_after
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Records a successful delivery and removes the task from the queue
+#[tracing::instrument(skip_all)]
+async fn record_delivery(
+    mut transaction: PostgresTransaction,
+    issue_id: Uuid,
+    email: &str,
+    delivery_id: Uuid,
+    sent: &SentEmail,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_deliveries (
+            newsletter_issue_id,
+            subscriber_email,
+            delivered_at,
+            delivery_id,
+            provider_message_id,
+            provider_submitted_at
+        )
+        VALUES ($1, $2, now(), $3, $4, $5)
+        ON CONFLICT DO NOTHING
+        "#,
+        issue_id,
+        email,
+        delivery_id,
+        sent.message_id,
+        sent.submitted_at
+    )
+    .execute(&mut transaction)
+    .await?;
+    delete_task(transaction, issue_id, email).await
+}
+
+/// Moves a task that cannot be delivered out of the queue and into `issue_delivery_failures`
+#[tracing::instrument(skip_all)]
+async fn park_task(
+    mut transaction: PostgresTransaction,
+    issue_id: Uuid,
+    email: &str,
+    n_retries: i16,
+    error: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_failures (
+            newsletter_issue_id,
+            subscriber_email,
+            n_retries,
+            failed_at,
+            error
+        )
+        VALUES ($1, $2, $3, now(), $4)
+        ON CONFLICT DO NOTHING
+        "#,
+        issue_id,
+        email,
+        n_retries,
+        error
+    )
+    .execute(&mut transaction)
+    .await?;
+    delete_task(transaction, issue_id, email).await
 }
 
 #[tracing::instrument(skip_all)]
@@ -119,6 +303,10 @@ struct NewsletterIssue {
     title: String,
     text_content: String,
     html_content: String,
+    click_tracking: bool,
+    /// Slug of the newsletter the issue belongs to, which picks the sender identity
+    newsletter_slug: String,
+    newsletter_name: String,
 }
 
 #[tracing::instrument(skip_all)]
@@ -126,8 +314,15 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     let issue = sqlx::query_as!(
         NewsletterIssue,
         r#"
-        SELECT title, text_content, html_content
+        SELECT
+            title,
+            text_content,
+            html_content,
+            click_tracking,
+            slug AS newsletter_slug,
+            name AS newsletter_name
         FROM newsletter_issues
+        JOIN newsletters USING (newsletter_id)
         WHERE
             newsletter_issue_id = $1
         "#,
@@ -138,22 +333,37 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     Ok(issue)
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
-    loop {
-        match try_execute_task(&pool, &email_client).await {
-            Ok(ExecutionOutcome::EmptyQueue) => {
-                tokio::time::sleep(Duration::from_secs(10)).await;
-            }
-            Err(_) => {
-                tokio::time::sleep(Duration::from_secs(1)).await;
-            }
-            Ok(ExecutionOutcome::TaskCompleted) => {}
-        }
-    }
+/// The details needed to personalize an issue for one of its recipients
+struct Recipient {
+    subscriber_id: Uuid,
+    name: String,
 }
 
-pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
-    let connection_pool = get_connection_pool(&configuration.database);
-    let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+/// Looks up a subscriber of the newsletter of the issue who is still confirmed; returns `None` if
+/// they unsubscribed since the issue was published
+#[tracing::instrument(skip_all)]
+async fn get_recipient(
+    pool: &PgPool,
+    issue_id: Uuid,
+    email: &str,
+) -> Result<Option<Recipient>, anyhow::Error> {
+    let recipient = sqlx::query_as!(
+        Recipient,
+        r#"
+        SELECT id AS subscriber_id, name
+        FROM subscriptions
+        WHERE
+            email = $1 AND
+            status = 'confirmed' AND
+            deleted_at IS NULL AND
+            subscriptions.newsletter_id =
+                (SELECT newsletter_id FROM newsletter_issues WHERE newsletter_issue_id = $2)
+        LIMIT 1
+        "#,
+        email,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(recipient)
 }
@@ -0,0 +1,99 @@
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Workers write at most one heartbeat per interval: the loop iterates once per email sent
+const MIN_BEAT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The heartbeat of one worker process, identified by a new id every time it starts
+pub struct Heartbeat {
+    worker_id: Uuid,
+    hostname: String,
+    started_at: DateTime<Utc>,
+    last_beat: Option<Instant>,
+}
+
+impl Heartbeat {
+    pub fn new() -> Self {
+        Self {
+            worker_id: Uuid::new_v4(),
+            hostname: std::env::var("HOSTNAME").unwrap_or_else(|_| "unknown".into()),
+            started_at: Utc::now(),
+            last_beat: None,
+        }
+    }
+
+    /// Records that the worker is alive. Failures are only logged: a database outage already
+    /// shows up in the heartbeat going stale.
+    pub async fn beat(&mut self, pool: &PgPool) {
+        if self
+            .last_beat
+            .map_or(false, |last_beat| last_beat.elapsed() < MIN_BEAT_INTERVAL)
+        {
+            return;
+        }
+        if let Err(e) = self.record(pool).await {
+            tracing::warn!(error.cause_chain = ?e, "Failed to record the worker heartbeat.");
+            return;
+        }
+        self.last_beat = Some(Instant::now());
+    }
+
+    #[tracing::instrument(name = "Record worker heartbeat", skip(self, pool))]
+    pub async fn record(&self, pool: &PgPool) -> Result<(), anyhow::Error> {
+        sqlx::query!(
+            r#"
+            INSERT INTO worker_heartbeats (worker_id, hostname, started_at, last_beat_at)
+            VALUES ($1, $2, $3, now())
+            ON CONFLICT (worker_id) DO UPDATE SET last_beat_at = EXCLUDED.last_beat_at
+            "#,
+            self.worker_id,
+            self.hostname,
+            self.started_at
+        )
+        .execute(pool)
+        .await
+        .context("Failed to perform a query to record the worker heartbeat.")?;
+        Ok(())
+    }
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The last heartbeat of a worker process
+#[derive(Debug, serde::Serialize)]
+pub struct WorkerHeartbeat {
+    pub worker_id: Uuid,
+    pub hostname: String,
+    pub started_at: DateTime<Utc>,
+    pub last_beat_at: DateTime<Utc>,
+}
+
+/// Returns the workers heard from since `since`, the most recent heartbeat first
+#[tracing::instrument(name = "Get worker heartbeats", skip(pool))]
+pub async fn get_worker_heartbeats(
+    pool: &PgPool,
+    since: DateTime<Utc>,
+) -> Result<Vec<WorkerHeartbeat>, anyhow::Error> {
+    let heartbeats = sqlx::query_as!(
+        WorkerHeartbeat,
+        r#"
+        SELECT worker_id, hostname, started_at, last_beat_at
+        FROM worker_heartbeats
+        WHERE last_beat_at >= $1
+        ORDER BY last_beat_at DESC
+        "#,
+        since
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve the worker heartbeats.")?;
+    Ok(heartbeats)
+}
@@ -0,0 +1,9 @@
+mod heartbeat;
+mod queue;
+mod send_outcome;
+mod worker;
+
+pub use heartbeat::*;
+pub use queue::*;
+pub use send_outcome::*;
+pub use worker::*;
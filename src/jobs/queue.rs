@@ -0,0 +1,334 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::field::display;
+use tracing::Span;
+use uuid::Uuid;
+
+use crate::configuration::{
+    DeletedSubscriberSettings, DeliveryAlertSettings, IdempotencySettings, Settings,
+};
+use crate::delivery_backlog::check_delivery_backlog;
+use crate::email_client::EmailClient;
+use crate::idempotency::purge_expired_idempotency_keys;
+use crate::jobs::{ExecutionOutcome, RetryPolicy};
+use crate::send_window::SendWindow;
+use crate::subscriber_purge::purge_deleted_subscribers;
+
+type PostgresTransaction = Transaction<'static, Postgres>;
+
+/// A unit of background work stored in `background_jobs`. The variant and its fields are saved
+/// as the payload, so new kinds of work only need a variant and a branch in `execute`.
+#[derive(serde::Serialize, serde::Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Job {
+    /// Deletes the responses saved for idempotency keys past their retention
+    PurgeIdempotencyKeys,
+    /// Erases the subscribers deleted longer ago than the retention window
+    PurgeDeletedSubscribers,
+    /// Warns when deliveries are backing up past the thresholds of `delivery_alerts`
+    CheckDeliveryBacklog,
+}
+
+impl Job {
+    /// The name saved alongside the payload, to tell jobs apart in queries and traces
+    pub fn job_type(&self) -> &'static str {
+        match self {
+            Job::PurgeIdempotencyKeys => "purge_idempotency_keys",
+            Job::PurgeDeletedSubscribers => "purge_deleted_subscribers",
+            Job::CheckDeliveryBacklog => "check_delivery_backlog",
+        }
+    }
+
+    async fn execute(&self, context: &JobContext) -> Result<(), anyhow::Error> {
+        match self {
+            Job::PurgeIdempotencyKeys => {
+                let rows_purged =
+                    purge_expired_idempotency_keys(&context.pool, context.idempotency.retention())
+                        .await?;
+                tracing::info!(rows_purged, "Purged expired idempotency keys.");
+            }
+            Job::PurgeDeletedSubscribers => {
+                let subscribers_purged = purge_deleted_subscribers(
+                    &context.pool,
+                    context.deleted_subscribers.retention(),
+                )
+                .await?;
+                tracing::info!(subscribers_purged, "Purged deleted subscribers.");
+            }
+            Job::CheckDeliveryBacklog => {
+                check_delivery_backlog(
+                    &context.pool,
+                    &context.email_client,
+                    &context.delivery_alerts,
+                    context.send_window.as_ref(),
+                )
+                .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What jobs need to run
+pub struct JobContext {
+    pub pool: PgPool,
+    pub idempotency: IdempotencySettings,
+    pub deleted_subscribers: DeletedSubscriberSettings,
+    pub delivery_alerts: DeliveryAlertSettings,
+    pub email_client: Arc<EmailClient>,
+    pub send_window: Option<SendWindow>,
+}
+
+impl JobContext {
+    /// Jobs send through `email_client`, which the API shares when it runs in the same process
+    pub fn new(pool: PgPool, configuration: &Settings, email_client: Arc<EmailClient>) -> Self {
+        Self {
+            pool,
+            idempotency: configuration.idempotency.clone(),
+            deleted_subscribers: configuration.deleted_subscribers.clone(),
+            delivery_alerts: configuration.delivery_alerts.clone(),
+            email_client,
+            send_window: configuration.issue_delivery.send_window.clone(),
+        }
+    }
+}
+
+/// Queues a job that runs once, as soon as a worker is free after `execute_after`
+#[tracing::instrument(skip(pool), fields(job_type = job.job_type()))]
+pub async fn enqueue_job(
+    pool: &PgPool,
+    job: &Job,
+    execute_after: DateTime<Utc>,
+) -> Result<Uuid, anyhow::Error> {
+    let job_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO background_jobs (job_id, job_type, payload, execute_after)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        job_id,
+        job.job_type(),
+        serde_json::to_value(job)?,
+        execute_after
+    )
+    .execute(pool)
+    .await?;
+    Ok(job_id)
+}
+
+/// Makes sure a job runs every `interval`, starting now if it was not scheduled yet. Scheduling
+/// the same type of job again only updates its interval, so every worker can call this on start.
+#[tracing::instrument(skip(pool), fields(job_type = job.job_type()))]
+pub async fn schedule_recurring_job(
+    pool: &PgPool,
+    job: &Job,
+    interval: Duration,
+) -> Result<(), anyhow::Error> {
+    let repeat_every_seconds = i64::try_from(interval.as_secs())?.max(1);
+    sqlx::query!(
+        r#"
+        INSERT INTO background_jobs (job_id, job_type, payload, repeat_every_seconds)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (job_type) WHERE repeat_every_seconds IS NOT NULL
+        DO UPDATE SET
+            payload = EXCLUDED.payload,
+            repeat_every_seconds = EXCLUDED.repeat_every_seconds
+        "#,
+        Uuid::new_v4(),
+        job.job_type(),
+        serde_json::to_value(job)?,
+        repeat_every_seconds
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Runs a job that is due, if any. Failed jobs are retried with the backoff of the retry policy;
+/// once out of retries, recurring jobs wait for their next run while the others are parked.
+#[tracing::instrument(
+skip_all,
+fields(
+    job_id=tracing::field::Empty,
+    job_type=tracing::field::Empty
+),
+err
+)]
+pub async fn try_execute_job(
+    context: &JobContext,
+    retry_policy: &RetryPolicy,
+) -> Result<ExecutionOutcome, anyhow::Error> {
+    let Some((transaction, queued_job)) = dequeue_job(&context.pool).await? else {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    };
+    Span::current()
+        .record("job_id", &display(queued_job.job_id))
+        .record("job_type", &display(&queued_job.job_type));
+    let job: Job = match serde_json::from_value(queued_job.payload.clone()) {
+        Ok(job) => job,
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Parking a job whose payload cannot be read.",
+            );
+            park_job(transaction, &queued_job, &e.to_string()).await?;
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+    };
+    let next_run = queued_job
+        .repeat_every_seconds
+        .map(|seconds| Utc::now() + chrono::Duration::seconds(seconds));
+    match job.execute(context).await {
+        Ok(()) => match next_run {
+            Some(next_run) => reschedule_job(transaction, &queued_job, 0, next_run).await?,
+            None => delete_job(transaction, &queued_job).await?,
+        },
+        Err(e) => {
+            let n_retries = queued_job.n_retries + 1;
+            if n_retries < retry_policy.max_retries {
+                let backoff = retry_policy.backoff(n_retries);
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    n_retries,
+                    "Failed to run a background job. Retrying in {:?}.",
+                    backoff
+                );
+                let execute_after = Utc::now() + chrono::Duration::from_std(backoff)?;
+                reschedule_job(transaction, &queued_job, n_retries, execute_after).await?;
+            } else if let Some(next_run) = next_run {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    n_retries,
+                    "Failed to run a background job. Skipping this run.",
+                );
+                reschedule_job(transaction, &queued_job, 0, next_run).await?;
+            } else {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    n_retries,
+                    "Failed to run a background job. Giving up.",
+                );
+                park_job(transaction, &queued_job, &e.to_string()).await?;
+            }
+        }
+    }
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+struct QueuedJob {
+    job_id: Uuid,
+    job_type: String,
+    payload: serde_json::Value,
+    repeat_every_seconds: Option<i64>,
+    n_retries: i16,
+}
+
+/// Dequeues a job that is due, skipping the ones locked by another worker and the parked ones
+#[tracing::instrument(skip_all)]
+async fn dequeue_job(
+    pool: &PgPool,
+) -> Result<Option<(PostgresTransaction, QueuedJob)>, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let job = sqlx::query_as!(
+        QueuedJob,
+        r#"
+        SELECT job_id, job_type, payload, repeat_every_seconds, n_retries
+        FROM background_jobs
+        WHERE
+            execute_after <= now() AND
+            failed_at IS NULL
+        ORDER BY execute_after
+        FOR UPDATE
+        SKIP LOCKED
+        LIMIT 1
+        "#
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    Ok(job.map(|job| (transaction, job)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn reschedule_job(
+    mut transaction: PostgresTransaction,
+    job: &QueuedJob,
+    n_retries: i16,
+    execute_after: DateTime<Utc>,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE background_jobs
+        SET
+            n_retries = $2,
+            execute_after = $3
+        WHERE job_id = $1
+        "#,
+        job.job_id,
+        n_retries,
+        execute_after
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// Keeps a job that will not be retried, with the error that stopped it
+#[tracing::instrument(skip_all)]
+async fn park_job(
+    mut transaction: PostgresTransaction,
+    job: &QueuedJob,
+    error: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE background_jobs
+        SET
+            failed_at = now(),
+            last_error = $2
+        WHERE job_id = $1
+        "#,
+        job.job_id,
+        error
+    )
+    .execute(&mut transaction)
+    .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_job(
+    mut transaction: PostgresTransaction,
+    job: &QueuedJob,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!("DELETE FROM background_jobs WHERE job_id = $1", job.job_id)
+        .execute(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Job;
+
+    #[test]
+    fn jobs_are_saved_with_their_type() {
+        for job in [
+            Job::PurgeIdempotencyKeys,
+            Job::PurgeDeletedSubscribers,
+            Job::CheckDeliveryBacklog,
+        ] {
+            let payload = serde_json::to_value(&job).unwrap();
+            assert_eq!(payload["type"], job.job_type());
+            assert_eq!(serde_json::from_value::<Job>(payload).unwrap(), job);
+        }
+    }
+}
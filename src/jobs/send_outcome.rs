@@ -0,0 +1,164 @@
+use std::time::Duration;
+
+use crate::email_client::{SendEmailError, SentEmail};
+use crate::jobs::RetryPolicy;
+
+/// What the delivery and automation workers do with a task once they tried to send its email
+#[derive(Debug)]
+pub enum SendOutcome {
+    Sent(SentEmail),
+    /// Try again after `backoff`, recording `n_retries`
+    Retry {
+        n_retries: i16,
+        backoff: Duration,
+    },
+    /// The provider will never deliver to the recipient: drop the task and unsubscribe them
+    Rejected {
+        n_retries: i16,
+        error: String,
+    },
+    /// The task failed too many times: drop it, but leave the recipient subscribed
+    GiveUp {
+        n_retries: i16,
+        error: String,
+    },
+}
+
+/// Decides what happens to a task after a send, logging the failures. `email` describes what was
+/// sent, e.g. "an issue", for the logs. Waiting for the provider to recover or to lift a rate
+/// limit does not use up a retry, since it says nothing about this email.
+pub fn handle_send_outcome(
+    outcome: Result<SentEmail, SendEmailError>,
+    n_retries: i16,
+    retry_policy: &RetryPolicy,
+    email: &str,
+) -> SendOutcome {
+    match outcome {
+        Ok(sent) => SendOutcome::Sent(sent),
+        Err(SendEmailError::CircuitOpen { retry_after }) => SendOutcome::Retry {
+            n_retries,
+            backoff: retry_after,
+        },
+        Err(SendEmailError::RateLimited { retry_after }) => {
+            let backoff = retry_after.unwrap_or(retry_policy.base_delay);
+            tracing::warn!(
+                "The email provider is rate limiting emails. Retrying {} in {:?}.",
+                email,
+                backoff
+            );
+            SendOutcome::Retry { n_retries, backoff }
+        }
+        // retrying would only get the same answer
+        Err(e @ SendEmailError::Permanent { .. }) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "The email provider rejected the recipient of {}. Unsubscribing them.",
+                email
+            );
+            SendOutcome::Rejected {
+                n_retries,
+                error: e.to_string(),
+            }
+        }
+        Err(e) => {
+            let n_retries = n_retries + 1;
+            if n_retries >= retry_policy.max_retries {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    n_retries,
+                    "Failed to send {}. Giving up.",
+                    email
+                );
+                SendOutcome::GiveUp {
+                    n_retries,
+                    error: e.to_string(),
+                }
+            } else {
+                let backoff = retry_policy.backoff(n_retries);
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    error.message = %e,
+                    n_retries,
+                    "Failed to send {}. Retrying in {:?}.",
+                    email,
+                    backoff
+                );
+                SendOutcome::Retry { n_retries, backoff }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{handle_send_outcome, SendOutcome};
+    use crate::email_client::SendEmailError;
+    use crate::jobs::RetryPolicy;
+    use std::time::Duration;
+
+    fn retry_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 3,
+            base_delay: Duration::from_secs(10),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn waiting_for_the_provider_does_not_use_up_a_retry() {
+        let circuit_open = handle_send_outcome(
+            Err(SendEmailError::CircuitOpen {
+                retry_after: Duration::from_secs(30),
+            }),
+            1,
+            &retry_policy(),
+            "an issue",
+        );
+        let rate_limited = handle_send_outcome(
+            Err(SendEmailError::RateLimited { retry_after: None }),
+            1,
+            &retry_policy(),
+            "an issue",
+        );
+        assert!(matches!(
+            circuit_open,
+            SendOutcome::Retry { n_retries: 1, backoff } if backoff == Duration::from_secs(30)
+        ));
+        assert!(matches!(
+            rate_limited,
+            SendOutcome::Retry { n_retries: 1, backoff } if backoff == Duration::from_secs(10)
+        ));
+    }
+
+    #[test]
+    fn permanent_errors_reject_the_recipient_straight_away() {
+        let outcome = handle_send_outcome(
+            Err(SendEmailError::Permanent {
+                status: 422,
+                error_code: Some(406),
+                message: "Inactive recipient".into(),
+            }),
+            0,
+            &retry_policy(),
+            "an issue",
+        );
+        assert!(matches!(
+            outcome,
+            SendOutcome::Rejected { n_retries: 0, .. }
+        ));
+    }
+
+    #[test]
+    fn other_failures_are_retried_until_the_last_retry() {
+        let failure = || SendEmailError::UnexpectedResponse { status: 302 };
+        let retried = handle_send_outcome(Err(failure()), 1, &retry_policy(), "an issue");
+        let given_up = handle_send_outcome(Err(failure()), 2, &retry_policy(), "an issue");
+        assert!(matches!(
+            retried,
+            SendOutcome::Retry { n_retries: 2, backoff } if backoff == Duration::from_secs(40)
+        ));
+        assert!(matches!(given_up, SendOutcome::GiveUp { n_retries: 3, .. }));
+    }
+}
@@ -0,0 +1,210 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use sqlx::PgPool;
+
+use crate::automation_worker::try_execute_automation_step;
+use crate::configuration::{EmailFooterSettings, Settings};
+use crate::delivery_enqueuer::{try_enqueue_batch, EnqueueOutcome, ENQUEUE_BATCH_SIZE};
+use crate::email_client::EmailClient;
+use crate::issue_delivery_worker::try_execute_task;
+use crate::jobs::{schedule_recurring_job, try_execute_job, Heartbeat, Job, JobContext};
+use crate::rate_limiter::TokenBucket;
+use crate::startup::get_connection_pool;
+use crate::subscription_tokens::SubscriptionTokens;
+use crate::url_builder::UrlBuilder;
+
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+/// Controls how failed tasks and jobs are rescheduled
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// Number of attempts after which a task is given up on
+    pub max_retries: i16,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Exponential backoff: the delay doubles with each retry, capped at `max_delay`
+    pub fn backoff(&self, n_retries: i16) -> Duration {
+        let exponent = n_retries.clamp(0, 31) as u32;
+        self.base_delay
+            .checked_mul(2u32.pow(exponent))
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay))
+    }
+}
+
+/// How often the worker looks for newly published issues while none is being enqueued
+const ENQUEUE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Queues the recipients of published issues from the worker loop: a batch per iteration while
+/// an issue is being enqueued, otherwise at most once per `ENQUEUE_POLL_INTERVAL`, so that
+/// sending an email does not also cost a lookup for issues to enqueue
+#[derive(Default)]
+struct EnqueuePoller {
+    next_poll: Option<Instant>,
+}
+
+impl EnqueuePoller {
+    async fn poll(&mut self, pool: &PgPool) -> Result<(), anyhow::Error> {
+        if matches!(self.next_poll, Some(next_poll) if Instant::now() < next_poll) {
+            return Ok(());
+        }
+        let outcome = try_enqueue_batch(pool, ENQUEUE_BATCH_SIZE).await;
+        self.next_poll = match outcome {
+            Ok(EnqueueOutcome::BatchEnqueued) => None,
+            _ => Some(Instant::now() + ENQUEUE_POLL_INTERVAL),
+        };
+        if let Err(e) = &outcome {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to enqueue the deliveries of an issue.",
+            );
+        }
+        outcome.map(|_| ())
+    }
+}
+
+/// Polls every queue of background work, in order of priority: due jobs first, since they are
+/// few and quick, then issue deliveries, then automation steps. Jobs and deliveries share the
+/// email client of the context, and so its circuit breaker; deliveries and automation steps
+/// share the rate limiter, taking a token only when they have an email to send.
+async fn worker_loop(
+    context: JobContext,
+    retry_policy: RetryPolicy,
+    urls: UrlBuilder,
+    subscription_tokens: SubscriptionTokens,
+    email_footer: Option<EmailFooterSettings>,
+    mut rate_limiter: Option<TokenBucket>,
+) -> Result<(), anyhow::Error> {
+    let pool = &context.pool;
+    let email_client = &context.email_client;
+    let mut heartbeat = Heartbeat::new();
+    let mut enqueue_poller = EnqueuePoller::default();
+    loop {
+        heartbeat.beat(pool).await;
+        // recipients of newly published issues are queued a batch at a time, alongside deliveries
+        let outcome = match enqueue_poller.poll(pool).await {
+            Ok(()) => try_execute_job(&context, &retry_policy).await,
+            Err(e) => Err(e),
+        };
+        let outcome = match outcome {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                match try_execute_task(
+                    pool,
+                    email_client,
+                    &retry_policy,
+                    context.send_window.as_ref(),
+                    &urls,
+                    &subscription_tokens,
+                    email_footer.as_ref(),
+                    rate_limiter.as_mut(),
+                )
+                .await
+                {
+                    // automation steps go out whenever no issue is waiting to be delivered
+                    Ok(ExecutionOutcome::EmptyQueue) => {
+                        try_execute_automation_step(
+                            pool,
+                            email_client,
+                            &retry_policy,
+                            &urls,
+                            &subscription_tokens,
+                            email_footer.as_ref(),
+                            rate_limiter.as_mut(),
+                        )
+                        .await
+                    }
+                    outcome => outcome,
+                }
+            }
+            outcome => outcome,
+        };
+        match outcome {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Err(_) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+        }
+    }
+}
+
+/// Schedules the maintenance jobs, then processes background work until the process stops.
+/// Emails go out through `email_client`, shared with the API when both run in one process.
+pub async fn run_worker_until_stopped(
+    configuration: Settings,
+    email_client: Arc<EmailClient>,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    schedule_recurring_job(
+        &connection_pool,
+        &Job::PurgeIdempotencyKeys,
+        configuration.idempotency.cleanup_interval(),
+    )
+    .await?;
+    schedule_recurring_job(
+        &connection_pool,
+        &Job::PurgeDeletedSubscribers,
+        configuration.deleted_subscribers.purge_interval(),
+    )
+    .await?;
+    schedule_recurring_job(
+        &connection_pool,
+        &Job::CheckDeliveryBacklog,
+        configuration.delivery_alerts.check_interval(),
+    )
+    .await?;
+    let context = JobContext::new(connection_pool, &configuration, email_client);
+    let rate_limiter = configuration
+        .email_client
+        .max_send_rate
+        .map(TokenBucket::new);
+    let retry_policy = configuration.issue_delivery.retry_policy();
+    let subscription_tokens = SubscriptionTokens::new(configuration.application.hmac_secret);
+    worker_loop(
+        context,
+        retry_policy,
+        UrlBuilder::new(configuration.application.base_url),
+        subscription_tokens,
+        configuration.email_footer,
+        rate_limiter,
+    )
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetryPolicy;
+    use std::time::Duration;
+
+    fn retry_policy() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 5,
+            base_delay: Duration::from_secs(10),
+            max_delay: Duration::from_secs(60),
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_with_each_retry() {
+        let policy = retry_policy();
+        assert_eq!(policy.backoff(0), Duration::from_secs(10));
+        assert_eq!(policy.backoff(1), Duration::from_secs(20));
+        assert_eq!(policy.backoff(2), Duration::from_secs(40));
+    }
+
+    #[test]
+    fn backoff_is_capped_at_max_delay() {
+        let policy = retry_policy();
+        assert_eq!(policy.backoff(3), Duration::from_secs(60));
+        assert_eq!(policy.backoff(i16::MAX), Duration::from_secs(60));
+    }
+}
@@ -1,13 +1,47 @@
+pub mod analytics;
 pub mod async_helpers;
+pub mod audit_log;
 pub mod authentication;
+pub mod automation_worker;
+pub mod automations;
+pub mod bot_protection;
+pub mod captcha;
+pub mod cli;
+pub mod click_tracking;
 pub mod configuration;
+pub mod configuration_check;
+pub mod css_inlining;
+pub mod delivery_backlog;
+pub mod delivery_enqueuer;
+pub mod delivery_progress;
 pub mod domain;
 pub mod email_client;
+pub mod email_templates;
+pub mod email_validation;
 mod error_handling;
+pub mod events;
+pub mod http_caching;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod jobs;
+pub mod media_storage;
+pub mod newsletter_content;
+pub mod newsletters;
+pub mod qr_code;
+pub mod rate_limiter;
+pub mod rate_limiting;
+pub mod request_metrics;
+pub mod reverse_proxy;
 pub mod routes;
 mod routing_helpers;
+pub mod run_mode;
+pub mod send_window;
 pub mod session_state;
 pub mod startup;
+pub mod subscriber_purge;
+pub mod subscription_consents;
+pub mod subscription_events;
+pub mod subscription_tokens;
 pub mod telemetry;
+pub mod templates;
+pub mod url_builder;
@@ -1,29 +1,85 @@
+use anyhow::Context;
+use clap::Parser;
+use email_newsletter::cli::Cli;
 use email_newsletter::configuration::get_configuration;
-use email_newsletter::issue_delivery_worker::run_worker_until_stopped;
-use email_newsletter::startup::Application;
+use email_newsletter::jobs::run_worker_until_stopped;
+use email_newsletter::run_mode::RunMode;
+use email_newsletter::startup::{run_worker_metrics, Application};
 use email_newsletter::telemetry;
 use std::fmt::{Debug, Display};
+use std::net::TcpListener;
+use std::sync::Arc;
 use tokio::task::JoinError;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    let configuration = get_configuration().expect("Failed to read configuration.");
+
+    let log_file = configuration
+        .telemetry
+        .log_file
+        .as_ref()
+        .map(telemetry::RollingFileAppender::open)
+        .transpose()
+        .context("Failed to open the log file.")?;
     let subscriber = telemetry::get_tracing_subscriber(
         "email-newsletter".into(),
         "info".into(),
         std::io::stdout,
+        log_file,
     );
     telemetry::init_subscriber(subscriber);
+    telemetry::set_pii_redaction(configuration.telemetry.redact_pii);
 
-    let configuration = get_configuration().expect("Failed to read configuration.");
+    if let Some(command) = cli.command {
+        return command.run(configuration).await;
+    }
 
-    let application = Application::build(configuration.clone()).await?;
-    let application_task = tokio::spawn(application.run_until_stopped());
-    let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
+    match cli.mode {
+        RunMode::Api => {
+            let application = Application::build(configuration).await?;
+            let output = tokio::spawn(application.run_until_stopped()).await;
+            report_exit("API", output);
+        }
+        RunMode::Worker => {
+            let email_client = Arc::new(configuration.email_client.clone().client());
+            let metrics_server = configuration
+                .issue_delivery
+                .metrics_port
+                .map(|port| {
+                    let address = format!("{}:{}", configuration.application.host, port);
+                    run_worker_metrics(TcpListener::bind(address)?, email_client.clone())
+                })
+                .transpose()
+                .context("Failed to serve the metrics of the worker.")?;
+            let worker_task = tokio::spawn(run_worker_until_stopped(configuration, email_client));
+            match metrics_server {
+                Some(metrics_server) => {
+                    let metrics_task = tokio::spawn(metrics_server);
+                    tokio::select! {
+                        output = worker_task => report_exit("Background worker", output),
+                        output = metrics_task => report_exit("Worker metrics", output),
+                    };
+                }
+                None => report_exit("Background worker", worker_task.await),
+            }
+        }
+        RunMode::All => {
+            // one client for both, so that they share its circuit breaker and its counters
+            let email_client = Arc::new(configuration.email_client.clone().client());
+            let application =
+                Application::build_with_email_client(configuration.clone(), email_client.clone())
+                    .await?;
+            let application_task = tokio::spawn(application.run_until_stopped());
+            let worker_task = tokio::spawn(run_worker_until_stopped(configuration, email_client));
 
-    tokio::select! {
-        output = application_task => report_exit("API", output),
-        output = worker_task => report_exit("Background worker", output),
-    };
+            tokio::select! {
+                output = application_task => report_exit("API", output),
+                output = worker_task => report_exit("Background worker", output),
+            };
+        }
+    }
 
     Ok(())
 }
@@ -0,0 +1,241 @@
+use std::path::PathBuf;
+
+use actix_web::web::Bytes;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+
+use crate::async_helpers::spawn_blocking_with_tracing;
+use crate::configuration::{MediaSettings, MediaStorageKind, S3Settings};
+use crate::email_client::{hmac_sha256, signing_key};
+use crate::url_builder::UrlBuilder;
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// The image formats that can be uploaded. They are recognized by their first bytes rather than
+/// by the type the browser claims, so that no HTML or SVG document, which could run scripts, is
+/// ever served from the URLs of the media library.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageType {
+    Png,
+    Jpeg,
+    Gif,
+    Webp,
+}
+
+impl ImageType {
+    pub fn sniff(content: &[u8]) -> Option<Self> {
+        if content.starts_with(b"\x89PNG\r\n\x1a\n") {
+            Some(Self::Png)
+        } else if content.starts_with(b"\xff\xd8\xff") {
+            Some(Self::Jpeg)
+        } else if content.starts_with(b"GIF87a") || content.starts_with(b"GIF89a") {
+            Some(Self::Gif)
+        } else if content.len() >= 12 && &content[..4] == b"RIFF" && &content[8..12] == b"WEBP" {
+            Some(Self::Webp)
+        } else {
+            None
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Png => "image/png",
+            Self::Jpeg => "image/jpeg",
+            Self::Gif => "image/gif",
+            Self::Webp => "image/webp",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::Gif => "gif",
+            Self::Webp => "webp",
+        }
+    }
+}
+
+/// Where the uploaded images are written, and the public URLs they get there
+#[derive(Debug)]
+pub enum MediaStorage {
+    /// Files of a directory, served by the application under `/media`
+    Disk {
+        directory: PathBuf,
+        urls: UrlBuilder,
+    },
+    S3 {
+        http_client: reqwest::Client,
+        settings: S3Settings,
+    },
+}
+
+impl MediaStorage {
+    pub fn new(settings: &MediaSettings, urls: UrlBuilder) -> Self {
+        match settings.storage {
+            MediaStorageKind::Disk => Self::Disk {
+                directory: PathBuf::from(&settings.directory),
+                urls,
+            },
+            MediaStorageKind::S3 => Self::S3 {
+                http_client: reqwest::Client::new(),
+                settings: settings
+                    .s3
+                    .clone()
+                    .expect("Missing `media.s3` settings for the S3 storage."),
+            },
+        }
+    }
+
+    /// Stores `content` under `key` and returns its public URL
+    #[tracing::instrument(name = "Store an uploaded image", skip(self, content))]
+    pub async fn store(
+        &self,
+        key: &str,
+        image_type: ImageType,
+        content: Bytes,
+    ) -> Result<String, anyhow::Error> {
+        match self {
+            Self::Disk { directory, urls } => {
+                let (directory, file_name) = (directory.clone(), key.to_owned());
+                spawn_blocking_with_tracing(move || {
+                    std::fs::create_dir_all(&directory)?;
+                    std::fs::write(directory.join(file_name), content)
+                })
+                .await?
+                .context("Failed to write the image to the media directory.")?;
+                Ok(urls.url(&format!("/media/{}", key)))
+            }
+            Self::S3 {
+                http_client,
+                settings,
+            } => {
+                let url = Url::parse(&format!(
+                    "{}/{}/{}",
+                    settings.endpoint.trim_end_matches('/'),
+                    settings.bucket,
+                    key
+                ))
+                .context("Invalid `media.s3.endpoint`.")?;
+                let signed = sign_put_request(
+                    settings,
+                    &url,
+                    image_type.content_type(),
+                    &content,
+                    Utc::now(),
+                );
+                http_client
+                    .put(url.clone())
+                    .header("Content-Type", image_type.content_type())
+                    .header("X-Amz-Date", signed.amz_date)
+                    .header("X-Amz-Content-Sha256", signed.content_sha256)
+                    .header("Authorization", signed.authorization)
+                    .body(content)
+                    .send()
+                    .await
+                    .context("Failed to upload the image to the bucket.")?
+                    .error_for_status()
+                    .context("The bucket rejected the image.")?;
+                Ok(match &settings.public_base_url {
+                    Some(public_base_url) => {
+                        format!("{}/{}", public_base_url.trim_end_matches('/'), key)
+                    }
+                    None => url.to_string(),
+                })
+            }
+        }
+    }
+}
+
+struct SignedHeaders {
+    amz_date: String,
+    content_sha256: String,
+    authorization: String,
+}
+
+/// Signs a PUT of an object following AWS Signature Version 4, like the requests to SES
+fn sign_put_request(
+    settings: &S3Settings,
+    url: &Url,
+    content_type: &str,
+    content: &[u8],
+    now: DateTime<Utc>,
+) -> SignedHeaders {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date_stamp = now.format("%Y%m%d").to_string();
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let content_sha256 = hex::encode(Sha256::digest(content));
+    let signed_headers = "content-type;host;x-amz-content-sha256;x-amz-date";
+    let canonical_request = format!(
+        "PUT\n{}\n\ncontent-type:{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+        url.path(),
+        content_type,
+        host,
+        content_sha256,
+        amz_date,
+        signed_headers,
+        content_sha256,
+    );
+    let credential_scope = format!("{}/{}/s3/aws4_request", date_stamp, settings.region);
+    let string_to_sign = format!(
+        "{}\n{}\n{}\n{}",
+        ALGORITHM,
+        amz_date,
+        credential_scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes())),
+    );
+    let signing_key = signing_key(
+        &settings.secret_access_key,
+        &date_stamp,
+        &settings.region,
+        "s3",
+    );
+    let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+    let authorization = format!(
+        "{} Credential={}/{}, SignedHeaders={}, Signature={}",
+        ALGORITHM, settings.access_key_id, credential_scope, signed_headers, signature
+    );
+    SignedHeaders {
+        amz_date,
+        content_sha256,
+        authorization,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ImageType;
+
+    #[test]
+    fn images_are_recognized_by_their_first_bytes() {
+        assert_eq!(
+            ImageType::sniff(b"\x89PNG\r\n\x1a\n...."),
+            Some(ImageType::Png)
+        );
+        assert_eq!(
+            ImageType::sniff(b"\xff\xd8\xff\xe0...."),
+            Some(ImageType::Jpeg)
+        );
+        assert_eq!(ImageType::sniff(b"GIF89a...."), Some(ImageType::Gif));
+        assert_eq!(
+            ImageType::sniff(b"RIFF\0\0\0\0WEBPVP8 "),
+            Some(ImageType::Webp)
+        );
+    }
+
+    #[test]
+    fn documents_are_not_images() {
+        for content in [
+            &b"<svg xmlns=\"http://www.w3.org/2000/svg\"><script>steal()</script></svg>"[..],
+            b"<html><body>Hello</body></html>",
+            b"",
+        ] {
+            assert_eq!(ImageType::sniff(content), None);
+        }
+    }
+}
@@ -0,0 +1,622 @@
+use std::collections::HashSet;
+
+use crate::configuration::{EmailFooterSettings, HtmlSanitizationSettings, UtmSettings};
+use crate::css_inlining::inline_css;
+use crate::routing_helpers::html_escape;
+use crate::url_builder::UrlBuilder;
+
+/// Placeholder that authors can put in the content of an issue to control where the
+/// "view in browser" link ends up. If it is missing, the link is added at the top of the email.
+pub const VIEW_IN_BROWSER_PLACEHOLDER: &str = "{{view_in_browser_url}}";
+
+/// Per-recipient values for the `{{ name }}`, `{{ email }}` and `{{ unsubscribe_url }}`
+/// placeholders, expanded by the delivery worker right before an issue is sent
+pub struct Personalization<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub unsubscribe_url: &'a str,
+}
+
+impl Personalization<'_> {
+    fn get(&self, placeholder: &str) -> Option<&str> {
+        match placeholder {
+            "name" => Some(self.name),
+            "email" => Some(self.email),
+            "unsubscribe_url" => Some(self.unsubscribe_url),
+            _ => None,
+        }
+    }
+}
+
+/// Expands the personalization placeholders in the HTML content of an issue, escaping the values
+pub fn personalize_html(html_content: &str, personalization: &Personalization) -> String {
+    expand_placeholders(html_content, |placeholder| {
+        personalization.get(placeholder).map(html_escape)
+    })
+}
+
+/// Expands the personalization placeholders in the plain text content of an issue
+pub fn personalize_text(text_content: &str, personalization: &Personalization) -> String {
+    expand_placeholders(text_content, |placeholder| {
+        personalization.get(placeholder).map(str::to_string)
+    })
+}
+
+/// Replaces every `{{ placeholder }}` known to `lookup`. Anything else is left untouched, since
+/// issues are written by hand and may legitimately contain braces.
+pub(crate) fn expand_placeholders(
+    content: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> String {
+    let mut expanded = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}").map(|end| start + end) else {
+            break;
+        };
+        match lookup(rest[start + 2..end].trim()) {
+            Some(value) => {
+                expanded.push_str(&rest[..start]);
+                expanded.push_str(&value);
+            }
+            None => expanded.push_str(&rest[..end + 2]),
+        }
+        rest = &rest[end + 2..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// The link added at the top of the HTML content of issues without a placeholder
+fn view_in_browser_header_html(issue_url: &str) -> String {
+    format!(
+        "<p><a href=\"{}\">View this issue in your browser</a></p>\n",
+        issue_url
+    )
+}
+
+/// The link added at the top of the plain text content of issues without a placeholder
+fn view_in_browser_header_text(issue_url: &str) -> String {
+    format!("View this issue in your browser: {}\n\n", issue_url)
+}
+
+/// Injects a link to the hosted version of the issue in its HTML content
+pub fn inject_view_in_browser_link_html(html_content: &str, issue_url: &str) -> String {
+    if html_content.contains(VIEW_IN_BROWSER_PLACEHOLDER) {
+        html_content.replace(VIEW_IN_BROWSER_PLACEHOLDER, issue_url)
+    } else {
+        view_in_browser_header_html(issue_url) + html_content
+    }
+}
+
+/// Injects a link to the hosted version of the issue in its plain text content
+pub fn inject_view_in_browser_link_text(text_content: &str, issue_url: &str) -> String {
+    if text_content.contains(VIEW_IN_BROWSER_PLACEHOLDER) {
+        text_content.replace(VIEW_IN_BROWSER_PLACEHOLDER, issue_url)
+    } else {
+        view_in_browser_header_text(issue_url) + text_content
+    }
+}
+
+/// Reverts `inject_view_in_browser_link_html`, so that the content of a published issue can be
+/// reused for another one
+pub fn remove_view_in_browser_link_html(html_content: &str, issue_url: &str) -> String {
+    match html_content.strip_prefix(&view_in_browser_header_html(issue_url)) {
+        Some(content) => content.to_string(),
+        None => html_content.replace(issue_url, VIEW_IN_BROWSER_PLACEHOLDER),
+    }
+}
+
+/// Reverts `inject_view_in_browser_link_text`, so that the content of a published issue can be
+/// reused for another one
+pub fn remove_view_in_browser_link_text(text_content: &str, issue_url: &str) -> String {
+    match text_content.strip_prefix(&view_in_browser_header_text(issue_url)) {
+        Some(content) => content.to_string(),
+        None => text_content.replace(issue_url, VIEW_IN_BROWSER_PLACEHOLDER),
+    }
+}
+
+/// Returns the targets of the links that click tracking applies to, in the order they appear in
+/// the HTML content; a link's position in the list is its `link_id`
+pub fn trackable_links(html_content: &str) -> Vec<String> {
+    let mut links = Vec::new();
+    rewrite_trackable_links(html_content, |_, url| {
+        links.push(url.to_string());
+        None
+    });
+    links
+}
+
+/// Points the trackable links of the HTML content of an issue to the click tracking redirect of
+/// a delivery
+pub fn track_link_clicks(html_content: &str, urls: &UrlBuilder, delivery_id: uuid::Uuid) -> String {
+    rewrite_trackable_links(html_content, |link_id, _| {
+        Some(urls.click_tracking_url(delivery_id, link_id))
+    })
+}
+
+/// The UTM parameters of the links of an issue, which website analytics attribute visits by
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UtmParameters {
+    pub source: String,
+    pub medium: String,
+    pub campaign: String,
+}
+
+impl UtmParameters {
+    /// The parameters given for an issue, falling back to the defaults of `settings`, or to
+    /// `newsletter` / `email` and the title of the issue. Returns `None` if nothing is given and
+    /// there are no defaults, so that links are left alone.
+    pub fn for_issue(
+        settings: Option<&UtmSettings>,
+        title: &str,
+        source: &str,
+        medium: &str,
+        campaign: &str,
+    ) -> Option<Self> {
+        fn given(value: &str) -> Option<String> {
+            let value = value.trim();
+            (!value.is_empty()).then(|| value.to_owned())
+        }
+        let (source, medium, campaign) = (given(source), given(medium), given(campaign));
+        if settings.is_none() && source.is_none() && medium.is_none() && campaign.is_none() {
+            return None;
+        }
+        Some(Self {
+            source: source
+                .or_else(|| settings.map(|s| s.source.clone()))
+                .unwrap_or_else(|| "newsletter".into()),
+            medium: medium
+                .or_else(|| settings.map(|s| s.medium.clone()))
+                .unwrap_or_else(|| "email".into()),
+            campaign: campaign
+                .or_else(|| settings.and_then(|s| s.campaign.clone()))
+                .unwrap_or_else(|| campaign_name(title)),
+        })
+    }
+}
+
+/// Lowercases the title and joins its words with dashes, e.g. "Issue #3: Hello!" -> "issue-3-hello"
+fn campaign_name(title: &str) -> String {
+    title
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Adds the UTM parameters to the http(s) links of the HTML content of an issue, replacing the
+/// ones they already have, e.g. from the issue it was duplicated from
+pub fn add_utm_parameters(html_content: &str, utm: &UtmParameters) -> String {
+    rewrite_trackable_links(html_content, |_, url| {
+        let mut url = reqwest::Url::parse(url).ok()?;
+        let query: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(name, _)| {
+                !matches!(name.as_ref(), "utm_source" | "utm_medium" | "utm_campaign")
+            })
+            .map(|(name, value)| (name.into_owned(), value.into_owned()))
+            .collect();
+        url.query_pairs_mut()
+            .clear()
+            .extend_pairs(query)
+            .append_pair("utm_source", &utm.source)
+            .append_pair("utm_medium", &utm.medium)
+            .append_pair("utm_campaign", &utm.campaign);
+        Some(url.into())
+    })
+}
+
+/// Keeps the tags allowed by `settings` and the attributes they need, so that the HTML content
+/// of an issue cannot run scripts in the browsers of the readers of the archive. Links are kept
+/// as written, placeholders such as `{{ unsubscribe_url }}` included, and so are `style`
+/// attributes, which CSS inlining relies on.
+pub fn sanitize_html(html_content: &str, settings: &HtmlSanitizationSettings) -> String {
+    let mut builder = ammonia::Builder::default();
+    builder.link_rel(None).add_generic_attributes(["style"]);
+    if !settings.allowed_tags.is_empty() {
+        let tags: HashSet<&str> = settings
+            .allowed_tags
+            .iter()
+            .map(String::as_str)
+            .filter(|tag| *tag != "script")
+            .collect();
+        if tags.contains("style") {
+            builder.rm_clean_content_tags(["style"]);
+        }
+        builder.tags(tags);
+    }
+    builder.clean(html_content).to_string()
+}
+
+/// Prepares the HTML content submitted by an editor to be stored: the rules of its `<style>`
+/// blocks are inlined unless `skip_css_inlining`, then it is sanitized
+pub fn prepare_html_content(
+    html_content: &str,
+    skip_css_inlining: bool,
+    settings: &HtmlSanitizationSettings,
+) -> String {
+    if skip_css_inlining {
+        return sanitize_html(html_content, settings);
+    }
+    sanitize_html(&inline_css(html_content), settings)
+}
+
+/// Appends an invisible image loading the open tracking pixel to the HTML content of an issue,
+/// right before `</body>` if the content has one
+pub fn inject_open_tracking_pixel(html_content: &str, pixel_url: &str) -> String {
+    let pixel = format!(
+        r#"<img src="{}" width="1" height="1" alt="" style="display:none">"#,
+        html_escape(pixel_url)
+    );
+    append_to_body(html_content, &pixel)
+}
+
+/// Appends the compliance footer to the HTML content of an email
+pub fn append_footer_html(html_content: &str, footer: &EmailFooterSettings) -> String {
+    let mut markup = String::from(r#"<div class="email-footer">"#);
+    if let Some(legal_text) = &footer.legal_text {
+        markup.push_str(&format!("<p>{}</p>", html_escape(legal_text)));
+    }
+    let address_lines: Vec<String> = footer.postal_address.lines().map(html_escape).collect();
+    markup.push_str(&format!("<p>{}</p></div>", address_lines.join("<br>")));
+    append_to_body(html_content, &markup)
+}
+
+/// Appends the compliance footer to the plain text content of an email
+pub fn append_footer_text(text_content: &str, footer: &EmailFooterSettings) -> String {
+    let mut footer_text = String::from("\n\n--\n");
+    if let Some(legal_text) = &footer.legal_text {
+        footer_text.push_str(legal_text);
+        footer_text.push('\n');
+    }
+    footer_text.push_str(&footer.postal_address);
+    text_content.trim_end().to_string() + &footer_text
+}
+
+/// Adds `markup` at the end of the body of the document, or of the fragment if it has no body
+fn append_to_body(html_content: &str, markup: &str) -> String {
+    match html_content.to_ascii_lowercase().rfind("</body>") {
+        Some(end) => format!("{}{}{}", &html_content[..end], markup, &html_content[end..]),
+        None => format!("{}\n{}", html_content, markup),
+    }
+}
+
+/// Calls `rewrite` with the position and target of every quoted `href` pointing to an http(s)
+/// URL, and replaces the target with what it returns. Other links, e.g. `mailto:` links or the
+/// `{{ unsubscribe_url }}` placeholder, are left alone.
+fn rewrite_trackable_links(
+    html_content: &str,
+    mut rewrite: impl FnMut(usize, &str) -> Option<String>,
+) -> String {
+    // ASCII lowercasing keeps byte offsets, so positions found in `lowercase` apply to the content
+    let lowercase = html_content.to_ascii_lowercase();
+    let mut rewritten = String::with_capacity(html_content.len());
+    let mut position = 0;
+    let mut link_id = 0;
+    while let Some(offset) = lowercase[position..].find("href") {
+        let after_name = position + offset + "href".len();
+        let Some(rest) = html_content[after_name..].trim_start().strip_prefix('=') else {
+            rewritten.push_str(&html_content[position..after_name]);
+            position = after_name;
+            continue;
+        };
+        // the value starts with its opening quote
+        let value_start = html_content.len() - rest.trim_start().len();
+        let quote = html_content[value_start..].chars().next();
+        let value_end = match quote {
+            Some(quote @ ('"' | '\'')) => html_content[value_start + 1..]
+                .find(quote)
+                .map(|end| value_start + 1 + end),
+            _ => None,
+        };
+        let Some(value_end) = value_end else {
+            rewritten.push_str(&html_content[position..after_name]);
+            position = after_name;
+            continue;
+        };
+        let url = html_unescape(&html_content[value_start + 1..value_end]);
+        let scheme = url.to_ascii_lowercase();
+        if !(scheme.starts_with("http://") || scheme.starts_with("https://")) {
+            rewritten.push_str(&html_content[position..value_end]);
+            position = value_end;
+            continue;
+        }
+        rewritten.push_str(&html_content[position..value_start + 1]);
+        match rewrite(link_id, &url) {
+            Some(replacement) => rewritten.push_str(&html_escape(&replacement)),
+            None => rewritten.push_str(&html_content[value_start + 1..value_end]),
+        }
+        link_id += 1;
+        position = value_end;
+    }
+    rewritten.push_str(&html_content[position..]);
+    rewritten
+}
+
+/// Decodes the character references `html_escape` produces, e.g. the `&amp;` separating the
+/// query parameters of a link
+fn html_unescape(s: &str) -> String {
+    s.replace("&quot;", "\"")
+        .replace("&#x27;", "'")
+        .replace("&#39;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        add_utm_parameters, append_footer_html, append_footer_text, inject_open_tracking_pixel,
+        inject_view_in_browser_link_html, inject_view_in_browser_link_text, personalize_html,
+        personalize_text, prepare_html_content, remove_view_in_browser_link_html,
+        remove_view_in_browser_link_text, sanitize_html, track_link_clicks, trackable_links,
+        Personalization, UtmParameters,
+    };
+    use crate::configuration::{EmailFooterSettings, HtmlSanitizationSettings, UtmSettings};
+    use crate::url_builder::UrlBuilder;
+
+    const URL: &str = "https://example.com/issues/1";
+
+    #[test]
+    fn link_is_prepended_when_there_is_no_placeholder() {
+        let html = inject_view_in_browser_link_html("<p>Hello</p>", URL);
+        assert_eq!(
+            html,
+            "<p><a href=\"https://example.com/issues/1\">View this issue in your browser</a></p>\n<p>Hello</p>"
+        );
+        let text = inject_view_in_browser_link_text("Hello", URL);
+        assert_eq!(
+            text,
+            "View this issue in your browser: https://example.com/issues/1\n\nHello"
+        );
+    }
+
+    #[test]
+    fn placeholders_are_replaced_with_the_issue_url() {
+        let html = inject_view_in_browser_link_html(
+            r#"<p>Hello</p><a href="{{view_in_browser_url}}">Online</a>"#,
+            URL,
+        );
+        assert_eq!(
+            html,
+            r#"<p>Hello</p><a href="https://example.com/issues/1">Online</a>"#
+        );
+        let text = inject_view_in_browser_link_text("Hello\nOnline: {{view_in_browser_url}}", URL);
+        assert_eq!(text, "Hello\nOnline: https://example.com/issues/1");
+    }
+
+    #[test]
+    fn removing_the_link_restores_the_original_content() {
+        for html in [
+            "<p>Hello</p>",
+            r#"<p>Hello</p><a href="{{view_in_browser_url}}">Online</a>"#,
+        ] {
+            let injected = inject_view_in_browser_link_html(html, URL);
+            assert_eq!(remove_view_in_browser_link_html(&injected, URL), html);
+        }
+        for text in ["Hello", "Hello\nOnline: {{view_in_browser_url}}"] {
+            let injected = inject_view_in_browser_link_text(text, URL);
+            assert_eq!(remove_view_in_browser_link_text(&injected, URL), text);
+        }
+    }
+
+    fn personalization() -> Personalization<'static> {
+        Personalization {
+            name: "Tom & Jerry",
+            email: "tom@example.com",
+            unsubscribe_url: "https://example.com/unsubscribe?token=abc",
+        }
+    }
+
+    #[test]
+    fn personalization_placeholders_are_expanded() {
+        let text = personalize_text(
+            "Hi {{ name }} ({{email}})! Unsubscribe: {{ unsubscribe_url }}",
+            &personalization(),
+        );
+        assert_eq!(
+            text,
+            "Hi Tom & Jerry (tom@example.com)! Unsubscribe: https://example.com/unsubscribe?token=abc"
+        );
+    }
+
+    #[test]
+    fn personalization_values_are_escaped_in_html() {
+        let html = personalize_html("<p>Hi {{ name }}</p>", &personalization());
+        assert_eq!(html, "<p>Hi Tom &amp; Jerry</p>");
+    }
+
+    #[test]
+    fn unknown_and_unclosed_placeholders_are_left_untouched() {
+        let content = "{{ unknown }} and {{ name";
+        assert_eq!(personalize_text(content, &personalization()), content);
+    }
+
+    const HTML_WITH_LINKS: &str = r#"<a href="https://example.com/a?x=1&amp;y=2">A</a>
+<a href='mailto:tom@example.com'>Mail</a>
+<a HREF = "{{ unsubscribe_url }}">Unsubscribe</a>
+<a class="b" href="http://example.com/b">B</a>
+<p>No href here</p>"#;
+
+    #[test]
+    fn only_http_links_are_trackable() {
+        assert_eq!(
+            trackable_links(HTML_WITH_LINKS),
+            vec!["https://example.com/a?x=1&y=2", "http://example.com/b"]
+        );
+    }
+
+    #[test]
+    fn trackable_links_are_rewritten_to_the_click_tracking_redirect() {
+        let delivery_id = uuid::Uuid::nil();
+        let html = track_link_clicks(
+            HTML_WITH_LINKS,
+            &UrlBuilder::new("https://newsletter.com"),
+            delivery_id,
+        );
+        assert_eq!(
+            html,
+            r#"<a href="https://newsletter.com/t/click/00000000-0000-0000-0000-000000000000/0">A</a>
+<a href='mailto:tom@example.com'>Mail</a>
+<a HREF = "{{ unsubscribe_url }}">Unsubscribe</a>
+<a class="b" href="https://newsletter.com/t/click/00000000-0000-0000-0000-000000000000/1">B</a>
+<p>No href here</p>"#
+        );
+    }
+
+    #[test]
+    fn content_without_links_is_left_untouched() {
+        let html = "<p>hrefs are mentioned, href = not quoted</p>";
+        assert_eq!(
+            track_link_clicks(
+                html,
+                &UrlBuilder::new("https://newsletter.com"),
+                uuid::Uuid::nil()
+            ),
+            html
+        );
+        assert!(trackable_links(html).is_empty());
+    }
+
+    #[test]
+    fn the_open_tracking_pixel_goes_at_the_end_of_the_body() {
+        let pixel_url = "https://newsletter.com/t/open/1";
+        assert_eq!(
+            inject_open_tracking_pixel("<html><BODY><p>Hi</p></BODY></html>", pixel_url),
+            r#"<html><BODY><p>Hi</p><img src="https://newsletter.com/t/open/1" width="1" height="1" alt="" style="display:none"></BODY></html>"#
+        );
+        assert_eq!(
+            inject_open_tracking_pixel("<p>Hi</p>", pixel_url),
+            "<p>Hi</p>\n<img src=\"https://newsletter.com/t/open/1\" width=\"1\" height=\"1\" alt=\"\" style=\"display:none\">"
+        );
+    }
+
+    fn footer() -> EmailFooterSettings {
+        EmailFooterSettings {
+            postal_address: "Ursula & Co\n1 Main Street\nPortland, OR 97201".into(),
+            legal_text: Some("You get this email because you subscribed.".into()),
+        }
+    }
+
+    #[test]
+    fn the_footer_goes_at_the_end_of_the_body() {
+        assert_eq!(
+            append_footer_html("<html><body><p>Hi</p></body></html>", &footer()),
+            "<html><body><p>Hi</p><div class=\"email-footer\"><p>You get this email because you subscribed.</p><p>Ursula &amp; Co<br>1 Main Street<br>Portland, OR 97201</p></div></body></html>"
+        );
+        assert_eq!(
+            append_footer_text("Hi\n", &footer()),
+            "Hi\n\n--\nYou get this email because you subscribed.\nUrsula & Co\n1 Main Street\nPortland, OR 97201"
+        );
+    }
+
+    fn utm() -> UtmParameters {
+        UtmParameters {
+            source: "newsletter".into(),
+            medium: "email".into(),
+            campaign: "issue-3".into(),
+        }
+    }
+
+    #[test]
+    fn utm_parameters_are_added_to_http_links_only() {
+        let html = add_utm_parameters(
+            r#"<a href="https://example.com/post?id=1#top">Post</a> <a href="mailto:me@example.com">Me</a>"#,
+            &utm(),
+        );
+        assert_eq!(
+            html,
+            r#"<a href="https://example.com/post?id=1&amp;utm_source=newsletter&amp;utm_medium=email&amp;utm_campaign=issue-3#top">Post</a> <a href="mailto:me@example.com">Me</a>"#
+        );
+    }
+
+    #[test]
+    fn utm_parameters_replace_the_ones_links_already_have() {
+        let html = add_utm_parameters(
+            r#"<a href="https://example.com/?utm_campaign=issue-2&amp;utm_source=newsletter&amp;utm_medium=email">Home</a>"#,
+            &utm(),
+        );
+        assert_eq!(
+            trackable_links(&html),
+            vec![
+                "https://example.com/?utm_source=newsletter&utm_medium=email&utm_campaign=issue-3"
+            ]
+        );
+    }
+
+    #[test]
+    fn utm_parameters_fall_back_to_the_settings_then_to_the_title() {
+        let settings = UtmSettings {
+            source: "weekly".into(),
+            medium: "mail".into(),
+            campaign: None,
+        };
+        assert_eq!(
+            UtmParameters::for_issue(Some(&settings), "Issue #3: Hello!", "", " ", "spring"),
+            Some(UtmParameters {
+                source: "weekly".into(),
+                medium: "mail".into(),
+                campaign: "spring".into(),
+            })
+        );
+        assert_eq!(
+            UtmParameters::for_issue(None, "Issue #3: Hello!", "", "", "").map(|utm| utm.campaign),
+            None
+        );
+        assert_eq!(
+            UtmParameters::for_issue(None, "Issue #3: Hello!", "blog", "", "")
+                .map(|utm| utm.campaign),
+            Some("issue-3-hello".to_string())
+        );
+    }
+
+    #[test]
+    fn scripts_and_event_handlers_are_removed() {
+        let html = sanitize_html(
+            r#"<p onclick="steal()">Hello<script>steal()</script></p><a href="javascript:steal()">x</a>"#,
+            &HtmlSanitizationSettings::default(),
+        );
+        assert_eq!(html, "<p>Hello</p><a>x</a>");
+    }
+
+    #[test]
+    fn links_and_placeholders_are_kept_as_written() {
+        let html = r#"<p>Hi {{ name }}, <a href="https://example.com/a?b=1&amp;c=2">read</a> or <a href="{{ unsubscribe_url }}">unsubscribe</a></p>"#;
+        assert_eq!(
+            sanitize_html(html, &HtmlSanitizationSettings::default()),
+            html
+        );
+    }
+
+    #[test]
+    fn only_the_allowed_tags_are_kept() {
+        let settings = HtmlSanitizationSettings {
+            allowed_tags: vec!["p".into(), "script".into()],
+        };
+        let html = sanitize_html("<p><b>Bold</b> text</p><script>steal()</script>", &settings);
+        assert_eq!(html, "<p>Bold text</p>");
+    }
+
+    #[test]
+    fn inlined_styles_survive_sanitization() {
+        let html = "<style>p { color: red }</style><p>Hello</p>";
+        let settings = HtmlSanitizationSettings::default();
+        assert_eq!(
+            prepare_html_content(html, false, &settings),
+            r#"<p style="color: red">Hello</p>"#
+        );
+        assert_eq!(prepare_html_content(html, true, &settings), "<p>Hello</p>");
+    }
+
+    #[test]
+    fn style_blocks_are_kept_when_allowed() {
+        let settings = HtmlSanitizationSettings {
+            allowed_tags: vec!["style".into(), "p".into()],
+        };
+        let html = "<style>p { color: red }</style><p>Hello</p>";
+        assert_eq!(prepare_html_content(html, true, &settings), html);
+    }
+}
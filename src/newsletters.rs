@@ -0,0 +1,99 @@
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+use crate::templates::SelectOption;
+
+/// One of the newsletters, i.e. lists, run by the deployment. Every subscription and issue
+/// belongs to exactly one of them.
+pub struct Newsletter {
+    pub newsletter_id: Uuid,
+    pub slug: String,
+    pub name: String,
+}
+
+/// Finds the newsletter with the given slug. An empty slug designates the default newsletter,
+/// the first one created, so that forms and clients unaware of lists keep working.
+#[tracing::instrument(name = "Find a newsletter", skip(executor))]
+pub async fn find_newsletter(
+    executor: impl PgExecutor<'_>,
+    slug: &str,
+) -> Result<Option<Newsletter>, sqlx::Error> {
+    sqlx::query_as!(
+        Newsletter,
+        r#"
+        SELECT newsletter_id, slug, name
+        FROM newsletters
+        WHERE $1 = '' OR slug = $1
+        ORDER BY created_at
+        LIMIT 1
+        "#,
+        slug
+    )
+    .fetch_optional(executor)
+    .await
+}
+
+/// Every newsletter, the default one first
+#[tracing::instrument(name = "Get newsletters", skip_all)]
+pub async fn get_newsletters(pool: &PgPool) -> Result<Vec<Newsletter>, sqlx::Error> {
+    sqlx::query_as!(
+        Newsletter,
+        "SELECT newsletter_id, slug, name FROM newsletters ORDER BY created_at"
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// The options of a select field picking a newsletter by slug; the default newsletter is
+/// selected if `selected` is empty
+pub fn newsletter_options(newsletters: &[Newsletter], selected: &str) -> Vec<SelectOption> {
+    newsletters
+        .iter()
+        .enumerate()
+        .map(|(i, newsletter)| SelectOption {
+            value: newsletter.slug.clone(),
+            label: newsletter.name.clone(),
+            selected: newsletter.slug == selected || (selected.is_empty() && i == 0),
+        })
+        .collect()
+}
+
+/// The `List-Id` header of the emails of a newsletter, as defined by RFC 2919, which lets mail
+/// clients filter them, e.g. `Rust Weekly <rust-weekly.example.com>`
+pub fn list_id(name: &str, slug: &str, base_url: &str) -> String {
+    let host = reqwest::Url::parse(base_url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_owned))
+        .unwrap_or_else(|| "localhost".to_owned());
+    // the name must neither end the header nor the angle brackets of the identifier early
+    let name: String = name
+        .chars()
+        .filter(|c| !c.is_control() && !matches!(c, '<' | '>'))
+        .collect();
+    format!("{} <{}.{}>", name.trim(), slug, host)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::list_id;
+
+    #[test]
+    fn list_ids_are_scoped_to_the_host_of_the_deployment() {
+        assert_eq!(
+            list_id(
+                "Rust Weekly",
+                "rust-weekly",
+                "https://news.example.com:8443"
+            ),
+            "Rust Weekly <rust-weekly.news.example.com>"
+        );
+    }
+
+    #[test]
+    fn list_ids_cannot_be_broken_by_the_name() {
+        assert_eq!(
+            list_id("Evil\r\nBcc: <x@y.z>", "evil", "http://127.0.0.1"),
+            "EvilBcc: x@y.z <evil.127.0.0.1>"
+        );
+    }
+}
@@ -0,0 +1,488 @@
+//! A minimal QR code encoder, used to show TOTP secrets to authenticator apps.
+//!
+//! Only what the application needs is supported: byte mode, error correction level M and versions
+//! 1 to 10, which hold up to 213 bytes. The encoder follows ISO/IEC 18004: data is split in
+//! Reed-Solomon protected blocks, placed around the function patterns and masked with the pattern
+//! that scores the lowest penalty.
+
+use std::fmt::Write;
+
+/// Error correction blocks of a version at level M: `(ec codewords per block, [(number of
+/// blocks, data codewords per block)])`
+const BLOCKS: [(usize, &[(usize, usize)]); 10] = [
+    (10, &[(1, 16)]),
+    (16, &[(1, 28)]),
+    (26, &[(1, 44)]),
+    (18, &[(2, 32)]),
+    (24, &[(2, 43)]),
+    (16, &[(4, 27)]),
+    (18, &[(4, 31)]),
+    (22, &[(2, 38), (2, 39)]),
+    (22, &[(3, 36), (2, 37)]),
+    (26, &[(4, 43), (1, 44)]),
+];
+
+/// Centers of the alignment patterns of each version, along both axes
+const ALIGNMENT_POSITIONS: [&[usize]; 10] = [
+    &[],
+    &[6, 18],
+    &[6, 22],
+    &[6, 26],
+    &[6, 30],
+    &[6, 34],
+    &[6, 22, 38],
+    &[6, 24, 42],
+    &[6, 26, 46],
+    &[6, 28, 50],
+];
+
+/// Format bits identifying error correction level M
+const LEVEL_M_BITS: u32 = 0b00;
+
+#[derive(thiserror::Error, Debug)]
+#[error("The data is too long to fit in a QR code")]
+pub struct DataTooLong;
+
+/// A square grid of modules, `true` being dark
+#[derive(Debug)]
+pub struct QrCode {
+    size: usize,
+    modules: Vec<Vec<bool>>,
+    is_function: Vec<Vec<bool>>,
+}
+
+impl QrCode {
+    /// Encodes `data` in the smallest version it fits in
+    pub fn encode(data: &[u8]) -> Result<Self, DataTooLong> {
+        let version = (1..=BLOCKS.len())
+            .find(|&version| data.len() <= byte_capacity(version))
+            .ok_or(DataTooLong)?;
+        let codewords = add_error_correction(version, &data_codewords(version, data));
+
+        let mut qr_code = Self::with_function_patterns(version);
+        qr_code.draw_codewords(&codewords);
+        let mask = (0..8)
+            .min_by_key(|&mask| {
+                qr_code.apply_mask(mask);
+                qr_code.draw_format_bits(mask);
+                let penalty = qr_code.penalty();
+                // masks are their own inverse
+                qr_code.apply_mask(mask);
+                penalty
+            })
+            .unwrap();
+        qr_code.apply_mask(mask);
+        qr_code.draw_format_bits(mask);
+        Ok(qr_code)
+    }
+
+    /// Renders the code as an SVG image, including the quiet zone around it
+    pub fn to_svg(&self, module_size: usize) -> String {
+        let quiet_zone = 4;
+        let dimension = self.size + 2 * quiet_zone;
+        let mut path = String::new();
+        for (y, row) in self.modules.iter().enumerate() {
+            for (x, &dark) in row.iter().enumerate() {
+                if dark {
+                    write!(path, "M{},{}h1v1h-1z", x + quiet_zone, y + quiet_zone).unwrap();
+                }
+            }
+        }
+        format!(
+            r##"<svg xmlns="http://www.w3.org/2000/svg" width="{size}" height="{size}" viewBox="0 0 {dimension} {dimension}" shape-rendering="crispEdges"><rect width="100%" height="100%" fill="#ffffff"/><path d="{path}" fill="#000000"/></svg>"##,
+            size = dimension * module_size,
+        )
+    }
+
+    fn with_function_patterns(version: usize) -> Self {
+        let size = 17 + 4 * version;
+        let mut qr_code = Self {
+            size,
+            modules: vec![vec![false; size]; size],
+            is_function: vec![vec![false; size]; size],
+        };
+        for i in 0..size {
+            qr_code.set_function_module(6, i, i % 2 == 0);
+            qr_code.set_function_module(i, 6, i % 2 == 0);
+        }
+        qr_code.draw_finder_pattern(3, 3);
+        qr_code.draw_finder_pattern(size - 4, 3);
+        qr_code.draw_finder_pattern(3, size - 4);
+        let positions = ALIGNMENT_POSITIONS[version - 1];
+        let last = positions.len().saturating_sub(1);
+        for (i, &x) in positions.iter().enumerate() {
+            for (j, &y) in positions.iter().enumerate() {
+                // the corners with finder patterns have no alignment pattern
+                let is_finder_corner =
+                    (i, j) == (0, 0) || (i, j) == (0, last) || (i, j) == (last, 0);
+                if !is_finder_corner {
+                    qr_code.draw_alignment_pattern(x, y);
+                }
+            }
+        }
+        // reserves the format areas; the actual bits depend on the mask
+        qr_code.draw_format_bits(0);
+        qr_code.draw_version(version);
+        qr_code
+    }
+
+    fn set_function_module(&mut self, x: usize, y: usize, dark: bool) {
+        self.modules[y][x] = dark;
+        self.is_function[y][x] = true;
+    }
+
+    fn draw_finder_pattern(&mut self, x: usize, y: usize) {
+        for dy in -4_i32..=4 {
+            for dx in -4_i32..=4 {
+                let (xx, yy) = (x as i32 + dx, y as i32 + dy);
+                if (0..self.size as i32).contains(&xx) && (0..self.size as i32).contains(&yy) {
+                    let distance = dx.abs().max(dy.abs());
+                    self.set_function_module(
+                        xx as usize,
+                        yy as usize,
+                        distance != 2 && distance != 4,
+                    );
+                }
+            }
+        }
+    }
+
+    fn draw_alignment_pattern(&mut self, x: usize, y: usize) {
+        for dy in -2_i32..=2 {
+            for dx in -2_i32..=2 {
+                self.set_function_module(
+                    (x as i32 + dx) as usize,
+                    (y as i32 + dy) as usize,
+                    dx.abs().max(dy.abs()) != 1,
+                );
+            }
+        }
+    }
+
+    fn draw_format_bits(&mut self, mask: u32) {
+        let bits = format_bits(LEVEL_M_BITS, mask);
+        let bit = |i: usize| (bits >> i) & 1 != 0;
+        let size = self.size;
+        // around the top left finder pattern
+        for i in 0..6 {
+            self.set_function_module(8, i, bit(i));
+        }
+        self.set_function_module(8, 7, bit(6));
+        self.set_function_module(8, 8, bit(7));
+        self.set_function_module(7, 8, bit(8));
+        for i in 9..15 {
+            self.set_function_module(14 - i, 8, bit(i));
+        }
+        // split between the two other finder patterns
+        for i in 0..8 {
+            self.set_function_module(size - 1 - i, 8, bit(i));
+        }
+        for i in 8..15 {
+            self.set_function_module(8, size - 15 + i, bit(i));
+        }
+        self.set_function_module(8, size - 8, true);
+    }
+
+    fn draw_version(&mut self, version: usize) {
+        if version < 7 {
+            return;
+        }
+        let bits = version_bits(version as u32);
+        for i in 0..18 {
+            let dark = (bits >> i) & 1 != 0;
+            let a = self.size - 11 + i % 3;
+            let b = i / 3;
+            self.set_function_module(a, b, dark);
+            self.set_function_module(b, a, dark);
+        }
+    }
+
+    /// Places the codewords in the zigzag order, two columns at a time from the bottom right
+    fn draw_codewords(&mut self, codewords: &[u8]) {
+        let mut i = 0;
+        let mut right = self.size - 1;
+        while right >= 1 {
+            // the vertical timing pattern is skipped entirely
+            if right == 6 {
+                right = 5;
+            }
+            for vertical in 0..self.size {
+                for j in 0..2 {
+                    let x = right - j;
+                    let upward = (right + 1) & 2 == 0;
+                    let y = if upward {
+                        self.size - 1 - vertical
+                    } else {
+                        vertical
+                    };
+                    if !self.is_function[y][x] && i < codewords.len() * 8 {
+                        self.modules[y][x] = (codewords[i >> 3] >> (7 - (i & 7))) & 1 != 0;
+                        i += 1;
+                    }
+                }
+            }
+            if right < 2 {
+                break;
+            }
+            right -= 2;
+        }
+    }
+
+    fn apply_mask(&mut self, mask: u32) {
+        for y in 0..self.size {
+            for x in 0..self.size {
+                let invert = match mask {
+                    0 => (x + y) % 2 == 0,
+                    1 => y % 2 == 0,
+                    2 => x % 3 == 0,
+                    3 => (x + y) % 3 == 0,
+                    4 => (x / 3 + y / 2) % 2 == 0,
+                    5 => x * y % 2 + x * y % 3 == 0,
+                    6 => (x * y % 2 + x * y % 3) % 2 == 0,
+                    7 => ((x + y) % 2 + x * y % 3) % 2 == 0,
+                    _ => unreachable!("There are 8 masks"),
+                };
+                if invert && !self.is_function[y][x] {
+                    self.modules[y][x] = !self.modules[y][x];
+                }
+            }
+        }
+    }
+
+    /// Scores how hard the code is to scan; lower is better
+    fn penalty(&self) -> usize {
+        let size = self.size;
+        let get = |x: usize, y: usize, transpose: bool| {
+            if transpose {
+                self.modules[x][y]
+            } else {
+                self.modules[y][x]
+            }
+        };
+        let mut penalty = 0;
+        for transpose in [false, true] {
+            for y in 0..size {
+                // runs of five or more modules of the same color
+                let mut run = 1;
+                for x in 1..size {
+                    if get(x, y, transpose) == get(x - 1, y, transpose) {
+                        run += 1;
+                    } else {
+                        if run >= 5 {
+                            penalty += run - 2;
+                        }
+                        run = 1;
+                    }
+                }
+                if run >= 5 {
+                    penalty += run - 2;
+                }
+                // patterns looking like a finder pattern
+                for x in 0..size.saturating_sub(10) {
+                    let window: Vec<bool> = (x..x + 11).map(|x| get(x, y, transpose)).collect();
+                    let finder = [true, false, true, true, true, false, true];
+                    if (window[..7] == finder && window[7..].iter().all(|&dark| !dark))
+                        || (window[..4].iter().all(|&dark| !dark) && window[4..] == finder)
+                    {
+                        penalty += 40;
+                    }
+                }
+            }
+        }
+        // blocks of 2x2 modules of the same color
+        for y in 0..size - 1 {
+            for x in 0..size - 1 {
+                let color = self.modules[y][x];
+                if color == self.modules[y][x + 1]
+                    && color == self.modules[y + 1][x]
+                    && color == self.modules[y + 1][x + 1]
+                {
+                    penalty += 3;
+                }
+            }
+        }
+        // imbalance between dark and light modules
+        let dark = self.modules.iter().flatten().filter(|&&dark| dark).count();
+        let total = size * size;
+        let deviation = (dark * 20).abs_diff(total * 10);
+        penalty + deviation / total * 10
+    }
+}
+
+fn byte_capacity(version: usize) -> usize {
+    let count_bits = if version < 10 { 8 } else { 16 };
+    (total_data_codewords(version) * 8 - 4 - count_bits) / 8
+}
+
+fn total_data_codewords(version: usize) -> usize {
+    BLOCKS[version - 1]
+        .1
+        .iter()
+        .map(|(blocks, data)| blocks * data)
+        .sum()
+}
+
+/// Encodes `data` in byte mode and pads it to the capacity of the version
+fn data_codewords(version: usize, data: &[u8]) -> Vec<u8> {
+    let mut bits: Vec<bool> = Vec::new();
+    let mut push = |value: u32, length: u32| {
+        for i in (0..length).rev() {
+            bits.push((value >> i) & 1 != 0);
+        }
+    };
+    push(0b0100, 4);
+    push(data.len() as u32, if version < 10 { 8 } else { 16 });
+    for &byte in data {
+        push(byte as u32, 8);
+    }
+    let capacity = total_data_codewords(version) * 8;
+    let terminator = (capacity - bits.len()).min(4);
+    bits.extend(std::iter::repeat(false).take(terminator));
+    while bits.len() % 8 != 0 {
+        bits.push(false);
+    }
+    let mut codewords: Vec<u8> = bits
+        .chunks(8)
+        .map(|byte| byte.iter().fold(0, |acc, &bit| acc << 1 | bit as u8))
+        .collect();
+    for padding in [0xEC, 0x11].into_iter().cycle() {
+        if codewords.len() == total_data_codewords(version) {
+            break;
+        }
+        codewords.push(padding);
+    }
+    codewords
+}
+
+/// Splits the data in blocks, computes their error correction codewords, and interleaves it all
+fn add_error_correction(version: usize, data: &[u8]) -> Vec<u8> {
+    let (ec_length, groups) = BLOCKS[version - 1];
+    let divisor = reed_solomon_divisor(ec_length);
+    let mut blocks = Vec::new();
+    let mut rest = data;
+    for &(n_blocks, data_length) in groups {
+        for _ in 0..n_blocks {
+            let (block, remaining) = rest.split_at(data_length);
+            blocks.push((block, reed_solomon_remainder(block, &divisor)));
+            rest = remaining;
+        }
+    }
+    let max_data_length = groups.iter().map(|&(_, length)| length).max().unwrap();
+    let mut codewords = Vec::new();
+    for i in 0..max_data_length {
+        codewords.extend(blocks.iter().filter_map(|(block, _)| block.get(i)));
+    }
+    for i in 0..ec_length {
+        codewords.extend(blocks.iter().map(|(_, ec)| ec[i]));
+    }
+    codewords
+}
+
+fn reed_solomon_divisor(degree: usize) -> Vec<u8> {
+    let mut result = vec![0; degree];
+    result[degree - 1] = 1;
+    let mut root = 1;
+    for _ in 0..degree {
+        for j in 0..degree {
+            result[j] = gf_multiply(result[j], root);
+            if j + 1 < degree {
+                result[j] ^= result[j + 1];
+            }
+        }
+        root = gf_multiply(root, 0x02);
+    }
+    result
+}
+
+fn reed_solomon_remainder(data: &[u8], divisor: &[u8]) -> Vec<u8> {
+    let mut result = vec![0; divisor.len()];
+    for &byte in data {
+        let factor = byte ^ result.remove(0);
+        result.push(0);
+        for (x, &y) in result.iter_mut().zip(divisor) {
+            *x ^= gf_multiply(y, factor);
+        }
+    }
+    result
+}
+
+/// Multiplication in GF(2^8) modulo x^8 + x^4 + x^3 + x^2 + 1
+fn gf_multiply(x: u8, y: u8) -> u8 {
+    let mut z: u8 = 0;
+    for i in (0..8).rev() {
+        z = (z << 1) ^ ((z >> 7) * 0x1D);
+        z ^= ((y >> i) & 1) * x;
+    }
+    z
+}
+
+/// The 15 format bits, protected by a BCH code and masked
+fn format_bits(level: u32, mask: u32) -> u32 {
+    let data = level << 3 | mask;
+    let mut remainder = data;
+    for _ in 0..10 {
+        remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+    }
+    (data << 10 | remainder) ^ 0x5412
+}
+
+/// The 18 version bits, protected by a BCH code
+fn version_bits(version: u32) -> u32 {
+    let mut remainder = version;
+    for _ in 0..12 {
+        remainder = (remainder << 1) ^ ((remainder >> 11) * 0x1F25);
+    }
+    version << 12 | remainder
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        byte_capacity, format_bits, reed_solomon_divisor, reed_solomon_remainder, version_bits,
+        QrCode,
+    };
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn error_correction_matches_the_example_of_the_specification() {
+        // "01234567" in numeric mode, version 1-M
+        let data = [
+            0x10, 0x20, 0x0C, 0x56, 0x61, 0x80, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11, 0xEC, 0x11,
+            0xEC, 0x11,
+        ];
+        assert_eq!(
+            reed_solomon_remainder(&data, &reed_solomon_divisor(10)),
+            [165, 36, 212, 193, 237, 54, 199, 135, 44, 85]
+        );
+    }
+
+    #[test]
+    fn format_and_version_bits_match_the_specification() {
+        assert_eq!(format_bits(0b00, 0), 0b101010000010010);
+        assert_eq!(format_bits(0b01, 0), 0b111011111000100);
+        assert_eq!(version_bits(7), 0b000111110010010100);
+    }
+
+    #[test]
+    fn the_smallest_version_that_fits_is_used() {
+        assert_eq!(byte_capacity(1), 14);
+        assert_eq!(byte_capacity(10), 213);
+        let qr_code = assert_ok!(QrCode::encode(b"otpauth://totp/Newsletter"));
+        assert_eq!(qr_code.size, 25);
+    }
+
+    #[test]
+    fn finder_patterns_are_in_three_corners() {
+        let qr_code = assert_ok!(QrCode::encode(&[b'a'; 150]));
+        let size = qr_code.size;
+        for (x, y) in [(0, 0), (size - 7, 0), (0, size - 7)] {
+            assert!(qr_code.modules[y][x] && qr_code.modules[y + 6][x + 6]);
+            assert!(!qr_code.modules[y + 1][x + 1] && qr_code.modules[y + 3][x + 3]);
+        }
+    }
+
+    #[test]
+    fn data_over_the_capacity_is_rejected() {
+        assert_err!(QrCode::encode(&[0; 214]));
+    }
+}
@@ -0,0 +1,117 @@
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// A number of operations per second, always positive
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+#[serde(try_from = "f64")]
+pub struct Rate(f64);
+
+impl Rate {
+    pub fn parse(per_second: f64) -> Result<Rate, String> {
+        if per_second > 0.0 && per_second.is_finite() {
+            Ok(Self(per_second))
+        } else {
+            Err(format!(
+                "{} is not a valid rate: it must be a positive number per second",
+                per_second
+            ))
+        }
+    }
+}
+
+impl TryFrom<f64> for Rate {
+    type Error = String;
+
+    fn try_from(per_second: f64) -> Result<Self, Self::Error> {
+        Self::parse(per_second)
+    }
+}
+
+/// A token bucket limiting how many operations can happen per second.
+/// The bucket starts full, which allows short bursts of up to `capacity` operations.
+pub struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    refill_per_second: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a bucket refilling at `rate` tokens per second. The burst capacity matches the
+    /// rate, but is never smaller than a single token.
+    pub fn new(rate: Rate) -> Self {
+        let per_second = rate.0;
+        let capacity = per_second.max(1.0);
+        Self {
+            capacity,
+            tokens: capacity,
+            refill_per_second: per_second,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Waits until a token is available and consumes it
+    pub async fn acquire(&mut self) {
+        loop {
+            self.refill();
+            if self.tokens >= 1.0 {
+                self.tokens -= 1.0;
+                return;
+            }
+            let missing = 1.0 - self.tokens;
+            tokio::time::sleep(Duration::from_secs_f64(missing / self.refill_per_second)).await;
+        }
+    }
+
+    fn refill(&mut self) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.refill_per_second).min(self.capacity);
+        self.last_refill = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Rate, TokenBucket};
+    use claims::assert_err;
+    use std::time::Duration;
+    use tokio::time::Instant;
+
+    #[tokio::test]
+    async fn a_full_bucket_allows_a_burst() {
+        let mut bucket = TokenBucket::new(Rate::parse(5.0).unwrap());
+        let start = Instant::now();
+        for _ in 0..5 {
+            bucket.acquire().await;
+        }
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn an_empty_bucket_waits_for_a_refill() {
+        let mut bucket = TokenBucket::new(Rate::parse(20.0).unwrap());
+        for _ in 0..20 {
+            bucket.acquire().await;
+        }
+        let start = Instant::now();
+        bucket.acquire().await;
+        // one token is refilled every 50ms
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[tokio::test]
+    async fn fractional_rates_allow_a_single_token() {
+        let mut bucket = TokenBucket::new(Rate::parse(0.5).unwrap());
+        let start = Instant::now();
+        bucket.acquire().await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn rates_that_are_not_positive_are_rejected() {
+        assert_err!(Rate::parse(0.0));
+        assert_err!(Rate::parse(-1.0));
+        assert_err!(Rate::parse(f64::NAN));
+    }
+}
@@ -0,0 +1,54 @@
+use crate::rate_limiting::{Decision, RateLimiter, RouteGroup};
+use crate::reverse_proxy::ClientInfo;
+use crate::routes::ApiError;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{HeaderValue, RETRY_AFTER};
+use actix_web::{web, HttpResponse, ResponseError};
+use actix_web_lab::middleware::Next;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Rejects requests with a 429 once their client exceeded the limit of the route group.
+/// Requests are let through if the store cannot be reached: a store outage must not take the
+/// subscribe and login forms down.
+pub async fn rate_limit_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let group = RouteGroup::of(req.method(), req.path());
+    let limiter = req.app_data::<web::Data<RateLimiter>>().cloned();
+    let (Some(group), Some(limiter)) = (group, limiter) else {
+        return next.call(req).await;
+    };
+    let Some(client) = ClientInfo::of(req.request()).map(|client| client.ip.to_string()) else {
+        return next.call(req).await;
+    };
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("The system clock is set before the Unix epoch")
+        .as_secs();
+    match limiter.check(group, &client, now).await {
+        Ok(Decision::Allowed) => next.call(req).await,
+        Ok(Decision::Limited { retry_after }) => {
+            tracing::warn!(client, ?group, "Rate limited a client.");
+            // clients of the JSON API expect its error envelope
+            let mut response = if req.path().starts_with("/api/") {
+                ApiError::TooManyRequests("Too many requests. Try again later.".into())
+                    .error_response()
+            } else {
+                HttpResponse::TooManyRequests().finish()
+            };
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(retry_after.as_secs()));
+            let e = anyhow::anyhow!("The client exceeded the rate limit of {:?}", group);
+            Err(InternalError::from_response(e, response).into())
+        }
+        Err(e) => {
+            tracing::error!(error.cause_chain = ?e, "Failed to check the rate limit.");
+            next.call(req).await
+        }
+    }
+}
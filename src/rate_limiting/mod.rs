@@ -0,0 +1,181 @@
+//! Per-client request limits on the public endpoints that are expensive to abuse.
+//!
+//! Requests are counted in fixed windows, per route group and client IP address.
+
+mod middleware;
+mod store;
+
+pub use middleware::rate_limit_requests;
+pub use store::RateLimitStore;
+
+use actix_web::http::Method;
+use std::time::Duration;
+
+/// How many requests a client can make to a route group within a window
+#[derive(serde::Deserialize, Clone, Copy, Debug)]
+pub struct RateLimit {
+    pub max_requests: u64,
+    pub window_seconds: u64,
+}
+
+/// Endpoints sharing the same limit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RouteGroup {
+    /// Endpoints sending emails to the address in the form
+    Subscriptions,
+    Login,
+}
+
+impl RouteGroup {
+    /// The group a request belongs to, if it is rate limited at all
+    pub fn of(method: &Method, path: &str) -> Option<Self> {
+        if method != Method::POST {
+            return None;
+        }
+        match path {
+            "/subscriptions" | "/subscriptions/resend-confirmation" | "/api/v1/subscriptions" => {
+                Some(RouteGroup::Subscriptions)
+            }
+            "/login" | "/login/two-factor" => Some(RouteGroup::Login),
+            _ => None,
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            RouteGroup::Subscriptions => "subscriptions",
+            RouteGroup::Login => "login",
+        }
+    }
+}
+
+/// The outcome of counting a request against its limit
+#[derive(Debug, PartialEq, Eq)]
+pub enum Decision {
+    Allowed,
+    Limited { retry_after: Duration },
+}
+
+pub struct RateLimiter {
+    store: RateLimitStore,
+    subscriptions: RateLimit,
+    login: RateLimit,
+}
+
+impl RateLimiter {
+    pub fn new(store: RateLimitStore, subscriptions: RateLimit, login: RateLimit) -> Self {
+        Self {
+            store,
+            subscriptions,
+            login,
+        }
+    }
+
+    fn limit(&self, group: RouteGroup) -> RateLimit {
+        match group {
+            RouteGroup::Subscriptions => self.subscriptions,
+            RouteGroup::Login => self.login,
+        }
+    }
+
+    /// Counts a request from `client` to `group` made at `now`, a Unix timestamp in seconds
+    pub async fn check(
+        &self,
+        group: RouteGroup,
+        client: &str,
+        now: u64,
+    ) -> Result<Decision, anyhow::Error> {
+        let limit = self.limit(group);
+        let window = limit.window_seconds.max(1);
+        let window_index = now / window;
+        let key = format!("rate_limit:{}:{}:{}", group.as_str(), client, window_index);
+        let window_end = (window_index + 1) * window;
+        let count = self.store.increment(&key, now, window_end).await?;
+        if count > limit.max_requests {
+            Ok(Decision::Limited {
+                retry_after: Duration::from_secs(window_end - now),
+            })
+        } else {
+            Ok(Decision::Allowed)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decision, RateLimit, RateLimitStore, RateLimiter, RouteGroup};
+    use actix_web::http::Method;
+    use claims::{assert_none, assert_ok_eq, assert_some_eq};
+    use std::time::Duration;
+
+    fn limiter() -> RateLimiter {
+        let limit = RateLimit {
+            max_requests: 2,
+            window_seconds: 60,
+        };
+        RateLimiter::new(RateLimitStore::in_memory(), limit, limit)
+    }
+
+    #[test]
+    fn only_posts_to_the_public_forms_are_rate_limited() {
+        assert_some_eq!(
+            RouteGroup::of(&Method::POST, "/subscriptions"),
+            RouteGroup::Subscriptions
+        );
+        assert_some_eq!(
+            RouteGroup::of(&Method::POST, "/subscriptions/resend-confirmation"),
+            RouteGroup::Subscriptions
+        );
+        assert_some_eq!(
+            RouteGroup::of(&Method::POST, "/api/v1/subscriptions"),
+            RouteGroup::Subscriptions
+        );
+        assert_some_eq!(RouteGroup::of(&Method::POST, "/login"), RouteGroup::Login);
+        assert_some_eq!(
+            RouteGroup::of(&Method::POST, "/login/two-factor"),
+            RouteGroup::Login
+        );
+        assert_none!(RouteGroup::of(&Method::GET, "/login"));
+        assert_none!(RouteGroup::of(&Method::POST, "/admin/newsletters"));
+    }
+
+    #[tokio::test]
+    async fn requests_over_the_limit_are_limited_until_the_window_ends() {
+        let limiter = limiter();
+        for _ in 0..2 {
+            assert_ok_eq!(
+                limiter.check(RouteGroup::Login, "1.2.3.4", 125).await,
+                Decision::Allowed
+            );
+        }
+        assert_ok_eq!(
+            limiter.check(RouteGroup::Login, "1.2.3.4", 130).await,
+            Decision::Limited {
+                retry_after: Duration::from_secs(50)
+            }
+        );
+        assert_ok_eq!(
+            limiter.check(RouteGroup::Login, "1.2.3.4", 180).await,
+            Decision::Allowed
+        );
+    }
+
+    #[tokio::test]
+    async fn clients_and_route_groups_are_counted_separately() {
+        let limiter = limiter();
+        for _ in 0..3 {
+            limiter
+                .check(RouteGroup::Login, "1.2.3.4", 0)
+                .await
+                .unwrap();
+        }
+        assert_ok_eq!(
+            limiter.check(RouteGroup::Login, "5.6.7.8", 0).await,
+            Decision::Allowed
+        );
+        assert_ok_eq!(
+            limiter.check(RouteGroup::Subscriptions, "1.2.3.4", 0).await,
+            Decision::Allowed
+        );
+    }
+}
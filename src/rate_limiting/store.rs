@@ -0,0 +1,57 @@
+use redis::aio::ConnectionManager;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Past this many counters, expired ones are dropped from the in-memory store
+const IN_MEMORY_PRUNE_THRESHOLD: usize = 10_000;
+
+/// Where request counters are kept. The in-memory store is local to each instance of the
+/// application, while Redis shares counters between instances.
+pub enum RateLimitStore {
+    /// Counters and the Unix timestamp at which they expire
+    InMemory(Mutex<HashMap<String, (u64, u64)>>),
+    Redis(ConnectionManager),
+}
+
+impl RateLimitStore {
+    pub fn in_memory() -> Self {
+        Self::InMemory(Mutex::new(HashMap::new()))
+    }
+
+    pub async fn redis(redis_uri: &str) -> Result<Self, redis::RedisError> {
+        let client = redis::Client::open(redis_uri)?;
+        Ok(Self::Redis(ConnectionManager::new(client).await?))
+    }
+
+    /// Increments the counter stored at `key`, which expires at `expires_at`, and returns its
+    /// new value. Timestamps are Unix timestamps in seconds.
+    pub async fn increment(
+        &self,
+        key: &str,
+        now: u64,
+        expires_at: u64,
+    ) -> Result<u64, anyhow::Error> {
+        match self {
+            RateLimitStore::InMemory(counters) => {
+                let mut counters = counters.lock().unwrap();
+                if counters.len() > IN_MEMORY_PRUNE_THRESHOLD {
+                    counters.retain(|_, (_, counter_expires_at)| *counter_expires_at > now);
+                }
+                let (count, _) = counters.entry(key.to_string()).or_insert((0, expires_at));
+                *count += 1;
+                Ok(*count)
+            }
+            RateLimitStore::Redis(connection) => {
+                let (count,): (u64,) = redis::pipe()
+                    .incr(key, 1)
+                    .cmd("EXPIREAT")
+                    .arg(key)
+                    .arg(expires_at)
+                    .ignore()
+                    .query_async(&mut connection.clone())
+                    .await?;
+                Ok(count)
+            }
+        }
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::web;
+use actix_web_lab::middleware::Next;
+
+/// Upper bounds of the latency histogram buckets, in seconds
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Requests that matched no route share one label, so that scanners cannot create new series
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// What the requests of a series have in common
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct SeriesKey {
+    method: String,
+    /// The pattern of the route, e.g. `/issues/{issue_id}`, rather than the path
+    route: String,
+    status: u16,
+}
+
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Counts per bucket of `LATENCY_BUCKETS`, not cumulated
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|bound| seconds <= *bound) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+/// Latency histograms and error counts of the requests served, per route and status
+#[derive(Debug, Default)]
+pub struct RequestMetrics {
+    series: Mutex<BTreeMap<SeriesKey, Histogram>>,
+}
+
+impl RequestMetrics {
+    fn observe(&self, key: SeriesKey, latency: Duration) {
+        let mut series = self
+            .series
+            .lock()
+            .expect("The request metrics lock is poisoned");
+        series
+            .entry(key)
+            .or_default()
+            .observe(latency.as_secs_f64());
+    }
+
+    /// Writes the histograms and the count of server errors in the Prometheus text format
+    pub fn write_prometheus(&self, body: &mut String) {
+        let series = self
+            .series
+            .lock()
+            .expect("The request metrics lock is poisoned");
+        writeln!(
+            body,
+            "# HELP http_server_request_duration_seconds Latency of the requests served, by route and status.\n\
+            # TYPE http_server_request_duration_seconds histogram"
+        )
+        .unwrap();
+        for (key, histogram) in series.iter() {
+            let labels = labels(key);
+            let mut cumulated = 0;
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                cumulated += count;
+                writeln!(
+                    body,
+                    "http_server_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, cumulated
+                )
+                .unwrap();
+            }
+            writeln!(
+                body,
+                "http_server_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}\n\
+                http_server_request_duration_seconds_sum{{{}}} {}\n\
+                http_server_request_duration_seconds_count{{{}}} {}",
+                labels, histogram.count, labels, histogram.sum, labels, histogram.count
+            )
+            .unwrap();
+        }
+        writeln!(
+            body,
+            "# HELP http_server_errors_total Requests answered with a server error, by route and status.\n\
+            # TYPE http_server_errors_total counter"
+        )
+        .unwrap();
+        for (key, histogram) in series.iter().filter(|(key, _)| key.status >= 500) {
+            writeln!(
+                body,
+                "http_server_errors_total{{{}}} {}",
+                labels(key),
+                histogram.count
+            )
+            .unwrap();
+        }
+    }
+}
+
+fn labels(key: &SeriesKey) -> String {
+    format!(
+        "method=\"{}\",route=\"{}\",status=\"{}\"",
+        key.method,
+        key.route.replace('\\', "\\\\").replace('"', "\\\""),
+        key.status
+    )
+}
+
+/// Records the latency of every request under the pattern of the route that served it
+pub async fn record_request_metrics(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let metrics = req.app_data::<web::Data<RequestMetrics>>().cloned();
+    let method = req.method().to_string();
+    let started_at = Instant::now();
+    let response = next.call(req).await;
+    if let Some(metrics) = metrics {
+        let (route, status) = match &response {
+            Ok(response) => (
+                response.request().match_pattern(),
+                response.status().as_u16(),
+            ),
+            Err(e) => (None, e.as_response_error().status_code().as_u16()),
+        };
+        let key = SeriesKey {
+            method,
+            route: route.unwrap_or_else(|| UNMATCHED_ROUTE.into()),
+            status,
+        };
+        metrics.observe(key, started_at.elapsed());
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RequestMetrics, SeriesKey};
+    use std::time::Duration;
+
+    fn key(status: u16) -> SeriesKey {
+        SeriesKey {
+            method: "POST".into(),
+            route: "/subscriptions".into(),
+            status,
+        }
+    }
+
+    #[test]
+    fn buckets_are_cumulated() {
+        let metrics = RequestMetrics::default();
+        metrics.observe(key(303), Duration::from_millis(3));
+        metrics.observe(key(303), Duration::from_millis(30));
+        metrics.observe(key(303), Duration::from_secs(60));
+        let mut body = String::new();
+        metrics.write_prometheus(&mut body);
+        let labels = r#"method="POST",route="/subscriptions",status="303""#;
+        for (bound, count) in [
+            ("0.005", 1),
+            ("0.025", 1),
+            ("0.05", 2),
+            ("10", 2),
+            ("+Inf", 3),
+        ] {
+            let line = format!(
+                "http_server_request_duration_seconds_bucket{{{},le=\"{}\"}} {}\n",
+                labels, bound, count
+            );
+            assert!(body.contains(&line), "missing {}", line);
+        }
+        assert!(body.contains(&format!(
+            "http_server_request_duration_seconds_count{{{}}} 3\n",
+            labels
+        )));
+    }
+
+    #[test]
+    fn only_server_errors_are_counted_as_errors() {
+        let metrics = RequestMetrics::default();
+        metrics.observe(key(400), Duration::from_millis(1));
+        metrics.observe(key(500), Duration::from_millis(1));
+        metrics.observe(key(500), Duration::from_millis(1));
+        let mut body = String::new();
+        metrics.write_prometheus(&mut body);
+        assert!(body.contains(
+            r#"http_server_errors_total{method="POST",route="/subscriptions",status="500"} 2"#
+        ));
+        assert!(!body.contains(
+            r#"http_server_errors_total{method="POST",route="/subscriptions",status="400"}"#
+        ));
+    }
+}
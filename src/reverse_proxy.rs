@@ -0,0 +1,315 @@
+//! The address and scheme of the client behind the reverse proxies a request went through.
+//!
+//! Forwarded headers are only believed when the peer is a trusted proxy, and are read from the
+//! nearest proxy outwards, so that a client cannot pick its own address by sending them.
+
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderMap, FORWARDED, X_FORWARDED_FOR, X_FORWARDED_PROTO};
+use actix_web::{web, HttpMessage, HttpRequest};
+use actix_web_lab::middleware::Next;
+use ipnet::IpNet;
+use tracing::Span;
+use tracing_actix_web::{DefaultRootSpanBuilder, RootSpanBuilder};
+
+/// The reverse proxies and load balancers allowed to tell the address and scheme of clients
+#[derive(Clone, Debug, Default)]
+pub struct TrustedProxies(Vec<IpNet>);
+
+impl TrustedProxies {
+    pub fn new(networks: Vec<IpNet>) -> Self {
+        Self(networks)
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        self.0.iter().any(|network| network.contains(&ip))
+    }
+}
+
+/// Accepts single addresses as well as networks, e.g. `10.0.0.1` or `10.0.0.0/8`
+impl<'de> serde::Deserialize<'de> for TrustedProxies {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let entries = Vec::<String>::deserialize(deserializer)?;
+        entries
+            .iter()
+            .map(|entry| {
+                IpNet::from_str(entry)
+                    .or_else(|_| IpAddr::from_str(entry).map(IpNet::from))
+                    .map_err(|_| {
+                        serde::de::Error::custom(format!(
+                            "{} is not a valid IP address or network.",
+                            entry
+                        ))
+                    })
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+/// Where a request really comes from, as resolved by `resolve_client_info`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub ip: IpAddr,
+    /// `http` or `https`, as the client sent the request
+    pub scheme: &'static str,
+}
+
+impl ClientInfo {
+    /// The client of `req`, falling back to the peer address if the middleware did not run
+    pub fn of(req: &HttpRequest) -> Option<Self> {
+        if let Some(client) = req.extensions().get::<ClientInfo>() {
+            return Some(*client);
+        }
+        let peer = req.peer_addr()?;
+        Some(resolve(
+            peer,
+            connection_scheme(req),
+            req.headers(),
+            &TrustedProxies::default(),
+        ))
+    }
+}
+
+/// Resolves the client of every request and stores it in the request extensions. It must wrap
+/// `TracingLogger`, so that the root span of the request records the resolved client.
+pub async fn resolve_client_info(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    if let Some(peer) = req.peer_addr() {
+        let trusted_proxies = req
+            .app_data::<web::Data<TrustedProxies>>()
+            .map(|proxies| proxies.get_ref().clone())
+            .unwrap_or_default();
+        let client = resolve(
+            peer,
+            connection_scheme(req.request()),
+            req.headers(),
+            &trusted_proxies,
+        );
+        req.extensions_mut().insert(client);
+    }
+    next.call(req).await
+}
+
+/// The default root span, with the client address and scheme resolved by `resolve_client_info`
+/// instead of the forwarded headers as sent by anyone
+pub struct ProxyAwareRootSpanBuilder;
+
+impl RootSpanBuilder for ProxyAwareRootSpanBuilder {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        let span = DefaultRootSpanBuilder::on_request_start(request);
+        if let Some(client) = request.extensions().get::<ClientInfo>() {
+            span.record("http.client_ip", &tracing::field::display(client.ip));
+            span.record("http.scheme", client.scheme);
+        }
+        span
+    }
+
+    fn on_request_end<B: MessageBody>(
+        span: Span,
+        outcome: &Result<ServiceResponse<B>, actix_web::Error>,
+    ) {
+        DefaultRootSpanBuilder::on_request_end(span, outcome);
+    }
+}
+
+fn connection_scheme(req: &HttpRequest) -> &'static str {
+    if req.app_config().secure() {
+        "https"
+    } else {
+        "http"
+    }
+}
+
+/// An element of the forwarded headers, added by the proxy that received the request from it
+#[derive(Debug, Default, PartialEq, Eq)]
+struct ForwardedHop {
+    /// `None` if the proxy hid or did not know the address
+    ip: Option<IpAddr>,
+    scheme: Option<&'static str>,
+}
+
+/// Walks the hops from the nearest proxy outwards, for as long as they are trusted: the first
+/// untrusted address is the client
+fn resolve(
+    peer: SocketAddr,
+    connection_scheme: &'static str,
+    headers: &HeaderMap,
+    trusted_proxies: &TrustedProxies,
+) -> ClientInfo {
+    let mut client = ClientInfo {
+        ip: peer.ip(),
+        scheme: connection_scheme,
+    };
+    for hop in forwarded_hops(headers).into_iter().rev() {
+        if !trusted_proxies.contains(client.ip) {
+            break;
+        }
+        let Some(ip) = hop.ip else {
+            break;
+        };
+        client.ip = ip;
+        if let Some(scheme) = hop.scheme {
+            client.scheme = scheme;
+        }
+    }
+    client
+}
+
+/// The hops of the `Forwarded` header or, without it, of `X-Forwarded-For`, the farthest first.
+/// `X-Forwarded-Proto` only tells the scheme the nearest proxy received.
+fn forwarded_hops(headers: &HeaderMap) -> Vec<ForwardedHop> {
+    let forwarded = header_elements(headers, &FORWARDED);
+    if !forwarded.is_empty() {
+        return forwarded
+            .iter()
+            .map(|element| parse_forwarded(element))
+            .collect();
+    }
+    let mut hops: Vec<ForwardedHop> = header_elements(headers, &X_FORWARDED_FOR)
+        .iter()
+        .map(|address| ForwardedHop {
+            ip: parse_node(address),
+            scheme: None,
+        })
+        .collect();
+    if let Some(last) = hops.last_mut() {
+        last.scheme = header_elements(headers, &X_FORWARDED_PROTO)
+            .last()
+            .and_then(|scheme| parse_scheme(scheme));
+    }
+    hops
+}
+
+/// The comma-separated elements of every occurrence of a header, in order
+fn header_elements(headers: &HeaderMap, name: &actix_web::http::header::HeaderName) -> Vec<String> {
+    headers
+        .get_all(name)
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|element| element.trim().to_string())
+        .filter(|element| !element.is_empty())
+        .collect()
+}
+
+/// Reads an element of the `Forwarded` header, e.g. `for=192.0.2.60;proto=https;by=203.0.113.43`
+fn parse_forwarded(element: &str) -> ForwardedHop {
+    let mut hop = ForwardedHop::default();
+    for pair in element.split(';') {
+        let Some((name, value)) = pair.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"');
+        match name.trim().to_ascii_lowercase().as_str() {
+            "for" => hop.ip = parse_node(value),
+            "proto" => hop.scheme = parse_scheme(value),
+            _ => {}
+        }
+    }
+    hop
+}
+
+/// Reads an address with an optional port, e.g. `192.0.2.60`, `192.0.2.60:4711` or
+/// `[2001:db8::1]:4711`
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim();
+    IpAddr::from_str(node)
+        .ok()
+        .or_else(|| SocketAddr::from_str(node).ok().map(|address| address.ip()))
+        .or_else(|| {
+            node.strip_prefix('[')
+                .and_then(|node| node.strip_suffix(']'))
+                .and_then(|node| IpAddr::from_str(node).ok())
+        })
+}
+
+fn parse_scheme(scheme: &str) -> Option<&'static str> {
+    match scheme.trim().to_ascii_lowercase().as_str() {
+        "http" => Some("http"),
+        "https" => Some("https"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{resolve, ClientInfo, TrustedProxies};
+    use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+    use std::net::SocketAddr;
+
+    fn trusted(networks: &[&str]) -> TrustedProxies {
+        let networks = serde_json::json!(networks);
+        serde_json::from_value(networks).unwrap()
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(
+                HeaderName::from_static(name),
+                HeaderValue::from_static(value),
+            );
+        }
+        headers
+    }
+
+    fn client(peer: &str, headers: &HeaderMap, proxies: &TrustedProxies) -> ClientInfo {
+        let peer: SocketAddr = peer.parse().unwrap();
+        resolve(peer, "http", headers, proxies)
+    }
+
+    #[test]
+    fn forwarded_headers_from_untrusted_peers_are_ignored() {
+        let headers = headers(&[
+            ("x-forwarded-for", "203.0.113.7"),
+            ("x-forwarded-proto", "https"),
+        ]);
+        let client = client("198.51.100.1:5000", &headers, &trusted(&["10.0.0.0/8"]));
+        assert_eq!(client.ip.to_string(), "198.51.100.1");
+        assert_eq!(client.scheme, "http");
+    }
+
+    #[test]
+    fn the_first_untrusted_hop_is_the_client() {
+        // the client made up the first address, the proxies appended the others
+        let headers = headers(&[
+            ("x-forwarded-for", "1.2.3.4, 203.0.113.7, 10.0.0.2"),
+            ("x-forwarded-proto", "https"),
+        ]);
+        let client = client("10.0.0.1:5000", &headers, &trusted(&["10.0.0.0/8"]));
+        assert_eq!(client.ip.to_string(), "203.0.113.7");
+        assert_eq!(client.scheme, "https");
+    }
+
+    #[test]
+    fn the_forwarded_header_takes_precedence() {
+        let headers = headers(&[
+            ("x-forwarded-for", "1.2.3.4"),
+            (
+                "forwarded",
+                r#"for=203.0.113.7;proto=https, for="[2001:db8::1]:4711";proto=http"#,
+            ),
+        ]);
+        let client = client("10.0.0.1:5000", &headers, &trusted(&["10.0.0.1"]));
+        assert_eq!(client.ip.to_string(), "2001:db8::1");
+        assert_eq!(client.scheme, "http");
+    }
+
+    #[test]
+    fn hidden_addresses_stop_the_walk_at_the_last_known_proxy() {
+        let headers = headers(&[("forwarded", "for=_hidden, for=10.0.0.2")]);
+        let client = client("10.0.0.1:5000", &headers, &trusted(&["10.0.0.0/8"]));
+        assert_eq!(client.ip.to_string(), "10.0.0.2");
+    }
+
+    #[test]
+    fn invalid_proxies_are_rejected() {
+        let networks = serde_json::json!(["10.0.0.0/8", "load-balancer"]);
+        assert!(serde_json::from_value::<TrustedProxies>(networks).is_err());
+    }
+}
@@ -0,0 +1,256 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context as _;
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::audit_log::record_audit_entry;
+use crate::authentication::UserId;
+use crate::automations::{
+    find_sequence, get_sequences, get_steps, AutomationSequence, AutomationStep,
+};
+use crate::configuration::PayloadLimitSettings;
+use crate::newsletters::{find_newsletter, get_newsletters, newsletter_options};
+use crate::routing_helpers::{e413, e500, see_other};
+use crate::templates::{collect_flash_messages, Branding, Layout, SelectOption};
+
+/// Steps cannot be scheduled more than about ten years after the confirmation
+const MAX_DAY_OFFSET: i32 = 3650;
+
+#[derive(serde::Deserialize)]
+pub struct SequenceFormData {
+    name: String,
+    /// Slug of the newsletter whose new subscribers go through the sequence
+    #[serde(default)]
+    newsletter: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct StepFormData {
+    day_offset: i32,
+    title: String,
+    text_content: String,
+    html_content: String,
+}
+
+#[derive(Template)]
+#[template(path = "automations.html")]
+struct AutomationsPage<'a> {
+    layout: Layout<'a>,
+    messages: Vec<FlashMessage>,
+    sequences: Vec<AutomationSequence>,
+    newsletter_options: Vec<SelectOption>,
+}
+
+#[derive(Template)]
+#[template(path = "automation_sequence.html")]
+struct AutomationSequencePage<'a> {
+    layout: Layout<'a>,
+    messages: Vec<FlashMessage>,
+    sequence: &'a AutomationSequence,
+    steps: Vec<AutomationStep>,
+}
+
+/// Lists the automation sequences, with a form to start a new one
+pub async fn list_automations(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletters = get_newsletters(&pool).await.map_err(e500)?;
+    let body = AutomationsPage {
+        layout: Layout::new(&branding, "Automation sequences"),
+        messages: collect_flash_messages(&flash_messages),
+        sequences: get_sequences(&pool).await.map_err(e500)?,
+        newsletter_options: newsletter_options(&newsletters, ""),
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Starts a new, empty sequence for the subscribers of a newsletter
+#[tracing::instrument(
+    name = "Create an automation sequence",
+    skip(form, pool, user_id),
+    fields(name = %form.name)
+)]
+pub async fn create_automation(
+    form: web::Form<SequenceFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.0.name.trim();
+    if name.is_empty() || name.len() > 256 {
+        FlashMessage::error("The name must be between 1 and 256 characters.").send();
+        return Ok(see_other("/admin/automations"));
+    }
+    let Some(newsletter) = find_newsletter(pool.get_ref(), &form.0.newsletter)
+        .await
+        .context("Failed to look up the newsletter.")
+        .map_err(e500)?
+    else {
+        FlashMessage::error("Please pick one of the newsletters.").send();
+        return Ok(see_other("/admin/automations"));
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let sequence_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO automation_sequences (sequence_id, newsletter_id, name)
+        VALUES ($1, $2, $3)
+        "#,
+        sequence_id,
+        newsletter.newsletter_id,
+        name
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to insert the automation sequence.")
+    .map_err(e500)?;
+    record_audit_entry(
+        &mut transaction,
+        Some(**user_id),
+        "create_automation",
+        &sequence_id.to_string(),
+    )
+    .await
+    .context("Failed to record the creation in the audit log.")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to create an automation sequence.")
+        .map_err(e500)?;
+    FlashMessage::success(format!("The sequence {} has been created.", name)).send();
+    Ok(see_other(&format!("/admin/automations/{}", sequence_id)))
+}
+
+/// Shows the steps of a sequence, with a form to add one
+pub async fn automation_sequence(
+    sequence_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let sequence_id = sequence_id.into_inner();
+    let Some(sequence) = find_sequence(&pool, sequence_id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let body = AutomationSequencePage {
+        layout: Layout::new(&branding, &sequence.name),
+        messages: collect_flash_messages(&flash_messages),
+        sequence: &sequence,
+        steps: get_steps(&pool, sequence_id).await.map_err(e500)?,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Adds an email to a sequence. Subscribers already going through it receive the step too, unless
+/// they are past its day.
+#[tracing::instrument(
+    name = "Add an automation step",
+    skip(form, pool, user_id, payload_limits),
+    fields(day_offset = %form.day_offset)
+)]
+pub async fn add_automation_step(
+    sequence_id: web::Path<Uuid>,
+    form: web::Form<StepFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    payload_limits: web::Data<PayloadLimitSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let sequence_id = sequence_id.into_inner();
+    let sequence_url = format!("/admin/automations/{}", sequence_id);
+    let StepFormData {
+        day_offset,
+        title,
+        text_content,
+        html_content,
+    } = form.0;
+    payload_limits
+        .check_newsletter_html(&html_content)
+        .map_err(e413)?;
+    if !(0..=MAX_DAY_OFFSET).contains(&day_offset) {
+        FlashMessage::error(format!(
+            "The step must be sent between 0 and {} days after the confirmation.",
+            MAX_DAY_OFFSET
+        ))
+        .send();
+        return Ok(see_other(&sequence_url));
+    }
+    if [&title, &text_content, &html_content]
+        .iter()
+        .any(|field| field.trim().is_empty())
+    {
+        FlashMessage::error("Please enter a title, a plain text and an HTML content.").send();
+        return Ok(see_other(&sequence_url));
+    }
+    if find_sequence(&pool, sequence_id)
+        .await
+        .map_err(e500)?
+        .is_none()
+    {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO automation_steps (sequence_id, day_offset, title, text_content, html_content)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (sequence_id, day_offset) DO NOTHING
+        "#,
+        sequence_id,
+        day_offset,
+        title,
+        text_content,
+        html_content
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to insert the automation step.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_inserted == 0 {
+        FlashMessage::error(format!(
+            "The sequence already has a step on day {}.",
+            day_offset
+        ))
+        .send();
+        return Ok(see_other(&sequence_url));
+    }
+    record_audit_entry(
+        &mut transaction,
+        Some(**user_id),
+        "add_automation_step",
+        &format!("{}/{}", sequence_id, day_offset),
+    )
+    .await
+    .context("Failed to record the new step in the audit log.")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to add an automation step.")
+        .map_err(e500)?;
+    FlashMessage::success(format!("The step of day {} has been added.", day_offset)).send();
+    Ok(see_other(&sequence_url))
+}
@@ -1,45 +1,122 @@
 use actix_web::http::header::ContentType;
 use actix_web::{web, HttpResponse};
 use anyhow::Context;
+use askama::Template;
+use chrono::NaiveDate;
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::authentication::UserId;
+use crate::analytics::{
+    get_overview, get_subscriber_growth, Granularity, GrowthPoint, Overview, RECENT_SIGNUP_DAYS,
+};
+use crate::authentication::AuthenticatedUser;
+use crate::delivery_progress::DeliveryProgress;
 use crate::routing_helpers::e500;
+use crate::templates::{filters, Branding, Layout};
+
+/// The number of days the growth chart covers
+const GROWTH_CHART_DAYS: i32 = 30;
+/// The height of the bars of the busiest day of the growth chart, in pixels
+const CHART_BAR_MAX_HEIGHT: i64 = 40;
+const CHART_BAR_WIDTH: usize = 8;
+
+#[derive(Template)]
+#[template(path = "dashboard.html")]
+struct DashboardPage<'a> {
+    layout: Layout<'a>,
+    username: &'a str,
+    overview: &'a Overview,
+    n_days: i32,
+    growth_days: i32,
+    growth_chart: GrowthChart,
+}
+
+impl DashboardPage<'_> {
+    /// What the delivery of the last issue is at, in words
+    fn delivery_status<'p>(&self, progress: &'p DeliveryProgress) -> &'p str {
+        match progress.status.as_str() {
+            "enqueuing" => "queueing recipients",
+            "delivering" if progress.is_complete() => "delivery complete",
+            status => status,
+        }
+    }
+}
+
+/// The signups of each period drawn as bars above the axis and the unsubscribes as bars below it
+struct GrowthChart {
+    width: usize,
+    height: i64,
+    bar_width: usize,
+    /// Where the bars of unsubscribes start
+    axis_y: i64,
+    bars: Vec<ChartBar>,
+}
+
+struct ChartBar {
+    period_start: NaiveDate,
+    signups: i64,
+    unsubscribes: i64,
+    x: usize,
+    signups_y: i64,
+    signups_height: i64,
+    unsubscribes_height: i64,
+}
 
 pub async fn admin_dashboard(
-    user_id: web::ReqData<UserId>,
+    user: AuthenticatedUser,
     pool: web::Data<PgPool>,
+    branding: web::Data<Branding>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let username = get_username(*user_id.into_inner(), &pool)
+    let overview = get_overview(&pool).await.map_err(e500)?;
+    let growth = get_subscriber_growth(&pool, Granularity::Day, GROWTH_CHART_DAYS)
         .await
         .map_err(e500)?;
+    let body = DashboardPage {
+        layout: Layout::new(&branding, "Admin dashboard"),
+        username: &user.username,
+        overview: &overview,
+        n_days: RECENT_SIGNUP_DAYS,
+        growth_days: GROWTH_CHART_DAYS,
+        growth_chart: growth_chart(&growth),
+    }
+    .render()
+    .map_err(e500)?;
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(format!(
-            r#"
-            <!DOCTYPE html>
-            <html lang="en">
-            <head>
-                <meta http-equiv="content-type" content="text/html; charset=utf-8">
-                <title>Admin dashboard</title>
-            </head>
-            <body>
-                <p>Welcome {username}!</p>
-                <p>Available actions:</p>
-                <ol>
-                    <li><a href="/admin/newsletters">Send new newsletter</a></li>
-                    <li><a href="/admin/password">Change password</a></li>
-                    <li>
-                        <form name="logoutForm" action="/admin/logout" method="post">
-                            <input type="submit" value="Logout">
-                        </form>
-                    </li>
-                </ol>
-            </body>
-            </html>
-            "#
-        )))
+        .body(body))
+}
+
+/// Scales the bars of the growth chart to the largest count
+fn growth_chart(points: &[GrowthPoint]) -> GrowthChart {
+    let largest = points
+        .iter()
+        .flat_map(|point| [point.signups, point.unsubscribes])
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let bars = points
+        .iter()
+        .enumerate()
+        .map(|(i, point)| {
+            let signups_height = point.signups * CHART_BAR_MAX_HEIGHT / largest;
+            ChartBar {
+                period_start: point.period_start,
+                signups: point.signups,
+                unsubscribes: point.unsubscribes,
+                x: i * CHART_BAR_WIDTH,
+                signups_y: CHART_BAR_MAX_HEIGHT - signups_height,
+                signups_height,
+                unsubscribes_height: point.unsubscribes * CHART_BAR_MAX_HEIGHT / largest,
+            }
+        })
+        .collect();
+    GrowthChart {
+        width: points.len() * CHART_BAR_WIDTH,
+        height: 2 * CHART_BAR_MAX_HEIGHT,
+        bar_width: CHART_BAR_WIDTH - 2,
+        axis_y: CHART_BAR_MAX_HEIGHT,
+        bars,
+    }
 }
 
 #[tracing::instrument(name = "Get username", skip(pool))]
@@ -57,3 +134,40 @@ pub async fn get_username(user_id: Uuid, pool: &PgPool) -> Result<String, anyhow
     .context("Failed to perform a query to retrieve username.")?;
     Ok(row.username)
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDate;
+
+    use super::growth_chart;
+    use crate::analytics::GrowthPoint;
+
+    fn point(day: u32, signups: i64, unsubscribes: i64) -> GrowthPoint {
+        GrowthPoint {
+            period_start: NaiveDate::from_ymd_opt(2026, 10, day).unwrap(),
+            signups,
+            unsubscribes,
+        }
+    }
+
+    #[test]
+    fn bars_are_scaled_to_the_largest_count() {
+        let chart = growth_chart(&[point(1, 4, 1), point(2, 2, 0)]);
+        assert_eq!((chart.width, chart.height, chart.bar_width), (16, 80, 6));
+        let first = &chart.bars[0];
+        assert_eq!((first.x, first.signups_y, first.signups_height), (0, 0, 40));
+        assert_eq!(first.unsubscribes_height, 10);
+        let second = &chart.bars[1];
+        assert_eq!(
+            (second.x, second.signups_y, second.signups_height),
+            (8, 20, 20)
+        );
+    }
+
+    #[test]
+    fn a_period_without_activity_draws_empty_bars() {
+        let chart = growth_chart(&[point(1, 0, 0)]);
+        assert_eq!(chart.bars[0].signups_height, 0);
+        assert_eq!(chart.bars[0].unsubscribes_height, 0);
+    }
+}
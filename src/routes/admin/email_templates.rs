@@ -0,0 +1,263 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context as _;
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::audit_log::record_audit_entry;
+use crate::authentication::UserId;
+use crate::email_templates::{
+    find_email_template, get_email_templates, validate_layout, EmailTemplate, CONTENT_PLACEHOLDER,
+};
+use crate::routing_helpers::{e500, see_other};
+use crate::templates::{collect_flash_messages, Branding, Layout};
+
+/// What the create form starts with, so that admins see where the content of issues goes
+const STARTER_HTML_LAYOUT: &str =
+    "<div>\n{{ content }}\n</div>\n<p><a href=\"{{ unsubscribe_url }}\">Unsubscribe</a></p>";
+const STARTER_TEXT_LAYOUT: &str = "{{ content }}\n\nUnsubscribe: {{ unsubscribe_url }}";
+
+#[derive(Template)]
+#[template(path = "email_templates.html")]
+struct EmailTemplatesPage<'a> {
+    layout: Layout<'a>,
+    messages: Vec<FlashMessage>,
+    templates: Vec<EmailTemplate>,
+    content_placeholder: &'static str,
+    starter_html_layout: &'static str,
+    starter_text_layout: &'static str,
+}
+
+#[derive(Template)]
+#[template(path = "email_template.html")]
+struct EmailTemplatePage<'a> {
+    layout: Layout<'a>,
+    messages: Vec<FlashMessage>,
+    template: EmailTemplate,
+    content_placeholder: &'static str,
+    title_placeholder: &'static str,
+    unsubscribe_url_placeholder: &'static str,
+}
+
+#[derive(serde::Deserialize)]
+pub struct EmailTemplateFormData {
+    name: String,
+    html_layout: String,
+    text_layout: String,
+}
+
+impl EmailTemplateFormData {
+    /// The trimmed name of the template, or why the form cannot be saved
+    fn validate(&self) -> Result<&str, String> {
+        let name = self.name.trim();
+        if name.is_empty() || name.len() > 256 {
+            return Err("The name must be between 1 and 256 characters.".into());
+        }
+        validate_layout(&self.html_layout, "HTML")?;
+        validate_layout(&self.text_layout, "plain text")?;
+        Ok(name)
+    }
+}
+
+/// Lists the email templates, with a form to create another
+pub async fn list_email_templates(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let templates = get_email_templates(&pool)
+        .await
+        .context("Failed to perform a query to retrieve the email templates.")
+        .map_err(e500)?;
+    let body = EmailTemplatesPage {
+        layout: Layout::new(&branding, "Email templates"),
+        messages: collect_flash_messages(&flash_messages),
+        templates,
+        content_placeholder: CONTENT_PLACEHOLDER,
+        starter_html_layout: STARTER_HTML_LAYOUT,
+        starter_text_layout: STARTER_TEXT_LAYOUT,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Creates a template, which issues can then be published with
+#[tracing::instrument(
+    name = "Create an email template",
+    skip(form, pool, user_id),
+    fields(name = %form.name)
+)]
+pub async fn create_email_template(
+    form: web::Form<EmailTemplateFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = match form.validate() {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/templates"));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let email_template_id = Uuid::new_v4();
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO email_templates (
+            email_template_id, name, html_layout, text_layout, created_at, updated_at
+        )
+        VALUES ($1, $2, $3, $4, now(), now())
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        email_template_id,
+        name,
+        form.html_layout,
+        form.text_layout
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to insert the email template.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_inserted == 0 {
+        FlashMessage::error(format!("There already is a template named {}.", name)).send();
+        return Ok(see_other("/admin/templates"));
+    }
+    record_audit_entry(
+        &mut transaction,
+        Some(**user_id),
+        "create_email_template",
+        &email_template_id.to_string(),
+    )
+    .await
+    .context("Failed to record the creation in the audit log.")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to create an email template.")
+        .map_err(e500)?;
+    FlashMessage::success(format!("The template {} has been created.", name)).send();
+    Ok(see_other("/admin/templates"))
+}
+
+/// Renders the form to edit a template
+pub async fn edit_email_template_form(
+    email_template_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let template = find_email_template(pool.get_ref(), email_template_id.into_inner())
+        .await
+        .context("Failed to perform a query to retrieve the email template.")
+        .map_err(e500)?;
+    let Some(template) = template else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let body = EmailTemplatePage {
+        layout: Layout::new(&branding, "Email template"),
+        messages: collect_flash_messages(&flash_messages),
+        template,
+        content_placeholder: CONTENT_PLACEHOLDER,
+        title_placeholder: "{{ title }}",
+        unsubscribe_url_placeholder: "{{ unsubscribe_url }}",
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Saves the changes to a template. Issues already published keep the layout they were sent with;
+/// the changes apply to the issues published from now on.
+#[tracing::instrument(
+    name = "Update an email template",
+    skip(form, pool, user_id),
+    fields(name = %form.name)
+)]
+pub async fn update_email_template(
+    email_template_id: web::Path<Uuid>,
+    form: web::Form<EmailTemplateFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email_template_id = email_template_id.into_inner();
+    let edit_form = format!("/admin/templates/{}", email_template_id);
+    let name = match form.validate() {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&edit_form));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let name_taken = sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1 FROM email_templates WHERE name = $1 AND email_template_id <> $2
+        ) AS "name_taken!"
+        "#,
+        name,
+        email_template_id
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed to perform a query to check the name of the email template.")
+    .map_err(e500)?;
+    if name_taken {
+        FlashMessage::error(format!("There already is a template named {}.", name)).send();
+        return Ok(see_other(&edit_form));
+    }
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE email_templates
+        SET name = $2, html_layout = $3, text_layout = $4, updated_at = now()
+        WHERE email_template_id = $1
+        "#,
+        email_template_id,
+        name,
+        form.html_layout,
+        form.text_layout
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to update the email template.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated == 0 {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    record_audit_entry(
+        &mut transaction,
+        Some(**user_id),
+        "update_email_template",
+        &email_template_id.to_string(),
+    )
+    .await
+    .context("Failed to record the change in the audit log.")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update an email template.")
+        .map_err(e500)?;
+    FlashMessage::success(format!("The template {} has been saved.", name)).send();
+    Ok(see_other(&edit_form))
+}
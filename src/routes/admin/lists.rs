@@ -0,0 +1,114 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context as _;
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::audit_log::record_audit_entry;
+use crate::authentication::UserId;
+use crate::domain::NewsletterSlug;
+use crate::newsletters::{get_newsletters, Newsletter};
+use crate::routing_helpers::{e500, see_other};
+use crate::templates::{collect_flash_messages, Branding, Layout};
+
+#[derive(serde::Deserialize)]
+pub struct NewsletterFormData {
+    name: String,
+    slug: String,
+}
+
+#[derive(Template)]
+#[template(path = "newsletter_lists.html")]
+struct NewsletterListsPage<'a> {
+    layout: Layout<'a>,
+    messages: Vec<FlashMessage>,
+    /// The first one is the default newsletter
+    newsletters: Vec<Newsletter>,
+}
+
+/// Lists the newsletters run by the deployment, with a form to start a new one
+pub async fn list_newsletters(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = NewsletterListsPage {
+        layout: Layout::new(&branding, "Newsletters"),
+        messages: collect_flash_messages(&flash_messages),
+        newsletters: get_newsletters(&pool).await.map_err(e500)?,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Starts a new newsletter, which subscribers and issues can then pick by its slug
+#[tracing::instrument(
+    name = "Create a newsletter",
+    skip(form, pool, user_id),
+    fields(slug = %form.slug)
+)]
+pub async fn create_newsletter(
+    form: web::Form<NewsletterFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.0.name.trim();
+    if name.is_empty() || name.len() > 256 {
+        FlashMessage::error("The name must be between 1 and 256 characters.").send();
+        return Ok(see_other("/admin/lists"));
+    }
+    let slug = match NewsletterSlug::parse(form.0.slug) {
+        Ok(slug) => slug,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/lists"));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let newsletter_id = Uuid::new_v4();
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO newsletters (newsletter_id, slug, name)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (slug) DO NOTHING
+        "#,
+        newsletter_id,
+        slug.as_ref(),
+        name
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to insert the newsletter.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_inserted == 0 {
+        FlashMessage::error(format!("The slug {} is already taken.", slug.as_ref())).send();
+        return Ok(see_other("/admin/lists"));
+    }
+    record_audit_entry(
+        &mut transaction,
+        Some(**user_id),
+        "create_newsletter",
+        &newsletter_id.to_string(),
+    )
+    .await
+    .context("Failed to record the creation in the audit log.")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to create a newsletter.")
+        .map_err(e500)?;
+    FlashMessage::success(format!("The newsletter {} has been created.", name)).send();
+    Ok(see_other("/admin/lists"))
+}
@@ -1,11 +1,29 @@
-use actix_web::HttpResponse;
+use actix_web::{web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
+use sqlx::PgPool;
 
-use crate::routing_helpers::see_other;
+use crate::authentication::{
+    remember_me_removal_cookie, revoke_persistent_login, REMEMBER_ME_COOKIE,
+};
+use crate::routing_helpers::{e500, see_other};
 use crate::session_state::TypedSession;
 
-pub async fn log_out(session: TypedSession) -> HttpResponse {
+pub async fn log_out(
+    session: TypedSession,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
     session.log_out();
+    let mut response = see_other("/login");
+    // logging out also forgets the browser
+    if let Some(token) = request.cookie(REMEMBER_ME_COOKIE) {
+        revoke_persistent_login(&pool, token.value())
+            .await
+            .map_err(e500)?;
+        response
+            .add_cookie(&remember_me_removal_cookie())
+            .map_err(e500)?;
+    }
     FlashMessage::info("You have successfully logged out.").send();
-    see_other("/login")
+    Ok(response)
 }
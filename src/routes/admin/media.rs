@@ -0,0 +1,222 @@
+use std::fmt::Formatter;
+
+use actix_web::http::header::{self, ContentType, Header};
+use actix_web::http::StatusCode;
+use actix_web::web::BytesMut;
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context as _;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use futures_util::StreamExt;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::UserId;
+use crate::configuration::MediaSettings;
+use crate::error_handling::{self, NegotiatedError, UNEXPECTED_ERROR_MESSAGE};
+use crate::media_storage::{ImageType, MediaStorage};
+use crate::routing_helpers::{e500, see_other};
+use crate::templates::{collect_flash_messages, Branding, Layout};
+
+/// Room for the boundaries and headers of the multipart body around the image
+const MULTIPART_OVERHEAD_BYTES: usize = 16 * 1024;
+
+pub struct MediaItem {
+    media_id: Uuid,
+    file_name: String,
+    size_bytes: i32,
+    url: String,
+    uploaded_at: DateTime<Utc>,
+}
+
+impl MediaItem {
+    fn size_kib(&self) -> i32 {
+        (self.size_bytes + 1023) / 1024
+    }
+}
+
+#[derive(Template)]
+#[template(path = "media_library.html")]
+struct MediaLibraryPage<'a> {
+    layout: Layout<'a>,
+    messages: Vec<FlashMessage>,
+    max_file_bytes: usize,
+    media: Vec<MediaItem>,
+}
+
+/// Lists the uploaded images with their URLs, most recent first, with a form to upload another
+pub async fn media_library(
+    pool: web::Data<PgPool>,
+    settings: web::Data<MediaSettings>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = MediaLibraryPage {
+        layout: Layout::new(&branding, "Media library"),
+        messages: collect_flash_messages(&flash_messages),
+        max_file_bytes: settings.max_file_bytes,
+        media: get_media(&pool).await.map_err(e500)?,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// `POST /admin/media`: stores the image of the `file` field of a `multipart/form-data` body and
+/// adds it to the media library. Clients asking for JSON get its public URL; browsers are sent
+/// back to the library, which shows it.
+#[tracing::instrument(
+    name = "Upload an image",
+    skip_all,
+    fields(user_id=%&*user_id)
+)]
+pub async fn upload_media(
+    request: HttpRequest,
+    mut payload: web::Payload,
+    pool: web::Data<PgPool>,
+    storage: web::Data<MediaStorage>,
+    settings: web::Data<MediaSettings>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, MediaUploadError> {
+    let max_file_bytes = settings.max_file_bytes;
+    let boundary = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(|content_type| multer::parse_boundary(content_type).ok())
+        .ok_or_else(|| {
+            MediaUploadError::ValidationError("The image must be sent as a multipart form.".into())
+        })?;
+    // the body is read in full first, since the payload cannot be handed to another task
+    let mut body = BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.context("Failed to read the uploaded image.")?;
+        if body.len() + chunk.len() > max_file_bytes + MULTIPART_OVERHEAD_BYTES {
+            return Err(MediaUploadError::TooLarge(max_file_bytes));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    let body = body.freeze();
+    let stream = futures_util::stream::once(async move { Ok::<_, std::io::Error>(body) });
+    let mut multipart = multer::Multipart::new(stream, boundary);
+    let mut upload = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| MediaUploadError::ValidationError(e.to_string()))?
+    {
+        if field.name() != Some("file") {
+            continue;
+        }
+        let file_name = field.file_name().unwrap_or_default().to_owned();
+        let content = field
+            .bytes()
+            .await
+            .map_err(|e| MediaUploadError::ValidationError(e.to_string()))?;
+        upload = Some((file_name, content));
+        break;
+    }
+    let (file_name, content) = upload
+        .filter(|(_, content)| !content.is_empty())
+        .ok_or_else(|| MediaUploadError::ValidationError("Please pick an image.".into()))?;
+    if content.len() > max_file_bytes {
+        return Err(MediaUploadError::TooLarge(max_file_bytes));
+    }
+    let image_type = ImageType::sniff(&content).ok_or_else(|| {
+        MediaUploadError::ValidationError(
+            "Only PNG, JPEG, GIF and WebP images can be uploaded.".into(),
+        )
+    })?;
+
+    let media_id = Uuid::new_v4();
+    let key = format!("{}.{}", media_id, image_type.extension());
+    let size_bytes = content.len() as i32;
+    let url = storage.store(&key, image_type, content).await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO media (
+            media_id, file_name, content_type, size_bytes, url, uploaded_by, uploaded_at
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, now())
+        "#,
+        media_id,
+        file_name,
+        image_type.content_type(),
+        size_bytes,
+        url,
+        **user_id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to record the uploaded image.")?;
+
+    let prefers_json = header::Accept::parse(&request).map_or(false, |accept| {
+        accept.preference().essence_str() == "application/json"
+    });
+    if prefers_json {
+        return Ok(HttpResponse::Created().json(serde_json::json!({
+            "media_id": media_id,
+            "url": url,
+        })));
+    }
+    FlashMessage::success(format!("The image has been uploaded: {}", url)).send();
+    Ok(see_other("/admin/media"))
+}
+
+#[tracing::instrument(name = "Get the media library", skip(pool))]
+async fn get_media(pool: &PgPool) -> Result<Vec<MediaItem>, anyhow::Error> {
+    let media = sqlx::query_as!(
+        MediaItem,
+        r#"
+        SELECT media_id, file_name, size_bytes, url, uploaded_at
+        FROM media
+        ORDER BY uploaded_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve the media library.")?;
+    Ok(media)
+}
+
+#[derive(thiserror::Error)]
+pub enum MediaUploadError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("The image must not be larger than {} KiB.", .0 / 1024)]
+    TooLarge(usize),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for MediaUploadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_handling::error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for MediaUploadError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            MediaUploadError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            MediaUploadError::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            MediaUploadError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        self.negotiated(self.browser_response())
+    }
+}
+
+impl NegotiatedError for MediaUploadError {
+    fn client_message(&self) -> String {
+        match self {
+            MediaUploadError::UnexpectedError(_) => UNEXPECTED_ERROR_MESSAGE.to_string(),
+            e => e.to_string(),
+        }
+    }
+}
@@ -1,9 +1,25 @@
+mod automations;
 mod dashboard;
+mod email_templates;
+mod lists;
 mod logout;
+mod media;
 mod newsletters;
 mod password;
+mod security;
+mod stats;
+mod subscribers;
+mod users;
 
+pub use automations::*;
 pub use dashboard::*;
+pub use email_templates::*;
+pub use lists::*;
 pub use logout::log_out;
+pub use media::*;
 pub use newsletters::*;
 pub use password::*;
+pub use security::*;
+pub use stats::*;
+pub use subscribers::*;
+pub use users::*;
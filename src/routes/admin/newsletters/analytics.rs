@@ -0,0 +1,37 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::analytics::{get_issue_analytics, IssueAnalytics};
+use crate::routing_helpers::e500;
+use crate::templates::{filters, Branding, Layout};
+
+#[derive(Template)]
+#[template(path = "analytics.html")]
+struct AnalyticsPage<'a> {
+    layout: Layout<'a>,
+    analytics: IssueAnalytics,
+}
+
+/// Reports the delivery, open and click figures of an issue
+pub async fn newsletter_analytics(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let Some(analytics) = get_issue_analytics(&pool, issue_id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let body = AnalyticsPage {
+        layout: Layout::new(&branding, "Issue analytics"),
+        analytics,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
@@ -0,0 +1,271 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::routing_helpers::{e500, see_other};
+
+/// Pauses the delivery of an issue; queued tasks are kept until the delivery is resumed
+#[tracing::instrument(name = "Pause newsletter delivery", skip(pool))]
+pub async fn pause_newsletter_delivery(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let updated = transition_status(&pool, issue_id, &["delivering"], "paused")
+        .await
+        .map_err(e500)?;
+    respond(
+        &pool,
+        issue_id,
+        updated,
+        "The delivery has been paused.",
+        "Only an issue that is being delivered can be paused.",
+    )
+    .await
+}
+
+/// Resumes the delivery of a paused issue
+#[tracing::instrument(name = "Resume newsletter delivery", skip(pool))]
+pub async fn resume_newsletter_delivery(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let updated = transition_status(&pool, issue_id, &["paused"], "delivering")
+        .await
+        .map_err(e500)?;
+    respond(
+        &pool,
+        issue_id,
+        updated,
+        "The delivery has been resumed.",
+        "Only a paused delivery can be resumed.",
+    )
+    .await
+}
+
+/// Cancels the delivery of an issue, dropping every task that has not been executed yet
+#[tracing::instrument(name = "Cancel newsletter delivery", skip(pool))]
+pub async fn cancel_newsletter_delivery(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'cancelled'
+        WHERE
+            newsletter_issue_id = $1 AND
+            status IN ('enqueuing', 'delivering', 'paused')
+        "#,
+        issue_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to cancel the newsletter issue.")
+    .map_err(e500)?
+    .rows_affected();
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to drop the pending delivery tasks.")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to cancel a delivery.")
+        .map_err(e500)?;
+    respond(
+        &pool,
+        issue_id,
+        n_updated > 0,
+        "The delivery has been cancelled.",
+        "Only a delivery in progress can be cancelled.",
+    )
+    .await
+}
+
+/// Sends an issue straight away even if the send window is closed, e.g. for urgent news
+#[tracing::instrument(name = "Ignore the send window", skip(pool))]
+pub async fn ignore_send_window(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET ignores_send_window = true
+        WHERE
+            newsletter_issue_id = $1 AND
+            status IN ('enqueuing', 'delivering', 'paused')
+        "#,
+        issue_id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the newsletter issue.")
+    .map_err(e500)?
+    .rows_affected();
+    respond(
+        &pool,
+        issue_id,
+        n_updated > 0,
+        "The issue is sent without waiting for the send window.",
+        "Only a delivery in progress can ignore the send window.",
+    )
+    .await
+}
+
+/// Queues the failed deliveries of an issue again, so that recipients who missed it during an
+/// outage get it without sending it twice to everyone else. Failures of subscribers who are no
+/// longer confirmed are kept as they are.
+#[tracing::instrument(name = "Retry failed newsletter deliveries", skip(pool))]
+pub async fn retry_failed_deliveries(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let issue = sqlx::query!(
+        r#"
+        SELECT newsletter_id, status
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        FOR UPDATE
+        "#,
+        issue_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to perform a query to retrieve the newsletter issue.")
+    .map_err(e500)?;
+    let Some(issue) = issue else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    if !matches!(issue.status.as_str(), "delivering" | "paused") {
+        FlashMessage::error(
+            "Only the failures of a delivery in progress or paused can be retried.",
+        )
+        .send();
+        return Ok(status_page(issue_id));
+    }
+    let n_queued = sqlx::query!(
+        r#"
+        WITH retried AS (
+            DELETE FROM issue_delivery_failures
+            WHERE
+                newsletter_issue_id = $1 AND
+                subscriber_email IN (
+                    SELECT email
+                    FROM subscriptions
+                    WHERE
+                        status = 'confirmed' AND
+                        deleted_at IS NULL AND
+                        newsletter_id = $2
+                )
+            RETURNING subscriber_email
+        )
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email, newsletter_id)
+        SELECT $1, subscriber_email, $2
+        FROM retried
+        ON CONFLICT DO NOTHING
+        "#,
+        issue_id,
+        issue.newsletter_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to queue the failed deliveries again.")
+    .map_err(e500)?
+    .rows_affected();
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to retry failed deliveries.")
+        .map_err(e500)?;
+    if n_queued > 0 {
+        FlashMessage::success(format!("Failed deliveries queued again: {}.", n_queued)).send();
+    } else {
+        FlashMessage::error("There are no failed deliveries to retry.").send();
+    }
+    Ok(status_page(issue_id))
+}
+
+/// Moves an issue to `to` if its current status is one of `from`. Returns whether the issue was updated.
+async fn transition_status(
+    pool: &PgPool,
+    issue_id: Uuid,
+    from: &[&str],
+    to: &str,
+) -> Result<bool, anyhow::Error> {
+    let from: Vec<String> = from.iter().map(|s| s.to_string()).collect();
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = $3
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = ANY($2)
+        "#,
+        issue_id,
+        &from,
+        to
+    )
+    .execute(pool)
+    .await
+    .context("Failed to update the status of the newsletter issue.")?
+    .rows_affected();
+    Ok(n_updated > 0)
+}
+
+/// Redirects to the status page with a flash message, or returns a 404 if the issue does not exist
+async fn respond(
+    pool: &PgPool,
+    issue_id: Uuid,
+    updated: bool,
+    success_message: &str,
+    error_message: &str,
+) -> Result<HttpResponse, actix_web::Error> {
+    if updated {
+        FlashMessage::success(success_message).send();
+    } else if issue_exists(pool, issue_id).await.map_err(e500)? {
+        FlashMessage::error(error_message).send();
+    } else {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    Ok(status_page(issue_id))
+}
+
+fn status_page(issue_id: Uuid) -> HttpResponse {
+    see_other(&format!("/admin/newsletters/{}/status", issue_id))
+}
+
+async fn issue_exists(pool: &PgPool, issue_id: Uuid) -> Result<bool, anyhow::Error> {
+    let row = sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve the newsletter issue.")?;
+    Ok(row.is_some())
+}
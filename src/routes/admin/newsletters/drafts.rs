@@ -0,0 +1,377 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context as _;
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::UserId;
+use crate::configuration::{HtmlSanitizationSettings, PayloadLimitSettings};
+use crate::domain::SubscriberTag;
+use crate::email_templates::pick_email_template;
+use crate::newsletter_content::{
+    prepare_html_content, remove_view_in_browser_link_html, remove_view_in_browser_link_text,
+};
+use crate::newsletters::find_newsletter;
+use crate::routes::admin::newsletters::get::{newsletter_form, DraftContent};
+use crate::routes::admin::newsletters::revisions::{diff_lines, record_draft_revision, DiffLine};
+use crate::routing_helpers::{e400, e413, e500, see_other};
+use crate::templates::{Branding, Layout};
+use crate::url_builder::UrlBuilder;
+
+#[derive(Template)]
+#[template(path = "draft_conflict.html")]
+struct DraftConflictPage<'a> {
+    layout: Layout<'a>,
+    current: &'a DraftContent,
+    yours: &'a DraftContent,
+    skip_css_inlining: bool,
+    title_diff: Vec<DiffLine>,
+    text_content_diff: Vec<DiffLine>,
+    html_content_diff: Vec<DiffLine>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct DraftFormData {
+    title: String,
+    text_content: String,
+    html_content: String,
+    draft_id: Option<Uuid>,
+    /// Slug of the newsletter the draft is written for; the default newsletter if empty
+    #[serde(default)]
+    newsletter: String,
+    #[serde(default)]
+    segment: String,
+    #[serde(default)]
+    click_tracking: bool,
+    /// The version of the draft the form was filled with, if it was
+    version: Option<i32>,
+    #[serde(default)]
+    skip_css_inlining: bool,
+    /// The id of the template the draft is to be published with; none if empty
+    #[serde(default)]
+    email_template: String,
+}
+
+/// Saves the content of the newsletter form as a draft, without sending it to anybody
+#[tracing::instrument(name = "Save a newsletter draft", skip_all, fields(user_id=%&*user_id))]
+pub async fn save_newsletter_draft(
+    form: web::Form<DraftFormData>,
+    pool: web::Data<PgPool>,
+    payload_limits: web::Data<PayloadLimitSettings>,
+    html_sanitization: web::Data<HtmlSanitizationSettings>,
+    branding: web::Data<Branding>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let DraftFormData {
+        title,
+        text_content,
+        html_content,
+        draft_id,
+        newsletter,
+        segment,
+        click_tracking,
+        version,
+        skip_css_inlining,
+        email_template,
+    } = form.0;
+    payload_limits
+        .check_newsletter_html(&html_content)
+        .map_err(e413)?;
+    let html_content = prepare_html_content(&html_content, skip_css_inlining, &html_sanitization);
+    let newsletter_id = find_newsletter(pool.get_ref(), &newsletter)
+        .await
+        .context("Failed to look up the newsletter.")
+        .map_err(e500)?
+        .ok_or_else(|| e400(format!("There is no newsletter named {}.", newsletter)))?
+        .newsletter_id;
+    let segment = SubscriberTag::parse_optional(segment).map_err(e400)?;
+    let segment = segment.as_ref().map(AsRef::as_ref);
+    let email_template_id = pick_email_template(&pool, &email_template)
+        .await
+        .context("Failed to look up the email template.")
+        .map_err(e500)?
+        .map_err(e400)?
+        .map(|template| template.email_template_id);
+    let draft_id = match draft_id {
+        Some(draft_id) => {
+            let updated = update_draft(
+                &pool,
+                draft_id,
+                newsletter_id,
+                &title,
+                &text_content,
+                &html_content,
+                segment,
+                click_tracking,
+                email_template_id,
+                version,
+                **user_id,
+            )
+            .await
+            .context("Failed to update the newsletter draft")
+            .map_err(e500)?;
+            if !updated {
+                let current = get_draft(&pool, draft_id).await.map_err(e500)?;
+                if let (Some(current), Some(_)) = (current, version) {
+                    let yours = DraftContent {
+                        draft_id,
+                        newsletter,
+                        title,
+                        text_content,
+                        html_content,
+                        segment: segment.map(ToOwned::to_owned),
+                        click_tracking,
+                        version: current.version,
+                        email_template_id,
+                    };
+                    return render_draft_conflict(&branding, &current, &yours, skip_css_inlining);
+                }
+                FlashMessage::error("The draft no longer exists or has already been published.")
+                    .send();
+                return Ok(see_other("/admin/newsletters"));
+            }
+            draft_id
+        }
+        None => insert_draft(
+            &pool,
+            newsletter_id,
+            &title,
+            &text_content,
+            &html_content,
+            segment,
+            click_tracking,
+            email_template_id,
+        )
+        .await
+        .context("Failed to store the newsletter draft")
+        .map_err(e500)?,
+    };
+    FlashMessage::success("The draft has been saved.").send();
+    Ok(see_other(&format!(
+        "/admin/newsletters/drafts/{}",
+        draft_id
+    )))
+}
+
+/// Renders the page shown when a draft was edited by someone else since its form was filled: what
+/// saving the submitted content would change in the current version, and a form to merge both.
+/// The form carries the current version, so that saving it overwrites what was compared against.
+fn render_draft_conflict(
+    branding: &Branding,
+    current: &DraftContent,
+    yours: &DraftContent,
+    skip_css_inlining: bool,
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = DraftConflictPage {
+        layout: Layout::new(branding, "Conflicting edits"),
+        current,
+        yours,
+        skip_css_inlining,
+        title_diff: diff_lines(&current.title, &yours.title),
+        text_content_diff: diff_lines(&current.text_content, &yours.text_content),
+        html_content_diff: diff_lines(&current.html_content, &yours.html_content),
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Conflict()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Renders the newsletter form pre-filled with the content of a draft
+pub async fn edit_newsletter_draft(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = get_draft(&pool, draft_id.into_inner())
+        .await
+        .map_err(e500)?;
+    match draft {
+        Some(draft) => newsletter_form(&pool, &branding, flash_messages, Some(draft)).await,
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+/// Copies an issue into a new draft, so that its layout can be reused for the next one
+#[tracing::instrument(name = "Duplicate a newsletter issue", skip(pool, urls))]
+pub async fn duplicate_newsletter_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    urls: web::Data<UrlBuilder>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let issue = sqlx::query!(
+        r#"
+        SELECT newsletter_id, title, text_content, html_content, segment, click_tracking
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("Failed to perform a query to retrieve the newsletter issue.")
+    .map_err(e500)?;
+    let Some(issue) = issue else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    // the link to the archived version of the copied issue must not end up in the new one
+    let issue_url = urls.issue_url(issue_id);
+    let draft_id = insert_draft(
+        &pool,
+        issue.newsletter_id,
+        &format!("Copy of {}", issue.title),
+        &remove_view_in_browser_link_text(&issue.text_content, &issue_url),
+        &remove_view_in_browser_link_html(&issue.html_content, &issue_url),
+        issue.segment.as_deref(),
+        issue.click_tracking,
+        // the layout of the template is already part of the content of the copied issue
+        None,
+    )
+    .await
+    .context("Failed to store the newsletter draft")
+    .map_err(e500)?;
+    FlashMessage::success("The issue has been copied into a new draft.").send();
+    Ok(see_other(&format!(
+        "/admin/newsletters/drafts/{}",
+        draft_id
+    )))
+}
+
+/// Stores a new draft, returning its id
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(skip_all)]
+pub async fn insert_draft(
+    pool: &PgPool,
+    newsletter_id: Uuid,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+    segment: Option<&str>,
+    click_tracking: bool,
+    email_template_id: Option<Uuid>,
+) -> Result<Uuid, sqlx::Error> {
+    let draft_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            segment,
+            click_tracking,
+            newsletter_id,
+            email_template_id,
+            status
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, 'draft')
+        "#,
+        draft_id,
+        title,
+        text_content,
+        html_content,
+        segment,
+        click_tracking,
+        newsletter_id,
+        email_template_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(draft_id)
+}
+
+/// Updates the content of a draft, provided it is still at the expected version if one is given,
+/// keeping the content it replaces as a revision. Returns `false` if there is no such draft.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(skip_all)]
+async fn update_draft(
+    pool: &PgPool,
+    draft_id: Uuid,
+    newsletter_id: Uuid,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+    segment: Option<&str>,
+    click_tracking: bool,
+    email_template_id: Option<Uuid>,
+    version: Option<i32>,
+    user_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    record_draft_revision(
+        &mut transaction,
+        draft_id,
+        version,
+        title,
+        text_content,
+        html_content,
+        user_id,
+    )
+    .await?;
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            title = $2,
+            text_content = $3,
+            html_content = $4,
+            segment = $5,
+            click_tracking = $6,
+            newsletter_id = $8,
+            email_template_id = $9,
+            content_blocks = NULL,
+            version = version + 1
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'draft' AND
+            ($7::INTEGER IS NULL OR version = $7)
+        "#,
+        draft_id,
+        title,
+        text_content,
+        html_content,
+        segment,
+        click_tracking,
+        version,
+        newsletter_id,
+        email_template_id
+    )
+    .execute(&mut transaction)
+    .await?
+    .rows_affected();
+    transaction.commit().await?;
+    Ok(n_updated > 0)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_draft(pool: &PgPool, draft_id: Uuid) -> Result<Option<DraftContent>, anyhow::Error> {
+    let draft = sqlx::query_as!(
+        DraftContent,
+        r#"
+        SELECT
+            newsletter_issue_id AS draft_id,
+            (SELECT slug FROM newsletters n WHERE n.newsletter_id = i.newsletter_id) AS "newsletter!",
+            title,
+            text_content,
+            html_content,
+            segment,
+            click_tracking,
+            version,
+            email_template_id
+        FROM newsletter_issues i
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'draft'
+        "#,
+        draft_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve the newsletter draft.")?;
+    Ok(draft)
+}
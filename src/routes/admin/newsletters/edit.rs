@@ -0,0 +1,133 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::UserId;
+use crate::configuration::{HtmlSanitizationSettings, PayloadLimitSettings};
+use crate::newsletter_content::prepare_html_content;
+use crate::routes::admin::newsletters::revisions::record_draft_revision;
+use crate::routes::ApiError;
+
+#[derive(serde::Deserialize)]
+pub struct IssueEdit {
+    title: String,
+    text_content: String,
+    html_content: String,
+    /// The version of the issue the edit was made from
+    version: i32,
+    /// Keeps the `<style>` blocks as they are instead of inlining their rules
+    #[serde(default)]
+    skip_css_inlining: bool,
+}
+
+/// `PUT /admin/newsletters/{issue_id}`: replaces the title and content of a draft. Edits are
+/// rejected with a conflict if the draft changed since `version`, or once it has been published:
+/// its delivery starts being queued straight away.
+#[tracing::instrument(
+    name = "Edit a newsletter issue",
+    skip(edit, pool, html_sanitization, user_id),
+    fields(user_id=%&*user_id)
+)]
+pub async fn edit_newsletter_issue(
+    issue_id: web::Path<Uuid>,
+    edit: web::Json<IssueEdit>,
+    pool: web::Data<PgPool>,
+    payload_limits: web::Data<PayloadLimitSettings>,
+    html_sanitization: web::Data<HtmlSanitizationSettings>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, ApiError> {
+    let issue_id = issue_id.into_inner();
+    let mut edit = edit.into_inner();
+    if edit.title.trim().is_empty() {
+        return Err(ApiError::BadRequest("The title cannot be empty.".into()));
+    }
+    payload_limits
+        .check_newsletter_html(&edit.html_content)
+        .map_err(ApiError::PayloadTooLarge)?;
+    edit.html_content = prepare_html_content(
+        &edit.html_content,
+        edit.skip_css_inlining,
+        &html_sanitization,
+    );
+    if let Some(version) = update_draft_content(&pool, issue_id, &edit, **user_id).await? {
+        return Ok(HttpResponse::Ok().json(serde_json::json!({
+            "newsletter_issue_id": issue_id,
+            "version": version
+        })));
+    }
+    let issue = sqlx::query!(
+        "SELECT status, version FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        issue_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to perform a query to retrieve the newsletter issue.")?;
+    match issue {
+        None => Err(ApiError::NotFound(
+            "There is no newsletter issue with this id.".into(),
+        )),
+        Some(issue) if issue.status != "draft" => Err(ApiError::Conflict(
+            "The issue has already been published and can no longer be edited.".into(),
+        )),
+        Some(issue) => Err(ApiError::Conflict(format!(
+            "The issue was edited in the meantime: it is now at version {}.",
+            issue.version
+        ))),
+    }
+}
+
+/// Updates a draft that is still at the expected version, keeping the content it replaces as a
+/// revision, and returns its new version; returns `None` if there is no such draft
+#[tracing::instrument(skip(pool, edit))]
+async fn update_draft_content(
+    pool: &PgPool,
+    issue_id: Uuid,
+    edit: &IssueEdit,
+    user_id: Uuid,
+) -> Result<Option<i32>, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    record_draft_revision(
+        &mut transaction,
+        issue_id,
+        Some(edit.version),
+        &edit.title,
+        &edit.text_content,
+        &edit.html_content,
+        user_id,
+    )
+    .await
+    .context("Failed to keep the current content of the newsletter issue.")?;
+    let updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            title = $2,
+            text_content = $3,
+            html_content = $4,
+            content_blocks = NULL,
+            version = version + 1
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'draft' AND
+            version = $5
+        RETURNING version
+        "#,
+        issue_id,
+        edit.title,
+        edit.text_content,
+        edit.html_content,
+        edit.version
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to update the newsletter issue.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the SQL transaction to edit a newsletter issue.")?;
+    Ok(updated.map(|issue| issue.version))
+}
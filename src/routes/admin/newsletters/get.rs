@@ -1,59 +1,171 @@
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
-use actix_web_flash_messages::IncomingFlashMessages;
-use std::fmt::Write;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::email_templates::{
+    email_template_options, get_email_templates, parse_email_template_id, EmailTemplate,
+};
+use crate::newsletters::{get_newsletters, newsletter_options, Newsletter};
+use crate::routing_helpers::e500;
+use crate::templates::{collect_flash_messages, Branding, Layout, SelectOption};
+
+#[derive(Template)]
+#[template(path = "newsletter_form.html")]
+struct NewsletterFormPage<'a> {
+    layout: Layout<'a>,
+    messages: Vec<FlashMessage>,
+    newsletter_options: Vec<SelectOption>,
+    email_template_options: Vec<SelectOption>,
+    content: NewsletterFormContent,
+    errors: NewsletterFormErrors,
+    idempotency_key: &'a str,
+}
+
+/// The content of a draft to pre-fill the newsletter form with
+pub struct DraftContent {
+    pub draft_id: Uuid,
+    /// The slug of the newsletter the draft is written for
+    pub newsletter: String,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub segment: Option<String>,
+    pub click_tracking: bool,
+    pub version: i32,
+    /// The template the issue is to be published with
+    pub email_template_id: Option<Uuid>,
+}
+
+/// What the fields of the newsletter form are filled with
+#[derive(Default)]
+pub struct NewsletterFormContent {
+    pub draft_id: Option<Uuid>,
+    /// The version of the draft the form was filled with
+    pub version: Option<i32>,
+    /// The slug of the newsletter the issue goes to; the default newsletter if empty
+    pub newsletter: String,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub segment: String,
+    pub click_tracking: bool,
+    /// `HH:MM` in the time zone of each subscriber; sent right away if empty
+    pub local_send_time: String,
+    pub utm_source: String,
+    pub utm_medium: String,
+    pub utm_campaign: String,
+    /// Keeps the `<style>` blocks as they are instead of inlining their rules
+    pub skip_css_inlining: bool,
+    /// The id of the template the issue is published with; none if empty
+    pub email_template: String,
+}
+
+impl From<DraftContent> for NewsletterFormContent {
+    fn from(draft: DraftContent) -> Self {
+        Self {
+            draft_id: Some(draft.draft_id),
+            version: Some(draft.version),
+            newsletter: draft.newsletter,
+            title: draft.title,
+            text_content: draft.text_content,
+            html_content: draft.html_content,
+            segment: draft.segment.unwrap_or_default(),
+            click_tracking: draft.click_tracking,
+            email_template: draft
+                .email_template_id
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Why the fields of a submitted newsletter form are invalid; each error is shown under its field
+#[derive(Default)]
+pub struct NewsletterFormErrors {
+    pub newsletter: Option<String>,
+    pub title: Option<String>,
+    pub text_content: Option<String>,
+    pub html_content: Option<String>,
+    pub segment: Option<String>,
+    pub local_send_time: Option<String>,
+    pub email_template: Option<String>,
+}
+
+impl NewsletterFormErrors {
+    pub fn is_empty(&self) -> bool {
+        self.newsletter.is_none()
+            && self.title.is_none()
+            && self.text_content.is_none()
+            && self.html_content.is_none()
+            && self.segment.is_none()
+            && self.local_send_time.is_none()
+            && self.email_template.is_none()
+    }
+}
 
 pub async fn publish_newsletter_form(
+    pool: web::Data<PgPool>,
     flash_messages: IncomingFlashMessages,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    newsletter_form(&pool, &branding, flash_messages, None).await
+}
+
+/// Renders the newsletter form, pre-filled with the content of a draft if one is provided. Every
+/// form gets a fresh idempotency key, so that submitting it twice only publishes the issue once.
+pub async fn newsletter_form(
+    pool: &PgPool,
+    branding: &Branding,
+    flash_messages: IncomingFlashMessages,
+    draft: Option<DraftContent>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletters = get_newsletters(pool).await.map_err(e500)?;
+    let email_templates = get_email_templates(pool).await.map_err(e500)?;
+    render_newsletter_form(
+        branding,
+        StatusCode::OK,
+        collect_flash_messages(&flash_messages),
+        &newsletters,
+        &email_templates,
+        draft.map(Into::into).unwrap_or_default(),
+        NewsletterFormErrors::default(),
+        &Uuid::new_v4().to_string(),
+    )
+}
+
+/// Renders the newsletter form with the given content, errors and idempotency key; forms
+/// redisplayed after a validation error keep the key they were submitted with
+#[allow(clippy::too_many_arguments)]
+pub fn render_newsletter_form(
+    branding: &Branding,
+    status: StatusCode,
+    messages: Vec<FlashMessage>,
+    newsletters: &[Newsletter],
+    email_templates: &[EmailTemplate],
+    content: NewsletterFormContent,
+    errors: NewsletterFormErrors,
+    idempotency_key: &str,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let mut message_html = String::new();
-    for message in flash_messages.iter() {
-        writeln!(message_html, "<p><i>{}</i></p>", message.content()).unwrap();
+    let selected_email_template = parse_email_template_id(&content.email_template)
+        .ok()
+        .flatten();
+    let body = NewsletterFormPage {
+        layout: Layout::new(branding, "Publish Newsletter Issue"),
+        messages,
+        newsletter_options: newsletter_options(newsletters, &content.newsletter),
+        email_template_options: email_template_options(email_templates, selected_email_template),
+        content,
+        errors,
+        idempotency_key,
     }
-    let idempotency_key = uuid::Uuid::new_v4();
-    Ok(HttpResponse::Ok()
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::build(status)
         .content_type(ContentType::html())
-        .body(format!(
-            r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta http-equiv="content-type" content="text/html; charset=utf-8">
-    <title>Publish Newsletter Issue</title>
-</head>
-<body>
-    {message_html}
-    <form action="/admin/newsletters" method="post">
-        <label>Title:<br>
-            <input
-                type="text"
-                placeholder="Enter the issue title"
-                name="title"
-            >
-        </label>
-        <br>
-        <label>Plain text content:<br>
-            <textarea
-                placeholder="Enter the content in plain text"
-                name="text_content"
-                rows="20"
-                cols="50"
-            ></textarea>
-        </label>
-        <br>
-        <label>HTML content:<br>
-            <textarea
-                placeholder="Enter the content in HTML format"
-                name="html_content"
-                rows="20"
-                cols="50"
-            ></textarea>
-        </label>
-        <br>
-        <input hidden type="text" name="idempotency_key" value="{idempotency_key}">
-        <button type="submit">Publish</button>
-    </form>
-    <p><a href="/admin/dashboard">&lt;- Back</a></p>
-</body>
-</html>"#,
-        )))
+        .body(body))
 }
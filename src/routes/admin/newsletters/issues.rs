@@ -0,0 +1,94 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::routing_helpers::e500;
+use crate::templates::{Branding, Layout};
+
+const PAGE_SIZE: i64 = 20;
+
+#[derive(serde::Deserialize)]
+pub struct Pagination {
+    page: Option<i64>,
+}
+
+struct IssueSummary {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+    recipients: i64,
+}
+
+#[derive(Template)]
+#[template(path = "newsletter_issues.html")]
+struct NewsletterIssuesPage<'a> {
+    layout: Layout<'a>,
+    issues: Vec<IssueSummary>,
+    page: i64,
+    has_next_page: bool,
+}
+
+/// Lists published newsletter issues, most recent first
+pub async fn list_newsletter_issues(
+    pagination: web::Query<Pagination>,
+    pool: web::Data<PgPool>,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let page = pagination.page.unwrap_or(1).max(1);
+    let mut issues = get_published_issues(&pool, page).await.map_err(e500)?;
+    // we fetch one extra row to know whether there is a next page
+    let has_next_page = issues.len() as i64 > PAGE_SIZE;
+    issues.truncate(PAGE_SIZE as usize);
+
+    let body = NewsletterIssuesPage {
+        layout: Layout::new(&branding, "Newsletter issues"),
+        issues,
+        page,
+        has_next_page,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Fetches a page of published issues. Returns up to `PAGE_SIZE + 1` rows.
+#[tracing::instrument(name = "Get published newsletter issues", skip(pool))]
+async fn get_published_issues(
+    pool: &PgPool,
+    page: i64,
+) -> Result<Vec<IssueSummary>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            published_at AS "published_at!",
+            (
+                (SELECT COUNT(*) FROM issue_deliveries d
+                 WHERE d.newsletter_issue_id = i.newsletter_issue_id) +
+                (SELECT COUNT(*) FROM issue_delivery_failures f
+                 WHERE f.newsletter_issue_id = i.newsletter_issue_id) +
+                (SELECT COUNT(*) FROM issue_delivery_queue q
+                 WHERE q.newsletter_issue_id = i.newsletter_issue_id)
+            ) AS "recipients!"
+        FROM newsletter_issues i
+        WHERE status <> 'draft'
+        ORDER BY published_at DESC
+        LIMIT $1
+        OFFSET $2
+        "#,
+        PAGE_SIZE + 1,
+        (page - 1) * PAGE_SIZE
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve published newsletter issues.")?;
+    Ok(issues)
+}
@@ -1,5 +1,23 @@
+mod analytics;
+mod delivery_controls;
+mod drafts;
+mod edit;
 mod get;
+mod issues;
 mod post;
+mod preview;
+mod revisions;
+mod status;
+mod test_send;
 
+pub use analytics::*;
+pub use delivery_controls::*;
+pub use drafts::*;
+pub use edit::*;
 pub use get::*;
-pub use post::*;
+pub use issues::*;
+pub use post::{parse_local_send_time, publish_issue, publish_newsletter, NewIssue};
+pub use preview::*;
+pub use revisions::*;
+pub use status::*;
+pub use test_send::*;
@@ -1,55 +1,71 @@
-use std::fmt::{Debug, Formatter};
-
-use actix_web::body::BoxBody;
-use actix_web::http::{header, StatusCode};
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
+use chrono::NaiveTime;
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::authentication::UserId;
-use crate::error_handling::error_chain_fmt;
+use crate::configuration::{HtmlSanitizationSettings, PayloadLimitSettings, UtmSettings};
+use crate::domain::SubscriberTag;
+use crate::email_templates::{apply_email_template, get_email_templates, pick_email_template};
+use crate::events::{record_issue_event, EventType};
 use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::newsletter_content::{
+    add_utm_parameters, inject_view_in_browser_link_html, inject_view_in_browser_link_text,
+    prepare_html_content, trackable_links, UtmParameters,
+};
+use crate::newsletters::{find_newsletter, get_newsletters};
+use crate::routes::admin::newsletters::get::{
+    render_newsletter_form, NewsletterFormContent, NewsletterFormErrors,
+};
 use crate::routing_helpers::{e400, e500, see_other};
+use crate::send_window::parse_time;
+use crate::templates::Branding;
+use crate::url_builder::UrlBuilder;
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
+    // missing fields are reported next to the form rather than failing the extraction
+    #[serde(default)]
     title: String,
+    #[serde(default)]
     text_content: String,
+    #[serde(default)]
     html_content: String,
     idempotency_key: String,
+    draft_id: Option<Uuid>,
+    /// The version of the draft the form was filled with, kept when the form is redisplayed
+    version: Option<i32>,
+    /// Slug of the newsletter the issue goes to; the default newsletter if empty
+    #[serde(default)]
+    newsletter: String,
+    /// Only subscribers with this tag receive the issue; everybody does if it is empty
+    #[serde(default)]
+    segment: String,
+    /// Set by the checkbox of the form, which is only submitted when checked
+    #[serde(default)]
+    click_tracking: bool,
+    /// `HH:MM` in the time zone of each subscriber; sent right away if empty
+    #[serde(default)]
+    local_send_time: String,
+    /// UTM parameters of the links of the issue; the defaults of the settings if empty
+    #[serde(default)]
+    utm_source: String,
+    #[serde(default)]
+    utm_medium: String,
+    #[serde(default)]
+    utm_campaign: String,
+    /// Keeps the `<style>` blocks as they are instead of inlining their rules, set by a checkbox
+    #[serde(default)]
+    skip_css_inlining: bool,
+    /// The id of the template the content is put into; sent as written if empty
+    #[serde(default)]
+    email_template: String,
 }
 
-#[derive(thiserror::Error)]
-pub enum PublishError {
-    #[error("Authentication failed")]
-    AuthError(#[source] anyhow::Error),
-    #[error(transparent)]
-    UnexpectedError(#[from] anyhow::Error),
-}
-
-impl Debug for PublishError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        error_chain_fmt(self, f)
-    }
-}
-
-impl ResponseError for PublishError {
-    fn error_response(&self) -> HttpResponse<BoxBody> {
-        // by default, `error_response` invokes `status_code`, but since we have a bespoke `error_response`
-        // implementation, we don't need `status_code`
-        match self {
-            PublishError::UnexpectedError(_) => {
-                HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
-            }
-            PublishError::AuthError(_) => HttpResponse::build(StatusCode::UNAUTHORIZED)
-                .append_header((header::WWW_AUTHENTICATE, r#"Basic realm="publish""#))
-                .finish(),
-        }
-    }
-}
-
+#[allow(clippy::too_many_arguments)]
 #[tracing::instrument(
 name = "Publish a newsletter issue",
 skip_all,
@@ -59,6 +75,11 @@ pub async fn publish_newsletter(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    urls: web::Data<UrlBuilder>,
+    branding: web::Data<Branding>,
+    payload_limits: web::Data<PayloadLimitSettings>,
+    utm_settings: web::Data<Option<UtmSettings>>,
+    html_sanitization: web::Data<HtmlSanitizationSettings>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     let FormData {
@@ -66,8 +87,92 @@ pub async fn publish_newsletter(
         text_content,
         html_content,
         idempotency_key,
+        draft_id,
+        version,
+        newsletter,
+        segment,
+        click_tracking,
+        local_send_time,
+        utm_source,
+        utm_medium,
+        utm_campaign,
+        skip_css_inlining,
+        email_template,
     } = form.0;
     let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(e400)?;
+    let newsletter_id = find_newsletter(pool.get_ref(), &newsletter)
+        .await
+        .context("Failed to look up the newsletter.")
+        .map_err(e500)?
+        .map(|newsletter| newsletter.newsletter_id);
+    let picked_email_template = pick_email_template(&pool, &email_template)
+        .await
+        .context("Failed to look up the email template.")
+        .map_err(e500)?;
+    let parsed_segment = SubscriberTag::parse_optional(segment.clone());
+    let parsed_local_send_time = parse_local_send_time(&local_send_time);
+    let errors = NewsletterFormErrors {
+        newsletter: newsletter_id
+            .is_none()
+            .then(|| "Please pick one of the newsletters.".to_owned()),
+        title: required(&title, "Please enter a title."),
+        text_content: required(&text_content, "Please enter the plain text content."),
+        html_content: required(&html_content, "Please enter the HTML content.")
+            .or_else(|| payload_limits.check_newsletter_html(&html_content).err()),
+        segment: parsed_segment.as_ref().err().cloned(),
+        local_send_time: parsed_local_send_time.as_ref().err().cloned(),
+        email_template: picked_email_template.as_ref().err().cloned(),
+    };
+    let status = if html_content.len() > payload_limits.newsletter_html_bytes {
+        StatusCode::PAYLOAD_TOO_LARGE
+    } else {
+        StatusCode::BAD_REQUEST
+    };
+    let (newsletter_id, segment, local_send_time, email_template) = match (
+        newsletter_id,
+        parsed_segment,
+        parsed_local_send_time,
+        picked_email_template,
+    ) {
+        (Some(newsletter_id), Ok(segment), Ok(send_time), Ok(email_template))
+            if errors.is_empty() =>
+        {
+            (newsletter_id, segment, send_time, email_template)
+        }
+        _ => {
+            // the form keeps its idempotency key, so that the corrected issue is only published once
+            let newsletters = get_newsletters(&pool).await.map_err(e500)?;
+            let email_templates = get_email_templates(&pool).await.map_err(e500)?;
+            let content = NewsletterFormContent {
+                draft_id,
+                version,
+                newsletter,
+                title,
+                text_content,
+                html_content,
+                segment,
+                click_tracking,
+                local_send_time,
+                utm_source,
+                utm_medium,
+                utm_campaign,
+                skip_css_inlining,
+                email_template,
+            };
+            return render_newsletter_form(
+                &branding,
+                status,
+                vec![FlashMessage::error(
+                    "The newsletter issue was not published: please fix the errors below.",
+                )],
+                &newsletters,
+                &email_templates,
+                content,
+                errors,
+                idempotency_key.as_ref(),
+            );
+        }
+    };
     let mut transaction = match try_processing(&pool, &idempotency_key, *user_id)
         .await
         .map_err(e500)?
@@ -78,14 +183,34 @@ pub async fn publish_newsletter(
             return Ok(response);
         }
     };
-    let issue_id = insert_newsletter_issue(&mut transaction, &title, &text_content, &html_content)
-        .await
-        .context("Failed to store newsletter issue details")
-        .map_err(e500)?;
-    enqueue_delivery_tasks(&mut transaction, issue_id)
+    // the form is redisplayed with the content as written, so it is only put into the template
+    // and sanitized once valid; the styles of the template are inlined with those of the content
+    let (text_content, html_content) =
+        apply_email_template(email_template.as_ref(), &title, text_content, html_content);
+    let html_content = prepare_html_content(&html_content, skip_css_inlining, &html_sanitization);
+    let issue = NewIssue {
+        newsletter_id,
+        title: &title,
+        text_content: &text_content,
+        html_content: &html_content,
+        segment: segment.as_ref(),
+        click_tracking,
+        local_send_time,
+        email_template_id: email_template
+            .as_ref()
+            .map(|template| template.email_template_id),
+        utm: UtmParameters::for_issue(
+            utm_settings.as_ref().as_ref(),
+            &title,
+            &utm_source,
+            &utm_medium,
+            &utm_campaign,
+        ),
+    };
+    publish_issue(&mut transaction, &urls, draft_id, issue)
         .await
-        .context("Failed to enqueue delivery tasks")
-        .map_err(e500)?;
+        .map_err(e500)?
+        .ok_or_else(|| e400("The draft no longer exists or has already been published."))?;
     let response = see_other("/admin/newsletters");
     let response = save_response(transaction, &idempotency_key, *user_id, response)
         .await
@@ -94,19 +219,101 @@ pub async fn publish_newsletter(
     Ok(response)
 }
 
+/// Parses the optional `HH:MM` at which subscribers get the issue in their own time zone
+pub fn parse_local_send_time(local_send_time: &str) -> Result<Option<NaiveTime>, String> {
+    let local_send_time = local_send_time.trim();
+    if local_send_time.is_empty() {
+        return Ok(None);
+    }
+    parse_time(local_send_time)
+        .map(Some)
+        .map_err(|_| format!("{} is not a time of day such as 09:00.", local_send_time))
+}
+
+/// The error to show under a required field that was left empty
+fn required(value: &str, error: &str) -> Option<String> {
+    value.trim().is_empty().then(|| error.to_owned())
+}
+
 fn success_message() -> FlashMessage {
-    FlashMessage::info("The newsletter issue has been published!")
+    FlashMessage::success("The newsletter issue has been published!")
+}
+
+/// The content of an issue being published
+pub struct NewIssue<'a> {
+    /// The newsletter whose subscribers receive the issue
+    pub newsletter_id: Uuid,
+    pub title: &'a str,
+    pub text_content: &'a str,
+    pub html_content: &'a str,
+    /// Only subscribers with this tag receive the issue; everybody does if it is `None`
+    pub segment: Option<&'a SubscriberTag>,
+    /// Whether the links of the HTML content go through the click tracking redirect
+    pub click_tracking: bool,
+    /// When set, recipients get the issue at this time of day in their own time zone
+    pub local_send_time: Option<NaiveTime>,
+    /// The template whose layouts the content was put into
+    pub email_template_id: Option<Uuid>,
+    /// Added to the links of the HTML content when it is published
+    pub utm: Option<UtmParameters>,
+}
+
+/// Stores the issue, or publishes the draft it was written in. Its delivery to every confirmed
+/// subscriber in the segment is queued by the delivery worker afterwards, so publishing takes
+/// the same time whatever the size of the list. Returns `None` if the draft no longer exists or
+/// has already been published.
+pub async fn publish_issue(
+    transaction: &mut Transaction<'_, Postgres>,
+    urls: &UrlBuilder,
+    draft_id: Option<Uuid>,
+    issue: NewIssue<'_>,
+) -> Result<Option<Uuid>, anyhow::Error> {
+    // every email links to the hosted version of the issue in the public archive
+    let issue_id = draft_id.unwrap_or_else(Uuid::new_v4);
+    let issue_url = urls.issue_url(issue_id);
+    let text_content = inject_view_in_browser_link_text(issue.text_content, &issue_url);
+    let html_content = match &issue.utm {
+        Some(utm) => add_utm_parameters(issue.html_content, utm),
+        None => issue.html_content.to_owned(),
+    };
+    let html_content = inject_view_in_browser_link_html(&html_content, &issue_url);
+    let issue = NewIssue {
+        text_content: &text_content,
+        html_content: &html_content,
+        ..issue
+    };
+    let issue_id = match draft_id {
+        Some(draft_id) => {
+            let published = publish_draft(transaction, draft_id, &issue)
+                .await
+                .context("Failed to publish the newsletter draft")?;
+            match published {
+                Some(issue_id) => issue_id,
+                None => return Ok(None),
+            }
+        }
+        None => insert_newsletter_issue(transaction, issue_id, &issue)
+            .await
+            .context("Failed to store newsletter issue details")?,
+    };
+    if issue.click_tracking {
+        store_issue_links(transaction, issue_id, &trackable_links(issue.html_content))
+            .await
+            .context("Failed to store the links of the newsletter issue")?;
+    }
+    record_issue_event(transaction, EventType::IssuePublished, issue_id)
+        .await
+        .context("Failed to record the publication in the event feed")?;
+    Ok(Some(issue_id))
 }
 
 /// Inserts a new newsletter issue
 #[tracing::instrument(skip_all)]
 async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
-    title: &str,
-    text_content: &str,
-    html_content: &str,
+    newsletter_issue_id: Uuid,
+    issue: &NewIssue<'_>,
 ) -> Result<Uuid, sqlx::Error> {
-    let newsletter_issue_id = Uuid::new_v4();
     sqlx::query!(
         r#"
         INSERT INTO newsletter_issues (
@@ -114,37 +321,87 @@ async fn insert_newsletter_issue(
             title,
             text_content,
             html_content,
+            segment,
+            click_tracking,
+            newsletter_id,
+            local_send_time,
+            email_template_id,
+            status,
             published_at
         )
-        VALUES ($1, $2, $3, $4, now())
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, 'enqueuing', now())
         "#,
         newsletter_issue_id,
-        title,
-        text_content,
-        html_content
+        issue.title,
+        issue.text_content,
+        issue.html_content,
+        issue.segment.map(AsRef::as_ref),
+        issue.click_tracking,
+        issue.newsletter_id,
+        issue.local_send_time,
+        issue.email_template_id
     )
     .execute(transaction)
     .await?;
     Ok(newsletter_issue_id)
 }
 
-/// Inserts a newsletter delivery task into the queue table
+/// Publishes a draft with the latest content of the form. Returns `None` if there is no draft with the given id.
+#[tracing::instrument(skip_all)]
+async fn publish_draft(
+    transaction: &mut Transaction<'_, Postgres>,
+    draft_id: Uuid,
+    issue: &NewIssue<'_>,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            title = $2,
+            text_content = $3,
+            html_content = $4,
+            segment = $5,
+            click_tracking = $6,
+            newsletter_id = $7,
+            local_send_time = $8,
+            email_template_id = $9,
+            status = 'enqueuing',
+            published_at = now()
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'draft'
+        "#,
+        draft_id,
+        issue.title,
+        issue.text_content,
+        issue.html_content,
+        issue.segment.map(AsRef::as_ref),
+        issue.click_tracking,
+        issue.newsletter_id,
+        issue.local_send_time,
+        issue.email_template_id
+    )
+    .execute(transaction)
+    .await?
+    .rows_affected();
+    Ok((n_updated > 0).then_some(draft_id))
+}
+
+/// Stores the targets of the tracked links of an issue, numbered by their position
 #[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
+async fn store_issue_links(
     transaction: &mut Transaction<'_, Postgres>,
     newsletter_issue_id: Uuid,
+    links: &[String],
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
         r#"
-        INSERT INTO issue_delivery_queue (
-            newsletter_issue_id,
-            subscriber_email
-        )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
+        INSERT INTO issue_links (newsletter_issue_id, link_id, url)
+        SELECT $1, link_id - 1, url
+        FROM UNNEST($2::TEXT[]) WITH ORDINALITY AS links(url, link_id)
         "#,
-        newsletter_issue_id
+        newsletter_issue_id,
+        links
     )
     .execute(transaction)
     .await?;
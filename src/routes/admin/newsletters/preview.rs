@@ -0,0 +1,127 @@
+use actix_web::http::header::{ContentType, CONTENT_SECURITY_POLICY};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::configuration::{EmailFooterSettings, HtmlSanitizationSettings, PayloadLimitSettings};
+use crate::email_templates::{apply_email_template, pick_email_template};
+use crate::newsletter_content::{
+    append_footer_html, append_footer_text, inject_view_in_browser_link_html,
+    inject_view_in_browser_link_text, personalize_html, personalize_text, prepare_html_content,
+    Personalization,
+};
+use crate::routing_helpers::{e400, e413, e500};
+use crate::url_builder::UrlBuilder;
+
+/// A page of its own rather than one extending the layout, so that it can be shown in an iframe
+#[derive(Template)]
+#[template(path = "preview.html")]
+struct PreviewPage<'a> {
+    title: &'a str,
+    /// Rendered as is: the page is sandboxed
+    html_content: &'a str,
+    text_content: &'a str,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PreviewFormData {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    text_content: String,
+    #[serde(default)]
+    html_content: String,
+    #[serde(default)]
+    skip_css_inlining: bool,
+    #[serde(default)]
+    email_template: String,
+}
+
+/// Prepares the HTML and plain text content of an issue that is not published yet the way the
+/// delivery worker would for the given recipient. Since the issue does not exist, its links
+/// point to where it will be with a made-up id.
+pub(super) fn personalize_unpublished_issue(
+    urls: &UrlBuilder,
+    html_content: &str,
+    text_content: &str,
+    name: &str,
+    email: &str,
+    email_footer: Option<&EmailFooterSettings>,
+) -> (String, String) {
+    let issue_url = urls.issue_url(Uuid::nil());
+    let (html_content, text_content) = match email_footer {
+        Some(footer) => (
+            append_footer_html(html_content, footer),
+            append_footer_text(text_content, footer),
+        ),
+        None => (html_content.to_owned(), text_content.to_owned()),
+    };
+    let unsubscribe_url = urls.unsubscribe_url("preview");
+    let personalization = Personalization {
+        name,
+        email,
+        unsubscribe_url: &unsubscribe_url,
+    };
+    let html_content = personalize_html(
+        &inject_view_in_browser_link_html(&html_content, &issue_url),
+        &personalization,
+    );
+    let text_content = personalize_text(
+        &inject_view_in_browser_link_text(&text_content, &issue_url),
+        &personalization,
+    );
+    (html_content, text_content)
+}
+
+/// Renders the content of the newsletter form the way a subscriber receives it, without storing
+/// or sending anything. The page stands on its own, so that it can be shown in an iframe.
+pub async fn preview_newsletter(
+    form: web::Form<PreviewFormData>,
+    pool: web::Data<PgPool>,
+    urls: web::Data<UrlBuilder>,
+    payload_limits: web::Data<PayloadLimitSettings>,
+    html_sanitization: web::Data<HtmlSanitizationSettings>,
+    email_footer: web::Data<Option<EmailFooterSettings>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let PreviewFormData {
+        title,
+        text_content,
+        html_content,
+        skip_css_inlining,
+        email_template,
+    } = form.0;
+    payload_limits
+        .check_newsletter_html(&html_content)
+        .map_err(e413)?;
+    let email_template = pick_email_template(&pool, &email_template)
+        .await
+        .context("Failed to look up the email template.")
+        .map_err(e500)?
+        .map_err(e400)?;
+    let (text_content, html_content) =
+        apply_email_template(email_template.as_ref(), &title, text_content, html_content);
+    // placeholders are filled with the details of a sample subscriber
+    let (html_content, text_content) = personalize_unpublished_issue(
+        &urls,
+        &prepare_html_content(&html_content, skip_css_inlining, &html_sanitization),
+        &text_content,
+        "Ursula Le Guin",
+        "ursula.le.guin@example.com",
+        email_footer.as_ref().as_ref(),
+    );
+    let body = PreviewPage {
+        title: &title,
+        html_content: &html_content,
+        text_content: &text_content,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        // the HTML content is rendered as is: the sandbox keeps any script it contains from
+        // acting on behalf of the logged-in admin
+        .insert_header((CONTENT_SECURITY_POLICY, "sandbox"))
+        .body(body))
+}
@@ -0,0 +1,392 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context as _;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use similar::{ChangeTag, TextDiff};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::authentication::UserId;
+use crate::routing_helpers::{e500, see_other};
+use crate::templates::{collect_flash_messages, Branding, Layout};
+
+struct Revision {
+    revision_id: Uuid,
+    version: i32,
+    title: String,
+    text_content: String,
+    html_content: String,
+    replaced_at: DateTime<Utc>,
+    replaced_by: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "draft_revisions.html")]
+struct DraftRevisionsPage<'a> {
+    layout: Layout<'a>,
+    messages: Vec<FlashMessage>,
+    draft_id: Uuid,
+    title: String,
+    revisions: Vec<Revision>,
+}
+
+#[derive(Template)]
+#[template(path = "draft_revision.html")]
+struct DraftRevisionPage<'a> {
+    layout: Layout<'a>,
+    draft_id: Uuid,
+    revision: &'a Revision,
+    title_diff: Vec<DiffLine>,
+    text_content_diff: Vec<DiffLine>,
+    html_content_diff: Vec<DiffLine>,
+}
+
+/// Lists the earlier versions of a draft, most recent first
+pub async fn draft_revisions(
+    draft_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft_id = draft_id.into_inner();
+    let Some(draft_title) = get_draft_title(&pool, draft_id).await.map_err(e500)? else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let body = DraftRevisionsPage {
+        layout: Layout::new(&branding, "Draft history"),
+        messages: collect_flash_messages(&flash_messages),
+        draft_id,
+        title: draft_title,
+        revisions: get_revisions(&pool, draft_id).await.map_err(e500)?,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Shows what changed in a draft since one of its earlier versions, with a form to restore it
+pub async fn draft_revision(
+    path: web::Path<(Uuid, Uuid)>,
+    pool: web::Data<PgPool>,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (draft_id, revision_id) = path.into_inner();
+    let Some(revision) = get_revision(&pool, draft_id, revision_id)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let draft = sqlx::query!(
+        r#"
+        SELECT title, text_content, html_content
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+        "#,
+        draft_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to perform a query to retrieve the newsletter draft.")
+    .map_err(e500)?;
+    let Some(draft) = draft else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let body = DraftRevisionPage {
+        layout: Layout::new(&branding, "Draft history"),
+        draft_id,
+        revision: &revision,
+        title_diff: diff_lines(&revision.title, &draft.title),
+        text_content_diff: diff_lines(&revision.text_content, &draft.text_content),
+        html_content_diff: diff_lines(&revision.html_content, &draft.html_content),
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Puts the content of an earlier version back into the draft. The content it replaces is kept
+/// as a revision, like on any other save, so that restoring can be undone.
+#[tracing::instrument(
+    name = "Restore a draft revision",
+    skip(pool, user_id),
+    fields(user_id=%&*user_id)
+)]
+pub async fn restore_draft_revision(
+    path: web::Path<(Uuid, Uuid)>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (draft_id, revision_id) = path.into_inner();
+    let Some(revision) = get_revision(&pool, draft_id, revision_id)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    record_draft_revision(
+        &mut transaction,
+        draft_id,
+        None,
+        &revision.title,
+        &revision.text_content,
+        &revision.html_content,
+        **user_id,
+    )
+    .await
+    .context("Failed to keep the current content of the draft.")
+    .map_err(e500)?;
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            title = $2,
+            text_content = $3,
+            html_content = $4,
+            content_blocks = NULL,
+            version = version + 1
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+        "#,
+        draft_id,
+        revision.title,
+        revision.text_content,
+        revision.html_content
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to restore the draft revision.")
+    .map_err(e500)?
+    .rows_affected();
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the SQL transaction to restore a draft revision.")
+        .map_err(e500)?;
+    if n_updated == 0 {
+        FlashMessage::error("The draft has already been published.").send();
+        return Ok(see_other("/admin/newsletters"));
+    }
+    FlashMessage::success(format!(
+        "Version {} of the draft has been restored.",
+        revision.version
+    ))
+    .send();
+    Ok(see_other(&format!(
+        "/admin/newsletters/drafts/{}",
+        draft_id
+    )))
+}
+
+/// Keeps the current content of a draft as a revision before it is replaced with the given
+/// content, provided the draft is at the expected version if one is given. Nothing is kept if
+/// the content does not change.
+#[tracing::instrument(skip(transaction, title, text_content, html_content))]
+pub async fn record_draft_revision(
+    transaction: &mut Transaction<'_, Postgres>,
+    draft_id: Uuid,
+    version: Option<i32>,
+    title: &str,
+    text_content: &str,
+    html_content: &str,
+    user_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_revisions (
+            revision_id,
+            newsletter_issue_id,
+            version,
+            title,
+            text_content,
+            html_content,
+            replaced_at,
+            replaced_by
+        )
+        SELECT $2, newsletter_issue_id, version, title, text_content, html_content, now(), $7
+        FROM newsletter_issues
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'draft' AND
+            ($3::INTEGER IS NULL OR version = $3) AND
+            (title, text_content, html_content) IS DISTINCT FROM ($4, $5, $6)
+        FOR UPDATE
+        "#,
+        draft_id,
+        Uuid::new_v4(),
+        version,
+        title,
+        text_content,
+        html_content,
+        user_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_draft_title(pool: &PgPool, draft_id: Uuid) -> Result<Option<String>, anyhow::Error> {
+    let title = sqlx::query_scalar!(
+        r#"
+        SELECT title
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1 AND status = 'draft'
+        "#,
+        draft_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve the newsletter draft.")?;
+    Ok(title)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_revisions(pool: &PgPool, draft_id: Uuid) -> Result<Vec<Revision>, anyhow::Error> {
+    let revisions = sqlx::query_as!(
+        Revision,
+        r#"
+        SELECT
+            r.revision_id,
+            r.version,
+            r.title,
+            r.text_content,
+            r.html_content,
+            r.replaced_at,
+            u.username AS "replaced_by?"
+        FROM issue_revisions r
+        LEFT JOIN users u ON u.user_id = r.replaced_by
+        WHERE r.newsletter_issue_id = $1
+        ORDER BY r.version DESC, r.replaced_at DESC
+        "#,
+        draft_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve the revisions of the draft.")?;
+    Ok(revisions)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_revision(
+    pool: &PgPool,
+    draft_id: Uuid,
+    revision_id: Uuid,
+) -> Result<Option<Revision>, anyhow::Error> {
+    let revision = sqlx::query_as!(
+        Revision,
+        r#"
+        SELECT
+            r.revision_id,
+            r.version,
+            r.title,
+            r.text_content,
+            r.html_content,
+            r.replaced_at,
+            u.username AS "replaced_by?"
+        FROM issue_revisions r
+        LEFT JOIN users u ON u.user_id = r.replaced_by
+        WHERE r.newsletter_issue_id = $1 AND r.revision_id = $2
+        "#,
+        draft_id,
+        revision_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve the draft revision.")?;
+    Ok(revision)
+}
+
+/// A line of the difference between two versions of a text, shown by the `diff` macro
+#[derive(Debug, PartialEq)]
+pub(super) struct DiffLine {
+    pub(super) change: ChangeTag,
+    /// Ends with a newline, even the last line of a text without a trailing one
+    pub(super) text: String,
+}
+
+impl DiffLine {
+    /// The element highlighting a removed or an added line
+    pub(super) fn element(&self) -> Option<&'static str> {
+        match self.change {
+            ChangeTag::Delete => Some("del"),
+            ChangeTag::Insert => Some("ins"),
+            ChangeTag::Equal => None,
+        }
+    }
+
+    pub(super) fn marker(&self) -> &'static str {
+        match self.change {
+            ChangeTag::Delete => "- ",
+            ChangeTag::Insert => "+ ",
+            ChangeTag::Equal => "  ",
+        }
+    }
+}
+
+/// Compares two versions of a text line by line
+pub(super) fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .map(|change| {
+            let mut text = change.value().to_owned();
+            if change.missing_newline() {
+                text.push('\n');
+            }
+            DiffLine {
+                change: change.tag(),
+                text,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use askama::Template;
+
+    use super::{diff_lines, DiffLine};
+
+    #[derive(Template)]
+    #[template(
+        source = r#"{% import "macros.html" as macros %}{% call macros::diff(lines) %}"#,
+        ext = "html"
+    )]
+    struct Diff {
+        lines: Vec<DiffLine>,
+    }
+
+    fn diff_markup(old: &str, new: &str) -> String {
+        Diff {
+            lines: diff_lines(old, new),
+        }
+        .render()
+        .unwrap()
+    }
+
+    #[test]
+    fn changed_lines_are_marked_and_escaped() {
+        assert_eq!(
+            diff_markup("<h1>Hello</h1>\n<p>Old</p>", "<h1>Hello</h1>\n<p>New</p>"),
+            "  &lt;h1&gt;Hello&lt;/h1&gt;\n\
+            <del>- &lt;p&gt;Old&lt;/p&gt;\n</del>\
+            <ins>+ &lt;p&gt;New&lt;/p&gt;\n</ins>"
+        );
+    }
+
+    #[test]
+    fn identical_content_has_no_changes() {
+        let markup = diff_markup("Same\ntext\n", "Same\ntext\n");
+        assert_eq!(markup, "  Same\n  text\n");
+    }
+}
@@ -0,0 +1,136 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama::Template;
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+use crate::click_tracking::{get_click_stats, ClickStats};
+use crate::configuration::IssueDeliverySettings;
+use crate::delivery_progress::{get_delivery_progress, DeliveryProgress};
+use crate::routing_helpers::e500;
+use crate::templates::{collect_flash_messages, filters, Branding, Layout};
+
+#[derive(Template)]
+#[template(path = "delivery_status.html")]
+struct DeliveryStatusPage<'a> {
+    layout: Layout<'a>,
+    messages: Vec<FlashMessage>,
+    issue_id: Uuid,
+    progress: DeliveryProgress,
+    /// What the delivery is doing, e.g. its ETA
+    state: String,
+    /// The path and label of the controls that apply to the delivery
+    actions: Vec<(&'static str, &'static str)>,
+    click_stats: Option<ClickStats>,
+}
+
+pub async fn newsletter_delivery_status(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    issue_delivery: web::Data<IssueDeliverySettings>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue_id = issue_id.into_inner();
+    let progress = match get_delivery_progress(&pool, issue_id).await.map_err(e500)? {
+        Some(progress) => progress,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let now = chrono::Utc::now();
+    let closed_window = issue_delivery
+        .send_window
+        .as_ref()
+        .filter(|window| !progress.ignores_send_window && !window.is_open(now));
+    let state = if progress.status == "enqueuing" {
+        "queueing recipients".to_string()
+    } else if progress.status != "delivering" {
+        format!("delivery {}", progress.status)
+    } else if progress.is_complete() {
+        "delivery complete".to_string()
+    } else if let Some(window) = closed_window {
+        format!(
+            "waiting for the send window to open at {} ({})",
+            window.local_time(window.next_opening(now)).format("%H:%M"),
+            window.time_zone
+        )
+    } else {
+        match progress.eta(now) {
+            Some(eta) => format!("ETA {}", format_eta(eta)),
+            None => "ETA unknown".to_string(),
+        }
+    };
+    let mut actions = match progress.status.as_str() {
+        "delivering" if !progress.is_complete() => vec![("pause", "Pause"), ("cancel", "Cancel")],
+        "paused" => vec![("resume", "Resume"), ("cancel", "Cancel")],
+        "enqueuing" => vec![("cancel", "Cancel")],
+        _ => vec![],
+    };
+    if progress.failed > 0 && matches!(progress.status.as_str(), "delivering" | "paused") {
+        actions.push(("retry-failures", "Retry failed deliveries"));
+    }
+    if progress.status == "delivering" && !progress.is_complete() && closed_window.is_some() {
+        actions.push(("ignore-send-window", "Send now, ignoring the send window"));
+    }
+    let body = DeliveryStatusPage {
+        layout: Layout::new(&branding, "Delivery status"),
+        messages: collect_flash_messages(&flash_messages),
+        issue_id,
+        progress,
+        state,
+        actions,
+        click_stats: get_click_stats(&pool, issue_id).await.map_err(e500)?,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Formats a count with a comma as thousands separator, e.g. 3200 -> "3,200"
+pub(crate) fn format_count(count: i64) -> String {
+    let digits = count.unsigned_abs().to_string();
+    let mut formatted = String::with_capacity(digits.len() + digits.len() / 3 + 1);
+    if count < 0 {
+        formatted.push('-');
+    }
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i) % 3 == 0 {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+    formatted
+}
+
+/// Formats an ETA rounded up to the minute
+fn format_eta(eta: Duration) -> String {
+    let minutes = (eta.as_secs() + 59) / 60;
+    if minutes <= 1 {
+        "1 min".to_string()
+    } else {
+        format!("{} min", minutes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_count, format_eta};
+    use std::time::Duration;
+
+    #[test]
+    fn counts_are_formatted_with_thousands_separators() {
+        assert_eq!(format_count(0), "0");
+        assert_eq!(format_count(999), "999");
+        assert_eq!(format_count(3200), "3,200");
+        assert_eq!(format_count(1234567), "1,234,567");
+    }
+
+    #[test]
+    fn eta_is_rounded_up_to_the_minute() {
+        assert_eq!(format_eta(Duration::from_secs(5)), "1 min");
+        assert_eq!(format_eta(Duration::from_secs(200)), "4 min");
+    }
+}
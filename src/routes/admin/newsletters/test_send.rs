@@ -0,0 +1,192 @@
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::get::{render_newsletter_form, NewsletterFormContent, NewsletterFormErrors};
+use super::preview::personalize_unpublished_issue;
+use crate::authentication::UserId;
+use crate::configuration::{EmailFooterSettings, HtmlSanitizationSettings, PayloadLimitSettings};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::email_templates::{apply_email_template, get_email_templates, pick_email_template};
+use crate::newsletter_content::prepare_html_content;
+use crate::newsletters::{find_newsletter, get_newsletters};
+use crate::routing_helpers::{e413, e500};
+use crate::templates::Branding;
+use crate::url_builder::UrlBuilder;
+
+#[derive(serde::Deserialize)]
+pub struct TestSendFormData {
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    text_content: String,
+    #[serde(default)]
+    html_content: String,
+    #[serde(default)]
+    idempotency_key: String,
+    draft_id: Option<Uuid>,
+    /// The version of the draft the form was filled with, kept when the form is redisplayed
+    version: Option<i32>,
+    #[serde(default)]
+    newsletter: String,
+    #[serde(default)]
+    segment: String,
+    #[serde(default)]
+    click_tracking: bool,
+    #[serde(default)]
+    local_send_time: String,
+    #[serde(default)]
+    utm_source: String,
+    #[serde(default)]
+    utm_medium: String,
+    #[serde(default)]
+    utm_campaign: String,
+    #[serde(default)]
+    skip_css_inlining: bool,
+    #[serde(default)]
+    email_template: String,
+}
+
+/// Emails the content of the newsletter form to the logged-in admin only, then redisplays the
+/// form as it was. Nothing is stored: the delivery queue and the idempotency key of the form are
+/// left alone, so the issue can still be published afterwards.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "Send a test newsletter email",
+    skip_all,
+    fields(user_id=%&*user_id)
+)]
+pub async fn send_test_newsletter(
+    form: web::Form<TestSendFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    urls: web::Data<UrlBuilder>,
+    user_id: web::ReqData<UserId>,
+    branding: web::Data<Branding>,
+    payload_limits: web::Data<PayloadLimitSettings>,
+    html_sanitization: web::Data<HtmlSanitizationSettings>,
+    email_footer: web::Data<Option<EmailFooterSettings>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let TestSendFormData {
+        title,
+        text_content,
+        html_content,
+        idempotency_key,
+        draft_id,
+        version,
+        newsletter,
+        segment,
+        click_tracking,
+        local_send_time,
+        utm_source,
+        utm_medium,
+        utm_campaign,
+        skip_css_inlining,
+        email_template,
+    } = form.0;
+    payload_limits
+        .check_newsletter_html(&html_content)
+        .map_err(e413)?;
+    let (username, email) = get_username_and_email(&pool, **user_id)
+        .await
+        .map_err(e500)?;
+    let picked_email_template = pick_email_template(&pool, &email_template)
+        .await
+        .context("Failed to look up the email template.")
+        .map_err(e500)?;
+    let message = match (email.map(SubscriberEmail::parse), &picked_email_template) {
+        (_, Err(e)) => FlashMessage::error(format!("The test email was not sent: {}", e)),
+        (Some(Ok(email)), Ok(email_template)) => {
+            // the form is redisplayed with the content as written
+            let (templated_text, templated_html) = apply_email_template(
+                email_template.as_ref(),
+                &title,
+                text_content.clone(),
+                html_content.clone(),
+            );
+            let (personalized_html, personalized_text) = personalize_unpublished_issue(
+                &urls,
+                &prepare_html_content(&templated_html, skip_css_inlining, &html_sanitization),
+                &templated_text,
+                &username,
+                email.as_ref(),
+                email_footer.as_ref().as_ref(),
+            );
+            // the test comes from the identity the subscribers of the picked newsletter will see
+            let newsletter_slug = find_newsletter(pool.get_ref(), &newsletter)
+                .await
+                .context("Failed to look up the newsletter.")
+                .map_err(e500)?
+                .map(|newsletter| newsletter.slug)
+                .unwrap_or_default();
+            email_client
+                .send_email_from(
+                    email_client.sender_of(&newsletter_slug),
+                    &email,
+                    &format!("[Test] {}", title),
+                    &personalized_html,
+                    &personalized_text,
+                )
+                .await
+                .context("Failed to send the test email.")
+                .map_err(e500)?;
+            FlashMessage::success(format!("A test email has been sent to {}.", email.as_ref()))
+        }
+        (Some(Err(_)) | None, Ok(_)) => {
+            FlashMessage::error("Your account has no valid email address to send a test to.")
+        }
+    };
+    let content = NewsletterFormContent {
+        draft_id,
+        version,
+        newsletter,
+        title,
+        text_content,
+        html_content,
+        segment,
+        click_tracking,
+        local_send_time,
+        utm_source,
+        utm_medium,
+        utm_campaign,
+        skip_css_inlining,
+        email_template,
+    };
+    // the form keeps its idempotency key, unless it was submitted without one
+    let idempotency_key = if idempotency_key.is_empty() {
+        Uuid::new_v4().to_string()
+    } else {
+        idempotency_key
+    };
+    let newsletters = get_newsletters(&pool).await.map_err(e500)?;
+    let email_templates = get_email_templates(&pool).await.map_err(e500)?;
+    render_newsletter_form(
+        &branding,
+        StatusCode::OK,
+        vec![message],
+        &newsletters,
+        &email_templates,
+        content,
+        NewsletterFormErrors::default(),
+        &idempotency_key,
+    )
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_username_and_email(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<(String, Option<String>), anyhow::Error> {
+    let user = sqlx::query!(
+        "SELECT username, email FROM users WHERE user_id = $1",
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to perform a query to retrieve the email address of the user.")?;
+    Ok((user.username, user.email))
+}
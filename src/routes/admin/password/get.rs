@@ -1,56 +1,29 @@
-use std::fmt::Write;
-
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
-use actix_web_flash_messages::{IncomingFlashMessages, Level};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama::Template;
+
+use crate::routing_helpers::e500;
+use crate::templates::{collect_flash_messages, Branding, Layout};
+
+#[derive(Template)]
+#[template(path = "change_password.html")]
+struct ChangePasswordPage<'a> {
+    layout: Layout<'a>,
+    messages: Vec<FlashMessage>,
+}
 
 pub async fn change_password_form(
     flash_messages: IncomingFlashMessages,
+    branding: web::Data<Branding>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let mut error_html = String::new();
-    for message in flash_messages.iter().filter(|m| m.level() == Level::Error) {
-        writeln!(error_html, "<p><i>{}</i></p>", message.content()).unwrap();
+    let body = ChangePasswordPage {
+        layout: Layout::new(&branding, "Change Password"),
+        messages: collect_flash_messages(&flash_messages),
     }
+    .render()
+    .map_err(e500)?;
     Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(format!(
-            r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta http-equiv="content-type" content="text/html; charset=utf-8">
-    <title>Change Password</title>
-</head>
-<body>
-    {error_html}
-    <form action="/admin/password" method="post">
-        <label>Current password
-            <input
-                type="password"
-                placeholder="Enter current password"
-                name="current_password"
-            >
-        </label>
-        <br>
-        <label>New password
-            <input
-                type="password"
-                placeholder="Enter new password"
-                name="new_password"
-            >
-        </label>
-        <br>
-        <label>Confirm new password
-            <input
-                type="password"
-                placeholder="Enter new password again"
-                name="new_password_check"
-            >
-        </label>
-        <br>
-        <button type="submit">Change password</button>
-    </form>
-    <p><a href="/admin/dashboard">&lt;- Back</a></p>
-</body>
-</html>"#,
-        )))
+        .body(body))
 }
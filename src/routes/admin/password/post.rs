@@ -1,12 +1,14 @@
 use actix_web::{web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
-use secrecy::{ExposeSecret, Secret};
+use secrecy::Secret;
 use sqlx::PgPool;
-use validator::HasLen;
 
-use crate::authentication::{validate_credentials, AuthError, Credentials, UserId};
-use crate::routes::admin::dashboard::get_username;
+use crate::authentication::{
+    validate_credentials, validate_new_password, AuthError, AuthenticatedUser, Credentials,
+    PasswordHashingPolicy,
+};
 use crate::routing_helpers::{e500, see_other};
+use crate::session_state::TypedSession;
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
@@ -18,35 +20,20 @@ pub struct FormData {
 pub async fn change_password(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
-    user_id: web::ReqData<UserId>,
+    hashing_policy: web::Data<PasswordHashingPolicy>,
+    user: AuthenticatedUser,
+    session: TypedSession,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let user_id = user_id.into_inner();
-
-    let new_password = form.new_password.expose_secret();
-    if new_password != form.new_password_check.expose_secret() {
-        FlashMessage::error(
-            "You entered two different new passwords - the field values must match.",
-        )
-        .send();
-        return Ok(see_other("/admin/password"));
-    }
-
-    if new_password.length() <= 12 {
-        FlashMessage::error("Password must be at least 12 characters.").send();
-        return Ok(see_other("/admin/password"));
-    }
-
-    if new_password.length() > 128 {
-        FlashMessage::error("Password must be no more than 128 characters.").send();
+    if let Err(reason) = validate_new_password(&form.new_password, &form.new_password_check) {
+        FlashMessage::error(reason).send();
         return Ok(see_other("/admin/password"));
     }
 
-    let username = get_username(*user_id, &pool).await.map_err(e500)?;
     let credentials = Credentials {
-        username,
+        username: user.username,
         password: form.0.current_password,
     };
-    if let Err(e) = validate_credentials(credentials, &pool).await {
+    if let Err(e) = validate_credentials(credentials, &hashing_policy, &pool).await {
         return match e {
             AuthError::InvalidCredentials(_) => {
                 FlashMessage::error("The current password is incorrect.").send();
@@ -55,9 +42,19 @@ pub async fn change_password(
             AuthError::UnexpectedError(_) => Err(e500(e)),
         };
     }
-    crate::authentication::change_password(*user_id, form.0.new_password, &pool)
-        .await
+    let password_version = crate::authentication::change_password(
+        user.user_id,
+        form.0.new_password,
+        &hashing_policy,
+        &pool,
+    )
+    .await
+    .map_err(e500)?;
+    // every other session of the user is logged out on its next request
+    session.renew();
+    session
+        .insert_password_version(password_version)
         .map_err(e500)?;
-    FlashMessage::error("Your password has been changed.").send();
+    FlashMessage::success("Your password has been changed.").send();
     Ok(see_other("/admin/password"))
 }
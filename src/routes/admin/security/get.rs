@@ -0,0 +1,85 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama::Template;
+use sqlx::PgPool;
+
+use crate::authentication::{get_totp_secret, AuthenticatedUser, TotpSecret};
+use crate::qr_code::QrCode;
+use crate::routing_helpers::e500;
+use crate::session_state::TypedSession;
+use crate::templates::{collect_flash_messages, Branding, Layout};
+
+/// The issuer authenticator apps list the account under
+const TOTP_ISSUER: &str = "Newsletter";
+/// Size in pixels of a QR code module
+const QR_CODE_MODULE_SIZE: usize = 4;
+
+#[derive(Template)]
+#[template(path = "security_enabled.html")]
+struct SecurityEnabledPage<'a> {
+    layout: Layout<'a>,
+    messages: Vec<FlashMessage>,
+}
+
+#[derive(Template)]
+#[template(path = "security_setup.html")]
+struct SecuritySetupPage<'a> {
+    layout: Layout<'a>,
+    messages: Vec<FlashMessage>,
+    /// An SVG drawn by the QR code encoder, empty if the URI does not fit
+    qr_code: String,
+    secret: &'a str,
+}
+
+/// Shows whether two-factor authentication is enabled, or how to set it up. The secret being set
+/// up is kept in the session until the user proves they added it to their authenticator app.
+pub async fn security_settings(
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let layout = Layout::new(&branding, "Security");
+    let messages = collect_flash_messages(&flash_messages);
+    if get_totp_secret(&pool, user.user_id)
+        .await
+        .map_err(e500)?
+        .is_some()
+    {
+        let body = SecurityEnabledPage { layout, messages }
+            .render()
+            .map_err(e500)?;
+        return Ok(HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .body(body));
+    }
+
+    let secret = match session.get_totp_setup_secret().map_err(e500)? {
+        Some(secret) => TotpSecret::from_base32(secret),
+        None => {
+            let secret = TotpSecret::generate();
+            session
+                .insert_totp_setup_secret(secret.expose_base32())
+                .map_err(e500)?;
+            secret
+        }
+    };
+    let uri = secret.provisioning_uri(TOTP_ISSUER, &user.username);
+    // Very long usernames do not fit in a QR code: the secret can still be entered manually
+    let qr_code = QrCode::encode(uri.as_bytes())
+        .map(|qr_code| qr_code.to_svg(QR_CODE_MODULE_SIZE))
+        .unwrap_or_default();
+    let body = SecuritySetupPage {
+        layout,
+        messages,
+        qr_code,
+        secret: secret.expose_base32(),
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
@@ -0,0 +1,92 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use askama::Template;
+use sqlx::PgPool;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::authentication::{
+    disable_two_factor, enable_two_factor, get_totp_secret, verify_second_factor, TotpSecret,
+    UserId,
+};
+use crate::routing_helpers::{e500, see_other};
+use crate::session_state::TypedSession;
+use crate::templates::{Branding, Layout};
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    code: String,
+}
+
+#[derive(Template)]
+#[template(path = "recovery_codes.html")]
+struct RecoveryCodesPage<'a> {
+    layout: Layout<'a>,
+    recovery_codes: Vec<String>,
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("The system clock is set before the Unix epoch")
+        .as_secs()
+}
+
+/// Enables two-factor authentication once the user enters a valid code for the secret being set
+/// up, and shows their recovery codes
+pub async fn enable_two_factor_authentication(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    session: TypedSession,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let Some(secret) = session.get_totp_setup_secret().map_err(e500)? else {
+        return Ok(see_other("/admin/security"));
+    };
+    let secret = TotpSecret::from_base32(secret);
+    let Some(step) = secret.verify(&form.code, unix_time()) else {
+        FlashMessage::error("The code is incorrect. Check the time of your device and try again.")
+            .send();
+        return Ok(see_other("/admin/security"));
+    };
+
+    let recovery_codes = enable_two_factor(&pool, *user_id, &secret, step)
+        .await
+        .map_err(e500)?;
+    session.remove_totp_setup_secret();
+
+    let body = RecoveryCodesPage {
+        layout: Layout::new(&branding, "Recovery codes"),
+        recovery_codes,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Disables two-factor authentication, which takes a valid code or recovery code so that a
+/// hijacked session cannot do it
+pub async fn disable_two_factor_authentication(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let Some(secret) = get_totp_secret(&pool, *user_id).await.map_err(e500)? else {
+        return Ok(see_other("/admin/security"));
+    };
+    if !verify_second_factor(&pool, *user_id, &secret, &form.code, unix_time())
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::error("Invalid authentication code.").send();
+        return Ok(see_other("/admin/security"));
+    }
+    disable_two_factor(&pool, *user_id).await.map_err(e500)?;
+    FlashMessage::success("Two-factor authentication has been disabled.").send();
+    Ok(see_other("/admin/security"))
+}
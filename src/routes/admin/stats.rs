@@ -0,0 +1,39 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+use crate::analytics::{get_subscriber_growth, Granularity};
+use crate::routing_helpers::{e400, e500};
+
+/// The number of periods returned when the request does not say
+const DEFAULT_PERIODS: i32 = 30;
+/// Enough for a year of daily figures
+const MAX_PERIODS: i32 = 366;
+
+#[derive(serde::Deserialize)]
+pub struct GrowthQuery {
+    #[serde(default)]
+    granularity: Granularity,
+    periods: Option<i32>,
+}
+
+/// `GET /admin/api/stats/subscribers`: the signups and unsubscribes of each recent day, week or
+/// month, to track the growth of the audience
+pub async fn subscriber_stats(
+    query: web::Query<GrowthQuery>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let periods = query.periods.unwrap_or(DEFAULT_PERIODS);
+    if !(1..=MAX_PERIODS).contains(&periods) {
+        return Err(e400(format!(
+            "The number of periods must be between 1 and {}.",
+            MAX_PERIODS
+        )));
+    }
+    let series = get_subscriber_growth(&pool, query.granularity, periods)
+        .await
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "granularity": query.granularity,
+        "series": series,
+    })))
+}
@@ -0,0 +1,367 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::audit_log::record_audit_entry;
+use crate::authentication::UserId;
+use crate::automations::enroll_subscriber;
+use crate::domain::SubscriptionStatus;
+use crate::events::{record_subscriber_erasure, record_subscriber_event, EventType};
+use crate::routing_helpers::{e500, see_other};
+use crate::subscription_events::record_status_change;
+
+/// Deletes a subscriber from the admin page, then goes back to the list of subscribers. The
+/// subscriber can be restored until the purge job erases them.
+#[tracing::instrument(name = "Delete subscriber", skip(pool, user_id))]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let deleted = soft_delete_subscriber(&pool, subscriber_id.into_inner(), **user_id)
+        .await
+        .map_err(e500)?;
+    if !deleted {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    FlashMessage::success("The subscriber has been deleted.").send();
+    Ok(see_other("/admin/subscribers"))
+}
+
+/// Brings back a deleted subscriber, then goes back to the list of deleted subscribers
+#[tracing::instrument(name = "Restore subscriber", skip(pool, user_id))]
+pub async fn restore_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let n_restored = sqlx::query!(
+        "UPDATE subscriptions SET deleted_at = NULL WHERE id = $1 AND deleted_at IS NOT NULL",
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to restore the subscriber.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_restored == 0 {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    record_audit_entry(
+        &mut transaction,
+        Some(**user_id),
+        "restore_subscriber",
+        &subscriber_id.to_string(),
+    )
+    .await
+    .context("Failed to record the restoration in the audit log.")
+    .map_err(e500)?;
+    record_subscriber_event(
+        &mut transaction,
+        EventType::SubscriberRestored,
+        subscriber_id,
+    )
+    .await
+    .context("Failed to record the restoration in the event feed.")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to restore a subscriber.")
+        .map_err(e500)?;
+    FlashMessage::success("The subscriber has been restored.").send();
+    Ok(see_other("/admin/subscribers/deleted"))
+}
+
+/// Hides a subscriber from every list and stops sending them emails, keeping their data so that
+/// they can be restored. Their pending deliveries and automation sequences are dropped for good.
+/// Returns `false` if the subscriber does not exist or is already deleted.
+#[tracing::instrument(skip(pool))]
+pub async fn soft_delete_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    deleted_by: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let n_deleted = sqlx::query!(
+        "UPDATE subscriptions SET deleted_at = now() WHERE id = $1 AND deleted_at IS NULL",
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to mark the subscriber as deleted.")?
+    .rows_affected();
+    if n_deleted == 0 {
+        return Ok(false);
+    }
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE (newsletter_id, subscriber_email) =
+            (SELECT newsletter_id, email FROM subscriptions WHERE id = $1)
+        "#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to drop the pending deliveries of the subscriber.")?;
+    sqlx::query!(
+        "DELETE FROM automation_schedule WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to drop the automation sequences of the subscriber.")?;
+    record_audit_entry(
+        &mut transaction,
+        Some(deleted_by),
+        "soft_delete_subscriber",
+        &subscriber_id.to_string(),
+    )
+    .await
+    .context("Failed to record the deletion in the audit log.")?;
+    record_subscriber_event(
+        &mut transaction,
+        EventType::SubscriberDeleted,
+        subscriber_id,
+    )
+    .await
+    .context("Failed to record the deletion in the event feed.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete a subscriber.")?;
+    Ok(true)
+}
+
+/// `DELETE /admin/subscribers/{id}`: erases a subscriber, e.g. to comply with a GDPR request
+#[tracing::instrument(name = "Erase subscriber", skip(pool, user_id))]
+pub async fn erase_subscriber_data(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let erased = erase_subscriber(&pool, subscriber_id.into_inner(), Some(**user_id))
+        .await
+        .map_err(e500)?;
+    if erased {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Ok(HttpResponse::NotFound().finish())
+    }
+}
+
+/// Removes a subscriber together with their tokens, tags, consents, pending deliveries and
+/// automation sequences, replaces their address in the records of past deliveries, which the
+/// clicks and opens of the analytics refer to, and records who did it in the audit log;
+/// `deleted_by` is `None` when subscribers erase their own data or when deleted subscribers are
+/// purged.
+/// Everything happens in a single transaction. Returns `false` if the subscriber does not exist.
+#[tracing::instrument(skip(pool))]
+pub async fn erase_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    deleted_by: Option<Uuid>,
+) -> Result<bool, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE (newsletter_id, subscriber_email) =
+            (SELECT newsletter_id, email FROM subscriptions WHERE id = $1)
+        "#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to drop the pending deliveries of the subscriber.")?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries SET subscriber_email = 'erased-' || gen_random_uuid()
+        FROM subscriptions, newsletter_issues
+        WHERE subscriptions.id = $1
+            AND issue_deliveries.subscriber_email = subscriptions.email
+            AND newsletter_issues.newsletter_issue_id = issue_deliveries.newsletter_issue_id
+            AND newsletter_issues.newsletter_id = subscriptions.newsletter_id
+        "#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to anonymize the deliveries to the subscriber.")?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_failures SET subscriber_email = 'erased-' || gen_random_uuid()
+        FROM subscriptions, newsletter_issues
+        WHERE subscriptions.id = $1
+            AND issue_delivery_failures.subscriber_email = subscriptions.email
+            AND newsletter_issues.newsletter_issue_id = issue_delivery_failures.newsletter_issue_id
+            AND newsletter_issues.newsletter_id = subscriptions.newsletter_id
+        "#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to anonymize the failed deliveries to the subscriber.")?;
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the subscription tokens of the subscriber.")?;
+    sqlx::query!(
+        "DELETE FROM email_change_requests WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the email change requests of the subscriber.")?;
+    sqlx::query!(
+        "DELETE FROM automation_schedule WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to drop the automation sequences of the subscriber.")?;
+    sqlx::query!(
+        "DELETE FROM subscriber_tags WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the tags of the subscriber.")?;
+    sqlx::query!(
+        "DELETE FROM subscription_consents WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete the consents of the subscriber.")?;
+    let n_deleted = sqlx::query!("DELETE FROM subscriptions WHERE id = $1", subscriber_id)
+        .execute(&mut transaction)
+        .await
+        .context("Failed to delete the subscriber.")?
+        .rows_affected();
+    if n_deleted == 0 {
+        return Ok(false);
+    }
+    // only the id is logged: keeping the email address would defeat the purpose of the erasure
+    record_audit_entry(
+        &mut transaction,
+        deleted_by,
+        "delete_subscriber",
+        &subscriber_id.to_string(),
+    )
+    .await
+    .context("Failed to record the deletion in the audit log.")?;
+    record_subscriber_erasure(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to record the erasure in the event feed.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to delete a subscriber.")?;
+    Ok(true)
+}
+
+/// Confirms a pending subscriber on their behalf, e.g. when the confirmation email got lost
+#[tracing::instrument(name = "Force-confirm subscriber", skip(pool))]
+pub async fn confirm_subscriber_manually(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    match confirm_pending_subscriber(&pool, *subscriber_id)
+        .await
+        .map_err(e500)?
+    {
+        ManualConfirmation::NotFound => Ok(HttpResponse::NotFound().finish()),
+        ManualConfirmation::NotPending => {
+            FlashMessage::error("Only a pending subscriber can be confirmed.").send();
+            Ok(see_other("/admin/subscribers"))
+        }
+        ManualConfirmation::Confirmed => {
+            FlashMessage::success("The subscriber has been confirmed.").send();
+            Ok(see_other("/admin/subscribers"))
+        }
+    }
+}
+
+pub enum ManualConfirmation {
+    Confirmed,
+    NotPending,
+    NotFound,
+}
+
+/// Confirms a subscriber still waiting for confirmation. Their row is locked until the
+/// confirmation commits, so that it cannot be unsubscribed, deleted or confirmed again meanwhile.
+pub async fn confirm_pending_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<ManualConfirmation, anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let subscriber = sqlx::query!(
+        r#"
+        SELECT status FROM subscriptions WHERE id = $1 AND deleted_at IS NULL FOR UPDATE
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to retrieve the subscriber.")?;
+    let Some(subscriber) = subscriber else {
+        return Ok(ManualConfirmation::NotFound);
+    };
+    if subscriber.status != "pending_confirmation" {
+        return Ok(ManualConfirmation::NotPending);
+    }
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions SET status = 'confirmed' WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to confirm the subscriber.")?;
+    record_status_change(
+        &mut transaction,
+        subscriber_id,
+        Some(SubscriptionStatus::PendingConfirmation),
+        SubscriptionStatus::Confirmed,
+    )
+    .await
+    .context("Failed to record the confirmation in the subscription history.")?;
+    enroll_subscriber(&mut transaction, subscriber_id)
+        .await
+        .context("Failed to enroll the subscriber in the automation sequences.")?;
+    record_subscriber_event(
+        &mut transaction,
+        EventType::SubscriberConfirmed,
+        subscriber_id,
+    )
+    .await
+    .context("Failed to record the confirmation in the event feed.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm a subscriber.")?;
+    Ok(ManualConfirmation::Confirmed)
+}
@@ -0,0 +1,191 @@
+use actix_web::http::header::{ContentDisposition, DispositionParam, DispositionType};
+use actix_web::web::Bytes;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, StreamExt};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// Number of subscribers fetched from the database for each chunk of the export
+const BATCH_SIZE: i64 = 500;
+
+struct ExportedSubscriber {
+    id: Uuid,
+    name: String,
+    email: String,
+    /// Slug of the newsletter of the subscription, since an address can be on several
+    newsletter: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    consented_at: Option<DateTime<Utc>>,
+    consent_ip_address: Option<String>,
+    consent_text_version: Option<String>,
+    consent_country: Option<String>,
+    consent_source_url: Option<String>,
+}
+
+/// Where the next batch of the export starts
+enum ExportCursor {
+    Start,
+    After(DateTime<Utc>, Uuid),
+    Done,
+}
+
+/// Streams every subscriber as CSV, with their newsletter and the most recent consent they gave. Subscribers are
+/// fetched in batches as the response is written, so the list is never held in memory as a whole.
+pub async fn export_subscribers(pool: web::Data<PgPool>) -> HttpResponse {
+    let pool = pool.into_inner();
+    let header = stream::once(async {
+        to_csv(&[[
+            "name",
+            "email",
+            "newsletter",
+            "status",
+            "subscribed_at",
+            "consented_at",
+            "consent_ip_address",
+            "consent_text_version",
+            "consent_country",
+            "consent_source_url",
+        ]])
+    });
+    let rows = stream::try_unfold(ExportCursor::Start, move |cursor| {
+        let pool = pool.clone();
+        async move {
+            let after = match cursor {
+                ExportCursor::Done => return Ok(None),
+                ExportCursor::Start => None,
+                ExportCursor::After(subscribed_at, id) => Some((subscribed_at, id)),
+            };
+            let batch = get_subscriber_batch(&pool, after).await?;
+            let next_cursor = match batch.last() {
+                Some(last) if batch.len() as i64 == BATCH_SIZE => {
+                    ExportCursor::After(last.subscribed_at, last.id)
+                }
+                Some(_) => ExportCursor::Done,
+                None => return Ok(None),
+            };
+            let records: Vec<[String; 10]> = batch
+                .into_iter()
+                .map(|s| {
+                    [
+                        escape_formula(s.name),
+                        escape_formula(s.email),
+                        s.newsletter,
+                        s.status,
+                        s.subscribed_at.to_rfc3339(),
+                        s.consented_at
+                            .map(|consented_at| consented_at.to_rfc3339())
+                            .unwrap_or_default(),
+                        escape_formula(s.consent_ip_address.unwrap_or_default()),
+                        escape_formula(s.consent_text_version.unwrap_or_default()),
+                        escape_formula(s.consent_country.unwrap_or_default()),
+                        escape_formula(s.consent_source_url.unwrap_or_default()),
+                    ]
+                })
+                .collect();
+            Ok(Some((to_csv(&records)?, next_cursor)))
+        }
+    });
+    HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("subscribers.csv".into())],
+        })
+        .streaming(header.chain(rows))
+}
+
+/// Spreadsheets run fields starting with one of these as formulas
+const FORMULA_PREFIXES: [char; 6] = ['=', '+', '-', '@', '\t', '\r'];
+
+/// Prefixes `'` to a field that a spreadsheet would run as a formula, so that a subscriber
+/// named `=HYPERLINK(...)` is shown as text to the admin who opens the export
+fn escape_formula(field: String) -> String {
+    if field.starts_with(FORMULA_PREFIXES) {
+        format!("'{}", field)
+    } else {
+        field
+    }
+}
+
+fn to_csv<R, F>(records: &[R]) -> Result<Bytes, anyhow::Error>
+where
+    R: AsRef<[F]>,
+    F: AsRef<[u8]>,
+{
+    let mut writer = csv::Writer::from_writer(vec![]);
+    for record in records {
+        writer.write_record(record.as_ref())?;
+    }
+    let buffer = writer
+        .into_inner()
+        .context("Failed to flush the CSV writer")?;
+    Ok(Bytes::from(buffer))
+}
+
+/// Fetches the next batch of subscribers, ordered by subscription date
+#[tracing::instrument(name = "Get a batch of subscribers to export", skip(pool))]
+async fn get_subscriber_batch(
+    pool: &PgPool,
+    after: Option<(DateTime<Utc>, Uuid)>,
+) -> Result<Vec<ExportedSubscriber>, anyhow::Error> {
+    let (after_subscribed_at, after_id) = after.unzip();
+    let subscribers = sqlx::query_as!(
+        ExportedSubscriber,
+        r#"
+        SELECT
+            id, subscriptions.name, email, slug AS newsletter, status, subscribed_at,
+            consent.consented_at AS "consented_at?",
+            consent.ip_address AS consent_ip_address,
+            consent.consent_text_version,
+            consent.country AS consent_country,
+            consent.source_url AS consent_source_url
+        FROM subscriptions
+        JOIN newsletters USING (newsletter_id)
+        LEFT JOIN LATERAL (
+            SELECT consented_at, ip_address, consent_text_version, country, source_url
+            FROM subscription_consents
+            WHERE subscriber_id = subscriptions.id
+            ORDER BY consented_at DESC
+            LIMIT 1
+        ) AS consent ON true
+        WHERE
+            deleted_at IS NULL AND
+            ($1::timestamptz IS NULL OR (subscribed_at, id) > ($1, $2))
+        ORDER BY subscribed_at, id
+        LIMIT $3
+        "#,
+        after_subscribed_at,
+        after_id,
+        BATCH_SIZE
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve subscribers to export.")?;
+    Ok(subscribers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape_formula, to_csv};
+
+    #[test]
+    fn fields_are_quoted_when_needed() {
+        let csv = to_csv(&[["le guin, ursula", "\"quoted\""]]).unwrap();
+        assert_eq!(&csv[..], b"\"le guin, ursula\",\"\"\"quoted\"\"\"\n");
+    }
+
+    #[test]
+    fn fields_that_would_run_as_formulas_are_escaped() {
+        let csv = to_csv(&[[
+            escape_formula("=1+1".into()),
+            escape_formula("@SUM(A1:A2)".into()),
+            escape_formula("\t-1".into()),
+            escape_formula("ursula le guin".into()),
+        ]])
+        .unwrap();
+        assert_eq!(&csv[..], b"'=1+1,'@SUM(A1:A2),'\t-1,ursula le guin\n");
+    }
+}
@@ -0,0 +1,237 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context as _;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::configuration::DeletedSubscriberSettings;
+use crate::routing_helpers::e500;
+use crate::subscription_consents::{get_consents, RecordedConsent};
+use crate::subscription_events::{get_subscription_history, StatusChange};
+use crate::templates::{collect_flash_messages, Branding, Layout};
+
+const PAGE_SIZE: i64 = 50;
+
+#[derive(serde::Deserialize)]
+pub struct SubscriberQuery {
+    page: Option<i64>,
+    /// Only subscribers whose name or email contains this text are listed
+    #[serde(default)]
+    search: String,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubscriberSummary {
+    id: Uuid,
+    name: String,
+    email: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[derive(Template)]
+#[template(path = "subscribers.html")]
+struct SubscribersPage<'a> {
+    layout: Layout<'a>,
+    messages: Vec<FlashMessage>,
+    search: &'a str,
+    subscribers: Vec<SubscriberSummary>,
+    previous_page: Option<String>,
+    next_page: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "subscriber.html")]
+struct SubscriberPage<'a> {
+    layout: Layout<'a>,
+    subscriber: &'a SubscriberSummary,
+    consents: Vec<RecordedConsent>,
+    /// The most recent change first, like the consents
+    history: Vec<StatusChange>,
+}
+
+#[derive(Template)]
+#[template(path = "deleted_subscribers.html")]
+struct DeletedSubscribersPage<'a> {
+    layout: Layout<'a>,
+    messages: Vec<FlashMessage>,
+    settings: &'a DeletedSubscriberSettings,
+    subscribers: Vec<DeletedSubscriber>,
+}
+
+impl DeletedSubscribersPage<'_> {
+    /// When the data of a subscriber deleted at `deleted_at` is erased
+    fn purged_at(&self, deleted_at: &DateTime<Utc>) -> DateTime<Utc> {
+        *deleted_at + self.settings.retention()
+    }
+}
+
+/// Lists subscribers, most recent first
+pub async fn list_subscribers(
+    query: web::Query<SubscriberQuery>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let page = query.page.unwrap_or(1).max(1);
+    let search = query.search.trim();
+    let (subscribers, has_next_page) = get_subscribers(&pool, page, search).await.map_err(e500)?;
+    let body = SubscribersPage {
+        layout: Layout::new(&branding, "Subscribers"),
+        messages: collect_flash_messages(&flash_messages),
+        search,
+        subscribers,
+        previous_page: (page > 1).then(|| page_url(page - 1, search)),
+        next_page: has_next_page.then(|| page_url(page + 1, search)),
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Shows a subscriber with the consents they gave and the history of their status, e.g. to
+/// prove their opt-in when they complain about receiving the newsletter
+pub async fn subscriber_details(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+        SELECT id, name, email, status, subscribed_at
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to perform a query to retrieve the subscriber.")
+    .map_err(e500)?;
+    let Some(subscriber) = subscriber else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let consents = get_consents(&pool, subscriber_id).await.map_err(e500)?;
+    let mut history = get_subscription_history(&pool, subscriber_id)
+        .await
+        .map_err(e500)?;
+    history.reverse();
+    let body = SubscriberPage {
+        layout: Layout::new(&branding, &subscriber.name),
+        subscriber: &subscriber,
+        consents,
+        history,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+struct DeletedSubscriber {
+    id: Uuid,
+    name: String,
+    email: String,
+    status: String,
+    deleted_at: DateTime<Utc>,
+}
+
+/// Lists the deleted subscribers that can still be restored, the most recently deleted first
+pub async fn list_deleted_subscribers(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<Branding>,
+    settings: web::Data<DeletedSubscriberSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscribers = get_deleted_subscribers(&pool).await.map_err(e500)?;
+    let body = DeletedSubscribersPage {
+        layout: Layout::new(&branding, "Deleted subscribers"),
+        messages: collect_flash_messages(&flash_messages),
+        settings: &settings,
+        subscribers,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+#[tracing::instrument(name = "Get deleted subscribers", skip(pool))]
+async fn get_deleted_subscribers(pool: &PgPool) -> Result<Vec<DeletedSubscriber>, anyhow::Error> {
+    let subscribers = sqlx::query_as!(
+        DeletedSubscriber,
+        r#"
+        SELECT id, name, email, status, deleted_at AS "deleted_at!"
+        FROM subscriptions
+        WHERE deleted_at IS NOT NULL
+        ORDER BY deleted_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve the deleted subscribers.")?;
+    Ok(subscribers)
+}
+
+/// Builds the link to another page of results. Percent-encoding the search also makes the URL
+/// safe to embed in the page as-is.
+fn page_url(page: i64, search: &str) -> String {
+    let query =
+        serde_urlencoded::to_string([("page", page.to_string().as_str()), ("search", search)])
+            .expect("Failed to encode query parameters");
+    format!("/admin/subscribers?{}", query)
+}
+
+/// Fetches a page of subscribers matching the search, and whether there is a next page
+#[tracing::instrument(name = "Get subscribers", skip(pool))]
+pub async fn get_subscribers(
+    pool: &PgPool,
+    page: i64,
+    search: &str,
+) -> Result<(Vec<SubscriberSummary>, bool), anyhow::Error> {
+    let mut subscribers = sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+        SELECT id, name, email, status, subscribed_at
+        FROM subscriptions
+        WHERE
+            deleted_at IS NULL AND
+            (name ILIKE '%' || $3 || '%' OR email ILIKE '%' || $3 || '%')
+        ORDER BY subscribed_at DESC
+        LIMIT $1
+        OFFSET $2
+        "#,
+        PAGE_SIZE + 1,
+        (page - 1) * PAGE_SIZE,
+        search
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve subscribers.")?;
+    // we fetch one extra row to know whether there is a next page
+    let has_next_page = subscribers.len() as i64 > PAGE_SIZE;
+    subscribers.truncate(PAGE_SIZE as usize);
+    Ok((subscribers, has_next_page))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::page_url;
+
+    #[test]
+    fn page_urls_keep_the_search() {
+        assert_eq!(
+            page_url(2, "ursula le"),
+            "/admin/subscribers?page=2&search=ursula+le"
+        );
+    }
+}
@@ -0,0 +1,7 @@
+mod actions;
+mod export;
+mod get;
+
+pub use actions::*;
+pub use export::*;
+pub use get::*;
@@ -0,0 +1,66 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::audit_log::record_audit_entry;
+use crate::authentication::UserId;
+use crate::routing_helpers::{e500, see_other};
+
+/// Prevents an admin user from logging in again and ends their sessions. Pending invitations of
+/// the user are revoked.
+#[tracing::instrument(name = "Deactivate admin user", skip(pool, user_id))]
+pub async fn deactivate_user(
+    target_user_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let target_user_id = target_user_id.into_inner();
+    if target_user_id == **user_id {
+        FlashMessage::error("You cannot deactivate your own account.").send();
+        return Ok(see_other("/admin/users"));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let n_updated = sqlx::query!(
+        "UPDATE users SET is_active = FALSE WHERE user_id = $1",
+        target_user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to deactivate the user.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated == 0 {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    sqlx::query!(
+        "DELETE FROM user_invitations WHERE user_id = $1",
+        target_user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to revoke the invitations of the user.")
+    .map_err(e500)?;
+    record_audit_entry(
+        &mut transaction,
+        Some(**user_id),
+        "deactivate_user",
+        &target_user_id.to_string(),
+    )
+    .await
+    .context("Failed to record the deactivation in the audit log.")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to deactivate a user.")
+        .map_err(e500)?;
+    FlashMessage::success("The user has been deactivated.").send();
+    Ok(see_other("/admin/users"))
+}
@@ -0,0 +1,88 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context as _;
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::UserId;
+use crate::routing_helpers::e500;
+use crate::templates::{collect_flash_messages, Branding, Layout};
+
+#[derive(Template)]
+#[template(path = "users.html")]
+struct UsersPage<'a> {
+    layout: Layout<'a>,
+    messages: Vec<FlashMessage>,
+    users: Vec<UserSummary>,
+    current_user_id: Uuid,
+}
+
+impl UsersPage<'_> {
+    fn can_deactivate(&self, user: &UserSummary) -> bool {
+        // admins cannot lock themselves out
+        user.is_active && user.user_id != self.current_user_id
+    }
+}
+
+struct UserSummary {
+    user_id: Uuid,
+    username: String,
+    email: Option<String>,
+    is_active: bool,
+    has_password: bool,
+}
+
+impl UserSummary {
+    fn status(&self) -> &'static str {
+        if !self.is_active {
+            "deactivated"
+        } else if !self.has_password {
+            "invited"
+        } else {
+            "active"
+        }
+    }
+}
+
+/// Lists admin users, with a form to invite new ones
+pub async fn list_users(
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = UsersPage {
+        layout: Layout::new(&branding, "Admin users"),
+        messages: collect_flash_messages(&flash_messages),
+        users: get_users(&pool).await.map_err(e500)?,
+        current_user_id: **user_id,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+#[tracing::instrument(name = "Get admin users", skip(pool))]
+async fn get_users(pool: &PgPool) -> Result<Vec<UserSummary>, anyhow::Error> {
+    let users = sqlx::query_as!(
+        UserSummary,
+        r#"
+        SELECT
+            user_id,
+            username,
+            email,
+            is_active,
+            password_hash IS NOT NULL AS "has_password!"
+        FROM users
+        ORDER BY username
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve admin users.")?;
+    Ok(users)
+}
@@ -0,0 +1,170 @@
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::audit_log::record_audit_entry;
+use crate::authentication::UserId;
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClient, SendEmailError};
+use crate::routes::generate_subscription_token;
+use crate::routing_helpers::{e500, html_escape, see_other};
+use crate::url_builder::UrlBuilder;
+
+/// How long an invitation link stays valid
+pub const INVITATION_TTL_DAYS: i64 = 7;
+
+#[derive(serde::Deserialize)]
+pub struct InviteFormData {
+    username: String,
+    email: String,
+}
+
+/// Creates an admin user without a password and emails them a link to set one
+#[tracing::instrument(
+    name = "Invite admin user",
+    skip(form, pool, email_client, urls, user_id),
+    fields(username = %form.username)
+)]
+pub async fn invite_user(
+    form: web::Form<InviteFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    urls: web::Data<UrlBuilder>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let username = form.0.username.trim();
+    if username.is_empty() || username.len() > 256 {
+        FlashMessage::error("The username must be between 1 and 256 characters.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    let email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let Some(invited_user_id) = insert_invited_user(&mut transaction, username, &email)
+        .await
+        .context("Failed to insert the invited user.")
+        .map_err(e500)?
+    else {
+        FlashMessage::error(format!("The username {} is already taken.", username)).send();
+        return Ok(see_other("/admin/users"));
+    };
+    let invitation_token = generate_subscription_token();
+    store_invitation(&mut transaction, invited_user_id, &invitation_token)
+        .await
+        .context("Failed to store the invitation.")
+        .map_err(e500)?;
+    record_audit_entry(
+        &mut transaction,
+        Some(**user_id),
+        "invite_user",
+        &invited_user_id.to_string(),
+    )
+    .await
+    .context("Failed to record the invitation in the audit log.")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to invite a user.")
+        .map_err(e500)?;
+
+    send_invitation_email(&email_client, &email, username, &urls, &invitation_token)
+        .await
+        .context("Failed to send the invitation email.")
+        .map_err(e500)?;
+    FlashMessage::success(format!(
+        "An invitation has been sent to {}.",
+        email.as_ref()
+    ))
+    .send();
+    Ok(see_other("/admin/users"))
+}
+
+/// Returns `None` if the username is already taken
+async fn insert_invited_user(
+    transaction: &mut Transaction<'_, Postgres>,
+    username: &str,
+    email: &SubscriberEmail,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let user_id = Uuid::new_v4();
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO users (user_id, username, email, password_hash)
+        VALUES ($1, $2, $3, NULL)
+        ON CONFLICT (username) DO NOTHING
+        "#,
+        user_id,
+        username,
+        email.as_ref()
+    )
+    .execute(transaction)
+    .await?
+    .rows_affected();
+    Ok((n_inserted > 0).then_some(user_id))
+}
+
+async fn store_invitation(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    invitation_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO user_invitations (invitation_token, user_id, expires_at)
+        VALUES ($1, $2, $3)
+        "#,
+        invitation_token,
+        user_id,
+        Utc::now() + Duration::days(INVITATION_TTL_DAYS)
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Send an invitation email",
+    skip(email_client, recipient, urls, invitation_token)
+)]
+async fn send_invitation_email(
+    email_client: &EmailClient,
+    recipient: &SubscriberEmail,
+    username: &str,
+    urls: &UrlBuilder,
+    invitation_token: &str,
+) -> Result<(), SendEmailError> {
+    let invitation_link = urls.invitation_url(invitation_token);
+    let html_body = format!(
+        "You have been invited to manage the newsletter as {}.<br />\
+        Click <a href=\"{}\">here</a> to choose your password. The link expires in {} days.",
+        html_escape(username),
+        invitation_link,
+        INVITATION_TTL_DAYS
+    );
+    let text_body = format!(
+        "You have been invited to manage the newsletter as {}.\n\
+        Visit {} to choose your password. The link expires in {} days.",
+        username, invitation_link, INVITATION_TTL_DAYS
+    );
+    email_client
+        .send_email(
+            recipient,
+            "You have been invited to manage the newsletter",
+            &html_body,
+            &text_body,
+        )
+        .await
+}
@@ -0,0 +1,7 @@
+mod actions;
+mod get;
+mod invite;
+
+pub use actions::*;
+pub use get::*;
+pub use invite::*;
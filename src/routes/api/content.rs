@@ -0,0 +1,239 @@
+use actix_web::http::header;
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::UserId;
+use crate::configuration::{HtmlSanitizationSettings, PayloadLimitSettings};
+use crate::newsletter_content::prepare_html_content;
+use crate::routes::api::ApiError;
+use crate::routes::record_draft_revision;
+use crate::session_state::TypedSession;
+use crate::url_builder::UrlBuilder;
+
+/// Autosaves of the same editor are kept as one revision per such interval, rather than one per
+/// request
+const AUTOSAVE_REVISION_INTERVAL_MINUTES: i32 = 10;
+
+#[derive(serde::Deserialize)]
+pub struct ContentUpdate {
+    title: String,
+    text_content: String,
+    html_content: String,
+    /// The document of the rich text editor, stored as is
+    #[serde(default)]
+    blocks: Option<serde_json::Value>,
+    /// The version of the draft the editor started from
+    version: i32,
+    /// Keeps the `<style>` blocks as they are instead of inlining their rules
+    #[serde(default)]
+    skip_css_inlining: bool,
+}
+
+/// `GET /api/v1/newsletters/{issue_id}/content`: the content of an issue, with the document of the
+/// rich text editor it was written with and the version to send back when saving it
+#[tracing::instrument(name = "Get the content of a newsletter issue", skip(pool))]
+pub async fn api_get_issue_content(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let issue_id = issue_id.into_inner();
+    let issue = sqlx::query!(
+        r#"
+        SELECT status, title, text_content, html_content, content_blocks, version
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to perform a query to retrieve the newsletter issue.")?
+    .ok_or_else(|| ApiError::NotFound("There is no newsletter issue with this id.".into()))?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "newsletter_issue_id": issue_id,
+        "status": issue.status,
+        "title": issue.title,
+        "text_content": issue.text_content,
+        "html_content": issue.html_content,
+        "blocks": issue.content_blocks,
+        "version": issue.version,
+    })))
+}
+
+/// `PUT /api/v1/newsletters/{issue_id}/content`: saves the content of a draft from a rich text
+/// editor, which may call it as often as it likes. Saves are rejected with a conflict if the draft
+/// changed since `version`; saving the content the draft already has changes nothing, and does
+/// not bump its version.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "Save the content of a newsletter draft",
+    skip_all,
+    fields(issue_id=%issue_id, user_id=%&*user_id)
+)]
+pub async fn api_save_issue_content(
+    issue_id: web::Path<Uuid>,
+    update: web::Json<ContentUpdate>,
+    request: HttpRequest,
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    urls: web::Data<UrlBuilder>,
+    payload_limits: web::Data<PayloadLimitSettings>,
+    html_sanitization: web::Data<HtmlSanitizationSettings>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, ApiError> {
+    check_same_origin(&request, &session, &urls)?;
+    let issue_id = issue_id.into_inner();
+    let update = update.into_inner();
+    payload_limits
+        .check_newsletter_html(&update.html_content)
+        .map_err(ApiError::PayloadTooLarge)?;
+    let html_content = prepare_html_content(
+        &update.html_content,
+        update.skip_css_inlining,
+        &html_sanitization,
+    );
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let draft = sqlx::query!(
+        r#"
+        SELECT status, title, text_content, html_content, content_blocks, version
+        FROM newsletter_issues
+        WHERE newsletter_issue_id = $1
+        FOR UPDATE
+        "#,
+        issue_id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to perform a query to retrieve the newsletter issue.")?
+    .ok_or_else(|| ApiError::NotFound("There is no newsletter issue with this id.".into()))?;
+    if draft.status != "draft" {
+        return Err(ApiError::Conflict(
+            "The issue has already been published and can no longer be edited.".into(),
+        ));
+    }
+    if draft.version != update.version {
+        return Err(ApiError::Conflict(format!(
+            "The issue was edited in the meantime: it is now at version {}.",
+            draft.version
+        )));
+    }
+    let unchanged = draft.title == update.title
+        && draft.text_content == update.text_content
+        && draft.html_content == html_content
+        && draft.content_blocks == update.blocks;
+    if unchanged {
+        return Ok(HttpResponse::Ok().json(serde_json::json!({
+            "newsletter_issue_id": issue_id,
+            "version": draft.version,
+            "saved": false,
+        })));
+    }
+
+    if !autosaved_recently(&mut transaction, issue_id, **user_id).await? {
+        record_draft_revision(
+            &mut transaction,
+            issue_id,
+            Some(update.version),
+            &update.title,
+            &update.text_content,
+            &html_content,
+            **user_id,
+        )
+        .await
+        .context("Failed to keep the current content of the newsletter draft.")?;
+    }
+    let version = sqlx::query_scalar!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            title = $2,
+            text_content = $3,
+            html_content = $4,
+            content_blocks = $5,
+            version = version + 1
+        WHERE newsletter_issue_id = $1
+        RETURNING version
+        "#,
+        issue_id,
+        update.title,
+        update.text_content,
+        html_content,
+        update.blocks
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .context("Failed to save the content of the newsletter draft.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the SQL transaction to save a newsletter draft.")?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "newsletter_issue_id": issue_id,
+        "version": version,
+        "saved": true,
+    })))
+}
+
+/// Writes authenticated by the session cookie must come from the pages of the application: a page
+/// on another site could otherwise make the browser of a logged-in admin send them. Browsers
+/// always tell where a `PUT` comes from with the `Origin` header. Clients sending credentials are
+/// not concerned, since browsers never send those on their own.
+fn check_same_origin(
+    request: &HttpRequest,
+    session: &TypedSession,
+    urls: &UrlBuilder,
+) -> Result<(), ApiError> {
+    let uses_session = session
+        .get_user_id()
+        .context("Failed to read the session.")?
+        .is_some();
+    let Some(origin) = request.headers().get(header::ORIGIN) else {
+        return Ok(());
+    };
+    let own_origin = reqwest::Url::parse(urls.base_url())
+        .context("Invalid `application.base_url`.")?
+        .origin()
+        .ascii_serialization();
+    if uses_session && origin.as_bytes() != own_origin.as_bytes() {
+        return Err(ApiError::Forbidden(
+            "Requests authenticated by the session must come from the pages of the application."
+                .into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether the latest revision of the draft was kept by the same editor less than
+/// `AUTOSAVE_REVISION_INTERVAL_MINUTES` ago
+async fn autosaved_recently(
+    transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    issue_id: Uuid,
+    user_id: Uuid,
+) -> Result<bool, anyhow::Error> {
+    let recently = sqlx::query_scalar!(
+        r#"
+        SELECT
+            COALESCE(
+                replaced_by = $2 AND replaced_at > now() - make_interval(mins => $3),
+                false
+            ) AS "recently!"
+        FROM issue_revisions
+        WHERE newsletter_issue_id = $1
+        ORDER BY replaced_at DESC
+        LIMIT 1
+        "#,
+        issue_id,
+        user_id,
+        AUTOSAVE_REVISION_INTERVAL_MINUTES
+    )
+    .fetch_optional(transaction)
+    .await
+    .context("Failed to perform a query to retrieve the latest revision of the draft.")?;
+    Ok(recently.unwrap_or(false))
+}
@@ -0,0 +1,132 @@
+use std::fmt::Formatter;
+
+use actix_web::error::JsonPayloadError;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+
+use crate::error_handling::{error_chain_fmt, UNEXPECTED_ERROR_MESSAGE};
+use crate::routes::{ConfirmSubscriberError, SubscribeError};
+
+/// Errors of the JSON API. They are all reported with the same envelope:
+/// `{"error": {"code": "not_found", "message": "..."}}`
+#[derive(thiserror::Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Unauthorized(String),
+    #[error("{0}")]
+    Forbidden(String),
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    Conflict(String),
+    #[error("{0}")]
+    Gone(String),
+    #[error("{0}")]
+    PayloadTooLarge(String),
+    #[error("{0}")]
+    TooManyRequests(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl ApiError {
+    /// A stable identifier of the kind of error, for clients to match on
+    fn code(&self) -> &'static str {
+        match self {
+            ApiError::BadRequest(_) => "bad_request",
+            ApiError::Unauthorized(_) => "unauthorized",
+            ApiError::Forbidden(_) => "forbidden",
+            ApiError::NotFound(_) => "not_found",
+            ApiError::Conflict(_) => "conflict",
+            ApiError::Gone(_) => "gone",
+            ApiError::PayloadTooLarge(_) => "payload_too_large",
+            ApiError::TooManyRequests(_) => "too_many_requests",
+            ApiError::UnexpectedError(_) => "internal_error",
+        }
+    }
+}
+
+impl std::fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            ApiError::Forbidden(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+            ApiError::Conflict(_) => StatusCode::CONFLICT,
+            ApiError::Gone(_) => StatusCode::GONE,
+            ApiError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            ApiError::TooManyRequests(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        // the cause of unexpected errors is logged, never shown to clients
+        let message = match self {
+            ApiError::UnexpectedError(_) => UNEXPECTED_ERROR_MESSAGE.to_string(),
+            e => e.to_string(),
+        };
+        HttpResponse::build(self.status_code()).json(serde_json::json!({
+            "error": {
+                "code": self.code(),
+                "message": message,
+            }
+        }))
+    }
+}
+
+impl From<SubscribeError> for ApiError {
+    fn from(e: SubscribeError) -> Self {
+        match e {
+            SubscribeError::ValidationError(message) => ApiError::BadRequest(message),
+            SubscribeError::UnexpectedError(e) => ApiError::UnexpectedError(e),
+        }
+    }
+}
+
+impl From<ConfirmSubscriberError> for ApiError {
+    fn from(e: ConfirmSubscriberError) -> Self {
+        match e {
+            ConfirmSubscriberError::UnknownToken => ApiError::Unauthorized(e.to_string()),
+            ConfirmSubscriberError::ExpiredToken => ApiError::Gone(e.to_string()),
+            ConfirmSubscriberError::UnexpectedError(e) => ApiError::UnexpectedError(e),
+        }
+    }
+}
+
+/// Makes malformed or oversized JSON bodies get the error envelope instead of a plain text
+/// response
+pub fn api_json_config(limit: usize) -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(limit)
+        .error_handler(|e, _| match e {
+            JsonPayloadError::Overflow { .. } | JsonPayloadError::OverflowKnownLength { .. } => {
+                ApiError::PayloadTooLarge(e.to_string()).into()
+            }
+            e => ApiError::BadRequest(e.to_string()).into(),
+        })
+}
+
+/// Makes malformed query strings get the error envelope instead of a plain text response
+pub fn api_query_config() -> web::QueryConfig {
+    web::QueryConfig::default().error_handler(|e, _| ApiError::BadRequest(e.to_string()).into())
+}
+
+/// Makes malformed path segments get the error envelope instead of a plain text response
+pub fn api_path_config() -> web::PathConfig {
+    web::PathConfig::default().error_handler(|e, _| ApiError::NotFound(e.to_string()).into())
+}
+
+/// Fallback for the paths under the API that match no endpoint
+pub async fn api_route_not_found() -> Result<HttpResponse, ApiError> {
+    Err(ApiError::NotFound("There is no such endpoint.".into()))
+}
@@ -0,0 +1,40 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+
+use crate::events::get_events;
+use crate::routes::api::ApiError;
+
+/// The number of events returned when the request does not say
+const DEFAULT_LIMIT: i64 = 100;
+const MAX_LIMIT: i64 = 1000;
+
+#[derive(serde::Deserialize)]
+pub struct EventsRequest {
+    /// The `next_cursor` of the previous response; the feed starts from the beginning without it
+    #[serde(default)]
+    since: i64,
+    limit: Option<i64>,
+}
+
+/// `GET /api/v1/events`: the changes to subscribers and issues since a cursor, the oldest first,
+/// for tools that poll for changes instead of receiving webhooks
+pub async fn api_list_events(
+    query: web::Query<EventsRequest>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT);
+    if !(1..=MAX_LIMIT).contains(&limit) {
+        return Err(ApiError::BadRequest(format!(
+            "The limit must be between 1 and {}.",
+            MAX_LIMIT
+        )));
+    }
+    let (events, has_more) = get_events(&pool, query.since, limit).await?;
+    // polling with the same cursor again is harmless when there was nothing new
+    let next_cursor = events.last().map_or(query.since, |event| event.id);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "events": events,
+        "next_cursor": next_cursor,
+        "has_more": has_more,
+    })))
+}
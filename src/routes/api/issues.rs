@@ -0,0 +1,23 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::routes::api::ApiError;
+use crate::routes::{get_published_issue, get_published_issues};
+
+/// `GET /api/v1/issues`: the public archive of published issues, most recent first
+pub async fn api_list_issues(pool: web::Data<PgPool>) -> Result<HttpResponse, ApiError> {
+    let issues = get_published_issues(&pool).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "issues": issues })))
+}
+
+/// `GET /api/v1/issues/{issue_id}`: the content of a published issue
+pub async fn api_get_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let issue = get_published_issue(&pool, issue_id.into_inner())
+        .await?
+        .ok_or_else(|| ApiError::NotFound("There is no published issue with this id.".into()))?;
+    Ok(HttpResponse::Ok().json(issue))
+}
@@ -0,0 +1,17 @@
+//! Version 1 of the JSON API, for mobile apps and other services. It exposes the same
+//! capabilities as the HTML pages, with JSON bodies and errors instead of forms and redirects.
+mod content;
+mod error;
+mod events;
+mod issues;
+mod newsletters;
+mod subscribers;
+mod subscriptions;
+
+pub use content::*;
+pub use error::*;
+pub use events::*;
+pub use issues::*;
+pub use newsletters::*;
+pub use subscribers::*;
+pub use subscriptions::*;
@@ -0,0 +1,282 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::analytics::get_issue_analytics;
+use crate::authentication::UserId;
+use crate::configuration::{HtmlSanitizationSettings, PayloadLimitSettings, UtmSettings};
+use crate::domain::SubscriberTag;
+use crate::email_templates::{apply_email_template, find_email_template};
+use crate::idempotency::IdempotentTransaction;
+use crate::newsletter_content::{
+    prepare_html_content, remove_view_in_browser_link_html, remove_view_in_browser_link_text,
+    UtmParameters,
+};
+use crate::newsletters::find_newsletter;
+use crate::routes::api::ApiError;
+use crate::routes::{insert_draft, parse_local_send_time, publish_issue, NewIssue};
+use crate::url_builder::UrlBuilder;
+
+#[derive(serde::Deserialize)]
+pub struct PublishRequest {
+    title: String,
+    text_content: String,
+    html_content: String,
+    draft_id: Option<Uuid>,
+    /// Slug of the newsletter the issue goes to; the default newsletter if missing
+    #[serde(default)]
+    newsletter: String,
+    /// Only subscribers with this tag receive the issue; everybody does if it is missing
+    segment: Option<String>,
+    #[serde(default)]
+    click_tracking: bool,
+    /// `HH:MM` at which each subscriber gets the issue in their own time zone; right away if missing
+    local_send_time: Option<String>,
+    /// UTM parameters of the links of the issue; the defaults of the settings if missing
+    #[serde(default)]
+    utm_source: String,
+    #[serde(default)]
+    utm_medium: String,
+    #[serde(default)]
+    utm_campaign: String,
+    /// Keeps the `<style>` blocks as they are instead of inlining their rules
+    #[serde(default)]
+    skip_css_inlining: bool,
+    /// The template the content is put into; sent as written if missing
+    email_template_id: Option<Uuid>,
+}
+
+/// `POST /api/v1/admin/newsletters`: publishes an issue. Clients must send an `Idempotency-Key`
+/// header, so that retrying a request whose response got lost does not send the issue twice:
+/// retries are answered by the idempotency middleware. The issue is published in the transaction
+/// holding the key, so that it is only committed along with the saved response.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "Publish a newsletter issue through the API",
+    skip_all,
+    fields(user_id=%&*user_id)
+)]
+pub async fn api_publish_newsletter(
+    request: web::Json<PublishRequest>,
+    mut transaction: IdempotentTransaction,
+    user_id: web::ReqData<UserId>,
+    urls: web::Data<UrlBuilder>,
+    payload_limits: web::Data<PayloadLimitSettings>,
+    utm_settings: web::Data<Option<UtmSettings>>,
+    html_sanitization: web::Data<HtmlSanitizationSettings>,
+) -> Result<HttpResponse, ApiError> {
+    let PublishRequest {
+        title,
+        text_content,
+        html_content,
+        draft_id,
+        newsletter,
+        segment,
+        click_tracking,
+        local_send_time,
+        utm_source,
+        utm_medium,
+        utm_campaign,
+        skip_css_inlining,
+        email_template_id,
+    } = request.0;
+    payload_limits
+        .check_newsletter_html(&html_content)
+        .map_err(ApiError::PayloadTooLarge)?;
+    let email_template = match email_template_id {
+        Some(email_template_id) => Some(
+            find_email_template(&mut *transaction, email_template_id)
+                .await
+                .context("Failed to look up the email template.")?
+                .ok_or_else(|| {
+                    ApiError::BadRequest("There is no email template with this id.".into())
+                })?,
+        ),
+        None => None,
+    };
+    let (text_content, html_content) =
+        apply_email_template(email_template.as_ref(), &title, text_content, html_content);
+    let html_content = prepare_html_content(&html_content, skip_css_inlining, &html_sanitization);
+    let newsletter_id = find_newsletter(&mut *transaction, &newsletter)
+        .await
+        .context("Failed to look up the newsletter.")?
+        .ok_or_else(|| {
+            ApiError::BadRequest(format!("There is no newsletter named {}.", newsletter))
+        })?
+        .newsletter_id;
+    let segment =
+        SubscriberTag::parse_optional(segment.unwrap_or_default()).map_err(ApiError::BadRequest)?;
+    let local_send_time = parse_local_send_time(&local_send_time.unwrap_or_default())
+        .map_err(ApiError::BadRequest)?;
+    let issue = NewIssue {
+        newsletter_id,
+        title: &title,
+        text_content: &text_content,
+        html_content: &html_content,
+        segment: segment.as_ref(),
+        click_tracking,
+        local_send_time,
+        email_template_id,
+        utm: UtmParameters::for_issue(
+            utm_settings.as_ref().as_ref(),
+            &title,
+            &utm_source,
+            &utm_medium,
+            &utm_campaign,
+        ),
+    };
+    let issue_id = publish_issue(&mut transaction, &urls, draft_id, issue)
+        .await?
+        .ok_or_else(|| {
+            ApiError::Conflict("The draft no longer exists or has already been published.".into())
+        })?;
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "newsletter_issue_id": issue_id
+    })))
+}
+
+/// `GET /api/v1/admin/newsletters/{issue_id}/analytics`: the delivery, open and click figures of
+/// an issue
+pub async fn api_newsletter_analytics(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let analytics = get_issue_analytics(&pool, issue_id.into_inner())
+        .await?
+        .ok_or_else(|| ApiError::NotFound("There is no issue with this id.".into()))?;
+    let rates = serde_json::json!({
+        "failure_rate": analytics.failure_rate(),
+        "open_rate": analytics.open_rate(),
+        "click_through_rate": analytics.click_through_rate(),
+    });
+    let mut body = serde_json::to_value(&analytics)
+        .context("Failed to serialize the analytics of the issue.")?;
+    body["rates"] = rates;
+    Ok(HttpResponse::Ok().json(body))
+}
+
+/// An issue in a form that can be moved to another deployment: the newsletter is referred to by
+/// its slug, and nothing links back to the deployment it comes from
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct IssueDocument {
+    title: String,
+    text_content: String,
+    html_content: String,
+    /// Slug of the newsletter the issue is for; the default newsletter if missing
+    #[serde(default)]
+    newsletter: String,
+    segment: Option<String>,
+    #[serde(default)]
+    click_tracking: bool,
+    /// Where the issue comes from, for information: it is ignored on import
+    #[serde(default, skip_deserializing)]
+    metadata: Option<IssueMetadata>,
+}
+
+#[derive(serde::Serialize)]
+pub struct IssueMetadata {
+    newsletter_issue_id: Uuid,
+    status: String,
+    published_at: Option<DateTime<Utc>>,
+    exported_at: DateTime<Utc>,
+}
+
+/// `GET /api/v1/admin/newsletters/{issue_id}/export`: the content and settings of an issue or a
+/// draft, in the format `POST /api/v1/admin/newsletters/import` accepts
+#[tracing::instrument(name = "Export a newsletter issue", skip(pool, urls))]
+pub async fn api_export_newsletter_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    urls: web::Data<UrlBuilder>,
+) -> Result<HttpResponse, ApiError> {
+    let issue_id = issue_id.into_inner();
+    let issue = sqlx::query!(
+        r#"
+        SELECT
+            title,
+            text_content,
+            html_content,
+            segment,
+            click_tracking,
+            status,
+            published_at,
+            (SELECT slug FROM newsletters n WHERE n.newsletter_id = i.newsletter_id) AS "newsletter!"
+        FROM newsletter_issues i
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool.as_ref())
+    .await
+    .context("Failed to perform a query to retrieve the newsletter issue.")?
+    .ok_or_else(|| ApiError::NotFound("There is no issue with this id.".into()))?;
+    // the link to the archived version would point at this deployment
+    let issue_url = urls.issue_url(issue_id);
+    let document = IssueDocument {
+        title: issue.title,
+        text_content: remove_view_in_browser_link_text(&issue.text_content, &issue_url),
+        html_content: remove_view_in_browser_link_html(&issue.html_content, &issue_url),
+        newsletter: issue.newsletter,
+        segment: issue.segment,
+        click_tracking: issue.click_tracking,
+        metadata: Some(IssueMetadata {
+            newsletter_issue_id: issue_id,
+            status: issue.status,
+            published_at: issue.published_at,
+            exported_at: Utc::now(),
+        }),
+    };
+    Ok(HttpResponse::Ok().json(document))
+}
+
+/// `POST /api/v1/admin/newsletters/import`: stores an exported issue as a new draft, to be
+/// reviewed and published from the admin panel
+#[tracing::instrument(name = "Import a newsletter issue", skip_all)]
+pub async fn api_import_newsletter_issue(
+    document: web::Json<IssueDocument>,
+    pool: web::Data<PgPool>,
+    payload_limits: web::Data<PayloadLimitSettings>,
+    html_sanitization: web::Data<HtmlSanitizationSettings>,
+) -> Result<HttpResponse, ApiError> {
+    let IssueDocument {
+        title,
+        text_content,
+        html_content,
+        newsletter,
+        segment,
+        click_tracking,
+        metadata: _,
+    } = document.0;
+    if title.trim().is_empty() {
+        return Err(ApiError::BadRequest("The issue has no title.".into()));
+    }
+    payload_limits
+        .check_newsletter_html(&html_content)
+        .map_err(ApiError::PayloadTooLarge)?;
+    let html_content = prepare_html_content(&html_content, false, &html_sanitization);
+    let newsletter_id = find_newsletter(pool.get_ref(), &newsletter)
+        .await
+        .context("Failed to look up the newsletter.")?
+        .ok_or_else(|| {
+            ApiError::BadRequest(format!("There is no newsletter named {}.", newsletter))
+        })?
+        .newsletter_id;
+    let segment =
+        SubscriberTag::parse_optional(segment.unwrap_or_default()).map_err(ApiError::BadRequest)?;
+    let draft_id = insert_draft(
+        &pool,
+        newsletter_id,
+        &title,
+        &text_content,
+        &html_content,
+        segment.as_ref().map(AsRef::as_ref),
+        click_tracking,
+        // templates belong to the deployment, like the ids the document leaves out
+        None,
+    )
+    .await
+    .context("Failed to store the imported draft.")?;
+    Ok(HttpResponse::Created().json(serde_json::json!({ "draft_id": draft_id })))
+}
@@ -0,0 +1,67 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::UserId;
+use crate::routes::api::ApiError;
+use crate::routes::{
+    confirm_pending_subscriber, erase_subscriber, get_subscribers, ManualConfirmation,
+};
+
+#[derive(serde::Deserialize)]
+pub struct SubscribersRequest {
+    page: Option<i64>,
+    /// Only subscribers whose name or email contains this text are listed
+    #[serde(default)]
+    search: String,
+}
+
+/// `GET /api/v1/admin/subscribers`: a page of subscribers, most recent first
+pub async fn api_list_subscribers(
+    query: web::Query<SubscribersRequest>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    let page = query.page.unwrap_or(1).max(1);
+    let (subscribers, has_next_page) = get_subscribers(&pool, page, query.search.trim()).await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "subscribers": subscribers,
+        "page": page,
+        "has_next_page": has_next_page,
+    })))
+}
+
+/// `DELETE /api/v1/admin/subscribers/{subscriber_id}`: erases a subscriber and their data
+#[tracing::instrument(name = "Erase subscriber through the API", skip(pool, user_id))]
+pub async fn api_delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, ApiError> {
+    if erase_subscriber(&pool, subscriber_id.into_inner(), Some(**user_id)).await? {
+        Ok(HttpResponse::NoContent().finish())
+    } else {
+        Err(subscriber_not_found())
+    }
+}
+
+/// `POST /api/v1/admin/subscribers/{subscriber_id}/confirm`: confirms a pending subscriber on
+/// their behalf
+#[tracing::instrument(name = "Force-confirm subscriber through the API", skip(pool))]
+pub async fn api_confirm_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ApiError> {
+    match confirm_pending_subscriber(&pool, subscriber_id.into_inner()).await? {
+        ManualConfirmation::Confirmed => {
+            Ok(HttpResponse::Ok().json(serde_json::json!({ "status": "confirmed" })))
+        }
+        ManualConfirmation::NotPending => Err(ApiError::Conflict(
+            "Only a pending subscriber can be confirmed.".into(),
+        )),
+        ManualConfirmation::NotFound => Err(subscriber_not_found()),
+    }
+}
+
+fn subscriber_not_found() -> ApiError {
+    ApiError::NotFound("There is no subscriber with this id.".into())
+}
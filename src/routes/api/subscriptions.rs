@@ -0,0 +1,143 @@
+use actix_web::{web, HttpRequest, HttpResponse};
+use sqlx::PgPool;
+
+use crate::captcha::CaptchaClient;
+use crate::domain::{
+    NewSubscriber, SubscriberEmail, SubscriberName, SubscriberNamePolicy, SubscriberTag,
+};
+use crate::email_client::EmailClient;
+use crate::email_validation::EmailValidator;
+use crate::routes::api::ApiError;
+use crate::routes::{
+    check_captcha, confirm_subscription, consent_of, find_subscribed_newsletter,
+    register_subscriber,
+};
+use crate::subscription_tokens::SubscriptionTokens;
+use crate::telemetry::pii_email;
+use crate::templates::ConfirmationEmailTemplate;
+use crate::url_builder::UrlBuilder;
+
+#[derive(serde::Deserialize)]
+pub struct SubscribeRequest {
+    email: String,
+    name: String,
+    #[serde(default)]
+    tags: Vec<String>,
+    /// Slug of the newsletter to subscribe to; the default newsletter if missing
+    #[serde(default)]
+    newsletter: String,
+    /// Required when a CAPTCHA is configured; apps get it from the provider's mobile SDK
+    #[serde(default)]
+    captcha_response: String,
+    /// Which wording of the consent text the app shows
+    #[serde(default)]
+    consent_text_version: String,
+    /// The country whose consent rules the app applies
+    #[serde(default)]
+    country: String,
+    /// The page the subscriber signed up on, for clients embedding a form on a website
+    #[serde(default)]
+    source_url: String,
+}
+
+impl SubscribeRequest {
+    fn into_new_subscriber(
+        self,
+        name_policy: &SubscriberNamePolicy,
+    ) -> Result<NewSubscriber, String> {
+        let name = SubscriberName::parse_with(self.name, name_policy)?;
+        let email = SubscriberEmail::parse(self.email)?;
+        let tags = self
+            .tags
+            .into_iter()
+            .map(SubscriberTag::parse)
+            .collect::<Result<_, _>>()?;
+        Ok(NewSubscriber { name, email, tags })
+    }
+}
+
+/// `POST /api/v1/subscriptions`: sends a confirmation email, exactly like the subscribe form.
+/// The response does not tell whether the email address was already subscribed.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "Adding a new subscriber through the API",
+    skip(
+        http_request,
+        request,
+        connection_pool,
+        email_client,
+        confirmation_email,
+        urls,
+        subscription_tokens,
+        captcha,
+        email_validator,
+        name_policy
+    ),
+    fields(subscriber_email = %pii_email(&request.email))
+)]
+pub async fn api_subscribe(
+    http_request: HttpRequest,
+    request: web::Json<SubscribeRequest>,
+    connection_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    confirmation_email: web::Data<ConfirmationEmailTemplate>,
+    urls: web::Data<UrlBuilder>,
+    subscription_tokens: web::Data<SubscriptionTokens>,
+    captcha: web::Data<Option<CaptchaClient>>,
+    email_validator: web::Data<EmailValidator>,
+    name_policy: web::Data<SubscriberNamePolicy>,
+) -> Result<HttpResponse, ApiError> {
+    check_captcha(captcha.as_ref().as_ref(), &request.captcha_response).await?;
+    let newsletter = find_subscribed_newsletter(&connection_pool, &request.newsletter).await?;
+    let consent = consent_of(
+        &http_request,
+        &request.consent_text_version,
+        &request.country,
+        &request.source_url,
+    )
+    .map_err(ApiError::BadRequest)?;
+    let new_subscriber = request
+        .0
+        .into_new_subscriber(&name_policy)
+        .map_err(ApiError::BadRequest)?;
+    email_validator
+        .check(&new_subscriber.email)
+        .await
+        .map_err(ApiError::BadRequest)?;
+    register_subscriber(
+        &connection_pool,
+        &email_client,
+        &confirmation_email,
+        &urls,
+        &subscription_tokens,
+        &newsletter,
+        &new_subscriber,
+        &consent,
+    )
+    .await?;
+    Ok(HttpResponse::Accepted().json(serde_json::json!({
+        "status": "pending_confirmation"
+    })))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ConfirmRequest {
+    subscription_token: String,
+}
+
+/// `POST /api/v1/subscriptions/confirm`: confirms a subscriber with the token of their
+/// confirmation link
+#[tracing::instrument(name = "Confirm a pending subscriber through the API", skip_all)]
+pub async fn api_confirm(
+    request: web::Json<ConfirmRequest>,
+    connection_pool: web::Data<PgPool>,
+    subscription_tokens: web::Data<SubscriptionTokens>,
+) -> Result<HttpResponse, ApiError> {
+    confirm_subscription(
+        &request.subscription_token,
+        &subscription_tokens,
+        &connection_pool,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "status": "confirmed" })))
+}
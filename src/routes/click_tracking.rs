@@ -0,0 +1,78 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::routing_helpers::{e500, see_other};
+
+/// `GET /t/click/{delivery_id}/{link_id}`: records a click on a tracked link of an issue, then
+/// redirects to the target of the link
+#[tracing::instrument(name = "Follow a tracked link", skip(pool))]
+pub async fn follow_tracked_link(
+    path: web::Path<(Uuid, i32)>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (delivery_id, link_id) = path.into_inner();
+    let Some(link) = get_tracked_link(&pool, delivery_id, link_id)
+        .await
+        .map_err(e500)?
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    // failing to count a click must not break the link
+    if let Err(e) = record_click(&pool, link.newsletter_issue_id, link_id, delivery_id).await {
+        tracing::error!(error.cause_chain = ?e, "Failed to record a click on a tracked link.");
+    }
+    Ok(see_other(&link.url))
+}
+
+struct TrackedLink {
+    newsletter_issue_id: Uuid,
+    url: String,
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_tracked_link(
+    pool: &PgPool,
+    delivery_id: Uuid,
+    link_id: i32,
+) -> Result<Option<TrackedLink>, anyhow::Error> {
+    let link = sqlx::query_as!(
+        TrackedLink,
+        r#"
+        SELECT l.newsletter_issue_id, l.url
+        FROM issue_deliveries d
+        JOIN issue_links l ON l.newsletter_issue_id = d.newsletter_issue_id
+        WHERE
+            d.delivery_id = $1 AND
+            l.link_id = $2
+        "#,
+        delivery_id,
+        link_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve a tracked link.")?;
+    Ok(link)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn record_click(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+    link_id: i32,
+    delivery_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO link_clicks (newsletter_issue_id, link_id, delivery_id, clicked_at)
+        VALUES ($1, $2, $3, now())
+        "#,
+        newsletter_issue_id,
+        link_id,
+        delivery_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
@@ -1,5 +1,50 @@
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use sqlx::PgPool;
+
+use crate::configuration::IssueDeliverySettings;
+use crate::jobs::get_worker_heartbeats;
+use crate::routing_helpers::e500;
 
 pub async fn health_check() -> HttpResponse {
     HttpResponse::Ok().finish()
 }
+
+/// Workers heard from within this window are listed, so that restarts show up
+const RECENT_WORKERS_WINDOW_HOURS: i64 = 24;
+
+/// Reports whether a background worker beat recently: `503 Service Unavailable` if none did
+/// within `issue_delivery.heartbeat_stale_after_seconds`, even though the API itself is up
+pub async fn worker_health(
+    pool: web::Data<PgPool>,
+    issue_delivery: web::Data<IssueDeliverySettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let now = Utc::now();
+    let workers = get_worker_heartbeats(
+        &pool,
+        now - chrono::Duration::hours(RECENT_WORKERS_WINDOW_HOURS),
+    )
+    .await
+    .map_err(e500)?;
+    let last_beat_at = workers.first().map(|worker| worker.last_beat_at);
+    let seconds_since_last_beat =
+        last_beat_at.map(|last_beat_at| (now - last_beat_at).num_seconds().max(0));
+    let status = match seconds_since_last_beat {
+        None => "missing",
+        Some(seconds) if seconds as u64 > issue_delivery.heartbeat_stale_after_seconds => "stale",
+        Some(_) => "ok",
+    };
+    let body = serde_json::json!({
+        "status": status,
+        "last_beat_at": last_beat_at,
+        "seconds_since_last_beat": seconds_since_last_beat,
+        "stale_after_seconds": issue_delivery.heartbeat_stale_after_seconds,
+        "workers": workers,
+    });
+    let mut response = if status == "ok" {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+    Ok(response.json(body))
+}
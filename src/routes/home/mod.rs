@@ -1,8 +1,42 @@
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpResponse};
+use askama::Template;
+use chrono::Utc;
+use sqlx::PgPool;
 
-pub async fn home() -> HttpResponse {
-    HttpResponse::Ok()
+use crate::bot_protection::BotProtection;
+use crate::captcha::CaptchaClient;
+use crate::newsletters::{get_newsletters, newsletter_options};
+use crate::routing_helpers::e500;
+use crate::templates::{Branding, Layout, SelectOption};
+
+#[derive(Template)]
+#[template(path = "home.html")]
+struct HomePage<'a> {
+    layout: Layout<'a>,
+    form_token: String,
+    /// Only shown once there are several newsletters to pick from
+    newsletter_options: Vec<SelectOption>,
+    captcha: Option<&'a CaptchaClient>,
+}
+
+pub async fn home(
+    bot_protection: web::Data<BotProtection>,
+    captcha: web::Data<Option<CaptchaClient>>,
+    pool: web::Data<PgPool>,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // visitors only pick a newsletter when there is more than one
+    let newsletters = get_newsletters(&pool).await.map_err(e500)?;
+    let body = HomePage {
+        layout: Layout::new(&branding, "Home"),
+        form_token: bot_protection.form_token(Utc::now()),
+        newsletter_options: newsletter_options(&newsletters, ""),
+        captcha: captcha.as_ref().as_ref(),
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(include_str!("home.html"))
+        .body(body))
 }
@@ -0,0 +1,127 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::configuration::HtmlSanitizationSettings;
+use crate::newsletter_content::sanitize_html;
+use crate::routing_helpers::e500;
+use crate::templates::{Branding, Layout};
+
+#[derive(serde::Serialize)]
+pub struct PublishedIssue {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: DateTime<Utc>,
+}
+
+#[derive(Template)]
+#[template(path = "issues_archive.html")]
+struct IssuesArchivePage<'a> {
+    layout: Layout<'a>,
+    issues: Vec<PublishedIssue>,
+}
+
+#[derive(Template)]
+#[template(path = "archived_issue.html")]
+struct ArchivedIssuePage<'a> {
+    layout: Layout<'a>,
+    title: &'a str,
+    /// Sanitized, so rendered as is
+    html_content: String,
+}
+
+/// Public archive listing every published newsletter issue, most recent first
+pub async fn issues_archive(
+    pool: web::Data<PgPool>,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issues = get_published_issues(&pool).await.map_err(e500)?;
+    let body = IssuesArchivePage {
+        layout: Layout::new(&branding, "Newsletter archive"),
+        issues,
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Public page rendering the HTML content of a published issue
+pub async fn view_issue(
+    issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    branding: web::Data<Branding>,
+    html_sanitization: web::Data<HtmlSanitizationSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let issue = match get_published_issue(&pool, issue_id.into_inner())
+        .await
+        .map_err(e500)?
+    {
+        Some(issue) => issue,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let body = ArchivedIssuePage {
+        layout: Layout::new(&branding, &issue.title),
+        title: &issue.title,
+        // sanitized again, for the issues stored before sanitization or under laxer settings
+        html_content: sanitize_html(&issue.html_content, &html_sanitization),
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+#[tracing::instrument(name = "Get archived newsletter issues", skip(pool))]
+pub async fn get_published_issues(pool: &PgPool) -> Result<Vec<PublishedIssue>, anyhow::Error> {
+    let issues = sqlx::query_as!(
+        PublishedIssue,
+        r#"
+        SELECT
+            newsletter_issue_id,
+            title,
+            published_at AS "published_at!"
+        FROM newsletter_issues
+        WHERE status IN ('enqueuing', 'delivering', 'paused')
+        ORDER BY published_at DESC
+        "#
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve archived newsletter issues.")?;
+    Ok(issues)
+}
+
+#[derive(serde::Serialize)]
+pub struct IssueContent {
+    title: String,
+    html_content: String,
+}
+
+#[tracing::instrument(name = "Get archived newsletter issue", skip(pool))]
+pub async fn get_published_issue(
+    pool: &PgPool,
+    issue_id: Uuid,
+) -> Result<Option<IssueContent>, anyhow::Error> {
+    let issue = sqlx::query_as!(
+        IssueContent,
+        r#"
+        SELECT title, html_content
+        FROM newsletter_issues
+        WHERE
+            newsletter_issue_id = $1 AND
+            status IN ('enqueuing', 'delivering', 'paused')
+        "#,
+        issue_id
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to perform a query to retrieve an archived newsletter issue.")?;
+    Ok(issue)
+}
@@ -1,43 +1,39 @@
-use std::fmt::Write;
-
 use actix_web::http::header::ContentType;
-use actix_web::HttpResponse;
-use actix_web_flash_messages::IncomingFlashMessages;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama::Template;
+
+use crate::routes::login::valid_next;
+use crate::routing_helpers::e500;
+use crate::templates::{collect_flash_messages, Branding, Layout};
+
+#[derive(Template)]
+#[template(path = "login.html")]
+struct LoginPage<'a> {
+    layout: Layout<'a>,
+    messages: Vec<FlashMessage>,
+    next: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct QueryParams {
+    /// The page of the admin panel to go back to once logged in
+    next: Option<String>,
+}
 
-pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
-    let mut error_html = String::new();
-    for message in flash_messages.iter() {
-        writeln!(error_html, "<p><i>{}</i></p>", message.content()).unwrap();
+pub async fn login_form(
+    query: web::Query<QueryParams>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = LoginPage {
+        layout: Layout::new(&branding, "Login"),
+        messages: collect_flash_messages(&flash_messages),
+        next: valid_next(query.0.next).unwrap_or_default(),
     }
-    HttpResponse::Ok()
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
         .content_type(ContentType::html())
-        .body(format!(
-            r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta http-equiv="content-type" content="text/html; charset=utf-8">
-    <title>Login</title>
-</head>
-<body>
-    {error_html}
-    <form action="/login" method="post">
-        <label>Username
-            <input
-                type="text"
-                placeholder="Enter Username"
-                name="username"
-            >
-        </label>
-        <label>Password
-            <input
-                type="password"
-                placeholder="Enter Password"
-                name="password"
-            >
-        </label>
-        <button type="submit">Login</button>
-    </form>
-</body>
-</html>"#,
-        ))
+        .body(body))
 }
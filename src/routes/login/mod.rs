@@ -1,5 +1,70 @@
 mod get;
 mod post;
+mod two_factor;
 
 pub use get::login_form;
 pub use post::login;
+pub use two_factor::{two_factor_form, verify_two_factor};
+
+/// Where users land once logged in when they did not ask for a page of the admin panel
+const DEFAULT_LANDING_PAGE: &str = "/admin/dashboard";
+
+/// Whether `next` can be redirected to after logging in. Only pages of the admin panel are
+/// accepted, so that the login form cannot be used to send users to another site.
+fn is_valid_next(next: &str) -> bool {
+    next == "/admin" || next.starts_with("/admin/") || next.starts_with("/admin?")
+}
+
+/// The page requested before logging in, if it can be redirected to
+pub fn valid_next(next: Option<String>) -> Option<String> {
+    next.filter(|next| is_valid_next(next))
+}
+
+/// The page to redirect to once logged in
+pub fn landing_page(next: Option<&str>) -> &str {
+    next.filter(|next| is_valid_next(next))
+        .unwrap_or(DEFAULT_LANDING_PAGE)
+}
+
+/// The login page, remembering the page to go back to once logged in
+pub fn login_page(next: Option<&str>) -> String {
+    match next.filter(|next| is_valid_next(next)) {
+        Some(next) => format!(
+            "/login?{}",
+            serde_urlencoded::to_string([("next", next)]).expect("A string pair can be encoded")
+        ),
+        None => "/login".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{landing_page, login_page};
+
+    #[test]
+    fn pages_of_the_admin_panel_are_redirected_to() {
+        assert_eq!(
+            landing_page(Some("/admin/subscribers?page=2")),
+            "/admin/subscribers?page=2"
+        );
+        assert_eq!(
+            login_page(Some("/admin/subscribers?page=2")),
+            "/login?next=%2Fadmin%2Fsubscribers%3Fpage%3D2"
+        );
+    }
+
+    #[test]
+    fn other_pages_are_not_redirected_to() {
+        for next in [
+            "https://evil.example.com/admin/",
+            "//evil.example.com/admin/",
+            "/administrator",
+            "/login",
+            "",
+        ] {
+            assert_eq!(landing_page(Some(next)), "/admin/dashboard");
+            assert_eq!(login_page(Some(next)), "/login");
+        }
+        assert_eq!(landing_page(None), "/admin/dashboard");
+    }
+}
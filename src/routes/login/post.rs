@@ -4,62 +4,147 @@ use actix_web::body::BoxBody;
 use actix_web::error::InternalError;
 use actix_web::http::header::LOCATION;
 use actix_web::http::StatusCode;
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
 use actix_web_flash_messages::FlashMessage;
 use secrecy::Secret;
 use sqlx::PgPool;
 
-use crate::authentication::{validate_credentials, AuthError, Credentials};
+use crate::authentication::{
+    clear_failed_logins, format_cooldown, get_password_version, get_totp_secret,
+    issue_persistent_login, login_lockout, record_failed_login, validate_credentials, AuthError,
+    Credentials, LoginLockoutPolicy, PasswordHashingPolicy,
+};
+use crate::configuration::SessionSettings;
 use crate::error_handling::error_chain_fmt;
+use crate::reverse_proxy::ClientInfo;
+use crate::routes::login::{landing_page, login_page, valid_next};
 use crate::session_state::TypedSession;
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     username: String,
     password: Secret<String>,
+    #[serde(default)]
+    remember_me: bool,
+    /// The page of the admin panel to go back to, as passed on by the login form
+    next: Option<String>,
 }
 
+#[allow(clippy::too_many_arguments)]
 #[tracing::instrument(
-    skip(form, pool, session)
+    skip(
+        form,
+        pool,
+        session,
+        request,
+        lockout_policy,
+        hashing_policy,
+        session_settings
+    )
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 pub async fn login(
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     session: TypedSession,
+    request: HttpRequest,
+    lockout_policy: web::Data<LoginLockoutPolicy>,
+    hashing_policy: web::Data<PasswordHashingPolicy>,
+    session_settings: web::Data<SessionSettings>,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
+    let remember_me = form.remember_me;
+    let next = valid_next(form.0.next);
+    let login_page = login_page(next.as_deref());
     let credentials = Credentials {
         username: form.0.username,
         password: form.0.password,
     };
     tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    let username = credentials.username.clone();
+    let ip_address = ClientInfo::of(&request)
+        .map(|client| client.ip.to_string())
+        .unwrap_or_else(|| "unknown".into());
 
-    match validate_credentials(credentials, &pool).await {
+    // a locked out client is not told whether its credentials are valid
+    let lockout = login_lockout(&pool, &lockout_policy, &username, &ip_address)
+        .await
+        .map_err(|e| login_redirect(&login_page, LoginError::UnexpectedError(e)))?;
+    if let Some(cooldown) = lockout {
+        return Err(login_redirect(&login_page, LoginError::LockedOut(cooldown)));
+    }
+
+    match validate_credentials(credentials, &hashing_policy, &pool).await {
         Ok(user_id) => {
             tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+            let has_second_factor = get_totp_secret(&pool, user_id)
+                .await
+                .map_err(|e| login_redirect(&login_page, LoginError::UnexpectedError(e)))?
+                .is_some();
             session.renew();
+            if has_second_factor {
+                // failures are only forgotten once the second factor is entered too, so that
+                // the lockout also covers guessing codes
+                session
+                    .insert_pending_second_factor(user_id, remember_me, next.as_deref())
+                    .map_err(|e| {
+                        login_redirect(&login_page, LoginError::UnexpectedError(e.into()))
+                    })?;
+                return Ok(HttpResponse::SeeOther()
+                    .insert_header((LOCATION, "/login/two-factor"))
+                    .finish());
+            }
+            clear_failed_logins(&pool, &username, &ip_address)
+                .await
+                .map_err(|e| login_redirect(&login_page, LoginError::UnexpectedError(e.into())))?;
+            let password_version = get_password_version(&pool, user_id)
+                .await
+                .map_err(|e| login_redirect(&login_page, LoginError::UnexpectedError(e)))?;
             session
-                .insert_user_id(user_id)
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
-            Ok(HttpResponse::SeeOther()
-                .insert_header((LOCATION, "/admin/dashboard"))
-                .finish())
+                .insert_user_id(user_id, password_version)
+                .map_err(|e| login_redirect(&login_page, LoginError::UnexpectedError(e.into())))?;
+            let mut response = HttpResponse::SeeOther()
+                .insert_header((LOCATION, landing_page(next.as_deref())))
+                .finish();
+            if remember_me {
+                let cookie = issue_persistent_login(
+                    &pool,
+                    user_id,
+                    password_version,
+                    session_settings.remember_me_ttl(),
+                )
+                .await
+                .map_err(|e| login_redirect(&login_page, LoginError::UnexpectedError(e)))?;
+                response.add_cookie(&cookie).map_err(|e| {
+                    login_redirect(&login_page, LoginError::UnexpectedError(e.into()))
+                })?;
+            }
+            Ok(response)
         }
         Err(e) => {
             let e = match e {
-                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+                AuthError::InvalidCredentials(_) => {
+                    record_failed_login(&pool, &lockout_policy, &username, &ip_address)
+                        .await
+                        .map_err(|e| login_redirect(&login_page, LoginError::UnexpectedError(e)))?;
+                    LoginError::AuthError(e.into())
+                }
                 AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
             };
-            Err(login_redirect(e))
+            Err(login_redirect(&login_page, e))
         }
     }
 }
 
 /// Redirect to the login page with an error message
-fn login_redirect(e: LoginError) -> InternalError<LoginError> {
-    FlashMessage::error(e.to_string()).send();
+fn login_redirect(login_page: &str, e: LoginError) -> InternalError<LoginError> {
+    match e {
+        // the credentials may well be right: the user only has to wait
+        LoginError::LockedOut(_) => FlashMessage::warning(e.to_string()),
+        _ => FlashMessage::error(e.to_string()),
+    }
+    .send();
     let response = HttpResponse::SeeOther()
-        .insert_header((LOCATION, "/login"))
+        .insert_header((LOCATION, login_page))
         .finish();
     // InternalError implements ResponseError, so it can be returned in a request handler;
     // you pass it an error and a response; it handles propagating the root error upstream
@@ -71,6 +156,11 @@ fn login_redirect(e: LoginError) -> InternalError<LoginError> {
 pub enum LoginError {
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
+    #[error(
+        "Too many failed login attempts. Try again in {}.",
+        format_cooldown(*.0)
+    )]
+    LockedOut(std::time::Duration),
     #[error("Something went wrong")]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -0,0 +1,131 @@
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama::Template;
+use sqlx::PgPool;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::authentication::{
+    clear_failed_logins, format_cooldown, get_password_version, get_totp_secret,
+    issue_persistent_login, login_lockout, record_failed_login, verify_second_factor,
+    LoginLockoutPolicy,
+};
+use crate::configuration::SessionSettings;
+use crate::reverse_proxy::ClientInfo;
+use crate::routes::{get_username, landing_page};
+use crate::routing_helpers::{e500, see_other};
+use crate::session_state::TypedSession;
+use crate::templates::{collect_flash_messages, Branding, Layout};
+
+#[derive(Template)]
+#[template(path = "two_factor.html")]
+struct TwoFactorPage<'a> {
+    layout: Layout<'a>,
+    messages: Vec<FlashMessage>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TwoFactorFormData {
+    code: String,
+}
+
+/// Asks users who entered their password for their second factor
+pub async fn two_factor_form(
+    session: TypedSession,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_pending_second_factor().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let body = TwoFactorPage {
+        layout: Layout::new(&branding, "Two-factor authentication"),
+        messages: collect_flash_messages(&flash_messages),
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Completes the login of users with two-factor authentication. Wrong codes count as failed
+/// logins, so guessing them leads to a lockout as well.
+#[tracing::instrument(
+    skip(form, pool, session, request, lockout_policy, session_settings),
+    fields(user_id=tracing::field::Empty)
+)]
+pub async fn verify_two_factor(
+    form: web::Form<TwoFactorFormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    request: HttpRequest,
+    lockout_policy: web::Data<LoginLockoutPolicy>,
+    session_settings: web::Data<SessionSettings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(user_id) = session.get_pending_second_factor().map_err(e500)? else {
+        return Ok(see_other("/login"));
+    };
+    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+    let username = get_username(user_id, &pool).await.map_err(e500)?;
+    let ip_address = ClientInfo::of(&request)
+        .map(|client| client.ip.to_string())
+        .unwrap_or_else(|| "unknown".into());
+
+    if let Some(cooldown) = login_lockout(&pool, &lockout_policy, &username, &ip_address)
+        .await
+        .map_err(e500)?
+    {
+        FlashMessage::warning(format!(
+            "Too many failed login attempts. Try again in {}.",
+            format_cooldown(cooldown)
+        ))
+        .send();
+        return Ok(see_other("/login/two-factor"));
+    }
+
+    let Some(secret) = get_totp_secret(&pool, user_id).await.map_err(e500)? else {
+        // two-factor authentication was disabled in the meantime: start over
+        session.remove_pending_second_factor();
+        return Ok(see_other("/login"));
+    };
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("The system clock is set before the Unix epoch")
+        .as_secs();
+    if !verify_second_factor(&pool, user_id, &secret, &form.code, now)
+        .await
+        .map_err(e500)?
+    {
+        record_failed_login(&pool, &lockout_policy, &username, &ip_address)
+            .await
+            .map_err(e500)?;
+        FlashMessage::error("Invalid authentication code.").send();
+        return Ok(see_other("/login/two-factor"));
+    }
+
+    clear_failed_logins(&pool, &username, &ip_address)
+        .await
+        .map_err(e500)?;
+    let password_version = get_password_version(&pool, user_id).await.map_err(e500)?;
+    let remember_me = session.get_pending_remember_me().map_err(e500)?;
+    let next = session.get_pending_next().map_err(e500)?;
+    session.renew();
+    session.remove_pending_second_factor();
+    session
+        .insert_user_id(user_id, password_version)
+        .map_err(e500)?;
+    let mut response = see_other(landing_page(next.as_deref()));
+    if remember_me {
+        let cookie = issue_persistent_login(
+            &pool,
+            user_id,
+            password_version,
+            session_settings.remember_me_ttl(),
+        )
+        .await
+        .map_err(e500)?;
+        response.add_cookie(&cookie).map_err(e500)?;
+    }
+    Ok(response)
+}
@@ -0,0 +1,62 @@
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use std::fmt::Write;
+
+use crate::delivery_backlog::get_delivery_backlog;
+use crate::email_client::EmailClient;
+use crate::idempotency::get_idempotency_cleanup_stats;
+use crate::request_metrics::RequestMetrics;
+use crate::routing_helpers::e500;
+use crate::startup::MaxDatabaseConnections;
+
+/// Reports the state of the application in the Prometheus text format
+pub async fn metrics(
+    pool: web::Data<PgPool>,
+    max_connections: web::Data<MaxDatabaseConnections>,
+    request_metrics: web::Data<RequestMetrics>,
+    email_client: web::Data<EmailClient>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut body = String::new();
+    write_pool_metrics(&mut body, &pool, max_connections.0);
+    request_metrics.write_prometheus(&mut body);
+    // a worker in the same process shares this client; a standalone one serves its own metrics
+    email_client.write_prometheus(&mut body);
+    let backlog = get_delivery_backlog(&pool).await.map_err(e500)?;
+    backlog.write_prometheus(&mut body, chrono::Utc::now());
+    // the purges run in the worker, and are read back from the database like the backlog
+    let cleanup_stats = get_idempotency_cleanup_stats(&pool).await.map_err(e500)?;
+    cleanup_stats.write_prometheus(&mut body);
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body))
+}
+
+/// Reports the counters of the email client of a worker running in a process of its own
+pub async fn worker_metrics(email_client: web::Data<EmailClient>) -> HttpResponse {
+    let mut body = String::new();
+    email_client.write_prometheus(&mut body);
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(body)
+}
+
+/// How many connections of the pool are open, in use or idle, out of how many it may open
+fn write_pool_metrics(body: &mut String, pool: &PgPool, max_connections: u32) {
+    let size = pool.size();
+    // the pool is not locked while it is inspected, so both figures may be slightly off
+    let idle = (pool.num_idle() as u32).min(size);
+    writeln!(
+        body,
+        "# HELP db_pool_connections Open connections of the database pool, by state.\n\
+        # TYPE db_pool_connections gauge\n\
+        db_pool_connections{{state=\"in_use\"}} {}\n\
+        db_pool_connections{{state=\"idle\"}} {}\n\
+        # HELP db_pool_max_connections Maximum number of connections of the database pool.\n\
+        # TYPE db_pool_max_connections gauge\n\
+        db_pool_max_connections {}",
+        size - idle,
+        idle,
+        max_connections
+    )
+    .unwrap();
+}
@@ -1,14 +1,38 @@
 mod admin;
+mod api;
+mod click_tracking;
 mod health_check;
 mod home;
+mod issues;
 mod login;
+mod metrics;
+mod open_tracking;
+mod postmark_webhook;
 mod subscriptions;
+mod subscriptions_change_email;
 mod subscriptions_confirm;
+mod subscriptions_erase;
+mod subscriptions_resend;
+mod subscriptions_time_zone;
+mod subscriptions_unsubscribe;
+mod users_accept_invitation;
 
 pub use admin::*;
+pub use api::*;
+pub use click_tracking::*;
 pub use health_check::*;
 pub use home::*;
+pub use issues::*;
 pub use login::*;
+pub use metrics::*;
+pub use open_tracking::*;
+pub use postmark_webhook::*;
 pub use subscriptions::FormData as SubscriptionFormData;
 pub use subscriptions::*;
+pub use subscriptions_change_email::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_erase::*;
+pub use subscriptions_resend::*;
+pub use subscriptions_time_zone::*;
+pub use subscriptions_unsubscribe::*;
+pub use users_accept_invitation::*;
@@ -0,0 +1,46 @@
+use actix_web::http::header::{CacheControl, CacheDirective};
+use actix_web::{web, HttpResponse};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+/// A transparent 1x1 GIF
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+/// `GET /t/open/{delivery_id}`: records that a delivery was opened, then serves the tracking pixel
+/// embedded in its HTML content
+#[tracing::instrument(name = "Track an email open", skip(pool))]
+pub async fn track_email_open(
+    delivery_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> HttpResponse {
+    // the image is served regardless, a broken image would be visible in the email
+    if let Err(e) = record_open(&pool, delivery_id.into_inner()).await {
+        tracing::error!(error.cause_chain = ?e, "Failed to record an email open.");
+    }
+    HttpResponse::Ok()
+        .content_type("image/gif")
+        // every load should reach us to be counted
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(PIXEL)
+}
+
+/// Records an open of a delivery; unknown deliveries are ignored
+#[tracing::instrument(skip(pool))]
+async fn record_open(pool: &PgPool, delivery_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_opens (newsletter_issue_id, delivery_id, opened_at)
+        SELECT newsletter_issue_id, delivery_id, now()
+        FROM issue_deliveries
+        WHERE delivery_id = $1
+        "#,
+        delivery_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
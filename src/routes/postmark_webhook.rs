@@ -0,0 +1,128 @@
+use actix_web::http::header::WWW_AUTHENTICATE;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+
+use crate::authentication::{basic_credentials, constant_time_eq};
+use crate::configuration::PostmarkWebhookSettings;
+use crate::routing_helpers::e500;
+
+/// An event posted by Postmark. The other record types, e.g. bounces, are acknowledged and
+/// ignored, so that Postmark does not retry them.
+#[derive(serde::Deserialize, Debug)]
+#[serde(tag = "RecordType")]
+enum PostmarkEvent {
+    /// The recipient's mail server accepted the email
+    Delivery {
+        #[serde(rename = "MessageID")]
+        message_id: String,
+        #[serde(rename = "DeliveredAt")]
+        delivered_at: DateTime<Utc>,
+    },
+    /// Postmark's own tracking pixel was loaded
+    Open {
+        #[serde(rename = "MessageID")]
+        message_id: String,
+        #[serde(rename = "ReceivedAt")]
+        received_at: DateTime<Utc>,
+    },
+    #[serde(other)]
+    Other,
+}
+
+/// `POST /webhooks/postmark`: records the delivery and open events of the issues sent through
+/// Postmark, matched to their deliveries by the id Postmark gave the message. Events of other
+/// emails, e.g. confirmations, are ignored.
+#[tracing::instrument(name = "Receive a Postmark webhook", skip_all)]
+pub async fn postmark_webhook(
+    request: HttpRequest,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    settings: web::Data<Option<PostmarkWebhookSettings>>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(settings) = settings.as_ref() else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    if !is_authorized(&request, settings) {
+        return Ok(HttpResponse::Unauthorized()
+            .insert_header((WWW_AUTHENTICATE, r#"Basic realm="postmark""#))
+            .finish());
+    }
+    let event: PostmarkEvent = match serde_json::from_slice(&body) {
+        Ok(event) => event,
+        Err(e) => {
+            tracing::warn!(error.message = %e, "Rejecting an unreadable Postmark event.");
+            return Ok(HttpResponse::BadRequest().finish());
+        }
+    };
+    match event {
+        PostmarkEvent::Delivery {
+            message_id,
+            delivered_at,
+        } => confirm_delivery(&pool, &message_id, delivered_at)
+            .await
+            .map_err(e500)?,
+        PostmarkEvent::Open {
+            message_id,
+            received_at,
+        } => record_open(&pool, &message_id, received_at)
+            .await
+            .map_err(e500)?,
+        PostmarkEvent::Other => {}
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+fn is_authorized(request: &HttpRequest, settings: &PostmarkWebhookSettings) -> bool {
+    let Ok(credentials) = basic_credentials(request.headers()) else {
+        return false;
+    };
+    credentials.username == settings.username
+        && constant_time_eq(
+            credentials.password.expose_secret().as_bytes(),
+            settings.password.expose_secret().as_bytes(),
+        )
+}
+
+/// Postmark may send an event more than once: the first confirmation is kept
+#[tracing::instrument(skip(pool))]
+async fn confirm_delivery(
+    pool: &PgPool,
+    message_id: &str,
+    delivered_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_deliveries
+        SET confirmed_at = $2
+        WHERE provider_message_id = $1 AND confirmed_at IS NULL
+        "#,
+        message_id,
+        delivered_at
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(pool))]
+async fn record_open(
+    pool: &PgPool,
+    message_id: &str,
+    opened_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_opens (newsletter_issue_id, delivery_id, opened_at)
+        SELECT newsletter_issue_id, delivery_id, $2
+        FROM issue_deliveries
+        WHERE provider_message_id = $1 AND delivery_id IS NOT NULL
+        "#,
+        message_id,
+        opened_at
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
@@ -1,8 +1,10 @@
 use std::fmt::Formatter;
 
+use actix_web::http::header::{self, ContentType, Header};
 use actix_web::http::StatusCode;
-use actix_web::{web, HttpResponse, ResponseError};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
+use askama::Template;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use sqlx::types::chrono::Utc;
@@ -10,33 +12,245 @@ use sqlx::types::uuid;
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
-use crate::domain::NewSubscriber;
-use crate::email_client::EmailClient;
-use crate::error_handling;
-use crate::startup::ApplicationBaseUrl;
+use crate::bot_protection::BotProtection;
+use crate::captcha::CaptchaClient;
+use crate::domain::{
+    NewSubscriber, SubscriberEmail, SubscriberNamePolicy, SubscriberTag, SubscriptionStatus,
+};
+use crate::email_client::{EmailClient, EmailOptions, SenderIdentity};
+use crate::email_validation::EmailValidator;
+use crate::error_handling::{self, NegotiatedError, UNEXPECTED_ERROR_MESSAGE};
+use crate::events::{record_subscriber_event, EventType};
+use crate::newsletters::{find_newsletter, Newsletter};
+use crate::reverse_proxy::ClientInfo;
+use crate::routing_helpers::{e500, see_other};
+use crate::subscription_consents::{is_web_url, record_consent, Consent};
+use crate::subscription_events::{parse_previous_status, record_status_change};
+use crate::subscription_tokens::SubscriptionTokens;
+use crate::telemetry::{pii_email, pii_name};
+use crate::templates::{Branding, ConfirmationEmailTemplate, Layout};
+use crate::url_builder::UrlBuilder;
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     pub email: String,
     pub name: String,
+    /// Comma-separated list of tags the subscriber opts into
+    #[serde(default)]
+    pub tags: String,
+    /// Slug of the newsletter to subscribe to; the default newsletter if empty
+    #[serde(default)]
+    pub newsletter: String,
+    /// Hidden from humans on the subscribe form: only bots fill it in
+    #[serde(default, rename = "website")]
+    pub honeypot: String,
+    /// Signed timestamp of when the subscribe form was rendered
+    #[serde(default)]
+    pub form_token: String,
+    /// Response of the CAPTCHA widget, under the field name used by either provider
+    #[serde(
+        default,
+        rename = "h-captcha-response",
+        alias = "cf-turnstile-response"
+    )]
+    pub captcha_response: String,
+    /// Which wording of the consent text the form shows
+    #[serde(default)]
+    pub consent_text_version: String,
+    /// The country whose consent rules the form applies
+    #[serde(default)]
+    pub country: String,
+    /// The page the form is on; the Referer of the request if empty
+    #[serde(default)]
+    pub source_url: String,
 }
 
+#[allow(clippy::too_many_arguments)]
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, connection_pool, email_client, application_base_url),
+    skip(
+        request,
+        form,
+        connection_pool,
+        email_client,
+        confirmation_email,
+        urls,
+        subscription_tokens,
+        bot_protection,
+        captcha,
+        email_validator,
+        name_policy
+    ),
     fields(
-        subscriber_email = %form.email,
-        subscriber_name = %form.name
+        subscriber_email = %pii_email(&form.email),
+        subscriber_name = %pii_name(&form.name)
     )
 )]
 pub async fn subscribe(
+    request: HttpRequest,
     form: web::Form<FormData>,
     connection_pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
-    application_base_url: web::Data<ApplicationBaseUrl>,
+    confirmation_email: web::Data<ConfirmationEmailTemplate>,
+    urls: web::Data<UrlBuilder>,
+    subscription_tokens: web::Data<SubscriptionTokens>,
+    bot_protection: web::Data<BotProtection>,
+    captcha: web::Data<Option<CaptchaClient>>,
+    email_validator: web::Data<EmailValidator>,
+    name_policy: web::Data<SubscriberNamePolicy>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    // bots get the same response as humans, so they have no reason to adapt
+    if bot_protection.is_bot(&form.honeypot, &form.form_token, Utc::now()) {
+        tracing::info!("Dropping a subscription submitted by a bot.");
+        return Ok(subscription_pending_response(&request));
+    }
+    check_captcha(captcha.as_ref().as_ref(), &form.captcha_response).await?;
+    let newsletter = find_subscribed_newsletter(&connection_pool, &form.newsletter).await?;
+    let consent = consent_of(
+        &request,
+        &form.consent_text_version,
+        &form.country,
+        &form.source_url,
+    )
+    .map_err(SubscribeError::ValidationError)?;
+    let new_subscriber =
+        NewSubscriber::parse(form.0, &name_policy).map_err(SubscribeError::ValidationError)?;
+    email_validator
+        .check(&new_subscriber.email)
+        .await
+        .map_err(SubscribeError::ValidationError)?;
+    register_subscriber(
+        &connection_pool,
+        &email_client,
+        &confirmation_email,
+        &urls,
+        &subscription_tokens,
+        &newsletter,
+        &new_subscriber,
+        &consent,
+    )
+    .await?;
+    Ok(subscription_pending_response(&request))
+}
+
+/// The consent given by the client of `request`. Forms embedded on other websites do not have to
+/// tell which page they are on: the Referer is kept instead, when it is a valid URL.
+pub fn consent_of(
+    request: &HttpRequest,
+    consent_text_version: &str,
+    country: &str,
+    source_url: &str,
+) -> Result<Consent, String> {
+    let referer = request
+        .headers()
+        .get(header::REFERER)
+        .and_then(|referer| referer.to_str().ok())
+        .filter(|referer| is_web_url(referer));
+    let source_url = match referer {
+        Some(referer) if source_url.trim().is_empty() => referer,
+        _ => source_url,
+    };
+    let ip_address = ClientInfo::of(request).map(|client| client.ip);
+    Consent::parse(ip_address, consent_text_version, country, source_url)
+}
+
+/// Clients asking for JSON get the status of the new subscription; browsers, e.g. submitting a
+/// form embedded on another website, are sent to a page telling them to check their inbox
+fn subscription_pending_response(request: &HttpRequest) -> HttpResponse {
+    let prefers_json = header::Accept::parse(request).map_or(false, |accept| {
+        accept.preference().essence_str() == "application/json"
+    });
+    if prefers_json {
+        HttpResponse::Ok().json(serde_json::json!({ "status": "pending_confirmation" }))
+    } else {
+        see_other("/subscriptions/pending")
+    }
+}
+
+#[derive(Template)]
+#[template(path = "subscription_pending.html")]
+struct SubscriptionPendingPage<'a> {
+    layout: Layout<'a>,
+}
+
+/// The page new subscribers land on after submitting the subscribe form
+pub async fn subscription_pending(
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let body = SubscriptionPendingPage {
+        layout: Layout::new(&branding, "Check your inbox"),
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
 
+/// Rejects the submission if a CAPTCHA is configured and `captcha_response` does not pass it
+pub async fn check_captcha(
+    captcha: Option<&CaptchaClient>,
+    captcha_response: &str,
+) -> Result<(), SubscribeError> {
+    let Some(captcha) = captcha else {
+        return Ok(());
+    };
+    let is_human = captcha
+        .verify(captcha_response)
+        .await
+        .context("Failed to verify the captcha response.")?;
+    if is_human {
+        Ok(())
+    } else {
+        Err(SubscribeError::ValidationError(
+            "The captcha could not be verified.".into(),
+        ))
+    }
+}
+
+/// Resolves the slug of the newsletter a subscription is for; an empty slug means the default one
+pub async fn find_subscribed_newsletter(
+    pool: &PgPool,
+    slug: &str,
+) -> Result<Newsletter, SubscribeError> {
+    let newsletter = find_newsletter(pool, slug)
+        .await
+        .context("Failed to look up the newsletter.")?
+        .ok_or_else(|| {
+            SubscribeError::ValidationError(format!("There is no newsletter named {}.", slug))
+        })?;
+    Ok(newsletter)
+}
+
+/// Stores a new subscriber of a newsletter and emails them a confirmation link. Subscribing again must not fail:
+/// depending on where the subscriber is in the lifecycle, we either do nothing or send them
+/// through the confirmation flow once more. Every signup that is not ignored records the consent
+/// it was given with.
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "Registering a subscriber",
+    skip(
+        connection_pool,
+        email_client,
+        confirmation_email,
+        urls,
+        subscription_tokens,
+        newsletter,
+        new_subscriber,
+        consent
+    )
+)]
+pub async fn register_subscriber(
+    connection_pool: &PgPool,
+    email_client: &EmailClient,
+    confirmation_email: &ConfirmationEmailTemplate,
+    urls: &UrlBuilder,
+    subscription_tokens: &SubscriptionTokens,
+    newsletter: &Newsletter,
+    new_subscriber: &NewSubscriber,
+    consent: &Consent,
+) -> Result<(), anyhow::Error> {
+    let newsletter_id = newsletter.newsletter_id;
     // creating an sqlx Transaction struct by calling begin on the pool
     // this struct implements the Executor trait, so it can be used instead of a reference to the connection pool
     let mut transaction = connection_pool
@@ -44,30 +258,65 @@ pub async fn subscribe(
         .await
         .context("Failed to acquire a Postgres connection from the pool.")?;
 
-    let subscriber_id = insert_subscriber(&new_subscriber, &mut transaction)
+    let existing = get_existing_subscriber(&mut transaction, newsletter_id, &new_subscriber.email)
+        .await
+        .context("Failed to look up an existing subscriber.")?;
+    // pending subscribers signing up again only get a new confirmation email
+    let (subscriber_id, signed_up) = match existing {
+        None => (
+            insert_subscriber(new_subscriber, newsletter_id, &mut transaction)
+                .await
+                .context("Failed to insert new subscriber in the database.")?,
+            true,
+        ),
+        Some((_, SubscriptionStatus::Confirmed)) => return Ok(()),
+        Some((subscriber_id, SubscriptionStatus::PendingConfirmation)) => (subscriber_id, false),
+        Some((subscriber_id, SubscriptionStatus::Unsubscribed)) => {
+            update_subscription_status(
+                &mut transaction,
+                subscriber_id,
+                SubscriptionStatus::PendingConfirmation,
+            )
+            .await
+            .context("Failed to resubscribe a subscriber.")?;
+            (subscriber_id, true)
+        }
+    };
+    insert_subscriber_tags(&mut transaction, subscriber_id, &new_subscriber.tags)
+        .await
+        .context("Failed to store the tags of a new subscriber.")?;
+    record_consent(&mut transaction, subscriber_id, consent)
         .await
-        .context("Failed to insert new subscriber in the database.")?;
+        .context("Failed to record the consent of a new subscriber.")?;
 
-    let token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &token)
+    if signed_up {
+        record_subscriber_event(
+            &mut transaction,
+            EventType::SubscriberSignedUp,
+            subscriber_id,
+        )
         .await
-        .context("Failed to store the confirmation token for a new subscriber.")?;
+        .context("Failed to record the signup in the event feed.")?;
+    }
 
     transaction
         .commit()
         .await
         .context("Failed to commit SQL transaction to store a new subscriber.")?;
 
+    let token = subscription_tokens.confirmation_token(subscriber_id, Utc::now());
     send_confirmation_email(
-        &email_client,
-        new_subscriber,
-        &application_base_url.0,
+        email_client,
+        email_client.sender_of(&newsletter.slug),
+        confirmation_email,
+        &new_subscriber.email,
+        new_subscriber.name.as_ref(),
+        urls,
         &token,
     )
     .await
     .context("Failed to send a confirmation email.")?;
-
-    Ok(HttpResponse::Ok().finish())
+    Ok(())
 }
 
 /// An error type that owns HTTP-related logic
@@ -92,6 +341,19 @@ impl ResponseError for SubscribeError {
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        self.negotiated(self.browser_response())
+    }
+}
+
+impl NegotiatedError for SubscribeError {
+    fn client_message(&self) -> String {
+        match self {
+            SubscribeError::ValidationError(message) => message.clone(),
+            SubscribeError::UnexpectedError(_) => UNEXPECTED_ERROR_MESSAGE.to_string(),
+        }
+    }
 }
 
 #[tracing::instrument(
@@ -100,108 +362,153 @@ impl ResponseError for SubscribeError {
 )]
 pub async fn insert_subscriber(
     new_subscriber: &NewSubscriber,
+    newsletter_id: Uuid,
     connection: &mut Transaction<'_, Postgres>,
 ) -> Result<Uuid, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
     sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, newsletter_id)
+        VALUES ($1, $2, $3, $4, 'pending_confirmation', $5)
         "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
-        Utc::now()
+        Utc::now(),
+        newsletter_id
+    )
+    .execute(&mut *connection)
+    .await?;
+    record_status_change(
+        connection,
+        subscriber_id,
+        None,
+        SubscriptionStatus::PendingConfirmation,
     )
-    .execute(connection)
     .await?;
     Ok(subscriber_id)
 }
 
-#[tracing::instrument(
-    name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber)
-)]
-pub async fn send_confirmation_email(
-    email_client: &EmailClient,
-    new_subscriber: NewSubscriber,
-    base_url: &str,
-    subscription_token: &str,
-) -> Result<(), reqwest::Error> {
-    let confirmation_link = format!(
-        "{}/subscriptions/confirm?subscription_token={}",
-        base_url, subscription_token
-    );
-    email_client
-        .send_email(
-            &new_subscriber.email,
-            "Welcome!",
-            &format!(
-                "Welcome to our newsletter!<br />\
-                        Click <a href=\"{}\">here</a> to confirm your subscription.",
-                confirmation_link
-            ),
-            &format!(
-                "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-                confirmation_link
-            ),
-        )
-        .await
+/// Returns the id and status of the subscriber of the newsletter with the given email, if there
+/// is one. Deleted subscribers are reported as unsubscribed, so that signing up again brings them
+/// back. The row is locked until the end of the transaction.
+#[tracing::instrument(name = "Looking up an existing subscriber", skip_all)]
+pub async fn get_existing_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_id: Uuid,
+    email: &SubscriberEmail,
+) -> Result<Option<(Uuid, SubscriptionStatus)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT
+            id,
+            CASE WHEN deleted_at IS NULL THEN status ELSE 'unsubscribed' END AS "status!"
+        FROM subscriptions
+        WHERE newsletter_id = $1 AND email = $2
+        FOR UPDATE
+        "#,
+        newsletter_id,
+        email.as_ref()
+    )
+    .fetch_optional(transaction)
+    .await?;
+    match row {
+        Some(row) => {
+            let status = SubscriptionStatus::try_from(row.status).map_err(anyhow::Error::msg)?;
+            Ok(Some((row.id, status)))
+        }
+        None => Ok(None),
+    }
 }
 
-/// Stores a subscriber's subscription token in the database
-#[tracing::instrument(
-    name = "Store subscription token in the database",
-    skip(subscription_token, connection)
-)]
-pub async fn store_token(
-    connection: &mut Transaction<'_, Postgres>,
+#[tracing::instrument(name = "Updating the status of a subscriber", skip(transaction))]
+pub async fn update_subscription_status(
+    transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
-    subscription_token: &str,
-) -> Result<(), StoreTokenError> {
-    sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-        VALUES ($1, $2)"#,
-        subscription_token,
+    status: SubscriptionStatus,
+) -> Result<(), sqlx::Error> {
+    let previous = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET
+            status = $2,
+            unsubscribed_at = CASE WHEN $2 = 'unsubscribed' THEN now() END,
+            deleted_at = NULL
+        FROM (SELECT status FROM subscriptions WHERE id = $1 FOR UPDATE) AS previous
+        WHERE id = $1
+        RETURNING previous.status AS previous_status
+        "#,
         subscriber_id,
+        status.as_str()
     )
-    .execute(connection)
-    .await
-    .map_err(StoreTokenError)?;
-    Ok(())
-}
-
-pub struct StoreTokenError(sqlx::Error);
-
-impl std::fmt::Display for StoreTokenError {
-    // Must implement Display and Debug in order to implement ResponseError (below)
-    // which in turn is needed to implement From<T> for actix_web::Error
-    // In other words, if we implement ResponseError on our error types, we can let actix build a
-    // response out of our custom error types in order to provide information to the end user when we
-    // encounter particular errors
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "A database error was encountered while trying to store a subscription token."
-        )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    if let Some(previous) = previous {
+        let previous_status = parse_previous_status(previous.previous_status)?;
+        // restoring a deleted subscriber can leave their status as it was
+        if previous_status != status {
+            record_status_change(transaction, subscriber_id, Some(previous_status), status).await?;
+        }
     }
+    Ok(())
 }
 
-impl std::fmt::Debug for StoreTokenError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        error_handling::error_chain_fmt(self, f)
-    }
+#[tracing::instrument(name = "Saving new subscriber tags in the database", skip_all)]
+pub async fn insert_subscriber_tags(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    tags: &[SubscriberTag],
+) -> Result<(), sqlx::Error> {
+    let tags: Vec<String> = tags.iter().map(|tag| tag.as_ref().to_owned()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_tags (subscriber_id, tag)
+        SELECT $1, tag FROM UNNEST($2::TEXT[]) AS tag
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_id,
+        &tags
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
 }
 
-impl std::error::Error for StoreTokenError {
-    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
-        // compiler can implicitly cast `&sqlx::Error` into `&dyn Error`
-        Some(&self.0)
-    }
+#[tracing::instrument(
+    name = "Send a confirmation email to a subscriber",
+    skip(email_client, sender, template, email, name)
+)]
+pub async fn send_confirmation_email(
+    email_client: &EmailClient,
+    sender: &SenderIdentity,
+    template: &ConfirmationEmailTemplate,
+    email: &SubscriberEmail,
+    name: &str,
+    urls: &UrlBuilder,
+    subscription_token: &str,
+) -> Result<(), anyhow::Error> {
+    let confirmation_link = urls.confirmation_url(subscription_token);
+    let rendered = template
+        .render(name, &confirmation_link)
+        .context("Failed to render the confirmation email.")?;
+    email_client
+        .send_email_with(
+            sender,
+            email,
+            &rendered.subject,
+            &rendered.html,
+            &rendered.text,
+            &EmailOptions {
+                attachments: template.attachment().into_iter().cloned().collect(),
+                ..Default::default()
+            },
+        )
+        .await?;
+    Ok(())
 }
 
 /// Generate a random 25-character subscription token
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -0,0 +1,464 @@
+use std::fmt::Formatter;
+
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClient, SendEmailError};
+use crate::email_validation::EmailValidator;
+use crate::error_handling;
+use crate::routes::{generate_subscription_token, get_subscriber_id_from_token};
+use crate::routing_helpers::html_escape;
+use crate::subscription_tokens::{SubscriptionTokens, SUBSCRIPTION_TOKEN_TTL_DAYS};
+use crate::url_builder::UrlBuilder;
+
+#[derive(serde::Deserialize)]
+pub struct ChangeEmailFormData {
+    subscription_token: String,
+    email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ApproveEmailChangeParameters {
+    approval_token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ChangeEmailParameters {
+    change_token: String,
+}
+
+/// Records the new address a subscriber wants issues sent to, and emails the current address a
+/// link to approve the change: the token of the request comes with every issue, so whoever an
+/// issue was forwarded to has it too. Nothing is sent if the new address is already subscribed to
+/// the same newsletter, without telling the requester.
+#[tracing::instrument(
+    name = "Request a subscriber email change",
+    skip(
+        form,
+        connection_pool,
+        email_client,
+        urls,
+        subscription_tokens,
+        email_validator
+    )
+)]
+pub async fn request_email_change(
+    form: web::Form<ChangeEmailFormData>,
+    connection_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    urls: web::Data<UrlBuilder>,
+    subscription_tokens: web::Data<SubscriptionTokens>,
+    email_validator: web::Data<EmailValidator>,
+) -> Result<HttpResponse, ChangeEmailError> {
+    let ChangeEmailFormData {
+        subscription_token,
+        email,
+    } = form.0;
+    let subscriber_id =
+        get_subscriber_id_from_token(&subscription_token, &subscription_tokens, &connection_pool)
+            .await
+            .context("Failed to get subscriber ID from token")?
+            .ok_or(ChangeEmailError::UnknownToken)?;
+    let new_email = SubscriberEmail::parse(email).map_err(ChangeEmailError::ValidationError)?;
+    email_validator
+        .check(&new_email)
+        .await
+        .map_err(ChangeEmailError::ValidationError)?;
+    if is_subscribed(&connection_pool, subscriber_id, &new_email)
+        .await
+        .context("Failed to look up the new address.")?
+    {
+        return Ok(HttpResponse::Ok().finish());
+    }
+    let current_email = get_current_email(&connection_pool, subscriber_id)
+        .await
+        .context("Failed to look up the current address.")?;
+    let current_email = SubscriberEmail::parse(current_email)
+        .map_err(|e| anyhow::anyhow!(e))
+        .context("The stored address of the subscriber is invalid.")?;
+    let approval_token = generate_subscription_token();
+    let change_token = generate_subscription_token();
+    store_change_request(
+        &connection_pool,
+        subscriber_id,
+        &new_email,
+        &approval_token,
+        &change_token,
+    )
+    .await
+    .context("Failed to store the email change request.")?;
+    send_change_approval_email(
+        &email_client,
+        &current_email,
+        &new_email,
+        &urls,
+        &approval_token,
+    )
+    .await
+    .context("Failed to send the email change approval.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Handles the link sent to the current address: emails the new address a link to confirm it
+#[tracing::instrument(
+    name = "Approve a subscriber email change",
+    skip(parameters, connection_pool, email_client, urls)
+)]
+pub async fn approve_email_change(
+    parameters: web::Query<ApproveEmailChangeParameters>,
+    connection_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    urls: web::Data<UrlBuilder>,
+) -> Result<HttpResponse, ChangeEmailError> {
+    let request = get_request_to_approve(&connection_pool, &parameters.approval_token)
+        .await
+        .context("Failed to get the email change request.")?
+        .ok_or(ChangeEmailError::UnknownToken)?;
+    if request.expires_at < Utc::now() {
+        return Err(ChangeEmailError::ExpiredToken);
+    }
+    // following the link again does not send the confirmation again
+    if approve_change_request(&connection_pool, &parameters.approval_token)
+        .await
+        .context("Failed to approve the email change request.")?
+    {
+        let new_email = SubscriberEmail::parse(request.new_email)
+            .map_err(|e| anyhow::anyhow!(e))
+            .context("The stored new address is invalid.")?;
+        send_change_confirmation_email(&email_client, &new_email, &urls, &request.change_token)
+            .await
+            .context("Failed to send the email change confirmation.")?;
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Handles the link sent to the new address: swaps the address of the subscription, including
+/// for the issues still queued for delivery to the old one
+#[tracing::instrument(
+    name = "Confirm a subscriber email change",
+    skip(parameters, connection_pool)
+)]
+pub async fn confirm_email_change(
+    parameters: web::Query<ChangeEmailParameters>,
+    connection_pool: web::Data<PgPool>,
+) -> Result<HttpResponse, ChangeEmailError> {
+    let mut transaction = connection_pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let request = get_change_request(&mut transaction, &parameters.change_token)
+        .await
+        .context("Failed to get the email change request.")?
+        .ok_or(ChangeEmailError::UnknownToken)?;
+    if request.expires_at < Utc::now() {
+        return Err(ChangeEmailError::ExpiredToken);
+    }
+    let changed = change_subscriber_email(&mut transaction, &request)
+        .await
+        .context("Failed to change the email of the subscriber.")?;
+    if !changed {
+        return Err(ChangeEmailError::AlreadySubscribed);
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to change the email of a subscriber.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(thiserror::Error)]
+pub enum ChangeEmailError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+    #[error("The confirmation link has expired.")]
+    ExpiredToken,
+    #[error("The new address has been subscribed in the meantime.")]
+    AlreadySubscribed,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ChangeEmailError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_handling::error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ChangeEmailError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ChangeEmailError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ChangeEmailError::UnknownToken => StatusCode::UNAUTHORIZED,
+            ChangeEmailError::ExpiredToken => StatusCode::GONE,
+            ChangeEmailError::AlreadySubscribed => StatusCode::CONFLICT,
+            ChangeEmailError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Whether the address is subscribed to the newsletter of the given subscriber
+#[tracing::instrument(name = "Check whether an address is subscribed", skip_all)]
+async fn is_subscribed(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    email: &SubscriberEmail,
+) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT EXISTS(
+            SELECT 1 FROM subscriptions
+            WHERE
+                email = $2 AND
+                newsletter_id = (SELECT newsletter_id FROM subscriptions WHERE id = $1)
+        ) AS "exists!"
+        "#,
+        subscriber_id,
+        email.as_ref()
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row.exists)
+}
+
+#[tracing::instrument(name = "Get the current address of a subscriber", skip(pool))]
+async fn get_current_email(pool: &PgPool, subscriber_id: Uuid) -> Result<String, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row.email)
+}
+
+#[tracing::instrument(
+    name = "Store an email change request",
+    skip(pool, new_email, approval_token, change_token)
+)]
+async fn store_change_request(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    new_email: &SubscriberEmail,
+    approval_token: &str,
+    change_token: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_change_requests (
+            change_token,
+            approval_token,
+            subscriber_id,
+            new_email,
+            expires_at
+        )
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        change_token,
+        approval_token,
+        subscriber_id,
+        new_email.as_ref(),
+        Utc::now() + chrono::Duration::days(SUBSCRIPTION_TOKEN_TTL_DAYS),
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Send an email change approval",
+    skip(email_client, current_email, new_email, urls, approval_token)
+)]
+async fn send_change_approval_email(
+    email_client: &EmailClient,
+    current_email: &SubscriberEmail,
+    new_email: &SubscriberEmail,
+    urls: &UrlBuilder,
+    approval_token: &str,
+) -> Result<(), SendEmailError> {
+    let approval_link = urls.email_change_approval_url(approval_token);
+    let html_body = format!(
+        "Someone asked for the newsletter to be sent to {} instead of this address.<br />\
+        If it was you, click <a href=\"{}\">here</a> to approve the change; we will then send a \
+        confirmation link to the new address. Otherwise, ignore this email and nothing will \
+        change. The link expires in {} days.",
+        html_escape(new_email.as_ref()),
+        approval_link,
+        SUBSCRIPTION_TOKEN_TTL_DAYS
+    );
+    let text_body = format!(
+        "Someone asked for the newsletter to be sent to {} instead of this address.\n\
+        If it was you, visit {} to approve the change; we will then send a confirmation link to \
+        the new address. Otherwise, ignore this email and nothing will change. The link expires \
+        in {} days.",
+        new_email.as_ref(),
+        approval_link,
+        SUBSCRIPTION_TOKEN_TTL_DAYS
+    );
+    email_client
+        .send_email(
+            current_email,
+            "Approve the change of your email address",
+            &html_body,
+            &text_body,
+        )
+        .await
+}
+
+struct RequestToApprove {
+    new_email: String,
+    change_token: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Ignores the requests of deleted subscribers
+#[tracing::instrument(name = "Get an email change request to approve", skip_all)]
+async fn get_request_to_approve(
+    pool: &PgPool,
+    approval_token: &str,
+) -> Result<Option<RequestToApprove>, sqlx::Error> {
+    sqlx::query_as!(
+        RequestToApprove,
+        r#"
+        SELECT new_email, change_token, expires_at
+        FROM email_change_requests
+        JOIN subscriptions ON subscriptions.id = email_change_requests.subscriber_id
+        WHERE approval_token = $1 AND deleted_at IS NULL
+        "#,
+        approval_token
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Returns `false` if the request had already been approved
+#[tracing::instrument(name = "Approve an email change request", skip_all)]
+async fn approve_change_request(pool: &PgPool, approval_token: &str) -> Result<bool, sqlx::Error> {
+    let n_approved = sqlx::query!(
+        r#"
+        UPDATE email_change_requests
+        SET approved_at = now()
+        WHERE approval_token = $1 AND approved_at IS NULL
+        "#,
+        approval_token
+    )
+    .execute(pool)
+    .await?
+    .rows_affected();
+    Ok(n_approved > 0)
+}
+
+#[tracing::instrument(
+    name = "Send an email change confirmation",
+    skip(email_client, new_email, urls, change_token)
+)]
+async fn send_change_confirmation_email(
+    email_client: &EmailClient,
+    new_email: &SubscriberEmail,
+    urls: &UrlBuilder,
+    change_token: &str,
+) -> Result<(), SendEmailError> {
+    let confirmation_link = urls.email_change_confirmation_url(change_token);
+    let html_body = format!(
+        "You asked for the newsletter to be sent to this address from now on.<br />\
+        Click <a href=\"{}\">here</a> to confirm. The link expires in {} days.",
+        confirmation_link, SUBSCRIPTION_TOKEN_TTL_DAYS
+    );
+    let text_body = format!(
+        "You asked for the newsletter to be sent to this address from now on.\n\
+        Visit {} to confirm. The link expires in {} days.",
+        confirmation_link, SUBSCRIPTION_TOKEN_TTL_DAYS
+    );
+    email_client
+        .send_email(
+            new_email,
+            "Confirm your new email address",
+            &html_body,
+            &text_body,
+        )
+        .await
+}
+
+struct ChangeRequest {
+    subscriber_id: Uuid,
+    new_email: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Ignores the requests of deleted subscribers, and the ones not approved from the current
+/// address. The row is locked until the end of the transaction, so that a link followed twice at
+/// once only changes the address once
+#[tracing::instrument(name = "Get an email change request", skip_all)]
+async fn get_change_request(
+    transaction: &mut Transaction<'_, Postgres>,
+    change_token: &str,
+) -> Result<Option<ChangeRequest>, sqlx::Error> {
+    sqlx::query_as!(
+        ChangeRequest,
+        r#"
+        SELECT subscriber_id, new_email, expires_at
+        FROM email_change_requests
+        JOIN subscriptions ON subscriptions.id = email_change_requests.subscriber_id
+        WHERE change_token = $1 AND approved_at IS NOT NULL AND deleted_at IS NULL
+        FOR UPDATE OF email_change_requests
+        "#,
+        change_token
+    )
+    .fetch_optional(transaction)
+    .await
+}
+
+/// Moves the subscription and its queued deliveries to the new address, and drops every pending
+/// change request of the subscriber. Returns `false` if the new address is already subscribed to
+/// the same newsletter.
+#[tracing::instrument(name = "Change the email of a subscriber", skip_all)]
+async fn change_subscriber_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    request: &ChangeRequest,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET subscriber_email = $2
+        WHERE (newsletter_id, subscriber_email) =
+            (SELECT newsletter_id, email FROM subscriptions WHERE id = $1)
+        "#,
+        request.subscriber_id,
+        request.new_email
+    )
+    .execute(&mut *transaction)
+    .await?;
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE subscriptions s
+        SET email = $2
+        WHERE
+            id = $1 AND
+            NOT EXISTS (
+                SELECT 1 FROM subscriptions
+                WHERE email = $2 AND newsletter_id = s.newsletter_id
+            )
+        "#,
+        request.subscriber_id,
+        request.new_email
+    )
+    .execute(&mut *transaction)
+    .await?
+    .rows_affected();
+    if n_updated == 0 {
+        return Ok(false);
+    }
+    sqlx::query!(
+        "DELETE FROM email_change_requests WHERE subscriber_id = $1",
+        request.subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    Ok(true)
+}
@@ -1,23 +1,62 @@
 use std::fmt::Formatter;
 
+use actix_web::http::header::ContentType;
 use actix_web::http::StatusCode;
 use actix_web::{web, HttpResponse, ResponseError};
 use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 use uuid::Uuid;
 
-use crate::error_handling;
+use crate::automations::enroll_subscriber;
+use crate::configuration::ConfirmationPageSettings;
+use crate::domain::SubscriptionStatus;
+use crate::error_handling::{self, NegotiatedError, UNEXPECTED_ERROR_MESSAGE};
+use crate::events::{record_subscriber_event, EventType};
+use crate::subscription_events::{parse_previous_status, record_status_change};
+use crate::subscription_tokens::{is_signed_token, SubscriptionTokens, TokenError, TokenPurpose};
+use crate::templates::{Branding, Layout};
+
+#[derive(Template)]
+#[template(path = "confirmation_success.html")]
+struct ConfirmationSuccessPage<'a> {
+    layout: Layout<'a>,
+    heading: &'a str,
+    message: &'a str,
+    link_text: &'a str,
+    site_url: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "confirmation_expired.html")]
+struct ConfirmationExpiredPage<'a> {
+    layout: Layout<'a>,
+}
+
+#[derive(Template)]
+#[template(path = "confirmation_unknown.html")]
+struct ConfirmationUnknownPage<'a> {
+    layout: Layout<'a>,
+}
 
 #[derive(serde::Deserialize)]
 pub struct Parameters {
     subscription_token: String,
 }
 
-/// Handles confirming a subscriber using a subscription token; updates status to confirmed
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters))]
+/// Handles confirming a subscriber using a subscription token; updates status to confirmed and
+/// shows the subscriber a page saying so
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(parameters, connection_pool, subscription_tokens, page, branding)
+)]
 pub async fn confirm(
     parameters: web::Query<Parameters>,
     connection_pool: web::Data<PgPool>,
+    subscription_tokens: web::Data<SubscriptionTokens>,
+    page: web::Data<ConfirmationPageSettings>,
+    branding: web::Data<Branding>,
 ) -> Result<HttpResponse, ConfirmSubscriberError> {
     // using web::Query<Parameters> tells actix that the parameters are mandatory; this handler is only called if
     // those query parameters extract; otherwise, returns a 400
@@ -33,15 +72,75 @@ pub async fn confirm(
         parameters.subscription_token
     )
      */
-    let subscriber_id =
-        get_subscriber_id_from_token(&parameters.subscription_token, &connection_pool)
+    match confirm_subscription(
+        &parameters.subscription_token,
+        &subscription_tokens,
+        &connection_pool,
+    )
+    .await
+    {
+        Ok(()) => {}
+        // subscribers land here from their inbox: expired links get a page to request a new one,
+        // unknown ones a way back to the subscribe form
+        Err(e @ ConfirmSubscriberError::ExpiredToken) => {
+            let page = ConfirmationExpiredPage {
+                layout: Layout::new(&branding, "Link expired"),
+            };
+            return e.page(page.render());
+        }
+        Err(e @ ConfirmSubscriberError::UnknownToken) => {
+            let page = ConfirmationUnknownPage {
+                layout: Layout::new(&branding, "Invalid link"),
+            };
+            return e.page(page.render());
+        }
+        Err(e) => return Err(e),
+    }
+    let body = ConfirmationSuccessPage {
+        layout: Layout::new(&branding, &page.heading),
+        heading: &page.heading,
+        message: &page.message,
+        link_text: &page.link_text,
+        site_url: page.site_url.as_deref().unwrap_or("/"),
+    }
+    .render()
+    .context("Failed to render the confirmation page")?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Confirms the subscriber a confirmation link was sent to, unless the link has expired. Links
+/// sent before tokens were signed are still looked up in `subscription_tokens`.
+pub async fn confirm_subscription(
+    subscription_token: &str,
+    subscription_tokens: &SubscriptionTokens,
+    connection_pool: &PgPool,
+) -> Result<(), ConfirmSubscriberError> {
+    let subscriber_id = if is_signed_token(subscription_token) {
+        subscription_tokens
+            .verify(subscription_token, TokenPurpose::Confirmation, Utc::now())
+            .map_err(|e| match e {
+                TokenError::Invalid => ConfirmSubscriberError::UnknownToken,
+                TokenError::Expired => ConfirmSubscriberError::ExpiredToken,
+            })?
+    } else {
+        let token = get_confirmation_token(subscription_token, connection_pool)
             .await
             .context("Failed to get subscriber ID from token")?
             .ok_or(ConfirmSubscriberError::UnknownToken)?;
-    confirm_subscriber(subscriber_id, &connection_pool)
+        if token.expires_at < Utc::now() {
+            return Err(ConfirmSubscriberError::ExpiredToken);
+        }
+        token.subscriber_id
+    };
+    let found = confirm_subscriber(subscriber_id, connection_pool)
         .await
         .context("Failed to confirm subscriber.")?;
-    Ok(HttpResponse::Ok().finish())
+    if !found {
+        return Err(ConfirmSubscriberError::UnknownToken);
+    }
+    Ok(())
 }
 
 #[derive(thiserror::Error)]
@@ -50,6 +149,8 @@ pub enum ConfirmSubscriberError {
     UnexpectedError(#[from] anyhow::Error),
     #[error("There is no subscriber associated with the provided token.")]
     UnknownToken,
+    #[error("The confirmation link has expired.")]
+    ExpiredToken,
 }
 
 impl std::fmt::Debug for ConfirmSubscriberError {
@@ -58,15 +159,46 @@ impl std::fmt::Debug for ConfirmSubscriberError {
     }
 }
 
+impl ConfirmSubscriberError {
+    /// The page explaining the error to subscribers following their confirmation link
+    fn page(
+        &self,
+        body: Result<String, askama::Error>,
+    ) -> Result<HttpResponse, ConfirmSubscriberError> {
+        let body = body.context("Failed to render the confirmation error page")?;
+        Ok(self.negotiated(
+            HttpResponse::build(self.status_code())
+                .content_type(ContentType::html())
+                .body(body),
+        ))
+    }
+}
+
 impl ResponseError for ConfirmSubscriberError {
     fn status_code(&self) -> StatusCode {
         match self {
             ConfirmSubscriberError::UnknownToken => StatusCode::UNAUTHORIZED,
+            ConfirmSubscriberError::ExpiredToken => StatusCode::GONE,
             ConfirmSubscriberError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        self.negotiated(self.browser_response())
+    }
+}
+
+impl NegotiatedError for ConfirmSubscriberError {
+    fn client_message(&self) -> String {
+        match self {
+            ConfirmSubscriberError::UnexpectedError(_) => UNEXPECTED_ERROR_MESSAGE.to_string(),
+            e => e.to_string(),
+        }
+    }
 }
 
+/// Returns `false` if there is no such subscriber, e.g. because they were deleted since the
+/// link was sent
 #[tracing::instrument(
     name = "Mark subscriber as confirmed",
     skip(subscriber_id, connection_pool)
@@ -74,28 +206,116 @@ impl ResponseError for ConfirmSubscriberError {
 pub async fn confirm_subscriber(
     subscriber_id: Uuid,
     connection_pool: &PgPool,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
+) -> Result<bool, sqlx::Error> {
+    let mut transaction = connection_pool.begin().await?;
+    let previous = sqlx::query!(
         r#"
-        UPDATE subscriptions SET status = 'confirmed' WHERE id = $1
-    "#,
+        UPDATE subscriptions
+        SET status = 'confirmed'
+        FROM (
+            SELECT status FROM subscriptions WHERE id = $1 AND deleted_at IS NULL FOR UPDATE
+        ) AS previous
+        WHERE id = $1
+        RETURNING previous.status AS previous_status
+        "#,
         subscriber_id
     )
-    .execute(connection_pool)
+    .fetch_optional(&mut transaction)
     .await?;
-    Ok(())
+    let Some(previous) = previous else {
+        return Ok(false);
+    };
+    let previous_status = parse_previous_status(previous.previous_status)?;
+    // following the link again must not start the sequences over
+    if previous_status != SubscriptionStatus::Confirmed {
+        record_status_change(
+            &mut transaction,
+            subscriber_id,
+            Some(previous_status),
+            SubscriptionStatus::Confirmed,
+        )
+        .await?;
+        enroll_subscriber(&mut transaction, subscriber_id).await?;
+        record_subscriber_event(
+            &mut transaction,
+            EventType::SubscriberConfirmed,
+            subscriber_id,
+        )
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(true)
+}
+
+struct ConfirmationToken {
+    subscriber_id: Uuid,
+    expires_at: DateTime<Utc>,
 }
 
 #[tracing::instrument(
-    name = "Get subscriber_id from token",
+    name = "Get confirmation token",
     skip(subscription_token, connection_pool)
 )]
+async fn get_confirmation_token(
+    subscription_token: &str,
+    connection_pool: &PgPool,
+) -> Result<Option<ConfirmationToken>, sqlx::Error> {
+    sqlx::query_as!(
+        ConfirmationToken,
+        r#"
+        SELECT subscriber_id, expires_at
+        FROM subscription_tokens
+        JOIN subscriptions ON subscriptions.id = subscription_tokens.subscriber_id
+        WHERE subscription_token = $1 AND deleted_at IS NULL
+        "#,
+        subscription_token,
+    )
+    .fetch_optional(connection_pool)
+    .await
+}
+
+/// Looks up the subscriber of the token of an unsubscribe link. The tokens of deleted subscribers
+/// are unknown.
+#[tracing::instrument(
+    name = "Get subscriber_id from token",
+    skip(subscription_token, subscription_tokens, connection_pool)
+)]
 pub async fn get_subscriber_id_from_token(
     subscription_token: &str,
+    subscription_tokens: &SubscriptionTokens,
     connection_pool: &PgPool,
 ) -> Result<Option<Uuid>, sqlx::Error> {
+    if !is_signed_token(subscription_token) {
+        return get_subscriber_id_from_stored_token(subscription_token, connection_pool).await;
+    }
+    let Ok(subscriber_id) =
+        subscription_tokens.verify(subscription_token, TokenPurpose::Unsubscribe, Utc::now())
+    else {
+        return Ok(None);
+    };
     let result = sqlx::query!(
-        "SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1",
+        "SELECT id FROM subscriptions WHERE id = $1 AND deleted_at IS NULL",
+        subscriber_id
+    )
+    .fetch_optional(connection_pool)
+    .await?;
+    Ok(result.map(|r| r.id))
+}
+
+/// Looks up the subscriber of a token issued before tokens were signed, whether or not the token
+/// has expired: expiry only applies to confirmation links, while the same token also backs the
+/// unsubscribe links of the issues sent back then
+async fn get_subscriber_id_from_stored_token(
+    subscription_token: &str,
+    connection_pool: &PgPool,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        SELECT subscriber_id
+        FROM subscription_tokens
+        JOIN subscriptions ON subscriptions.id = subscription_tokens.subscriber_id
+        WHERE subscription_token = $1 AND deleted_at IS NULL
+        "#,
         subscription_token,
     )
     .fetch_optional(connection_pool)
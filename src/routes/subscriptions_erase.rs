@@ -0,0 +1,157 @@
+use std::fmt::Formatter;
+
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::{EmailClient, SendEmailError};
+use crate::error_handling;
+use crate::routes::{erase_subscriber, get_subscriber_id_from_token};
+use crate::subscription_tokens::{
+    SubscriptionTokens, TokenError, TokenPurpose, ERASURE_TOKEN_TTL_HOURS,
+};
+use crate::url_builder::UrlBuilder;
+
+#[derive(serde::Deserialize)]
+pub struct EraseFormData {
+    subscription_token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ConfirmErasureParameters {
+    erasure_token: String,
+}
+
+/// Lets subscribers ask for every piece of data we hold about them to be erased, using the token
+/// from their emails. Since that token comes with every issue, whoever an issue was forwarded to
+/// has it too: nothing is erased until the link emailed to the subscriber's address is followed.
+#[tracing::instrument(
+    name = "Request the erasure of own subscription",
+    skip(form, connection_pool, email_client, urls, subscription_tokens)
+)]
+pub async fn erase_own_subscription(
+    form: web::Form<EraseFormData>,
+    connection_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    urls: web::Data<UrlBuilder>,
+    subscription_tokens: web::Data<SubscriptionTokens>,
+) -> Result<HttpResponse, EraseError> {
+    let subscriber_id = get_subscriber_id_from_token(
+        &form.subscription_token,
+        &subscription_tokens,
+        &connection_pool,
+    )
+    .await
+    .context("Failed to get subscriber ID from token")?
+    .ok_or(EraseError::UnknownToken)?;
+    let email = get_subscriber_email(&connection_pool, subscriber_id)
+        .await
+        .context("Failed to look up the address of the subscriber.")?;
+    let email = SubscriberEmail::parse(email)
+        .map_err(|e| anyhow::anyhow!(e))
+        .context("The stored address of the subscriber is invalid.")?;
+    let erasure_token = subscription_tokens.erasure_token(subscriber_id, Utc::now());
+    send_erasure_confirmation_email(&email_client, &email, &urls, &erasure_token)
+        .await
+        .context("Failed to send the erasure confirmation.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Handles the link sent to the subscriber: erases them, as an admin would
+#[tracing::instrument(
+    name = "Confirm the erasure of own subscription",
+    skip(parameters, connection_pool, subscription_tokens)
+)]
+pub async fn confirm_erasure(
+    parameters: web::Query<ConfirmErasureParameters>,
+    connection_pool: web::Data<PgPool>,
+    subscription_tokens: web::Data<SubscriptionTokens>,
+) -> Result<HttpResponse, EraseError> {
+    let subscriber_id = subscription_tokens
+        .verify(&parameters.erasure_token, TokenPurpose::Erasure, Utc::now())
+        .map_err(|e| match e {
+            TokenError::Invalid => EraseError::UnknownToken,
+            TokenError::Expired => EraseError::ExpiredToken,
+        })?;
+    let erased = erase_subscriber(&connection_pool, subscriber_id, None)
+        .await
+        .context("Failed to erase subscriber.")?;
+    if !erased {
+        return Err(EraseError::UnknownToken);
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(thiserror::Error)]
+pub enum EraseError {
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+    #[error("The confirmation link has expired.")]
+    ExpiredToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for EraseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_handling::error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for EraseError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            EraseError::UnknownToken => StatusCode::UNAUTHORIZED,
+            EraseError::ExpiredToken => StatusCode::GONE,
+            EraseError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[tracing::instrument(name = "Get the address of a subscriber", skip(pool))]
+async fn get_subscriber_email(pool: &PgPool, subscriber_id: Uuid) -> Result<String, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT email FROM subscriptions WHERE id = $1",
+        subscriber_id
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row.email)
+}
+
+#[tracing::instrument(
+    name = "Send an erasure confirmation",
+    skip(email_client, email, urls, erasure_token)
+)]
+async fn send_erasure_confirmation_email(
+    email_client: &EmailClient,
+    email: &SubscriberEmail,
+    urls: &UrlBuilder,
+    erasure_token: &str,
+) -> Result<(), SendEmailError> {
+    let confirmation_link = urls.erasure_confirmation_url(erasure_token);
+    let html_body = format!(
+        "Someone asked for every piece of data we hold about this address to be erased.<br />\
+        If it was you, click <a href=\"{}\">here</a> to confirm; this cannot be undone. \
+        Otherwise, ignore this email and nothing will change. The link expires in {} hours.",
+        confirmation_link, ERASURE_TOKEN_TTL_HOURS
+    );
+    let text_body = format!(
+        "Someone asked for every piece of data we hold about this address to be erased.\n\
+        If it was you, visit {} to confirm; this cannot be undone. Otherwise, ignore this email \
+        and nothing will change. The link expires in {} hours.",
+        confirmation_link, ERASURE_TOKEN_TTL_HOURS
+    );
+    email_client
+        .send_email(
+            email,
+            "Confirm the erasure of your data",
+            &html_body,
+            &text_body,
+        )
+        .await
+}
@@ -0,0 +1,90 @@
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::routes::{send_confirmation_email, SubscribeError};
+use crate::subscription_tokens::SubscriptionTokens;
+use crate::templates::ConfirmationEmailTemplate;
+use crate::url_builder::UrlBuilder;
+
+#[derive(serde::Deserialize)]
+pub struct ResendConfirmationFormData {
+    email: String,
+}
+
+struct PendingSubscriber {
+    id: Uuid,
+    name: String,
+    /// The confirmation link comes from the sender of the newsletter subscribed to
+    newsletter_slug: String,
+}
+
+/// Sends a new confirmation link to a pending subscriber, e.g. because the previous one expired;
+/// an address pending confirmation for several newsletters gets a link for each. The response does not depend on whether the address is on the list, so that this endpoint
+/// cannot be used to find out who subscribed.
+#[tracing::instrument(
+    name = "Resend a confirmation email",
+    skip(
+        form,
+        connection_pool,
+        email_client,
+        confirmation_email,
+        urls,
+        subscription_tokens
+    )
+)]
+pub async fn resend_confirmation(
+    form: web::Form<ResendConfirmationFormData>,
+    connection_pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    confirmation_email: web::Data<ConfirmationEmailTemplate>,
+    urls: web::Data<UrlBuilder>,
+    subscription_tokens: web::Data<SubscriptionTokens>,
+) -> Result<HttpResponse, SubscribeError> {
+    let email = SubscriberEmail::parse(form.0.email).map_err(SubscribeError::ValidationError)?;
+    let subscribers = get_pending_subscribers(&connection_pool, &email)
+        .await
+        .context("Failed to look up the subscriber.")?;
+
+    for subscriber in subscribers {
+        let token = subscription_tokens.confirmation_token(subscriber.id, Utc::now());
+        send_confirmation_email(
+            &email_client,
+            email_client.sender_of(&subscriber.newsletter_slug),
+            &confirmation_email,
+            &email,
+            &subscriber.name,
+            &urls,
+            &token,
+        )
+        .await
+        .context("Failed to send a confirmation email.")?;
+    }
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(name = "Get pending subscribers", skip_all)]
+async fn get_pending_subscribers(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<Vec<PendingSubscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        PendingSubscriber,
+        r#"
+        SELECT id, subscriptions.name, slug AS newsletter_slug
+        FROM subscriptions
+        JOIN newsletters USING (newsletter_id)
+        WHERE
+            email = $1 AND
+            status = 'pending_confirmation' AND
+            deleted_at IS NULL
+        "#,
+        email.as_ref()
+    )
+    .fetch_all(pool)
+    .await
+}
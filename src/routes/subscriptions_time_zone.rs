@@ -0,0 +1,113 @@
+use std::fmt::Formatter;
+
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::error_handling;
+use crate::routes::get_subscriber_id_from_token;
+use crate::subscription_tokens::SubscriptionTokens;
+
+#[derive(serde::Deserialize)]
+pub struct TimeZoneFormData {
+    subscription_token: String,
+    /// An IANA name such as `Europe/Paris`; left empty to forget the time zone
+    #[serde(default)]
+    time_zone: String,
+}
+
+/// Records the time zone of a subscriber, which issues sent at a local time of day follow.
+/// Subscribers without one get these issues at that time in UTC. The send window ignores it: its
+/// hours are read in the time zone it is configured with.
+#[tracing::instrument(
+    name = "Set the time zone of a subscriber",
+    skip(form, connection_pool, subscription_tokens)
+)]
+pub async fn set_subscriber_time_zone(
+    form: web::Form<TimeZoneFormData>,
+    connection_pool: web::Data<PgPool>,
+    subscription_tokens: web::Data<SubscriptionTokens>,
+) -> Result<HttpResponse, TimeZoneError> {
+    let subscriber_id = get_subscriber_id_from_token(
+        &form.subscription_token,
+        &subscription_tokens,
+        &connection_pool,
+    )
+    .await
+    .context("Failed to get subscriber ID from token")?
+    .ok_or(TimeZoneError::UnknownToken)?;
+    let time_zone = form.time_zone.trim();
+    let time_zone = if time_zone.is_empty() {
+        None
+    } else if is_known_time_zone(&connection_pool, time_zone)
+        .await
+        .context("Failed to look up the time zone.")?
+    {
+        Some(time_zone)
+    } else {
+        return Err(TimeZoneError::ValidationError(format!(
+            "{} is not a known time zone.",
+            time_zone
+        )));
+    };
+    store_time_zone(&connection_pool, subscriber_id, time_zone)
+        .await
+        .context("Failed to store the time zone of the subscriber.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(thiserror::Error)]
+pub enum TimeZoneError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for TimeZoneError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_handling::error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for TimeZoneError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            TimeZoneError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            TimeZoneError::UnknownToken => StatusCode::UNAUTHORIZED,
+            TimeZoneError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Whether Postgres knows the time zone, since it computes the local send times of deliveries
+#[tracing::instrument(name = "Check whether a time zone is known", skip(pool))]
+async fn is_known_time_zone(pool: &PgPool, time_zone: &str) -> Result<bool, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT EXISTS(SELECT 1 FROM pg_timezone_names WHERE name = $1) AS "exists!""#,
+        time_zone
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row.exists)
+}
+
+#[tracing::instrument(name = "Store the time zone of a subscriber", skip(pool))]
+async fn store_time_zone(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    time_zone: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE subscriptions SET time_zone = $2 WHERE id = $1",
+        subscriber_id,
+        time_zone
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
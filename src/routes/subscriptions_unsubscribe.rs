@@ -0,0 +1,106 @@
+use std::fmt::Formatter;
+
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::SubscriptionStatus;
+use crate::error_handling;
+use crate::events::{record_subscriber_event, EventType};
+use crate::routes::get_subscriber_id_from_token;
+use crate::subscription_events::{parse_previous_status, record_status_change};
+use crate::subscription_tokens::SubscriptionTokens;
+
+#[derive(serde::Deserialize)]
+pub struct UnsubscribeParameters {
+    subscription_token: String,
+}
+
+/// Handles the unsubscribe links included in newsletter issues; updates status to unsubscribed
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(parameters, connection_pool, subscription_tokens)
+)]
+pub async fn unsubscribe(
+    parameters: web::Query<UnsubscribeParameters>,
+    connection_pool: web::Data<PgPool>,
+    subscription_tokens: web::Data<SubscriptionTokens>,
+) -> Result<HttpResponse, UnsubscribeError> {
+    let subscriber_id = get_subscriber_id_from_token(
+        &parameters.subscription_token,
+        &subscription_tokens,
+        &connection_pool,
+    )
+    .await
+    .context("Failed to get subscriber ID from token")?
+    .ok_or(UnsubscribeError::UnknownToken)?;
+    mark_subscriber_as_unsubscribed(subscriber_id, &connection_pool)
+        .await
+        .context("Failed to unsubscribe subscriber.")?;
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("There is no subscriber associated with the provided token.")]
+    UnknownToken,
+}
+
+impl std::fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_handling::error_chain_fmt(&self, f)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            UnsubscribeError::UnknownToken => StatusCode::UNAUTHORIZED,
+            UnsubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(connection_pool))]
+pub async fn mark_subscriber_as_unsubscribed(
+    subscriber_id: Uuid,
+    connection_pool: &PgPool,
+) -> Result<(), sqlx::Error> {
+    let mut transaction = connection_pool.begin().await?;
+    let previous = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET
+            status = 'unsubscribed',
+            unsubscribed_at = COALESCE(unsubscribed_at, now())
+        FROM (SELECT status FROM subscriptions WHERE id = $1 FOR UPDATE) AS previous
+        WHERE id = $1 AND previous.status <> 'unsubscribed'
+        RETURNING previous.status AS previous_status
+        "#,
+        subscriber_id
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    // following the link again is not a new event
+    if let Some(previous) = previous {
+        record_status_change(
+            &mut transaction,
+            subscriber_id,
+            Some(parse_previous_status(previous.previous_status)?),
+            SubscriptionStatus::Unsubscribed,
+        )
+        .await?;
+        record_subscriber_event(
+            &mut transaction,
+            EventType::SubscriberUnsubscribed,
+            subscriber_id,
+        )
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
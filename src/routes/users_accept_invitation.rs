@@ -0,0 +1,144 @@
+use std::fmt::Formatter;
+
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{web, HttpResponse, ResponseError};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context as _;
+use askama::Template;
+use secrecy::Secret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::{change_password, validate_new_password, PasswordHashingPolicy};
+use crate::error_handling;
+use crate::routing_helpers::see_other;
+use crate::templates::{collect_flash_messages, Branding, Layout};
+
+#[derive(serde::Deserialize)]
+pub struct InvitationParameters {
+    invitation_token: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct AcceptInvitationFormData {
+    invitation_token: String,
+    new_password: Secret<String>,
+    new_password_check: Secret<String>,
+}
+
+#[derive(Template)]
+#[template(path = "accept_invitation.html")]
+struct AcceptInvitationPage<'a> {
+    layout: Layout<'a>,
+    messages: Vec<FlashMessage>,
+    username: &'a str,
+    invitation_token: &'a str,
+}
+
+struct Invitee {
+    user_id: Uuid,
+    username: String,
+}
+
+/// Shows invited admins a form to choose their password
+#[tracing::instrument(name = "Show invitation", skip(parameters, pool, flash_messages))]
+pub async fn accept_invitation_form(
+    parameters: web::Query<InvitationParameters>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    branding: web::Data<Branding>,
+) -> Result<HttpResponse, AcceptInvitationError> {
+    let invitee = get_invitee(&pool, &parameters.invitation_token)
+        .await
+        .context("Failed to retrieve the invitation.")?
+        .ok_or(AcceptInvitationError::InvalidInvitation)?;
+    let body = AcceptInvitationPage {
+        layout: Layout::new(&branding, "Accept invitation"),
+        messages: collect_flash_messages(&flash_messages),
+        username: &invitee.username,
+        invitation_token: &parameters.invitation_token,
+    }
+    .render()
+    .context("Failed to render the invitation page.")?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(body))
+}
+
+/// Sets the password of an invited admin, who can then log in
+#[tracing::instrument(name = "Accept invitation", skip(form, pool, hashing_policy))]
+pub async fn accept_invitation(
+    form: web::Form<AcceptInvitationFormData>,
+    pool: web::Data<PgPool>,
+    hashing_policy: web::Data<PasswordHashingPolicy>,
+) -> Result<HttpResponse, AcceptInvitationError> {
+    let invitee = get_invitee(&pool, &form.invitation_token)
+        .await
+        .context("Failed to retrieve the invitation.")?
+        .ok_or(AcceptInvitationError::InvalidInvitation)?;
+    if let Err(reason) = validate_new_password(&form.new_password, &form.new_password_check) {
+        FlashMessage::error(reason).send();
+        return Ok(see_other(&format!(
+            "/users/accept-invitation?invitation_token={}",
+            form.invitation_token
+        )));
+    }
+
+    change_password(invitee.user_id, form.0.new_password, &hashing_policy, &pool).await?;
+    sqlx::query!(
+        "DELETE FROM user_invitations WHERE user_id = $1",
+        invitee.user_id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to delete the accepted invitation.")?;
+    FlashMessage::success("Your password has been set. You can now log in.").send();
+    Ok(see_other("/login"))
+}
+
+/// Returns the user an invitation was sent to, if the invitation is still valid
+#[tracing::instrument(name = "Get invitee", skip(pool, invitation_token))]
+async fn get_invitee(
+    pool: &PgPool,
+    invitation_token: &str,
+) -> Result<Option<Invitee>, sqlx::Error> {
+    sqlx::query_as!(
+        Invitee,
+        r#"
+        SELECT users.user_id, users.username
+        FROM user_invitations
+        JOIN users ON users.user_id = user_invitations.user_id
+        WHERE
+            user_invitations.invitation_token = $1 AND
+            user_invitations.expires_at > now() AND
+            users.is_active
+        "#,
+        invitation_token
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[derive(thiserror::Error)]
+pub enum AcceptInvitationError {
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+    #[error("This invitation is invalid or has expired.")]
+    InvalidInvitation,
+}
+
+impl std::fmt::Debug for AcceptInvitationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        error_handling::error_chain_fmt(&self, f)
+    }
+}
+
+impl ResponseError for AcceptInvitationError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AcceptInvitationError::InvalidInvitation => StatusCode::UNAUTHORIZED,
+            AcceptInvitationError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
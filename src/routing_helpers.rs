@@ -18,9 +18,53 @@ where
     actix_web::error::ErrorBadRequest(e)
 }
 
+/// Return a 413 with the user-representation of the error as body, for request bodies over a
+/// limit
+pub fn e413<T>(e: T) -> actix_web::Error
+where
+    T: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    actix_web::error::ErrorPayloadTooLarge(e)
+}
+
 /// Return an HttpResponse redirecting to the provided location
 pub fn see_other(location: &str) -> HttpResponse {
     HttpResponse::SeeOther()
         .insert_header((LOCATION, location))
         .finish()
 }
+
+/// Escapes the characters that have a special meaning in HTML, so that user-provided content
+/// can be safely embedded in a page
+pub fn html_escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::html_escape;
+
+    #[test]
+    fn html_special_characters_are_escaped() {
+        assert_eq!(
+            html_escape(r#"<p class="a">Tom & 'Jerry'</p>"#),
+            "&lt;p class=&quot;a&quot;&gt;Tom &amp; &#x27;Jerry&#x27;&lt;/p&gt;"
+        );
+    }
+
+    #[test]
+    fn plain_text_is_left_untouched() {
+        assert_eq!(html_escape("Hello world"), "Hello world");
+    }
+}
@@ -0,0 +1,12 @@
+/// Which parts of the application a process runs, so that the HTTP server and the issue
+/// delivery worker can be deployed and scaled independently
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum RunMode {
+    /// Only the HTTP server
+    Api,
+    /// Only the issue delivery worker
+    Worker,
+    /// Both, in the same process
+    #[default]
+    All,
+}
@@ -0,0 +1,209 @@
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+/// The hours during which issues are sent, e.g. from 07:00 to 22:00 so that nobody gets a
+/// newsletter in the middle of the night. Times are read in `time_zone`, the time zone of the
+/// audience, and follow its changes to and from summer time; a window whose end comes before its
+/// start spans midnight, and one that starts when it ends never closes.
+///
+/// The window is the same for every recipient: it is not evaluated in the time zones subscribers
+/// set for themselves. Issues that should reach each subscriber at a given local time are
+/// scheduled for it when they are published instead.
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct SendWindow {
+    #[serde(deserialize_with = "deserialize_time")]
+    pub start: NaiveTime,
+    #[serde(deserialize_with = "deserialize_time")]
+    pub end: NaiveTime,
+    /// An IANA time zone, e.g. `Europe/Paris`; UTC if not set
+    #[serde(default = "utc")]
+    pub time_zone: Tz,
+}
+
+impl SendWindow {
+    pub fn is_open(&self, now: DateTime<Utc>) -> bool {
+        let time = self.local_time(now);
+        if self.start < self.end {
+            self.start <= time && time < self.end
+        } else if self.start > self.end {
+            self.start <= time || time < self.end
+        } else {
+            true
+        }
+    }
+
+    /// When the window last opened, `now` being within a day of it
+    pub fn last_opening(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        let local_now = now.with_timezone(&self.time_zone);
+        let mut date = local_now.date_naive();
+        if local_now.time() < self.start {
+            date = date.pred_opt().expect("The date is out of range");
+        }
+        self.opening_on(date)
+    }
+
+    /// When the window opens next, or `now` if it is open
+    pub fn next_opening(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        if self.is_open(now) {
+            return now;
+        }
+        let local_now = now.with_timezone(&self.time_zone);
+        let mut date = local_now.date_naive();
+        if local_now.time() >= self.start {
+            date = date.succ_opt().expect("The date is out of range");
+        }
+        self.opening_on(date)
+    }
+
+    /// The time of day of `at` in the time zone of the window
+    pub fn local_time(&self, at: DateTime<Utc>) -> NaiveTime {
+        at.with_timezone(&self.time_zone).time()
+    }
+
+    /// When the window opens on a local `date`. A start repeated as clocks go back is taken the
+    /// first time; one skipped as they go forward is taken once they did.
+    fn opening_on(&self, date: NaiveDate) -> DateTime<Utc> {
+        let start = date.and_time(self.start);
+        (0..=24)
+            .find_map(|hours| {
+                self.time_zone
+                    .from_local_datetime(&(start + Duration::hours(hours)))
+                    .earliest()
+            })
+            .expect("Time zones never skip more than a day")
+            .with_timezone(&Utc)
+    }
+}
+
+fn utc() -> Tz {
+    Tz::UTC
+}
+
+/// Parses a time of day written `HH:MM` or `HH:MM:SS`
+pub fn parse_time(time: &str) -> Result<NaiveTime, chrono::ParseError> {
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M:%S"))
+}
+
+fn deserialize_time<'de, D>(deserializer: D) -> Result<NaiveTime, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let time = String::deserialize(deserializer)?;
+    parse_time(&time).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_time, SendWindow};
+    use chrono::{DateTime, NaiveTime, TimeZone, Utc};
+    use chrono_tz::Tz;
+
+    fn window(start: &str, end: &str, time_zone: Tz) -> SendWindow {
+        SendWindow {
+            start: parse_time(start).unwrap(),
+            end: parse_time(end).unwrap(),
+            time_zone,
+        }
+    }
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 10, 16, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn a_daytime_window_is_closed_at_night() {
+        let window = window("07:00", "22:00", Tz::UTC);
+        assert!(window.is_open(at(7, 0)));
+        assert!(window.is_open(at(21, 59)));
+        assert!(!window.is_open(at(22, 0)));
+        assert!(!window.is_open(at(3, 0)));
+    }
+
+    #[test]
+    fn a_window_ending_before_it_starts_spans_midnight() {
+        let window = window("20:00", "02:00", Tz::UTC);
+        assert!(window.is_open(at(23, 0)));
+        assert!(window.is_open(at(1, 0)));
+        assert!(!window.is_open(at(12, 0)));
+    }
+
+    #[test]
+    fn a_window_starting_when_it_ends_never_closes() {
+        assert!(window("00:00", "00:00", Tz::UTC).is_open(at(3, 0)));
+    }
+
+    #[test]
+    fn times_are_read_in_the_time_zone_of_the_window() {
+        // 21:00 UTC is 23:00 in Paris in October, and 22:00 in December
+        let window = window("07:00", "22:00", Tz::Europe__Paris);
+        assert!(!window.is_open(at(21, 0)));
+        assert_eq!(
+            window.local_time(at(21, 0)),
+            NaiveTime::from_hms_opt(23, 0, 0).unwrap()
+        );
+        assert!(window.is_open(Utc.with_ymd_and_hms(2026, 12, 16, 20, 30, 0).unwrap()));
+    }
+
+    #[test]
+    fn a_closed_window_opens_at_its_next_start() {
+        let window = window("07:00", "22:00", Tz::UTC);
+        assert_eq!(window.next_opening(at(3, 0)), at(7, 0));
+        assert_eq!(
+            window.next_opening(at(23, 0)),
+            at(7, 0) + chrono::Duration::days(1)
+        );
+        assert_eq!(window.next_opening(at(12, 0)), at(12, 0));
+        assert_eq!(
+            window.last_opening(at(3, 0)),
+            at(7, 0) - chrono::Duration::days(1)
+        );
+    }
+
+    #[test]
+    fn openings_follow_the_changes_to_and_from_summer_time() {
+        // clocks go back in Paris on the night of October 25, 2026
+        let window = window("07:00", "22:00", Tz::Europe__Paris);
+        let saturday_night = Utc.with_ymd_and_hms(2026, 10, 24, 21, 0, 0).unwrap();
+        let sunday_morning = Utc.with_ymd_and_hms(2026, 10, 25, 6, 0, 0).unwrap();
+        assert_eq!(window.next_opening(saturday_night), sunday_morning);
+        assert_eq!(
+            window.last_opening(sunday_morning + chrono::Duration::hours(1)),
+            sunday_morning
+        );
+    }
+
+    #[test]
+    fn a_start_skipped_by_summer_time_opens_the_window_once_clocks_went_forward() {
+        // in Paris, 02:30 does not exist on March 29, 2026
+        let window = window("02:30", "04:00", Tz::Europe__Paris);
+        let before = Utc.with_ymd_and_hms(2026, 3, 28, 23, 0, 0).unwrap();
+        assert_eq!(
+            window.next_opening(before),
+            Utc.with_ymd_and_hms(2026, 3, 29, 1, 30, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn times_are_configured_with_or_without_seconds() {
+        let window: SendWindow =
+            serde_json::from_str(r#"{"start": "07:00", "end": "22:30:15"}"#).unwrap();
+        assert_eq!(window.start, NaiveTime::from_hms_opt(7, 0, 0).unwrap());
+        assert_eq!(window.end, NaiveTime::from_hms_opt(22, 30, 15).unwrap());
+        assert_eq!(window.time_zone, Tz::UTC);
+    }
+
+    #[test]
+    fn time_zones_are_configured_by_name() {
+        let window: SendWindow = serde_json::from_str(
+            r#"{"start": "07:00", "end": "22:00", "time_zone": "America/New_York"}"#,
+        )
+        .unwrap();
+        assert_eq!(window.time_zone, Tz::America__New_York);
+        assert!(serde_json::from_str::<SendWindow>(
+            r#"{"start": "07:00", "end": "22:00", "time_zone": "UTC+1"}"#
+        )
+        .is_err());
+    }
+}
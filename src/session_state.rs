@@ -2,30 +2,120 @@ use actix_session::{Session, SessionExt, SessionGetError, SessionInsertError};
 use actix_web::dev::Payload;
 use actix_web::{FromRequest, HttpRequest};
 use std::future::{ready, Ready};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use uuid::Uuid;
 
+/// A session is only authenticated once `user_id` is set. Users with two-factor authentication
+/// enabled are first recorded as pending, until they enter their second factor.
 pub struct TypedSession(Session);
 
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const PASSWORD_VERSION_KEY: &'static str = "password_version";
+    const AUTHENTICATED_AT_KEY: &'static str = "authenticated_at";
+    const PENDING_SECOND_FACTOR_KEY: &'static str = "pending_second_factor_user_id";
+    const PENDING_REMEMBER_ME_KEY: &'static str = "pending_remember_me";
+    const PENDING_NEXT_KEY: &'static str = "pending_next";
+    const TOTP_SETUP_SECRET_KEY: &'static str = "totp_setup_secret";
 
     pub fn renew(&self) {
         self.0.renew();
     }
 
-    pub fn insert_user_id(&self, user_id: Uuid) -> Result<(), SessionInsertError> {
-        self.0.insert(Self::USER_ID_KEY, user_id)
+    /// Authenticates the session, which stays valid until the user's password changes
+    pub fn insert_user_id(
+        &self,
+        user_id: Uuid,
+        password_version: i32,
+    ) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::USER_ID_KEY, user_id)?;
+        self.0.insert(Self::AUTHENTICATED_AT_KEY, unix_now())?;
+        self.insert_password_version(password_version)
+    }
+
+    /// Whether the user logged in more than `ttl` ago, however active the session has been since
+    pub fn is_expired(&self, ttl: Duration) -> Result<bool, SessionGetError> {
+        let authenticated_at: Option<u64> = self.0.get(Self::AUTHENTICATED_AT_KEY)?;
+        Ok(match authenticated_at {
+            Some(authenticated_at) => unix_now().saturating_sub(authenticated_at) >= ttl.as_secs(),
+            // sessions opened before the login time was recorded
+            None => true,
+        })
     }
 
     pub fn get_user_id(&self) -> Result<Option<Uuid>, SessionGetError> {
         self.0.get(Self::USER_ID_KEY)
     }
 
+    /// Keeps the session of a user who changed their own password valid
+    pub fn insert_password_version(&self, password_version: i32) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PASSWORD_VERSION_KEY, password_version)
+    }
+
+    /// Sessions opened before password versions were recorded count as the initial version
+    pub fn get_password_version(&self) -> Result<i32, SessionGetError> {
+        Ok(self.0.get(Self::PASSWORD_VERSION_KEY)?.unwrap_or(0))
+    }
+
+    /// Records a user who entered their password but still has to enter their second factor,
+    /// whether they asked to be remembered, and the page they asked for before logging in
+    pub fn insert_pending_second_factor(
+        &self,
+        user_id: Uuid,
+        remember_me: bool,
+        next: Option<&str>,
+    ) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::PENDING_SECOND_FACTOR_KEY, user_id)?;
+        self.0.insert(Self::PENDING_REMEMBER_ME_KEY, remember_me)?;
+        match next {
+            Some(next) => self.0.insert(Self::PENDING_NEXT_KEY, next),
+            None => Ok(()),
+        }
+    }
+
+    pub fn get_pending_second_factor(&self) -> Result<Option<Uuid>, SessionGetError> {
+        self.0.get(Self::PENDING_SECOND_FACTOR_KEY)
+    }
+
+    pub fn get_pending_remember_me(&self) -> Result<bool, SessionGetError> {
+        Ok(self.0.get(Self::PENDING_REMEMBER_ME_KEY)?.unwrap_or(false))
+    }
+
+    pub fn get_pending_next(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::PENDING_NEXT_KEY)
+    }
+
+    pub fn remove_pending_second_factor(&self) {
+        self.0.remove(Self::PENDING_SECOND_FACTOR_KEY);
+        self.0.remove(Self::PENDING_REMEMBER_ME_KEY);
+        self.0.remove(Self::PENDING_NEXT_KEY);
+    }
+
+    /// Keeps the TOTP secret shown during setup until the user confirms it with a code
+    pub fn insert_totp_setup_secret(&self, secret: &str) -> Result<(), SessionInsertError> {
+        self.0.insert(Self::TOTP_SETUP_SECRET_KEY, secret)
+    }
+
+    pub fn get_totp_setup_secret(&self) -> Result<Option<String>, SessionGetError> {
+        self.0.get(Self::TOTP_SETUP_SECRET_KEY)
+    }
+
+    pub fn remove_totp_setup_secret(&self) {
+        self.0.remove(Self::TOTP_SETUP_SECRET_KEY);
+    }
+
     pub fn log_out(self) {
         self.0.purge()
     }
 }
 
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("The system clock is set before the Unix epoch")
+        .as_secs()
+}
+
 /// Allows us to use `TypedSession` as an actix_web extractor.
 impl FromRequest for TypedSession {
     // this basically says we return the same error returned by
@@ -1,38 +1,119 @@
+use std::fs::File;
+use std::io::BufReader;
 use std::net::TcpListener;
+use std::sync::Arc;
 
+use actix_files::Files;
+use actix_session::config::{BrowserSession, TtlExtensionPolicy};
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
+use actix_web::cookie::time::Duration;
 use actix_web::cookie::Key;
 use actix_web::dev::Server;
+use actix_web::http::header::LOCATION;
+use actix_web::middleware::{Compress, Condition};
 use actix_web::web::Data;
-use actix_web::{web, App, HttpServer};
+use actix_web::{web, App, HttpRequest, HttpResponse, HttpServer};
 use actix_web_flash_messages::storage::CookieMessageStore;
 use actix_web_flash_messages::FlashMessagesFramework;
 use actix_web_lab::middleware::from_fn;
+use anyhow::Context;
+use futures_util::future::try_join;
+use rustls_pemfile::Item;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use tracing_actix_web::TracingLogger;
 
-use crate::authentication::reject_anonymous_users;
-use crate::configuration::{DatabaseSettings, Settings};
+use crate::authentication::{
+    reject_anonymous_users, reject_unauthenticated_api_clients, LoginLockoutPolicy,
+    PasswordHashingPolicy,
+};
+use crate::bot_protection::BotProtection;
+use crate::captcha::CaptchaClient;
+use crate::configuration::{
+    CaptchaSettings, ConfirmationPageSettings, CorsSettings, DatabaseSettings,
+    DeletedSubscriberSettings, EmailFooterSettings, HtmlSanitizationSettings, HttpCachingSettings,
+    IssueDeliverySettings, MediaSettings, MediaStorageKind, PayloadLimitSettings,
+    PostmarkWebhookSettings, SessionSettings, Settings, TlsSettings, UtmSettings,
+};
+use crate::configuration_check::{
+    check_database, check_email_provider, check_media_storage, check_redis, check_secrets,
+    check_urls, ConfigurationCheck, ConfigurationReport,
+};
+use crate::domain::SubscriberNamePolicy;
 use crate::email_client::EmailClient;
+use crate::email_validation::EmailValidator;
+use crate::error_handling::negotiate_error_responses;
+use crate::http_caching::{cache_public_pages, cache_static_assets};
+use crate::idempotency::replay_idempotent_requests;
+use crate::media_storage::MediaStorage;
+use crate::rate_limiting::{rate_limit_requests, RateLimiter};
+use crate::request_metrics::{record_request_metrics, RequestMetrics};
+use crate::reverse_proxy::{resolve_client_info, ProxyAwareRootSpanBuilder, TrustedProxies};
 use crate::routes::{
-    admin_dashboard, change_password, change_password_form, confirm, health_check, home, log_out,
-    login, login_form, publish_newsletter, publish_newsletter_form, subscribe,
+    accept_invitation, accept_invitation_form, add_automation_step, admin_dashboard, api_confirm,
+    api_confirm_subscriber, api_delete_subscriber, api_export_newsletter_issue, api_get_issue,
+    api_get_issue_content, api_import_newsletter_issue, api_json_config, api_list_events,
+    api_list_issues, api_list_subscribers, api_newsletter_analytics, api_path_config,
+    api_publish_newsletter, api_query_config, api_route_not_found, api_save_issue_content,
+    api_subscribe, approve_email_change, automation_sequence, cancel_newsletter_delivery,
+    change_password, change_password_form, confirm, confirm_email_change, confirm_erasure,
+    confirm_subscriber_manually, create_automation, create_email_template, create_newsletter,
+    deactivate_user, delete_subscriber, disable_two_factor_authentication, draft_revision,
+    draft_revisions, duplicate_newsletter_issue, edit_email_template_form, edit_newsletter_draft,
+    edit_newsletter_issue, enable_two_factor_authentication, erase_own_subscription,
+    erase_subscriber_data, export_subscribers, follow_tracked_link, health_check, home,
+    ignore_send_window, invite_user, issues_archive, list_automations, list_deleted_subscribers,
+    list_email_templates, list_newsletter_issues, list_newsletters, list_subscribers, list_users,
+    log_out, login, login_form, media_library, metrics, newsletter_analytics,
+    newsletter_delivery_status, pause_newsletter_delivery, postmark_webhook, preview_newsletter,
+    publish_newsletter, publish_newsletter_form, request_email_change, resend_confirmation,
+    restore_draft_revision, restore_subscriber, resume_newsletter_delivery,
+    retry_failed_deliveries, save_newsletter_draft, security_settings, send_test_newsletter,
+    set_subscriber_time_zone, subscribe, subscriber_details, subscriber_stats,
+    subscription_pending, track_email_open, two_factor_form, unsubscribe, update_email_template,
+    upload_media, verify_two_factor, view_issue, worker_health, worker_metrics,
 };
+use crate::subscription_tokens::SubscriptionTokens;
+use crate::templates::{Branding, ConfirmationEmailTemplate};
+use crate::url_builder::UrlBuilder;
 
 /// Holds the running server and its port
 pub struct Application {
     port: u16,
     server: Server,
+    /// Redirects plain HTTP requests when the application serves HTTPS
+    http_redirect: Option<(u16, Server)>,
 }
 
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, anyhow::Error> {
+        let email_client = Arc::new(configuration.email_client.clone().client());
+        Self::build_with_email_client(configuration, email_client).await
+    }
+
+    /// Like `build`, sending through an email client shared with a worker running in the same
+    /// process: both then go through the same circuit breaker, and `/metrics` counts the
+    /// retries and trips of the worker too
+    pub async fn build_with_email_client(
+        configuration: Settings,
+        email_client: Arc<EmailClient>,
+    ) -> Result<Self, anyhow::Error> {
         let connection_pool = get_connection_pool(&configuration.database);
 
-        let email_client = configuration.email_client.client();
+        let confirmation_email =
+            ConfirmationEmailTemplate::load(&configuration.confirmation_email)?;
+        let bot_protection = configuration
+            .bot_protection
+            .bot_protection(configuration.application.hmac_secret.clone());
+        let captcha = configuration.captcha.map(CaptchaSettings::client);
+        let email_validator = configuration.email_validation.validator()?;
+        configuration.cors.validate()?;
+        let rate_limiter = configuration
+            .rate_limiting
+            .limiter(&configuration.redis_uri)
+            .await?;
 
         let address = format!(
             "{}:{}",
@@ -40,49 +121,272 @@ impl Application {
         );
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
+        let tls = configuration
+            .application
+            .tls
+            .as_ref()
+            .map(load_rustls_config)
+            .transpose()?;
+        let http_redirect = match configuration
+            .application
+            .tls
+            .as_ref()
+            .and_then(|tls| tls.http_redirect_port)
+        {
+            Some(redirect_port) => {
+                let listener = TcpListener::bind(format!(
+                    "{}:{}",
+                    configuration.application.host, redirect_port
+                ))?;
+                let redirect_port = listener.local_addr().unwrap().port();
+                let server =
+                    run_https_redirect(listener, configuration.application.base_url.clone())?;
+                Some((redirect_port, server))
+            }
+            None => None,
+        };
         let server = run(
             listener,
+            tls,
             connection_pool,
+            configuration.database.max_connections,
             email_client,
+            confirmation_email,
+            configuration.confirmation_page,
+            configuration.cors,
+            configuration.branding,
+            configuration.application.static_dir,
+            configuration.application.trusted_proxies,
+            bot_protection,
+            captcha,
+            email_validator,
+            configuration.subscriber_names,
+            configuration.deleted_subscribers,
+            configuration.payload_limits,
+            configuration.http_caching,
+            configuration.issue_delivery,
+            configuration.postmark_webhook,
+            configuration.utm,
+            configuration.email_footer,
+            configuration.html_sanitization,
+            configuration.media,
+            rate_limiter,
+            configuration.login_lockout,
+            configuration.password_hashing,
+            configuration.session,
             configuration.application.base_url,
             configuration.application.hmac_secret,
             configuration.redis_uri,
         )
         .await?;
-        Ok(Self { port, server })
+        Ok(Self {
+            port,
+            server,
+            http_redirect,
+        })
+    }
+
+    /// Runs every check of the configuration without starting the server, including whether
+    /// the database and Redis can be reached
+    pub async fn check_configuration(configuration: &Settings) -> ConfigurationReport {
+        let checks = vec![
+            ConfigurationCheck::new("Secrets", check_secrets(configuration)),
+            ConfigurationCheck::new("URLs", check_urls(configuration)),
+            ConfigurationCheck::new("CORS", configuration.cors.validate()),
+            ConfigurationCheck::new("Email provider", check_email_provider(configuration)),
+            ConfigurationCheck::new("Media storage", check_media_storage(configuration)),
+            ConfigurationCheck::new(
+                "Confirmation email templates",
+                ConfirmationEmailTemplate::load(&configuration.confirmation_email).map(|_| ()),
+            ),
+            ConfigurationCheck::new("Database", check_database(configuration).await),
+            ConfigurationCheck::new("Redis", check_redis(configuration).await),
+        ];
+        ConfigurationReport { checks }
     }
 
     pub fn port(&self) -> u16 {
         self.port
     }
 
+    /// The port of the HTTP to HTTPS redirect, if any
+    pub fn http_redirect_port(&self) -> Option<u16> {
+        self.http_redirect.as_ref().map(|(port, _)| *port)
+    }
+
     /// This function runs the server and returns only when the application is stopped
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+        match self.http_redirect {
+            Some((_, redirect)) => try_join(self.server, redirect).await.map(|_| ()),
+            None => self.server.await,
+        }
     }
 }
 
 pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
     PgPoolOptions::new()
-        .acquire_timeout(std::time::Duration::from_secs(5))
+        .max_connections(configuration.max_connections)
+        .min_connections(configuration.min_connections)
+        .acquire_timeout(configuration.acquire_timeout())
+        .idle_timeout(configuration.idle_timeout())
         .connect_lazy_with(configuration.with_db())
 }
 
-// Need a wrapper type here in order to retrieve the base url in an actix extractor.
-// Actix extractors are type-based, so we need a unique type to try to extract.
-pub struct ApplicationBaseUrl(pub String);
+/// Reads the certificate chain and private key used to serve HTTPS
+fn load_rustls_config(settings: &TlsSettings) -> Result<rustls::ServerConfig, anyhow::Error> {
+    let certificates = rustls_pemfile::certs(&mut open_pem(&settings.certificate_path)?)
+        .context("Failed to parse the TLS certificate")?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect();
+    let private_key = rustls_pemfile::read_all(&mut open_pem(&settings.private_key_path)?)
+        .context("Failed to parse the TLS private key")?
+        .into_iter()
+        .find_map(|item| match item {
+            Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key) => {
+                Some(rustls::PrivateKey(key))
+            }
+            _ => None,
+        })
+        .with_context(|| format!("No private key found in {}", settings.private_key_path))?;
+    rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certificates, private_key)
+        .context("Invalid TLS certificate or private key")
+}
+
+fn open_pem(path: &str) -> Result<BufReader<File>, anyhow::Error> {
+    let file = File::open(path).with_context(|| format!("Failed to open {}", path))?;
+    Ok(BufReader::new(file))
+}
 
+/// Serves permanent redirects to the same path under `base_url`, the HTTPS address of the
+/// application
+fn run_https_redirect(listener: TcpListener, base_url: String) -> Result<Server, std::io::Error> {
+    let urls = web::Data::new(UrlBuilder::new(base_url));
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(TracingLogger::default())
+            .default_service(web::to(redirect_to_https))
+            .app_data(urls.clone())
+    })
+    .listen(listener)?
+    .run();
+    Ok(server)
+}
+
+/// Serves the `/metrics` of a worker running in a process of its own, which has no API to report
+/// the counters of its email client
+pub fn run_worker_metrics(
+    listener: TcpListener,
+    email_client: Arc<EmailClient>,
+) -> Result<Server, std::io::Error> {
+    let email_client = web::Data::from(email_client);
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(TracingLogger::default())
+            .route("/metrics", web::get().to(worker_metrics))
+            .app_data(email_client.clone())
+    })
+    .listen(listener)?
+    .run();
+    Ok(server)
+}
+
+async fn redirect_to_https(request: HttpRequest, urls: web::Data<UrlBuilder>) -> HttpResponse {
+    let path = request
+        .uri()
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+    // 308 rather than 301, so that forms are posted again rather than turned into a GET
+    HttpResponse::PermanentRedirect()
+        .insert_header((LOCATION, urls.url(path)))
+        .finish()
+}
+
+/// The size limit of the connection pool, reported by the metrics endpoint
+pub struct MaxDatabaseConnections(pub u32);
+
+#[allow(clippy::too_many_arguments)]
 async fn run(
     listener: TcpListener,
+    tls: Option<rustls::ServerConfig>,
     connection_pool: PgPool,
-    email_client: EmailClient,
+    max_connections: u32,
+    email_client: Arc<EmailClient>,
+    confirmation_email: ConfirmationEmailTemplate,
+    confirmation_page: ConfirmationPageSettings,
+    cors: CorsSettings,
+    branding: Branding,
+    static_dir: String,
+    trusted_proxies: TrustedProxies,
+    bot_protection: BotProtection,
+    captcha: Option<CaptchaClient>,
+    email_validator: EmailValidator,
+    name_policy: SubscriberNamePolicy,
+    deleted_subscribers: DeletedSubscriberSettings,
+    payload_limits: PayloadLimitSettings,
+    http_caching: HttpCachingSettings,
+    issue_delivery: IssueDeliverySettings,
+    postmark_webhook_settings: Option<PostmarkWebhookSettings>,
+    utm_settings: Option<UtmSettings>,
+    email_footer: Option<EmailFooterSettings>,
+    html_sanitization: HtmlSanitizationSettings,
+    media: MediaSettings,
+    rate_limiter: RateLimiter,
+    login_lockout: LoginLockoutPolicy,
+    password_hashing: PasswordHashingPolicy,
+    session_settings: SessionSettings,
     base_url: String,
     hmac_secret: Secret<String>,
     redis_uri: Secret<String>,
 ) -> Result<Server, anyhow::Error> {
     let connection_pool = web::Data::new(connection_pool);
-    let email_client = web::Data::new(email_client);
-    let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let max_connections = web::Data::new(MaxDatabaseConnections(max_connections));
+    let request_metrics = web::Data::new(RequestMetrics::default());
+    let email_client = web::Data::from(email_client);
+    let confirmation_email = web::Data::new(confirmation_email);
+    let confirmation_page = web::Data::new(confirmation_page);
+    let deleted_subscribers = web::Data::new(deleted_subscribers);
+    // extractor configs are not `Send`: they are built by each worker from the limits
+    let (form_limit, json_limit) = (payload_limits.form_bytes, payload_limits.json_bytes);
+    let payload_limits = web::Data::new(payload_limits);
+    let http_caching = web::Data::new(http_caching);
+    let issue_delivery = web::Data::new(issue_delivery);
+    let postmark_webhook_settings = web::Data::new(postmark_webhook_settings);
+    let utm_settings = web::Data::new(utm_settings);
+    let email_footer = web::Data::new(email_footer);
+    let html_sanitization = web::Data::new(html_sanitization);
+    // images kept on disk are served by the application itself
+    let media_directory = match media.storage {
+        MediaStorageKind::Disk => {
+            std::fs::create_dir_all(&media.directory)?;
+            Some(media.directory.clone())
+        }
+        MediaStorageKind::S3 => None,
+    };
+    let media_storage = web::Data::new(MediaStorage::new(&media, UrlBuilder::new(&base_url)));
+    let media = web::Data::new(media);
+    let branding = web::Data::new(branding);
+    let bot_protection = web::Data::new(bot_protection);
+    let captcha = web::Data::new(captcha);
+    let email_validator = web::Data::new(email_validator);
+    let name_policy = web::Data::new(name_policy);
+    let rate_limiter = web::Data::new(rate_limiter);
+    let trusted_proxies = web::Data::new(trusted_proxies);
+    let subscription_tokens = web::Data::new(SubscriptionTokens::new(hmac_secret.clone()));
+    let login_lockout = web::Data::new(login_lockout);
+    let password_hashing = web::Data::new(password_hashing);
+    // sessions are kept alive by activity, up to the TTL checked by the authentication middleware
+    let session_lifecycle = BrowserSession::default()
+        .state_ttl(Duration::seconds(
+            session_settings.idle_timeout_seconds as i64,
+        ))
+        .state_ttl_extension_policy(TtlExtensionPolicy::OnEveryRequest);
+    let session_settings = web::Data::new(session_settings);
+    let urls = web::Data::new(UrlBuilder::new(base_url));
 
     let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
 
@@ -95,34 +399,313 @@ async fn run(
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
     let server = HttpServer::new(move || {
         App::new()
+            .wrap(from_fn(negotiate_error_responses))
             .wrap(message_framework.clone())
-            .wrap(SessionMiddleware::new(
-                redis_store.clone(),
-                secret_key.clone(),
-            ))
-            .wrap(TracingLogger::default())
+            .wrap(
+                SessionMiddleware::builder(redis_store.clone(), secret_key.clone())
+                    .session_lifecycle(session_lifecycle.clone())
+                    .build(),
+            )
+            .wrap(from_fn(rate_limit_requests))
+            .wrap(from_fn(record_request_metrics))
+            .wrap(TracingLogger::<ProxyAwareRootSpanBuilder>::new())
+            .wrap(from_fn(resolve_client_info))
+            .service(
+                web::scope("/static")
+                    .wrap(from_fn(cache_static_assets))
+                    .wrap(Compress::default())
+                    .service(Files::new("", &static_dir)),
+            )
+            .configure(|config| {
+                if let Some(media_directory) = &media_directory {
+                    config.service(Files::new("/media", media_directory));
+                }
+            })
             .route("/health_check", web::get().to(health_check))
+            .route("/health/worker", web::get().to(worker_health))
+            .route("/metrics", web::get().to(metrics))
             .route("/subscriptions", web::post().to(subscribe))
+            .route(
+                "/subscriptions/pending",
+                web::get().to(subscription_pending),
+            )
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route("/subscriptions/unsubscribe", web::get().to(unsubscribe))
+            .route(
+                "/subscriptions/erase",
+                web::post().to(erase_own_subscription),
+            )
+            .route(
+                "/subscriptions/erase/confirm",
+                web::get().to(confirm_erasure),
+            )
+            .route(
+                "/subscriptions/resend-confirmation",
+                web::post().to(resend_confirmation),
+            )
+            .route(
+                "/subscriptions/change-email",
+                web::post().to(request_email_change),
+            )
+            .route(
+                "/subscriptions/change-email/approve",
+                web::get().to(approve_email_change),
+            )
+            .route(
+                "/subscriptions/change-email/confirm",
+                web::get().to(confirm_email_change),
+            )
+            .route(
+                "/subscriptions/time-zone",
+                web::post().to(set_subscriber_time_zone),
+            )
+            .route(
+                "/users/accept-invitation",
+                web::get().to(accept_invitation_form),
+            )
+            .route(
+                "/users/accept-invitation",
+                web::post().to(accept_invitation),
+            )
             .route("/login", web::get().to(login_form))
             .route("/login", web::post().to(login))
+            .route("/login/two-factor", web::get().to(two_factor_form))
+            .route("/login/two-factor", web::post().to(verify_two_factor))
             .route("/", web::get().to(home))
+            .service(
+                web::scope("/issues")
+                    .wrap(from_fn(cache_public_pages))
+                    .wrap(Compress::default())
+                    .route("", web::get().to(issues_archive))
+                    .route("/{issue_id}", web::get().to(view_issue)),
+            )
+            .route(
+                "/t/click/{delivery_id}/{link_id}",
+                web::get().to(follow_tracked_link),
+            )
+            .route("/t/open/{delivery_id}", web::get().to(track_email_open))
+            .route("/webhooks/postmark", web::post().to(postmark_webhook))
             .service(
                 web::scope("/admin")
                     .wrap(from_fn(reject_anonymous_users))
                     .route("/dashboard", web::get().to(admin_dashboard))
                     .route("/password", web::get().to(change_password_form))
                     .route("/password", web::post().to(change_password))
+                    .route("/security", web::get().to(security_settings))
+                    .route(
+                        "/security/two-factor",
+                        web::post().to(enable_two_factor_authentication),
+                    )
+                    .route(
+                        "/security/two-factor/disable",
+                        web::post().to(disable_two_factor_authentication),
+                    )
                     .route("/logout", web::post().to(log_out))
+                    .route("/subscribers", web::get().to(list_subscribers))
+                    .route("/api/stats/subscribers", web::get().to(subscriber_stats))
+                    .route("/lists", web::get().to(list_newsletters))
+                    .route("/lists", web::post().to(create_newsletter))
+                    .route("/media", web::get().to(media_library))
+                    .route("/media", web::post().to(upload_media))
+                    .route("/templates", web::get().to(list_email_templates))
+                    .route("/templates", web::post().to(create_email_template))
+                    .route(
+                        "/templates/{email_template_id}",
+                        web::get().to(edit_email_template_form),
+                    )
+                    .route(
+                        "/templates/{email_template_id}",
+                        web::post().to(update_email_template),
+                    )
+                    .route("/automations", web::get().to(list_automations))
+                    .route("/automations", web::post().to(create_automation))
+                    .route(
+                        "/automations/{sequence_id}",
+                        web::get().to(automation_sequence),
+                    )
+                    .route(
+                        "/automations/{sequence_id}/steps",
+                        web::post().to(add_automation_step),
+                    )
+                    .route("/users", web::get().to(list_users))
+                    .route("/users", web::post().to(invite_user))
+                    .route(
+                        "/users/{user_id}/deactivate",
+                        web::post().to(deactivate_user),
+                    )
+                    .route("/subscribers/export", web::get().to(export_subscribers))
+                    .route(
+                        "/subscribers/deleted",
+                        web::get().to(list_deleted_subscribers),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::get().to(subscriber_details),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}",
+                        web::delete().to(erase_subscriber_data),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/delete",
+                        web::post().to(delete_subscriber),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/confirm",
+                        web::post().to(confirm_subscriber_manually),
+                    )
+                    .route(
+                        "/subscribers/{subscriber_id}/restore",
+                        web::post().to(restore_subscriber),
+                    )
                     .route("/newsletters", web::post().to(publish_newsletter))
-                    .route("/newsletters", web::get().to(publish_newsletter_form)),
+                    .route("/newsletters", web::get().to(publish_newsletter_form))
+                    .route("/newsletters/issues", web::get().to(list_newsletter_issues))
+                    .route("/newsletters/drafts", web::post().to(save_newsletter_draft))
+                    .route("/newsletters/preview", web::post().to(preview_newsletter))
+                    .route(
+                        "/newsletters/test-send",
+                        web::post().to(send_test_newsletter),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}",
+                        web::put().to(edit_newsletter_issue),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}",
+                        web::get().to(edit_newsletter_draft),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}/revisions",
+                        web::get().to(draft_revisions),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}/revisions/{revision_id}",
+                        web::get().to(draft_revision),
+                    )
+                    .route(
+                        "/newsletters/drafts/{draft_id}/revisions/{revision_id}/restore",
+                        web::post().to(restore_draft_revision),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/status",
+                        web::get().to(newsletter_delivery_status),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/analytics",
+                        web::get().to(newsletter_analytics),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/pause",
+                        web::post().to(pause_newsletter_delivery),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/resume",
+                        web::post().to(resume_newsletter_delivery),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/cancel",
+                        web::post().to(cancel_newsletter_delivery),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/ignore-send-window",
+                        web::post().to(ignore_send_window),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/retry-failures",
+                        web::post().to(retry_failed_deliveries),
+                    )
+                    .route(
+                        "/newsletters/{issue_id}/duplicate",
+                        web::post().to(duplicate_newsletter_issue),
+                    ),
+            )
+            .service(
+                web::scope("/api/v1")
+                    .wrap(Condition::new(cors.is_enabled(), cors.cors()))
+                    .app_data(api_json_config(json_limit))
+                    .app_data(api_query_config())
+                    .app_data(api_path_config())
+                    .route("/subscriptions", web::post().to(api_subscribe))
+                    .route("/subscriptions/confirm", web::post().to(api_confirm))
+                    .route("/issues", web::get().to(api_list_issues))
+                    .route("/issues/{issue_id}", web::get().to(api_get_issue))
+                    .service(
+                        web::resource("/events")
+                            .wrap(from_fn(reject_unauthenticated_api_clients))
+                            .route(web::get().to(api_list_events)),
+                    )
+                    .service(
+                        web::resource("/newsletters/{issue_id}/content")
+                            .wrap(from_fn(reject_unauthenticated_api_clients))
+                            .route(web::get().to(api_get_issue_content))
+                            .route(web::put().to(api_save_issue_content)),
+                    )
+                    .service(
+                        web::scope("/admin")
+                            .wrap(from_fn(replay_idempotent_requests))
+                            .wrap(from_fn(reject_unauthenticated_api_clients))
+                            .route("/newsletters", web::post().to(api_publish_newsletter))
+                            .route(
+                                "/newsletters/import",
+                                web::post().to(api_import_newsletter_issue),
+                            )
+                            .route(
+                                "/newsletters/{issue_id}/export",
+                                web::get().to(api_export_newsletter_issue),
+                            )
+                            .route(
+                                "/newsletters/{issue_id}/analytics",
+                                web::get().to(api_newsletter_analytics),
+                            )
+                            .route("/subscribers", web::get().to(api_list_subscribers))
+                            .route(
+                                "/subscribers/{subscriber_id}",
+                                web::delete().to(api_delete_subscriber),
+                            )
+                            .route(
+                                "/subscribers/{subscriber_id}/confirm",
+                                web::post().to(api_confirm_subscriber),
+                            ),
+                    )
+                    .default_service(web::to(api_route_not_found)),
             )
             .app_data(connection_pool.clone())
+            .app_data(max_connections.clone())
+            .app_data(request_metrics.clone())
             .app_data(email_client.clone())
-            .app_data(base_url.clone())
+            .app_data(confirmation_email.clone())
+            .app_data(confirmation_page.clone())
+            .app_data(deleted_subscribers.clone())
+            .app_data(branding.clone())
+            .app_data(bot_protection.clone())
+            .app_data(captcha.clone())
+            .app_data(email_validator.clone())
+            .app_data(name_policy.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(login_lockout.clone())
+            .app_data(password_hashing.clone())
+            .app_data(payload_limits.clone())
+            .app_data(http_caching.clone())
+            .app_data(issue_delivery.clone())
+            .app_data(postmark_webhook_settings.clone())
+            .app_data(utm_settings.clone())
+            .app_data(email_footer.clone())
+            .app_data(html_sanitization.clone())
+            .app_data(media.clone())
+            .app_data(media_storage.clone())
+            .app_data(web::FormConfig::default().limit(form_limit))
+            .app_data(web::JsonConfig::default().limit(json_limit))
+            .app_data(session_settings.clone())
+            .app_data(urls.clone())
+            .app_data(subscription_tokens.clone())
             .app_data(Data::new(HmacSecret(hmac_secret.clone())))
-    })
-    .listen(listener)?
+    });
+    let server = match tls {
+        Some(tls) => server.listen_rustls(listener, tls)?,
+        None => server.listen(listener)?,
+    }
     .run();
     Ok(server)
 }
@@ -0,0 +1,42 @@
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::routes::erase_subscriber;
+
+/// Erases the subscribers deleted more than `retention` ago, returning how many were purged
+#[tracing::instrument(
+    name = "Purge deleted subscribers",
+    skip(pool),
+    fields(subscribers_purged = tracing::field::Empty)
+)]
+pub async fn purge_deleted_subscribers(
+    pool: &PgPool,
+    retention: chrono::Duration,
+) -> Result<u64, anyhow::Error> {
+    let subscriber_ids = get_expired_subscriber_ids(pool, retention).await?;
+    let mut subscribers_purged = 0;
+    for subscriber_id in subscriber_ids {
+        if erase_subscriber(pool, subscriber_id, None).await? {
+            subscribers_purged += 1;
+        }
+    }
+    tracing::Span::current().record("subscribers_purged", subscribers_purged);
+    Ok(subscribers_purged)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_expired_subscriber_ids(
+    pool: &PgPool,
+    retention: chrono::Duration,
+) -> Result<Vec<Uuid>, anyhow::Error> {
+    let deleted_before = chrono::Utc::now() - retention;
+    let subscriber_ids = sqlx::query_scalar!(
+        "SELECT id FROM subscriptions WHERE deleted_at < $1",
+        deleted_before
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to retrieve the subscribers to purge.")?;
+    Ok(subscriber_ids)
+}
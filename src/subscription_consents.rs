@@ -0,0 +1,154 @@
+use std::net::IpAddr;
+
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use reqwest::Url;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+const MAX_CONSENT_TEXT_VERSION_LENGTH: usize = 64;
+const MAX_SOURCE_URL_LENGTH: usize = 2048;
+
+/// How a subscriber opted in, recorded when they sign up so that their consent can be proven
+#[derive(Debug, Default)]
+pub struct Consent {
+    pub ip_address: Option<String>,
+    /// Which wording of the consent text the form showed, e.g. one per country
+    pub consent_text_version: Option<String>,
+    /// ISO 3166-1 alpha-2 code of the country whose consent rules the form applies
+    pub country: Option<String>,
+    /// The page the subscriber signed up on
+    pub source_url: Option<String>,
+}
+
+impl Consent {
+    /// Validates what the form or API client tells about the consent. Empty fields are left out.
+    pub fn parse(
+        ip_address: Option<IpAddr>,
+        consent_text_version: &str,
+        country: &str,
+        source_url: &str,
+    ) -> Result<Self, String> {
+        let consent_text_version = consent_text_version.trim();
+        if consent_text_version.chars().count() > MAX_CONSENT_TEXT_VERSION_LENGTH {
+            return Err(format!(
+                "The consent text version must be at most {} characters.",
+                MAX_CONSENT_TEXT_VERSION_LENGTH
+            ));
+        }
+        let country = country.trim();
+        let is_country_code =
+            country.len() == 2 && country.chars().all(|c| c.is_ascii_alphabetic());
+        if !(country.is_empty() || is_country_code) {
+            return Err("The country must be a two-letter ISO 3166 code.".into());
+        }
+        let source_url = source_url.trim();
+        if !source_url.is_empty() && !is_web_url(source_url) {
+            return Err("The source URL must be an http or https URL.".into());
+        }
+        Ok(Self {
+            ip_address: ip_address.map(|ip| ip.to_string()),
+            consent_text_version: non_empty(consent_text_version),
+            country: non_empty(country).map(|country| country.to_ascii_uppercase()),
+            source_url: non_empty(source_url),
+        })
+    }
+}
+
+/// Whether `url` can be kept as the page a subscriber signed up on
+pub fn is_web_url(url: &str) -> bool {
+    url.len() <= MAX_SOURCE_URL_LENGTH
+        && Url::parse(url).map_or(false, |url| matches!(url.scheme(), "http" | "https"))
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    (!value.is_empty()).then(|| value.to_owned())
+}
+
+/// A consent as it was recorded
+#[derive(Debug)]
+pub struct RecordedConsent {
+    pub consented_at: DateTime<Utc>,
+    pub ip_address: Option<String>,
+    pub consent_text_version: Option<String>,
+    pub country: Option<String>,
+    pub source_url: Option<String>,
+}
+
+/// Stores the consent given by a subscriber signing up. It must be called in the same transaction
+/// as the signup, so that every subscriber has the proof of their opt-in.
+#[tracing::instrument(
+    name = "Record the consent of a subscriber",
+    skip(transaction, consent)
+)]
+pub async fn record_consent(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    consent: &Consent,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_consents (
+            consent_id, subscriber_id, consented_at, ip_address, consent_text_version, country,
+            source_url
+        )
+        VALUES ($1, $2, now(), $3, $4, $5, $6)
+        "#,
+        Uuid::new_v4(),
+        subscriber_id,
+        consent.ip_address,
+        consent.consent_text_version,
+        consent.country,
+        consent.source_url
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Returns every consent given by a subscriber, the most recent first
+#[tracing::instrument(name = "Get the consents of a subscriber", skip(pool))]
+pub async fn get_consents(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<RecordedConsent>, anyhow::Error> {
+    let consents = sqlx::query_as!(
+        RecordedConsent,
+        r#"
+        SELECT consented_at, ip_address, consent_text_version, country, source_url
+        FROM subscription_consents
+        WHERE subscriber_id = $1
+        ORDER BY consented_at DESC
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve the consents of a subscriber.")?;
+    Ok(consents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Consent;
+
+    #[test]
+    fn countries_must_be_two_letter_codes() {
+        let consent = Consent::parse(None, "", "de", "").unwrap();
+        assert_eq!(consent.country.as_deref(), Some("DE"));
+        assert!(Consent::parse(None, "", "Germany", "").is_err());
+        assert!(Consent::parse(None, "", "4x", "").is_err());
+    }
+
+    #[test]
+    fn source_urls_must_be_web_pages() {
+        let consent = Consent::parse(None, "v1", "", "https://example.com/blog").unwrap();
+        assert_eq!(
+            consent.source_url.as_deref(),
+            Some("https://example.com/blog")
+        );
+        assert_eq!(consent.consent_text_version.as_deref(), Some("v1"));
+        assert!(Consent::parse(None, "", "", "javascript:alert(1)").is_err());
+        assert!(Consent::parse(None, "", "", "not a url").is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::SubscriptionStatus;
+
+/// A change of the status of a subscriber
+#[derive(Debug)]
+pub struct StatusChange {
+    /// `None` for the creation of the subscriber
+    pub from_status: Option<SubscriptionStatus>,
+    pub to_status: SubscriptionStatus,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// Appends a change of status to the history of a subscriber. It must be called in the same
+/// transaction as the change, so that the history always matches the subscriptions table.
+#[tracing::instrument(name = "Record subscription status change", skip(transaction))]
+pub async fn record_status_change(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    from_status: Option<SubscriptionStatus>,
+    to_status: SubscriptionStatus,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscription_events (subscriber_id, from_status, to_status)
+        VALUES ($1, $2, $3)
+        "#,
+        subscriber_id,
+        from_status.map(|status| status.as_str()),
+        to_status.as_str()
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+/// Reads a status returned by a query updating the status of a subscriber
+pub fn parse_previous_status(status: String) -> Result<SubscriptionStatus, sqlx::Error> {
+    SubscriptionStatus::try_from(status).map_err(|e| sqlx::Error::Decode(e.into()))
+}
+
+/// Returns every change of status of a subscriber, the oldest first. The history outlives the
+/// subscriber, so it is still available once they have been erased.
+#[tracing::instrument(name = "Get subscription history", skip(pool))]
+pub async fn get_subscription_history(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<StatusChange>, anyhow::Error> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT from_status, to_status, occurred_at
+        FROM subscription_events
+        WHERE subscriber_id = $1
+        ORDER BY subscription_event_id
+        "#,
+        subscriber_id
+    )
+    .fetch_all(pool)
+    .await
+    .context("Failed to perform a query to retrieve the subscription history.")?;
+    rows.into_iter()
+        .map(|row| {
+            Ok(StatusChange {
+                from_status: row
+                    .from_status
+                    .map(SubscriptionStatus::try_from)
+                    .transpose()
+                    .map_err(anyhow::Error::msg)?,
+                to_status: SubscriptionStatus::try_from(row.to_status)
+                    .map_err(anyhow::Error::msg)?,
+                occurred_at: row.occurred_at,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,244 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// How long a confirmation link stays valid
+pub const SUBSCRIPTION_TOKEN_TTL_DAYS: i64 = 7;
+
+/// How long the link confirming that subscribers want their data erased stays valid
+pub const ERASURE_TOKEN_TTL_HOURS: i64 = 24;
+
+/// What a token lets its holder do: a token issued for one purpose is rejected for the others
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TokenPurpose {
+    /// Sent in confirmation emails, expires after `SUBSCRIPTION_TOKEN_TTL_DAYS`
+    Confirmation,
+    /// Sent with every issue to unsubscribe, or to ask to change address or erase their data;
+    /// never expires
+    Unsubscribe,
+    /// Sent to the subscriber's address to confirm they want their data erased, expires after
+    /// `ERASURE_TOKEN_TTL_HOURS`
+    Erasure,
+}
+
+impl TokenPurpose {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TokenPurpose::Confirmation => "confirmation",
+            TokenPurpose::Unsubscribe => "unsubscribe",
+            TokenPurpose::Erasure => "erasure",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum TokenError {
+    /// Malformed, forged, or issued for another purpose
+    Invalid,
+    Expired,
+}
+
+/// Issues and verifies the tokens of subscription links.
+///
+/// A token is the id of the subscriber and the expiry of the link, signed along with the purpose
+/// of the link: checking it does not need the database. Tokens issued before signed tokens were
+/// introduced are random strings stored in `subscription_tokens`, without dots.
+#[derive(Clone)]
+pub struct SubscriptionTokens {
+    hmac_secret: Secret<String>,
+}
+
+impl SubscriptionTokens {
+    pub fn new(hmac_secret: Secret<String>) -> Self {
+        Self { hmac_secret }
+    }
+
+    /// Issues the token of a confirmation link sent at `now`
+    pub fn confirmation_token(&self, subscriber_id: Uuid, now: DateTime<Utc>) -> String {
+        let expires_at = now + chrono::Duration::days(SUBSCRIPTION_TOKEN_TTL_DAYS);
+        self.issue(
+            subscriber_id,
+            TokenPurpose::Confirmation,
+            expires_at.timestamp(),
+        )
+    }
+
+    /// Issues the token of the link confirming an erasure, sent at `now`
+    pub fn erasure_token(&self, subscriber_id: Uuid, now: DateTime<Utc>) -> String {
+        let expires_at = now + chrono::Duration::hours(ERASURE_TOKEN_TTL_HOURS);
+        self.issue(subscriber_id, TokenPurpose::Erasure, expires_at.timestamp())
+    }
+
+    /// Issues the token of the unsubscribe links of a subscriber
+    pub fn unsubscribe_token(&self, subscriber_id: Uuid) -> String {
+        // 0 stands for no expiry: issues stay in inboxes for years
+        self.issue(subscriber_id, TokenPurpose::Unsubscribe, 0)
+    }
+
+    /// Returns the subscriber a token was issued to, if it was signed by us for `purpose` and has
+    /// not expired
+    pub fn verify(
+        &self,
+        token: &str,
+        purpose: TokenPurpose,
+        now: DateTime<Utc>,
+    ) -> Result<Uuid, TokenError> {
+        let mut parts = token.splitn(3, '.');
+        let (Some(subscriber_id), Some(expires_at), Some(signature)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(TokenError::Invalid);
+        };
+        let subscriber_id = Uuid::try_parse(subscriber_id).map_err(|_| TokenError::Invalid)?;
+        let expires_at: i64 = expires_at.parse().map_err(|_| TokenError::Invalid)?;
+        let signature = hex::decode(signature).map_err(|_| TokenError::Invalid)?;
+        self.mac(subscriber_id, purpose, expires_at)
+            .verify_slice(&signature)
+            .map_err(|_| TokenError::Invalid)?;
+        if expires_at != 0 && expires_at < now.timestamp() {
+            return Err(TokenError::Expired);
+        }
+        Ok(subscriber_id)
+    }
+
+    fn issue(&self, subscriber_id: Uuid, purpose: TokenPurpose, expires_at: i64) -> String {
+        let signature = self
+            .mac(subscriber_id, purpose, expires_at)
+            .finalize()
+            .into_bytes();
+        format!(
+            "{}.{}.{}",
+            subscriber_id.simple(),
+            expires_at,
+            hex::encode(signature)
+        )
+    }
+
+    fn mac(&self, subscriber_id: Uuid, purpose: TokenPurpose, expires_at: i64) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.hmac_secret.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size");
+        mac.update(b"subscription-token:");
+        mac.update(purpose.as_str().as_bytes());
+        mac.update(b":");
+        mac.update(subscriber_id.as_bytes());
+        mac.update(b":");
+        mac.update(expires_at.to_string().as_bytes());
+        mac
+    }
+}
+
+/// Whether a token is a signed one rather than one stored in `subscription_tokens`
+pub fn is_signed_token(token: &str) -> bool {
+    token.contains('.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_signed_token, SubscriptionTokens, TokenError, TokenPurpose};
+    use chrono::{Duration, Utc};
+    use claims::{assert_err_eq, assert_ok_eq};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn tokens(secret: &str) -> SubscriptionTokens {
+        SubscriptionTokens::new(Secret::new(secret.to_string()))
+    }
+
+    #[test]
+    fn tokens_are_verified_for_their_purpose() {
+        let tokens = tokens("secret");
+        let subscriber_id = Uuid::new_v4();
+        let now = Utc::now();
+        let confirmation = tokens.confirmation_token(subscriber_id, now);
+        let unsubscribe = tokens.unsubscribe_token(subscriber_id);
+        assert!(is_signed_token(&confirmation));
+        assert_ok_eq!(
+            tokens.verify(&confirmation, TokenPurpose::Confirmation, now),
+            subscriber_id
+        );
+        assert_ok_eq!(
+            tokens.verify(&unsubscribe, TokenPurpose::Unsubscribe, now),
+            subscriber_id
+        );
+        assert_err_eq!(
+            tokens.verify(&unsubscribe, TokenPurpose::Confirmation, now),
+            TokenError::Invalid
+        );
+    }
+
+    #[test]
+    fn confirmation_tokens_expire() {
+        let tokens = tokens("secret");
+        let sent_at = Utc::now();
+        let token = tokens.confirmation_token(Uuid::new_v4(), sent_at);
+        assert_err_eq!(
+            tokens.verify(
+                &token,
+                TokenPurpose::Confirmation,
+                sent_at + Duration::days(8)
+            ),
+            TokenError::Expired
+        );
+    }
+
+    #[test]
+    fn erasure_tokens_expire_after_a_day() {
+        let tokens = tokens("secret");
+        let subscriber_id = Uuid::new_v4();
+        let sent_at = Utc::now();
+        let token = tokens.erasure_token(subscriber_id, sent_at);
+        assert_ok_eq!(
+            tokens.verify(&token, TokenPurpose::Erasure, sent_at + Duration::hours(23)),
+            subscriber_id
+        );
+        assert_err_eq!(
+            tokens.verify(&token, TokenPurpose::Erasure, sent_at + Duration::hours(25)),
+            TokenError::Expired
+        );
+        assert_err_eq!(
+            tokens.verify(
+                &tokens.unsubscribe_token(subscriber_id),
+                TokenPurpose::Erasure,
+                sent_at
+            ),
+            TokenError::Invalid
+        );
+    }
+
+    #[test]
+    fn unsubscribe_tokens_do_not_expire() {
+        let tokens = tokens("secret");
+        let subscriber_id = Uuid::new_v4();
+        let token = tokens.unsubscribe_token(subscriber_id);
+        assert_ok_eq!(
+            tokens.verify(
+                &token,
+                TokenPurpose::Unsubscribe,
+                Utc::now() + Duration::days(3650)
+            ),
+            subscriber_id
+        );
+    }
+
+    #[test]
+    fn forged_or_tampered_tokens_are_invalid() {
+        let subscriber_id = Uuid::new_v4();
+        let now = Utc::now();
+        let forged = tokens("another secret").unsubscribe_token(subscriber_id);
+        let tokens = tokens("secret");
+        let tampered = tokens.unsubscribe_token(subscriber_id).replacen(
+            &subscriber_id.simple().to_string(),
+            &Uuid::new_v4().simple().to_string(),
+            1,
+        );
+        for token in [forged.as_str(), tampered.as_str(), "a.b.c", "legacytoken"] {
+            assert_err_eq!(
+                tokens.verify(token, TokenPurpose::Unsubscribe, now),
+                TokenError::Invalid
+            );
+        }
+        assert!(!is_signed_token("legacytoken"));
+    }
+}
@@ -0,0 +1,108 @@
+mod rolling_file;
+
+pub use rolling_file::{RollingFileAppender, RollingFileWriter};
+
+use sha2::{Digest, Sha256};
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tracing::subscriber::set_global_default;
+use tracing::Subscriber;
+use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
+use tracing_log::LogTracer;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::{EnvFilter, Registry};
+
+/// Formats the logs as bunyan JSON to `sink`, and to `log_file` as well if there is one
+pub fn get_tracing_subscriber<Sink>(
+    name: String,
+    env_filter: String,
+    sink: Sink,
+    log_file: Option<RollingFileAppender>,
+) -> impl Subscriber + Send + Sync
+where
+    Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let env_filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
+    let file_layer = log_file.map(|appender| BunyanFormattingLayer::new(name.clone(), appender));
+    let formatting_layer = BunyanFormattingLayer::new(name, sink);
+    Registry::default()
+        .with(env_filter)
+        .with(JsonStorageLayer)
+        .with(formatting_layer)
+        .with(file_layer)
+}
+
+pub fn init_subscriber(subscriber: impl Subscriber + Send + Sync) {
+    LogTracer::init().expect("Failed to set logger");
+    set_global_default(subscriber).expect("Failed to set subscriber");
+}
+
+/// Whether `pii_email` and `pii_name` hash what they are given; on unless configured otherwise
+static REDACT_PII: AtomicBool = AtomicBool::new(true);
+
+/// Turns the redaction of subscriber emails and names in spans and events on or off, e.g. off
+/// when debugging locally
+pub fn set_pii_redaction(enabled: bool) {
+    REDACT_PII.store(enabled, Ordering::Relaxed);
+}
+
+/// Personal data of a subscriber, to be recorded as a span or event field with `%`
+pub struct Pii<'a> {
+    value: &'a str,
+    is_email: bool,
+}
+
+/// An email address as logged: its local part is replaced with a hash, so that the entries about
+/// one subscriber can still be matched and the domain still tells their provider
+pub fn pii_email(email: &str) -> Pii<'_> {
+    Pii {
+        value: email,
+        is_email: true,
+    }
+}
+
+/// A name as logged: replaced with a hash
+pub fn pii_name(name: &str) -> Pii<'_> {
+    Pii {
+        value: name,
+        is_email: false,
+    }
+}
+
+impl Display for Pii<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if !REDACT_PII.load(Ordering::Relaxed) {
+            return f.write_str(self.value);
+        }
+        match self.value.rsplit_once('@') {
+            Some((local_part, domain)) if self.is_email => {
+                write!(f, "{}@{}", redacted(local_part), domain)
+            }
+            _ => f.write_str(&redacted(self.value)),
+        }
+    }
+}
+
+fn redacted(value: &str) -> String {
+    let digest = Sha256::digest(value.trim().to_lowercase().as_bytes());
+    format!("redacted:{}", hex::encode(&digest[..6]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{pii_email, pii_name};
+
+    #[test]
+    fn personal_data_is_hashed_consistently() {
+        let email = pii_email("Ursula@Example.com").to_string();
+        assert!(email.starts_with("redacted:"));
+        assert!(email.ends_with("@Example.com"));
+        assert!(!email.contains("Ursula"));
+        assert_eq!(email, pii_email("ursula@Example.com").to_string());
+        let name = pii_name("Ursula Le Guin").to_string();
+        assert!(name.starts_with("redacted:"));
+        assert!(!name.contains("Ursula"));
+    }
+}
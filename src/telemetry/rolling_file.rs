@@ -0,0 +1,239 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use chrono::{NaiveDate, Utc};
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::configuration::LogFileSettings;
+
+/// Writes logs to `{prefix}.{date}.{index}.log` files in a directory, starting a new file every
+/// day (UTC) and whenever the current one would grow over `max_file_bytes`, and keeping only the
+/// newest `max_files` files
+pub struct RollingFileAppender {
+    state: Mutex<State>,
+}
+
+struct State {
+    directory: PathBuf,
+    prefix: String,
+    max_file_bytes: u64,
+    max_files: usize,
+    file: File,
+    date: NaiveDate,
+    index: u32,
+    size: u64,
+}
+
+impl RollingFileAppender {
+    /// Appends to the newest file of today, if any, so that restarts do not start a new file
+    pub fn open(settings: &LogFileSettings) -> Result<Self, io::Error> {
+        let directory = PathBuf::from(&settings.directory);
+        fs::create_dir_all(&directory)?;
+        let date = Utc::now().date_naive();
+        let index = log_files(&directory, &settings.file_name_prefix)?
+            .into_iter()
+            .filter(|file| file.date == date)
+            .map(|file| file.index)
+            .max()
+            .unwrap_or(0);
+        let (file, size) = open_log_file(&directory, &settings.file_name_prefix, date, index)?;
+        let state = State {
+            directory,
+            prefix: settings.file_name_prefix.clone(),
+            max_file_bytes: settings.max_file_bytes,
+            max_files: settings.max_files,
+            file,
+            date,
+            index,
+            size,
+        };
+        state.remove_old_files()?;
+        Ok(Self {
+            state: Mutex::new(state),
+        })
+    }
+}
+
+impl State {
+    fn roll_if_needed(&mut self, incoming_bytes: u64) -> Result<(), io::Error> {
+        let today = Utc::now().date_naive();
+        let (date, index) = if today != self.date {
+            (today, 0)
+        } else if self.size > 0 && self.size + incoming_bytes > self.max_file_bytes {
+            (today, self.index + 1)
+        } else {
+            return Ok(());
+        };
+        let (file, size) = open_log_file(&self.directory, &self.prefix, date, index)?;
+        self.file = file;
+        self.date = date;
+        self.index = index;
+        self.size = size;
+        self.remove_old_files()
+    }
+
+    fn remove_old_files(&self) -> Result<(), io::Error> {
+        let mut files = log_files(&self.directory, &self.prefix)?;
+        files.sort_by_key(|file| std::cmp::Reverse((file.date, file.index)));
+        for file in files.into_iter().skip(self.max_files.max(1)) {
+            fs::remove_file(file.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// A log line is written in a single call, so a line never straddles two files
+pub struct RollingFileWriter<'a>(MutexGuard<'a, State>);
+
+impl Write for RollingFileWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let state = &mut self.0;
+        state.roll_if_needed(buf.len() as u64)?;
+        let written = state.file.write(buf)?;
+        state.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.file.flush()
+    }
+}
+
+impl<'a> MakeWriter<'a> for RollingFileAppender {
+    type Writer = RollingFileWriter<'a>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        // a thread that panicked while logging leaves a usable file behind
+        RollingFileWriter(
+            self.state
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        )
+    }
+}
+
+struct LogFile {
+    path: PathBuf,
+    date: NaiveDate,
+    index: u32,
+}
+
+fn file_name(prefix: &str, date: NaiveDate, index: u32) -> String {
+    format!("{}.{}.{}.log", prefix, date.format("%Y-%m-%d"), index)
+}
+
+fn open_log_file(
+    directory: &Path,
+    prefix: &str,
+    date: NaiveDate,
+    index: u32,
+) -> Result<(File, u64), io::Error> {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(directory.join(file_name(prefix, date, index)))?;
+    let size = file.metadata()?.len();
+    Ok((file, size))
+}
+
+/// The log files of the directory written with `prefix`; other files are left alone
+fn log_files(directory: &Path, prefix: &str) -> Result<Vec<LogFile>, io::Error> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let Some((date, index)) = name
+            .strip_prefix(prefix)
+            .and_then(|name| name.strip_prefix('.'))
+            .and_then(|name| name.strip_suffix(".log"))
+            .and_then(|name| name.split_once('.'))
+        else {
+            continue;
+        };
+        let (Ok(date), Ok(index)) = (NaiveDate::parse_from_str(date, "%Y-%m-%d"), index.parse())
+        else {
+            continue;
+        };
+        files.push(LogFile { path, date, index });
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{file_name, RollingFileAppender};
+    use crate::configuration::LogFileSettings;
+    use chrono::Utc;
+    use std::io::Write;
+    use std::path::PathBuf;
+    use tracing_subscriber::fmt::MakeWriter;
+
+    fn settings(max_file_bytes: u64, max_files: usize) -> (LogFileSettings, PathBuf) {
+        let directory = std::env::temp_dir().join(format!("logs-{}", uuid::Uuid::new_v4()));
+        let settings = LogFileSettings {
+            directory: directory.to_string_lossy().into_owned(),
+            file_name_prefix: "newsletter".into(),
+            max_file_bytes,
+            max_files,
+        };
+        (settings, directory)
+    }
+
+    fn log_file_names(directory: &PathBuf) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn a_new_file_is_started_when_the_current_one_is_full() {
+        let (settings, directory) = settings(10, 10);
+        let appender = RollingFileAppender::open(&settings).unwrap();
+        for line in ["line one\n", "line two\n", "line three\n"] {
+            appender.make_writer().write_all(line.as_bytes()).unwrap();
+        }
+
+        let today = Utc::now().date_naive();
+        assert_eq!(
+            log_file_names(&directory),
+            vec![
+                file_name("newsletter", today, 0),
+                file_name("newsletter", today, 1),
+                file_name("newsletter", today, 2),
+            ]
+        );
+        let first = std::fs::read_to_string(directory.join(file_name("newsletter", today, 0)));
+        assert_eq!(first.unwrap(), "line one\n");
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn only_the_newest_files_are_kept() {
+        let (settings, directory) = settings(5, 2);
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(directory.join("newsletter.2020-01-01.0.log"), "old\n").unwrap();
+        std::fs::write(directory.join("unrelated.log"), "kept\n").unwrap();
+        let appender = RollingFileAppender::open(&settings).unwrap();
+        for line in ["line one\n", "line two\n", "line three\n"] {
+            appender.make_writer().write_all(line.as_bytes()).unwrap();
+        }
+
+        let today = Utc::now().date_naive();
+        assert_eq!(
+            log_file_names(&directory),
+            vec![
+                file_name("newsletter", today, 1),
+                file_name("newsletter", today, 2),
+                "unrelated.log".to_string(),
+            ]
+        );
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
@@ -0,0 +1,87 @@
+/// The identity shown at the top of every page, with the colors of the stylesheet
+#[derive(serde::Deserialize, Clone, Debug)]
+pub struct Branding {
+    pub name: String,
+    /// Address of the logo, e.g. a file of the static directory such as `/static/logo.svg`
+    pub logo_url: Option<String>,
+    pub primary_color: HexColor,
+    pub background_color: HexColor,
+    pub text_color: HexColor,
+}
+
+impl Default for Branding {
+    fn default() -> Self {
+        Self {
+            name: "Newsletter".into(),
+            logo_url: None,
+            primary_color: HexColor("#2b6cb0".into()),
+            background_color: HexColor("#ffffff".into()),
+            text_color: HexColor("#1a202c".into()),
+        }
+    }
+}
+
+/// A color such as `#2b6cb0`, the only syntax accepted so that configured colors cannot inject
+/// CSS into the pages
+#[derive(serde::Deserialize, Clone, Debug)]
+#[serde(try_from = "String")]
+pub struct HexColor(String);
+
+impl HexColor {
+    pub fn parse(s: String) -> Result<Self, String> {
+        let is_valid = s.strip_prefix('#').map_or(false, |digits| {
+            matches!(digits.len(), 3 | 6) && digits.chars().all(|c| c.is_ascii_hexdigit())
+        });
+        if is_valid {
+            Ok(Self(s))
+        } else {
+            Err(format!("{} is not a color of the form #rrggbb or #rgb.", s))
+        }
+    }
+}
+
+impl TryFrom<String> for HexColor {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(value)
+    }
+}
+
+impl std::fmt::Display for HexColor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl AsRef<str> for HexColor {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::HexColor;
+
+    #[test]
+    fn hex_colors_are_accepted() {
+        assert_ok!(HexColor::parse("#2b6cb0".into()));
+        assert_ok!(HexColor::parse("#FFF".into()));
+    }
+
+    #[test]
+    fn other_colors_are_rejected() {
+        for color in [
+            "red",
+            "2b6cb0",
+            "#2b6cb",
+            "#zzzzzz",
+            "#fff; } body { display: none",
+        ] {
+            assert_err!(HexColor::parse(color.into()));
+        }
+    }
+}
@@ -0,0 +1,185 @@
+use anyhow::Context as _;
+
+use crate::configuration::{AttachmentSettings, ConfirmationEmailSettings};
+use crate::email_client::Attachment;
+use crate::error_handling::error_chain_fmt;
+use crate::routing_helpers::html_escape;
+
+/// The templates used to build the email sent to new subscribers.
+/// They can refer to the `name` of the subscriber and to the `confirmation_link`.
+pub struct ConfirmationEmailTemplate {
+    subject: String,
+    html: String,
+    text: String,
+    attachment: Option<Attachment>,
+}
+
+/// A confirmation email ready to be sent
+#[derive(Debug)]
+pub struct ConfirmationEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+impl ConfirmationEmailTemplate {
+    pub fn new(subject: String, html: String, text: String) -> Self {
+        Self {
+            subject,
+            html,
+            text,
+            attachment: None,
+        }
+    }
+
+    /// Sends the given file along with every confirmation email
+    pub fn with_attachment(mut self, attachment: Attachment) -> Self {
+        self.attachment = Some(attachment);
+        self
+    }
+
+    pub fn attachment(&self) -> Option<&Attachment> {
+        self.attachment.as_ref()
+    }
+
+    /// Reads the templates from the files listed in the settings, and makes sure they only refer
+    /// to known placeholders so that a typo is caught on startup rather than on the first signup
+    pub fn load(settings: &ConfirmationEmailSettings) -> Result<Self, anyhow::Error> {
+        let html = std::fs::read_to_string(&settings.html_template).with_context(|| {
+            format!(
+                "Failed to read the confirmation email template at {}",
+                settings.html_template
+            )
+        })?;
+        let text = std::fs::read_to_string(&settings.text_template).with_context(|| {
+            format!(
+                "Failed to read the confirmation email template at {}",
+                settings.text_template
+            )
+        })?;
+        let mut template = Self::new(settings.subject.clone(), html, text);
+        if let Some(attachment) = &settings.attachment {
+            template = template.with_attachment(load_attachment(attachment)?);
+        }
+        template
+            .render("name", "https://example.com")
+            .context("Invalid confirmation email template")?;
+        Ok(template)
+    }
+
+    pub fn render(
+        &self,
+        name: &str,
+        confirmation_link: &str,
+    ) -> Result<ConfirmationEmail, TemplateError> {
+        let values = [("name", name), ("confirmation_link", confirmation_link)];
+        // the subject and the text body are never interpreted as HTML, so nothing is escaped
+        Ok(ConfirmationEmail {
+            subject: fill_placeholders(&self.subject, &values, str::to_owned)?,
+            html: fill_placeholders(&self.html, &values, html_escape)?,
+            text: fill_placeholders(&self.text, &values, str::to_owned)?,
+        })
+    }
+}
+
+#[derive(thiserror::Error)]
+pub enum TemplateError {
+    #[error("The template refers to `{0}`, which is not a known placeholder")]
+    UnknownVariable(String),
+    #[error("The template has a placeholder that is never closed")]
+    UnclosedPlaceholder,
+}
+
+impl std::fmt::Debug for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+/// Replaces every `{{ name }}` placeholder of `template` with its value, as formatted by
+/// `escape`. The templates are read from the configuration when the application starts, so they
+/// cannot be compiled with the pages.
+fn fill_placeholders(
+    template: &str,
+    values: &[(&str, &str)],
+    escape: fn(&str) -> String,
+) -> Result<String, TemplateError> {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        filled.push_str(&rest[..start]);
+        let placeholder = &rest[start + 2..];
+        let end = placeholder
+            .find("}}")
+            .ok_or(TemplateError::UnclosedPlaceholder)?;
+        let name = placeholder[..end].trim();
+        let (_, value) = values
+            .iter()
+            .find(|(known, _)| *known == name)
+            .ok_or_else(|| TemplateError::UnknownVariable(name.into()))?;
+        filled.push_str(&escape(value));
+        rest = &placeholder[end + 2..];
+    }
+    filled.push_str(rest);
+    Ok(filled)
+}
+
+fn load_attachment(settings: &AttachmentSettings) -> Result<Attachment, anyhow::Error> {
+    let content = std::fs::read(&settings.path).with_context(|| {
+        format!(
+            "Failed to read the confirmation email attachment at {}",
+            settings.path
+        )
+    })?;
+    let filename = match &settings.filename {
+        Some(filename) => filename.clone(),
+        None => std::path::Path::new(&settings.path)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| settings.path.clone()),
+    };
+    Attachment::new(filename, settings.content_type.clone(), content).map_err(anyhow::Error::msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use claims::{assert_err, assert_ok};
+
+    use super::ConfirmationEmailTemplate;
+
+    #[test]
+    fn placeholders_are_replaced_in_every_part() {
+        let template = ConfirmationEmailTemplate::new(
+            "Welcome {{ name }}".into(),
+            r#"<a href="{{ confirmation_link }}">Hi {{ name }}</a>"#.into(),
+            "Hi {{ name }}, visit {{ confirmation_link }}".into(),
+        );
+        let email = assert_ok!(template.render("Tom & Jerry", "https://example.com/confirm"));
+        assert_eq!(email.subject, "Welcome Tom & Jerry");
+        assert_eq!(
+            email.html,
+            r#"<a href="https://example.com/confirm">Hi Tom &amp; Jerry</a>"#
+        );
+        assert_eq!(
+            email.text,
+            "Hi Tom & Jerry, visit https://example.com/confirm"
+        );
+    }
+
+    #[test]
+    fn unknown_placeholders_are_rejected() {
+        let template = ConfirmationEmailTemplate::new(
+            "Welcome".into(),
+            "{{ unsubscribe_link }}".into(),
+            "".into(),
+        );
+        assert_err!(template.render("Ursula", "https://example.com/confirm"));
+    }
+
+    #[test]
+    fn unclosed_placeholders_are_rejected() {
+        let template =
+            ConfirmationEmailTemplate::new("Welcome {{ name".into(), "".into(), "".into());
+        assert_err!(template.render("Ursula", "https://example.com/confirm"));
+    }
+}
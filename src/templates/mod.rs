@@ -0,0 +1,133 @@
+//! The HTML pages served by the application, as Askama templates compiled along with the crate.
+//!
+//! Values are HTML-escaped when rendered, unless a template marks them `|safe`. Pages extend
+//! `layout.html`, which reads the title and the branding from their `layout` field, and list
+//! their rows with loops rather than markup built in Rust.
+
+mod branding;
+mod confirmation_email;
+
+pub use branding::{Branding, HexColor};
+pub use confirmation_email::{ConfirmationEmail, ConfirmationEmailTemplate, TemplateError};
+
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+
+/// What the shared layout shows around the content of a page
+pub struct Layout<'a> {
+    pub title: &'a str,
+    pub branding: &'a Branding,
+}
+
+impl<'a> Layout<'a> {
+    pub fn new(branding: &'a Branding, title: &'a str) -> Self {
+        Self { title, branding }
+    }
+}
+
+/// The flash messages of a request, for pages including `flash_messages.html`
+pub fn collect_flash_messages(messages: &IncomingFlashMessages) -> Vec<FlashMessage> {
+    messages.iter().cloned().collect()
+}
+
+/// Filters for the templates of the modules importing this one
+pub mod filters {
+    use std::borrow::Borrow;
+
+    /// Formats a count with thousands separators, e.g. `1,234`
+    pub fn count(count: impl Borrow<i64>) -> askama::Result<String> {
+        Ok(crate::routes::format_count(*count.borrow()))
+    }
+
+    /// Formats a percentage with one decimal, e.g. `12.5%`
+    pub fn rate(rate: impl Borrow<Option<f64>>) -> askama::Result<String> {
+        Ok(match rate.borrow() {
+            Some(rate) => format!("{:.1}%", rate),
+            None => "n/a".to_string(),
+        })
+    }
+}
+
+/// An `<option>` of a select field, rendered by the `select_options` macro
+pub struct SelectOption {
+    pub value: String,
+    pub label: String,
+    pub selected: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web_flash_messages::FlashMessage;
+    use askama::Template;
+    use claims::assert_ok;
+
+    use super::{filters, Branding, Layout};
+
+    #[derive(Template)]
+    #[template(
+        source = r#"{% extends "layout.html" %}{% block content %}<p>{{ name }}</p>{% include "flash_messages.html" %}{% endblock %}"#,
+        ext = "html"
+    )]
+    struct TestPage<'a> {
+        layout: Layout<'a>,
+        name: &'a str,
+        messages: Vec<FlashMessage>,
+    }
+
+    #[test]
+    fn pages_are_rendered_inside_the_layout_and_values_are_escaped() {
+        let branding = Branding::default();
+        let page = assert_ok!(TestPage {
+            layout: Layout::new(&branding, "A & B"),
+            name: "<script>alert('hi')</script>",
+            messages: vec![],
+        }
+        .render());
+        assert!(page.starts_with("<!DOCTYPE html>"));
+        assert!(page.contains("<title>A &amp; B</title>"));
+        assert!(page.contains("<span>Newsletter</span>"));
+        assert!(page.contains("--primary-color: #2b6cb0;"));
+        assert!(page.contains("<p>&lt;script&gt;alert(&#x27;hi&#x27;)&lt;/script&gt;</p>"));
+    }
+
+    #[test]
+    fn rates_are_formatted_with_one_decimal() {
+        assert_eq!(filters::rate(Some(12.345)).unwrap(), "12.3%");
+        assert_eq!(filters::rate(Some(100.0)).unwrap(), "100.0%");
+        assert_eq!(filters::rate(None).unwrap(), "n/a");
+    }
+
+    #[test]
+    fn the_header_escapes_the_name_and_logo() {
+        let branding = Branding {
+            name: "Tom & Jerry".into(),
+            logo_url: Some(r#"/static/logo.svg" onerror="alert(1)"#.into()),
+            ..Branding::default()
+        };
+        let page = assert_ok!(TestPage {
+            layout: Layout::new(&branding, "Home"),
+            name: "",
+            messages: vec![],
+        }
+        .render());
+        assert!(page.contains("<span>Tom &amp; Jerry</span>"));
+        assert!(page.contains(r#"src="/static/logo.svg&quot; onerror=&quot;alert(1)""#));
+    }
+
+    #[test]
+    fn flash_messages_are_styled_after_their_level_and_escaped() {
+        let branding = Branding::default();
+        let page = assert_ok!(TestPage {
+            layout: Layout::new(&branding, "Home"),
+            name: "",
+            messages: vec![
+                FlashMessage::success("Saved."),
+                FlashMessage::error("<b>Failed</b>"),
+            ],
+        }
+        .render());
+        assert!(page.contains(
+            "<p class=\"flash flash-success\"><i>Saved.</i></p>\n\
+             <p class=\"flash flash-error\"><i>&lt;b&gt;Failed&lt;/b&gt;</i></p>\n"
+        ));
+    }
+}
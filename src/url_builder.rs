@@ -0,0 +1,127 @@
+use uuid::Uuid;
+
+/// Builds the absolute URLs of the application, for emails and redirects, from
+/// `application.base_url`. The base URL may end with a slash and may include a path prefix,
+/// e.g. `https://example.com/newsletter/`.
+#[derive(Clone, Debug)]
+pub struct UrlBuilder {
+    base_url: String,
+}
+
+impl UrlBuilder {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let base_url = base_url.into().trim_end_matches('/').to_string();
+        Self { base_url }
+    }
+
+    /// The base URL, without a trailing slash
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Joins `path`, which may include a query string, onto the base URL
+    pub fn url(&self, path: &str) -> String {
+        format!("{}/{}", self.base_url, path.trim_start_matches('/'))
+    }
+
+    /// Joins `path` onto the base URL, with `query` encoded as its query string
+    pub fn url_with_query(&self, path: &str, query: &[(&str, &str)]) -> String {
+        let query = serde_urlencoded::to_string(query).expect("Query pairs can always be encoded");
+        format!("{}?{}", self.url(path), query)
+    }
+
+    /// The link of confirmation emails, which confirms a subscription
+    pub fn confirmation_url(&self, subscription_token: &str) -> String {
+        self.url_with_query(
+            "/subscriptions/confirm",
+            &[("subscription_token", subscription_token)],
+        )
+    }
+
+    /// The link sent to the current address of a subscriber, to approve changing it
+    pub fn email_change_approval_url(&self, approval_token: &str) -> String {
+        self.url_with_query(
+            "/subscriptions/change-email/approve",
+            &[("approval_token", approval_token)],
+        )
+    }
+
+    /// The link a subscriber follows to confirm their new address
+    pub fn email_change_confirmation_url(&self, change_token: &str) -> String {
+        self.url_with_query(
+            "/subscriptions/change-email/confirm",
+            &[("change_token", change_token)],
+        )
+    }
+
+    /// The link a subscriber follows to confirm they want their data erased
+    pub fn erasure_confirmation_url(&self, erasure_token: &str) -> String {
+        self.url_with_query(
+            "/subscriptions/erase/confirm",
+            &[("erasure_token", erasure_token)],
+        )
+    }
+
+    /// The link of invitation emails, where invited users choose their password
+    pub fn invitation_url(&self, invitation_token: &str) -> String {
+        self.url_with_query(
+            "/users/accept-invitation",
+            &[("invitation_token", invitation_token)],
+        )
+    }
+
+    /// The URL a subscriber can visit to stop receiving issues
+    pub fn unsubscribe_url(&self, subscription_token: &str) -> String {
+        self.url_with_query(
+            "/subscriptions/unsubscribe",
+            &[("subscription_token", subscription_token)],
+        )
+    }
+
+    /// The public URL of an issue in the web archive
+    pub fn issue_url(&self, issue_id: Uuid) -> String {
+        self.url(&format!("/issues/{}", issue_id))
+    }
+
+    /// The URL that records a click on a link of a delivery before redirecting to its target
+    pub fn click_tracking_url(&self, delivery_id: Uuid, link_id: usize) -> String {
+        self.url(&format!("/t/click/{}/{}", delivery_id, link_id))
+    }
+
+    /// The URL of the tracking pixel that records when a delivery is opened
+    pub fn open_tracking_url(&self, delivery_id: Uuid) -> String {
+        self.url(&format!("/t/open/{}", delivery_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UrlBuilder;
+
+    #[test]
+    fn paths_are_joined_with_a_single_slash() {
+        for base_url in ["https://example.com", "https://example.com/"] {
+            let urls = UrlBuilder::new(base_url);
+            assert_eq!(urls.url("/issues"), "https://example.com/issues");
+            assert_eq!(urls.url("issues"), "https://example.com/issues");
+        }
+    }
+
+    #[test]
+    fn path_prefixes_of_the_base_url_are_kept() {
+        let urls = UrlBuilder::new("https://example.com/newsletter/");
+        assert_eq!(
+            urls.url("/subscriptions/pending"),
+            "https://example.com/newsletter/subscriptions/pending"
+        );
+    }
+
+    #[test]
+    fn query_values_are_encoded() {
+        let urls = UrlBuilder::new("https://example.com");
+        assert_eq!(
+            urls.confirmation_url("a.b&c=d"),
+            "https://example.com/subscriptions/confirm?subscription_token=a.b%26c%3Dd"
+        );
+    }
+}
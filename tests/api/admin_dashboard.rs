@@ -1,3 +1,8 @@
+use chrono::Utc;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
 use crate::helpers::{assert_is_redirect_to, spawn_app};
 
 #[tokio::test]
@@ -9,7 +14,7 @@ async fn user_must_be_logged_in_to_access_admin_dashboard() {
     let response = app.get_admin_dashboard().await;
 
     // assert
-    assert_is_redirect_to(&response, "/login");
+    assert_is_redirect_to(&response, "/login?next=%2Fadmin%2Fdashboard");
 }
 
 #[tokio::test]
@@ -35,9 +40,65 @@ async fn logout_clears_session_state() {
 
     // act 4: follow the redirect
     let html_page = app.get_login_html().await;
-    assert!(html_page.contains(r#"<p><i>You have successfully logged out.</i></p>"#));
+    assert!(html_page
+        .contains(r#"<p class="flash flash-info"><i>You have successfully logged out.</i></p>"#));
 
     // act 5: attempt to load admin panel
     let response = app.get_admin_dashboard().await;
-    assert_is_redirect_to(&response, "/login");
+    assert_is_redirect_to(&response, "/login?next=%2Fadmin%2Fdashboard");
+}
+
+#[tokio::test]
+async fn dashboard_shows_subscriber_counts_signups_and_the_delivery_queue() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.seed_confirmed_subscribers(3).await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com".to_string();
+    app.post_subscriptions(body).await;
+    app.post_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.enqueue_all_deliveries().await;
+
+    // act
+    let html_page = app.get_admin_dashboard_html().await;
+
+    // assert
+    assert!(html_page.contains("<tr><th>Total</th><td>4</td></tr>"));
+    assert!(html_page.contains("<tr><th>Confirmed</th><td>3</td></tr>"));
+    assert!(html_page.contains("<tr><th>Pending confirmation</th><td>1</td></tr>"));
+    assert!(html_page.contains("<tr><th>Unsubscribed</th><td>0</td></tr>"));
+    let today = Utc::now().format("%Y-%m-%d");
+    assert!(html_page.contains(&format!("<tr><td>{}</td><td>4</td></tr>", today)));
+    assert!(html_page.contains("Newsletter title</a>: sent 0 / 3, 0 failures, delivering"));
+    assert!(html_page.contains("<tr><th>Issue emails pending</th><td>3</td></tr>"));
+    assert!(html_page.contains(&format!(
+        "<title>{}: 4 signups, 0 unsubscribes</title>",
+        today
+    )));
+}
+
+#[tokio::test]
+async fn dashboard_handles_an_empty_newsletter() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+
+    // act
+    let html_page = app.get_admin_dashboard_html().await;
+
+    // assert
+    assert!(html_page.contains("<tr><th>Total</th><td>0</td></tr>"));
+    assert!(html_page.contains("No issues have been published yet."));
+    assert!(html_page.contains("<tr><th>Issue emails pending</th><td>0</td></tr>"));
 }
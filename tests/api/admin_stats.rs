@@ -0,0 +1,108 @@
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+async fn subscribe(app: &TestApp, email: &str) {
+    let body = serde_urlencoded::to_string([("name", "le guin"), ("email", email)]).unwrap();
+    app.post_subscriptions(body).await;
+}
+
+async fn unsubscribe(app: &TestApp, email: &str) {
+    let subscription_token = app.unsubscribe_token(email).await;
+    let response = reqwest::get(&format!(
+        "{}/subscriptions/unsubscribe?subscription_token={}",
+        app.address, subscription_token
+    ))
+    .await
+    .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn daily_stats_count_the_signups_and_unsubscribes_of_each_day() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    subscribe(&app, "ursula_le_guin@gmail.com").await;
+    subscribe(&app, "octavia_butler@gmail.com").await;
+    unsubscribe(&app, "octavia_butler@gmail.com").await;
+    sqlx::query!("UPDATE subscriptions SET subscribed_at = now() - interval '2 days'")
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+
+    // act
+    let response = app.get_subscriber_stats("granularity=day&periods=7").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["granularity"], "day");
+    let series = body["series"].as_array().unwrap();
+    assert_eq!(series.len(), 7);
+    let today = Utc::now().date_naive();
+    let two_days_ago = today - Duration::days(2);
+    assert_eq!(series[6]["period_start"], today.to_string());
+    assert_eq!(series[6]["signups"], 0);
+    assert_eq!(series[6]["unsubscribes"], 1);
+    assert_eq!(series[4]["period_start"], two_days_ago.to_string());
+    assert_eq!(series[4]["signups"], 2);
+    assert_eq!(series[4]["unsubscribes"], 0);
+}
+
+#[tokio::test]
+async fn weekly_stats_start_on_monday() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+
+    // act
+    let response = app.get_subscriber_stats("granularity=week&periods=4").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let series = body["series"].as_array().unwrap();
+    assert_eq!(series.len(), 4);
+    for point in series {
+        let period_start: NaiveDate = point["period_start"].as_str().unwrap().parse().unwrap();
+        assert_eq!(period_start.weekday(), chrono::Weekday::Mon);
+    }
+}
+
+#[tokio::test]
+async fn invalid_stats_queries_are_rejected_with_400() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+
+    for query in ["granularity=hour", "periods=0", "periods=1000"] {
+        // act
+        let response = app.get_subscriber_stats(query).await;
+
+        // assert
+        assert_eq!(response.status().as_u16(), 400, "query: {}", query);
+    }
+}
+
+#[tokio::test]
+async fn must_be_logged_in_to_get_stats() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.get_subscriber_stats("granularity=day").await;
+
+    // assert
+    assert_is_redirect_to(
+        &response,
+        "/login?next=%2Fadmin%2Fapi%2Fstats%2Fsubscribers%3Fgranularity%3Dday",
+    );
+}
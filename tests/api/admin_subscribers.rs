@@ -0,0 +1,691 @@
+use email_newsletter::routes::{
+    confirm_pending_subscriber, mark_subscriber_as_unsubscribed, ManualConfirmation,
+};
+use email_newsletter::subscriber_purge::purge_deleted_subscribers;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+#[tokio::test]
+async fn must_be_logged_in_to_see_subscribers() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.get_subscribers("").await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login?next=%2Fadmin%2Fsubscribers%3F");
+}
+
+#[tokio::test]
+async fn subscribers_are_listed_and_searchable() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    subscribe(&app, "le guin", "ursula_le_guin@gmail.com").await;
+    subscribe(&app, "butler", "octavia_butler@gmail.com").await;
+
+    // act
+    let all = app.get_subscribers_html("").await;
+    let filtered = app.get_subscribers_html("search=URSULA").await;
+
+    // assert
+    assert!(all.contains("ursula_le_guin@gmail.com"));
+    assert!(all.contains("octavia_butler@gmail.com"));
+    assert!(all.contains("pending_confirmation"));
+    assert!(filtered.contains("ursula_le_guin@gmail.com"));
+    assert!(!filtered.contains("octavia_butler@gmail.com"));
+    assert!(filtered.contains(r#"value="URSULA""#));
+}
+
+#[tokio::test]
+async fn subscribers_can_be_deleted() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let subscriber_id = subscribe(&app, "le guin", "ursula_le_guin@gmail.com").await;
+
+    // act
+    let response = app.post_subscriber_action(subscriber_id, "delete").await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page
+        .contains(r#"<p class="flash flash-success"><i>The subscriber has been deleted.</i></p>"#));
+    assert!(!html_page.contains("ursula_le_guin@gmail.com"));
+    let export = app.get_subscribers_export().await.text().await.unwrap();
+    assert!(!export.contains("ursula_le_guin@gmail.com"));
+    let entry = sqlx::query!("SELECT action, subject FROM audit_log")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(entry.action, "soft_delete_subscriber");
+    assert_eq!(entry.subject, subscriber_id.to_string());
+}
+
+#[tokio::test]
+async fn deleted_subscribers_can_be_restored() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let subscriber_id = subscribe(&app, "le guin", "ursula_le_guin@gmail.com").await;
+    app.post_subscriber_action(subscriber_id, "delete").await;
+    let html_page = app.get_deleted_subscribers_html().await;
+    assert!(html_page.contains("ursula_le_guin@gmail.com"));
+
+    // act
+    let response = app.post_subscriber_action(subscriber_id, "restore").await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/subscribers/deleted");
+    let html_page = app.get_deleted_subscribers_html().await;
+    assert!(html_page.contains(
+        r#"<p class="flash flash-success"><i>The subscriber has been restored.</i></p>"#
+    ));
+    assert!(!html_page.contains("ursula_le_guin@gmail.com"));
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains("ursula_le_guin@gmail.com"));
+}
+
+#[tokio::test]
+async fn deleted_subscribers_do_not_receive_issues() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    for (name, email) in [
+        ("le guin", "ursula_le_guin@gmail.com"),
+        ("butler", "octavia_butler@gmail.com"),
+    ] {
+        let subscriber_id = subscribe(&app, name, email).await;
+        app.post_subscriber_action(subscriber_id, "confirm").await;
+    }
+    let deleted_id =
+        sqlx::query!("SELECT id FROM subscriptions WHERE email = 'octavia_butler@gmail.com'")
+            .fetch_one(&app.connection_pool)
+            .await
+            .unwrap()
+            .id;
+    app.post_subscriber_action(deleted_id, "delete").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    app.post_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn deleted_subscribers_who_sign_up_again_are_back_as_pending() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let subscriber_id = subscribe(&app, "le guin", "ursula_le_guin@gmail.com").await;
+    app.post_subscriber_action(subscriber_id, "confirm").await;
+    app.post_subscriber_action(subscriber_id, "delete").await;
+
+    // act
+    let resubscriber_id = subscribe(&app, "le guin", "ursula_le_guin@gmail.com").await;
+
+    // assert
+    assert_eq!(resubscriber_id, subscriber_id);
+    let subscriber = sqlx::query!("SELECT status, deleted_at FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(subscriber.status, "pending_confirmation");
+    assert_eq!(subscriber.deleted_at, None);
+}
+
+#[tokio::test]
+async fn deleted_subscribers_are_purged_after_the_retention_window() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let expired_id = subscribe(&app, "le guin", "ursula_le_guin@gmail.com").await;
+    let recent_id = subscribe(&app, "butler", "octavia_butler@gmail.com").await;
+    app.store_legacy_token(expired_id, "expiredLegacyToken0123456")
+        .await;
+    app.store_legacy_token(recent_id, "recentLegacyToken01234567")
+        .await;
+    app.post_subscriber_action(expired_id, "delete").await;
+    app.post_subscriber_action(recent_id, "delete").await;
+    sqlx::query!(
+        "UPDATE subscriptions SET deleted_at = now() - interval '31 days' WHERE id = $1",
+        expired_id
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+
+    // act
+    let n_purged = purge_deleted_subscribers(&app.connection_pool, chrono::Duration::days(30))
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(n_purged, 1);
+    let remaining = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_all(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].id, recent_id);
+    let n_tokens = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM subscription_tokens"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_tokens, 1);
+}
+
+#[tokio::test]
+async fn pending_subscribers_can_be_confirmed_manually() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let subscriber_id = subscribe(&app, "le guin", "ursula_le_guin@gmail.com").await;
+
+    // act
+    let response = app.post_subscriber_action(subscriber_id, "confirm").await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get_subscribers_html("").await;
+    assert!(html_page.contains(
+        r#"<p class="flash flash-success"><i>The subscriber has been confirmed.</i></p>"#
+    ));
+    let status = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "confirmed");
+}
+
+#[tokio::test]
+async fn concurrent_confirmations_confirm_a_subscriber_once() {
+    // arrange
+    let app = spawn_app().await;
+    let subscriber_id = subscribe(&app, "le guin", "ursula_le_guin@gmail.com").await;
+
+    // act
+    let (first, second) = tokio::join!(
+        confirm_pending_subscriber(&app.connection_pool, subscriber_id),
+        confirm_pending_subscriber(&app.connection_pool, subscriber_id),
+    );
+
+    // assert
+    let outcomes = [first.unwrap(), second.unwrap()];
+    assert_eq!(
+        outcomes
+            .iter()
+            .filter(|o| matches!(o, ManualConfirmation::Confirmed))
+            .count(),
+        1
+    );
+    assert!(outcomes
+        .iter()
+        .any(|o| matches!(o, ManualConfirmation::NotPending)));
+    let n_confirmations = sqlx::query!(
+        r#"SELECT COUNT(*) AS "n!" FROM subscription_events WHERE to_status = 'confirmed'"#
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap()
+    .n;
+    assert_eq!(n_confirmations, 1);
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_cannot_be_confirmed() {
+    // arrange
+    let app = spawn_app().await;
+    let subscriber_id = subscribe(&app, "le guin", "ursula_le_guin@gmail.com").await;
+    mark_subscriber_as_unsubscribed(subscriber_id, &app.connection_pool)
+        .await
+        .unwrap();
+
+    // act
+    let outcome = confirm_pending_subscriber(&app.connection_pool, subscriber_id)
+        .await
+        .unwrap();
+
+    // assert
+    assert!(matches!(outcome, ManualConfirmation::NotPending));
+}
+
+#[tokio::test]
+async fn erasing_a_subscriber_removes_their_data_and_is_audited() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let subscriber_id = subscribe(&app, "le guin", "ursula_le_guin@gmail.com").await;
+    app.store_legacy_token(subscriber_id, "aLegacyToken0123456789xyz")
+        .await;
+    sqlx::query!(
+        "INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email, newsletter_id) \
+         VALUES ($1, 'ursula_le_guin@gmail.com', (SELECT newsletter_id FROM newsletters))",
+        insert_issue(&app).await
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+
+    // act
+    let response = app.delete_subscriber(subscriber_id).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 204);
+    let remaining = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM subscriptions) AS "subscriptions!",
+            (SELECT COUNT(*) FROM subscription_tokens) AS "tokens!",
+            (SELECT COUNT(*) FROM subscription_consents) AS "consents!",
+            (SELECT COUNT(*) FROM issue_delivery_queue) AS "queue!"
+        "#
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        (
+            remaining.subscriptions,
+            remaining.tokens,
+            remaining.consents,
+            remaining.queue
+        ),
+        (0, 0, 0, 0)
+    );
+    let entry = sqlx::query!("SELECT user_id, action, subject FROM audit_log")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(entry.user_id, Some(app.test_user.user_id));
+    assert_eq!(entry.action, "delete_subscriber");
+    assert_eq!(entry.subject, subscriber_id.to_string());
+}
+
+#[tokio::test]
+async fn erasing_a_subscriber_anonymizes_their_past_deliveries_and_keeps_the_analytics() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let subscriber_id = subscribe(&app, "le guin", "ursula_le_guin@gmail.com").await;
+    let delivered_issue_id = insert_issue(&app).await;
+    let failed_issue_id = insert_issue(&app).await;
+    let delivery_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO issue_deliveries (newsletter_issue_id, subscriber_email, delivered_at, delivery_id) \
+         VALUES ($1, 'ursula_le_guin@gmail.com', now(), $2)",
+        delivered_issue_id,
+        delivery_id
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO email_opens (newsletter_issue_id, delivery_id, opened_at) VALUES ($1, $2, now())",
+        delivered_issue_id,
+        delivery_id
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "INSERT INTO issue_delivery_failures (newsletter_issue_id, subscriber_email, n_retries, failed_at) \
+         VALUES ($1, 'ursula_le_guin@gmail.com', 3, now())",
+        failed_issue_id
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+
+    // act
+    let response = app.delete_subscriber(subscriber_id).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 204);
+    let remaining = sqlx::query!(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM issue_deliveries) AS "deliveries!",
+            (SELECT COUNT(*) FROM issue_delivery_failures) AS "failures!",
+            (SELECT COUNT(*) FROM email_opens) AS "opens!",
+            (SELECT COUNT(*) FROM issue_deliveries WHERE subscriber_email LIKE '%@%')
+                + (SELECT COUNT(*) FROM issue_delivery_failures WHERE subscriber_email LIKE '%@%')
+                AS "addresses!"
+        "#
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        (
+            remaining.deliveries,
+            remaining.failures,
+            remaining.opens,
+            remaining.addresses
+        ),
+        (1, 1, 1, 0)
+    );
+}
+
+#[tokio::test]
+async fn must_be_logged_in_to_erase_a_subscriber() {
+    // arrange
+    let app = spawn_app().await;
+    let subscriber_id = subscribe(&app, "le guin", "ursula_le_guin@gmail.com").await;
+
+    // act
+    let response = app.delete_subscriber(subscriber_id).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_can_erase_their_own_data() {
+    // arrange
+    let app = spawn_app().await;
+    subscribe(&app, "le guin", "ursula_le_guin@gmail.com").await;
+    let token = app.unsubscribe_token("ursula_le_guin@gmail.com").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // act - part 1: ask for the erasure with the token of the issues
+    let response = app.post_erase_own_subscription(&token).await;
+
+    // assert - part 1: nothing is erased before the link sent to the address is followed
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(count_subscribers(&app).await, 1);
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula_le_guin@gmail.com");
+
+    // act - part 2: follow the link
+    let confirmation_links = app.get_confirmation_links(&email_request).await;
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // assert - part 2
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(count_subscribers(&app).await, 0);
+    let entry = sqlx::query!("SELECT user_id FROM audit_log")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(entry.user_id, None);
+
+    // act - part 3: the link only works once
+    let response = reqwest::get(confirmation_links.plain_text).await.unwrap();
+
+    // assert - part 3
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn erasure_links_are_rejected_once_expired_or_forged() {
+    // arrange
+    let app = spawn_app().await;
+    let subscriber_id = subscribe(&app, "le guin", "ursula_le_guin@gmail.com").await;
+    let expired = app.subscription_tokens.erasure_token(
+        subscriber_id,
+        chrono::Utc::now() - chrono::Duration::days(2),
+    );
+    let unsubscribe_token = app.unsubscribe_token("ursula_le_guin@gmail.com").await;
+
+    // act
+    let responses = [
+        (expired.as_str(), 410),
+        (unsubscribe_token.as_str(), 401),
+        ("forged", 401),
+    ];
+    for (token, expected_status) in responses {
+        let response = reqwest::get(format!(
+            "{}/subscriptions/erase/confirm?erasure_token={}",
+            app.address, token
+        ))
+        .await
+        .unwrap();
+
+        // assert
+        assert_eq!(response.status().as_u16(), expected_status);
+    }
+    assert_eq!(count_subscribers(&app).await, 1);
+}
+
+async fn count_subscribers(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM subscriptions"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .n
+}
+
+#[tokio::test]
+async fn actions_on_unknown_subscribers_return_404() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+
+    // act
+    let delete = app.post_subscriber_action(Uuid::new_v4(), "delete").await;
+    let confirm = app.post_subscriber_action(Uuid::new_v4(), "confirm").await;
+    let restore = app.post_subscriber_action(Uuid::new_v4(), "restore").await;
+    let erase = app.delete_subscriber(Uuid::new_v4()).await;
+
+    // assert
+    assert_eq!(delete.status().as_u16(), 404);
+    assert_eq!(confirm.status().as_u16(), 404);
+    assert_eq!(restore.status().as_u16(), 404);
+    assert_eq!(erase.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn subscribers_can_be_exported_as_csv() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    subscribe(&app, "le guin", "ursula_le_guin@gmail.com").await;
+    subscribe(&app, "butler, octavia", "octavia_butler@gmail.com").await;
+    app.post_admin_list("Rust Weekly", "rust-weekly").await;
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    app.post_subscriptions(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&newsletter=rust-weekly".into(),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // act
+    let response = app.get_subscribers_export().await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "text/csv; charset=utf-8"
+    );
+    let csv = response.text().await.unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(
+        lines[0],
+        "name,email,newsletter,status,subscribed_at,consented_at,consent_ip_address,\
+         consent_text_version,consent_country,consent_source_url"
+    );
+    assert!(
+        lines[1].starts_with("le guin,ursula_le_guin@gmail.com,newsletter,pending_confirmation,")
+    );
+    assert!(lines[2].starts_with("\"butler, octavia\",octavia_butler@gmail.com,newsletter,"));
+    // the same address on another list is told apart by its newsletter
+    assert!(lines[3].starts_with("le guin,ursula_le_guin@gmail.com,rust-weekly,"));
+}
+
+#[tokio::test]
+async fn the_consent_of_subscribers_is_shown_and_exported() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let body = serde_urlencoded::to_string([
+        ("name", "le guin"),
+        ("email", "ursula_le_guin@gmail.com"),
+        ("consent_text_version", "fr-v2"),
+        ("country", "FR"),
+        ("source_url", "https://example.com/blog?a=1&b=2"),
+    ])
+    .unwrap();
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    let subscriber_id = sqlx::query_scalar!("SELECT id FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+
+    // act - part 1
+    let html_page = app.get_subscriber_html(subscriber_id).await;
+
+    // assert - part 1
+    assert!(html_page.contains(
+        "<td>127.0.0.1</td><td>fr-v2</td><td>FR</td><td>https://example.com/blog?a=1&amp;b=2</td>"
+    ));
+    assert!(html_page.contains("<td></td><td>pending_confirmation</td>"));
+    assert!(app.get_subscribers_html("").await.contains(&format!(
+        r#"<a href="/admin/subscribers/{}">le guin</a>"#,
+        subscriber_id
+    )));
+
+    // act - part 2
+    let csv = app.get_subscribers_export().await.text().await.unwrap();
+
+    // assert - part 2
+    let record = csv.lines().nth(1).unwrap();
+    assert!(record.ends_with(",127.0.0.1,fr-v2,FR,https://example.com/blog?a=1&b=2"));
+}
+
+#[tokio::test]
+async fn unknown_subscribers_have_no_details_page() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+
+    // act
+    let response = app.get_subscriber(Uuid::new_v4()).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn large_subscriber_lists_are_exported_in_full() {
+    // arrange: several batches' worth of subscribers, all sharing the same subscription date
+    let app = spawn_app().await;
+    app.default_login().await;
+    app.seed_confirmed_subscribers(2_345).await;
+
+    // act
+    let response = app.get_subscribers_export().await;
+
+    // assert: every subscriber appears once, whatever batch they fell in
+    assert_eq!(response.status().as_u16(), 200);
+    let csv = response.text().await.unwrap();
+    let emails: std::collections::HashSet<&str> = csv
+        .lines()
+        .skip(1)
+        .map(|line| line.split(',').nth(1).unwrap())
+        .collect();
+    assert_eq!(csv.lines().count(), 2_346);
+    assert_eq!(emails.len(), 2_345);
+}
+
+#[tokio::test]
+async fn must_be_logged_in_to_export_subscribers() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.get_subscribers_export().await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login?next=%2Fadmin%2Fsubscribers%2Fexport");
+}
+
+/// Subscribes through the public API and returns the id of the pending subscriber
+async fn subscribe(app: &TestApp, name: &str, email: &str) -> Uuid {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let body = serde_urlencoded::to_string([("name", name), ("email", email)]).unwrap();
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+async fn insert_issue(app: &TestApp) -> Uuid {
+    let issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (newsletter_issue_id, title, text_content, html_content, published_at, newsletter_id)
+        VALUES ($1, 'Title', 'text', 'html', now(), (SELECT newsletter_id FROM newsletters))
+        "#,
+        issue_id
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    issue_id
+}
@@ -0,0 +1,296 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use email_newsletter::authentication::create_user;
+use secrecy::Secret;
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Invites a user and returns the invitation token sent to them
+async fn invite(app: &TestApp, username: &str) -> String {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = app
+        .post_invite_user(&serde_json::json!({
+            "username": username,
+            "email": "grace@example.com",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let link = app.get_confirmation_links(&email_request).await.html;
+    link.query_pairs()
+        .find(|(name, _)| name == "invitation_token")
+        .map(|(_, token)| token.into_owned())
+        .expect("The invitation link has no token")
+}
+
+async fn user_id(app: &TestApp, username: &str) -> Uuid {
+    sqlx::query!("SELECT user_id FROM users WHERE username = $1", username)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .user_id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_users() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app
+        .post_invite_user(&serde_json::json!({
+            "username": "grace",
+            "email": "grace@example.com",
+        }))
+        .await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn invited_users_can_set_their_password_and_log_in() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let token = invite(&app, "grace").await;
+    assert!(app
+        .get_admin_users_html()
+        .await
+        .contains("<td>invited</td>"));
+    app.post_logout().await;
+
+    // act
+    let response = app
+        .post_accept_invitation(&serde_json::json!({
+            "invitation_token": token,
+            "new_password": "a-long-enough-password",
+            "new_password_check": "a-long-enough-password",
+        }))
+        .await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "grace",
+            "password": "a-long-enough-password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    // the invitation can only be used once
+    let response = app
+        .post_accept_invitation(&serde_json::json!({
+            "invitation_token": token,
+            "new_password": "another-long-password",
+            "new_password_check": "another-long-password",
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn invited_users_cannot_log_in_before_accepting() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    invite(&app, "grace").await;
+    app.post_logout().await;
+
+    // act
+    let response = app
+        .post_login(&serde_json::json!({ "username": "grace", "password": "" }))
+        .await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn usernames_must_be_unique() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    let response = app
+        .post_invite_user(&serde_json::json!({
+            "username": &app.test_user.username,
+            "email": "grace@example.com",
+        }))
+        .await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/users");
+    let html = app.get_admin_users_html().await;
+    assert!(html.contains(&format!(
+        r#"<p class="flash flash-error"><i>The username {} is already taken.</i></p>"#,
+        app.test_user.username
+    )));
+}
+
+#[tokio::test]
+async fn invalid_invitation_tokens_are_rejected() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app
+        .api_client
+        .get(&format!(
+            "{}/users/accept-invitation?invitation_token=forged",
+            app.address
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn deactivated_users_cannot_log_in_and_lose_their_sessions() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let token = invite(&app, "grace").await;
+    let grace_id = user_id(&app, "grace").await;
+    app.post_logout().await;
+    app.post_accept_invitation(&serde_json::json!({
+        "invitation_token": token,
+        "new_password": "a-long-enough-password",
+        "new_password_check": "a-long-enough-password",
+    }))
+    .await;
+    let grace_login = serde_json::json!({
+        "username": "grace",
+        "password": "a-long-enough-password",
+    });
+    app.post_login(&grace_login).await;
+
+    // act: the test user deactivates grace, from another device, while grace is logged in
+    let other_device = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    other_device
+        .post(&format!("{}/login", app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+    let response = other_device
+        .post(&format!(
+            "{}/admin/users/{}/deactivate",
+            app.address, grace_id
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/users");
+
+    // assert
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login?next=%2Fadmin%2Fdashboard");
+    let response = app.post_login(&grace_login).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn admins_cannot_deactivate_themselves() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+
+    // act
+    let response = app.post_deactivate_user(app.test_user.user_id).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/users");
+    let html = app.get_admin_users_html().await;
+    assert!(html.contains(
+        r#"<p class="flash flash-error"><i>You cannot deactivate your own account.</i></p>"#
+    ));
+    assert!(html.contains("<td>active</td>"));
+}
+
+#[tokio::test]
+async fn accepting_an_invitation_requires_matching_passwords() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let token = invite(&app, "grace").await;
+
+    // act
+    let response = app
+        .post_accept_invitation(&serde_json::json!({
+            "invitation_token": token,
+            "new_password": "a-long-enough-password",
+            "new_password_check": "a-different-password",
+        }))
+        .await;
+
+    // assert
+    assert_is_redirect_to(
+        &response,
+        &format!("/users/accept-invitation?invitation_token={}", token),
+    );
+}
+
+#[tokio::test]
+async fn users_created_from_the_command_line_can_log_in() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let user_id = create_user(
+        "grace",
+        Secret::new("a-long-enough-password".to_string()),
+        &app.password_hashing,
+        &app.connection_pool,
+    )
+    .await
+    .unwrap();
+
+    // assert
+    assert!(user_id.is_some());
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "grace",
+            "password": "a-long-enough-password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    // usernames stay unique
+    let user_id = create_user(
+        "grace",
+        Secret::new("another-long-password".to_string()),
+        &app.password_hashing,
+        &app.connection_pool,
+    )
+    .await
+    .unwrap();
+    assert!(user_id.is_none());
+}
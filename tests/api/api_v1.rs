@@ -0,0 +1,1115 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use email_newsletter::idempotency::purge_expired_idempotency_keys;
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+/// Posts a JSON body to the API without credentials
+async fn post_api(app: &TestApp, api_path: &str, body: &serde_json::Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(&format!("{}/api/v1{}", app.address, api_path))
+        .json(body)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+/// Builds a request to the admin endpoints of the API, authenticated with the default user's
+/// Basic credentials
+fn admin_request(
+    app: &TestApp,
+    method: reqwest::Method,
+    api_path: &str,
+) -> reqwest::RequestBuilder {
+    reqwest::Client::new()
+        .request(method, format!("{}/api/v1/admin{}", app.address, api_path))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+}
+
+async fn publish(app: &TestApp, idempotency_key: &str) -> reqwest::Response {
+    admin_request(app, reqwest::Method::POST, "/newsletters")
+        .header("Idempotency-Key", idempotency_key)
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+/// Subscribes through the API and returns the token of the confirmation link
+async fn subscribe(app: &TestApp, email: &str) -> String {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let response = post_api(
+        app,
+        "/subscriptions",
+        &serde_json::json!({ "name": "le guin", "email": email }),
+    )
+    .await;
+    assert_eq!(response.status().as_u16(), 202);
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let link = app.get_confirmation_links(&email_request).await.html;
+    link.query_pairs()
+        .find(|(name, _)| name == "subscription_token")
+        .map(|(_, token)| token.into_owned())
+        .expect("The confirmation link has no token")
+}
+
+async fn subscriber_id(app: &TestApp, email: &str) -> Uuid {
+    sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .id
+}
+
+/// Asserts that the response is an error in the envelope of the API
+async fn assert_api_error(response: reqwest::Response, status: u16, code: &str) {
+    assert_eq!(response.status().as_u16(), status);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], code);
+    assert!(body["error"]["message"].is_string());
+}
+
+#[tokio::test]
+async fn subscribing_through_the_api_sends_a_confirmation_email() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    subscribe(&app, "ursula_le_guin@gmail.com").await;
+
+    // assert
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn invalid_subscriptions_are_rejected_with_a_json_error() {
+    // arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({ "name": "", "email": "ursula_le_guin@gmail.com" }),
+            "empty name",
+        ),
+        (
+            serde_json::json!({ "name": "le guin", "email": "not-an-email" }),
+            "invalid email",
+        ),
+        (
+            serde_json::json!({ "name": "le guin", "email": "ursula_le_guin@gmail.com", "tags": ["no spaces"] }),
+            "invalid tag",
+        ),
+        (serde_json::json!({ "name": "le guin" }), "missing email"),
+    ];
+
+    for (body, description) in test_cases {
+        // act
+        let response = post_api(&app, "/subscriptions", &body).await;
+
+        // assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not reject the subscription with {}.",
+            description
+        );
+        assert_api_error(response, 400, "bad_request").await;
+    }
+}
+
+#[tokio::test]
+async fn subscribers_can_confirm_through_the_api() {
+    // arrange
+    let app = spawn_app().await;
+    let token = subscribe(&app, "ursula_le_guin@gmail.com").await;
+
+    // act
+    let response = post_api(
+        &app,
+        "/subscriptions/confirm",
+        &serde_json::json!({ "subscription_token": token }),
+    )
+    .await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn confirming_with_an_unknown_token_returns_a_json_error() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = post_api(
+        &app,
+        "/subscriptions/confirm",
+        &serde_json::json!({ "subscription_token": "unknown" }),
+    )
+    .await;
+
+    // assert
+    assert_api_error(response, 401, "unauthorized").await;
+}
+
+#[tokio::test]
+async fn malformed_json_bodies_are_rejected_with_a_json_error() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = reqwest::Client::new()
+        .post(&format!("{}/api/v1/subscriptions", app.address))
+        .header("Content-Type", "application/json")
+        .body("{not json")
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    assert_api_error(response, 400, "bad_request").await;
+}
+
+#[tokio::test]
+async fn unknown_endpoints_return_a_json_error() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = reqwest::get(&format!("{}/api/v1/nothing-here", app.address))
+        .await
+        .unwrap();
+
+    // assert
+    assert_api_error(response, 404, "not_found").await;
+}
+
+#[tokio::test]
+async fn admin_endpoints_reject_anonymous_clients_with_a_json_error() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = reqwest::get(&format!("{}/api/v1/admin/subscribers", app.address))
+        .await
+        .unwrap();
+
+    // assert
+    assert_api_error(response, 401, "unauthorized").await;
+}
+
+#[tokio::test]
+async fn admin_endpoints_reject_invalid_credentials() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = reqwest::Client::new()
+        .get(&format!("{}/api/v1/admin/subscribers", app.address))
+        .basic_auth(&app.test_user.username, Some("wrong-password"))
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    assert_api_error(response, 401, "unauthorized").await;
+}
+
+#[tokio::test]
+async fn basic_credentials_are_refused_for_users_with_two_factor_authentication() {
+    // arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        "UPDATE users SET totp_secret = 'MZXW6YTBOI' WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+
+    // act
+    let response = admin_request(&app, reqwest::Method::GET, "/subscribers")
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    assert_api_error(response, 401, "unauthorized").await;
+}
+
+#[tokio::test]
+async fn logged_in_users_can_use_the_admin_endpoints_with_their_session() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+
+    // act
+    let response = app
+        .api_client
+        .get(&format!("{}/api/v1/admin/subscribers", app.address))
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn subscribers_can_be_listed_confirmed_and_deleted() {
+    // arrange
+    let app = spawn_app().await;
+    subscribe(&app, "ursula_le_guin@gmail.com").await;
+    let subscriber_id = subscriber_id(&app, "ursula_le_guin@gmail.com").await;
+
+    // act - part 1 - list
+    let response = admin_request(&app, reqwest::Method::GET, "/subscribers?search=ursula")
+        .send()
+        .await
+        .unwrap();
+
+    // assert - part 1
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["subscribers"][0]["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(body["subscribers"][0]["status"], "pending_confirmation");
+    assert_eq!(body["has_next_page"], false);
+
+    // act - part 2 - confirm
+    let confirm_path = format!("/subscribers/{}/confirm", subscriber_id);
+    let response = admin_request(&app, reqwest::Method::POST, &confirm_path)
+        .send()
+        .await
+        .unwrap();
+
+    // assert - part 2
+    assert_eq!(response.status().as_u16(), 200);
+    let response = admin_request(&app, reqwest::Method::POST, &confirm_path)
+        .send()
+        .await
+        .unwrap();
+    assert_api_error(response, 409, "conflict").await;
+
+    // act - part 3 - delete
+    let subscriber_path = format!("/subscribers/{}", subscriber_id);
+    let response = admin_request(&app, reqwest::Method::DELETE, &subscriber_path)
+        .send()
+        .await
+        .unwrap();
+
+    // assert - part 3
+    assert_eq!(response.status().as_u16(), 204);
+    let response = admin_request(&app, reqwest::Method::DELETE, &subscriber_path)
+        .send()
+        .await
+        .unwrap();
+    assert_api_error(response, 404, "not_found").await;
+}
+
+#[tokio::test]
+async fn retried_admin_requests_with_an_idempotency_key_get_the_saved_response() {
+    // arrange
+    let app = spawn_app().await;
+    subscribe(&app, "ursula_le_guin@gmail.com").await;
+    let subscriber_id = subscriber_id(&app, "ursula_le_guin@gmail.com").await;
+    let confirm_path = format!("/subscribers/{}/confirm", subscriber_id);
+    let subscriber_path = format!("/subscribers/{}", subscriber_id);
+    let confirm = || {
+        admin_request(&app, reqwest::Method::POST, &confirm_path)
+            .header("Idempotency-Key", "confirm-key")
+            .send()
+    };
+    let delete = || {
+        admin_request(&app, reqwest::Method::DELETE, &subscriber_path)
+            .header("Idempotency-Key", "delete-key")
+            .send()
+    };
+
+    // act - part 1 - confirm twice
+    let response = confirm().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let first_body = response.text().await.unwrap();
+    let response = confirm().await.unwrap();
+
+    // assert - part 1: the retry is not a conflict
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.text().await.unwrap(), first_body);
+
+    // act - part 2 - delete twice
+    let response = delete().await.unwrap();
+    assert_eq!(response.status().as_u16(), 204);
+    let response = delete().await.unwrap();
+
+    // assert - part 2: the retry is not a 404
+    assert_eq!(response.status().as_u16(), 204);
+}
+
+#[tokio::test]
+async fn failed_requests_are_not_replayed() {
+    // arrange
+    let app = spawn_app().await;
+    let subscriber_path = format!("/subscribers/{}", Uuid::new_v4());
+
+    // act
+    let response = admin_request(&app, reqwest::Method::DELETE, &subscriber_path)
+        .header("Idempotency-Key", "delete-key")
+        .send()
+        .await
+        .unwrap();
+    assert_api_error(response, 404, "not_found").await;
+
+    // assert: the key was not saved
+    let n_keys = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM idempotency"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_keys, 0);
+}
+
+#[tokio::test]
+async fn published_issues_are_listed_by_the_api() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = publish(&app, "publish-once").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 201);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let issue_id = body["newsletter_issue_id"].as_str().unwrap().to_owned();
+
+    let body: serde_json::Value = reqwest::get(&format!("{}/api/v1/issues", app.address))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["issues"][0]["newsletter_issue_id"], issue_id.as_str());
+    assert_eq!(body["issues"][0]["title"], "Newsletter title");
+
+    let response = reqwest::get(&format!("{}/api/v1/issues/{}", app.address, issue_id))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["html_content"]
+        .as_str()
+        .unwrap()
+        .contains("<p>Newsletter body as HTML</p>"));
+}
+
+#[tokio::test]
+async fn publishing_is_idempotent() {
+    // arrange
+    let app = spawn_app().await;
+    // the subscriber confirms, so that publishing queues a delivery
+    let token = subscribe(&app, "ursula_le_guin@gmail.com").await;
+    post_api(
+        &app,
+        "/subscriptions/confirm",
+        &serde_json::json!({ "subscription_token": token }),
+    )
+    .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    let first = publish(&app, "publish-twice").await;
+    let second = publish(&app, "publish-twice").await;
+
+    // assert
+    assert_eq!(first.status().as_u16(), 201);
+    assert_eq!(second.status().as_u16(), 201);
+    assert_eq!(first.text().await.unwrap(), second.text().await.unwrap());
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 1);
+}
+
+#[tokio::test]
+async fn issues_are_not_published_when_the_response_cannot_be_saved() {
+    // arrange: saving the response fails until the trigger is dropped
+    let app = spawn_app().await;
+    sqlx::query(
+        r#"
+        CREATE FUNCTION reject_saved_responses() RETURNS trigger AS $$
+        BEGIN
+            RAISE EXCEPTION 'responses cannot be saved';
+        END;
+        $$ LANGUAGE plpgsql
+        "#,
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        r#"
+        CREATE TRIGGER reject_saved_responses BEFORE UPDATE ON idempotency
+        FOR EACH ROW EXECUTE FUNCTION reject_saved_responses()
+        "#,
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+
+    // act
+    let failed = publish(&app, "publish-once").await;
+    let n_issues_after_failure =
+        sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+            .fetch_one(&app.connection_pool)
+            .await
+            .unwrap()
+            .count;
+    sqlx::query("DROP TRIGGER reject_saved_responses ON idempotency")
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+    let retried = publish(&app, "publish-once").await;
+
+    // assert
+    assert_eq!(failed.status().as_u16(), 500);
+    assert_eq!(n_issues_after_failure, 0);
+    assert_eq!(retried.status().as_u16(), 201);
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_issues, 1);
+}
+
+#[tokio::test]
+async fn expired_idempotency_keys_are_purged() {
+    // arrange: no subscribers, so publishing sends nothing
+    let app = spawn_app().await;
+    publish(&app, "old-key").await;
+    publish(&app, "recent-key").await;
+    sqlx::query!(
+        "UPDATE idempotency SET created_at = now() - interval '3 days' WHERE idempotency_key = $1",
+        "old-key"
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+
+    // act
+    let rows_purged = purge_expired_idempotency_keys(
+        &app.connection_pool,
+        std::time::Duration::from_secs(2 * 24 * 60 * 60),
+    )
+    .await
+    .unwrap();
+
+    // assert
+    assert_eq!(rows_purged, 1);
+    let keys = sqlx::query!("SELECT idempotency_key FROM idempotency")
+        .fetch_all(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0].idempotency_key, "recent-key");
+}
+
+#[tokio::test]
+async fn publishing_requires_an_idempotency_key() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = admin_request(&app, reqwest::Method::POST, "/newsletters")
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    assert_api_error(response, 400, "bad_request").await;
+}
+
+#[tokio::test]
+async fn publishing_oversized_html_content_is_rejected_with_a_json_error() {
+    // arrange
+    let app = spawn_app_with(|c| c.payload_limits.newsletter_html_bytes = 1024).await;
+
+    // act
+    let response = admin_request(&app, reqwest::Method::POST, "/newsletters")
+        .header("Idempotency-Key", "oversized")
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "a".repeat(2048),
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    assert_api_error(response, 413, "payload_too_large").await;
+}
+
+#[tokio::test]
+async fn json_bodies_over_the_size_limit_are_rejected_with_a_json_error() {
+    // arrange
+    let app = spawn_app_with(|c| c.payload_limits.json_bytes = 1024).await;
+
+    // act
+    let response = post_api(
+        &app,
+        "/subscriptions",
+        &serde_json::json!({ "name": "a".repeat(2048), "email": "ursula@example.com" }),
+    )
+    .await;
+
+    // assert
+    assert_api_error(response, 413, "payload_too_large").await;
+}
+
+/// Sends the preflight request a browser on `origin` makes before posting JSON to the API
+async fn preflight(app: &TestApp, api_path: &str, origin: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .request(
+            reqwest::Method::OPTIONS,
+            &format!("{}/api/v1{}", app.address, api_path),
+        )
+        .header("Origin", origin)
+        .header("Access-Control-Request-Method", "POST")
+        .header("Access-Control-Request-Headers", "content-type")
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+#[tokio::test]
+async fn browsers_on_allowed_origins_can_call_the_api() {
+    // arrange
+    let app = spawn_app_with(|c| c.cors.allowed_origins = vec!["https://example.com".into()]).await;
+
+    // act
+    let response = preflight(&app, "/subscriptions", "https://example.com").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Access-Control-Allow-Origin"],
+        "https://example.com"
+    );
+    let allowed_methods = response.headers()["Access-Control-Allow-Methods"]
+        .to_str()
+        .unwrap();
+    assert!(allowed_methods.contains("POST"));
+}
+
+#[tokio::test]
+async fn browsers_on_other_origins_are_not_allowed_to_call_the_api() {
+    // arrange
+    let app = spawn_app_with(|c| c.cors.allowed_origins = vec!["https://example.com".into()]).await;
+
+    // act
+    let response = reqwest::Client::new()
+        .get(&format!("{}/api/v1/issues", app.address))
+        .header("Origin", "https://evil.example.org")
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .headers()
+        .get("Access-Control-Allow-Origin")
+        .is_none());
+}
+
+#[tokio::test]
+async fn cross_origin_calls_are_not_allowed_by_default() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = preflight(&app, "/subscriptions", "https://example.com").await;
+
+    // assert
+    assert!(response
+        .headers()
+        .get("Access-Control-Allow-Origin")
+        .is_none());
+}
+
+/// Polls the event feed with the default user's Basic credentials
+async fn get_events(app: &TestApp, query: &str) -> reqwest::Response {
+    reqwest::Client::new()
+        .get(format!("{}/api/v1/events{}", app.address, query))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+fn event_types(body: &serde_json::Value) -> Vec<&str> {
+    body["events"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|event| event["type"].as_str().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn changes_to_subscribers_and_issues_are_listed_in_the_event_feed() {
+    // arrange
+    let app = spawn_app().await;
+    let token = subscribe(&app, "ursula_le_guin@gmail.com").await;
+    post_api(
+        &app,
+        "/subscriptions/confirm",
+        &serde_json::json!({ "subscription_token": token }),
+    )
+    .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    publish(&app, "publish-once").await;
+
+    // act
+    let response = get_events(&app, "").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        event_types(&body),
+        [
+            "subscriber.signed_up",
+            "subscriber.confirmed",
+            "issue.published"
+        ]
+    );
+    assert_eq!(
+        body["events"][1]["data"]["email"],
+        "ursula_le_guin@gmail.com"
+    );
+    assert_eq!(body["events"][1]["data"]["status"], "confirmed");
+    assert_eq!(body["events"][2]["data"]["title"], "Newsletter title");
+    assert_eq!(body["next_cursor"], body["events"][2]["id"]);
+    assert_eq!(body["has_more"], false);
+}
+
+#[tokio::test]
+async fn the_event_feed_is_paginated_with_a_cursor() {
+    // arrange
+    let app = spawn_app().await;
+    subscribe(&app, "ursula_le_guin@gmail.com").await;
+    subscribe(&app, "octavia_butler@gmail.com").await;
+
+    // act - part 1 - first page
+    let body: serde_json::Value = get_events(&app, "?limit=1").await.json().await.unwrap();
+
+    // assert - part 1
+    assert_eq!(
+        body["events"][0]["data"]["email"],
+        "ursula_le_guin@gmail.com"
+    );
+    assert_eq!(body["has_more"], true);
+
+    // act - part 2 - next page
+    let query = format!("?limit=1&since={}", body["next_cursor"]);
+    let body: serde_json::Value = get_events(&app, &query).await.json().await.unwrap();
+
+    // assert - part 2
+    assert_eq!(
+        body["events"][0]["data"]["email"],
+        "octavia_butler@gmail.com"
+    );
+    assert_eq!(body["has_more"], false);
+
+    // act - part 3 - nothing new
+    let cursor = body["next_cursor"].clone();
+    let query = format!("?since={}", cursor);
+    let body: serde_json::Value = get_events(&app, &query).await.json().await.unwrap();
+
+    // assert - part 3
+    assert_eq!(body["events"].as_array().unwrap().len(), 0);
+    assert_eq!(body["next_cursor"], cursor);
+}
+
+#[tokio::test]
+async fn erasing_a_subscriber_removes_their_personal_data_from_the_event_feed() {
+    // arrange
+    let app = spawn_app().await;
+    subscribe(&app, "ursula_le_guin@gmail.com").await;
+    let subscriber_id = subscriber_id(&app, "ursula_le_guin@gmail.com").await;
+
+    // act
+    let subscriber_path = format!("/subscribers/{}", subscriber_id);
+    admin_request(&app, reqwest::Method::DELETE, &subscriber_path)
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    let body: serde_json::Value = get_events(&app, "").await.json().await.unwrap();
+    assert_eq!(event_types(&body), ["subscriber.erased"]);
+    assert_eq!(
+        body["events"][0]["data"],
+        serde_json::json!({ "subscriber_id": subscriber_id })
+    );
+}
+
+#[tokio::test]
+async fn the_event_feed_rejects_anonymous_clients() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = reqwest::get(&format!("{}/api/v1/events", app.address))
+        .await
+        .unwrap();
+
+    // assert
+    assert_api_error(response, 401, "unauthorized").await;
+}
+
+#[tokio::test]
+async fn the_event_feed_rejects_invalid_parameters() {
+    // arrange
+    let app = spawn_app().await;
+
+    for query in ["?limit=0", "?limit=1001", "?since=latest"] {
+        // act
+        let response = get_events(&app, query).await;
+
+        // assert
+        assert_api_error(response, 400, "bad_request").await;
+    }
+}
+
+#[tokio::test]
+async fn exported_issues_can_be_imported_as_drafts() {
+    // arrange
+    let app = spawn_app().await;
+    let body: serde_json::Value = publish(&app, "publish-once").await.json().await.unwrap();
+    let issue_id = body["newsletter_issue_id"].as_str().unwrap().to_owned();
+
+    // act - part 1 - export
+    let export_path = format!("/newsletters/{}/export", issue_id);
+    let response = admin_request(&app, reqwest::Method::GET, &export_path)
+        .send()
+        .await
+        .unwrap();
+
+    // assert - part 1
+    assert_eq!(response.status().as_u16(), 200);
+    let document: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(document["title"], "Newsletter title");
+    assert_eq!(document["html_content"], "<p>Newsletter body as HTML</p>");
+    assert_eq!(document["text_content"], "Newsletter body as plain text");
+    assert_eq!(document["newsletter"], "newsletter");
+    assert_eq!(
+        document["metadata"]["newsletter_issue_id"],
+        issue_id.as_str()
+    );
+    assert!(document["metadata"]["published_at"].is_string());
+
+    // act - part 2 - import
+    let response = admin_request(&app, reqwest::Method::POST, "/newsletters/import")
+        .json(&document)
+        .send()
+        .await
+        .unwrap();
+
+    // assert - part 2
+    assert_eq!(response.status().as_u16(), 201);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let draft_id: Uuid = body["draft_id"].as_str().unwrap().parse().unwrap();
+    let draft = sqlx::query!(
+        "SELECT title, html_content, status FROM newsletter_issues WHERE newsletter_issue_id = $1",
+        draft_id
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap();
+    assert_eq!(draft.title, "Newsletter title");
+    assert_eq!(draft.html_content, "<p>Newsletter body as HTML</p>");
+    assert_eq!(draft.status, "draft");
+}
+
+#[tokio::test]
+async fn importing_an_issue_for_an_unknown_newsletter_is_rejected() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = admin_request(&app, reqwest::Method::POST, "/newsletters/import")
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "newsletter": "staging-only",
+        }))
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    assert_api_error(response, 400, "bad_request").await;
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn exporting_an_unknown_issue_returns_404() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let export_path = format!("/newsletters/{}/export", Uuid::new_v4());
+    let response = admin_request(&app, reqwest::Method::GET, &export_path)
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    assert_api_error(response, 404, "not_found").await;
+}
+
+/// Builds a request to the content endpoint of an issue, authenticated with the default user's
+/// Basic credentials
+fn content_request(
+    app: &TestApp,
+    method: reqwest::Method,
+    issue_id: &str,
+) -> reqwest::RequestBuilder {
+    reqwest::Client::new()
+        .request(
+            method,
+            format!("{}/api/v1/newsletters/{}/content", app.address, issue_id),
+        )
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+}
+
+/// Imports a draft through the API and returns its id
+async fn create_draft(app: &TestApp) -> String {
+    let response = admin_request(app, reqwest::Method::POST, "/newsletters/import")
+        .json(&serde_json::json!({
+            "title": "Draft title",
+            "text_content": "Draft body as plain text",
+            "html_content": "<p>Draft body as HTML</p>",
+        }))
+        .send()
+        .await
+        .unwrap();
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["draft_id"].as_str().unwrap().to_owned()
+}
+
+fn content_update(title: &str, version: i32) -> serde_json::Value {
+    serde_json::json!({
+        "title": title,
+        "text_content": "Draft body as plain text",
+        "html_content": "<p>Draft body as HTML</p>",
+        "blocks": {"blocks": [{"type": "paragraph", "text": "Draft body"}]},
+        "version": version,
+    })
+}
+
+#[tokio::test]
+async fn drafts_can_be_read_and_autosaved_through_the_content_api() {
+    // arrange
+    let app = spawn_app().await;
+    let draft_id = create_draft(&app).await;
+
+    // act - part 1
+    let response = content_request(&app, reqwest::Method::GET, &draft_id)
+        .send()
+        .await
+        .unwrap();
+
+    // assert - part 1
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "draft");
+    assert_eq!(body["title"], "Draft title");
+    assert_eq!(body["blocks"], serde_json::Value::Null);
+    assert_eq!(body["version"], 0);
+
+    // act - part 2
+    let response = content_request(&app, reqwest::Method::PUT, &draft_id)
+        .json(&content_update("Autosaved title", 0))
+        .send()
+        .await
+        .unwrap();
+
+    // assert - part 2
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["version"], 1);
+    assert_eq!(body["saved"], true);
+    let body: serde_json::Value = content_request(&app, reqwest::Method::GET, &draft_id)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["title"], "Autosaved title");
+    assert_eq!(body["blocks"]["blocks"][0]["text"], "Draft body");
+
+    // act - part 3: autosaving the same content again changes nothing
+    let response = content_request(&app, reqwest::Method::PUT, &draft_id)
+        .json(&content_update("Autosaved title", 1))
+        .send()
+        .await
+        .unwrap();
+
+    // assert - part 3
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["version"], 1);
+    assert_eq!(body["saved"], false);
+
+    // act - part 4: the following autosaves of the editor are kept as a single revision
+    let response = content_request(&app, reqwest::Method::PUT, &draft_id)
+        .json(&content_update("Autosaved title, again", 1))
+        .send()
+        .await
+        .unwrap();
+
+    // assert - part 4
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["version"], 2);
+    let revisions: Vec<String> = sqlx::query_scalar!("SELECT title FROM issue_revisions")
+        .fetch_all(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(revisions, vec!["Draft title"]);
+
+    // act - part 5: an editor working from an older version
+    let response = content_request(&app, reqwest::Method::PUT, &draft_id)
+        .json(&content_update("Stale title", 1))
+        .send()
+        .await
+        .unwrap();
+
+    // assert - part 5
+    assert_api_error(response, 409, "conflict").await;
+}
+
+#[tokio::test]
+async fn only_drafts_can_be_saved_through_the_content_api() {
+    // arrange
+    let app = spawn_app().await;
+    let body: serde_json::Value = publish(&app, "publish-once").await.json().await.unwrap();
+    let issue_id = body["newsletter_issue_id"].as_str().unwrap().to_owned();
+    let draft_id = create_draft(&app).await;
+
+    // act
+    let published = content_request(&app, reqwest::Method::PUT, &issue_id)
+        .json(&content_update("Edited title", 0))
+        .send()
+        .await
+        .unwrap();
+    let unknown = content_request(&app, reqwest::Method::PUT, &Uuid::new_v4().to_string())
+        .json(&content_update("Edited title", 0))
+        .send()
+        .await
+        .unwrap();
+    let anonymous = reqwest::Client::new()
+        .put(format!(
+            "{}/api/v1/newsletters/{}/content",
+            app.address, draft_id
+        ))
+        .json(&content_update("Edited title", 0))
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    assert_api_error(published, 409, "conflict").await;
+    assert_api_error(unknown, 404, "not_found").await;
+    assert_api_error(anonymous, 401, "unauthorized").await;
+}
+
+#[tokio::test]
+async fn session_writes_to_the_content_api_must_come_from_the_application() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let draft_id = create_draft(&app).await;
+    let content_url = format!("{}/api/v1/newsletters/{}/content", app.address, draft_id);
+
+    // act - part 1: a page on another site making the browser of the admin save the draft
+    let response = app
+        .api_client
+        .put(&content_url)
+        .header("Origin", "https://attacker.example.com")
+        .json(&content_update("Defaced", 0))
+        .send()
+        .await
+        .unwrap();
+
+    // assert - part 1
+    assert_api_error(response, 403, "forbidden").await;
+
+    // act - part 2: the pages of the application
+    let response = app
+        .api_client
+        .put(&content_url)
+        .header("Origin", "http://127.0.0.1")
+        .json(&content_update("Edited title", 0))
+        .send()
+        .await
+        .unwrap();
+
+    // assert - part 2
+    assert_eq!(response.status().as_u16(), 200);
+
+    // act - part 3: credentials are never sent by browsers on their own
+    let response = content_request(&app, reqwest::Method::PUT, &draft_id)
+        .header("Origin", "https://editor.example.com")
+        .json(&content_update("Edited again", 1))
+        .send()
+        .await
+        .unwrap();
+
+    // assert - part 3
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,216 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+/// Subscribes an address to the default newsletter and follows the confirmation link
+async fn subscribe_and_confirm(app: &TestApp, email: &str) {
+    let body = serde_urlencoded::to_string([("name", "le guin"), ("email", email)]).unwrap();
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request).await;
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+/// Creates a sequence with a step on each of the given days and returns its location
+async fn create_sequence(app: &TestApp, day_offsets: &[i32]) -> String {
+    let response = app.post_automation("Welcome series").await;
+    assert_eq!(response.status().as_u16(), 303);
+    let location = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_owned();
+    for day_offset in day_offsets {
+        let response = app
+            .post_automation_step(
+                &location,
+                &serde_json::json!({
+                    "day_offset": day_offset,
+                    "title": format!("Day {}", day_offset),
+                    "text_content": "Hi {{ name }}!",
+                    "html_content": "<p>Hi {{ name }}!</p>",
+                }),
+            )
+            .await;
+        assert_is_redirect_to(&response, &location);
+    }
+    location
+}
+
+/// Moves the confirmation of every subscriber going through a sequence `days` days back
+async fn travel_in_time(app: &TestApp, days: i32) {
+    sqlx::query!(
+        r#"
+        UPDATE automation_schedule
+        SET
+            enrolled_at = enrolled_at - make_interval(days => $1),
+            execute_after = execute_after - make_interval(days => $1)
+        "#,
+        days
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+}
+
+async fn sent_titles(app: &TestApp) -> Vec<String> {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            body["Subject"].as_str().unwrap().to_owned()
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn new_subscribers_receive_the_steps_of_a_sequence_on_their_day() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    create_sequence(&app, &[3, 0]).await;
+    subscribe_and_confirm(&app, "ursula_le_guin@gmail.com").await;
+
+    // act - part 1
+    app.dispatch_due_automation_steps().await;
+
+    // assert - part 1
+    let titles = sent_titles(&app).await;
+    assert_eq!(titles.len(), 2);
+    assert_eq!(titles[1], "Day 0");
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "ursula_le_guin@gmail.com");
+    assert_eq!(body["TextBody"], "Hi le guin!");
+
+    // act - part 2
+    travel_in_time(&app, 2).await;
+    app.dispatch_due_automation_steps().await;
+
+    // assert - part 2
+    assert_eq!(sent_titles(&app).await.len(), 2);
+
+    // act - part 3
+    travel_in_time(&app, 1).await;
+    app.dispatch_due_automation_steps().await;
+    travel_in_time(&app, 30).await;
+    app.dispatch_due_automation_steps().await;
+
+    // assert - part 3
+    let titles = sent_titles(&app).await;
+    assert_eq!(titles.len(), 3);
+    assert_eq!(titles[2], "Day 3");
+    let n_enrolled = sqlx::query!("SELECT COUNT(*) AS n FROM automation_schedule")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_enrolled, Some(0));
+}
+
+#[tokio::test]
+async fn subscribers_confirmed_before_a_sequence_was_created_do_not_receive_it() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    subscribe_and_confirm(&app, "ursula_le_guin@gmail.com").await;
+    create_sequence(&app, &[0]).await;
+
+    // act
+    app.dispatch_due_automation_steps().await;
+
+    // assert
+    assert_eq!(sent_titles(&app).await.len(), 1);
+}
+
+#[tokio::test]
+async fn subscribers_who_unsubscribe_leave_the_sequence() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    create_sequence(&app, &[1]).await;
+    subscribe_and_confirm(&app, "ursula_le_guin@gmail.com").await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+
+    // act
+    travel_in_time(&app, 1).await;
+    app.dispatch_due_automation_steps().await;
+
+    // assert
+    assert_eq!(sent_titles(&app).await.len(), 1);
+}
+
+#[tokio::test]
+async fn a_sequence_has_at_most_one_step_per_day() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let location = create_sequence(&app, &[0]).await;
+
+    // act
+    app.post_automation_step(
+        &location,
+        &serde_json::json!({
+            "day_offset": 0,
+            "title": "Another one",
+            "text_content": "Hi!",
+            "html_content": "<p>Hi!</p>",
+        }),
+    )
+    .await;
+
+    // assert
+    let html_page = app.get_automation_sequence_html(&location).await;
+    assert!(html_page.contains("The sequence already has a step on day 0."));
+    assert!(html_page.contains("<tr><td>0</td><td>Day 0</td></tr>"));
+    assert!(!html_page.contains("Another one"));
+}
+
+#[tokio::test]
+async fn must_be_logged_in_to_create_a_sequence() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.post_automation("Welcome series").await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+}
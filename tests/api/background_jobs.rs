@@ -0,0 +1,234 @@
+use std::time::Duration;
+
+use email_newsletter::domain::SubscriberEmail;
+use email_newsletter::jobs::{enqueue_job, schedule_recurring_job, Job};
+use uuid::Uuid;
+use wiremock::matchers::{any, body_string_contains};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+
+/// Inserts a subscriber deleted `days_ago` days ago
+async fn insert_deleted_subscriber(app: &TestApp, days_ago: i32) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, newsletter_id, deleted_at)
+        VALUES (
+            $1, $2, 'deleted', now(), 'confirmed',
+            (SELECT newsletter_id FROM newsletters), now() - $3 * interval '1 day'
+        )
+        "#,
+        subscriber_id,
+        format!("{}@example.com", subscriber_id),
+        f64::from(days_ago)
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+async fn count_subscribers(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM subscriptions"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .n
+}
+
+#[tokio::test]
+async fn scheduling_a_recurring_job_again_keeps_a_single_job() {
+    // arrange
+    let app = spawn_app().await;
+    let job = Job::PurgeDeletedSubscribers;
+
+    // act
+    schedule_recurring_job(&app.connection_pool, &job, Duration::from_secs(60))
+        .await
+        .unwrap();
+    schedule_recurring_job(&app.connection_pool, &job, Duration::from_secs(120))
+        .await
+        .unwrap();
+
+    // assert
+    let jobs = sqlx::query!("SELECT repeat_every_seconds FROM background_jobs")
+        .fetch_all(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(jobs.len(), 1);
+    assert_eq!(jobs[0].repeat_every_seconds, Some(120));
+}
+
+#[tokio::test]
+async fn recurring_jobs_run_and_are_rescheduled() {
+    // arrange
+    let app = spawn_app().await;
+    insert_deleted_subscriber(&app, 31).await;
+    insert_deleted_subscriber(&app, 1).await;
+    schedule_recurring_job(
+        &app.connection_pool,
+        &Job::PurgeDeletedSubscribers,
+        Duration::from_secs(3600),
+    )
+    .await
+    .unwrap();
+
+    // act
+    app.run_due_jobs().await;
+
+    // assert
+    assert_eq!(count_subscribers(&app).await, 1);
+    let job =
+        sqlx::query!(r#"SELECT execute_after > now() AS "in_the_future!" FROM background_jobs"#)
+            .fetch_one(&app.connection_pool)
+            .await
+            .unwrap();
+    assert!(job.in_the_future);
+}
+
+#[tokio::test]
+async fn one_off_jobs_are_removed_once_they_have_run() {
+    // arrange
+    let app = spawn_app().await;
+    insert_deleted_subscriber(&app, 31).await;
+    enqueue_job(
+        &app.connection_pool,
+        &Job::PurgeDeletedSubscribers,
+        chrono::Utc::now(),
+    )
+    .await
+    .unwrap();
+
+    // act
+    app.run_due_jobs().await;
+
+    // assert
+    assert_eq!(count_subscribers(&app).await, 0);
+    let n_jobs = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM background_jobs"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_jobs, 0);
+}
+
+#[tokio::test]
+async fn jobs_are_not_run_before_they_are_due() {
+    // arrange
+    let app = spawn_app().await;
+    insert_deleted_subscriber(&app, 31).await;
+    enqueue_job(
+        &app.connection_pool,
+        &Job::PurgeDeletedSubscribers,
+        chrono::Utc::now() + chrono::Duration::hours(1),
+    )
+    .await
+    .unwrap();
+
+    // act
+    app.run_due_jobs().await;
+
+    // assert
+    assert_eq!(count_subscribers(&app).await, 1);
+}
+
+#[tokio::test]
+async fn jobs_with_an_unknown_payload_are_parked() {
+    // arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO background_jobs (job_id, job_type, payload)
+        VALUES ($1, 'retired_job', '{"type": "retired_job"}')
+        "#,
+        Uuid::new_v4()
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+
+    // act
+    app.run_due_jobs().await;
+
+    // assert
+    let job = sqlx::query!("SELECT failed_at, last_error FROM background_jobs")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert!(job.failed_at.is_some());
+    assert!(job.last_error.is_some());
+}
+
+/// Publishes an issue to `n_subscribers` subscribers and queues its deliveries, without sending
+/// them
+async fn queue_deliveries(app: &TestApp, n_subscribers: i32) {
+    app.seed_confirmed_subscribers(n_subscribers).await;
+    app.default_login().await;
+    app.post_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.enqueue_all_deliveries().await;
+}
+
+#[tokio::test]
+async fn a_backed_up_delivery_queue_is_reported_to_the_alert_email() {
+    // arrange
+    let app = spawn_app_with(|c| {
+        c.delivery_alerts.max_queue_depth = 2;
+        c.delivery_alerts.alert_email =
+            Some(SubscriberEmail::parse("admin@example.com".into()).unwrap());
+    })
+    .await;
+    queue_deliveries(&app, 3).await;
+    Mock::given(body_string_contains("admin@example.com"))
+        .and(body_string_contains("3 deliveries are queued"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    enqueue_job(
+        &app.connection_pool,
+        &Job::CheckDeliveryBacklog,
+        chrono::Utc::now(),
+    )
+    .await
+    .unwrap();
+    app.run_due_jobs().await;
+
+    // assert: the mock verifies the alert was sent on drop
+}
+
+#[tokio::test]
+async fn no_alert_is_sent_while_the_queue_is_within_the_thresholds() {
+    // arrange
+    let app = spawn_app_with(|c| {
+        c.delivery_alerts.alert_email =
+            Some(SubscriberEmail::parse("admin@example.com".into()).unwrap());
+    })
+    .await;
+    queue_deliveries(&app, 3).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    enqueue_job(
+        &app.connection_pool,
+        &Job::CheckDeliveryBacklog,
+        chrono::Utc::now(),
+    )
+    .await
+    .unwrap();
+    app.run_due_jobs().await;
+
+    // assert: the mock verifies no email was sent on drop
+}
@@ -0,0 +1,68 @@
+use email_newsletter::templates::HexColor;
+
+use crate::helpers::{spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn the_stylesheet_is_served_from_the_static_directory() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app
+        .api_client
+        .get(&format!("{}/static/style.css", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let content_type = response.headers().get("Content-Type").unwrap();
+    assert!(content_type.to_str().unwrap().starts_with("text/css"));
+    assert!(response.text().await.unwrap().contains("--primary-color"));
+}
+
+#[tokio::test]
+async fn static_assets_can_be_cached_and_are_compressed() {
+    // arrange
+    let app = spawn_app_with(|c| c.http_caching.static_max_age_seconds = 600).await;
+
+    // act
+    let response = app
+        .api_client
+        .get(&format!("{}/static/style.css", &app.address))
+        .header("Accept-Encoding", "gzip")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let headers = response.headers();
+    assert_eq!(headers["Cache-Control"], "public, max-age=600");
+    assert_eq!(headers["Content-Encoding"], "gzip");
+    assert!(headers.contains_key("ETag"));
+}
+
+#[tokio::test]
+async fn pages_show_the_configured_name_logo_and_colors() {
+    // arrange
+    let app = spawn_app_with(|c| {
+        c.branding.name = "The Weekly Gazette".into();
+        c.branding.logo_url = Some("/static/logo.svg".into());
+        c.branding.primary_color = HexColor::parse("#aa3300".into()).unwrap();
+    })
+    .await;
+
+    // act
+    let login_page = app.get_login_html().await;
+    let archive_page = app.get_issues_archive_html().await;
+
+    // assert
+    for html_page in [login_page, archive_page] {
+        assert!(html_page.contains("<span>The Weekly Gazette</span>"));
+        assert!(html_page.contains(r#"<img src="/static/logo.svg""#));
+        assert!(html_page.contains("--primary-color: #aa3300;"));
+        assert!(html_page.contains(r#"<link rel="stylesheet" href="/static/style.css">"#));
+    }
+}
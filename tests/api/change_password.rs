@@ -10,7 +10,7 @@ async fn user_must_be_logged_in_to_see_change_password_form() {
     let response = app.get_change_password().await;
 
     // assert
-    assert_is_redirect_to(&response, "/login");
+    assert_is_redirect_to(&response, "/login?next=%2Fadmin%2Fpassword");
 }
 
 #[tokio::test]
@@ -61,7 +61,7 @@ async fn new_password_fields_must_match() {
     // act 3: follow the redirect
     let html_page = app.get_change_password_html().await;
     assert!(html_page.contains(
-        "<p><i>You entered two different new passwords - \
+        "<p class=\"flash flash-error\"><i>You entered two different new passwords - \
             the field values must match.</i></p>"
     ));
 }
@@ -93,7 +93,8 @@ async fn current_password_must_be_valid() {
 
     // act 3: follow the redirect
     let html_page = app.get_change_password_html().await;
-    assert!(html_page.contains("<p><i>The current password is incorrect.</i></p>"));
+    assert!(html_page
+        .contains(r#"<p class="flash flash-error"><i>The current password is incorrect.</i></p>"#));
 }
 
 #[tokio::test]
@@ -122,7 +123,9 @@ async fn current_password_is_too_short() {
 
     // act 3: follow the redirect
     let html_page = app.get_change_password_html().await;
-    assert!(html_page.contains("<p><i>Password must be at least 12 characters.</i></p>"));
+    assert!(html_page.contains(
+        r#"<p class="flash flash-error"><i>Password must be at least 12 characters.</i></p>"#
+    ));
 }
 
 #[tokio::test]
@@ -152,7 +155,9 @@ async fn current_password_is_too_long() {
 
     // act 3: follow the redirect
     let html_page = app.get_change_password_html().await;
-    assert!(html_page.contains("<p><i>Password must be no more than 128 characters.</i></p>"));
+    assert!(html_page.contains(
+        r#"<p class="flash flash-error"><i>Password must be no more than 128 characters.</i></p>"#
+    ));
 }
 
 #[tokio::test]
@@ -182,7 +187,8 @@ async fn full_change_password_flow() {
 
     // act 3: follow the redirect
     let html_page = app.get_change_password_html().await;
-    assert!(html_page.contains("<p><i>Your password has been changed.</i></p>"));
+    assert!(html_page
+        .contains(r#"<p class="flash flash-success"><i>Your password has been changed.</i></p>"#));
 
     // act 4: logout
     let response = app.post_logout().await;
@@ -190,7 +196,8 @@ async fn full_change_password_flow() {
 
     // act 5: follow the redirect
     let html_page = app.get_login_html().await;
-    assert!(html_page.contains("<p><i>You have successfully logged out.</i></p>"));
+    assert!(html_page
+        .contains(r#"<p class="flash flash-info"><i>You have successfully logged out.</i></p>"#));
 
     // act 6: log in using new password
     let response = app
@@ -201,3 +208,45 @@ async fn full_change_password_flow() {
         .await;
     assert_is_redirect_to(&response, "/admin/dashboard");
 }
+
+#[tokio::test]
+async fn changing_password_logs_out_every_other_session() {
+    // arrange: the test user is logged in on two devices
+    let app = spawn_app().await;
+    app.default_login().await;
+    let other_device = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    other_device
+        .post(&format!("{}/login", app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .send()
+        .await
+        .unwrap();
+    let new_password = Uuid::new_v4().to_string();
+
+    // act
+    let response = app
+        .post_change_password(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "new_password_check": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/password");
+
+    // assert: the session used to change the password survives
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = other_device
+        .get(&format!("{}/admin/dashboard", app.address))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login?next=%2Fadmin%2Fdashboard");
+}
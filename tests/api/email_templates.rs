@@ -0,0 +1,200 @@
+use uuid::Uuid;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+
+const HTML_LAYOUT: &str = "<h1>Weekly: {{ title }}</h1>\n{{ content }}\n<p>See you next week</p>";
+const TEXT_LAYOUT: &str = "Weekly: {{ title }}\n\n{{ content }}\n\nSee you next week";
+
+/// Creates a template with the layouts above, returning its id
+async fn create_email_template(app: &TestApp, name: &str) -> Uuid {
+    let response = app
+        .post_email_template(None, name, HTML_LAYOUT, TEXT_LAYOUT)
+        .await;
+    assert_is_redirect_to(&response, "/admin/templates");
+    sqlx::query_scalar!(
+        "SELECT email_template_id FROM email_templates WHERE name = $1",
+        name
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn admins_can_create_and_edit_email_templates() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+
+    // act - part 1
+    let email_template_id = create_email_template(&app, "Weekly").await;
+
+    // assert - part 1
+    let html_page = app.get_email_templates_html().await;
+    assert!(html_page.contains("The template Weekly has been created."));
+    assert!(html_page.contains(&format!(
+        r#"<tr><td>Weekly</td><td><a href="/admin/templates/{}">Edit</a></td></tr>"#,
+        email_template_id
+    )));
+
+    // act - part 2
+    let response = app
+        .post_email_template(
+            Some(email_template_id),
+            "Weekly digest",
+            "<div>{{ content }}</div>",
+            TEXT_LAYOUT,
+        )
+        .await;
+
+    // assert - part 2
+    assert_is_redirect_to(
+        &response,
+        &format!("/admin/templates/{}", email_template_id),
+    );
+    let html_page = app.get_email_template_html(email_template_id).await;
+    assert!(html_page.contains("The template Weekly digest has been saved."));
+    assert!(html_page.contains("&lt;div&gt;{{ content }}&lt;/div&gt;"));
+
+    // act - part 3
+    app.post_email_template(None, "Weekly digest", HTML_LAYOUT, TEXT_LAYOUT)
+        .await;
+
+    // assert - part 3
+    let html_page = app.get_email_templates_html().await;
+    assert!(html_page.contains("There already is a template named Weekly digest."));
+}
+
+#[tokio::test]
+async fn layouts_must_say_where_the_content_goes() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+
+    // act
+    let response = app
+        .post_email_template(None, "Weekly", "<h1>{{ title }}</h1>", TEXT_LAYOUT)
+        .await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/templates");
+    let html_page = app.get_email_templates_html().await;
+    assert!(html_page.contains("The HTML layout must contain {{ content }}"));
+    let n_templates: Option<i64> = sqlx::query_scalar!("SELECT count(*) FROM email_templates")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_templates, Some(0));
+}
+
+#[tokio::test]
+async fn issues_are_published_into_their_email_template() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let email_template_id = create_email_template(&app, "Weekly").await;
+
+    // act
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Issue #1",
+            "text_content": "Hello from the text",
+            "html_content": "<p>Hello from the HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "email_template": email_template_id.to_string(),
+        }))
+        .await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let issue =
+        sqlx::query!("SELECT text_content, html_content, email_template_id FROM newsletter_issues")
+            .fetch_one(&app.connection_pool)
+            .await
+            .unwrap();
+    assert_eq!(issue.email_template_id, Some(email_template_id));
+    assert!(issue.html_content.contains(
+        "<h1>Weekly: Issue #1</h1>\n<p>Hello from the HTML</p>\n<p>See you next week</p>"
+    ));
+    assert!(issue
+        .text_content
+        .contains("Weekly: Issue #1\n\nHello from the text\n\nSee you next week"));
+}
+
+#[tokio::test]
+async fn drafts_keep_the_email_template_they_are_written_for() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let email_template_id = create_email_template(&app, "Weekly").await;
+
+    // act
+    let response = app
+        .post_newsletter_draft(&serde_json::json!({
+            "title": "Issue #1",
+            "text_content": "Hello from the text",
+            "html_content": "<p>Hello from the HTML</p>",
+            "email_template": email_template_id.to_string(),
+        }))
+        .await;
+
+    // assert
+    let location = response.headers()["Location"].to_str().unwrap();
+    let draft_id: Uuid = location.rsplit('/').next().unwrap().parse().unwrap();
+    let html_page = app
+        .get_newsletter_draft(draft_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains(&format!(
+        r#"<option value="{}" selected>Weekly</option>"#,
+        email_template_id
+    )));
+    // the content of the draft is kept as written, and only put into the template on publication
+    let html_content = sqlx::query_scalar!("SELECT html_content FROM newsletter_issues")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert!(!html_content.contains("See you next week"));
+}
+
+#[tokio::test]
+async fn unknown_email_templates_are_rejected() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+
+    // act
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Issue #1",
+            "text_content": "Hello from the text",
+            "html_content": "<p>Hello from the HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "email_template": Uuid::new_v4().to_string(),
+        }))
+        .await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("The email template no longer exists."));
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_create_email_templates() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app
+        .post_email_template(None, "Weekly", HTML_LAYOUT, TEXT_LAYOUT)
+        .await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+}
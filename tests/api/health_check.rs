@@ -1,4 +1,5 @@
 use crate::helpers::spawn_app;
+use email_newsletter::jobs::Heartbeat;
 
 #[tokio::test]
 async fn health_check_responds_200() {
@@ -17,3 +18,68 @@ async fn health_check_responds_200() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 }
+
+async fn get_worker_health(test_app: &crate::helpers::TestApp) -> reqwest::Response {
+    test_app
+        .api_client
+        .get(&format!("{}/health/worker", &test_app.address))
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+#[tokio::test]
+async fn worker_health_is_503_without_any_heartbeat() {
+    // arrange
+    let test_app = spawn_app().await;
+
+    // act
+    let response = get_worker_health(&test_app).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 503);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "missing");
+}
+
+#[tokio::test]
+async fn worker_health_is_200_after_a_heartbeat() {
+    // arrange
+    let test_app = spawn_app().await;
+    Heartbeat::new()
+        .record(&test_app.connection_pool)
+        .await
+        .unwrap();
+
+    // act
+    let response = get_worker_health(&test_app).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "ok");
+    assert_eq!(body["workers"].as_array().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn worker_health_is_503_when_the_heartbeat_is_stale() {
+    // arrange
+    let test_app = spawn_app().await;
+    Heartbeat::new()
+        .record(&test_app.connection_pool)
+        .await
+        .unwrap();
+    sqlx::query("UPDATE worker_heartbeats SET last_beat_at = now() - interval '10 minutes'")
+        .execute(&test_app.connection_pool)
+        .await
+        .unwrap();
+
+    // act
+    let response = get_worker_health(&test_app).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 503);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "stale");
+    assert!(body["seconds_since_last_beat"].as_i64().unwrap() >= 600);
+}
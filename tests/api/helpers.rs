@@ -2,14 +2,23 @@ use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
 use once_cell::sync::Lazy;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::sync::Arc;
 use uuid::Uuid;
 use wiremock::MockServer;
 
-use email_newsletter::configuration::{get_configuration, DatabaseSettings};
+use email_newsletter::authentication::PasswordHashingPolicy;
+use email_newsletter::automation_worker::try_execute_automation_step;
+use email_newsletter::configuration::{
+    get_configuration, DatabaseSettings, EmailFooterSettings, Settings,
+};
+use email_newsletter::delivery_enqueuer::{try_enqueue_batch, EnqueueOutcome, ENQUEUE_BATCH_SIZE};
 use email_newsletter::email_client::EmailClient;
-use email_newsletter::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use email_newsletter::issue_delivery_worker::try_execute_task;
+use email_newsletter::jobs::{try_execute_job, ExecutionOutcome, JobContext, RetryPolicy};
 use email_newsletter::startup::{get_connection_pool, Application};
+use email_newsletter::subscription_tokens::SubscriptionTokens;
 use email_newsletter::telemetry::{get_tracing_subscriber, init_subscriber};
+use email_newsletter::url_builder::UrlBuilder;
 
 // ensure that the tracing stack is only initialized once
 static TRACING: Lazy<()> = Lazy::new(|| {
@@ -17,11 +26,11 @@ static TRACING: Lazy<()> = Lazy::new(|| {
     let subscriber_name = "test".to_string();
     if std::env::var("TEST_LOG").is_ok() {
         let subscriber =
-            get_tracing_subscriber(subscriber_name, default_filter_level, std::io::stdout);
+            get_tracing_subscriber(subscriber_name, default_filter_level, std::io::stdout, None);
         init_subscriber(subscriber);
     } else {
         let subscriber =
-            get_tracing_subscriber(subscriber_name, default_filter_level, std::io::sink);
+            get_tracing_subscriber(subscriber_name, default_filter_level, std::io::sink, None);
         init_subscriber(subscriber);
     }
 });
@@ -79,22 +88,156 @@ pub struct TestApp {
     pub port: u16,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
-    pub email_client: EmailClient,
+    /// Shared with the application, as when the worker runs in the same process
+    pub email_client: Arc<EmailClient>,
+    pub retry_policy: RetryPolicy,
+    pub job_context: JobContext,
+    /// Signs tokens with the secret of the application, as the links of its emails are
+    pub subscription_tokens: SubscriptionTokens,
+    /// Appended to the emails the workers send, as configured
+    pub email_footer: Option<EmailFooterSettings>,
+    pub password_hashing: PasswordHashingPolicy,
 }
 
 impl TestApp {
+    /// Inserts `n` confirmed subscribers straight into the database, all subscribed at the same
+    /// instant, to test how large lists are handled
+    pub async fn seed_confirmed_subscribers(&self, n: i32) {
+        sqlx::query!(
+            r#"
+            INSERT INTO subscriptions (id, email, name, subscribed_at, status, newsletter_id)
+            SELECT
+                gen_random_uuid(), 'subscriber' || i || '@example.com', 'subscriber ' || i, now(),
+                'confirmed', (SELECT newsletter_id FROM newsletters)
+            FROM generate_series(1, $1) AS i
+            "#,
+            n
+        )
+        .execute(&self.connection_pool)
+        .await
+        .expect("Failed to seed subscribers");
+    }
+
+    /// Queues the deliveries of every published issue, as the delivery worker does in the
+    /// background
+    pub async fn enqueue_all_deliveries(&self) {
+        while let EnqueueOutcome::BatchEnqueued =
+            try_enqueue_batch(&self.connection_pool, ENQUEUE_BATCH_SIZE)
+                .await
+                .unwrap()
+        {}
+    }
+
     pub async fn dispatch_all_pending_emails(&self) {
+        self.enqueue_all_deliveries().await;
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.connection_pool, &self.email_client)
-                    .await
-                    .unwrap()
+            if let ExecutionOutcome::EmptyQueue = try_execute_task(
+                &self.connection_pool,
+                &self.email_client,
+                &self.retry_policy,
+                self.job_context.send_window.as_ref(),
+                &UrlBuilder::new(&self.address),
+                &self.subscription_tokens,
+                self.email_footer.as_ref(),
+                None,
+            )
+            .await
+            .unwrap()
             {
                 break;
             }
         }
     }
 
+    /// Runs every background job that is due, as the worker does before delivering issues
+    pub async fn run_due_jobs(&self) {
+        while let ExecutionOutcome::TaskCompleted =
+            try_execute_job(&self.job_context, &self.retry_policy)
+                .await
+                .unwrap()
+        {}
+    }
+
+    /// Sends every automation step that is due, as the delivery worker does when no issue is
+    /// waiting
+    pub async fn dispatch_due_automation_steps(&self) {
+        while let ExecutionOutcome::TaskCompleted = try_execute_automation_step(
+            &self.connection_pool,
+            &self.email_client,
+            &self.retry_policy,
+            &UrlBuilder::new(&self.address),
+            &self.subscription_tokens,
+            self.email_footer.as_ref(),
+            None,
+        )
+        .await
+        .unwrap()
+        {}
+    }
+
+    /// The token of the unsubscribe links sent to the subscriber with the given address
+    pub async fn unsubscribe_token(&self, email: &str) -> String {
+        let subscriber_id = sqlx::query!("SELECT id FROM subscriptions WHERE email = $1", email)
+            .fetch_one(&self.connection_pool)
+            .await
+            .unwrap()
+            .id;
+        self.subscription_tokens.unsubscribe_token(subscriber_id)
+    }
+
+    /// Stores a token the way they were before tokens were signed, as in the links of emails
+    /// sent back then
+    pub async fn store_legacy_token(&self, subscriber_id: Uuid, token: &str) {
+        sqlx::query!(
+            r#"
+            INSERT INTO subscription_tokens (subscription_token, subscriber_id, expires_at, newsletter_id)
+            SELECT $1, id, now() + interval '7 days', newsletter_id FROM subscriptions WHERE id = $2
+            "#,
+            token,
+            subscriber_id
+        )
+        .execute(&self.connection_pool)
+        .await
+        .unwrap();
+    }
+
+    /// Creates an automation sequence for the default newsletter from the admin panel
+    pub async fn post_automation(&self, name: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/automations", self.address))
+            .form(&[("name", name)])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_automation_step<Body>(
+        &self,
+        sequence_location: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}{}/steps", self.address, sequence_location))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_automation_sequence_html(&self, sequence_location: &str) -> String {
+        self.api_client
+            .get(&format!("{}{}", self.address, sequence_location))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
     /// Gets the logout endpoint
     pub async fn post_logout(&self) -> reqwest::Response {
         self.api_client
@@ -182,6 +325,18 @@ impl TestApp {
     }
 
     /// Posts the provided body to the subscriptions endpoint
+    /// Returns the rendered HTML of the home page, which holds the subscribe form
+    pub async fn get_home_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         self.api_client
             .post(&format!("{}/subscriptions", &self.address))
@@ -192,6 +347,58 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    /// Requests that issues go to `email` from now on, for the subscriber owning `subscription_token`
+    pub async fn post_change_email(
+        &self,
+        subscription_token: &str,
+        email: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/subscriptions/change-email", &self.address))
+            .form(&[("subscription_token", subscription_token), ("email", email)])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_erase_own_subscription(&self, subscription_token: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/subscriptions/erase", &self.address))
+            .form(&[("subscription_token", subscription_token)])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_time_zone(
+        &self,
+        subscription_token: &str,
+        time_zone: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/subscriptions/time-zone", &self.address))
+            .form(&[
+                ("subscription_token", subscription_token),
+                ("time_zone", time_zone),
+            ])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_resend_confirmation(&self, body: String) -> reqwest::Response {
+        self.api_client
+            .post(&format!(
+                "{}/subscriptions/resend-confirmation",
+                &self.address
+            ))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     /// Posts the provided body to the newsletters endpoint
     pub async fn post_newsletter(&self, body: &serde_json::Value) -> reqwest::Response {
         self.api_client
@@ -202,6 +409,122 @@ impl TestApp {
             .expect("Failed to execute request")
     }
 
+    /// Posts the provided body to the newsletter drafts endpoint
+    pub async fn post_newsletter_draft(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/newsletters/drafts", self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Posts the provided body to the newsletter preview endpoint
+    pub async fn post_newsletter_preview(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/newsletters/preview", self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Posts the provided body to the endpoint sending a test of the newsletter to the admin
+    pub async fn post_newsletter_test_send(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/newsletters/test-send", self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Puts the provided JSON body to the endpoint editing a newsletter issue
+    pub async fn put_newsletter(
+        &self,
+        issue_id: Uuid,
+        body: &serde_json::Value,
+    ) -> reqwest::Response {
+        self.api_client
+            .put(&format!("{}/admin/newsletters/{}", self.address, issue_id))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Gets the newsletter form pre-filled with a draft
+    pub async fn get_newsletter_draft(&self, draft_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(&format!(
+                "{}/admin/newsletters/drafts/{}",
+                self.address, draft_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Gets the HTML of the revision history of a draft
+    pub async fn get_draft_revisions_html(&self, draft_id: Uuid) -> String {
+        self.api_client
+            .get(&format!(
+                "{}/admin/newsletters/drafts/{}/revisions",
+                self.address, draft_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Gets the HTML of the changes made to a draft since one of its revisions
+    pub async fn get_draft_revision_html(&self, draft_id: Uuid, revision_id: Uuid) -> String {
+        self.api_client
+            .get(&format!(
+                "{}/admin/newsletters/drafts/{}/revisions/{}",
+                self.address, draft_id, revision_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_restore_draft_revision(
+        &self,
+        draft_id: Uuid,
+        revision_id: Uuid,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(&format!(
+                "{}/admin/newsletters/drafts/{}/revisions/{}/restore",
+                self.address, draft_id, revision_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Gets the HTML of a page of the published newsletter issues list
+    pub async fn get_newsletter_issues_html(&self, page: u32) -> String {
+        self.api_client
+            .get(&format!(
+                "{}/admin/newsletters/issues?page={}",
+                self.address, page
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
     /// Get newsletter endpoint
     pub async fn get_newsletter(&self) -> reqwest::Response {
         self.api_client
@@ -216,6 +539,359 @@ impl TestApp {
         self.get_newsletter().await.text().await.unwrap()
     }
 
+    /// Gets the delivery status page of a newsletter issue
+    pub async fn get_newsletter_status(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(&format!(
+                "{}/admin/newsletters/{}/status",
+                self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Gets the analytics page of a newsletter issue
+    pub async fn get_newsletter_analytics(&self, issue_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(&format!(
+                "{}/admin/newsletters/{}/analytics",
+                self.address, issue_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Posts an action (`pause`, `resume`, `cancel` or `duplicate`) for a newsletter issue
+    pub async fn post_newsletter_action(&self, issue_id: Uuid, action: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!(
+                "{}/admin/newsletters/{}/{}",
+                self.address, issue_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Gets the subscriber management page; `query` is appended to the URL as-is
+    pub async fn get_subscribers(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(&format!("{}/admin/subscribers?{}", self.address, query))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_subscribers_html(&self, query: &str) -> String {
+        self.get_subscribers(query).await.text().await.unwrap()
+    }
+
+    /// Gets the subscriber growth statistics; `query` is appended to the URL as-is
+    pub async fn get_subscriber_stats(&self, query: &str) -> reqwest::Response {
+        self.api_client
+            .get(&format!(
+                "{}/admin/api/stats/subscribers?{}",
+                self.address, query
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_subscribers_export(&self) -> reqwest::Response {
+        self.api_client
+            .get(&format!("{}/admin/subscribers/export", self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_subscriber(&self, subscriber_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .get(&format!(
+                "{}/admin/subscribers/{}",
+                self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_subscriber_html(&self, subscriber_id: Uuid) -> String {
+        self.get_subscriber(subscriber_id)
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn delete_subscriber(&self, subscriber_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .delete(&format!(
+                "{}/admin/subscribers/{}",
+                self.address, subscriber_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_deleted_subscribers_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/subscribers/deleted", self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Posts a subscriber management action (`delete`, `restore` or `confirm`)
+    pub async fn post_subscriber_action(
+        &self,
+        subscriber_id: Uuid,
+        action: &str,
+    ) -> reqwest::Response {
+        self.api_client
+            .post(&format!(
+                "{}/admin/subscribers/{}/{}",
+                self.address, subscriber_id, action
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    /// Gets the HTML of the public issues archive, without any session cookie
+    pub async fn get_issues_archive_html(&self) -> String {
+        reqwest::get(&format!("{}/issues", self.address))
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Gets a public archived issue, without any session cookie
+    pub async fn get_archived_issue(&self, issue_id: Uuid) -> reqwest::Response {
+        reqwest::get(&format!("{}/issues/{}", self.address, issue_id))
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_admin_lists_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/lists", self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Creates a newsletter with the given name and slug from the admin panel
+    pub async fn post_admin_list(&self, name: &str, slug: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/lists", self.address))
+            .form(&[("name", name), ("slug", slug)])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_email_templates_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/templates", self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn get_email_template_html(&self, email_template_id: Uuid) -> String {
+        self.api_client
+            .get(&format!(
+                "{}/admin/templates/{}",
+                self.address, email_template_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Creates an email template from the admin panel, or saves the changes to an existing one
+    pub async fn post_email_template(
+        &self,
+        email_template_id: Option<Uuid>,
+        name: &str,
+        html_layout: &str,
+        text_layout: &str,
+    ) -> reqwest::Response {
+        let url = match email_template_id {
+            Some(email_template_id) => {
+                format!("{}/admin/templates/{}", self.address, email_template_id)
+            }
+            None => format!("{}/admin/templates", self.address),
+        };
+        self.api_client
+            .post(&url)
+            .form(&[
+                ("name", name),
+                ("html_layout", html_layout),
+                ("text_layout", text_layout),
+            ])
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_media_library_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/media", self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    /// Uploads `content` as the `file` field of a multipart form, asking for `accept` in return
+    pub async fn post_media(
+        &self,
+        file_name: &str,
+        content: &[u8],
+        accept: &str,
+    ) -> reqwest::Response {
+        let boundary = "media-upload-boundary";
+        let mut body = format!(
+            "--{}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{}\"\r\n\
+            Content-Type: application/octet-stream\r\n\r\n",
+            boundary, file_name
+        )
+        .into_bytes();
+        body.extend_from_slice(content);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+        self.api_client
+            .post(&format!("{}/admin/media", self.address))
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={}", boundary),
+            )
+            .header("Accept", accept)
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_admin_users_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/users", self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_invite_user<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/admin/users", self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_deactivate_user(&self, user_id: Uuid) -> reqwest::Response {
+        self.api_client
+            .post(&format!(
+                "{}/admin/users/{}/deactivate",
+                self.address, user_id
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_accept_invitation<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.api_client
+            .post(&format!("{}/users/accept-invitation", self.address))
+            .form(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_security_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/admin/security", self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_enable_two_factor(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/admin/security/two-factor", self.address))
+            .form(&serde_json::json!({ "code": code }))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_disable_two_factor(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!(
+                "{}/admin/security/two-factor/disable",
+                self.address
+            ))
+            .form(&serde_json::json!({ "code": code }))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn get_login_two_factor_html(&self) -> String {
+        self.api_client
+            .get(&format!("{}/login/two-factor", self.address))
+            .send()
+            .await
+            .expect("Failed to execute request")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_login_two_factor(&self, code: &str) -> reqwest::Response {
+        self.api_client
+            .post(&format!("{}/login/two-factor", self.address))
+            .form(&serde_json::json!({ "code": code }))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
     /// Extracts confirmation links from mocked email API requests
     pub async fn get_confirmation_links(
         &self,
@@ -248,6 +924,11 @@ impl TestApp {
 
 /// Spawns an app inside a future and returns the configured TestApp.
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+/// Like `spawn_app`, letting the test adjust the configuration before the app is built.
+pub async fn spawn_app_with(customize: impl FnOnce(&mut Settings)) -> TestApp {
     Lazy::force(&TRACING);
     let email_server = MockServer::start().await;
 
@@ -259,6 +940,12 @@ pub async fn spawn_app() -> TestApp {
         c.application.port = 0;
         // User the mock server's uri as email API
         c.email_client.base_url = email_server.uri();
+        // Keep the uploaded images of each test case apart
+        c.media.directory = std::env::temp_dir()
+            .join(format!("media-{}", Uuid::new_v4()))
+            .to_string_lossy()
+            .into_owned();
+        customize(&mut c);
         c
     };
 
@@ -266,9 +953,11 @@ pub async fn spawn_app() -> TestApp {
     configure_database(&configuration.database).await;
 
     // Launch the application as a background task
-    let application = Application::build(configuration.clone())
-        .await
-        .expect("Failed to build application");
+    let email_client = Arc::new(configuration.email_client.clone().client());
+    let application =
+        Application::build_with_email_client(configuration.clone(), email_client.clone())
+            .await
+            .expect("Failed to build application");
     let port = application.port();
     let address = format!("http://127.0.0.1:{}", port);
     tokio::spawn(application.run_until_stopped());
@@ -280,6 +969,11 @@ pub async fn spawn_app() -> TestApp {
         .build()
         .unwrap();
 
+    let job_context = JobContext::new(
+        get_connection_pool(&configuration.database),
+        &configuration,
+        email_client.clone(),
+    );
     let test_app = TestApp {
         address,
         connection_pool: get_connection_pool(&configuration.database),
@@ -287,7 +981,12 @@ pub async fn spawn_app() -> TestApp {
         port,
         test_user: TestUser::generate(),
         api_client: client,
-        email_client: configuration.email_client.client(),
+        email_client,
+        retry_policy: configuration.issue_delivery.retry_policy(),
+        job_context,
+        subscription_tokens: SubscriptionTokens::new(configuration.application.hmac_secret.clone()),
+        email_footer: configuration.email_footer.clone(),
+        password_hashing: configuration.password_hashing.clone(),
     };
     test_app.test_user.store(&test_app.connection_pool).await;
     test_app
@@ -305,9 +1004,11 @@ async fn configure_database(config: &DatabaseSettings) -> PgPool {
         .await
         .expect("Failed to create database");
 
-    let connection_pool = PgPool::connect_with(config.with_db())
-        .await
-        .expect("Failed to connect to postgres.");
+    // migrations must not be cut short by the statement timeout some tests configure
+    let connection_pool =
+        PgPool::connect_with(config.with_db().options([("statement_timeout", "0")]))
+            .await
+            .expect("Failed to connect to postgres.");
 
     sqlx::migrate!("./migrations")
         .run(&connection_pool)
@@ -0,0 +1,227 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use uuid::Uuid;
+
+#[tokio::test]
+async fn archive_lists_published_issues_only() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let issue_id = publish_issue(&app, "Published title").await;
+    save_draft(&app, "Draft title").await;
+
+    // act
+    let html_page = app.get_issues_archive_html().await;
+
+    // assert
+    assert!(html_page.contains(&format!(
+        r#"<a href="/issues/{}">Published title</a>"#,
+        issue_id
+    )));
+    assert!(!html_page.contains("Draft title"));
+}
+
+#[tokio::test]
+async fn archived_issue_renders_its_html_content() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let issue_id = publish_issue(&app, "Published title").await;
+
+    // act
+    let response = app.get_archived_issue(issue_id).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<p>Newsletter body as HTML</p>"));
+}
+
+#[tokio::test]
+async fn scripts_are_removed_from_issues_before_they_are_stored() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+
+    // act
+    app.post_newsletter(&serde_json::json!({
+        "title": "Published title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": r#"<p onmouseover="steal()">Newsletter body<script>steal()</script></p>"#,
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    // assert
+    let issue = sqlx::query!("SELECT html_content FROM newsletter_issues")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert!(issue.html_content.contains("<p>Newsletter body</p>"));
+    assert!(!issue.html_content.contains("steal()"));
+}
+
+#[tokio::test]
+async fn archived_issues_stored_with_scripts_are_rendered_without_them() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let issue_id = publish_issue(&app, "Published title").await;
+    sqlx::query!(
+        "UPDATE newsletter_issues SET html_content = $1 WHERE newsletter_issue_id = $2",
+        r#"<p>Newsletter body as HTML</p><img src="x" onerror="steal()">"#,
+        issue_id
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+
+    // act
+    let html_page = app.get_archived_issue(issue_id).await.text().await.unwrap();
+
+    // assert
+    assert!(html_page.contains(r#"<p>Newsletter body as HTML</p><img src="x">"#));
+    assert!(!html_page.contains("steal()"));
+}
+
+#[tokio::test]
+async fn only_the_configured_tags_are_kept() {
+    // arrange
+    let app = spawn_app_with(|c| c.html_sanitization.allowed_tags = vec!["p".into()]).await;
+    app.default_login().await;
+
+    // act
+    app.post_newsletter(&serde_json::json!({
+        "title": "Published title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter <b>body</b></p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    // assert
+    let issue = sqlx::query!("SELECT html_content FROM newsletter_issues")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert!(issue.html_content.contains("<p>Newsletter body</p>"));
+}
+
+#[tokio::test]
+async fn drafts_and_unknown_issues_are_not_archived() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let draft_id = save_draft(&app, "Draft title").await;
+
+    // act & assert
+    for issue_id in [draft_id, Uuid::new_v4()] {
+        let response = app.get_archived_issue(issue_id).await;
+        assert_eq!(response.status().as_u16(), 404);
+    }
+}
+
+#[tokio::test]
+async fn archive_pages_can_be_cached_and_revalidated() {
+    // arrange
+    let app = spawn_app_with(|c| c.http_caching.pages_max_age_seconds = 60).await;
+    app.default_login().await;
+    let issue_id = publish_issue(&app, "Published title").await;
+
+    for path in ["/issues".to_string(), format!("/issues/{}", issue_id)] {
+        // act - part 1 - first visit
+        let url = format!("{}{}", app.address, path);
+        let response = reqwest::get(&url).await.unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(response.headers()["Cache-Control"], "public, max-age=60");
+        let etag = response.headers()["ETag"].clone();
+
+        // act - part 2 - revalidate with the tag of the page we hold
+        let response = reqwest::Client::new()
+            .get(&url)
+            .header("If-None-Match", etag.clone())
+            .send()
+            .await
+            .unwrap();
+
+        // assert
+        assert_eq!(response.status().as_u16(), 304);
+        assert_eq!(response.headers()["ETag"], etag);
+        assert!(response.text().await.unwrap().is_empty());
+    }
+}
+
+#[tokio::test]
+async fn archive_pages_change_tag_once_an_issue_is_published() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let url = format!("{}/issues", app.address);
+    let etag = reqwest::get(&url).await.unwrap().headers()["ETag"].clone();
+    publish_issue(&app, "Published title").await;
+
+    // act
+    let response = reqwest::Client::new()
+        .get(&url)
+        .header("If-None-Match", etag.clone())
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_ne!(response.headers()["ETag"], etag);
+    assert!(response.text().await.unwrap().contains("Published title"));
+}
+
+#[tokio::test]
+async fn archive_pages_are_compressed_for_clients_accepting_it() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = reqwest::Client::new()
+        .get(&format!("{}/issues", app.address))
+        .header("Accept-Encoding", "br")
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Encoding"], "br");
+}
+
+async fn publish_issue(app: &TestApp, title: &str) -> Uuid {
+    let body = serde_json::json!({
+        "title": title,
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    app.post_newsletter(&body).await;
+    sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE title = $1",
+        title
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id
+}
+
+async fn save_draft(app: &TestApp, title: &str) -> Uuid {
+    let body = serde_json::json!({
+        "title": title,
+        "text_content": "Draft body as plain text",
+        "html_content": "<p>Draft body as HTML</p>",
+    });
+    app.post_newsletter_draft(&body).await;
+    sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE title = $1",
+        title
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id
+}
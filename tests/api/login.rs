@@ -1,4 +1,7 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app};
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use secrecy::Secret;
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
 
 #[tokio::test]
 async fn an_error_flash_message_is_set_on_failure() {
@@ -17,11 +20,11 @@ async fn an_error_flash_message_is_set_on_failure() {
 
     // act 2: follow the redirect
     let html_page = app.get_login_html().await;
-    assert!(html_page.contains(r#"<p><i>Authentication failed</i></p>"#));
+    assert!(html_page.contains(r#"<p class="flash flash-error"><i>Authentication failed</i></p>"#));
 
     // act 3: reload the login page
     let html_page = app.get_login_html().await;
-    assert!(!html_page.contains(r#"<p><i>Authentication failed</i></p>"#));
+    assert!(!html_page.contains(r#"<p class="flash flash-error"><i>Authentication failed</i></p>"#));
 }
 
 #[tokio::test]
@@ -41,3 +44,328 @@ async fn redirect_to_admin_dashboard_after_login_success() {
     let html_page = app.get_admin_dashboard_html().await;
     assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
 }
+
+#[tokio::test]
+async fn repeated_failures_lock_the_client_out_even_with_the_right_password() {
+    // arrange
+    let app = spawn_app_with(|c| c.login_lockout.max_failures = 3).await;
+    let wrong_password = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password",
+    });
+    for _ in 0..3 {
+        app.post_login(&wrong_password).await;
+    }
+    app.get_login_html().await;
+
+    // act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page
+        .contains(r#"<p class="flash flash-warning"><i>Too many failed login attempts. Try again in 15 minutes.</i></p>"#));
+}
+
+#[tokio::test]
+async fn a_lockout_only_applies_to_the_attacked_account() {
+    // arrange
+    let app = spawn_app_with(|c| c.login_lockout.max_failures = 3).await;
+    for _ in 0..3 {
+        app.post_login(&serde_json::json!({
+            "username": "someone-else",
+            "password": "wrong-password",
+        }))
+        .await;
+    }
+
+    // act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_successful_login_resets_the_failure_count() {
+    // arrange
+    let app = spawn_app_with(|c| c.login_lockout.max_failures = 3).await;
+    let wrong_password = serde_json::json!({
+        "username": &app.test_user.username,
+        "password": "wrong-password",
+    });
+    for _ in 0..2 {
+        app.post_login(&wrong_password).await;
+    }
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": &app.test_user.password,
+    }))
+    .await;
+    app.post_logout().await;
+
+    // act
+    app.post_login(&wrong_password).await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn sessions_expire_after_the_idle_timeout() {
+    // arrange
+    let app = spawn_app_with(|c| c.session.idle_timeout_seconds = 2).await;
+    app.default_login().await;
+    // activity keeps the session alive past the idle timeout
+    for _ in 0..2 {
+        tokio::time::sleep(std::time::Duration::from_millis(1200)).await;
+        let response = app.get_admin_dashboard().await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // act
+    tokio::time::sleep(std::time::Duration::from_millis(2500)).await;
+    let response = app.get_admin_dashboard().await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login?next=%2Fadmin%2Fdashboard");
+}
+
+#[tokio::test]
+async fn sessions_expire_after_their_ttl_even_when_active() {
+    // arrange
+    let app = spawn_app_with(|c| c.session.ttl_seconds = 1).await;
+    app.default_login().await;
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // act
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    let response = app.get_admin_dashboard().await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login?next=%2Fadmin%2Fdashboard");
+}
+
+/// The persistent login token set by a response
+fn remember_me_token(response: &reqwest::Response) -> String {
+    response
+        .cookies()
+        .find(|cookie| cookie.name() == "remember_me")
+        .map(|cookie| cookie.value().to_string())
+        .expect("No remember_me cookie was set")
+}
+
+/// Requests the admin dashboard with a persistent login token and no session
+async fn get_admin_dashboard_with_token(app: &TestApp, token: &str) -> reqwest::Response {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(&format!("{}/admin/dashboard", app.address))
+        .header("Cookie", format!("remember_me={}", token))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn remembered_users_are_logged_back_in_once_their_session_expires() {
+    // arrange
+    let app = spawn_app_with(|c| c.session.ttl_seconds = 1).await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+            "remember_me": true,
+        }))
+        .await;
+    let token = remember_me_token(&response);
+
+    // act
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    let response = app.get_admin_dashboard().await;
+
+    // assert: the token is rotated on use
+    assert_eq!(response.status().as_u16(), 200);
+    assert_ne!(remember_me_token(&response), token);
+}
+
+#[tokio::test]
+async fn reusing_a_rotated_token_revokes_every_persistent_login() {
+    // arrange
+    let app = spawn_app().await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+            "remember_me": true,
+        }))
+        .await;
+    let stolen_token = remember_me_token(&response);
+    let response = get_admin_dashboard_with_token(&app, &stolen_token).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let rotated_token = remember_me_token(&response);
+
+    // act
+    let response = get_admin_dashboard_with_token(&app, &stolen_token).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login?next=%2Fadmin%2Fdashboard");
+    let response = get_admin_dashboard_with_token(&app, &rotated_token).await;
+    assert_is_redirect_to(&response, "/login?next=%2Fadmin%2Fdashboard");
+}
+
+#[tokio::test]
+async fn logging_out_forgets_the_persistent_login() {
+    // arrange
+    let app = spawn_app().await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+            "remember_me": true,
+        }))
+        .await;
+    let token = remember_me_token(&response);
+
+    // act
+    app.post_logout().await;
+
+    // assert
+    let response = get_admin_dashboard_with_token(&app, &token).await;
+    assert_is_redirect_to(&response, "/login?next=%2Fadmin%2Fdashboard");
+}
+
+async fn stored_password_hash(app: &TestApp) -> String {
+    sqlx::query!(
+        "SELECT password_hash FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap()
+    .password_hash
+    .unwrap()
+}
+
+#[tokio::test]
+async fn outdated_password_hashes_are_upgraded_on_login() {
+    // arrange
+    let app = spawn_app_with(|c| c.password_hashing.iterations = 3).await;
+    assert!(stored_password_hash(&app).await.contains("t=2"));
+
+    // act
+    let response = app.default_login().await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    assert!(stored_password_hash(&app).await.contains("m=15000,t=3,p=1"));
+}
+
+#[tokio::test]
+async fn users_can_still_log_in_once_a_pepper_is_configured() {
+    // arrange
+    let app = spawn_app_with(|c| {
+        c.password_hashing.pepper = Some(Secret::new("a-pepper".to_string()));
+    })
+    .await;
+    let unpeppered_hash = stored_password_hash(&app).await;
+
+    // act
+    let first_login = app.default_login().await;
+    app.post_logout().await;
+    let second_login = app.default_login().await;
+
+    // assert
+    assert_is_redirect_to(&first_login, "/admin/dashboard");
+    assert_is_redirect_to(&second_login, "/admin/dashboard");
+    let peppered_hash = stored_password_hash(&app).await;
+    assert_ne!(peppered_hash, unpeppered_hash);
+    // the new hash cannot be checked without the pepper
+    assert!(Argon2::default()
+        .verify_password(
+            app.test_user.password.as_bytes(),
+            &PasswordHash::new(&peppered_hash).unwrap()
+        )
+        .is_err());
+}
+
+#[tokio::test]
+async fn users_are_sent_back_to_the_page_they_asked_for_once_logged_in() {
+    // arrange
+    let app = spawn_app().await;
+    let response = app.get_subscribers("page=2").await;
+    assert_is_redirect_to(&response, "/login?next=%2Fadmin%2Fsubscribers%3Fpage%3D2");
+
+    // act - part 1 - the login form remembers the page
+    let response = app
+        .api_client
+        .get(&format!(
+            "{}/login?next=%2Fadmin%2Fsubscribers%3Fpage%3D2",
+            app.address
+        ))
+        .send()
+        .await
+        .unwrap();
+    let html_page = response.text().await.unwrap();
+    assert!(html_page
+        .contains(r#"<input type="hidden" name="next" value="/admin/subscribers?page=2">"#));
+
+    // act - part 2 - failed attempts keep it
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "wrong-password",
+            "next": "/admin/subscribers?page=2",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login?next=%2Fadmin%2Fsubscribers%3Fpage%3D2");
+
+    // act - part 3
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+            "next": "/admin/subscribers?page=2",
+        }))
+        .await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/subscribers?page=2");
+}
+
+#[tokio::test]
+async fn users_are_never_sent_outside_the_admin_panel_once_logged_in() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+            "next": "//evil.example.com/admin/dashboard",
+        }))
+        .await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
@@ -1,8 +1,27 @@
 mod admin_dashboard;
+mod admin_stats;
+mod admin_subscribers;
+mod admin_users;
+mod api_v1;
+mod automations;
+mod background_jobs;
+mod branding;
 mod change_password;
+mod email_templates;
 mod health_check;
 mod helpers;
+mod issues_archive;
 mod login;
+mod media;
+mod metrics;
 mod newsletter;
+mod newsletter_lists;
+mod postmark_webhook;
+mod rate_limiting;
 mod subscriptions;
+mod subscriptions_change_email;
 mod subscriptions_confirm;
+mod subscriptions_time_zone;
+mod subscriptions_unsubscribe;
+mod tls;
+mod two_factor;
@@ -0,0 +1,168 @@
+use email_newsletter::configuration::{MediaStorageKind, S3Settings};
+use secrecy::Secret;
+use wiremock::matchers::{header, header_exists, method, path_regex};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with};
+
+/// The signature of a PNG file, followed by some bytes standing in for the image
+fn png_image() -> Vec<u8> {
+    let mut content = b"\x89PNG\r\n\x1a\n".to_vec();
+    content.extend_from_slice(&[0u8; 64]);
+    content
+}
+
+#[tokio::test]
+async fn uploaded_images_are_listed_and_served() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+
+    // act
+    let response = app
+        .post_media("header.png", &png_image(), "text/html")
+        .await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/media");
+    let html_page = app.get_media_library_html().await;
+    assert!(html_page.contains("The image has been uploaded: "));
+    assert!(html_page.contains("<td>header.png</td>"));
+    let url: String = sqlx::query_scalar!("SELECT url FROM media")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert!(html_page.contains(&format!("value=\"{}\"", url)));
+    let media_path = &url[url.find("/media/").unwrap()..];
+    let response = reqwest::get(&format!("{}{}", app.address, media_path))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers()["Content-Type"], "image/png");
+    assert_eq!(response.bytes().await.unwrap(), png_image());
+}
+
+#[tokio::test]
+async fn json_clients_get_the_url_of_the_uploaded_image() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+
+    // act
+    let response = app
+        .post_media("header.png", &png_image(), "application/json")
+        .await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 201);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let media_id = body["media_id"].as_str().unwrap();
+    let url = body["url"].as_str().unwrap();
+    assert!(url.ends_with(&format!("/media/{}.png", media_id)));
+}
+
+#[tokio::test]
+async fn files_that_are_not_images_are_rejected() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    let test_cases = vec![
+        (
+            &b"<svg xmlns=\"http://www.w3.org/2000/svg\"><script>alert(1)</script></svg>"[..],
+            "a script disguised as an image",
+        ),
+        (b"<html><body>Hello</body></html>", "an HTML document"),
+        (b"", "an empty file"),
+    ];
+
+    for (content, description) in test_cases {
+        // act
+        let response = app
+            .post_media("header.png", content, "application/json")
+            .await;
+
+        // assert
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The upload of {} was not rejected.",
+            description
+        );
+    }
+    let n_media: Option<i64> = sqlx::query_scalar!("SELECT count(*) FROM media")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_media, Some(0));
+}
+
+#[tokio::test]
+async fn images_over_the_size_limit_are_rejected() {
+    // arrange
+    let app = spawn_app_with(|c| c.media.max_file_bytes = 32).await;
+    app.default_login().await;
+
+    // act
+    let response = app
+        .post_media("header.png", &png_image(), "application/json")
+        .await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 413);
+}
+
+#[tokio::test]
+async fn images_can_be_stored_in_an_s3_bucket() {
+    // arrange
+    let bucket_server = MockServer::start().await;
+    let endpoint = bucket_server.uri();
+    let app = spawn_app_with(|c| {
+        c.media.storage = MediaStorageKind::S3;
+        c.media.s3 = Some(S3Settings {
+            endpoint,
+            bucket: "newsletter-media".into(),
+            region: "eu-west-1".into(),
+            access_key_id: "AKIDEXAMPLE".into(),
+            secret_access_key: Secret::new("secret".into()),
+            public_base_url: Some("https://media.example.com".into()),
+        });
+    })
+    .await;
+    app.default_login().await;
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/newsletter-media/[0-9a-f-]+\.png$"))
+        .and(header("Content-Type", "image/png"))
+        .and(header_exists("Authorization"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&bucket_server)
+        .await;
+
+    // act
+    let response = app
+        .post_media("header.png", &png_image(), "application/json")
+        .await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 201);
+    let body: serde_json::Value = response.json().await.unwrap();
+    let media_id = body["media_id"].as_str().unwrap();
+    assert_eq!(
+        body["url"],
+        format!("https://media.example.com/{}.png", media_id)
+    );
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_upload_images() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app
+        .post_media("header.png", &png_image(), "text/html")
+        .await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+}
@@ -0,0 +1,229 @@
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use email_newsletter::configuration::CircuitBreakerSettings;
+use email_newsletter::domain::SubscriberEmail;
+use email_newsletter::email_client::{CircuitBreakerPolicy, EmailClient, SendRetryPolicy};
+use email_newsletter::jobs::{enqueue_job, Job};
+use email_newsletter::startup::run_worker_metrics;
+use secrecy::Secret;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, spawn_app_with};
+
+#[tokio::test]
+async fn metrics_report_the_utilization_of_the_connection_pool() {
+    // arrange
+    let app = spawn_app_with(|c| c.database.max_connections = 7).await;
+
+    // act
+    let response = reqwest::get(&format!("{}/metrics", &app.address))
+        .await
+        .expect("Failed to execute request");
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("# TYPE db_pool_connections gauge"));
+    assert!(body.contains(r#"db_pool_connections{state="in_use"} "#));
+    assert!(body.contains(r#"db_pool_connections{state="idle"} "#));
+    assert!(body.contains("db_pool_max_connections 7\n"));
+}
+
+#[tokio::test]
+async fn metrics_report_request_latencies_per_route_and_status() {
+    // arrange
+    let app = spawn_app().await;
+    for issue_id in [uuid::Uuid::new_v4(), uuid::Uuid::new_v4()] {
+        app.get_archived_issue(issue_id).await;
+    }
+    app.post_subscriptions("name=&email=".into()).await;
+
+    // act
+    let response = reqwest::get(&format!("{}/metrics", &app.address))
+        .await
+        .expect("Failed to execute request");
+
+    // assert
+    let body = response.text().await.unwrap();
+    assert!(body.contains("# TYPE http_server_request_duration_seconds histogram"));
+    assert!(body.contains(
+        r#"http_server_request_duration_seconds_count{method="GET",route="/issues/{issue_id}",status="404"} 2"#
+    ));
+    assert!(body.contains(
+        r#"http_server_request_duration_seconds_bucket{method="POST",route="/subscriptions",status="400",le="+Inf"} 1"#
+    ));
+    assert!(body.contains("# TYPE http_server_errors_total counter"));
+}
+
+#[tokio::test]
+async fn queries_running_longer_than_the_statement_timeout_are_cancelled() {
+    // arrange
+    let app = spawn_app_with(|c| c.database.statement_timeout_milliseconds = 100).await;
+
+    // act
+    let fast = sqlx::query("SELECT pg_sleep(0.01)")
+        .execute(&app.connection_pool)
+        .await;
+    let slow = sqlx::query("SELECT pg_sleep(1)")
+        .execute(&app.connection_pool)
+        .await;
+
+    // assert
+    assert!(fast.is_ok());
+    assert!(slow.is_err());
+}
+
+#[tokio::test]
+async fn metrics_report_the_depth_of_the_delivery_queue() {
+    // arrange
+    let app = spawn_app().await;
+    app.seed_confirmed_subscribers(3).await;
+    app.default_login().await;
+    app.post_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.enqueue_all_deliveries().await;
+
+    // act
+    let response = reqwest::get(&format!("{}/metrics", &app.address))
+        .await
+        .expect("Failed to execute request");
+
+    // assert
+    let body = response.text().await.unwrap();
+    assert!(body.contains("# TYPE issue_delivery_queue_depth gauge"));
+    assert!(body.contains("issue_delivery_queue_depth 3\n"));
+    assert!(body.contains("issue_delivery_queue_oldest_task_age_seconds "));
+}
+
+#[tokio::test]
+async fn metrics_report_the_circuit_breaker_trips_of_the_worker() {
+    // arrange
+    let app = spawn_app_with(|c| {
+        c.email_client.circuit_breaker = Some(CircuitBreakerSettings {
+            failure_threshold: 1,
+            open_seconds: 60,
+        })
+    })
+    .await;
+    app.seed_confirmed_subscribers(1).await;
+    app.default_login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+
+    // act
+    app.dispatch_all_pending_emails().await;
+    let response = reqwest::get(&format!("{}/metrics", &app.address))
+        .await
+        .expect("Failed to execute request");
+
+    // assert
+    let body = response.text().await.unwrap();
+    assert!(body.contains("email_client_circuit_breaker_trips_total 1\n"));
+    assert!(body.contains("email_client_circuit_breaker_open 1\n"));
+}
+
+#[tokio::test]
+async fn a_standalone_worker_serves_the_counters_of_its_email_client() {
+    // arrange
+    let app = spawn_app().await;
+    let email_client = Arc::new(
+        EmailClient::new(
+            app.email_server.uri(),
+            SubscriberEmail::parse("worker@example.com".into())
+                .unwrap()
+                .into(),
+            Secret::new("token".into()),
+            Duration::from_secs(1),
+        )
+        .with_resilience(
+            SendRetryPolicy::none(),
+            Some(CircuitBreakerPolicy {
+                failure_threshold: 1,
+                open_for: Duration::from_secs(60),
+            }),
+        ),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+    tokio::spawn(run_worker_metrics(listener, email_client.clone()).unwrap());
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+    let recipient = SubscriberEmail::parse("reader@example.com".into()).unwrap();
+    let outcome = email_client
+        .send_email(&recipient, "Subject", "<p>Body</p>", "Body")
+        .await;
+    assert!(outcome.is_err());
+
+    // act
+    let response = reqwest::get(&format!("{}/metrics", address))
+        .await
+        .expect("Failed to execute request");
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body = response.text().await.unwrap();
+    assert!(body.contains("email_client_circuit_breaker_trips_total 1\n"));
+    assert!(!body.contains("db_pool_connections"));
+}
+
+#[tokio::test]
+async fn metrics_report_the_purges_of_expired_idempotency_keys() {
+    // arrange
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO idempotency (user_id, idempotency_key, created_at)
+        VALUES ($1, 'expired', now() - interval '30 days')
+        "#,
+        app.test_user.user_id
+    )
+    .execute(&app.connection_pool)
+    .await
+    .unwrap();
+    let before = reqwest::get(&format!("{}/metrics", &app.address))
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    enqueue_job(&app.connection_pool, &Job::PurgeIdempotencyKeys, Utc::now())
+        .await
+        .unwrap();
+    app.run_due_jobs().await;
+
+    // act
+    let response = reqwest::get(&format!("{}/metrics", &app.address))
+        .await
+        .expect("Failed to execute request");
+
+    // assert
+    assert!(before.contains("idempotency_keys_purged_total 0\n"));
+    assert!(before.contains("idempotency_cleanup_last_run_timestamp_seconds 0\n"));
+    let body = response.text().await.unwrap();
+    assert!(body.contains("# TYPE idempotency_keys_purged_total counter"));
+    assert!(body.contains("idempotency_keys_purged_total 1\n"));
+    assert!(!body.contains("idempotency_cleanup_last_run_timestamp_seconds 0\n"));
+}
@@ -0,0 +1,225 @@
+use email_newsletter::configuration::SenderSettings;
+use email_newsletter::domain::{NewsletterSlug, SubscriberEmail};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+
+/// Subscribes an address to the newsletter with the given slug and follows the confirmation link
+async fn subscribe_and_confirm(app: &TestApp, email: &str, newsletter: &str) {
+    let body = serde_urlencoded::to_string([
+        ("name", "le guin"),
+        ("email", email),
+        ("newsletter", newsletter),
+    ])
+    .unwrap();
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request).await;
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn admins_can_start_new_newsletters() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+
+    // act - part 1
+    let response = app.post_admin_list("Rust Weekly", "rust-weekly").await;
+
+    // assert - part 1
+    assert_is_redirect_to(&response, "/admin/lists");
+    let html_page = app.get_admin_lists_html().await;
+    assert!(html_page.contains("The newsletter Rust Weekly has been created."));
+    assert!(html_page.contains("<td>Rust Weekly</td><td>rust-weekly</td>"));
+
+    // act - part 2
+    app.post_admin_list("Another one", "rust-weekly").await;
+    let html_page = app.get_admin_lists_html().await;
+
+    // assert - part 2
+    assert!(html_page.contains("The slug rust-weekly is already taken."));
+
+    // act - part 3
+    app.post_admin_list("Bad slug", "Rust Weekly").await;
+    let html_page = app.get_admin_lists_html().await;
+
+    // assert - part 3
+    assert!(html_page.contains("Rust Weekly is not a valid newsletter slug"));
+}
+
+#[tokio::test]
+async fn must_be_logged_in_to_start_a_newsletter() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.post_admin_list("Rust Weekly", "rust-weekly").await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn issues_only_go_to_the_subscribers_of_their_newsletter() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    app.post_admin_list("Rust Weekly", "rust-weekly").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    // the same address can subscribe to both newsletters
+    subscribe_and_confirm(&app, "ursula_le_guin@gmail.com", "").await;
+    subscribe_and_confirm(&app, "ursula_le_guin@gmail.com", "rust-weekly").await;
+    subscribe_and_confirm(&app, "octavia_butler@gmail.com", "").await;
+    let n_sent = app.email_server.received_requests().await.unwrap().len();
+
+    // act
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "newsletter": "rust-weekly",
+            "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        }))
+        .await;
+    app.dispatch_all_pending_emails().await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/newsletters");
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    assert_eq!(email_requests.len(), n_sent + 1);
+    let body: serde_json::Value =
+        serde_json::from_slice(&email_requests.last().unwrap().body).unwrap();
+    assert_eq!(body["To"], "ursula_le_guin@gmail.com");
+    assert_eq!(body["Headers"][0]["Name"], "List-Id");
+    assert_eq!(
+        body["Headers"][0]["Value"],
+        "Rust Weekly <rust-weekly.127.0.0.1>"
+    );
+}
+
+#[tokio::test]
+async fn newsletters_with_their_own_sender_identity_send_from_it() {
+    // arrange
+    let app = spawn_app_with(|c| {
+        let sender = SenderSettings {
+            sender_email: SubscriberEmail::parse("rust-weekly@example.com".into()).unwrap(),
+            sender_name: Some("Rust Weekly".into()),
+            reply_to: Some(SubscriberEmail::parse("editor@example.com".into()).unwrap()),
+            message_stream: Some("broadcast".into()),
+        };
+        let slug = NewsletterSlug::parse("rust-weekly".into()).unwrap();
+        c.email_client.newsletter_senders.insert(slug, sender);
+    })
+    .await;
+    app.default_login().await;
+    app.post_admin_list("Rust Weekly", "rust-weekly").await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    subscribe_and_confirm(&app, "ursula_le_guin@gmail.com", "").await;
+    subscribe_and_confirm(&app, "octavia_butler@gmail.com", "rust-weekly").await;
+    app.post_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "newsletter": "rust-weekly",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+
+    // assert
+    let bodies: Vec<serde_json::Value> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| serde_json::from_slice(&request.body).unwrap())
+        .collect();
+    let [default_confirmation, confirmation, issue] = &bodies[..] else {
+        panic!("Expected three emails, got {}.", bodies.len());
+    };
+    assert_eq!(default_confirmation["From"], "test@gmail.com");
+    assert!(default_confirmation.get("ReplyTo").is_none());
+    for body in [confirmation, issue] {
+        assert_eq!(body["To"], "octavia_butler@gmail.com");
+        assert_eq!(body["From"], r#""Rust Weekly" <rust-weekly@example.com>"#);
+        assert_eq!(body["ReplyTo"], "editor@example.com");
+        assert_eq!(body["MessageStream"], "broadcast");
+    }
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_newsletter_is_rejected_with_400() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app
+        .post_subscriptions(
+            "name=le%20guin&email=ursula_le_guin%40gmail.com&newsletter=nope".into(),
+        )
+        .await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn visitors_pick_a_newsletter_once_there_are_several() {
+    // arrange
+    let app = spawn_app().await;
+    assert!(!app
+        .get_home_html()
+        .await
+        .contains(r#"<select name="newsletter">"#));
+    app.default_login().await;
+
+    // act
+    app.post_admin_list("Rust Weekly", "rust-weekly").await;
+
+    // assert
+    let html_page = app.get_home_html().await;
+    assert!(html_page.contains(r#"<select name="newsletter">"#));
+    assert!(html_page.contains(r#"<option value="rust-weekly">Rust Weekly</option>"#));
+}
+
+#[tokio::test]
+async fn the_newsletter_form_lets_admins_pick_the_newsletter_of_an_issue() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    app.post_admin_list("Rust Weekly", "rust-weekly").await;
+
+    // act
+    let html_page = app.get_newsletter_html().await;
+
+    // assert
+    assert!(html_page.contains(r#"<option value="newsletter" selected>Newsletter</option>"#));
+    assert!(html_page.contains(r#"<option value="rust-weekly">Rust Weekly</option>"#));
+}
@@ -0,0 +1,203 @@
+use email_newsletter::configuration::PostmarkWebhookSettings;
+use secrecy::Secret;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+
+async fn spawn_app_with_webhook() -> TestApp {
+    spawn_app_with(|c| {
+        c.postmark_webhook = Some(PostmarkWebhookSettings {
+            username: "postmark".into(),
+            password: Secret::new("webhook-password".into()),
+        })
+    })
+    .await
+}
+
+async fn post_event(app: &TestApp, password: &str, event: serde_json::Value) -> reqwest::Response {
+    app.api_client
+        .post(&format!("{}/webhooks/postmark", &app.address))
+        .basic_auth("postmark", Some(password))
+        .json(&event)
+        .send()
+        .await
+        .expect("Failed to execute request")
+}
+
+/// Delivers an issue to a single subscriber, through a provider that gives it `message_id`
+async fn deliver_issue(app: &TestApp, message_id: &str) -> Uuid {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "To": "subscriber1@example.com",
+            "SubmittedAt": "2026-10-16T12:01:05-05:00",
+            "MessageID": message_id,
+            "ErrorCode": 0,
+            "Message": "OK"
+        })))
+        .mount(&app.email_server)
+        .await;
+    app.seed_confirmed_subscribers(1).await;
+    app.default_login().await;
+    app.post_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    app.dispatch_all_pending_emails().await;
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+#[tokio::test]
+async fn deliveries_keep_the_message_id_and_submission_time_of_the_provider() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    deliver_issue(&app, "b7bc2f4a-e38e-4336-af7d-e6c392c2f817").await;
+
+    // assert
+    let delivery =
+        sqlx::query!("SELECT provider_message_id, provider_submitted_at FROM issue_deliveries")
+            .fetch_one(&app.connection_pool)
+            .await
+            .unwrap();
+    assert_eq!(
+        delivery.provider_message_id.as_deref(),
+        Some("b7bc2f4a-e38e-4336-af7d-e6c392c2f817")
+    );
+    assert_eq!(
+        delivery.provider_submitted_at.unwrap().to_rfc3339(),
+        "2026-10-16T17:01:05+00:00"
+    );
+}
+
+#[tokio::test]
+async fn delivery_events_confirm_the_delivery_on_the_status_page() {
+    // arrange
+    let app = spawn_app_with_webhook().await;
+    let issue_id = deliver_issue(&app, "message-1").await;
+
+    // act
+    let response = post_event(
+        &app,
+        "webhook-password",
+        serde_json::json!({
+            "RecordType": "Delivery",
+            "MessageID": "message-1",
+            "Recipient": "subscriber1@example.com",
+            "DeliveredAt": "2026-10-16T16:33:54.9070259Z",
+            "Details": "Test delivery webhook details"
+        }),
+    )
+    .await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = app
+        .get_newsletter_status(issue_id)
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("Sent 1 / 1, 1 confirmed delivered, 0 failures"));
+}
+
+#[tokio::test]
+async fn open_events_are_recorded_as_opens_of_the_delivery() {
+    // arrange
+    let app = spawn_app_with_webhook().await;
+    deliver_issue(&app, "message-1").await;
+
+    // act
+    let response = post_event(
+        &app,
+        "webhook-password",
+        serde_json::json!({
+            "RecordType": "Open",
+            "MessageID": "message-1",
+            "Recipient": "subscriber1@example.com",
+            "ReceivedAt": "2026-10-16T16:40:00Z",
+            "FirstOpen": true
+        }),
+    )
+    .await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let opens = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM email_opens"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(opens.n, 1);
+}
+
+#[tokio::test]
+async fn other_events_and_unknown_messages_are_acknowledged() {
+    // arrange
+    let app = spawn_app_with_webhook().await;
+
+    // act
+    let bounce = post_event(
+        &app,
+        "webhook-password",
+        serde_json::json!({ "RecordType": "Bounce", "MessageID": "message-1" }),
+    )
+    .await;
+    let unknown_delivery = post_event(
+        &app,
+        "webhook-password",
+        serde_json::json!({
+            "RecordType": "Delivery",
+            "MessageID": "unknown",
+            "DeliveredAt": "2026-10-16T16:33:54Z"
+        }),
+    )
+    .await;
+
+    // assert
+    assert_eq!(bounce.status().as_u16(), 200);
+    assert_eq!(unknown_delivery.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn events_with_the_wrong_credentials_are_rejected() {
+    // arrange
+    let app = spawn_app_with_webhook().await;
+
+    // act
+    let response = post_event(
+        &app,
+        "wrong-password",
+        serde_json::json!({ "RecordType": "Bounce" }),
+    )
+    .await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_webhook_is_disabled_without_credentials() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = post_event(
+        &app,
+        "webhook-password",
+        serde_json::json!({ "RecordType": "Bounce" }),
+    )
+    .await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 404);
+}
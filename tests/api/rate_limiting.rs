@@ -0,0 +1,152 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app_with, TestApp};
+use email_newsletter::rate_limiting::RateLimit;
+use email_newsletter::reverse_proxy::TrustedProxies;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const LIMIT: RateLimit = RateLimit {
+    max_requests: 2,
+    window_seconds: 3600,
+};
+
+async fn spawn_rate_limited_app() -> TestApp {
+    spawn_app_with(|c| {
+        c.rate_limiting.subscriptions = LIMIT;
+        c.rate_limiting.login = LIMIT;
+    })
+    .await
+}
+
+#[tokio::test]
+async fn subscribing_too_often_returns_429() {
+    // arrange
+    let test_app = spawn_rate_limited_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    // act
+    let mut statuses = Vec::new();
+    for i in 0..3 {
+        let body = format!("name=le%20guin&email=ursula{}%40gmail.com", i);
+        statuses.push(test_app.post_subscriptions(body).await.status().as_u16());
+    }
+
+    // assert
+    assert_eq!(statuses, vec![303, 303, 429]);
+}
+
+#[tokio::test]
+async fn rate_limited_responses_tell_the_client_when_to_retry() {
+    // arrange
+    let test_app = spawn_rate_limited_app().await;
+    let body = serde_json::json!({ "username": "random", "password": "random" });
+    for _ in 0..2 {
+        test_app.post_login(&body).await;
+    }
+
+    // act
+    let response = test_app.post_login(&body).await;
+
+    // assert
+    assert_eq!(429, response.status().as_u16());
+    let retry_after: u64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 3600);
+}
+
+#[tokio::test]
+async fn route_groups_are_limited_separately() {
+    // arrange
+    let test_app = spawn_rate_limited_app().await;
+    let body = serde_json::json!({ "username": "random", "password": "random" });
+    for _ in 0..3 {
+        test_app.post_login(&body).await;
+    }
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    // act
+    let response = test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    // assert
+    assert_is_redirect_to(&response, "/subscriptions/pending");
+}
+
+#[tokio::test]
+async fn pages_that_are_not_rate_limited_stay_available() {
+    // arrange
+    let test_app = spawn_rate_limited_app().await;
+    let body = serde_json::json!({ "username": "random", "password": "random" });
+    for _ in 0..3 {
+        test_app.post_login(&body).await;
+    }
+
+    // act
+    let html = test_app.get_login_html().await;
+
+    // assert
+    assert!(html.contains("<form"));
+}
+
+/// Posts wrong credentials to the login form on behalf of the client at `forwarded_for`
+async fn post_login_forwarded_for(test_app: &TestApp, forwarded_for: &str) -> u16 {
+    test_app
+        .api_client
+        .post(&format!("{}/login", &test_app.address))
+        .header("X-Forwarded-For", forwarded_for)
+        .form(&serde_json::json!({ "username": "random", "password": "random" }))
+        .send()
+        .await
+        .expect("Failed to execute request")
+        .status()
+        .as_u16()
+}
+
+#[tokio::test]
+async fn clients_behind_a_trusted_proxy_are_limited_by_their_own_address() {
+    // arrange
+    let test_app = spawn_app_with(|c| {
+        c.rate_limiting.login = LIMIT;
+        c.application.trusted_proxies = TrustedProxies::new(vec!["127.0.0.1/32".parse().unwrap()]);
+    })
+    .await;
+
+    // act
+    let mut statuses = Vec::new();
+    for _ in 0..3 {
+        statuses.push(post_login_forwarded_for(&test_app, "203.0.113.1").await);
+    }
+    statuses.push(post_login_forwarded_for(&test_app, "203.0.113.2").await);
+
+    // assert
+    assert_eq!(statuses, vec![303, 303, 429, 303]);
+}
+
+#[tokio::test]
+async fn forwarded_addresses_from_untrusted_peers_are_ignored() {
+    // arrange
+    let test_app = spawn_rate_limited_app().await;
+
+    // act
+    let mut statuses = Vec::new();
+    for address in ["203.0.113.1", "203.0.113.2", "203.0.113.3"] {
+        statuses.push(post_login_forwarded_for(&test_app, address).await);
+    }
+
+    // assert
+    assert_eq!(statuses, vec![303, 303, 429]);
+}
@@ -1,9 +1,13 @@
-use crate::helpers::spawn_app;
-use wiremock::matchers::{method, path};
-use wiremock::{Mock, ResponseTemplate};
+use crate::helpers::{assert_is_redirect_to, spawn_app, spawn_app_with, TestApp};
+use email_newsletter::captcha::CaptchaProvider;
+use email_newsletter::configuration::{AttachmentSettings, CaptchaSettings};
+use email_newsletter::domain::EmailAddressMode;
+use secrecy::Secret;
+use wiremock::matchers::{body_string_contains, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
 
 #[tokio::test]
-async fn subscribe_with_valid_form_data_returns_200() {
+async fn subscribe_with_valid_form_data_redirects_to_the_pending_page() {
     let test_app = spawn_app().await;
     let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
 
@@ -17,7 +21,87 @@ async fn subscribe_with_valid_form_data_returns_200() {
     let response = test_app.post_subscriptions(body.to_string()).await;
 
     // assert
-    assert_eq!(200, response.status().as_u16());
+    assert_is_redirect_to(&response, "/subscriptions/pending");
+}
+
+#[tokio::test]
+async fn the_pending_page_tells_new_subscribers_to_check_their_inbox() {
+    // arrange
+    let test_app = spawn_app().await;
+
+    // act
+    let response = reqwest::get(&format!("{}/subscriptions/pending", test_app.address))
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Check your inbox"));
+}
+
+#[tokio::test]
+async fn clients_asking_for_json_get_the_status_of_the_subscription() {
+    // arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    // act
+    let response = test_app
+        .api_client
+        .post(&format!("{}/subscriptions", &test_app.address))
+        .header("Accept", "application/json")
+        .form(&[("name", "le guin"), ("email", "ursula_le_guin@gmail.com")])
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "pending_confirmation");
+}
+
+#[tokio::test]
+async fn clients_asking_for_json_get_errors_as_json() {
+    // arrange
+    let test_app = spawn_app().await;
+
+    // act
+    let response = test_app
+        .api_client
+        .post(&format!("{}/subscriptions", &test_app.address))
+        .header("Accept", "application/json")
+        .form(&[("name", "le guin"), ("email", "not-an-email")])
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["error"].as_str().unwrap().contains("not-an-email"));
+    assert!(uuid::Uuid::parse_str(body["request_id"].as_str().unwrap()).is_ok());
+}
+
+#[tokio::test]
+async fn browsers_get_errors_as_text() {
+    // arrange
+    let test_app = spawn_app().await;
+
+    // act
+    let response = test_app
+        .post_subscriptions("name=le%20guin&email=not-an-email".into())
+        .await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+    let content_type = response.headers()["Content-Type"].to_str().unwrap();
+    assert!(content_type.starts_with("text/plain"));
 }
 
 #[tokio::test]
@@ -45,6 +129,143 @@ async fn subscribe_persists_the_new_subscriber() {
     assert_eq!(saved_subscriber.status, "pending_confirmation")
 }
 
+#[tokio::test]
+async fn subscribe_records_the_consent_of_the_new_subscriber() {
+    // arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    let body = serde_urlencoded::to_string([
+        ("name", "le guin"),
+        ("email", "ursula_le_guin@gmail.com"),
+        ("consent_text_version", "de-2026-10"),
+        ("country", "de"),
+        ("source_url", "https://example.com/blog"),
+    ])
+    .unwrap();
+
+    // act
+    test_app.post_subscriptions(body).await;
+
+    // assert
+    let consent = sqlx::query!(
+        "SELECT ip_address, consent_text_version, country, source_url FROM subscription_consents"
+    )
+    .fetch_one(&test_app.connection_pool)
+    .await
+    .unwrap();
+    assert_eq!(consent.ip_address.as_deref(), Some("127.0.0.1"));
+    assert_eq!(consent.consent_text_version.as_deref(), Some("de-2026-10"));
+    assert_eq!(consent.country.as_deref(), Some("DE"));
+    assert_eq!(
+        consent.source_url.as_deref(),
+        Some("https://example.com/blog")
+    );
+}
+
+#[tokio::test]
+async fn the_page_embedding_the_subscribe_form_is_the_default_source_of_the_consent() {
+    // arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    // act
+    test_app
+        .api_client
+        .post(&format!("{}/subscriptions", &test_app.address))
+        .header("Referer", "https://example.com/about")
+        .form(&[("name", "le guin"), ("email", "ursula_le_guin@gmail.com")])
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    let source_url = sqlx::query_scalar!("SELECT source_url FROM subscription_consents")
+        .fetch_one(&test_app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(source_url.as_deref(), Some("https://example.com/about"));
+}
+
+#[tokio::test]
+async fn subscribe_with_an_invalid_consent_country_returns_400() {
+    // arrange
+    let test_app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&country=Germany";
+
+    // act
+    let response = test_app.post_subscriptions(body.to_string()).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+    let n_subscribers: Option<i64> = sqlx::query_scalar!("SELECT count(*) FROM subscriptions")
+        .fetch_one(&test_app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_subscribers, Some(0));
+}
+
+#[tokio::test]
+async fn subscribe_normalizes_the_name_of_the_new_subscriber() {
+    // arrange
+    let test_app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+    // a decomposed "é", a line break and surrounding whitespace
+    let body = serde_urlencoded::to_string([
+        ("name", "  Rene\u{301}e\n Le Guin "),
+        ("email", "ursula_le_guin@gmail.com"),
+    ])
+    .unwrap();
+
+    // act
+    let response = test_app.post_subscriptions(body).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/subscriptions/pending");
+    let saved = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&test_app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Ren\u{e9}e Le Guin");
+}
+
+#[tokio::test]
+async fn subscribe_persists_the_tags_of_the_new_subscriber() {
+    let test_app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&tags=Fantasy%2C%20sci-fi";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    // act
+    test_app.post_subscriptions(body.to_string()).await;
+
+    // assert
+    let tags: Vec<String> = sqlx::query!("SELECT tag FROM subscriber_tags ORDER BY tag")
+        .fetch_all(&test_app.connection_pool)
+        .await
+        .expect("Failed to fetch saved tags.")
+        .into_iter()
+        .map(|r| r.tag)
+        .collect();
+
+    assert_eq!(tags, vec!["fantasy", "sci-fi"]);
+}
+
 #[tokio::test]
 async fn subscribe_with_missing_form_data_returns_400() {
     // arrange
@@ -77,6 +298,10 @@ async fn subscribe_with_invalid_fields_returns_400() {
         ("name=&email=test%40email.com", "empty name"),
         ("name=test&email=", "empty email"),
         ("name=test&email=invalid-email", "invalid email"),
+        (
+            "name=test&email=test%40email.com&tags=not%20a%20tag",
+            "invalid tag",
+        ),
     ];
 
     for (invalid_body, error_message) in test_cases {
@@ -138,3 +363,417 @@ async fn subscribe_sends_a_confirmation_email_with_a_link() {
 
     assert_eq!(confirmation_links.html, confirmation_links.plain_text)
 }
+
+#[tokio::test]
+async fn confirmation_email_greets_the_subscriber_by_name() {
+    // arrange
+    let test_app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    // act
+    test_app.post_subscriptions(body.to_string()).await;
+
+    // assert
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("Welcome to our newsletter, le guin!"));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("Welcome to our newsletter, le guin!"));
+}
+
+#[tokio::test]
+async fn confirmation_emails_carry_the_configured_attachment() {
+    // arrange
+    let path_to_pdf = std::env::temp_dir().join(format!("{}.pdf", uuid::Uuid::new_v4()));
+    std::fs::write(&path_to_pdf, b"%PDF").unwrap();
+    let test_app = spawn_app_with(|c| {
+        c.confirmation_email.attachment = Some(AttachmentSettings {
+            path: path_to_pdf.to_string_lossy().into_owned(),
+            filename: Some("Getting started.pdf".into()),
+            content_type: "application/pdf".into(),
+        })
+    })
+    .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&test_app.email_server)
+        .await;
+
+    // act
+    test_app.post_subscriptions(body.to_string()).await;
+
+    // assert
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(
+        body["Attachments"],
+        serde_json::json!([{
+            "Name": "Getting started.pdf",
+            "Content": "JVBERg==",
+            "ContentType": "application/pdf",
+        }])
+    );
+    std::fs::remove_file(path_to_pdf).unwrap();
+}
+
+#[tokio::test]
+async fn subscribing_with_a_disposable_address_is_rejected_with_400() {
+    // arrange
+    let test_app =
+        spawn_app_with(|c| c.email_validation.blocked_domains = vec!["mailinator.com".into()])
+            .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // act
+    let response = test_app
+        .post_subscriptions("name=le%20guin&email=ursula%40mailinator.com".into())
+        .await;
+
+    // assert
+    assert_eq!(400, response.status().as_u16());
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_optional(&test_app.connection_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn internationalized_domains_are_only_accepted_in_lenient_mode() {
+    for (mode, expected_status) in [
+        (EmailAddressMode::Strict, 400),
+        (EmailAddressMode::Lenient, 303),
+    ] {
+        // arrange
+        let test_app = spawn_app_with(|c| c.email_validation.address_mode = mode).await;
+        Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .mount(&test_app.email_server)
+            .await;
+        let body =
+            serde_urlencoded::to_string([("name", "le guin"), ("email", "ursula@münchen.de")])
+                .unwrap();
+
+        // act
+        let response = test_app.post_subscriptions(body).await;
+
+        // assert
+        assert_eq!(expected_status, response.status().as_u16(), "{:?}", mode);
+        let saved = sqlx::query!("SELECT email FROM subscriptions")
+            .fetch_optional(&test_app.connection_pool)
+            .await
+            .unwrap();
+        assert_eq!(
+            saved.map(|saved| saved.email),
+            (mode == EmailAddressMode::Lenient).then(|| "ursula@xn--mnchen-3ya.de".to_owned())
+        );
+    }
+}
+
+#[tokio::test]
+async fn subscribing_twice_while_pending_resends_the_confirmation_email() {
+    // arrange
+    let test_app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+
+    // act
+    test_app.post_subscriptions(body.to_string()).await;
+    let response = test_app.post_subscriptions(body.to_string()).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/subscriptions/pending");
+    let n_subscribers = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM subscriptions"#)
+        .fetch_one(&test_app.connection_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_subscribers, 1);
+    // the second email carries a new, working confirmation link
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[1];
+    let confirmation_links = test_app.get_confirmation_links(email_request).await;
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn subscribing_again_once_confirmed_does_nothing() {
+    // arrange
+    let test_app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    test_app.post_subscriptions(body.to_string()).await;
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = test_app.get_confirmation_links(email_request).await;
+    reqwest::get(confirmation_links.html).await.unwrap();
+
+    // act
+    let response = test_app.post_subscriptions(body.to_string()).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/subscriptions/pending");
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribing_again_after_unsubscribing_requires_a_new_confirmation() {
+    // arrange
+    let test_app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&test_app.email_server)
+        .await;
+    test_app.post_subscriptions(body.to_string()).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&test_app.connection_pool)
+        .await
+        .unwrap();
+
+    // act
+    let response = test_app.post_subscriptions(body.to_string()).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/subscriptions/pending");
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&test_app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+/// Extracts the value of the form token from the rendered subscribe form
+fn form_token(home_html: &str) -> String {
+    home_html
+        .split(r#"name="form_token" value=""#)
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .expect("The subscribe form has no form token")
+        .to_string()
+}
+
+#[tokio::test]
+async fn subscriptions_that_fill_in_the_honeypot_are_silently_dropped() {
+    // arrange
+    let test_app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&website=http%3A%2F%2Fspam.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    // act
+    let response = test_app.post_subscriptions(body.to_string()).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/subscriptions/pending");
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_optional(&test_app.connection_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn subscriptions_submitted_faster_than_the_minimum_fill_time_are_silently_dropped() {
+    // arrange
+    let test_app = spawn_app_with(|c| c.bot_protection.min_fill_time_seconds = 60).await;
+    let token = form_token(&test_app.get_home_html().await);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    for form_token in [token.as_str(), "", "1-forged"] {
+        // act
+        let body = serde_urlencoded::to_string([
+            ("name", "le guin"),
+            ("email", "ursula_le_guin@gmail.com"),
+            ("form_token", form_token),
+        ])
+        .unwrap();
+        let response = test_app.post_subscriptions(body).await;
+
+        // assert
+        assert_eq!(
+            303,
+            response.status().as_u16(),
+            "Bots should not be told they were caught (form token: {form_token:?})"
+        );
+    }
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_optional(&test_app.connection_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn subscriptions_from_the_rendered_form_are_accepted_once_the_minimum_fill_time_elapsed() {
+    // arrange
+    let test_app = spawn_app_with(|c| c.bot_protection.min_fill_time_seconds = 1).await;
+    let token = form_token(&test_app.get_home_html().await);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+    tokio::time::sleep(std::time::Duration::from_secs(2)).await;
+
+    // act
+    let body = serde_urlencoded::to_string([
+        ("name", "le guin"),
+        ("email", "ursula_le_guin@gmail.com"),
+        ("website", ""),
+        ("form_token", token.as_str()),
+    ])
+    .unwrap();
+    let response = test_app.post_subscriptions(body).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/subscriptions/pending");
+}
+
+/// Spawns an app requiring a Turnstile captcha, verified against `captcha_server`
+async fn spawn_app_with_captcha(captcha_server: &MockServer) -> TestApp {
+    let verify_url = format!("{}/siteverify", captcha_server.uri());
+    spawn_app_with(|c| {
+        c.captcha = Some(CaptchaSettings {
+            provider: CaptchaProvider::Turnstile,
+            site_key: "test-site-key".into(),
+            secret_key: Secret::new("test-secret-key".into()),
+            verify_url: Some(verify_url),
+            timeout_milliseconds: 1000,
+        })
+    })
+    .await
+}
+
+#[tokio::test]
+async fn subscriptions_with_a_valid_captcha_are_accepted() {
+    // arrange
+    let captcha_server = MockServer::start().await;
+    let test_app = spawn_app_with_captcha(&captcha_server).await;
+
+    Mock::given(path("/siteverify"))
+        .and(method("POST"))
+        .and(body_string_contains("response=valid-token"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({ "success": true })),
+        )
+        .expect(1)
+        .mount(&captcha_server)
+        .await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    // act
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&cf-turnstile-response=valid-token";
+    let response = test_app.post_subscriptions(body.to_string()).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/subscriptions/pending");
+}
+
+#[tokio::test]
+async fn subscriptions_with_an_invalid_or_missing_captcha_are_rejected() {
+    // arrange
+    let captcha_server = MockServer::start().await;
+    let test_app = spawn_app_with_captcha(&captcha_server).await;
+
+    Mock::given(path("/siteverify"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "success": false,
+            "error-codes": ["invalid-input-response"]
+        })))
+        .mount(&captcha_server)
+        .await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&test_app.email_server)
+        .await;
+
+    for body in [
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&h-captcha-response=forged",
+        "name=le%20guin&email=ursula_le_guin%40gmail.com",
+    ] {
+        // act
+        let response = test_app.post_subscriptions(body.to_string()).await;
+
+        // assert
+        assert_eq!(400, response.status().as_u16(), "Accepted body: {body}");
+    }
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_optional(&test_app.connection_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn the_subscribe_form_renders_the_captcha_widget_when_enabled() {
+    // arrange
+    let captcha_server = MockServer::start().await;
+    let test_app = spawn_app_with_captcha(&captcha_server).await;
+
+    // act
+    let html = test_app.get_home_html().await;
+
+    // assert
+    assert!(html.contains(r#"<div class="cf-turnstile" data-sitekey="test-site-key"></div>"#));
+    assert!(!spawn_app()
+        .await
+        .get_home_html()
+        .await
+        .contains("cf-turnstile"));
+}
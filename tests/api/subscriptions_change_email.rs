@@ -0,0 +1,228 @@
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, TestApp};
+
+/// Subscribes and confirms Ursula, returning the token from her emails
+async fn confirmed_subscriber_token(app: &TestApp) -> String {
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_links = app.get_confirmation_links(&email_request).await;
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.unsubscribe_token("ursula_le_guin@gmail.com").await
+}
+
+/// The body of the last email sent, and the address it was sent to
+async fn last_email(app: &TestApp) -> (wiremock::Request, String) {
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let recipient = body["To"].as_str().unwrap().to_owned();
+    (email_request, recipient)
+}
+
+/// Follows the link sent to the current address, returning the email then sent to the new one
+async fn approve_change(app: &TestApp) -> wiremock::Request {
+    let (email_request, _) = last_email(app).await;
+    let approval_links = app.get_confirmation_links(&email_request).await;
+    reqwest::get(approval_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    last_email(app).await.0
+}
+
+async fn subscriber_email(app: &TestApp) -> String {
+    sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .email
+}
+
+#[tokio::test]
+async fn the_address_only_changes_once_approved_and_the_new_one_is_confirmed() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let token = confirmed_subscriber_token(&app).await;
+
+    // act - part 1: request the change
+    let response = app.post_change_email(&token, "ursula@example.com").await;
+
+    // assert - part 1
+    assert_eq!(response.status().as_u16(), 200);
+    let (email_request, recipient) = last_email(&app).await;
+    assert_eq!(recipient, "ursula_le_guin@gmail.com");
+    assert_eq!(subscriber_email(&app).await, "ursula_le_guin@gmail.com");
+
+    // act - part 2: follow the link sent to the current address
+    let approval_links = app.get_confirmation_links(&email_request).await;
+    let response = reqwest::get(approval_links.html).await.unwrap();
+
+    // assert - part 2
+    assert_eq!(response.status().as_u16(), 200);
+    let (email_request, recipient) = last_email(&app).await;
+    assert_eq!(recipient, "ursula@example.com");
+    assert_eq!(subscriber_email(&app).await, "ursula_le_guin@gmail.com");
+
+    // act - part 3: follow the link sent to the new address
+    let confirmation_links = app.get_confirmation_links(&email_request).await;
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // assert - part 3
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_email(&app).await, "ursula@example.com");
+    let n_requests = sqlx::query!(r#"SELECT COUNT(*) AS "count!" FROM email_change_requests"#)
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_requests, 0);
+
+    // act - part 4: the link only works once
+    let response = reqwest::get(confirmation_links.plain_text).await.unwrap();
+
+    // assert - part 4
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn the_token_of_a_forwarded_issue_is_not_enough_to_change_the_address() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let token = confirmed_subscriber_token(&app).await;
+    let n_sent = app.email_server.received_requests().await.unwrap().len();
+
+    // act
+    app.post_change_email(&token, "someone_else@example.com")
+        .await
+        .error_for_status()
+        .unwrap();
+    let change_token = sqlx::query!("SELECT change_token FROM email_change_requests")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .change_token;
+    let response = reqwest::get(format!(
+        "{}/subscriptions/change-email/confirm?change_token={}",
+        app.address, change_token
+    ))
+    .await
+    .unwrap();
+
+    // assert
+    let sent = app.email_server.received_requests().await.unwrap();
+    assert_eq!(sent.len(), n_sent + 1);
+    let (_, recipient) = last_email(&app).await;
+    assert_eq!(recipient, "ursula_le_guin@gmail.com");
+    assert_eq!(response.status().as_u16(), 401);
+    assert_eq!(subscriber_email(&app).await, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn email_change_requests_are_validated() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let token = confirmed_subscriber_token(&app).await;
+
+    // act
+    let unknown_token = app.post_change_email("unknown", "ursula@example.com").await;
+    let invalid_email = app
+        .post_change_email(&token, "definitely-not-an-email")
+        .await;
+
+    // assert
+    assert_eq!(unknown_token.status().as_u16(), 401);
+    assert_eq!(invalid_email.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn nothing_is_sent_to_an_address_that_is_already_subscribed() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let token = confirmed_subscriber_token(&app).await;
+    app.post_subscriptions("name=butler&email=octavia_butler%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+    let n_sent = app.email_server.received_requests().await.unwrap().len();
+
+    // act
+    let response = app
+        .post_change_email(&token, "octavia_butler@gmail.com")
+        .await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        app.email_server.received_requests().await.unwrap().len(),
+        n_sent
+    );
+    assert_eq!(subscriber_email(&app).await, "ursula_le_guin@gmail.com");
+}
+
+#[tokio::test]
+async fn expired_email_change_links_are_rejected_with_410() {
+    // arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let token = confirmed_subscriber_token(&app).await;
+    app.post_change_email(&token, "ursula@example.com").await;
+    let email_request = approve_change(&app).await;
+    let confirmation_links = app.get_confirmation_links(&email_request).await;
+    sqlx::query!("UPDATE email_change_requests SET expires_at = now() - interval '1 minute'")
+        .execute(&app.connection_pool)
+        .await
+        .unwrap();
+
+    // act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 410);
+    assert_eq!(subscriber_email(&app).await, "ursula_le_guin@gmail.com");
+}
@@ -1,4 +1,6 @@
-use crate::helpers::spawn_app;
+use chrono::{Duration, Utc};
+
+use crate::helpers::{spawn_app, spawn_app_with};
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
@@ -41,6 +43,84 @@ async fn the_link_returned_by_subscribe_returns_a_200_if_called() {
     assert_eq!(response.status().as_u16(), 200);
 }
 
+#[tokio::test]
+async fn confirmed_subscribers_see_the_configured_confirmation_page() {
+    // arrange
+    let app = spawn_app_with(|c| {
+        c.confirmation_page.heading = "Welcome aboard!".into();
+        c.confirmation_page.message = "See you on <Monday>.".into();
+        c.confirmation_page.site_url = Some("https://example.com/blog".into());
+    })
+    .await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.to_string()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request).await;
+
+    // act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("<h1>Welcome aboard!</h1>"));
+    assert!(html_page.contains("See you on &lt;Monday&gt;."));
+    assert!(html_page.contains(r#"<a href="https://example.com/blog">"#));
+}
+
+#[tokio::test]
+async fn unknown_confirmation_links_are_rejected_with_a_page() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = reqwest::get(&format!(
+        "{}/subscriptions/confirm?subscription_token=unknown",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 401);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("This confirmation link is not valid."));
+}
+
+#[tokio::test]
+async fn clients_asking_for_json_get_confirmation_errors_as_json() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app
+        .api_client
+        .get(&format!(
+            "{}/subscriptions/confirm?subscription_token=unknown",
+            app.address
+        ))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 401);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(
+        body["error"],
+        "There is no subscriber associated with the provided token."
+    );
+    assert!(body["request_id"].is_string());
+}
+
 #[tokio::test]
 async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     // arrange
@@ -73,3 +153,141 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved_subscirber.name, "le guin");
     assert_eq!(saved_subscirber.status, "confirmed");
 }
+
+#[tokio::test]
+async fn expired_confirmation_links_are_rejected_with_a_resend_form() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.to_string()).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .id;
+    // the link of a confirmation email sent eight days ago
+    let expired_token = app
+        .subscription_tokens
+        .confirmation_token(subscriber_id, Utc::now() - Duration::days(8));
+
+    // act
+    let response = reqwest::get(&format!(
+        "{}/subscriptions/confirm?subscription_token={}",
+        app.address, expired_token
+    ))
+    .await
+    .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 410);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("This confirmation link has expired."));
+    assert!(html_page.contains(r#"action="/subscriptions/resend-confirmation""#));
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn a_resent_confirmation_link_confirms_a_subscriber() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.to_string()).await;
+
+    // act
+    let response = app
+        .post_resend_confirmation("email=ursula_le_guin%40gmail.com".to_string())
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let confirmation_links = app.get_confirmation_links(email_request).await;
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn confirmation_links_sent_before_tokens_were_signed_still_work() {
+    // arrange
+    let app = spawn_app().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.to_string()).await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .id;
+    app.store_legacy_token(subscriber_id, "aLegacyToken0123456789xyz")
+        .await;
+
+    // act
+    let response = reqwest::get(&format!(
+        "{}/subscriptions/confirm?subscription_token=aLegacyToken0123456789xyz",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn resending_a_confirmation_to_an_unknown_address_sends_nothing() {
+    // arrange
+    let app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // act
+    let response = app
+        .post_resend_confirmation("email=nobody%40gmail.com".to_string())
+        .await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,61 @@
+use crate::helpers::{spawn_app, TestApp};
+
+async fn stored_time_zone(app: &TestApp) -> Option<String> {
+    sqlx::query!("SELECT time_zone FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .time_zone
+}
+
+#[tokio::test]
+async fn subscribers_can_set_and_clear_their_time_zone() {
+    // arrange
+    let app = spawn_app().await;
+    app.seed_confirmed_subscribers(1).await;
+    let token = app.unsubscribe_token("subscriber1@example.com").await;
+
+    // act - part 1
+    let response = app.post_time_zone(&token, "Europe/Paris").await;
+
+    // assert - part 1
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        stored_time_zone(&app).await.as_deref(),
+        Some("Europe/Paris")
+    );
+
+    // act - part 2
+    let response = app.post_time_zone(&token, "").await;
+
+    // assert - part 2
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(stored_time_zone(&app).await, None);
+}
+
+#[tokio::test]
+async fn unknown_time_zones_are_rejected() {
+    // arrange
+    let app = spawn_app().await;
+    app.seed_confirmed_subscribers(1).await;
+    let token = app.unsubscribe_token("subscriber1@example.com").await;
+
+    // act
+    let response = app.post_time_zone(&token, "Europe/Atlantis").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+    assert_eq!(stored_time_zone(&app).await, None);
+}
+
+#[tokio::test]
+async fn setting_a_time_zone_requires_a_valid_token() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.post_time_zone("not-a-token", "Europe/Paris").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 401);
+}
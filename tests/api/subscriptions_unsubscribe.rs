@@ -0,0 +1,170 @@
+use email_newsletter::domain::SubscriptionStatus;
+use email_newsletter::subscription_events::get_subscription_history;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, TestApp};
+
+/// Subscribes, confirms and returns the id and token of the subscriber
+async fn create_confirmed_subscriber(app: &TestApp) -> (Uuid, String) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_links(email_request).await.html;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap()
+        .id;
+    let token = app.subscription_tokens.unsubscribe_token(subscriber_id);
+    (subscriber_id, token)
+}
+
+/// The token of the confirmation link sent to the only subscriber
+async fn confirmation_token(app: &TestApp) -> String {
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    app.get_confirmation_links(email_request)
+        .await
+        .html
+        .query_pairs()
+        .find(|(name, _)| name == "subscription_token")
+        .map(|(_, token)| token.into_owned())
+        .unwrap()
+}
+
+async fn unsubscribe(app: &TestApp, token: &str) -> reqwest::Response {
+    reqwest::get(&format!(
+        "{}/subscriptions/unsubscribe?subscription_token={}",
+        app.address, token
+    ))
+    .await
+    .unwrap()
+}
+
+#[tokio::test]
+async fn unsubscribing_without_token_is_rejected_with_400() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = reqwest::get(&format!("{}/subscriptions/unsubscribe", app.address))
+        .await
+        .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[tokio::test]
+async fn unsubscribing_with_an_unknown_token_is_rejected_with_401() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = reqwest::get(&format!(
+        "{}/subscriptions/unsubscribe?subscription_token=unknown",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    // assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn confirmation_links_cannot_be_used_to_unsubscribe() {
+    // arrange
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let token = confirmation_token(&app).await;
+
+    // act
+    let response = unsubscribe(&app, &token).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn unsubscribe_links_sent_before_tokens_were_signed_still_work() {
+    // arrange
+    let app = spawn_app().await;
+    let (subscriber_id, _) = create_confirmed_subscriber(&app).await;
+    app.store_legacy_token(subscriber_id, "aLegacyToken0123456789xyz")
+        .await;
+
+    // act
+    let response = unsubscribe(&app, "aLegacyToken0123456789xyz").await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.connection_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn every_status_change_is_recorded_in_the_subscription_history() {
+    // arrange
+    let app = spawn_app().await;
+    let (subscriber_id, token) = create_confirmed_subscriber(&app).await;
+
+    // act
+    let response = unsubscribe(&app, &token).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let history = get_subscription_history(&app.connection_pool, subscriber_id)
+        .await
+        .unwrap();
+    let changes: Vec<_> = history
+        .iter()
+        .map(|change| (change.from_status, change.to_status))
+        .collect();
+    assert_eq!(
+        changes,
+        [
+            (None, SubscriptionStatus::PendingConfirmation),
+            (
+                Some(SubscriptionStatus::PendingConfirmation),
+                SubscriptionStatus::Confirmed
+            ),
+            (
+                Some(SubscriptionStatus::Confirmed),
+                SubscriptionStatus::Unsubscribed
+            ),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn unsubscribing_twice_records_a_single_status_change() {
+    // arrange
+    let app = spawn_app().await;
+    let (subscriber_id, token) = create_confirmed_subscriber(&app).await;
+    unsubscribe(&app, &token).await;
+
+    // act
+    let response = unsubscribe(&app, &token).await;
+
+    // assert
+    assert_eq!(response.status().as_u16(), 200);
+    let history = get_subscription_history(&app.connection_pool, subscriber_id)
+        .await
+        .unwrap();
+    assert_eq!(history.len(), 3);
+}
@@ -0,0 +1,70 @@
+use email_newsletter::configuration::{get_configuration, TlsSettings};
+use email_newsletter::startup::Application;
+
+/// Serves the application over HTTPS with a self-signed certificate, returning its HTTPS and
+/// HTTP redirect ports
+async fn spawn_https_app() -> (u16, u16) {
+    let mut configuration = get_configuration().expect("Failed to read configuration");
+    configuration.application.port = 0;
+    configuration.application.tls = Some(TlsSettings {
+        certificate_path: "tests/fixtures/tls/cert.pem".into(),
+        private_key_path: "tests/fixtures/tls/key.pem".into(),
+        http_redirect_port: Some(0),
+    });
+    let application = Application::build(configuration)
+        .await
+        .expect("Failed to build application");
+    let ports = (
+        application.port(),
+        application.http_redirect_port().unwrap(),
+    );
+    tokio::spawn(application.run_until_stopped());
+    ports
+}
+
+#[tokio::test]
+async fn the_application_can_serve_https() {
+    // arrange
+    let (port, _) = spawn_https_app().await;
+    let client = reqwest::Client::builder()
+        .danger_accept_invalid_certs(true)
+        .build()
+        .unwrap();
+
+    // act
+    let response = client
+        .get(&format!("https://127.0.0.1:{}/health_check", port))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // assert
+    assert!(response.status().is_success());
+}
+
+#[tokio::test]
+async fn plain_http_requests_are_redirected_to_the_base_url() {
+    // arrange
+    let (_, redirect_port) = spawn_https_app().await;
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+
+    // act
+    let response = client
+        .post(&format!(
+            "http://127.0.0.1:{}/subscriptions?source=footer",
+            redirect_port
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // assert
+    assert_eq!(response.status().as_u16(), 308);
+    assert_eq!(
+        response.headers().get("Location").unwrap(),
+        "http://127.0.0.1/subscriptions?source=footer"
+    );
+}
@@ -0,0 +1,242 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use email_newsletter::authentication::TotpSecret;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Extracts the TOTP secret shown on the setup page
+fn setup_secret(html: &str) -> TotpSecret {
+    let start = html
+        .find(r#"<code id="totp-secret">"#)
+        .expect("The setup page shows no secret")
+        + r#"<code id="totp-secret">"#.len();
+    let end = start + html[start..].find("</code>").unwrap();
+    TotpSecret::from_base32(html[start..end].to_owned())
+}
+
+/// Extracts the recovery codes shown once two-factor authentication is enabled
+fn recovery_codes(html: &str) -> Vec<String> {
+    html.split("<li><code>")
+        .skip(1)
+        .map(|item| item.split("</code>").next().unwrap().to_owned())
+        .collect()
+}
+
+/// Logs in, enables two-factor authentication and logs out again.
+/// Returns the secret and the recovery codes.
+async fn enable_two_factor(app: &TestApp) -> (TotpSecret, Vec<String>) {
+    app.default_login().await;
+    let secret = setup_secret(&app.get_security_html().await);
+    let response = app.post_enable_two_factor(&secret.code_at(now())).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let codes = recovery_codes(&response.text().await.unwrap());
+    app.post_logout().await;
+    (secret, codes)
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_set_up_two_factor_authentication() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.post_enable_two_factor("123456").await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_setup_page_shows_a_qr_code_and_the_secret() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+
+    // act
+    let html = app.get_security_html().await;
+
+    // assert
+    assert!(html.contains("<svg"));
+    let secret = setup_secret(&html);
+    // the secret stays the same until setup is complete
+    let html = app.get_security_html().await;
+    assert_eq!(setup_secret(&html).expose_base32(), secret.expose_base32());
+}
+
+#[tokio::test]
+async fn two_factor_authentication_is_not_enabled_with_a_wrong_code() {
+    // arrange
+    let app = spawn_app().await;
+    app.default_login().await;
+    app.get_security_html().await;
+
+    // act
+    let response = app.post_enable_two_factor("000000").await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/security");
+    let html = app.get_security_html().await;
+    assert!(html.contains("The code is incorrect."));
+    assert!(html.contains("Two-factor authentication is disabled."));
+}
+
+#[tokio::test]
+async fn enabling_two_factor_authentication_shows_recovery_codes() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let (_, codes) = enable_two_factor(&app).await;
+
+    // assert
+    assert_eq!(codes.len(), 10);
+    let stored = sqlx::query!(
+        "SELECT code_hash FROM recovery_codes WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_all(&app.connection_pool)
+    .await
+    .unwrap();
+    assert_eq!(stored.len(), 10);
+    assert!(stored.iter().all(|row| !codes.contains(&row.code_hash)));
+}
+
+#[tokio::test]
+async fn a_password_is_not_enough_once_two_factor_authentication_is_enabled() {
+    // arrange
+    let app = spawn_app().await;
+    enable_two_factor(&app).await;
+
+    // act
+    let response = app.default_login().await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login/two-factor");
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login?next=%2Fadmin%2Fdashboard");
+}
+
+#[tokio::test]
+async fn a_valid_code_completes_the_login() {
+    // arrange
+    let app = spawn_app().await;
+    let (secret, _) = enable_two_factor(&app).await;
+    app.default_login().await;
+
+    // act
+    // the code of the current time step was used during setup, so take the next one
+    let response = app.post_login_two_factor(&secret.code_at(now() + 30)).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html = app.get_admin_dashboard_html().await;
+    assert!(html.contains(&format!("Welcome {}", app.test_user.username)));
+}
+
+#[tokio::test]
+async fn the_page_asked_for_before_logging_in_is_kept_until_the_second_factor() {
+    // arrange
+    let app = spawn_app().await;
+    let (secret, _) = enable_two_factor(&app).await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+            "next": "/admin/password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login/two-factor");
+
+    // act
+    let response = app.post_login_two_factor(&secret.code_at(now() + 30)).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/password");
+}
+
+#[tokio::test]
+async fn codes_cannot_be_replayed() {
+    // arrange
+    let app = spawn_app().await;
+    let (secret, _) = enable_two_factor(&app).await;
+    app.default_login().await;
+
+    let used_step = sqlx::query!(
+        "SELECT totp_last_used_step FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.connection_pool)
+    .await
+    .unwrap()
+    .totp_last_used_step
+    .unwrap();
+
+    // act
+    let response = app
+        .post_login_two_factor(&secret.code_at(used_step as u64 * 30))
+        .await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login/two-factor");
+    let html = app.get_login_two_factor_html().await;
+    assert!(html.contains("Invalid authentication code."));
+}
+
+#[tokio::test]
+async fn recovery_codes_can_only_be_used_once() {
+    // arrange
+    let app = spawn_app().await;
+    let (_, codes) = enable_two_factor(&app).await;
+    app.default_login().await;
+
+    // act - part 1 - use a recovery code
+    let response = app.post_login_two_factor(&codes[0]).await;
+
+    // assert - part 1
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    // act - part 2 - use it again
+    app.post_logout().await;
+    app.default_login().await;
+    let response = app.post_login_two_factor(&codes[0]).await;
+
+    // assert - part 2
+    assert_is_redirect_to(&response, "/login/two-factor");
+}
+
+#[tokio::test]
+async fn the_second_factor_page_requires_a_password_first() {
+    // arrange
+    let app = spawn_app().await;
+
+    // act
+    let response = app.post_login_two_factor("123456").await;
+
+    // assert
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn two_factor_authentication_can_be_disabled() {
+    // arrange
+    let app = spawn_app().await;
+    let (_, codes) = enable_two_factor(&app).await;
+    app.default_login().await;
+    app.post_login_two_factor(&codes[0]).await;
+
+    // act
+    let response = app.post_disable_two_factor(&codes[1]).await;
+
+    // assert
+    assert_is_redirect_to(&response, "/admin/security");
+    let html = app.get_security_html().await;
+    assert!(html.contains("Two-factor authentication has been disabled."));
+    app.post_logout().await;
+    let response = app.default_login().await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}